@@ -0,0 +1,185 @@
+//! Throughput of the building blocks of a simulation.
+//!
+//! Run with `cargo bench`. Every benchmark needs a GPU supporting ray queries; set
+//! `WGPU_ADAPTER_NAME` to pick one on machines with several.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use glam::{Affine3A, Vec3};
+use tokio::runtime::Runtime;
+use wgpu_rt_lidar::{
+    lidar::Lidar,
+    utils::{create_cube, dense_voxel::DenseVoxel, dense_voxel::VoxelItem, get_raytracing_gpu},
+    vertex, AssetMesh, Instance, RayTraceScene,
+};
+
+/// A flat `n` by `n` grid of quads in the xy plane, `2 * n * n` triangles in total.
+fn grid_mesh(n: u16) -> AssetMesh {
+    let mut vertex_buf = vec![];
+    for y in 0..=n {
+        for x in 0..=n {
+            vertex_buf.push(vertex([x as f32 / n as f32, y as f32 / n as f32, 0.0]));
+        }
+    }
+    let mut index_buf = vec![];
+    for y in 0..n {
+        for x in 0..n {
+            let i = y * (n + 1) + x;
+            index_buf.extend([i, i + 1, i + n + 2, i + n + 2, i + n + 1, i]);
+        }
+    }
+    AssetMesh {
+        vertex_buf,
+        index_buf,
+    }
+}
+
+/// Beams spread evenly over a sphere.
+fn beam_directions(count: usize) -> Vec<Vec3> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+    (0..count)
+        .map(|i| {
+            let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let r = (1.0 - z * z).sqrt();
+            let theta = golden_angle * i as f32;
+            Vec3::new(r * theta.cos(), r * theta.sin(), z)
+        })
+        .collect()
+}
+
+/// Cubes on a line along x.
+fn cube_instances(count: usize) -> Vec<Instance> {
+    (0..count)
+        .map(|i| Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_translation(Vec3::new(3.0 * i as f32, 0.0, 0.0)),
+            id: 0,
+        })
+        .collect()
+}
+
+fn blas_build(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = rt.block_on(get_raytracing_gpu(&instance));
+    let mut group = c.benchmark_group("blas_build");
+    group.sample_size(10);
+    for n in [1u16, 16, 64, 180] {
+        let mesh = vec![grid_mesh(n)];
+        let triangles = 2 * n as u64 * n as u64;
+        group.throughput(Throughput::Elements(triangles));
+        group.bench_with_input(BenchmarkId::from_parameter(triangles), &mesh, |b, mesh| {
+            b.iter(|| {
+                rt.block_on(RayTraceScene::new(
+                    &device,
+                    &queue,
+                    mesh,
+                    &cube_instances(1),
+                ))
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn lidar_rays(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = rt.block_on(get_raytracing_gpu(&instance));
+    let scene = rt
+        .block_on(RayTraceScene::new(
+            &device,
+            &queue,
+            &vec![create_cube(1.0)],
+            &cube_instances(16),
+        ))
+        .unwrap();
+    let pose = Affine3A::from_translation(Vec3::new(-3.0, 0.0, 0.0));
+    let mut group = c.benchmark_group("lidar_rays");
+    for beams in [1024usize, 16 * 1024, 128 * 1024] {
+        let mut lidar = rt.block_on(Lidar::new(&device, beam_directions(beams)));
+        group.throughput(Throughput::Elements(beams as u64));
+        group.bench_function(BenchmarkId::new("beams", beams), |b| {
+            b.iter(|| rt.block_on(lidar.render_lidar_beams(&scene, &device, &queue, &pose)))
+        });
+        group.bench_function(BenchmarkId::new("pointcloud", beams), |b| {
+            b.iter(|| rt.block_on(lidar.render_lidar_pointcloud(&scene, &device, &queue, &pose)))
+        });
+    }
+    group.finish();
+}
+
+fn tlas_update(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = rt.block_on(get_raytracing_gpu(&instance));
+    // The TLAS is rebuilt by the next render, so a single beam makes it happen.
+    let mut lidar = rt.block_on(Lidar::new(&device, vec![Vec3::X]));
+    let mut group = c.benchmark_group("tlas_update");
+    for count in [16usize, 256, 4096] {
+        let mut instances = cube_instances(count);
+        let mut scene = rt
+            .block_on(RayTraceScene::new(
+                &device,
+                &queue,
+                &vec![create_cube(1.0)],
+                &instances,
+            ))
+            .unwrap();
+        let indices: Vec<usize> = (0..count).collect();
+        let mut step = 0.0;
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| {
+                step += 0.01;
+                for instance in &mut instances {
+                    instance.transform.translation.z = step;
+                }
+                rt.block_on(async {
+                    scene
+                        .set_transform(&device, &instances, &indices)
+                        .await
+                        .unwrap();
+                    lidar
+                        .render_lidar_beams(&scene, &device, &queue, &Affine3A::IDENTITY)
+                        .await
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn voxel_nearest_neighbours(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = rt.block_on(get_raytracing_gpu(&instance));
+    let mut voxel = DenseVoxel::new(Vec3::splat(10.0), Vec3::ZERO, 0.5, 8);
+    let items: Vec<_> = beam_directions(4096)
+        .into_iter()
+        .map(|p| VoxelItem::new(p * 4.0 + Vec3::splat(5.0)))
+        .collect();
+    voxel.add_items(&items);
+    let grid = voxel.to_gpu_buffers(&device);
+    let mut group = c.benchmark_group("voxel_nearest_neighbours");
+    for count in [256usize, 4096, 65536] {
+        let queries: Vec<_> = beam_directions(count)
+            .into_iter()
+            .map(|p| p * 3.0 + Vec3::splat(5.0))
+            .collect();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| rt.block_on(grid.nearest_neighbour_indices(&device, &queue, &queries)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    blas_build,
+    lidar_rays,
+    tlas_update,
+    voxel_nearest_neighbours
+);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+fn main() {
+    // The `server` feature's gRPC service.
+    #[cfg(feature = "server")]
+    tonic_build::compile_protos("proto/sensor_simulation.proto")
+        .expect("Compiling proto/sensor_simulation.proto needs protoc");
+}
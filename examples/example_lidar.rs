@@ -0,0 +1,41 @@
+use glam::{Affine3A, Quat, Vec3};
+use wgpu_rt_lidar::{utils::create_cube, LiDARRenderScene, RenderContext};
+
+/// A single ring of beams around the Y axis, one per degree.
+fn ring_beam_directions() -> Vec<Vec3> {
+    (0..360)
+        .map(|i| Quat::from_rotation_y((i as f32).to_radians()) * Vec3::X)
+        .collect()
+}
+
+#[tokio::main]
+async fn main() {
+    // Falls back to tracing on the CPU if no GPU supports ray queries.
+    let ctx = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let mut scene = LiDARRenderScene::new(ctx);
+
+    let cube = scene.add_object(create_cube(1.0));
+    scene
+        .add_instance(cube, Affine3A::from_translation(Vec3::new(-4.0, 0.0, 0.0)))
+        .unwrap();
+    let moving = scene
+        .add_instance(cube, Affine3A::from_translation(Vec3::new(4.0, 0.0, 0.0)))
+        .unwrap();
+    let lidar = scene
+        .add_lidar(ring_beam_directions(), Affine3A::IDENTITY)
+        .await;
+
+    for step in 0..10 {
+        scene
+            .set_instance_transform(
+                moving,
+                Affine3A::from_translation(Vec3::new(4.0 + step as f32, 0.0, 0.0)),
+            )
+            .unwrap();
+        let points = scene.get_lidar_returns(lidar).await.unwrap();
+        println!(
+            "Step {step}: +X range {:.2}m, -X range {:.2}m",
+            points[0].w, points[180].w
+        );
+    }
+}
@@ -5,7 +5,7 @@ use glam::{Affine3A, Mat4, Quat, Vec3, Vec4};
 use wgpu::util::DeviceExt;
 use wgpu_rt_lidar::{
     depth_camera::DepthCamera,
-    lidar::Lidar,
+    lidar::{Lidar, PointColoring},
     utils::{create_cube, get_raytracing_gpu},
     vertex, AssetMesh, Instance, RayTraceScene, Vertex,
 };
@@ -84,11 +84,14 @@ async fn main() {
                         z: -30.0,
                     },
                 ),
+                id: 0,
             });
         }
     }
 
-    let mut scene = RayTraceScene::new(&device, &queue, &vec![cube], &instances).await;
+    let mut scene = RayTraceScene::new(&device, &queue, &vec![cube], &instances)
+        .await
+        .unwrap();
 
     // Set the camera frame size
     let mut depth_camera = DepthCamera::new(&device, 1024, 1024, 59.0, 50.0).await;
@@ -104,32 +107,16 @@ async fn main() {
     for i in 0..3 {
         let start_time = Instant::now();
         let res = depth_camera
-            .render_depth_camera(
+            .render_depth_camera_array(
                 &scene,
                 &device,
                 &queue,
                 Mat4::look_at_rh(Vec3::new(0.0, 0.0, 2.5 + i as f32), Vec3::ZERO, Vec3::Y),
             )
             .await;
-        //println!("{:?}", res);
         println!("Took {:?} to render a depth frame", start_time.elapsed());
-        use ndarray::ShapeBuilder;
 
-        let mut image = ndarray::Array::<u16, _>::from_elem(
-            (
-                depth_camera.width() as usize,
-                depth_camera.height() as usize,
-            )
-                .f(),
-            65535,
-        );
-        for (i, x) in res.iter().enumerate() {
-            let x = (x * 1000.0) as u16;
-            image[(
-                i / depth_camera.width() as usize,
-                i % depth_camera.width() as usize,
-            )] = x;
-        }
+        let image = res.mapv(|depth| (depth * 1000.0) as u16);
         let depth_image = rerun::DepthImage::try_from(image)
             .unwrap()
             .with_meter(1000.0)
@@ -183,14 +170,12 @@ async fn main() {
         "Took {:?} to render a lidar pointcloud",
         start_time.elapsed()
     );
-    let p = res
-        .chunks(4)
-        .filter(|p| p[3] < Lidar::no_hit_const())
-        .map(|p| {
-            lidar_pose
-                .transform_point3(Vec3::new(p[0], p[1], p[2]))
-                .to_array()
-        });
     lidar.visualize_rays(&rec, &lidar_pose, "lidar_beams");
-    rec.log("points", &rerun::Points3D::new(p)).unwrap();
+    lidar.visualize_pointcloud(
+        &rec,
+        &res,
+        &lidar_pose,
+        "points",
+        PointColoring::Range { max: 50.0 },
+    );
 }
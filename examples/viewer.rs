@@ -0,0 +1,32 @@
+use glam::{Mat4, Vec3};
+use wgpu_rt_lidar::{
+    fixtures::Fixture,
+    viewer::{FlyCam, Viewer},
+    RenderContext,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The viewer drives its own runtime, so the scene is set up on a separate one.
+    let (context, scene, camera) = tokio::runtime::Runtime::new()?.block_on(async {
+        // Falls back to tracing on the CPU if no GPU supports ray queries.
+        let context = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+        let scene = Fixture::cornell_box()
+            .create_scene(context.device(), context.queue())
+            .await?;
+        let camera = context.create_depth_camera(320, 240, 70.0, 10.0).await;
+        Ok::<_, wgpu_rt_lidar::Error>((context, scene, camera))
+    })?;
+
+    // A camera in a corner of the room looking at the blocks.
+    let view = Mat4::look_at_rh(
+        Vec3::new(-0.8, 0.8, 0.5),
+        Vec3::new(0.4, -0.4, -0.5),
+        Vec3::Z,
+    );
+    Viewer::new(context, scene)
+        .with_depth_camera(camera, view)
+        .with_fly_cam(FlyCam::new(Vec3::new(-0.9, 0.0, 0.0), Vec3::ZERO))
+        .with_max_depth(3.0)
+        .run()?;
+    Ok(())
+}
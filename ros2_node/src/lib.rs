@@ -0,0 +1,349 @@
+//! A ROS 2 node publishing the sensors of a scene, built on `rclrs`.
+//!
+//! A [`SensorNode`] attaches LiDARs and depth cameras to TF frames, follows `/tf` and
+//! `/tf_static` in a [`FrameTree`] and, on every tick, renders each sensor at the pose of its
+//! frame and publishes a `sensor_msgs/PointCloud2`, or a depth `sensor_msgs/Image` with its
+//! `sensor_msgs/CameraInfo`:
+//!
+//! ```no_run
+//! # async fn run(
+//! #     context: wgpu_rt_lidar::RenderContext,
+//! #     scene: wgpu_rt_lidar::RayTraceScene,
+//! #     lidar: wgpu_rt_lidar::lidar::Lidar,
+//! # ) -> Result<(), wgpu_rt_lidar_ros2_node::NodeError> {
+//! use wgpu_rt_lidar_ros2_node::SensorNode;
+//!
+//! let ros = rclrs::Context::new(std::env::args())?;
+//! let mut node = SensorNode::new(&ros, "simulated_sensors", context, scene, "world")?;
+//! node.add_lidar(lidar, "lidar_link", "lidar/points")?;
+//! node.run(10.0).await
+//! # }
+//! ```
+//!
+//! The message crates are generated by `ros2_rust`, so this crate is built by colcon in a
+//! sourced ROS 2 workspace rather than with the `wgpu_rt_lidar` workspace.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use glam::{Affine3A, DQuat, DVec3, Mat4};
+use rclrs::{
+    Node, Publisher, RclReturnCode, RclrsError, Subscription, QOS_PROFILE_DEFAULT,
+    QOS_PROFILE_SENSOR_DATA,
+};
+use tf2_msgs::msg::TFMessage;
+
+use wgpu_rt_lidar::{
+    depth_camera::DepthCamera,
+    frame_tree::FrameTree,
+    lidar::Lidar,
+    ros2::{self, CameraInfo, DepthEncoding, Header, Image, PointCloud2, Time},
+    Error, RayTraceScene, RenderContext,
+};
+
+/// How long transforms are kept for interpolation, like tf2's default buffer.
+const TF_BUFFER_SECS: f64 = 10.0;
+
+/// Errors of a [`SensorNode`].
+#[derive(Debug, thiserror::Error)]
+pub enum NodeError {
+    #[error(transparent)]
+    Ros(#[from] RclrsError),
+    #[error(transparent)]
+    Sensor(#[from] Error),
+}
+
+struct LidarSensor {
+    lidar: Lidar,
+    frame: String,
+    points: Arc<Publisher<sensor_msgs::msg::PointCloud2>>,
+}
+
+struct CameraSensor {
+    camera: DepthCamera,
+    frame: String,
+    image: Arc<Publisher<sensor_msgs::msg::Image>>,
+    info: Arc<Publisher<sensor_msgs::msg::CameraInfo>>,
+}
+
+/// A ROS 2 node rendering and publishing sensors attached to TF frames.
+pub struct SensorNode {
+    node: Arc<Node>,
+    context: RenderContext,
+    scene: RayTraceScene,
+    world_frame: String,
+    frames: Arc<Mutex<FrameTree>>,
+    lidars: Vec<LidarSensor>,
+    cameras: Vec<CameraSensor>,
+    _tf: [Arc<Subscription<TFMessage>>; 2],
+}
+
+impl SensorNode {
+    /// Creates the node `name`, rendering `scene`, whose coordinates are those of the TF frame
+    /// `world_frame`.
+    pub fn new(
+        ros: &rclrs::Context,
+        name: &str,
+        context: RenderContext,
+        scene: RayTraceScene,
+        world_frame: &str,
+    ) -> Result<Self, NodeError> {
+        let node = Node::new(ros, name)?;
+        let frames = Arc::new(Mutex::new(FrameTree::new()));
+        let tf = {
+            let frames = frames.clone();
+            node.create_subscription("/tf", QOS_PROFILE_DEFAULT, move |msg: TFMessage| {
+                add_transforms(&mut frames.lock().unwrap(), msg, false)
+            })?
+        };
+        let tf_static = {
+            let frames = frames.clone();
+            node.create_subscription(
+                "/tf_static",
+                QOS_PROFILE_DEFAULT.transient_local(),
+                move |msg: TFMessage| add_transforms(&mut frames.lock().unwrap(), msg, true),
+            )?
+        };
+        Ok(Self {
+            node,
+            context,
+            scene,
+            world_frame: world_frame.to_string(),
+            frames,
+            lidars: vec![],
+            cameras: vec![],
+            _tf: [tf, tf_static],
+        })
+    }
+
+    pub fn node(&self) -> &Arc<Node> {
+        &self.node
+    }
+
+    /// The scene, e.g. to move its instances between ticks.
+    pub fn scene_mut(&mut self) -> &mut RayTraceScene {
+        &mut self.scene
+    }
+
+    /// The transforms received so far.
+    pub fn frames(&self) -> &Arc<Mutex<FrameTree>> {
+        &self.frames
+    }
+
+    /// Attaches `lidar` to `frame` and publishes its point clouds on `topic`.
+    pub fn add_lidar(&mut self, lidar: Lidar, frame: &str, topic: &str) -> Result<(), NodeError> {
+        self.lidars.push(LidarSensor {
+            lidar,
+            frame: frame.to_string(),
+            points: self.node.create_publisher(topic, QOS_PROFILE_SENSOR_DATA)?,
+        });
+        Ok(())
+    }
+
+    /// Attaches `camera` to the optical frame `frame`, z forward and y down, and publishes its
+    /// depth images in meters on `{topic}/image_raw` and its intrinsics on `{topic}/camera_info`.
+    pub fn add_depth_camera(
+        &mut self,
+        camera: DepthCamera,
+        frame: &str,
+        topic: &str,
+    ) -> Result<(), NodeError> {
+        self.cameras.push(CameraSensor {
+            camera,
+            frame: frame.to_string(),
+            image: self
+                .node
+                .create_publisher(&format!("{topic}/image_raw"), QOS_PROFILE_SENSOR_DATA)?,
+            info: self
+                .node
+                .create_publisher(&format!("{topic}/camera_info"), QOS_PROFILE_SENSOR_DATA)?,
+        });
+        Ok(())
+    }
+
+    /// Handles the transforms received since the last call, without blocking.
+    pub fn spin_some(&self) -> Result<(), NodeError> {
+        match rclrs::spin_once(self.node.clone(), Some(Duration::ZERO)) {
+            Ok(())
+            | Err(RclrsError::RclError {
+                code: RclReturnCode::Timeout,
+                ..
+            }) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Renders every sensor at the pose of its frame at the current time of the node's clock,
+    /// which follows `/clock` with `use_sim_time`, and publishes the outputs.
+    ///
+    /// Sensors whose frame is not connected to the world frame yet are skipped. Returns the
+    /// number of sensors published.
+    pub async fn publish(&mut self) -> Result<usize, NodeError> {
+        let nanoseconds = self.node.get_clock().now().nsec;
+        let time = nanoseconds as f64 * 1e-9;
+        let stamp = Time::from_secs_f64(time);
+        let poses = {
+            let mut frames = self.frames.lock().unwrap();
+            frames.prune_before(time - TF_BUFFER_SECS);
+            let pose = |frame: &str| frames.transform(frame, &self.world_frame, time).ok();
+            (
+                self.lidars
+                    .iter()
+                    .map(|sensor| pose(&sensor.frame))
+                    .collect::<Vec<_>>(),
+                self.cameras
+                    .iter()
+                    .map(|sensor| pose(&sensor.frame))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let mut published = 0;
+        for (sensor, pose) in self.lidars.iter_mut().zip(poses.0) {
+            let Some(pose) = pose else { continue };
+            let points = self
+                .context
+                .render_lidar_pointcloud(&mut sensor.lidar, &self.scene, pose)
+                .await;
+            let header = Header::new(stamp, sensor.frame.as_str());
+            let cloud = PointCloud2::from_lidar_pointcloud(header, &points, &[])?;
+            sensor.points.publish(point_cloud_msg(cloud))?;
+            published += 1;
+        }
+        // The depth camera looks down -z with y up.
+        let optical_to_camera = Affine3A::from_rotation_x(std::f32::consts::PI);
+        for (sensor, pose) in self.cameras.iter_mut().zip(poses.1) {
+            let Some(pose) = pose else { continue };
+            let view_matrix = Mat4::from(pose * optical_to_camera).inverse();
+            let depth = self
+                .context
+                .render_depth_camera(&mut sensor.camera, &self.scene, view_matrix)
+                .await;
+            let header = Header::new(stamp, sensor.frame.as_str());
+            let image = Image::from_depth_image(
+                header.clone(),
+                &sensor.camera,
+                &depth,
+                DepthEncoding::Meters32,
+            )?;
+            sensor.image.publish(image_msg(image))?;
+            sensor
+                .info
+                .publish(camera_info_msg(CameraInfo::from_depth_camera(
+                    header,
+                    &sensor.camera,
+                )))?;
+            published += 1;
+        }
+        Ok(published)
+    }
+
+    /// Handles transforms and publishes every sensor at `rate` Hz until an error occurs.
+    pub async fn run(&mut self, rate: f64) -> Result<(), NodeError> {
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        loop {
+            interval.tick().await;
+            self.spin_some()?;
+            self.publish().await?;
+        }
+    }
+}
+
+/// Adds the transforms of a TF message to `frames`. Transforms that would move a frame to
+/// another parent are ignored.
+fn add_transforms(frames: &mut FrameTree, msg: TFMessage, is_static: bool) {
+    for transform in msg.transforms {
+        let t = &transform.transform;
+        let pose = Affine3A::from_rotation_translation(
+            DQuat::from_xyzw(t.rotation.x, t.rotation.y, t.rotation.z, t.rotation.w)
+                .as_quat()
+                .normalize(),
+            DVec3::new(t.translation.x, t.translation.y, t.translation.z).as_vec3(),
+        );
+        let frame = &transform.child_frame_id;
+        let parent = &transform.header.frame_id;
+        let _ = if is_static {
+            frames.set_static_transform(frame, parent, pose)
+        } else {
+            let stamp = &transform.header.stamp;
+            let time = stamp.sec as f64 + stamp.nanosec as f64 * 1e-9;
+            frames.set_transform(frame, parent, time, pose)
+        };
+    }
+}
+
+fn header_msg(header: Header) -> std_msgs::msg::Header {
+    std_msgs::msg::Header {
+        stamp: builtin_interfaces::msg::Time {
+            sec: header.stamp.sec,
+            nanosec: header.stamp.nanosec,
+        },
+        frame_id: header.frame_id,
+    }
+}
+
+fn point_cloud_msg(cloud: PointCloud2) -> sensor_msgs::msg::PointCloud2 {
+    sensor_msgs::msg::PointCloud2 {
+        header: header_msg(cloud.header),
+        height: cloud.height,
+        width: cloud.width,
+        fields: cloud
+            .fields
+            .into_iter()
+            .map(|field| sensor_msgs::msg::PointField {
+                name: field.name,
+                offset: field.offset,
+                datatype: field.datatype,
+                count: field.count,
+            })
+            .collect(),
+        is_bigendian: cloud.is_bigendian,
+        point_step: cloud.point_step,
+        row_step: cloud.row_step,
+        data: cloud.data,
+        is_dense: cloud.is_dense,
+    }
+}
+
+fn image_msg(image: Image) -> sensor_msgs::msg::Image {
+    sensor_msgs::msg::Image {
+        header: header_msg(image.header),
+        height: image.height,
+        width: image.width,
+        encoding: image.encoding,
+        is_bigendian: image.is_bigendian,
+        step: image.step,
+        data: image.data,
+    }
+}
+
+fn camera_info_msg(info: CameraInfo) -> sensor_msgs::msg::CameraInfo {
+    let ros2::RegionOfInterest {
+        x_offset,
+        y_offset,
+        height,
+        width,
+        do_rectify,
+    } = info.roi;
+    sensor_msgs::msg::CameraInfo {
+        header: header_msg(info.header),
+        height: info.height,
+        width: info.width,
+        distortion_model: info.distortion_model,
+        d: info.d,
+        k: info.k,
+        r: info.r,
+        p: info.p,
+        binning_x: info.binning_x,
+        binning_y: info.binning_y,
+        roi: sensor_msgs::msg::RegionOfInterest {
+            x_offset,
+            y_offset,
+            height,
+            width,
+            do_rectify,
+        },
+    }
+}
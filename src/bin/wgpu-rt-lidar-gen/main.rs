@@ -0,0 +1,217 @@
+//! Renders a dataset of a scene along a trajectory.
+//!
+//! ```text
+//! wgpu-rt-lidar-gen <scene.json> <rig.sdf|rig.json> <trajectory.txt> <output> [options]
+//! ```
+//!
+//! * The scene is a JSON object holding the `assets` and `instances` of a `RayTraceScene`.
+//! * The rig lists the LiDARs and depth cameras mounted on the vehicle, either as the
+//!   `<sensor>`s of an SDF model or as a JSON array of `sdf::SensorConfig`s. Poses are relative
+//!   to the vehicle.
+//! * The trajectory holds one pose of the vehicle per line in the TUM format,
+//!   `timestamp x y z qx qy qz qw` with the timestamp in seconds. Lines starting with `#` are
+//!   skipped.
+//!
+//! Every sensor is rendered at every pose of the trajectory, all of them in one submission per
+//! pose, and the captures are written as a nuScenes style dataset to the output directory, see
+//! `export::NuScenesWriter`.
+//!
+//! Options:
+//!
+//! * `--key-frame-interval <n>`: Make every n-th pose a sample, the others sweeps. Defaults to
+//!   1.
+//! * `--ply`: Also write the point clouds as PLY files under `ply/<channel>/`.
+
+use std::{error::Error, fs, path::Path, process::ExitCode};
+
+use glam::{Affine3A, Mat3, Quat, Vec3};
+use wgpu_rt_lidar::{
+    depth_camera::DepthCamera,
+    export::{nuscenes::Capture, NuScenesWriter, PlyCloud},
+    frame::FrameEncoder,
+    lidar::Lidar,
+    sdf::{self, DepthCameraConfig, LidarConfig, SensorConfig},
+    AssetMesh, Instance, RenderContext,
+};
+
+const USAGE: &str = "Usage: wgpu-rt-lidar-gen <scene.json> <rig.sdf|rig.json> <trajectory.txt> \
+                     <output> [--key-frame-interval <n>] [--ply]";
+
+/// The contents of a scene file.
+#[derive(serde::Deserialize)]
+struct SceneFile {
+    assets: Vec<AssetMesh>,
+    instances: Vec<Instance>,
+}
+
+struct Options {
+    scene: String,
+    rig: String,
+    trajectory: String,
+    output: String,
+    key_frame_interval: usize,
+    ply: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut key_frame_interval = 1;
+        let mut ply = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--key-frame-interval" => {
+                    key_frame_interval = args
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .filter(|n| *n > 0)
+                        .ok_or("--key-frame-interval needs a positive integer")?;
+                }
+                "--ply" => ply = true,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+                _ => positional.push(arg),
+            }
+        }
+        let [scene, rig, trajectory, output] = <[String; 4]>::try_from(positional)
+            .map_err(|_| "Expected a scene, a rig, a trajectory and an output".to_string())?;
+        Ok(Self {
+            scene,
+            rig,
+            trajectory,
+            output,
+            key_frame_interval,
+            ply,
+        })
+    }
+}
+
+/// Reads the sensors of an SDF model, or of a JSON array if the file ends in `.json`.
+fn read_rig(path: &str) -> Result<Vec<SensorConfig>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    if Path::new(path).extension().is_some_and(|e| e == "json") {
+        Ok(serde_json::from_str(&contents)?)
+    } else {
+        Ok(sdf::parse_sensors(&contents)?)
+    }
+}
+
+/// Reads the timestamps, in seconds, and poses of a TUM trajectory.
+fn read_trajectory(path: &str) -> Result<Vec<(f64, Affine3A)>, Box<dyn Error>> {
+    let mut poses = vec![];
+    for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values: Vec<f64> = line
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("{path}:{}: {e}", number + 1))?;
+        let [timestamp, x, y, z, qx, qy, qz, qw] = <[f64; 8]>::try_from(values)
+            .map_err(|_| format!("{path}:{}: expected 8 values", number + 1))?;
+        let rotation = Quat::from_xyzw(qx as f32, qy as f32, qz as f32, qw as f32).normalize();
+        let translation = Vec3::new(x as f32, y as f32, z as f32);
+        poses.push((
+            timestamp,
+            Affine3A::from_rotation_translation(rotation, translation),
+        ));
+    }
+    Ok(poses)
+}
+
+async fn generate(options: Options) -> Result<(), Box<dyn Error>> {
+    let scene_file: SceneFile = serde_json::from_str(&fs::read_to_string(&options.scene)?)?;
+    let rig = read_rig(&options.rig)?;
+    let trajectory = read_trajectory(&options.trajectory)?;
+
+    // Falls back to tracing on the CPU if no GPU supports ray queries.
+    let context = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let (device, queue) = (context.device(), context.queue());
+    let scene = context
+        .create_scene(&scene_file.assets, &scene_file.instances)
+        .await?;
+
+    let output = Path::new(&options.output);
+    let mut writer = NuScenesWriter::create(output, "v1.0-sim", "simulated")?;
+    let mut lidars: Vec<(LidarConfig, Lidar, _)> = vec![];
+    let mut cameras: Vec<(DepthCameraConfig, DepthCamera, _)> = vec![];
+    for sensor in rig {
+        match sensor {
+            SensorConfig::Lidar(config) => {
+                let id = writer.add_lidar(&config.name, &config.pose);
+                let lidar = config.create(device).await;
+                lidars.push((config, lidar, id));
+            }
+            SensorConfig::DepthCamera(config) => {
+                let camera = config.create(device).await;
+                // SDF cameras look along x with z up, nuScenes ones along z with y down.
+                let optical =
+                    Affine3A::from_mat3(Mat3::from_cols(Vec3::NEG_Y, Vec3::NEG_Z, Vec3::X));
+                let id = writer.add_depth_camera(&config.name, &(config.pose * optical), &camera);
+                cameras.push((config, camera, id));
+            }
+        }
+    }
+
+    for (index, (time, ego_pose)) in trajectory.iter().enumerate() {
+        let capture = Capture {
+            timestamp: (time * 1e6).round() as u64,
+            ego_pose: *ego_pose,
+            key_frame: index % options.key_frame_interval == 0,
+        };
+        if capture.key_frame {
+            writer.add_sample(capture.timestamp);
+        }
+
+        let mut frame = FrameEncoder::new(device, queue);
+        let points: Vec<_> = lidars
+            .iter_mut()
+            .map(|(config, lidar, _)| {
+                frame.render_lidar_pointcloud(lidar, &scene, *ego_pose * config.pose)
+            })
+            .collect();
+        let depths: Vec<_> = cameras
+            .iter_mut()
+            .map(|(config, camera, _)| {
+                frame.render_depth_camera(camera, &scene, config.view_matrix(ego_pose))
+            })
+            .collect();
+        let mut outputs = frame.submit().await;
+
+        for ((config, _, id), points) in lidars.iter().zip(points) {
+            let points = outputs.take(points);
+            writer.write_lidar(*id, capture, &points)?;
+            if options.ply {
+                let directory = output.join("ply").join(&config.name);
+                fs::create_dir_all(&directory)?;
+                PlyCloud::from_lidar_pointcloud(&points)?
+                    .save(directory.join(format!("{index:06}.ply")))?;
+            }
+        }
+        for ((_, camera, id), depth) in cameras.iter().zip(depths) {
+            writer.write_depth_image(*id, capture, camera, &outputs.take(depth))?;
+        }
+        println!("Rendered pose {} of {}", index + 1, trajectory.len());
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match generate(options).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Checking what an adapter supports before opening a device on it.
+//!
+//! [`crate::utils::get_raytracing_gpu`] panics when no adapter supports ray queries, and
+//! [`crate::utils::try_get_raytracing_gpu`] returns an error. Applications that would rather fall
+//! back, e.g. to the CPU tracer or to disabling their sensors, probe the adapters first:
+//!
+//! ```no_run
+//! let instance = wgpu::Instance::default();
+//! match wgpu_rt_lidar::supported_adapters(&instance).into_iter().next() {
+//!     Some((_adapter, capabilities)) => {
+//!         println!("Tracing on {}", capabilities.name);
+//!         println!("Up to {} instances per scene", capabilities.max_tlas_instances);
+//!     }
+//!     None => println!("No GPU supports ray queries, tracing on the CPU"),
+//! }
+//! ```
+
+use wgpu::Adapter;
+
+use crate::utils::RAYTRACING_FEATURES;
+
+/// What an adapter supports, as far as the crate is concerned.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// Name of the adapter.
+    pub name: String,
+    /// Backend the adapter was enumerated through.
+    pub backend: wgpu::Backend,
+    /// Whether ray queries against acceleration structures are supported.
+    pub ray_query: bool,
+    /// Whether compiled pipelines can be cached, see [`crate::pipeline_cache`].
+    pub pipeline_cache: bool,
+    /// Required features the adapter lacks. Empty if it is supported.
+    pub missing_features: wgpu::Features,
+    /// Largest number of instances in a scene.
+    pub max_tlas_instances: u32,
+    /// Largest number of triangles in a single asset.
+    pub max_blas_primitives: u32,
+    /// All limits of the adapter.
+    pub limits: wgpu::Limits,
+}
+
+impl Capabilities {
+    /// Probes `adapter`. Does not open a device.
+    pub fn probe(adapter: &Adapter) -> Self {
+        let info = adapter.get_info();
+        Self::from_parts(
+            info.name,
+            info.backend,
+            adapter.features(),
+            adapter.limits(),
+        )
+    }
+
+    fn from_parts(
+        name: String,
+        backend: wgpu::Backend,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+    ) -> Self {
+        Self {
+            name,
+            backend,
+            ray_query: features.contains(
+                wgpu::Features::EXPERIMENTAL_RAY_QUERY
+                    | wgpu::Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE,
+            ),
+            pipeline_cache: features.contains(wgpu::Features::PIPELINE_CACHE),
+            missing_features: RAYTRACING_FEATURES - features,
+            max_tlas_instances: limits.max_tlas_instance_count,
+            max_blas_primitives: limits.max_blas_primitive_count,
+            limits,
+        }
+    }
+
+    /// Returns true if the sensors can trace on the GPU, i.e. no required feature is missing.
+    pub fn is_supported(&self) -> bool {
+        self.missing_features.is_empty()
+    }
+}
+
+/// Returns true if the sensors can trace on `adapter`'s GPU.
+pub fn is_supported(adapter: &Adapter) -> bool {
+    Capabilities::probe(adapter).is_supported()
+}
+
+/// Lists every supported adapter with its capabilities, in the order reported by `instance`.
+pub fn supported_adapters(instance: &wgpu::Instance) -> Vec<(Adapter, Capabilities)> {
+    instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .map(|adapter| {
+            let capabilities = Capabilities::probe(&adapter);
+            (adapter, capabilities)
+        })
+        .filter(|(_, capabilities)| capabilities.is_supported())
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn test_capabilities_report_missing_features() {
+    let limits = wgpu::Limits {
+        max_tlas_instance_count: 1024,
+        ..Default::default()
+    };
+    let without_rt = Capabilities::from_parts(
+        "test".to_string(),
+        wgpu::Backend::Noop,
+        wgpu::Features::TEXTURE_BINDING_ARRAY | wgpu::Features::PIPELINE_CACHE,
+        limits.clone(),
+    );
+    assert!(!without_rt.ray_query);
+    assert!(without_rt.pipeline_cache);
+    assert!(!without_rt.is_supported());
+    assert!(without_rt
+        .missing_features
+        .contains(wgpu::Features::EXPERIMENTAL_RAY_QUERY));
+    assert!(!without_rt
+        .missing_features
+        .contains(wgpu::Features::TEXTURE_BINDING_ARRAY));
+
+    let with_rt = Capabilities::from_parts(
+        "test".to_string(),
+        wgpu::Backend::Vulkan,
+        RAYTRACING_FEATURES,
+        limits,
+    );
+    assert!(with_rt.ray_query);
+    assert!(with_rt.is_supported());
+    assert_eq!(with_rt.max_tlas_instances, 1024);
+}
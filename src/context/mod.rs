@@ -0,0 +1,219 @@
+//! Owning the GPU a simulation renders on.
+//!
+//! Scenes, sensors and their renders all take the `wgpu::Device` and `wgpu::Queue` they run on.
+//! A [`RenderContext`] opens them once and creates and renders scenes and sensors on them, so
+//! applications that use a single GPU do not have to pass them around:
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), wgpu_rt_lidar::Error> {
+//! use glam::{Affine3A, Vec3};
+//! use wgpu_rt_lidar::{utils::create_cube, Instance, RenderContext};
+//!
+//! let ctx = RenderContext::new(wgpu::Instance::default()).await?;
+//! let scene = ctx
+//!     .create_scene(
+//!         &[create_cube(1.0)],
+//!         &[Instance {
+//!             asset_mesh_index: 0,
+//!             transform: Affine3A::IDENTITY,
+//!             id: 0,
+//!         }],
+//!     )
+//!     .await?;
+//! let mut lidar = ctx.create_lidar(vec![Vec3::NEG_X]).await;
+//! let pose = Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0));
+//! let ranges = ctx.render_lidar_beams(&mut lidar, &scene, &pose).await;
+//! # Ok(())
+//! # }
+//! ```
+
+use glam::{Vec3, Vec4};
+
+use crate::{
+    depth_camera::DepthCamera,
+    lidar::Lidar,
+    pose::{IntoAffine3A, IntoMat4},
+    utils::{get_gpu, try_get_raytracing_gpu},
+    AssetMesh, Error, Instance, RayTraceScene, SceneError,
+};
+
+/// The instance, adapter, device and queue scenes and sensors are created and rendered on.
+#[derive(Debug)]
+pub struct RenderContext {
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl RenderContext {
+    /// Opens a device supporting ray queries on the adapter named by `WGPU_ADAPTER_NAME`, or on
+    /// the first supported adapter if it is not set.
+    ///
+    /// Fails with [`Error::Unsupported`] if no adapter supports ray queries.
+    pub async fn new(instance: wgpu::Instance) -> Result<Self, Error> {
+        let (adapter, device, queue) = try_get_raytracing_gpu(&instance).await?;
+        Ok(Self::from_parts(instance, adapter, device, queue))
+    }
+
+    /// Opens a device like [`RenderContext::new`], falling back to a device without ray queries
+    /// whose scenes are traced on the CPU, see [`get_gpu`].
+    pub async fn with_cpu_fallback(instance: wgpu::Instance) -> Self {
+        let (adapter, device, queue) = get_gpu(&instance).await;
+        Self::from_parts(instance, adapter, device, queue)
+    }
+
+    /// Wraps a device opened by the caller, e.g. one shared with a renderer.
+    pub fn from_parts(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+    ) -> Self {
+        Self {
+            instance,
+            adapter,
+            device,
+            queue,
+        }
+    }
+
+    pub fn instance(&self) -> &wgpu::Instance {
+        &self.instance
+    }
+
+    pub fn adapter(&self) -> &wgpu::Adapter {
+        &self.adapter
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Creates a scene, see [`RayTraceScene::new`].
+    pub async fn create_scene(
+        &self,
+        assets: &[AssetMesh],
+        instances: &[Instance],
+    ) -> Result<RayTraceScene, SceneError> {
+        RayTraceScene::new(&self.device, &self.queue, assets, instances).await
+    }
+
+    /// Creates a LiDAR, see [`Lidar::new`].
+    pub async fn create_lidar(&self, ray_directions: Vec<Vec3>) -> Lidar {
+        Lidar::new(&self.device, ray_directions).await
+    }
+
+    /// Creates a depth camera, see [`DepthCamera::new`].
+    pub async fn create_depth_camera(
+        &self,
+        width: u32,
+        height: u32,
+        fov_y: f32,
+        max_depth: f32,
+    ) -> DepthCamera {
+        DepthCamera::new(&self.device, width, height, fov_y, max_depth).await
+    }
+
+    /// Updates the transform of instances of `scene`, see [`RayTraceScene::set_transform`].
+    pub async fn set_transform(
+        &self,
+        scene: &mut RayTraceScene,
+        update_instance: &[Instance],
+        idx: &[usize],
+    ) -> Result<(), Error> {
+        scene
+            .set_transform(&self.device, update_instance, idx)
+            .await
+    }
+
+    /// Renders the beams of `lidar`, see [`Lidar::render_lidar_beams`].
+    pub async fn render_lidar_beams(
+        &self,
+        lidar: &mut Lidar,
+        scene: &RayTraceScene,
+        pose: impl IntoAffine3A,
+    ) -> Vec<f32> {
+        lidar
+            .render_lidar_beams(scene, &self.device, &self.queue, pose)
+            .await
+    }
+
+    /// Renders the point cloud of `lidar`, see [`Lidar::render_lidar_pointcloud`].
+    pub async fn render_lidar_pointcloud(
+        &self,
+        lidar: &mut Lidar,
+        scene: &RayTraceScene,
+        pose: impl IntoAffine3A,
+    ) -> Vec<f32> {
+        lidar
+            .render_lidar_pointcloud(scene, &self.device, &self.queue, pose)
+            .await
+    }
+
+    /// Renders the depth image of `camera`, see [`DepthCamera::render_depth_camera`].
+    pub async fn render_depth_camera(
+        &self,
+        camera: &mut DepthCamera,
+        scene: &RayTraceScene,
+        view_matrix: impl IntoMat4,
+    ) -> Vec<f32> {
+        camera
+            .render_depth_camera(scene, &self.device, &self.queue, view_matrix)
+            .await
+    }
+
+    /// Renders the point cloud of `camera`, see [`DepthCamera::render_depth_camera_pointcloud`].
+    pub async fn render_depth_camera_pointcloud(
+        &self,
+        camera: &mut DepthCamera,
+        scene: &RayTraceScene,
+        view_matrix: impl IntoMat4,
+    ) -> Vec<Vec4> {
+        camera
+            .render_depth_camera_pointcloud(scene, &self.device, &self.queue, view_matrix)
+            .await
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_render_context_renders_lidar() {
+    use crate::utils::create_cube;
+    use glam::Affine3A;
+
+    let ctx = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let mut scene = ctx
+        .create_scene(
+            &[create_cube(1.0)],
+            &[Instance {
+                asset_mesh_index: 0,
+                transform: Affine3A::IDENTITY,
+                id: 0,
+            }],
+        )
+        .await
+        .unwrap();
+    let mut lidar = ctx.create_lidar(vec![Vec3::NEG_X]).await;
+    let pose = Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0));
+    let beams = ctx.render_lidar_beams(&mut lidar, &scene, &pose).await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+
+    ctx.set_transform(
+        &mut scene,
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_translation(Vec3::new(1.0, 0.0, 0.0)),
+            id: 0,
+        }],
+        &[0],
+    )
+    .await
+    .unwrap();
+    let beams = ctx.render_lidar_beams(&mut lidar, &scene, &pose).await;
+    assert!((beams[0] - 3.0).abs() < 1e-4);
+}
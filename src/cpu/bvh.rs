@@ -0,0 +1,197 @@
+use glam::{Affine3A, Vec3};
+
+/// Largest number of primitives stored in a leaf.
+const MAX_LEAF_SIZE: usize = 4;
+
+/// An axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Aabb {
+    pub(crate) min: Vec3,
+    pub(crate) max: Vec3,
+}
+
+impl Aabb {
+    /// A box containing nothing, the identity of [`Aabb::union`].
+    pub(crate) const EMPTY: Self = Self {
+        min: Vec3::INFINITY,
+        max: Vec3::NEG_INFINITY,
+    };
+
+    pub(crate) fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, p| Self {
+            min: aabb.min.min(p),
+            max: aabb.max.max(p),
+        })
+    }
+
+    pub(crate) fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Bounds of the box once moved by `transform`.
+    pub(crate) fn transformed(&self, transform: &Affine3A) -> Self {
+        if self.min.cmpgt(self.max).any() {
+            return Self::EMPTY;
+        }
+        Self::from_points((0..8).map(|corner| {
+            transform.transform_point3(Vec3::new(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            ))
+        }))
+    }
+
+    /// Distance along the ray at which it enters the box, if it does so before `t_max`.
+    fn entry(&self, origin: Vec3, inv_dir: Vec3, t_max: f32) -> Option<f32> {
+        let t0 = (self.min - origin) * inv_dir;
+        let t1 = (self.max - origin) * inv_dir;
+        // `min` and `max` drop the NaNs of rays parallel to a slab starting on its plane.
+        let near = t0.min(t1).max_element().max(0.0);
+        let far = t0.max(t1).min_element().min(t_max);
+        (near <= far).then_some(near)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    /// First primitive of a leaf, or the left child of an inner node. The right child follows
+    /// the left one.
+    start: u32,
+    /// Number of primitives of a leaf, zero for inner nodes.
+    count: u32,
+}
+
+/// A bounding volume hierarchy over primitives given by their bounds.
+///
+/// Built by splitting at the median centroid along the longest axis, which is fast to build and
+/// good enough to trace sensors against scenes of a few million triangles.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bvh {
+    nodes: Vec<Node>,
+    /// Primitive indices, grouped by leaf.
+    primitives: Vec<u32>,
+}
+
+impl Bvh {
+    pub(crate) fn build(bounds: &[Aabb]) -> Self {
+        let mut bvh = Self {
+            nodes: vec![],
+            primitives: (0..bounds.len() as u32).collect(),
+        };
+        if !bounds.is_empty() {
+            bvh.nodes.push(Node {
+                bounds: Aabb::EMPTY,
+                start: 0,
+                count: bounds.len() as u32,
+            });
+            bvh.split(0, bounds);
+        }
+        bvh
+    }
+
+    fn split(&mut self, node: usize, bounds: &[Aabb]) {
+        let Node { start, count, .. } = self.nodes[node];
+        let range = start as usize..(start + count) as usize;
+        self.nodes[node].bounds = self.primitives[range.clone()]
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, &i| aabb.union(&bounds[i as usize]));
+        if range.len() <= MAX_LEAF_SIZE {
+            return;
+        }
+
+        let centroids = Aabb::from_points(
+            self.primitives[range.clone()]
+                .iter()
+                .map(|&i| bounds[i as usize].centroid()),
+        );
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let mid = range.len() / 2;
+        self.primitives[range.clone()].select_nth_unstable_by(mid, |&a, &b| {
+            bounds[a as usize].centroid()[axis].total_cmp(&bounds[b as usize].centroid()[axis])
+        });
+
+        let left = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: Aabb::EMPTY,
+            start,
+            count: mid as u32,
+        });
+        self.nodes.push(Node {
+            bounds: Aabb::EMPTY,
+            start: start + mid as u32,
+            count: count - mid as u32,
+        });
+        self.nodes[node].start = left as u32;
+        self.nodes[node].count = 0;
+        self.split(left, bounds);
+        self.split(left + 1, bounds);
+    }
+
+    /// Visits the primitives whose bounds the ray enters before the closest hit found so far.
+    ///
+    /// `intersect` is called with a primitive and the current closest distance and returns the
+    /// distance of a closer hit, if any. Returns the closest hit.
+    pub(crate) fn closest_hit(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        mut t_max: f32,
+        mut intersect: impl FnMut(u32, f32) -> Option<f32>,
+    ) -> Option<f32> {
+        let inv_dir = direction.recip();
+        let mut closest = None;
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop() {
+            let Node {
+                bounds,
+                start,
+                count,
+            } = self.nodes[node];
+            if bounds.entry(origin, inv_dir, t_max).is_none() {
+                continue;
+            }
+            if count == 0 {
+                stack.extend([start as usize, start as usize + 1]);
+                continue;
+            }
+            for &primitive in &self.primitives[start as usize..(start + count) as usize] {
+                if let Some(t) = intersect(primitive, t_max) {
+                    t_max = t;
+                    closest = Some(t);
+                }
+            }
+        }
+        closest
+    }
+}
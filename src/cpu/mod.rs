@@ -0,0 +1,538 @@
+//! Software ray tracing for devices without ray queries.
+//!
+//! When the device lacks [`crate::utils::RAYTRACING_FEATURES`], a [`crate::RayTraceScene`]
+//! builds a BVH per asset and one over the instances on the CPU instead of acceleration
+//! structures, and the synchronous renders of [`crate::lidar::Lidar`] and
+//! [`crate::depth_camera::DepthCamera`] trace their rays with rayon. Outputs follow the layout of
+//! the GPU shaders, noise included, with two differences:
+//!
+//! * Custom hit shaders are WGSL and can not run on the CPU, the payload is always the range.
+//! * Recording into a command encoder, other than through [`crate::frame::FrameEncoder`] which
+//!   traces such renders when they are enqueued, and the planners and voxel utilities tracing
+//!   the TLAS still require ray queries.
+
+pub(crate) mod bvh;
+
+use glam::{Affine3A, Mat4, Vec3, Vec4, Vec4Swizzles};
+use rayon::prelude::*;
+
+use crate::{
+    noise::NoiseModel,
+    rng::{PcgStream, RngSeed},
+    AssetMesh, Instance,
+};
+use bvh::{Aabb, Bvh};
+
+/// Ranges of the LiDAR shaders.
+pub(crate) const LIDAR_T_MIN: f32 = 0.1;
+pub(crate) const LIDAR_T_MAX: f32 = 50.0;
+/// Ranges of the depth camera shaders.
+pub(crate) const CAMERA_T_MIN: f32 = 0.1;
+pub(crate) const CAMERA_T_MAX: f32 = 200.0;
+
+/// Which hits the rays of a sensor accept, like the cull mask and `t_min` of a ray query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RayFilter {
+    /// Instances whose mask shares no bit with it are not hit.
+    pub(crate) cull_mask: u8,
+    /// Hits at or closer than it are skipped.
+    pub(crate) t_min: f32,
+}
+
+impl RayFilter {
+    /// The filter of a LiDAR with default settings.
+    pub(crate) const LIDAR: Self = Self {
+        cull_mask: 0xff,
+        t_min: LIDAR_T_MIN,
+    };
+    /// The filter of a depth camera with default settings.
+    pub(crate) const CAMERA: Self = Self {
+        cull_mask: 0xff,
+        t_min: CAMERA_T_MIN,
+    };
+}
+
+/// The triangles of one asset and their hierarchy.
+struct CpuMesh {
+    triangles: Vec<[Vec3; 3]>,
+    bounds: Aabb,
+    bvh: Bvh,
+}
+
+impl CpuMesh {
+    fn new(asset: &AssetMesh) -> Self {
+        let position = |i: u16| Vec3::from_slice(&asset.vertex_buf[i as usize]._pos[..3]);
+        let triangles: Vec<_> = asset
+            .index_buf
+            .chunks_exact(3)
+            .map(|t| [position(t[0]), position(t[1]), position(t[2])])
+            .collect();
+        let triangle_bounds: Vec<_> = triangles
+            .iter()
+            .map(|t| Aabb::from_points(t.iter().copied()))
+            .collect();
+        Self {
+            bounds: triangle_bounds
+                .iter()
+                .fold(Aabb::EMPTY, |aabb, t| aabb.union(t)),
+            bvh: Bvh::build(&triangle_bounds),
+            triangles,
+        }
+    }
+
+    /// Distance to the closest triangle hit in `(t_min, t_max)`, in the units of `direction`.
+    fn intersect(&self, origin: Vec3, direction: Vec3, t_min: f32, t_max: f32) -> Option<f32> {
+        self.bvh
+            .closest_hit(origin, direction, t_max, |triangle, t_max| {
+                intersect_triangle(
+                    &self.triangles[triangle as usize],
+                    origin,
+                    direction,
+                    t_min,
+                    t_max,
+                )
+            })
+    }
+}
+
+/// Möller-Trumbore intersection, culling nothing like the opaque geometry of the GPU scenes.
+fn intersect_triangle(
+    [v0, v1, v2]: &[Vec3; 3],
+    origin: Vec3,
+    direction: Vec3,
+    t_min: f32,
+    t_max: f32,
+) -> Option<f32> {
+    let e1 = *v1 - *v0;
+    let e2 = *v2 - *v0;
+    let p = direction.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f32::EPSILON * e1.length() * e2.length() * direction.length() {
+        return None;
+    }
+    let inv_det = det.recip();
+    let s = origin - *v0;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    (t > t_min && t < t_max).then_some(t)
+}
+
+/// An instance, the inverse of its transform, its mask and its id.
+struct CpuInstance {
+    mesh: usize,
+    world_to_object: Affine3A,
+    mask: u8,
+    id: u32,
+}
+
+/// The CPU counterpart of a scene's BLAS and TLAS.
+pub(crate) struct CpuScene {
+    meshes: Vec<CpuMesh>,
+    instances: Vec<CpuInstance>,
+    bvh: Bvh,
+}
+
+impl CpuScene {
+    pub(crate) fn new(assets: &[AssetMesh], instances: &[Instance], masks: &[u8]) -> Self {
+        let mut scene = Self {
+            meshes: assets.par_iter().map(CpuMesh::new).collect(),
+            instances: vec![],
+            bvh: Bvh::default(),
+        };
+        scene.set_instances(instances, masks);
+        scene
+    }
+
+    /// Rebuilds the hierarchy of the asset at `index` from its current geometry. The bounds of
+    /// its instances are only updated by [`CpuScene::set_instances`].
+    pub(crate) fn set_mesh(&mut self, index: usize, asset: &AssetMesh) {
+        self.meshes[index] = CpuMesh::new(asset);
+    }
+
+    /// Replaces the instances and their masks and rebuilds the hierarchy over them.
+    pub(crate) fn set_instances(&mut self, instances: &[Instance], masks: &[u8]) {
+        let bounds: Vec<_> = instances
+            .iter()
+            .map(|instance| {
+                self.meshes[instance.asset_mesh_index]
+                    .bounds
+                    .transformed(&instance.transform)
+            })
+            .collect();
+        self.instances = instances
+            .iter()
+            .zip(masks)
+            .map(|(instance, &mask)| CpuInstance {
+                mesh: instance.asset_mesh_index,
+                world_to_object: instance.transform.inverse(),
+                mask,
+                id: instance.id,
+            })
+            .collect();
+        self.bvh = Bvh::build(&bounds);
+    }
+
+    /// Distance to the closest hit in `(filter.t_min, t_max)` of an instance the filter does not
+    /// cull, in the units of `direction` like a ray query.
+    pub(crate) fn intersect(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        filter: RayFilter,
+        t_max: f32,
+    ) -> Option<f32> {
+        self.intersect_instance(origin, direction, filter, t_max)
+            .map(|(t, _)| t)
+    }
+
+    /// The closest hit like [`CpuScene::intersect`] and the id of the instance hit.
+    pub(crate) fn intersect_instance(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        filter: RayFilter,
+        t_max: f32,
+    ) -> Option<(f32, u32)> {
+        // Every hit is closer than the previous one, so the last id is the closest hit's.
+        let mut id = 0;
+        let t = self
+            .bvh
+            .closest_hit(origin, direction, t_max, |instance, t_max| {
+                let instance = &self.instances[instance as usize];
+                if instance.mask & filter.cull_mask == 0 {
+                    return None;
+                }
+                // Tracing in object space keeps `t` as long as the direction is not normalized.
+                let t = self.meshes[instance.mesh].intersect(
+                    instance.world_to_object.transform_point3(origin),
+                    instance.world_to_object.transform_vector3(direction),
+                    filter.t_min,
+                    t_max,
+                )?;
+                id = instance.id;
+                Some(t)
+            })?;
+        Some((t, id))
+    }
+}
+
+/// Applies `noise` the way `apply_noise` does for the invocation `index`. `None` if dropped.
+fn apply_noise(
+    range: f32,
+    noise: Option<&dyn NoiseModel>,
+    seed: RngSeed,
+    index: u32,
+) -> Option<f32> {
+    match noise {
+        Some(noise) => noise.apply(range, &mut PcgStream::new(seed, index)),
+        None => Some(range),
+    }
+}
+
+/// Traces the beams of a LiDAR, laid out like the output of `shader.wgsl`.
+pub(crate) fn trace_lidar_beams(
+    scene: &CpuScene,
+    directions: &[Vec4],
+    pose: &Affine3A,
+    filter: RayFilter,
+    noise: Option<&dyn NoiseModel>,
+    seed: RngSeed,
+) -> Vec<f32> {
+    directions
+        .par_iter()
+        .enumerate()
+        .map(|(i, direction)| {
+            scene
+                .intersect(
+                    pose.translation.into(),
+                    pose.matrix3 * direction.xyz(),
+                    filter,
+                    LIDAR_T_MAX,
+                )
+                .map_or(0.0, |t| {
+                    apply_noise(t, noise, seed, i as u32).unwrap_or(0.0)
+                })
+        })
+        .collect()
+}
+
+/// Traces the point cloud of a LiDAR, laid out like the output of `shader.pointcloud.wgsl`.
+pub(crate) fn trace_lidar_pointcloud(
+    scene: &CpuScene,
+    directions: &[Vec4],
+    pose: &Affine3A,
+    filter: RayFilter,
+    noise: Option<&dyn NoiseModel>,
+    seed: RngSeed,
+) -> Vec<f32> {
+    directions
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(i, direction)| {
+            let direction = direction.xyz();
+            let range = scene
+                .intersect(
+                    pose.translation.into(),
+                    pose.matrix3 * direction,
+                    filter,
+                    LIDAR_T_MAX,
+                )
+                .and_then(|t| apply_noise(t, noise, seed, i as u32));
+            match range {
+                Some(range) => (range * direction).extend(range).to_array(),
+                None => [10000.0, 10000.0, 100000.0, 100000.0],
+            }
+        })
+        .collect()
+}
+
+/// Traces the ids of the instances the beams of a LiDAR hit, laid out like the output of
+/// `shader.instance_ids.wgsl`.
+pub(crate) fn trace_lidar_instance_ids(
+    scene: &CpuScene,
+    directions: &[Vec4],
+    pose: &Affine3A,
+    filter: RayFilter,
+) -> Vec<u32> {
+    directions
+        .par_iter()
+        .map(|direction| {
+            scene
+                .intersect_instance(
+                    pose.translation.into(),
+                    pose.matrix3 * direction.xyz(),
+                    filter,
+                    LIDAR_T_MAX,
+                )
+                .map_or(Instance::NO_HIT, |(_, id)| id)
+        })
+        .collect()
+}
+
+/// The ray through the center of pixel `(x, y)`, as computed by the depth camera shaders.
+pub(crate) fn camera_ray(
+    view_inverse: &Mat4,
+    proj_inverse: &Mat4,
+    x: u32,
+    y: u32,
+    size: (u32, u32),
+) -> (Vec3, Vec3) {
+    let d = (glam::Vec2::new(x as f32, y as f32) + 0.5)
+        / glam::Vec2::new(size.0 as f32, size.1 as f32)
+        * 2.0
+        - 1.0;
+    let origin = (*view_inverse * Vec4::W).xyz();
+    let target = (*proj_inverse * Vec4::new(d.x, d.y, 1.0, 1.0))
+        .xyz()
+        .normalize();
+    (origin, (*view_inverse * target.extend(0.0)).xyz())
+}
+
+/// Traces a depth image, laid out like the output of the depth camera's `shader.wgsl`.
+pub(crate) fn trace_depth_image(
+    scene: &CpuScene,
+    view_inverse: &Mat4,
+    proj_inverse: &Mat4,
+    size: (u32, u32),
+    filter: RayFilter,
+    noise: Option<&dyn NoiseModel>,
+    seed: RngSeed,
+) -> Vec<f32> {
+    (0..size.0 * size.1)
+        .into_par_iter()
+        .map(|i| {
+            let (origin, direction) =
+                camera_ray(view_inverse, proj_inverse, i / size.1, i % size.1, size);
+            scene
+                .intersect(origin, direction, filter, CAMERA_T_MAX)
+                .and_then(|t| apply_noise(t, noise, seed, i))
+                .unwrap_or(99999.0)
+        })
+        .collect()
+}
+
+/// Traces a depth camera point cloud, laid out like the output of `shader.pointcloud.wgsl`.
+pub(crate) fn trace_depth_pointcloud(
+    scene: &CpuScene,
+    view_inverse: &Mat4,
+    proj_inverse: &Mat4,
+    size: (u32, u32),
+    filter: RayFilter,
+) -> Vec<Vec4> {
+    (0..size.0 * size.1)
+        .into_par_iter()
+        .map(|i| {
+            let (origin, direction) =
+                camera_ray(view_inverse, proj_inverse, i / size.1, i % size.1, size);
+            scene
+                .intersect(origin, direction, filter, CAMERA_T_MAX)
+                .map_or(Vec4::ZERO, |t| direction.extend(t))
+        })
+        .collect()
+}
+
+/// Traces the ids of the instances the pixels of a depth camera hit, laid out like the output of
+/// the depth camera's `shader.instance_ids.wgsl`.
+pub(crate) fn trace_depth_instance_ids(
+    scene: &CpuScene,
+    view_inverse: &Mat4,
+    proj_inverse: &Mat4,
+    size: (u32, u32),
+    filter: RayFilter,
+) -> Vec<u32> {
+    (0..size.0 * size.1)
+        .into_par_iter()
+        .map(|i| {
+            let (origin, direction) =
+                camera_ray(view_inverse, proj_inverse, i / size.1, i % size.1, size);
+            scene
+                .intersect_instance(origin, direction, filter, CAMERA_T_MAX)
+                .map_or(Instance::NO_HIT, |(_, id)| id)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn test_cpu_scene_matches_cube_geometry() {
+    use crate::utils::create_cube;
+
+    let instances = [
+        Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        },
+        Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_translation(Vec3::new(10.0, 0.0, 0.0)),
+            id: 0,
+        },
+    ];
+    let mut scene = CpuScene::new(&[create_cube(1.0)], &instances, &[0xff, 0xff]);
+    let directions = [Vec4::X, Vec4::NEG_X, Vec4::Z];
+    let pose = Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0));
+    let beams = trace_lidar_beams(
+        &scene,
+        &directions,
+        &pose,
+        RayFilter::LIDAR,
+        None,
+        RngSeed::default(),
+    );
+    assert_eq!(beams, [4.0, 4.0, 0.0]);
+
+    // Rotating the sensor rotates the beams.
+    let pose = pose * Affine3A::from_rotation_y(std::f32::consts::FRAC_PI_2);
+    let beams = trace_lidar_beams(
+        &scene,
+        &directions,
+        &pose,
+        RayFilter::LIDAR,
+        None,
+        RngSeed::default(),
+    );
+    assert!((beams[2] - 4.0).abs() < 1e-5);
+
+    // Moving an instance updates the hierarchy.
+    scene.set_instances(
+        &[
+            instances[0].clone(),
+            Instance {
+                asset_mesh_index: 0,
+                transform: Affine3A::from_translation(Vec3::new(8.0, 0.0, 0.0)),
+                id: 0,
+            },
+        ],
+        &[0xff, 0xff],
+    );
+    assert_eq!(
+        scene.intersect(
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::X,
+            RayFilter::LIDAR,
+            LIDAR_T_MAX
+        ),
+        Some(2.0)
+    );
+    // `t` is measured in units of the direction, like a ray query.
+    assert_eq!(
+        scene.intersect(
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::X * 2.0,
+            RayFilter::LIDAR,
+            LIDAR_T_MAX
+        ),
+        Some(1.0)
+    );
+
+    // Rays skip instances outside their cull mask and hits closer than their `t_min`.
+    scene.set_instances(&instances, &[0x01, 0x02]);
+    let origin = Vec3::new(5.0, 0.0, 0.0);
+    let filter = RayFilter {
+        cull_mask: 0x02,
+        ..RayFilter::LIDAR
+    };
+    assert_eq!(
+        scene.intersect(origin, Vec3::NEG_X, filter, LIDAR_T_MAX),
+        None
+    );
+    assert_eq!(
+        scene.intersect(origin, Vec3::X, filter, LIDAR_T_MAX),
+        Some(4.0)
+    );
+    let filter = RayFilter {
+        t_min: 4.5,
+        ..RayFilter::LIDAR
+    };
+    assert_eq!(
+        scene.intersect(origin, Vec3::X, filter, LIDAR_T_MAX),
+        Some(6.0)
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_sensors_render_on_any_device() {
+    use crate::{
+        depth_camera::DepthCamera, lidar::Lidar, utils::create_cube, utils::get_gpu, RayTraceScene,
+    };
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let mut lidar = Lidar::new(&device, vec![Vec3::NEG_X]).await;
+    let pose = Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0));
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+
+    let mut camera = DepthCamera::new(&device, 8, 8, 30.0, 10.0).await;
+    let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 4.0), Vec3::ZERO, Vec3::Y);
+    let depth = camera
+        .render_depth_camera(&scene, &device, &queue, view)
+        .await;
+    // The pixels next to the center look at the front face, 3m away.
+    let center = depth[4 * 8 + 4];
+    assert!(center > 3.0 && center < 3.05, "{center}");
+}
@@ -1,10 +1,30 @@
-use std::{borrow::Cow, iter};
+use std::sync::Arc;
 
 use bytemuck_derive::{Pod, Zeroable};
 use glam::{Mat4, Vec3, Vec4};
-use wgpu::util::DeviceExt;
-
-use crate::RayTraceScene;
+use half::f16;
+
+use crate::{
+    cpu,
+    hit_shader::HitShader,
+    noise::{self, NoiseModel, NoiseParameters},
+    pipeline_cache::{wgpu_cache, PipelineCache},
+    pose::IntoMat4,
+    rng::{GpuRng, RngSeed},
+    shaders,
+    utils::{
+        bind_layout::{
+            create_compute_layout, create_pipeline_layout, ACCELERATION_STRUCTURE, STORAGE, UNIFORM,
+        },
+        buffer_pool::BufferPool,
+        half_pack::HalfPacker,
+        pop_validation_scope,
+        readback_ring::ReadbackRing,
+        supports_ray_queries,
+        uniform_belt::UniformBelt,
+    },
+    Error, RayTraceScene,
+};
 
 /// Depth camera uniforms.
 #[repr(C)]
@@ -14,18 +34,112 @@ struct DepthCameraUniforms {
     proj_inverse: Mat4,
     width: u32,
     height: u32,
-    padding: [f32; 2],
+    /// Which hits the rays accept, see [`DepthCamera::set_cull_mask`].
+    cull_mask: u32,
+    t_min: f32,
+}
+
+/// The compute pipelines of a depth camera on a device supporting ray queries.
+struct DepthCameraPipelines {
+    /// Shared by all pipelines. The point cloud and instance id shaders ignore the noise and
+    /// seed.
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    pointcloud_pipeline: wgpu::ComputePipeline,
+    instance_id_pipeline: wgpu::ComputePipeline,
+}
+
+impl DepthCameraPipelines {
+    async fn new(
+        device: &wgpu::Device,
+        hit_shader: &HitShader,
+        cache: Option<&PipelineCache>,
+    ) -> Result<Self, Error> {
+        let camera_shader = shaders::compile(
+            device,
+            "rt_computer",
+            &[
+                shaders::RNG.source(),
+                shaders::NOISE.source(),
+                shaders::DEPTH_CAMERA.source(),
+            ]
+            .join("\n"),
+        )
+        .await?;
+
+        let pointcloud_shader = shaders::compile(
+            device,
+            "rt_pointcloud",
+            &hit_shader.compose(&[], &shaders::DEPTH_CAMERA_POINTCLOUD.source()),
+        )
+        .await?;
+
+        let instance_id_shader = shaders::compile(
+            device,
+            "rt_instance_ids",
+            &shaders::DEPTH_CAMERA_INSTANCE_IDS.source(),
+        )
+        .await?;
+
+        let bind_group_layout = create_compute_layout(
+            device,
+            "Depth Camera Bind Group Layout",
+            &[UNIFORM, ACCELERATION_STRUCTURE, STORAGE, UNIFORM, UNIFORM],
+        );
+        let pipeline_layout = create_pipeline_layout(device, &bind_group_layout);
+
+        Ok(Self {
+            pipeline: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("rt"),
+                layout: Some(&pipeline_layout),
+                module: &camera_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: wgpu_cache(cache),
+            }),
+            pointcloud_pipeline: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("rt"),
+                layout: Some(&pipeline_layout),
+                module: &pointcloud_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: wgpu_cache(cache),
+            }),
+            instance_id_pipeline: device.create_compute_pipeline(
+                &wgpu::ComputePipelineDescriptor {
+                    label: Some("rt_instance_ids"),
+                    layout: Some(&pipeline_layout),
+                    module: &instance_id_shader,
+                    entry_point: Some("main"),
+                    compilation_options: Default::default(),
+                    cache: wgpu_cache(cache),
+                },
+            ),
+            bind_group_layout,
+        })
+    }
 }
 
 /// Represents a depth camera sensor.
 ///
 /// This struct manages the compute pipelines and uniforms required for simulating a depth camera.
+///
+/// Sensors are `Send + Sync`. Rendering mutates their buffers and random state, so each thread
+/// renders its own sensors.
 pub struct DepthCamera {
-    pipeline: wgpu::ComputePipeline,
-    pointcloud_pipeline: wgpu::ComputePipeline,
+    /// `None` on devices without ray queries.
+    pipelines: Option<DepthCameraPipelines>,
     uniforms: DepthCameraUniforms,
     width: u32,
     height: u32,
+    noise_model: Option<Box<dyn NoiseModel>>,
+    rng: GpuRng,
+    buffers: Arc<BufferPool>,
+    depth_frames: ReadbackRing,
+    /// Created on the first half precision render.
+    half_packer: Option<HalfPacker>,
+    #[cfg(feature = "shader-hot-reload")]
+    hot_reload: shaders::HotReload,
 }
 
 impl DepthCamera {
@@ -45,6 +159,68 @@ impl DepthCamera {
         fov_y: f32,
         _max_depth: f32,
     ) -> Self {
+        Self::with_hit_shader(
+            device,
+            width,
+            height,
+            fov_y,
+            _max_depth,
+            &HitShader::default(),
+        )
+        .await
+        .expect("The built-in depth camera shaders are valid")
+    }
+
+    /// Creates a new depth camera sensor with a custom hit shader.
+    ///
+    /// The hit shader computes the fourth channel of every point returned by
+    /// [`DepthCamera::render_depth_camera_pointcloud`]. [`DepthCamera::new`] uses
+    /// [`HitShader::default`], which returns the range along the pixel's ray. Older versions
+    /// always wrote `1.0`, which a hit shader returning `1.0` reproduces.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use for creating GPU resources.
+    /// * `width` - The width of the depth camera image in pixels.
+    /// * `height` - The height of the depth camera image in pixels.
+    /// * `fov_y` - The vertical field of view in degrees.
+    /// * `_max_depth` - The maximum depth value.
+    /// * `hit_shader` - The WGSL hit shader spliced into the point cloud pipeline.
+    ///
+    /// Fails with [`Error::ShaderCompilation`] if the hit shader does not compile.
+    pub async fn with_hit_shader(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        fov_y: f32,
+        _max_depth: f32,
+        hit_shader: &HitShader,
+    ) -> Result<Self, Error> {
+        Self::with_pipeline_cache(device, width, height, fov_y, _max_depth, hit_shader, None).await
+    }
+
+    /// Creates a new depth camera sensor, compiling its pipelines through a pipeline cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use for creating GPU resources.
+    /// * `width` - The width of the depth camera image in pixels.
+    /// * `height` - The height of the depth camera image in pixels.
+    /// * `fov_y` - The vertical field of view in degrees.
+    /// * `_max_depth` - The maximum depth value.
+    /// * `hit_shader` - The WGSL hit shader spliced into the point cloud pipeline.
+    /// * `cache` - The cache to look up and store the compiled pipelines in.
+    ///
+    /// Fails with [`Error::ShaderCompilation`] if the hit shader does not compile.
+    pub async fn with_pipeline_cache(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        fov_y: f32,
+        _max_depth: f32,
+        hit_shader: &HitShader,
+        cache: Option<&PipelineCache>,
+    ) -> Result<Self, Error> {
         let uniforms = {
             let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 2.5), Vec3::ZERO, Vec3::Y);
             let proj = Mat4::perspective_rh(
@@ -59,41 +235,59 @@ impl DepthCamera {
                 proj_inverse: proj.inverse(),
                 width,
                 height,
-                padding: [0.0; 2],
+                cull_mask: cpu::RayFilter::CAMERA.cull_mask.into(),
+                t_min: cpu::RayFilter::CAMERA.t_min,
             }
         };
 
-        let camera_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("rt_computer"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-        });
-
-        let pointcloud_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("rt_computer"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.pointcloud.wgsl"))),
-        });
-
-        Self {
-            pipeline: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("rt"),
-                layout: None,
-                module: &camera_shader,
-                entry_point: Some("main"),
-                compilation_options: Default::default(),
-                cache: None,
-            }),
-            pointcloud_pipeline: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("rt"),
-                layout: None,
-                module: &pointcloud_shader,
-                entry_point: Some("main"),
-                compilation_options: Default::default(),
-                cache: None,
-            }),
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        // Without ray queries the shaders do not compile and scenes are traced on the CPU.
+        let pipelines = if supports_ray_queries(device) {
+            Some(DepthCameraPipelines::new(device, hit_shader, cache).await?)
+        } else {
+            None
+        };
+        pop_validation_scope(device).await?;
+        Ok(Self {
+            pipelines,
             uniforms,
             width,
             height,
+            noise_model: None,
+            rng: GpuRng::default(),
+            buffers: Arc::default(),
+            depth_frames: ReadbackRing::new(2),
+            half_packer: None,
+            #[cfg(feature = "shader-hot-reload")]
+            hot_reload: shaders::HotReload::new(
+                &[
+                    shaders::RNG,
+                    shaders::NOISE,
+                    shaders::HIT_INFO,
+                    shaders::DEPTH_CAMERA,
+                    shaders::DEPTH_CAMERA_POINTCLOUD,
+                    shaders::DEPTH_CAMERA_INSTANCE_IDS,
+                ],
+                hit_shader,
+            ),
+        })
+    }
+
+    /// Rebuilds the pipelines if a shader file they were built from changed on disk, see
+    /// [`crate::lidar::Lidar::reload_shaders`].
+    #[cfg(feature = "shader-hot-reload")]
+    pub async fn reload_shaders(&mut self, device: &wgpu::Device) -> Result<bool, Error> {
+        let Some(hit_shader) = self.hot_reload.poll() else {
+            return Ok(false);
+        };
+        if self.pipelines.is_none() {
+            return Ok(false);
         }
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = DepthCameraPipelines::new(device, hit_shader, None).await?;
+        pop_validation_scope(device).await?;
+        self.pipelines = Some(pipelines);
+        Ok(true)
     }
 
     /// Renders a depth image from the camera's perspective.
@@ -115,83 +309,297 @@ impl DepthCamera {
         scene: &RayTraceScene,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        view_matrix: Mat4,
+        view_matrix: impl IntoMat4,
     ) -> Vec<f32> {
+        let view_matrix = view_matrix.into_mat4();
+        if let Some(depth) = self.trace_on_cpu(scene, view_matrix) {
+            return depth;
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers =
+            self.encode_depth_image(scene, device, Some(queue), &mut encoder, view_matrix);
+        ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, buffers.into())
+            .unwrap()
+    }
+
+    /// Renders a depth image like [`DepthCamera::render_depth_camera`] as an array of `height`
+    /// rows of `width` depths, the top row first.
+    #[cfg(feature = "ndarray")]
+    pub async fn render_depth_camera_array(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_matrix: impl IntoMat4,
+    ) -> ndarray::Array2<f32> {
+        let view_matrix = view_matrix.into_mat4();
+        let depth = self
+            .render_depth_camera(scene, device, queue, view_matrix)
+            .await;
+        let height = self.height as usize;
+        // The camera renders column by column from the bottom.
+        ndarray::Array2::from_shape_fn((height, self.width as usize), |(row, column)| {
+            depth[column * height + height - 1 - row]
+        })
+    }
+
+    /// Renders a depth image like [`DepthCamera::render_depth_camera`], converted to half
+    /// precision on the GPU to halve the readback.
+    ///
+    /// Depths are rounded relative to their magnitude, to 2mm below 4m and 3cm between 32m and
+    /// 64m. Pixels that did not hit anything read as infinity, since
+    /// [`DepthCamera::no_hit_const`] does not fit in 16 bits.
+    pub async fn render_depth_camera_f16(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_matrix: impl IntoMat4,
+    ) -> Vec<f16> {
+        let view_matrix = view_matrix.into_mat4();
+        if let Some(depth) = self.trace_on_cpu(scene, view_matrix) {
+            return depth.into_iter().map(f16::from_f32).collect();
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers =
+            self.encode_depth_image(scene, device, Some(queue), &mut encoder, view_matrix);
+        let count = self.width * self.height;
+        let mut packed = self
+            .half_packer
+            .get_or_insert_with(|| HalfPacker::new(device))
+            .encode(
+                device,
+                queue,
+                &self.buffers,
+                &mut encoder,
+                &buffers[0],
+                count,
+            );
+        packed.extend(buffers);
+        let mut result: Vec<f16> = ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, packed)
+            .unwrap();
+        result.truncate(count as usize);
+        result
+    }
+
+    /// Queues a depth image without waiting for it.
+    ///
+    /// The GPU renders up to [`DepthCamera::frames_in_flight`] images back to back, so a camera
+    /// publishing at a fixed rate is not throttled by the readback of every frame. Once the queue
+    /// is full, each submission waits for the oldest image and returns it, laid out as in
+    /// [`DepthCamera::render_depth_camera`]. Use [`DepthCamera::flush_depth_images`] to collect
+    /// the images still queued.
+    ///
+    /// Scenes traced on the CPU are rendered right away and every submission returns its own
+    /// image.
+    ///
+    /// # Arguments
+    ///
+    /// * `scene` - The `RayTraceScene` to render.
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
+    /// * `view_matrix` - The `Mat4` view matrix of the camera.
+    pub async fn submit_depth_camera(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_matrix: impl IntoMat4,
+    ) -> Option<Vec<f32>> {
+        let view_matrix = view_matrix.into_mat4();
+        if let Some(depth) = self.trace_on_cpu(scene, view_matrix) {
+            return Some(depth);
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers =
+            self.encode_depth_image(scene, device, Some(queue), &mut encoder, view_matrix);
+        self.depth_frames
+            .submit(device, queue, &self.buffers, encoder, buffers.into())
+    }
+
+    /// Waits for the images queued by [`DepthCamera::submit_depth_camera`] and returns them
+    /// oldest first.
+    pub fn flush_depth_images(&mut self, device: &wgpu::Device) -> Vec<Vec<f32>> {
+        self.depth_frames.flush(device, &self.buffers)
+    }
+
+    /// Records a depth image render into an existing command encoder.
+    ///
+    /// Unlike [`DepthCamera::render_depth_camera`] nothing is read back; the returned buffer holds
+    /// one `f32` range per pixel (column-major, `x * height + y`) once the encoder is submitted, so it
+    /// can be consumed by further GPU passes.
+    ///
+    /// # Arguments
+    ///
+    /// * `scene` - The `RayTraceScene` to render.
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `encoder` - The command encoder to record into.
+    /// * `view_matrix` - The `Mat4` view matrix of the camera.
+    pub fn encode_depth_camera(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        view_matrix: impl IntoMat4,
+    ) -> wgpu::Buffer {
+        let view_matrix = view_matrix.into_mat4();
+        let [raw_buf, ..] = self.encode_depth_image(scene, device, None, encoder, view_matrix);
+        raw_buf
+    }
+
+    /// Traces the depth image on the CPU if `scene` is traced on the CPU.
+    pub(crate) fn trace_on_cpu(
+        &mut self,
+        scene: &RayTraceScene,
+        view_matrix: Mat4,
+    ) -> Option<Vec<f32>> {
+        let scene = scene.cpu()?;
         self.uniforms.view_inverse = view_matrix.inverse();
+        Some(cpu::trace_depth_image(
+            scene,
+            &self.uniforms.view_inverse,
+            &self.uniforms.proj_inverse,
+            (self.width, self.height),
+            self.ray_filter(),
+            self.noise_model.as_deref(),
+            self.rng.next_seed(),
+        ))
+    }
 
-        let compute_bind_group_layout = self.pipeline.get_bind_group_layout(0);
+    /// Records a depth image render and returns the output and uniform buffers.
+    ///
+    /// With a `queue` the buffers come from the camera's pool and the caller hands them back once
+    /// the submission completed. Without one they are freshly allocated, since the caller may
+    /// record several renders before submitting.
+    pub(crate) fn encode_depth_image(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: Option<&wgpu::Queue>,
+        encoder: &mut wgpu::CommandEncoder,
+        view_matrix: Mat4,
+    ) -> [wgpu::Buffer; 2] {
+        self.encode_pixels(scene, device, queue, encoder, view_matrix, |pipelines| {
+            &pipelines.pipeline
+        })
+    }
 
-        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[self.uniforms]),
-            usage: wgpu::BufferUsages::UNIFORM,
-        });
-        let raw_buf = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (self.width * self.height * 4) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
+    /// Records a render of `pipeline`, which writes 4 bytes per pixel, like
+    /// [`DepthCamera::encode_depth_image`].
+    fn encode_pixels(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: Option<&wgpu::Queue>,
+        encoder: &mut wgpu::CommandEncoder,
+        view_matrix: Mat4,
+        pipeline: impl FnOnce(&DepthCameraPipelines) -> &wgpu::ComputePipeline,
+    ) -> [wgpu::Buffer; 2] {
+        self.uniforms.view_inverse = view_matrix.inverse();
+        let pipelines = self
+            .pipelines
+            .as_ref()
+            .expect("Recording a depth camera render requires a device supporting ray queries");
+
+        let mut uniforms = UniformBelt::new(device);
+        let camera_slot = uniforms.push(&self.uniforms);
+        let noise_slot = uniforms.push(&noise::parameters_of(self.noise_model.as_deref()));
+        let rng_slot = uniforms.push(&self.rng.next_seed());
+        let uniform_buf = uniforms.finish(device, queue, &self.buffers);
+
+        let size = (self.width * self.height * 4) as u64;
+        let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC;
+        let raw_buf = match queue {
+            Some(_) => self.buffers.take(device, size, usage),
+            None => device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage,
+                mapped_at_creation: false,
+            }),
+        };
 
         let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            layout: &compute_bind_group_layout,
+            layout: &pipelines.bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: uniform_buf.as_entire_binding(),
+                    resource: camera_slot.binding(&uniform_buf),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::AccelerationStructure(&scene.tlas_package),
+                    resource: wgpu::BindingResource::AccelerationStructure(scene.tlas()),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
                     resource: raw_buf.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: noise_slot.binding(&uniform_buf),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: rng_slot.binding(&uniform_buf),
+                },
             ],
         });
 
-        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: raw_buf.size(),
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
-        encoder.build_acceleration_structures(iter::empty(), iter::once(&scene.tlas_package));
+        scene.encode_tlas_update(encoder);
 
         {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: None,
                 timestamp_writes: None,
             });
-            cpass.set_pipeline(&self.pipeline);
+            cpass.set_pipeline(pipeline(pipelines));
             cpass.set_bind_group(0, Some(&compute_bind_group), &[]);
             cpass.dispatch_workgroups(self.width / 8, self.height / 8, 1);
         }
-        encoder.copy_buffer_to_buffer(&raw_buf, 0, &staging_buffer, 0, staging_buffer.size());
-
-        queue.submit(Some(encoder.finish()));
-        let buffer_slice = staging_buffer.slice(..);
-        let (sender, receiver) = flume::bounded(1);
-        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-
-        device.poll(wgpu::PollType::wait()).unwrap();
-
-        receiver.recv().unwrap().unwrap();
-
-        {
-            let view = buffer_slice.get_mapped_range();
-            let result: Vec<f32> = bytemuck::cast_slice(&view).to_vec();
+        [raw_buf, uniform_buf]
+    }
 
-            drop(view);
-            staging_buffer.unmap();
-            result
+    /// Renders the id of the instance each pixel hits, see [`crate::Instance::id`], laid out like
+    /// the image of [`DepthCamera::render_depth_camera`], so it can be segmented by object.
+    ///
+    /// Pixels without a hit read [`crate::Instance::NO_HIT`]. The ids are traced without noise.
+    pub async fn render_depth_camera_instance_ids(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_matrix: impl IntoMat4,
+    ) -> Vec<u32> {
+        let view_matrix = view_matrix.into_mat4();
+        if let Some(scene) = scene.cpu() {
+            self.uniforms.view_inverse = view_matrix.inverse();
+            return cpu::trace_depth_instance_ids(
+                scene,
+                &self.uniforms.view_inverse,
+                &self.uniforms.proj_inverse,
+                (self.width, self.height),
+                self.ray_filter(),
+            );
         }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers = self.encode_pixels(
+            scene,
+            device,
+            Some(queue),
+            &mut encoder,
+            view_matrix,
+            |pipelines| &pipelines.instance_id_pipeline,
+        );
+        ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, buffers.into())
+            .unwrap()
     }
 
     /// Renders a point cloud from the camera's perspective.
@@ -208,66 +616,86 @@ impl DepthCamera {
     /// # Returns
     ///
     /// A `Vec<Vec4>` containing the point cloud data, where each point is represented by a `Vec4` (x, y, z, w).
+    /// The `w` channel is computed by the camera's hit shader and defaults to the hit distance.
     pub async fn render_depth_camera_pointcloud(
         &mut self,
         scene: &RayTraceScene,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        view_matrix: Mat4,
+        view_matrix: impl IntoMat4,
     ) -> Vec<Vec4> {
+        let view_matrix = view_matrix.into_mat4();
         self.uniforms.view_inverse = view_matrix.inverse();
-
-        let compute_bind_group_layout = self.pipeline.get_bind_group_layout(0);
-
-        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[self.uniforms]),
-            usage: wgpu::BufferUsages::UNIFORM,
-        });
-        let raw_buf = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (self.width * self.height * 4 * 4) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
+        if let Some(scene) = scene.cpu() {
+            return cpu::trace_depth_pointcloud(
+                scene,
+                &self.uniforms.view_inverse,
+                &self.uniforms.proj_inverse,
+                (self.width, self.height),
+                self.ray_filter(),
+            );
+        }
+        let pipelines = self
+            .pipelines
+            .as_ref()
+            .expect("Tracing on the GPU requires a device supporting ray queries");
+
+        let mut uniforms = UniformBelt::new(device);
+        let camera_slot = uniforms.push(&self.uniforms);
+        // Unused by the point cloud shader, but part of the shared layout.
+        let noise_slot = uniforms.push(&NoiseParameters::default());
+        let rng_slot = uniforms.push(&RngSeed::default());
+        let uniform_buf = uniforms.finish(device, Some(queue), &self.buffers);
+        let raw_buf = self.buffers.take(
+            device,
+            (self.width * self.height * 4 * 4) as u64,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
 
         let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            layout: &compute_bind_group_layout,
+            layout: &pipelines.bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: uniform_buf.as_entire_binding(),
+                    resource: camera_slot.binding(&uniform_buf),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::AccelerationStructure(&scene.tlas_package),
+                    resource: wgpu::BindingResource::AccelerationStructure(scene.tlas()),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
                     resource: raw_buf.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: noise_slot.binding(&uniform_buf),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: rng_slot.binding(&uniform_buf),
+                },
             ],
         });
 
-        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: raw_buf.size(),
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let staging_buffer = self.buffers.take(
+            device,
+            raw_buf.size(),
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
 
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        encoder.build_acceleration_structures(iter::empty(), iter::once(&scene.tlas_package));
+        scene.encode_tlas_update(&mut encoder);
 
         {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: None,
                 timestamp_writes: None,
             });
-            cpass.set_pipeline(&self.pointcloud_pipeline);
+            cpass.set_pipeline(&pipelines.pointcloud_pipeline);
             cpass.set_bind_group(0, Some(&compute_bind_group), &[]);
             cpass.dispatch_workgroups(self.width / 8, self.height / 8, 1);
         }
@@ -288,10 +716,112 @@ impl DepthCamera {
 
             drop(view);
             staging_buffer.unmap();
+            self.buffers.recycle([uniform_buf, raw_buf, staging_buffer]);
             result
         }
     }
 
+    /// Renders a point cloud like [`DepthCamera::render_depth_camera_pointcloud`] as an array of
+    /// one row of x, y, z and w per pixel.
+    #[cfg(feature = "ndarray")]
+    pub async fn render_depth_camera_pointcloud_array(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_matrix: impl IntoMat4,
+    ) -> ndarray::Array2<f32> {
+        let view_matrix = view_matrix.into_mat4();
+        let points = self
+            .render_depth_camera_pointcloud(scene, device, queue, view_matrix)
+            .await;
+        let rows = points.len();
+        let values = points.iter().flat_map(|p| p.to_array()).collect();
+        ndarray::Array2::from_shape_vec((rows, 4), values).expect("Points have 4 floats")
+    }
+
+    /// Returns the depth value written for pixels that did not hit anything.
+    pub fn no_hit_const() -> f32 {
+        99999.0
+    }
+
+    /// Attaches a noise model to the sensor. It is applied to the depth image of every subsequent
+    /// render.
+    pub fn set_noise_model(&mut self, model: impl NoiseModel + 'static) {
+        self.noise_model = Some(Box::new(model));
+    }
+
+    /// Removes any attached noise model.
+    pub fn clear_noise_model(&mut self) {
+        self.noise_model = None;
+    }
+
+    /// Swaps the noise model, e.g. to render the exact geometry and restore it afterwards.
+    #[cfg(feature = "validation")]
+    pub(crate) fn replace_noise_model(
+        &mut self,
+        model: Option<Box<dyn NoiseModel>>,
+    ) -> Option<Box<dyn NoiseModel>> {
+        std::mem::replace(&mut self.noise_model, model)
+    }
+
+    /// Sets the cull mask of the rays, `0xff` by default, see
+    /// [`crate::lidar::Lidar::set_cull_mask`].
+    pub fn set_cull_mask(&mut self, mask: u8) {
+        self.uniforms.cull_mask = mask.into();
+    }
+
+    /// Returns the cull mask of the rays, see [`DepthCamera::set_cull_mask`].
+    pub fn cull_mask(&self) -> u8 {
+        self.uniforms.cull_mask as u8
+    }
+
+    /// Sets the range along each ray within which pixels return nothing, 0.1 m by default, see
+    /// [`crate::lidar::Lidar::set_min_range`].
+    pub fn set_min_range(&mut self, range: f32) {
+        self.uniforms.t_min = range;
+    }
+
+    /// Returns the range within which pixels return nothing, see [`DepthCamera::set_min_range`].
+    pub fn min_range(&self) -> f32 {
+        self.uniforms.t_min
+    }
+
+    /// The cull mask and minimum range the rays are traced with.
+    pub(crate) fn ray_filter(&self) -> cpu::RayFilter {
+        cpu::RayFilter {
+            cull_mask: self.cull_mask(),
+            t_min: self.uniforms.t_min,
+        }
+    }
+
+    /// Reseeds the sensor's random stream. Renders issued after reseeding with the same seed
+    /// produce identical noise.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng.reseed(seed);
+    }
+
+    /// Sets how many images [`DepthCamera::submit_depth_camera`] keeps queued on the GPU.
+    /// Defaults to 2, i.e. double buffering. One makes every submission synchronous.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        self.depth_frames.set_frames_in_flight(frames_in_flight);
+    }
+
+    /// Returns how many images [`DepthCamera::submit_depth_camera`] keeps queued on the GPU.
+    pub fn frames_in_flight(&self) -> usize {
+        self.depth_frames.frames_in_flight()
+    }
+
+    /// Returns the pool the sensor's per-render buffers are taken from.
+    pub(crate) fn buffer_pool(&self) -> Arc<BufferPool> {
+        self.buffers.clone()
+    }
+
+    /// Returns the projection matrix of the camera.
+    pub fn projection_matrix(&self) -> Mat4 {
+        self.uniforms.proj_inverse.inverse()
+    }
+
     /// Returns the width of the depth camera image.
     pub fn width(&self) -> u32 {
         self.width
@@ -301,4 +831,86 @@ impl DepthCamera {
     pub fn height(&self) -> u32 {
         self.height
     }
+
+    /// Converts the output of [`DepthCamera::render_depth_camera`] into the depth along the
+    /// optical axis of each pixel, row by row from the top, as depth images usually hold.
+    /// Pixels without a return are NaN.
+    ///
+    /// The camera renders the range along each pixel's ray, column by column from the bottom.
+    ///
+    /// Fails if `depth` is not an image of the camera.
+    pub fn optical_depths(&self, depth: &[f32]) -> Result<Vec<f32>, Error> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if depth.len() != width * height {
+            return Err(Error::InvalidArgument(format!(
+                "A depth image of {} pixels given for a {width}x{height} camera",
+                depth.len()
+            )));
+        }
+        let mut depths = Vec::with_capacity(depth.len());
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let range = depth[x * height + y];
+                // The ray of the pixel as computed by the depth camera shader.
+                let d = (glam::Vec2::new(x as f32, y as f32) + 0.5)
+                    / glam::Vec2::new(width as f32, height as f32)
+                    * 2.0
+                    - 1.0;
+                let direction = (self.uniforms.proj_inverse * Vec4::new(d.x, d.y, 1.0, 1.0))
+                    .truncate()
+                    .normalize();
+                depths.push(if range < Self::no_hit_const() {
+                    range * direction.z.abs()
+                } else {
+                    f32::NAN
+                });
+            }
+        }
+        Ok(depths)
+    }
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+#[tokio::test]
+async fn test_depth_camera_array_top_row_first() {
+    use crate::{
+        utils::{create_cube, get_gpu},
+        Instance,
+    };
+    use glam::{Affine3A, Quat, Vec3};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    // A floor below the camera, which looks down -z.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_scale_rotation_translation(
+                Vec3::new(100.0, 1.0, 100.0),
+                Quat::IDENTITY,
+                Vec3::new(0.0, -3.0, 0.0),
+            ),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let mut camera = DepthCamera::new(&device, 6, 4, 60.0, 100.0).await;
+    let image = camera
+        .render_depth_camera_array(&scene, &device, &queue, Mat4::IDENTITY)
+        .await;
+    assert_eq!(image.dim(), (4, 6));
+    assert!(image
+        .row(0)
+        .iter()
+        .all(|&d| d == DepthCamera::no_hit_const()));
+    assert!(image.row(3).iter().all(|&d| d < 100.0));
+
+    let points = camera
+        .render_depth_camera_pointcloud_array(&scene, &device, &queue, Mat4::IDENTITY)
+        .await;
+    assert_eq!(points.dim(), (24, 4));
 }
@@ -0,0 +1,47 @@
+// Writes the id of the instance each pixel hits, see `Instance::id`, laid out like the depth
+// image. The ids do not depend on noise, so the noise and seed bindings are left out.
+
+struct Uniforms {
+    view_inv: mat4x4<f32>,
+    proj_inv: mat4x4<f32>,
+    width: u32,
+    height: u32,
+    cull_mask: u32,
+    t_min: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+@group(0) @binding(1)
+var acc_struct: acceleration_structure;
+
+@group(0) @binding(2)
+var<storage, read_write> instance_ids: array<u32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let target_size = vec2<u32>(uniforms.width, uniforms.height);
+
+	let pixel_center = vec2<f32>(global_id.xy) + vec2<f32>(0.5);
+	let in_uv = pixel_center/vec2<f32>(target_size.xy);
+	let d = in_uv * 2.0 - 1.0;
+
+	let origin = (uniforms.view_inv * vec4<f32>(0.0,0.0,0.0,1.0)).xyz;
+	let temp = uniforms.proj_inv * vec4<f32>(d.x, d.y, 1.0, 1.0);
+	let direction = (uniforms.view_inv * vec4<f32>(normalize(temp.xyz), 0.0)).xyz;
+
+    var rq: ray_query;
+    rayQueryInitialize(&rq, acc_struct, RayDesc(0u, uniforms.cull_mask, uniforms.t_min, 200.0, origin, direction));
+    rayQueryProceed(&rq);
+
+    let intersection = rayQueryGetCommittedIntersection(&rq);
+    if (intersection.kind != RAY_QUERY_INTERSECTION_NONE) {
+        instance_ids[global_id.x * target_size.y + global_id.y] = intersection.instance_custom_data;
+    }
+    else
+    {
+        // Matches `Instance::NO_HIT`.
+        instance_ids[global_id.x * target_size.y + global_id.y] = 0xffffffffu;
+    }
+}
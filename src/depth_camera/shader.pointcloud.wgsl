@@ -46,6 +46,8 @@ struct Uniforms {
     proj_inv: mat4x4<f32>,
     width: u32,
     height: u32,
+    cull_mask: u32,
+    t_min: f32,
 };
 
 @group(0) @binding(0)
@@ -70,11 +72,19 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 	let direction = (uniforms.view_inv * vec4<f32>(temp, 0.0)).xyz;
 
     var rq: ray_query;
-    rayQueryInitialize(&rq, acc_struct, RayDesc(0u, 0xFFu, 0.1, 200.0, origin, direction));
+    rayQueryInitialize(&rq, acc_struct, RayDesc(0u, uniforms.cull_mask, uniforms.t_min, 200.0, origin, direction));
     rayQueryProceed(&rq);
 
     let intersection = rayQueryGetCommittedIntersection(&rq);
     if (intersection.kind != RAY_QUERY_INTERSECTION_NONE) {
-        raw_buf[global_id.x * target_size.x + global_id.y] = vec4<f32>(direction.x, direction.y, direction.z, 1.0);
+        let index = global_id.x * target_size.x + global_id.y;
+        let hit = HitInfo(origin, direction, intersection.t,
+                          intersection.instance_index,
+                          intersection.instance_custom_data,
+                          intersection.primitive_index,
+                          intersection.barycentrics,
+                          intersection.front_face,
+                          index);
+        raw_buf[index] = vec4<f32>(direction.x, direction.y, direction.z, hit_shader(hit));
     }
 }
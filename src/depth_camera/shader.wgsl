@@ -46,6 +46,8 @@ struct Uniforms {
     proj_inv: mat4x4<f32>,
     width: u32,
     height: u32,
+    cull_mask: u32,
+    t_min: f32,
 };
 
 @group(0) @binding(0)
@@ -57,6 +59,12 @@ var acc_struct: acceleration_structure;
 @group(0) @binding(2)
 var<storage, read_write> raw_buf: array<f32>;
 
+@group(0) @binding(3)
+var<uniform> noise_params: NoiseParameters;
+
+@group(0) @binding(4)
+var<uniform> rng_seed: RngSeed;
+
 @compute @workgroup_size(8, 8)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let target_size = vec2<u32>(uniforms.width, uniforms.height);
@@ -70,12 +78,17 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 	let direction = (uniforms.view_inv * vec4<f32>(normalize(temp.xyz), 0.0)).xyz;
 
     var rq: ray_query;
-    rayQueryInitialize(&rq, acc_struct, RayDesc(0u, 0xFFu, 0.1, 200.0, origin, direction));
+    rayQueryInitialize(&rq, acc_struct, RayDesc(0u, uniforms.cull_mask, uniforms.t_min, 200.0, origin, direction));
     rayQueryProceed(&rq);
 
     let intersection = rayQueryGetCommittedIntersection(&rq);
+    var rng = rng_init(rng_seed, global_id.x * target_size.y + global_id.y);
+    var depth = -1.0;
     if (intersection.kind != RAY_QUERY_INTERSECTION_NONE) {
-        raw_buf[global_id.x * target_size.y + global_id.y] = intersection.t;
+        depth = apply_noise(intersection.t, noise_params, &rng);
+    }
+    if (depth >= 0.0) {
+        raw_buf[global_id.x * target_size.y + global_id.y] = depth;
     }
     else
     {
@@ -0,0 +1,138 @@
+//! Detecting device loss and errors nothing else captured.
+//!
+//! wgpu reports a lost device, e.g. after a driver reset, and errors outside of an error scope
+//! through callbacks that log or panic by default. A [`DeviceMonitor`] turns them into
+//! [`DeviceEvent`]s a long running simulation polls between frames:
+//!
+//! ```no_run
+//! # async fn run(mut scene: wgpu_rt_lidar::RayTraceScene) {
+//! use wgpu_rt_lidar::{device_monitor::DeviceMonitor, utils::get_raytracing_gpu};
+//!
+//! let instance = wgpu::Instance::default();
+//! let (_, mut device, mut queue) = get_raytracing_gpu(&instance).await;
+//! let mut monitor = DeviceMonitor::new(&device);
+//! loop {
+//!     // Render a frame...
+//!     for event in monitor.events() {
+//!         println!("{event:?}");
+//!     }
+//!     if monitor.is_lost() {
+//!         (_, device, queue) = get_raytracing_gpu(&instance).await;
+//!         monitor = DeviceMonitor::new(&device);
+//!         scene.recreate(&device, &queue).await.unwrap();
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Something that happened to a device outside of any call into the crate.
+#[derive(Debug)]
+pub enum DeviceEvent {
+    /// The device is gone. Every resource created on it, scenes and sensors included, is invalid.
+    Lost {
+        reason: wgpu::DeviceLostReason,
+        message: String,
+    },
+    /// An error no error scope captured, e.g. running out of memory.
+    UncapturedError(wgpu::Error),
+}
+
+/// Receives the [`DeviceEvent`]s of one device.
+#[derive(Debug)]
+pub struct DeviceMonitor {
+    receiver: flume::Receiver<DeviceEvent>,
+    lost: Arc<AtomicBool>,
+}
+
+impl DeviceMonitor {
+    /// Installs the device lost callback and uncaptured error handler of `device`, replacing any
+    /// set before.
+    pub fn new(device: &wgpu::Device) -> Self {
+        let (sender, receiver) = flume::unbounded();
+        let lost = Arc::new(AtomicBool::new(false));
+
+        let lost_sender = sender.clone();
+        let lost_flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            lost_flag.store(true, Ordering::Release);
+            // The monitor may have been dropped already.
+            let _ = lost_sender.send(DeviceEvent::Lost { reason, message });
+        });
+        device.on_uncaptured_error(Box::new(move |error| {
+            let _ = sender.send(DeviceEvent::UncapturedError(error));
+        }));
+        Self { receiver, lost }
+    }
+
+    /// Returns true once the device was lost.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    /// Returns the next event, if any, without waiting.
+    pub fn try_recv(&self) -> Option<DeviceEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Returns the events received so far, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = DeviceEvent> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// Waits for the next event.
+    pub async fn recv(&self) -> DeviceEvent {
+        self.receiver
+            .recv_async()
+            .await
+            .expect("The device callbacks hold a sender")
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_device_loss_and_scene_recreation() {
+    use crate::{lidar::Lidar, utils::create_cube, utils::get_raytracing_gpu, Instance};
+    use glam::{Affine3A, Vec3};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    let monitor = DeviceMonitor::new(&device);
+    let mut scene = crate::RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    assert!(!monitor.is_lost());
+
+    device.destroy();
+    let _ = device.poll(wgpu::PollType::Poll);
+    assert!(monitor.is_lost());
+    assert!(matches!(
+        monitor.recv().await,
+        DeviceEvent::Lost {
+            reason: wgpu::DeviceLostReason::Destroyed,
+            ..
+        }
+    ));
+
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    scene.recreate(&device, &queue).await.unwrap();
+    let mut lidar = Lidar::new(&device, vec![Vec3::NEG_X]).await;
+    let pose = Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0));
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+}
@@ -0,0 +1,122 @@
+//! The error type returned by fallible operations of the crate.
+
+/// Everything that can go wrong in the crate, for callers to match on.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A position or index lies outside of the grid or region it refers to.
+    #[error("{0} out of bounds")]
+    OutOfBounds(&'static str),
+    /// Instances and the indices they update are not of the same length.
+    #[error("{instances} instances given for {indices} indices")]
+    InstanceMismatch { instances: usize, indices: usize },
+    /// Two voxel grids, or a grid and its GPU representation, do not cover the same cells.
+    #[error("Voxel grids do not cover the same cells")]
+    GridMismatch,
+    /// Every slot of the voxel the item falls into is taken.
+    #[error("No space in voxel grid")]
+    NoSpace,
+    /// Nothing is stored at the index.
+    #[error("No item at index {0}")]
+    NoItem(usize),
+    /// An argument is out of the range the operation supports.
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    /// The planner ran out of iterations before reaching the goal.
+    #[error("No path found within the iteration limit")]
+    NoPathFound,
+    /// Point cloud registration could not be solved, e.g. for lack of correspondences.
+    #[error("Registration failed: {0}")]
+    RegistrationFailed(&'static str),
+    /// The system lacks something the operation requires, e.g. a GPU supporting ray queries.
+    #[error("Unsupported: {0}")]
+    Unsupported(&'static str),
+    /// Opening a device failed.
+    #[error("Failed to create device: {0}")]
+    DeviceRequestFailed(#[from] wgpu::RequestDeviceError),
+    /// A shader, e.g. a user supplied hit shader, does not compile.
+    #[error("Shader `{label}` failed to compile:\n{diagnostics}")]
+    ShaderCompilation { label: String, diagnostics: String },
+    /// wgpu rejected a resource or pipeline.
+    #[error("GPU error: {0}")]
+    Gpu(#[from] wgpu::Error),
+    /// Reading a file failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A scene could not be built or changed.
+    #[error(transparent)]
+    Scene(#[from] SceneError),
+}
+
+/// Why a [`crate::RayTraceScene`] could not be built from its assets and instances, or could
+/// not take an instance or new geometry.
+#[derive(Debug, thiserror::Error)]
+pub enum SceneError {
+    /// An asset has no vertices or no indices.
+    #[error("Asset {asset} has no triangles")]
+    EmptyAsset { asset: usize },
+    /// The index buffer of an asset does not hold whole triangles.
+    #[error("Asset {asset} has {indices} indices, which is not a multiple of 3")]
+    PartialTriangle { asset: usize, indices: usize },
+    /// A triangle of an asset refers to a vertex the asset does not have.
+    #[error("Asset {asset} refers to vertex {index} but has {vertices} vertices")]
+    VertexOutOfBounds {
+        asset: usize,
+        index: u16,
+        vertices: usize,
+    },
+    /// An instance refers to an asset the scene does not have.
+    #[error("Instance {instance} refers to asset {asset} but the scene has {assets} assets")]
+    AssetOutOfBounds {
+        instance: usize,
+        asset: usize,
+        assets: usize,
+    },
+    /// An instance has an id above [`crate::Instance::MAX_ID`].
+    #[error("Instance {instance} has id {id}, but ids are at most 24 bits")]
+    IdOutOfRange { instance: usize, id: u32 },
+    /// The scene has no asset at the index given.
+    #[error("No asset {asset}, the scene has {assets} assets")]
+    NoAsset { asset: usize, assets: usize },
+    /// The scene has no instance at the index given.
+    #[error("No instance {instance}, the scene has {instances} instances")]
+    NoInstance { instance: usize, instances: usize },
+    /// New geometry for an asset does not have as many vertices as the asset.
+    #[error("Asset {asset} has {expected} vertices but {vertices} were given")]
+    VertexCountMismatch {
+        asset: usize,
+        expected: usize,
+        vertices: usize,
+    },
+    /// The scene has no instances and no room was reserved for any.
+    #[error("Scene has no instances and no room for any")]
+    NoInstances,
+    /// Fewer instances than placed were asked room for.
+    #[error("Room for {capacity} instances asked for, but {instances} placed")]
+    CapacityExceeded { capacity: usize, instances: usize },
+    /// wgpu rejected the buffers or acceleration structures.
+    #[error("GPU error: {0}")]
+    Gpu(#[from] wgpu::Error),
+}
+
+#[cfg(test)]
+#[test]
+fn test_error_messages() {
+    let error = Error::InstanceMismatch {
+        instances: 2,
+        indices: 3,
+    };
+    assert_eq!(error.to_string(), "2 instances given for 3 indices");
+    assert_eq!(
+        Error::OutOfBounds("Voxel position").to_string(),
+        "Voxel position out of bounds"
+    );
+    assert_eq!(
+        Error::from(SceneError::VertexOutOfBounds {
+            asset: 1,
+            index: 8,
+            vertices: 8
+        })
+        .to_string(),
+        "Asset 1 refers to vertex 8 but has 8 vertices"
+    );
+}
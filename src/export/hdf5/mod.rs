@@ -0,0 +1,260 @@
+//! Recording of sensor outputs into HDF5 files.
+//!
+//! A [`Recorder`] keeps one group per stream, e.g. per sensor, holding a `data` dataset of every
+//! frame recorded on the stream stacked along its first axis, and their `timestamps`. Datasets
+//! are chunked frame by frame and grow as frames are appended, so generating a large dataset
+//! neither holds it in memory nor rewrites the file:
+//!
+//! | Stream | `data` shape | Content |
+//! |---|---|---|
+//! | Depth images | `(frames, height, width)` | Ranges, row by row from the top, NaN without a return |
+//! | Point clouds | `(frames, points, 4)` | The output of `render_lidar_pointcloud`, misses included |
+//! | Poses | `(frames, 7)` | Translation, then rotation as x, y, z, w |
+//!
+//! `timestamps` are `f64` seconds of shape `(frames,)`.
+
+use std::{collections::HashMap, path::Path};
+
+use ::hdf5::{Dataset, Extent, File, Group, H5Type};
+use glam::Affine3A;
+use ndarray::{s, ArrayView1, ArrayView2, ArrayView3};
+
+use crate::depth_camera::DepthCamera;
+
+/// Frames per chunk of the pose and timestamp datasets, which hold little per frame.
+const SMALL_CHUNK: usize = 1024;
+
+/// The datasets of a stream and their number of frames.
+struct Stream {
+    data: Dataset,
+    timestamps: Dataset,
+    frames: usize,
+}
+
+impl Stream {
+    fn create<T: H5Type>(
+        group: &Group,
+        frame_shape: &[usize],
+        chunk: usize,
+    ) -> ::hdf5::Result<Self> {
+        // Frames are appended along the first axis.
+        let shape: Vec<_> = std::iter::once(Extent::resizable(0))
+            .chain(frame_shape.iter().map(|&n| Extent::fixed(n)))
+            .collect();
+        let chunk_shape: Vec<_> = std::iter::once(chunk)
+            .chain(frame_shape.iter().copied())
+            .collect();
+        let data = group
+            .new_dataset::<T>()
+            .shape(shape)
+            .chunk(chunk_shape)
+            .create("data")?;
+        let timestamps = group
+            .new_dataset::<f64>()
+            .shape(vec![Extent::resizable(0)])
+            .chunk(SMALL_CHUNK)
+            .create("timestamps")?;
+        Ok(Self {
+            data,
+            timestamps,
+            frames: 0,
+        })
+    }
+
+    /// Grows the datasets by a frame and returns its index.
+    fn append(&mut self, timestamp: f64) -> ::hdf5::Result<usize> {
+        let frame = self.frames;
+        let mut shape = self.data.shape();
+        shape[0] = frame + 1;
+        self.data.resize(shape)?;
+        self.timestamps.resize(frame + 1)?;
+        self.timestamps
+            .write_slice(ArrayView1::from(&[timestamp]), s![frame..frame + 1])?;
+        self.frames += 1;
+        Ok(frame)
+    }
+}
+
+/// Appends sensor outputs to an HDF5 file.
+pub struct Recorder {
+    file: File,
+    streams: HashMap<String, Stream>,
+}
+
+impl Recorder {
+    /// Creates the file at `path`, replacing any existing one.
+    pub fn create(path: impl AsRef<Path>) -> ::hdf5::Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+            streams: HashMap::new(),
+        })
+    }
+
+    /// The stream named `name`, created with `T` frames of `frame_shape` if new.
+    fn stream<T: H5Type>(
+        &mut self,
+        name: &str,
+        frame_shape: &[usize],
+        chunk: usize,
+    ) -> ::hdf5::Result<&mut Stream> {
+        if !self.streams.contains_key(name) {
+            let group = self.file.create_group(name)?;
+            let stream = Stream::create::<T>(&group, frame_shape, chunk)?;
+            self.streams.insert(name.to_string(), stream);
+        }
+        let stream = self.streams.get_mut(name).unwrap();
+        if stream.data.shape()[1..] != *frame_shape {
+            return Err(format!(
+                "Frames of shape {frame_shape:?} given for the stream {name} of {:?}",
+                &stream.data.shape()[1..]
+            )
+            .into());
+        }
+        Ok(stream)
+    }
+
+    /// Appends the output of [`DepthCamera::render_depth_camera`] of `camera` to the stream
+    /// `name`.
+    pub fn record_depth_image(
+        &mut self,
+        name: &str,
+        timestamp: f64,
+        camera: &DepthCamera,
+        depth: &[f32],
+    ) -> ::hdf5::Result<()> {
+        let (width, height) = (camera.width() as usize, camera.height() as usize);
+        if depth.len() != width * height {
+            return Err(format!(
+                "A depth image of {} pixels given for a {width}x{height} camera",
+                depth.len()
+            )
+            .into());
+        }
+        // The camera renders column by column from the bottom.
+        let image: Vec<f32> = (0..height)
+            .rev()
+            .flat_map(|y| (0..width).map(move |x| depth[x * height + y]))
+            .map(|range| {
+                if range < DepthCamera::no_hit_const() {
+                    range
+                } else {
+                    f32::NAN
+                }
+            })
+            .collect();
+        let stream = self.stream::<f32>(name, &[height, width], 1)?;
+        let frame = stream.append(timestamp)?;
+        let image = ArrayView3::from_shape((1, height, width), &image).unwrap();
+        stream.data.write_slice(image, s![frame..frame + 1, .., ..])
+    }
+
+    /// Appends the output of [`crate::lidar::Lidar::render_lidar_pointcloud`] to the stream
+    /// `name`. Every frame of a stream has to hold the same number of points, which clouds of
+    /// one LiDAR do.
+    pub fn record_pointcloud(
+        &mut self,
+        name: &str,
+        timestamp: f64,
+        points: &[f32],
+    ) -> ::hdf5::Result<()> {
+        if !points.len().is_multiple_of(4) {
+            return Err(format!(
+                "A point cloud of {} floats is not made of points of 4",
+                points.len()
+            )
+            .into());
+        }
+        let count = points.len() / 4;
+        let stream = self.stream::<f32>(name, &[count, 4], 1)?;
+        let frame = stream.append(timestamp)?;
+        let points = ArrayView3::from_shape((1, count, 4), points).unwrap();
+        stream
+            .data
+            .write_slice(points, s![frame..frame + 1, .., ..])
+    }
+
+    /// Appends `pose`, e.g. of a sensor or the vehicle, to the stream `name`.
+    pub fn record_pose(
+        &mut self,
+        name: &str,
+        timestamp: f64,
+        pose: &Affine3A,
+    ) -> ::hdf5::Result<()> {
+        let (_, rotation, translation) = pose.to_scale_rotation_translation();
+        let mut row = translation.to_array().to_vec();
+        row.extend(rotation.to_array());
+        let stream = self.stream::<f32>(name, &[7], SMALL_CHUNK)?;
+        let frame = stream.append(timestamp)?;
+        let row = ArrayView2::from_shape((1, 7), &row).unwrap();
+        stream.data.write_slice(row, s![frame..frame + 1, ..])
+    }
+
+    /// Number of frames recorded on the stream `name`.
+    pub fn frames(&self, name: &str) -> usize {
+        self.streams.get(name).map_or(0, |stream| stream.frames)
+    }
+
+    /// Flushes the recorded frames to disk. The file is also flushed when the recorder is
+    /// dropped.
+    pub fn flush(&self) -> ::hdf5::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_recorder_appends_frames() {
+    use crate::utils::get_gpu;
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, _) = get_gpu(&instance).await;
+    let camera = DepthCamera::new(&device, 3, 2, 60.0, 100.0).await;
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("frames.h5");
+    let mut recorder = Recorder::create(&path).unwrap();
+
+    // Column by column from the bottom, with a miss at the bottom left.
+    let depth = [99999.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+    recorder
+        .record_depth_image("camera", 0.0, &camera, &depth)
+        .unwrap();
+    recorder
+        .record_depth_image("camera", 0.1, &camera, &depth)
+        .unwrap();
+    assert!(recorder
+        .record_depth_image("camera", 0.2, &camera, &depth[1..])
+        .is_err());
+    let pose = Affine3A::from_translation(Vec3::new(1.0, 2.0, 3.0));
+    recorder.record_pose("camera_pose", 0.0, &pose).unwrap();
+    recorder.record_pointcloud("lidar", 0.0, &[1.0; 8]).unwrap();
+    assert!(recorder
+        .record_pointcloud("lidar", 0.1, &[1.0; 12])
+        .is_err());
+    assert_eq!(recorder.frames("camera"), 2);
+    assert_eq!(recorder.frames("lidar"), 1);
+    recorder.flush().unwrap();
+
+    let file = File::open(&path).unwrap();
+    let images = file
+        .dataset("camera/data")
+        .unwrap()
+        .read_dyn::<f32>()
+        .unwrap();
+    assert_eq!(images.shape(), [2, 2, 3]);
+    assert_eq!(images[[1, 0, 0]], 1.0);
+    assert_eq!(images[[1, 0, 2]], 5.0);
+    assert!(images[[1, 1, 0]].is_nan());
+    let timestamps = file
+        .dataset("camera/timestamps")
+        .unwrap()
+        .read_raw::<f64>()
+        .unwrap();
+    assert_eq!(timestamps, [0.0, 0.1]);
+    let poses = file
+        .dataset("camera_pose/data")
+        .unwrap()
+        .read_raw::<f32>()
+        .unwrap();
+    assert_eq!(poses, [1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 1.0]);
+}
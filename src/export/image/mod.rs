@@ -0,0 +1,135 @@
+//! Depth images as 16 bit PNG and 32 bit EXR files.
+//!
+//! Both hold the depth along the optical axis, row by row from the top, see
+//! [`DepthCamera::optical_depths`]:
+//!
+//! * PNG files in millimeters, 0 without a return, as most depth datasets store them.
+//! * EXR files in meters, NaN without a return. The image crate writes color EXRs only, so the
+//!   depth is repeated in the R, G and B channels.
+
+use std::path::{Path, PathBuf};
+
+use ::image::{
+    error::{ParameterError, ParameterErrorKind},
+    ImageBuffer, ImageError, ImageResult, Luma, Rgb,
+};
+
+use crate::depth_camera::DepthCamera;
+
+/// A file format of depth images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthFormat {
+    /// 16 bit grayscale PNG, in millimeters.
+    Png16,
+    /// 32 bit float EXR, in meters.
+    Exr,
+}
+
+impl DepthFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DepthFormat::Png16 => "png",
+            DepthFormat::Exr => "exr",
+        }
+    }
+}
+
+/// Saves the output of [`DepthCamera::render_depth_camera`] of `camera` to `path` in `format`.
+///
+/// Fails if `depth` is not an image of `camera` or writing fails.
+pub fn save_depth_image(
+    path: impl AsRef<Path>,
+    camera: &DepthCamera,
+    depth: &[f32],
+    format: DepthFormat,
+) -> ImageResult<()> {
+    let depths = camera.optical_depths(depth).map_err(|_| {
+        ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        ))
+    })?;
+    let (width, height) = (camera.width(), camera.height());
+    match format {
+        DepthFormat::Png16 => {
+            let millimeters = depths
+                .iter()
+                .map(|z| {
+                    if z.is_nan() {
+                        0
+                    } else {
+                        (z * 1000.0).round().min(u16::MAX as f32) as u16
+                    }
+                })
+                .collect();
+            ImageBuffer::<Luma<u16>, Vec<u16>>::from_raw(width, height, millimeters)
+                .expect("One depth per pixel")
+                .save_with_format(path, ::image::ImageFormat::Png)
+        }
+        DepthFormat::Exr => {
+            let meters = depths.iter().flat_map(|&z| [z; 3]).collect();
+            ImageBuffer::<Rgb<f32>, Vec<f32>>::from_raw(width, height, meters)
+                .expect("One depth per pixel")
+                .save_with_format(path, ::image::ImageFormat::OpenExr)
+        }
+    }
+}
+
+/// Saves depth images rendered along a trajectory to `directory`, as `frame_000000.png`,
+/// `frame_000001.png` and so on, and returns their paths in order.
+///
+/// Fails like [`save_depth_image`], or if `directory` can not be created.
+pub fn save_depth_trajectory<'a>(
+    directory: impl AsRef<Path>,
+    camera: &DepthCamera,
+    frames: impl IntoIterator<Item = &'a [f32]>,
+    format: DepthFormat,
+) -> ImageResult<Vec<PathBuf>> {
+    let directory = directory.as_ref();
+    std::fs::create_dir_all(directory)?;
+    let mut paths = vec![];
+    for (i, depth) in frames.into_iter().enumerate() {
+        let path = directory.join(format!("frame_{i:06}.{}", format.extension()));
+        save_depth_image(&path, camera, depth, format)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_save_depth_images() {
+    use crate::utils::get_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, _) = get_gpu(&instance).await;
+    let camera = DepthCamera::new(&device, 3, 2, 60.0, 100.0).await;
+    // A miss at the bottom left.
+    let depth = [99999.0, 2.0, 2.0, 2.0, 2.0, 2.0];
+    let expected = camera.optical_depths(&depth).unwrap();
+    let directory = tempfile::tempdir().unwrap();
+
+    let paths = save_depth_trajectory(
+        directory.path(),
+        &camera,
+        [&depth[..], &depth[..]],
+        DepthFormat::Png16,
+    )
+    .unwrap();
+    assert_eq!(paths.len(), 2);
+    assert!(paths[1].ends_with("frame_000001.png"));
+    let png = ::image::open(&paths[0]).unwrap().into_luma16();
+    assert_eq!(png.dimensions(), (3, 2));
+    assert_eq!(png.get_pixel(0, 1)[0], 0);
+    assert_eq!(
+        png.get_pixel(2, 0)[0],
+        (expected[2] * 1000.0).round() as u16
+    );
+
+    let path = directory.path().join("depth.exr");
+    save_depth_image(&path, &camera, &depth, DepthFormat::Exr).unwrap();
+    let exr = ::image::open(&path).unwrap().into_rgb32f();
+    assert!(exr.get_pixel(0, 1)[0].is_nan());
+    assert_eq!(exr.get_pixel(1, 0)[0], expected[1]);
+
+    assert!(save_depth_image(&path, &camera, &depth[1..], DepthFormat::Exr).is_err());
+}
@@ -0,0 +1,282 @@
+//! LAS 1.4 export of world-frame point clouds, for surveying and GIS tools.
+//!
+//! A [`LasCloud`] accumulates LiDAR point clouds from several poses in the world frame and
+//! writes them as point data record format 6. Compress the file with LASzip to get a LAZ file.
+//!
+//! ```no_run
+//! # async fn run(
+//! #     scene: wgpu_rt_lidar::RayTraceScene,
+//! #     device: wgpu::Device,
+//! #     queue: wgpu::Queue,
+//! #     mut lidar: wgpu_rt_lidar::lidar::Lidar,
+//! #     poses: Vec<glam::Affine3A>,
+//! # ) -> Result<(), wgpu_rt_lidar::Error> {
+//! use wgpu_rt_lidar::export::LasCloud;
+//!
+//! let mut cloud = LasCloud::new();
+//! for pose in &poses {
+//!     let points = lidar.render_lidar_pointcloud(&scene, &device, &queue, pose).await;
+//!     cloud.add_lidar_pointcloud(&points, pose)?;
+//! }
+//! cloud.save("survey.las").unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use glam::{Affine3A, DVec3, Vec3};
+
+use crate::{lidar::Lidar, Error};
+
+const HEADER_SIZE: u16 = 375;
+const POINT_FORMAT: u8 = 6;
+const POINT_SIZE: u16 = 30;
+/// Global encoding bit required by point formats 6 and above.
+const WKT: u16 = 1 << 4;
+
+/// A point of a [`LasCloud`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LasPoint {
+    /// Position in the world frame.
+    pub position: Vec3,
+    pub intensity: u16,
+    /// ASPRS class, e.g. [`LasPoint::UNCLASSIFIED`].
+    pub classification: u8,
+}
+
+impl LasPoint {
+    pub const CREATED_NEVER_CLASSIFIED: u8 = 0;
+    pub const UNCLASSIFIED: u8 = 1;
+    pub const GROUND: u8 = 2;
+    pub const LOW_VEGETATION: u8 = 3;
+    pub const MEDIUM_VEGETATION: u8 = 4;
+    pub const HIGH_VEGETATION: u8 = 5;
+    pub const BUILDING: u8 = 6;
+}
+
+/// World-frame points accumulated for a LAS file.
+#[derive(Debug, Clone)]
+pub struct LasCloud {
+    points: Vec<LasPoint>,
+    scale: f64,
+}
+
+impl Default for LasCloud {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LasCloud {
+    /// Creates an empty cloud storing coordinates to a millimeter.
+    pub fn new() -> Self {
+        Self {
+            points: vec![],
+            scale: 0.001,
+        }
+    }
+
+    /// Sets the resolution coordinates are stored with, in meters.
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn points(&self) -> &[LasPoint] {
+        &self.points
+    }
+
+    pub fn add_point(&mut self, point: LasPoint) {
+        self.points.push(point);
+    }
+
+    /// Adds the output of [`Lidar::render_lidar_pointcloud`] rendered at `pose`, as
+    /// [`LasPoint::UNCLASSIFIED`] points.
+    ///
+    /// The intensity is the hit shader payload, rounded and clamped to `u16`, so hit shaders
+    /// meant for export return intensities in the range of the target tool. Beams without a
+    /// return are left out.
+    ///
+    /// Fails if `points` is not a point cloud.
+    pub fn add_lidar_pointcloud(&mut self, points: &[f32], pose: &Affine3A) -> Result<(), Error> {
+        self.add_classified(points, pose, |_| LasPoint::UNCLASSIFIED)
+    }
+
+    /// Adds a point cloud like [`LasCloud::add_lidar_pointcloud`], classifying each point with
+    /// `classify` from the instance its beam hit, `instance_ids` holding one per beam, e.g. from
+    /// [`Lidar::render_lidar_instance_ids`].
+    ///
+    /// Fails if `points` is not a point cloud or `instance_ids` does not match it.
+    pub fn add_lidar_pointcloud_with_instances(
+        &mut self,
+        points: &[f32],
+        pose: &Affine3A,
+        instance_ids: &[u32],
+        classify: impl Fn(u32) -> u8,
+    ) -> Result<(), Error> {
+        if instance_ids.len() * 4 != points.len() {
+            return Err(Error::InvalidArgument(format!(
+                "{} instance ids given for a point cloud of {} floats",
+                instance_ids.len(),
+                points.len()
+            )));
+        }
+        self.add_classified(points, pose, |i| classify(instance_ids[i]))
+    }
+
+    fn add_classified(
+        &mut self,
+        points: &[f32],
+        pose: &Affine3A,
+        classify: impl Fn(usize) -> u8,
+    ) -> Result<(), Error> {
+        if !points.len().is_multiple_of(4) {
+            return Err(Error::InvalidArgument(format!(
+                "A point cloud of {} floats is not made of points of 4",
+                points.len()
+            )));
+        }
+        for (i, point) in points.chunks_exact(4).enumerate() {
+            if point[0] == Lidar::no_hit_const() {
+                continue;
+            }
+            self.points.push(LasPoint {
+                position: pose.transform_point3(Vec3::from_slice(point)),
+                intensity: point[3].round().clamp(0.0, u16::MAX as f32) as u16,
+                classification: classify(i),
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes the cloud as a LAS 1.4 file with point data record format 6.
+    ///
+    /// Every point is recorded as the single return of its pulse.
+    pub fn write_to(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let (min, max) = self.points.iter().fold(
+            (DVec3::splat(f64::INFINITY), DVec3::splat(f64::NEG_INFINITY)),
+            |(min, max), p| {
+                let position = p.position.as_dvec3();
+                (min.min(position), max.max(position))
+            },
+        );
+        let (min, max) = if self.points.is_empty() {
+            (DVec3::ZERO, DVec3::ZERO)
+        } else {
+            (min, max)
+        };
+        // Coordinates are stored as 32 bit multiples of the scale relative to the offset.
+        let offset = (min / self.scale).floor() * self.scale;
+        if ((max - offset) / self.scale).max_element() > i32::MAX as f64 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The cloud is too large for its scale",
+            ));
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"LASF");
+        header.extend_from_slice(&0u16.to_le_bytes()); // File source id.
+        header.extend_from_slice(&WKT.to_le_bytes());
+        header.extend_from_slice(&[0; 16]); // Project id.
+        header.extend_from_slice(&[1, 4]);
+        header.extend_from_slice(&padded::<32>(b"wgpu_rt_lidar simulation"));
+        header.extend_from_slice(&padded::<32>(b"wgpu_rt_lidar"));
+        header.extend_from_slice(&[0; 4]); // Creation day and year, unknown.
+        header.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+        header.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes()); // Offset to the points.
+        header.extend_from_slice(&0u32.to_le_bytes()); // Variable length records.
+        header.push(POINT_FORMAT);
+        header.extend_from_slice(&POINT_SIZE.to_le_bytes());
+        // The legacy point counts stay zero for point formats 6 and above.
+        header.extend_from_slice(&[0; 4 + 5 * 4]);
+        for value in [self.scale; 3].into_iter().chain(offset.to_array()) {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        for (max, min) in max.to_array().into_iter().zip(min.to_array()) {
+            header.extend_from_slice(&max.to_le_bytes());
+            header.extend_from_slice(&min.to_le_bytes());
+        }
+        header.extend_from_slice(&[0; 8 + 8 + 4]); // No waveforms or extended records.
+        let count = self.points.len() as u64;
+        header.extend_from_slice(&count.to_le_bytes());
+        header.extend_from_slice(&count.to_le_bytes()); // All are first returns.
+        header.extend_from_slice(&[0; 14 * 8]);
+        debug_assert_eq!(header.len(), HEADER_SIZE as usize);
+        writer.write_all(&header)?;
+
+        let mut record = [0u8; POINT_SIZE as usize];
+        for point in &self.points {
+            let coordinates = ((point.position.as_dvec3() - offset) / self.scale).round();
+            for (i, value) in coordinates.to_array().into_iter().enumerate() {
+                record[4 * i..4 * i + 4].copy_from_slice(&(value as i32).to_le_bytes());
+            }
+            record[12..14].copy_from_slice(&point.intensity.to_le_bytes());
+            record[14] = 0x11; // Return 1 of 1.
+            record[15] = 0; // No flags, channel or scan direction.
+            record[16] = point.classification;
+            // User data, scan angle, point source id and GPS time stay zero.
+            writer.write_all(&record)?;
+        }
+        Ok(())
+    }
+
+    /// Saves the cloud to a file. See [`LasCloud::write_to`].
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// A null padded string field.
+fn padded<const N: usize>(text: &[u8]) -> [u8; N] {
+    let mut field = [0; N];
+    field[..text.len()].copy_from_slice(text);
+    field
+}
+
+#[cfg(test)]
+#[test]
+fn test_las_layout() {
+    let no_hit = Lidar::no_hit_const();
+    let points = [
+        1.0, 2.0, 3.0, 40.0, //
+        no_hit, no_hit, 100000.0, 100000.0, //
+        -1.0, 0.5, 0.25, 70000.0,
+    ];
+    let pose = Affine3A::from_translation(Vec3::new(10.0, 0.0, 0.0));
+    let mut cloud = LasCloud::new();
+    cloud
+        .add_lidar_pointcloud_with_instances(&points, &pose, &[3, 0, 7], |id| id as u8)
+        .unwrap();
+    assert!(cloud.add_lidar_pointcloud(&points[..6], &pose).is_err());
+    assert_eq!(cloud.points().len(), 2);
+
+    let mut bytes = vec![];
+    cloud.write_to(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), 375 + 2 * 30);
+    let u16_at = |i: usize| u16::from_le_bytes(bytes[i..i + 2].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+    let f64_at = |i: usize| f64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+    let i32_at = |i: usize| i32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    assert_eq!(&bytes[..4], b"LASF");
+    assert_eq!((bytes[24], bytes[25]), (1, 4));
+    assert_eq!(u16_at(94), 375);
+    assert_eq!((bytes[104], u16_at(105)), (6, 30));
+    assert_eq!(u64_at(247), 2);
+    // Max and min x.
+    assert_eq!((f64_at(179), f64_at(187)), (11.0, 9.0));
+
+    let offset_x = f64_at(155);
+    let second = &bytes[375 + 30..];
+    let x = offset_x + i32_at(375 + 30) as f64 * f64_at(131);
+    assert!((x - 9.0).abs() < 1e-9);
+    assert_eq!(u16::from_le_bytes([second[12], second[13]]), u16::MAX);
+    assert_eq!(second[16], 7);
+}
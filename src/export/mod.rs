@@ -0,0 +1,13 @@
+//! Writing sensor outputs to the file formats and dataset layouts of other tools.
+
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+#[cfg(feature = "image")]
+pub mod image;
+pub mod las;
+pub mod nuscenes;
+pub mod ply;
+
+pub use las::{LasCloud, LasPoint};
+pub use nuscenes::NuScenesWriter;
+pub use ply::PlyCloud;
@@ -0,0 +1,554 @@
+//! Datasets in the layout of nuScenes, for tooling built on its devkit.
+//!
+//! A [`NuScenesWriter`] records one scene: the sensors added to it with their calibration, the
+//! samples, i.e. the key frames annotations would refer to, and the rendered output of every
+//! capture of the sensors with the pose of the ego vehicle at that time. Key frame captures land
+//! in `samples/`, the others in `sweeps/`, and [`NuScenesWriter::finish`] writes the tables to
+//! `<version>/`:
+//!
+//! ```text
+//! root/
+//! ├── samples/LIDAR_TOP/0000000000000000000000000000000a.pcd.bin
+//! ├── sweeps/LIDAR_TOP/...
+//! └── v1.0-sim/{sensor, calibrated_sensor, ego_pose, sample, sample_data, scene, log, ...}.json
+//! ```
+//!
+//! LiDAR captures are stored like nuScenes', as little endian `f32` x, y, z, intensity and ring
+//! index per point in the frame of the sensor, the intensity being the payload of the point.
+//! nuScenes has no depth cameras, so depth images are stored as little endian `f32` ranges
+//! along each pixel's ray, row by row from the top and NaN without a return, with the file
+//! format `bin`. Annotation tables are left empty.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use glam::{Affine3A, Mat3};
+
+use crate::{depth_camera::DepthCamera, lidar::Lidar};
+
+/// A sensor added to a [`NuScenesWriter`], see [`NuScenesWriter::add_lidar`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SensorId(usize);
+
+/// When and from where a sensor captured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capture {
+    /// Microseconds since the start of the clock, e.g. the simulation.
+    pub timestamp: u64,
+    /// Pose of the ego vehicle in the world.
+    pub ego_pose: Affine3A,
+    /// Whether the capture belongs to the current sample, rather than being a sweep between
+    /// samples.
+    pub key_frame: bool,
+}
+
+struct Sensor {
+    channel: String,
+    modality: &'static str,
+    sensor_to_ego: Affine3A,
+    intrinsic: Option<Mat3>,
+}
+
+struct Sample {
+    timestamp: u64,
+}
+
+struct SampleData {
+    sensor: usize,
+    sample: usize,
+    capture: Capture,
+    filename: String,
+    fileformat: &'static str,
+    size: (u32, u32),
+}
+
+/// Writes a scene of a nuScenes style dataset.
+pub struct NuScenesWriter {
+    root: PathBuf,
+    version: String,
+    scene_name: String,
+    sensors: Vec<Sensor>,
+    samples: Vec<Sample>,
+    sample_data: Vec<SampleData>,
+}
+
+impl NuScenesWriter {
+    /// Starts the dataset `version`, e.g. `v1.0-sim`, in the directory `root`, holding the scene
+    /// `scene_name`.
+    pub fn create(
+        root: impl AsRef<Path>,
+        version: &str,
+        scene_name: &str,
+    ) -> std::io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(root.join(version))?;
+        Ok(Self {
+            root,
+            version: version.to_string(),
+            scene_name: scene_name.to_string(),
+            sensors: vec![],
+            samples: vec![],
+            sample_data: vec![],
+        })
+    }
+
+    /// Adds a LiDAR named `channel`, e.g. `LIDAR_TOP`, mounted at `sensor_to_ego` on the
+    /// vehicle.
+    pub fn add_lidar(&mut self, channel: &str, sensor_to_ego: &Affine3A) -> SensorId {
+        self.add_sensor(channel, "lidar", sensor_to_ego, None)
+    }
+
+    /// Adds a depth camera named `channel` mounted at `sensor_to_ego`, in the optical frame of
+    /// nuScenes cameras: z forward, x right and y down.
+    pub fn add_depth_camera(
+        &mut self,
+        channel: &str,
+        sensor_to_ego: &Affine3A,
+        camera: &DepthCamera,
+    ) -> SensorId {
+        let projection = camera.projection_matrix();
+        let (width, height) = (camera.width() as f32, camera.height() as f32);
+        let intrinsic = Mat3::from_cols_array(&[
+            projection.x_axis.x * width / 2.0,
+            0.0,
+            0.0,
+            0.0,
+            projection.y_axis.y * height / 2.0,
+            0.0,
+            width / 2.0 - 0.5,
+            height / 2.0 - 0.5,
+            1.0,
+        ]);
+        self.add_sensor(channel, "camera", sensor_to_ego, Some(intrinsic))
+    }
+
+    fn add_sensor(
+        &mut self,
+        channel: &str,
+        modality: &'static str,
+        sensor_to_ego: &Affine3A,
+        intrinsic: Option<Mat3>,
+    ) -> SensorId {
+        self.sensors.push(Sensor {
+            channel: channel.to_string(),
+            modality,
+            sensor_to_ego: *sensor_to_ego,
+            intrinsic,
+        });
+        SensorId(self.sensors.len() - 1)
+    }
+
+    /// Starts a sample at `timestamp`, in microseconds. Captures belong to the latest sample.
+    pub fn add_sample(&mut self, timestamp: u64) {
+        self.samples.push(Sample { timestamp });
+    }
+
+    /// Stores the output of [`Lidar::render_lidar_pointcloud`] of `sensor`. Beams without a
+    /// return are left out.
+    ///
+    /// Fails if `sensor` was not added to this writer, no sample was added yet, `points` is not a
+    /// point cloud or writing fails.
+    pub fn write_lidar(
+        &mut self,
+        sensor: SensorId,
+        capture: Capture,
+        points: &[f32],
+    ) -> std::io::Result<()> {
+        if !points.len().is_multiple_of(4) {
+            return Err(invalid_input(format!(
+                "A point cloud of {} floats is not made of points of 4",
+                points.len()
+            )));
+        }
+        let mut data = vec![];
+        for point in points.chunks_exact(4) {
+            if point[0] == Lidar::no_hit_const() {
+                continue;
+            }
+            for value in point.iter().chain(&[0.0]) {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        self.write_capture(sensor, capture, "pcd.bin", "pcd", (0, 0), &data)
+    }
+
+    /// Stores the output of [`DepthCamera::render_depth_camera`] of `sensor`, which rendered
+    /// `camera`.
+    ///
+    /// Fails if `sensor` was not added to this writer, no sample was added yet, `depth` is not an
+    /// image of `camera` or writing fails.
+    pub fn write_depth_image(
+        &mut self,
+        sensor: SensorId,
+        capture: Capture,
+        camera: &DepthCamera,
+        depth: &[f32],
+    ) -> std::io::Result<()> {
+        let (width, height) = (camera.width() as usize, camera.height() as usize);
+        if depth.len() != width * height {
+            return Err(invalid_input(format!(
+                "A depth image of {} pixels given for a {width}x{height} camera",
+                depth.len()
+            )));
+        }
+        let mut data = Vec::with_capacity(depth.len() * 4);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let range = depth[x * height + y];
+                let range = if range < DepthCamera::no_hit_const() {
+                    range
+                } else {
+                    f32::NAN
+                };
+                data.extend_from_slice(&range.to_le_bytes());
+            }
+        }
+        let size = (camera.width(), camera.height());
+        self.write_capture(sensor, capture, "bin", "bin", size, &data)
+    }
+
+    fn write_capture(
+        &mut self,
+        sensor: SensorId,
+        capture: Capture,
+        extension: &str,
+        fileformat: &'static str,
+        size: (u32, u32),
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let Some(sample) = self.samples.len().checked_sub(1) else {
+            return Err(invalid_input(
+                "Captures need a sample, see `add_sample`".to_string(),
+            ));
+        };
+        let Some(Sensor { channel, .. }) = self.sensors.get(sensor.0) else {
+            return Err(invalid_input(format!(
+                "Sensor {} was not added to this writer",
+                sensor.0
+            )));
+        };
+        let directory = if capture.key_frame {
+            "samples"
+        } else {
+            "sweeps"
+        };
+        let filename = format!(
+            "{directory}/{channel}/{}.{extension}",
+            token("sample_data", self.sample_data.len())
+        );
+        let path = self.root.join(&filename);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, data)?;
+        self.sample_data.push(SampleData {
+            sensor: sensor.0,
+            sample,
+            capture,
+            filename,
+            fileformat,
+            size,
+        });
+        Ok(())
+    }
+
+    /// Writes the tables of the dataset.
+    pub fn finish(self) -> std::io::Result<()> {
+        let scene = token("scene", 0);
+        let log = token("log", 0);
+
+        let sensors: Vec<_> = self
+            .sensors
+            .iter()
+            .enumerate()
+            .map(|(i, sensor)| {
+                Json::new()
+                    .string("token", &token("sensor", i))
+                    .string("channel", &sensor.channel)
+                    .string("modality", sensor.modality)
+            })
+            .collect();
+        let calibrated_sensors: Vec<_> = self
+            .sensors
+            .iter()
+            .enumerate()
+            .map(|(i, sensor)| {
+                let intrinsic = match sensor.intrinsic {
+                    // Row major.
+                    Some(k) => {
+                        let k = k.transpose().to_cols_array_2d();
+                        format!(
+                            "[{}]",
+                            k.iter()
+                                .map(|row| numbers(row))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    }
+                    None => "[]".to_string(),
+                };
+                pose(Json::new(), &sensor.sensor_to_ego)
+                    .string("token", &token("calibrated_sensor", i))
+                    .string("sensor_token", &token("sensor", i))
+                    .raw("camera_intrinsic", &intrinsic)
+            })
+            .collect();
+
+        let links = |tokens: &[String], i: usize| {
+            (
+                i.checked_sub(1)
+                    .map_or(String::new(), |p| tokens[p].clone()),
+                tokens.get(i + 1).cloned().unwrap_or_default(),
+            )
+        };
+        let sample_tokens: Vec<_> = (0..self.samples.len())
+            .map(|i| token("sample", i))
+            .collect();
+        let samples: Vec<_> = self
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let (prev, next) = links(&sample_tokens, i);
+                Json::new()
+                    .string("token", &sample_tokens[i])
+                    .number("timestamp", sample.timestamp)
+                    .string("prev", &prev)
+                    .string("next", &next)
+                    .string("scene_token", &scene)
+            })
+            .collect();
+
+        // Captures are chained per sensor.
+        let mut chains: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut positions = vec![];
+        for (i, data) in self.sample_data.iter().enumerate() {
+            let chain = chains.entry(data.sensor).or_default();
+            positions.push(chain.len());
+            chain.push(token("sample_data", i));
+        }
+        let mut ego_poses = vec![];
+        let mut sample_data = vec![];
+        for (i, data) in self.sample_data.iter().enumerate() {
+            let ego_pose = token("ego_pose", i);
+            ego_poses.push(
+                pose(Json::new(), &data.capture.ego_pose)
+                    .string("token", &ego_pose)
+                    .number("timestamp", data.capture.timestamp),
+            );
+            let (prev, next) = links(&chains[&data.sensor], positions[i]);
+            sample_data.push(
+                Json::new()
+                    .string("token", &token("sample_data", i))
+                    .string("sample_token", &sample_tokens[data.sample])
+                    .string("ego_pose_token", &ego_pose)
+                    .string(
+                        "calibrated_sensor_token",
+                        &token("calibrated_sensor", data.sensor),
+                    )
+                    .number("timestamp", data.capture.timestamp)
+                    .string("fileformat", data.fileformat)
+                    .raw("is_key_frame", &data.capture.key_frame.to_string())
+                    .number("height", data.size.1 as u64)
+                    .number("width", data.size.0 as u64)
+                    .string("filename", &data.filename)
+                    .string("prev", &prev)
+                    .string("next", &next),
+            );
+        }
+
+        let scenes = [Json::new()
+            .string("token", &scene)
+            .string("log_token", &log)
+            .number("nbr_samples", self.samples.len() as u64)
+            .string(
+                "first_sample_token",
+                sample_tokens.first().map_or("", String::as_str),
+            )
+            .string(
+                "last_sample_token",
+                sample_tokens.last().map_or("", String::as_str),
+            )
+            .string("name", &self.scene_name)
+            .string("description", "Simulated with wgpu_rt_lidar")];
+        let logs = [Json::new()
+            .string("token", &log)
+            .string("logfile", "")
+            .string("vehicle", "simulation")
+            .string("date_captured", "")
+            .string("location", "simulation")];
+
+        let tables = self.root.join(&self.version);
+        write_table(&tables, "sensor", &sensors)?;
+        write_table(&tables, "calibrated_sensor", &calibrated_sensors)?;
+        write_table(&tables, "sample", &samples)?;
+        write_table(&tables, "sample_data", &sample_data)?;
+        write_table(&tables, "ego_pose", &ego_poses)?;
+        write_table(&tables, "scene", &scenes)?;
+        write_table(&tables, "log", &logs)?;
+        for table in [
+            "category",
+            "attribute",
+            "visibility",
+            "instance",
+            "sample_annotation",
+            "map",
+        ] {
+            write_table(&tables, table, &[])?;
+        }
+        Ok(())
+    }
+}
+
+/// A 32 digit hexadecimal token, unique per table and row.
+fn token(table: &str, row: usize) -> String {
+    let table = table.bytes().fold(0u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100_0000_01b3)
+    });
+    format!("{:016x}{:016x}", table, row)
+}
+
+/// Adds the `translation` and `rotation`, as w, x, y, z, of `pose`.
+fn pose(json: Json, pose: &Affine3A) -> Json {
+    let (_, rotation, translation) = pose.to_scale_rotation_translation();
+    json.raw("translation", &numbers(&translation.to_array()))
+        .raw(
+            "rotation",
+            &numbers(&[rotation.w, rotation.x, rotation.y, rotation.z]),
+        )
+}
+
+fn numbers(values: &[f32]) -> String {
+    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// A JSON object, written field by field.
+struct Json(String);
+
+impl Json {
+    fn new() -> Self {
+        Self(String::new())
+    }
+
+    fn raw(mut self, key: &str, value: &str) -> Self {
+        if !self.0.is_empty() {
+            self.0.push_str(", ");
+        }
+        write!(self.0, "\"{key}\": {value}").unwrap();
+        self
+    }
+
+    fn string(self, key: &str, value: &str) -> Self {
+        let mut quoted = String::from("\"");
+        for c in value.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        self.raw(key, &quoted)
+    }
+
+    fn number(self, key: &str, value: u64) -> Self {
+        self.raw(key, &value.to_string())
+    }
+}
+
+fn write_table(directory: &Path, table: &str, rows: &[Json]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(directory.join(format!("{table}.json")))?);
+    writer.write_all(b"[")?;
+    for (i, row) in rows.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        write!(writer, "{separator}\n{{{}}}", row.0)?;
+    }
+    writer.write_all(b"\n]\n")?;
+    writer.flush()
+}
+
+fn invalid_input(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_nuscenes_layout() {
+    use crate::utils::get_gpu;
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, _) = get_gpu(&instance).await;
+    let camera = DepthCamera::new(&device, 4, 2, 60.0, 100.0).await;
+    let directory = tempfile::tempdir().unwrap();
+    let root = directory.path();
+    let mut writer = NuScenesWriter::create(root, "v1.0-sim", "test").unwrap();
+    let lidar = writer.add_lidar("LIDAR_TOP", &Affine3A::from_translation(Vec3::Z));
+    let depth = writer.add_depth_camera("DEPTH_FRONT", &Affine3A::IDENTITY, &camera);
+    let capture = |timestamp, key_frame| Capture {
+        timestamp,
+        ego_pose: Affine3A::from_translation(Vec3::new(timestamp as f32 / 1e6, 0.0, 0.0)),
+        key_frame,
+    };
+    let points = [1.0, 2.0, 3.0, 4.0, 10000.0, 10000.0, 100000.0, 100000.0];
+
+    assert!(writer
+        .write_lidar(lidar, capture(0, true), &points)
+        .is_err());
+    writer.add_sample(0);
+    writer
+        .write_lidar(lidar, capture(0, true), &points)
+        .unwrap();
+    writer
+        .write_depth_image(depth, capture(0, true), &camera, &[1.0; 8])
+        .unwrap();
+    writer
+        .write_lidar(lidar, capture(50_000, false), &points)
+        .unwrap();
+    writer.add_sample(500_000);
+    writer
+        .write_lidar(lidar, capture(500_000, true), &points)
+        .unwrap();
+    assert!(writer
+        .write_lidar(lidar, capture(0, true), &points[1..])
+        .is_err());
+    // A sensor of another writer.
+    let error = writer
+        .write_lidar(SensorId(2), capture(500_000, true), &points)
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    writer.finish().unwrap();
+
+    // Misses are dropped, and the ring index appended.
+    let file = root.join(format!(
+        "samples/LIDAR_TOP/{}.pcd.bin",
+        token("sample_data", 0)
+    ));
+    let cloud: Vec<f32> = bytemuck::pod_collect_to_vec(&fs::read(file).unwrap());
+    assert_eq!(cloud, [1.0, 2.0, 3.0, 4.0, 0.0]);
+    assert!(root
+        .join(format!(
+            "sweeps/LIDAR_TOP/{}.pcd.bin",
+            token("sample_data", 2)
+        ))
+        .exists());
+
+    let table =
+        |name: &str| fs::read_to_string(root.join(format!("v1.0-sim/{name}.json"))).unwrap();
+    let sample_data = table("sample_data");
+    assert_eq!(sample_data.matches("\"token\"").count(), 4);
+    // The sweep links the key frames of the LiDAR before and after it.
+    assert!(sample_data.contains(&format!(
+        "\"prev\": \"{}\", \"next\": \"{}\"",
+        token("sample_data", 0),
+        token("sample_data", 3)
+    )));
+    assert!(table("calibrated_sensor").contains("\"translation\": [0, 0, 1]"));
+    assert!(table("scene").contains("\"nbr_samples\": 2"));
+    assert_eq!(table("sample_annotation"), "[\n]\n");
+}
@@ -0,0 +1,165 @@
+//! Binary PLY export of point clouds with optional normals and colors.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use glam::{Vec3, Vec4};
+
+use crate::{lidar::Lidar, Error};
+
+/// A point cloud to write as a PLY file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlyCloud {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    colors: Option<Vec<[u8; 3]>>,
+}
+
+impl PlyCloud {
+    pub fn new(positions: Vec<Vec3>) -> Self {
+        Self {
+            positions,
+            normals: None,
+            colors: None,
+        }
+    }
+
+    /// Converts the output of [`Lidar::render_lidar_pointcloud`], in the frame of the sensor.
+    /// Beams without a return are left out.
+    ///
+    /// Fails if `points` is not a point cloud.
+    pub fn from_lidar_pointcloud(points: &[f32]) -> Result<Self, Error> {
+        if !points.len().is_multiple_of(4) {
+            return Err(Error::InvalidArgument(format!(
+                "A point cloud of {} floats is not made of points of 4",
+                points.len()
+            )));
+        }
+        Ok(Self::new(
+            points
+                .chunks_exact(4)
+                .filter(|point| point[0] != Lidar::no_hit_const())
+                .map(Vec3::from_slice)
+                .collect(),
+        ))
+    }
+
+    /// Converts the output of [`crate::depth_camera::DepthCamera::render_depth_camera_pointcloud`]
+    /// of a camera with the default hit shader, as offsets from the camera in the world frame.
+    /// Pixels without a return are left out.
+    pub fn from_depth_pointcloud(points: &[Vec4]) -> Self {
+        Self::new(
+            points
+                .iter()
+                .filter(|p| p.w > 0.0)
+                .map(|p| p.truncate() * p.w)
+                .collect(),
+        )
+    }
+
+    /// Adds a normal per point.
+    ///
+    /// Fails if there is not one normal per point.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Result<Self, Error> {
+        self.check_len("normals", normals.len())?;
+        self.normals = Some(normals);
+        Ok(self)
+    }
+
+    /// Adds an RGB color per point.
+    ///
+    /// Fails if there is not one color per point.
+    pub fn with_colors(mut self, colors: Vec<[u8; 3]>) -> Result<Self, Error> {
+        self.check_len("colors", colors.len())?;
+        self.colors = Some(colors);
+        Ok(self)
+    }
+
+    fn check_len(&self, what: &str, len: usize) -> Result<(), Error> {
+        if len != self.positions.len() {
+            return Err(Error::InvalidArgument(format!(
+                "{len} {what} given for {} points",
+                self.positions.len()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    /// Writes the cloud as a binary little endian PLY file with a `vertex` element of float
+    /// `x`, `y`, `z`, float `nx`, `ny`, `nz` with normals and `uchar` `red`, `green`, `blue`
+    /// with colors.
+    pub fn write_to(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let mut header = format!(
+            "ply\nformat binary_little_endian 1.0\ncomment wgpu_rt_lidar\nelement vertex {}\n\
+             property float x\nproperty float y\nproperty float z\n",
+            self.positions.len()
+        );
+        if self.normals.is_some() {
+            header.push_str("property float nx\nproperty float ny\nproperty float nz\n");
+        }
+        if self.colors.is_some() {
+            header.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+        }
+        header.push_str("end_header\n");
+        writer.write_all(header.as_bytes())?;
+
+        let write_vec3 = |writer: &mut dyn Write, v: &Vec3| {
+            v.to_array()
+                .iter()
+                .try_for_each(|x| writer.write_all(&x.to_le_bytes()))
+        };
+        for (i, position) in self.positions.iter().enumerate() {
+            write_vec3(&mut writer, position)?;
+            if let Some(normals) = &self.normals {
+                write_vec3(&mut writer, &normals[i])?;
+            }
+            if let Some(colors) = &self.colors {
+                writer.write_all(&colors[i])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the cloud to a file. See [`PlyCloud::write_to`].
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_ply_layout() {
+    let no_hit = Lidar::no_hit_const();
+    let points = [
+        1.0, 2.0, 3.0, 4.0, //
+        no_hit, no_hit, 100000.0, 100000.0,
+    ];
+    let cloud = PlyCloud::from_lidar_pointcloud(&points).unwrap();
+    assert_eq!(cloud.positions(), [Vec3::new(1.0, 2.0, 3.0)]);
+    assert!(cloud.clone().with_colors(vec![]).is_err());
+    let cloud = cloud
+        .with_normals(vec![Vec3::Z])
+        .unwrap()
+        .with_colors(vec![[255, 0, 7]])
+        .unwrap();
+
+    let mut bytes = vec![];
+    cloud.write_to(&mut bytes).unwrap();
+    let end = b"end_header\n";
+    let body = bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+    let header = std::str::from_utf8(&bytes[..body]).unwrap();
+    assert!(header.contains("element vertex 1\n"));
+    assert!(header.contains("property float nz\nproperty uchar red\n"));
+    assert_eq!(bytes.len() - body, 6 * 4 + 3);
+    assert_eq!(bytes[body..body + 4], 1.0f32.to_le_bytes());
+    assert_eq!(bytes[bytes.len() - 3..], [255, 0, 7]);
+}
@@ -0,0 +1,362 @@
+//! Canonical scenes of known geometry and the readings sensors should return in them.
+//!
+//! Each [`Fixture`] is built from boxes and spheres whose ranges are computed analytically, so
+//! renders can be checked against exact values, in the crate's tests or when validating a new
+//! GPU, driver or sensor configuration:
+//!
+//! ```no_run
+//! # async fn run(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<(), wgpu_rt_lidar::Error> {
+//! use glam::{Affine3A, Vec3};
+//! use wgpu_rt_lidar::{fixtures::Fixture, lidar::Lidar};
+//!
+//! let fixture = Fixture::corridor();
+//! let scene = fixture.create_scene(device, queue).await?;
+//! let beams = vec![Vec3::X, Vec3::Y];
+//! let mut lidar = Lidar::new(device, beams.clone()).await;
+//! let rendered = lidar
+//!     .render_lidar_beams(&scene, device, queue, Affine3A::IDENTITY)
+//!     .await;
+//! let expected = fixture.expected_lidar_beams(&beams, Affine3A::IDENTITY);
+//! assert_eq!(expected[0], 5.0);
+//! for (rendered, expected) in rendered.iter().zip(&expected) {
+//!     assert!((rendered - expected).abs() <= fixture.tolerance + 1e-3);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use glam::{Affine3A, Vec3, Vec4, Vec4Swizzles};
+
+use crate::{
+    cpu::{CAMERA_T_MAX, CAMERA_T_MIN, LIDAR_T_MAX, LIDAR_T_MIN},
+    depth_camera::DepthCamera,
+    pose::{IntoAffine3A, IntoMat4},
+    utils::{create_cube, create_sphere},
+    AssetMesh, Instance, RayTraceScene, SceneError,
+};
+
+/// Segments of the sphere meshes around their axis and from pole to pole.
+const SPHERE_SLICES: u16 = 48;
+const SPHERE_STACKS: u16 = 24;
+
+/// An analytic shape a fixture is made of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// An axis aligned box.
+    Box {
+        min: Vec3,
+        max: Vec3,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+}
+
+impl Shape {
+    /// The first crossing of the surface in `(t_min, t_max)`, `t` in units of `direction` like a
+    /// ray query. Surfaces are hit from either side.
+    pub fn intersect(&self, origin: Vec3, direction: Vec3, t_min: f32, t_max: f32) -> Option<f32> {
+        let (near, far) = match *self {
+            Shape::Box { min, max } => {
+                let inverse = direction.recip();
+                let a = (min - origin) * inverse;
+                let b = (max - origin) * inverse;
+                let near = a.min(b).max_element();
+                let far = a.max(b).min_element();
+                if near > far {
+                    return None;
+                }
+                (near, far)
+            }
+            Shape::Sphere { center, radius } => {
+                let offset = origin - center;
+                let a = direction.length_squared();
+                let b = offset.dot(direction);
+                let discriminant = b * b - a * (offset.length_squared() - radius * radius);
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let root = discriminant.sqrt();
+                ((-b - root) / a, (-b + root) / a)
+            }
+        };
+        [near, far].into_iter().find(|t| *t > t_min && *t < t_max)
+    }
+}
+
+/// A scene of known geometry.
+///
+/// `assets` and `instances` mesh the `shapes`: boxes exactly and spheres with vertices on the
+/// sphere, so a rendered range differs from the analytic one by at most `tolerance` along the
+/// surface normal.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub assets: Vec<AssetMesh>,
+    pub instances: Vec<Instance>,
+    pub shapes: Vec<Shape>,
+    /// The largest distance between a mesh and its shape, zero without spheres.
+    pub tolerance: f32,
+}
+
+impl Fixture {
+    /// A fixture of `shapes`, with a mesh for boxes and one for spheres.
+    pub fn from_shapes(shapes: Vec<Shape>) -> Self {
+        let sphere = create_sphere(1.0, SPHERE_SLICES, SPHERE_STACKS);
+        let mut tolerance: f32 = 0.0;
+        let instances = shapes
+            .iter()
+            .map(|shape| match *shape {
+                Shape::Box { min, max } => Instance {
+                    asset_mesh_index: 0,
+                    transform: Affine3A::from_scale_rotation_translation(
+                        (max - min) / 2.0,
+                        glam::Quat::IDENTITY,
+                        (max + min) / 2.0,
+                    ),
+                    id: 0,
+                },
+                Shape::Sphere { center, radius } => {
+                    tolerance = tolerance.max(radius * mesh_gap(&sphere));
+                    Instance {
+                        asset_mesh_index: 1,
+                        transform: Affine3A::from_scale_rotation_translation(
+                            Vec3::splat(radius),
+                            glam::Quat::IDENTITY,
+                            center,
+                        ),
+                        id: 0,
+                    }
+                }
+            })
+            .collect();
+        Self {
+            assets: vec![create_cube(1.0), sphere],
+            instances,
+            shapes,
+            tolerance,
+        }
+    }
+
+    /// A corridor along +x, 2 m wide and 2.5 m high, closed by a wall whose face is at exactly
+    /// x = 5. A sensor at the origin is 1 m above the floor and 1 m from either side wall, and
+    /// reads 5 m straight ahead.
+    pub fn corridor() -> Self {
+        Self::from_shapes(vec![
+            // Floor and ceiling
+            Shape::Box {
+                min: Vec3::new(-1.0, -1.5, -1.5),
+                max: Vec3::new(5.5, 1.5, -1.0),
+            },
+            Shape::Box {
+                min: Vec3::new(-1.0, -1.5, 1.5),
+                max: Vec3::new(5.5, 1.5, 2.0),
+            },
+            // Side walls
+            Shape::Box {
+                min: Vec3::new(-1.0, -1.5, -1.0),
+                max: Vec3::new(5.5, -1.0, 1.5),
+            },
+            Shape::Box {
+                min: Vec3::new(-1.0, 1.0, -1.0),
+                max: Vec3::new(5.5, 1.5, 1.5),
+            },
+            // End wall
+            Shape::Box {
+                min: Vec3::new(5.0, -1.0, -1.0),
+                max: Vec3::new(5.5, 1.0, 1.5),
+            },
+        ])
+    }
+
+    /// A closed room spanning [-1, 1]³ on the inside with a short and a tall block on its floor,
+    /// after the Cornell box with axis aligned blocks. Sensors go in the free space around the
+    /// origin.
+    pub fn cornell_box() -> Self {
+        const WALL: f32 = 0.1;
+        let wall = |axis: usize, side: f32| {
+            let mut min = Vec3::splat(-1.0 - WALL);
+            let mut max = Vec3::splat(1.0 + WALL);
+            if side < 0.0 {
+                max[axis] = -1.0;
+            } else {
+                min[axis] = 1.0;
+            }
+            Shape::Box { min, max }
+        };
+        let mut shapes: Vec<_> = (0..3)
+            .flat_map(|axis| [wall(axis, -1.0), wall(axis, 1.0)])
+            .collect();
+        shapes.extend([
+            Shape::Box {
+                min: Vec3::new(-0.7, -0.7, -1.0),
+                max: Vec3::new(-0.1, -0.1, -0.4),
+            },
+            Shape::Box {
+                min: Vec3::new(0.1, 0.1, -1.0),
+                max: Vec3::new(0.7, 0.7, 0.2),
+            },
+        ]);
+        Self::from_shapes(shapes)
+    }
+
+    /// Spheres of 0.5 m radius on a 4 by 4 grid with a 3 m pitch, centered on the origin in the
+    /// z = 0 plane. The origin lies 1.5 m from the nearest centers along either axis.
+    pub fn sphere_field() -> Self {
+        let coordinate = |i: usize| 3.0 * i as f32 - 4.5;
+        Self::from_shapes(
+            (0..16)
+                .map(|i| Shape::Sphere {
+                    center: Vec3::new(coordinate(i % 4), coordinate(i / 4), 0.0),
+                    radius: 0.5,
+                })
+                .collect(),
+        )
+    }
+
+    /// Builds the scene of the fixture.
+    pub async fn create_scene(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<RayTraceScene, SceneError> {
+        RayTraceScene::new(device, queue, &self.assets, &self.instances).await
+    }
+
+    /// The closest crossing of any shape in `(t_min, t_max)`, `t` in units of `direction`.
+    pub fn expected_range(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<f32> {
+        self.shapes
+            .iter()
+            .filter_map(|shape| shape.intersect(origin, direction, t_min, t_max))
+            .min_by(f32::total_cmp)
+    }
+
+    /// The output of [`Lidar::render_lidar_beams`](crate::lidar::Lidar::render_lidar_beams)
+    /// for a LiDAR with `beams` at `pose`: the range of each beam, 0 without a return.
+    pub fn expected_lidar_beams(&self, beams: &[Vec3], pose: impl IntoAffine3A) -> Vec<f32> {
+        let pose = pose.into_affine3a();
+        beams
+            .iter()
+            .map(|beam| {
+                self.expected_range(
+                    pose.translation.into(),
+                    pose.matrix3 * *beam,
+                    LIDAR_T_MIN,
+                    LIDAR_T_MAX,
+                )
+                .unwrap_or(0.0)
+            })
+            .collect()
+    }
+
+    /// The output of [`DepthCamera::render_depth_camera`] for `camera` rendered with
+    /// `view_matrix`: the range through the center of each pixel, column by column from the
+    /// bottom, 99999 without a return.
+    pub fn expected_depth_image(
+        &self,
+        camera: &DepthCamera,
+        view_matrix: impl IntoMat4,
+    ) -> Vec<f32> {
+        let view_inverse = view_matrix.into_mat4().inverse();
+        let proj_inverse = camera.projection_matrix().inverse();
+        let origin = (view_inverse * Vec4::W).xyz();
+        let (width, height) = (camera.width(), camera.height());
+        (0..width * height)
+            .map(|i| {
+                let pixel = glam::Vec2::new((i / height) as f32, (i % height) as f32);
+                let d = (pixel + 0.5) / glam::Vec2::new(width as f32, height as f32) * 2.0 - 1.0;
+                let target = (proj_inverse * Vec4::new(d.x, d.y, 1.0, 1.0))
+                    .xyz()
+                    .normalize();
+                let direction = (view_inverse * target.extend(0.0)).xyz();
+                self.expected_range(origin, direction, CAMERA_T_MIN, CAMERA_T_MAX)
+                    .unwrap_or(99999.0)
+            })
+            .collect()
+    }
+}
+
+/// The largest distance between a mesh of the unit sphere and the sphere, that of the triangle
+/// whose plane is closest to the center.
+fn mesh_gap(mesh: &AssetMesh) -> f32 {
+    let position = |i: u16| Vec3::from_slice(&mesh.vertex_buf[i as usize]._pos[..3]);
+    mesh.index_buf
+        .chunks_exact(3)
+        .map(|t| {
+            let (a, b, c) = (position(t[0]), position(t[1]), position(t[2]));
+            1.0 - (b - a).cross(c - a).normalize().dot(a).abs()
+        })
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fixtures_match_analytic_readings() {
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = crate::utils::get_gpu(&instance).await;
+
+    // Beams on 7 rings from 30° below to 30° above the horizon, 90 per ring.
+    let beams: Vec<_> = (0..7 * 90)
+        .map(|i| {
+            let elevation = (-30.0 + 10.0 * (i / 90) as f32).to_radians();
+            let azimuth = (i % 90) as f32 / 90.0 * std::f32::consts::TAU;
+            Vec3::new(
+                elevation.cos() * azimuth.cos(),
+                elevation.cos() * azimuth.sin(),
+                elevation.sin(),
+            )
+        })
+        .collect();
+    let mut lidar = crate::lidar::Lidar::new(&device, beams.clone()).await;
+    let mut camera = DepthCamera::new(&device, 32, 24, 70.0, 100.0).await;
+
+    for (fixture, position) in [
+        (Fixture::corridor(), Vec3::ZERO),
+        (Fixture::cornell_box(), Vec3::new(-0.4, 0.5, 0.0)),
+        (Fixture::sphere_field(), Vec3::new(0.0, 0.0, 0.2)),
+    ] {
+        let scene = fixture.create_scene(&device, &queue).await.unwrap();
+        let pose = Affine3A::from_rotation_translation(glam::Quat::from_rotation_z(0.3), position);
+        // Rays grazing a sphere mesh differ by more than the tolerance along the normal, so a
+        // few mismatches are allowed.
+        let tolerance = |range: f32| fixture.tolerance + 1e-3 * range.max(1.0);
+
+        let rendered = lidar
+            .render_lidar_beams(&scene, &device, &queue, pose)
+            .await;
+        let expected = fixture.expected_lidar_beams(&beams, pose);
+        let mismatches = rendered
+            .iter()
+            .zip(&expected)
+            .filter(|(r, e)| (*r - *e).abs() > tolerance(**e))
+            .count();
+        assert!(
+            mismatches <= beams.len() / 100,
+            "{mismatches} mismatched beams"
+        );
+
+        let view = glam::Mat4::look_at_rh(position, position + Vec3::new(1.0, 0.2, -0.1), Vec3::Z);
+        let rendered = camera
+            .render_depth_camera(&scene, &device, &queue, view)
+            .await;
+        let expected = fixture.expected_depth_image(&camera, view);
+        let mismatches = rendered
+            .iter()
+            .zip(&expected)
+            .filter(|(r, e)| (*r - *e).abs() > tolerance(**e))
+            .count();
+        assert!(
+            mismatches <= expected.len() / 100,
+            "{mismatches} mismatched pixels"
+        );
+    }
+
+    let forward = Fixture::corridor().expected_lidar_beams(&[Vec3::X], Affine3A::IDENTITY);
+    assert_eq!(forward, vec![5.0]);
+}
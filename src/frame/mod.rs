@@ -0,0 +1,373 @@
+//! Recording the renders of several sensors into a single submission.
+//!
+//! Every `render_*` method of the sensors creates its own command encoder, submits it and waits
+//! for the readback. When a simulation tick renders several sensors, a [`FrameEncoder`] records
+//! all of them into one command encoder instead, so the GPU sees one `queue.submit`, the TLAS is
+//! rebuilt at most once for all sensors, and all outputs are read back after a single wait.
+//! Renders of scenes traced on the CPU, see [`RayTraceScene::is_cpu_fallback`], are traced when
+//! they are enqueued.
+//!
+//! ```no_run
+//! # async fn tick(
+//! #     device: &wgpu::Device,
+//! #     queue: &wgpu::Queue,
+//! #     scene: &mut wgpu_rt_lidar::RayTraceScene,
+//! #     lidar: &mut wgpu_rt_lidar::lidar::Lidar,
+//! #     camera: &mut wgpu_rt_lidar::depth_camera::DepthCamera,
+//! #     moved: &[wgpu_rt_lidar::Instance],
+//! # ) {
+//! use wgpu_rt_lidar::frame::FrameEncoder;
+//!
+//! let mut frame = FrameEncoder::new(device, queue);
+//! frame.set_transform(scene, moved, &[0]).await.unwrap();
+//! let points = frame.render_lidar_pointcloud(lidar, scene, &glam::Affine3A::IDENTITY);
+//! let depth = frame.render_depth_camera(camera, scene, glam::Mat4::IDENTITY);
+//! let mut outputs = frame.submit().await;
+//! let points: Vec<f32> = outputs.take(points);
+//! let depth: Vec<f32> = outputs.take(depth);
+//! # }
+//! ```
+
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::{
+    depth_camera::DepthCamera,
+    lidar::{Lidar, LidarOutput},
+    pose::{IntoAffine3A, IntoMat4},
+    utils::buffer_pool::BufferPool,
+    Error, Instance, RayTraceScene,
+};
+
+/// An output recorded into a [`FrameEncoder`], with its element type.
+#[derive(Debug)]
+pub struct FrameOutput<T> {
+    index: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Clone for FrameOutput<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FrameOutput<T> {}
+
+/// One render of a frame.
+enum PendingOutput {
+    /// The buffers of a recorded render, output first, and the pool they return to.
+    Recorded {
+        pool: Arc<BufferPool>,
+        buffers: Vec<wgpu::Buffer>,
+    },
+    /// The output of a render traced on the CPU.
+    Traced(Vec<u8>),
+}
+
+/// Records the renders of several sensors into one command encoder and one submission.
+///
+/// Renders are recorded in the order they are enqueued, and scene updates only apply to the
+/// renders enqueued after them.
+pub struct FrameEncoder<'a> {
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    encoder: wgpu::CommandEncoder,
+    outputs: Vec<PendingOutput>,
+}
+
+impl<'a> FrameEncoder<'a> {
+    /// Starts recording a frame.
+    pub fn new(device: &'a wgpu::Device, queue: &'a wgpu::Queue) -> Self {
+        Self {
+            device,
+            queue,
+            encoder: device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Frame Encoder"),
+            }),
+            outputs: vec![],
+        }
+    }
+
+    /// Updates the transform of instances of `scene`, see [`RayTraceScene::set_transform`].
+    ///
+    /// The TLAS is rebuilt once, before the first render of this frame tracing `scene`.
+    pub async fn set_transform(
+        &mut self,
+        scene: &mut RayTraceScene,
+        update_instance: &[Instance],
+        idx: &[usize],
+    ) -> Result<(), Error> {
+        scene.set_transform(self.device, update_instance, idx).await
+    }
+
+    /// Records a point cloud render, read back as in [`Lidar::render_lidar_pointcloud`].
+    pub fn render_lidar_pointcloud(
+        &mut self,
+        lidar: &mut Lidar,
+        scene: &RayTraceScene,
+        pose: impl IntoAffine3A,
+    ) -> FrameOutput<f32> {
+        let pose = &pose.into_affine3a();
+        if let Some(values) = lidar.trace_on_cpu(scene, pose, LidarOutput::PointCloud) {
+            return self.push_traced(&values);
+        }
+        let buffers = lidar.encode_lidar(
+            scene,
+            self.device,
+            self.queue,
+            &mut self.encoder,
+            pose,
+            LidarOutput::PointCloud,
+            false,
+        );
+        self.push(lidar.buffer_pool(), buffers)
+    }
+
+    /// Records a beam render, read back as in [`Lidar::render_lidar_beams`].
+    pub fn render_lidar_beams(
+        &mut self,
+        lidar: &mut Lidar,
+        scene: &RayTraceScene,
+        pose: impl IntoAffine3A,
+    ) -> FrameOutput<f32> {
+        let pose = &pose.into_affine3a();
+        if let Some(values) = lidar.trace_on_cpu(scene, pose, LidarOutput::Beams) {
+            return self.push_traced(&values);
+        }
+        let buffers = lidar.encode_lidar(
+            scene,
+            self.device,
+            self.queue,
+            &mut self.encoder,
+            pose,
+            LidarOutput::Beams,
+            false,
+        );
+        self.push(lidar.buffer_pool(), buffers)
+    }
+
+    /// Records a depth image render, read back as in [`DepthCamera::render_depth_camera`].
+    pub fn render_depth_camera(
+        &mut self,
+        camera: &mut DepthCamera,
+        scene: &RayTraceScene,
+        view_matrix: impl IntoMat4,
+    ) -> FrameOutput<f32> {
+        let view_matrix = view_matrix.into_mat4();
+        if let Some(depth) = camera.trace_on_cpu(scene, view_matrix) {
+            return self.push_traced(&depth);
+        }
+        let buffers = camera.encode_depth_image(
+            scene,
+            self.device,
+            Some(self.queue),
+            &mut self.encoder,
+            view_matrix,
+        );
+        self.push(camera.buffer_pool(), buffers.into())
+    }
+
+    /// Returns the command encoder of the frame, to record additional passes between renders.
+    pub fn encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        &mut self.encoder
+    }
+
+    fn push<T>(&mut self, pool: Arc<BufferPool>, buffers: Vec<wgpu::Buffer>) -> FrameOutput<T> {
+        self.push_output(PendingOutput::Recorded { pool, buffers })
+    }
+
+    fn push_traced<T: bytemuck::Pod>(&mut self, values: &[T]) -> FrameOutput<T> {
+        self.push_output(PendingOutput::Traced(bytemuck::cast_slice(values).to_vec()))
+    }
+
+    fn push_output<T>(&mut self, output: PendingOutput) -> FrameOutput<T> {
+        self.outputs.push(output);
+        FrameOutput {
+            index: self.outputs.len() - 1,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Submits the frame and reads back every output once the GPU is done.
+    pub async fn submit(mut self) -> FrameOutputs {
+        let staging: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| {
+                let PendingOutput::Recorded { pool, buffers } = output else {
+                    return None;
+                };
+                let staging = pool.take(
+                    self.device,
+                    buffers[0].size(),
+                    wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                );
+                self.encoder
+                    .copy_buffer_to_buffer(&buffers[0], 0, &staging, 0, staging.size());
+                Some(staging)
+            })
+            .collect();
+        self.queue.submit(Some(self.encoder.finish()));
+
+        let receivers: Vec<_> = staging
+            .iter()
+            .map(|staging| {
+                let staging = staging.as_ref()?;
+                let (sender, receiver) = flume::bounded(1);
+                staging
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+                Some(receiver)
+            })
+            .collect();
+        self.device.poll(wgpu::PollType::wait()).unwrap();
+
+        let mut data = Vec::with_capacity(staging.len());
+        for ((output, staging), receiver) in self.outputs.into_iter().zip(staging).zip(receivers) {
+            let (pool, buffers, staging, receiver) = match (output, staging, receiver) {
+                (PendingOutput::Recorded { pool, buffers }, Some(staging), Some(receiver)) => {
+                    (pool, buffers, staging, receiver)
+                }
+                (PendingOutput::Traced(bytes), ..) => {
+                    data.push(Some(bytes));
+                    continue;
+                }
+                _ => unreachable!("Recorded outputs have a staging buffer"),
+            };
+            receiver.recv_async().await.unwrap().unwrap();
+            let view = staging.slice(..).get_mapped_range();
+            data.push(Some(view.to_vec()));
+            drop(view);
+            staging.unmap();
+            pool.recycle(buffers.into_iter().chain([staging]));
+        }
+        FrameOutputs { data }
+    }
+}
+
+/// The outputs of a submitted [`FrameEncoder`].
+#[derive(Debug)]
+pub struct FrameOutputs {
+    data: Vec<Option<Vec<u8>>>,
+}
+
+impl FrameOutputs {
+    /// Takes the data of `output`.
+    ///
+    /// # Panics
+    ///
+    /// If the output was already taken or recorded into another frame.
+    pub fn take<T: bytemuck::Pod>(&mut self, output: FrameOutput<T>) -> Vec<T> {
+        let bytes = self
+            .data
+            .get_mut(output.index)
+            .and_then(Option::take)
+            .expect("Frame output was already taken or belongs to another frame");
+        // The bytes are not necessarily aligned for `T`.
+        bytemuck::pod_collect_to_vec(&bytes)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_frame_encoder_matches_individual_renders() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+    use glam::{Affine3A, Mat4, Vec3};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    let mut scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let mut lidar = Lidar::new(&device, vec![Vec3::X, Vec3::NEG_Z, Vec3::NEG_X]).await;
+    let mut camera = DepthCamera::new(&device, 16, 16, 59.0, 10.0).await;
+    let pose = Affine3A::from_translation(Vec3::new(0.0, 0.0, 3.0));
+    let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 4.0), Vec3::ZERO, Vec3::Y);
+    let moved = [Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::from_translation(Vec3::new(0.0, 0.0, 0.5)),
+        id: 0,
+    }];
+
+    let mut frame = FrameEncoder::new(&device, &queue);
+    frame.set_transform(&mut scene, &moved, &[0]).await.unwrap();
+    let points = frame.render_lidar_pointcloud(&mut lidar, &scene, pose);
+    let beams = frame.render_lidar_beams(&mut lidar, &scene, pose);
+    let depth = frame.render_depth_camera(&mut camera, &scene, view);
+    let mut outputs = frame.submit().await;
+
+    assert_eq!(
+        outputs.take(points),
+        lidar
+            .render_lidar_pointcloud(&scene, &device, &queue, &pose)
+            .await
+    );
+    assert_eq!(
+        outputs.take(beams),
+        lidar
+            .render_lidar_beams(&scene, &device, &queue, &pose)
+            .await
+    );
+    assert_eq!(
+        outputs.take(depth),
+        camera
+            .render_depth_camera(&scene, &device, &queue, view)
+            .await
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_frame_encoder_traces_cpu_fallback_scenes() {
+    use crate::utils::{create_cube, get_gpu};
+    use glam::{Affine3A, Mat4, Vec3};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    if !scene.is_cpu_fallback() {
+        return;
+    }
+    let mut lidar = Lidar::new(&device, vec![Vec3::X, Vec3::NEG_Z, Vec3::NEG_X]).await;
+    let mut camera = DepthCamera::new(&device, 16, 16, 59.0, 10.0).await;
+    let pose = Affine3A::from_translation(Vec3::new(0.0, 0.0, 3.0));
+    let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 4.0), Vec3::ZERO, Vec3::Y);
+
+    let mut frame = FrameEncoder::new(&device, &queue);
+    let points = frame.render_lidar_pointcloud(&mut lidar, &scene, pose);
+    let depth = frame.render_depth_camera(&mut camera, &scene, view);
+    let mut outputs = frame.submit().await;
+
+    assert_eq!(
+        outputs.take(points),
+        lidar
+            .render_lidar_pointcloud(&scene, &device, &queue, &pose)
+            .await
+    );
+    assert_eq!(
+        outputs.take(depth),
+        camera
+            .render_depth_camera(&scene, &device, &queue, view)
+            .await
+    );
+}
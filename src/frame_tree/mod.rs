@@ -0,0 +1,262 @@
+//! Named coordinate frames and the transforms between them over time.
+//!
+//! A [`FrameTree`] holds a tree of frames, e.g. `world → robot → lidar`, like ROS' TF. Each frame
+//! stores its transform relative to its parent, either fixed or sampled over simulated time, and
+//! the tree composes the chain up to the root, interpolating between samples. Sensors are then
+//! rendered straight from the tree instead of composing poses by hand:
+//!
+//! ```no_run
+//! # async fn run(
+//! #     scene: &wgpu_rt_lidar::RayTraceScene,
+//! #     device: &wgpu::Device,
+//! #     queue: &wgpu::Queue,
+//! #     lidar: &mut wgpu_rt_lidar::lidar::Lidar,
+//! # ) -> Result<(), wgpu_rt_lidar::Error> {
+//! use glam::{Affine3A, Vec3};
+//! use wgpu_rt_lidar::frame_tree::FrameTree;
+//!
+//! let mut tree = FrameTree::new();
+//! tree.set_static_transform("lidar", "robot", Affine3A::from_translation(Vec3::Z))?;
+//! tree.set_transform("robot", "world", 0.0, Affine3A::IDENTITY)?;
+//! tree.set_transform("robot", "world", 1.0, Affine3A::from_translation(Vec3::X))?;
+//!
+//! let pose = tree.pose("lidar", 0.5)?;
+//! let points = lidar.render_lidar_pointcloud(scene, device, queue, pose).await;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use glam::{Affine3A, Mat4};
+
+use crate::{pose::IntoAffine3A, Error};
+
+/// A frame and its transform relative to its parent.
+struct Frame {
+    parent: String,
+    /// Samples of the transform, sorted by time. A single sample holds at all times.
+    samples: Vec<(f64, Affine3A)>,
+}
+
+impl Frame {
+    /// The transform at `time`, interpolated between the samples around it. Outside of the
+    /// sampled range the first or last sample holds.
+    fn transform_at(&self, time: f64) -> Affine3A {
+        let next = self.samples.partition_point(|(t, _)| *t <= time);
+        if next == 0 {
+            return self.samples[0].1;
+        }
+        let (t0, a) = self.samples[next - 1];
+        if next == self.samples.len() || t0 == time {
+            return a;
+        }
+        let (t1, b) = self.samples[next];
+        let s = ((time - t0) / (t1 - t0)) as f32;
+        let (scale_a, rotation_a, translation_a) = a.to_scale_rotation_translation();
+        let (scale_b, rotation_b, translation_b) = b.to_scale_rotation_translation();
+        Affine3A::from_scale_rotation_translation(
+            scale_a.lerp(scale_b, s),
+            rotation_a.slerp(rotation_b, s),
+            translation_a.lerp(translation_b, s),
+        )
+    }
+}
+
+/// A tree of named frames whose transforms may change over time.
+///
+/// Frames are created when their transform is first set. Frames that are only ever referred to
+/// as parents, e.g. `world`, are roots.
+#[derive(Default)]
+pub struct FrameTree {
+    frames: HashMap<String, Frame>,
+}
+
+impl FrameTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the transform of `frame` relative to `parent` for all times, replacing its samples.
+    ///
+    /// Fails if `frame` already has another parent or the link would close a cycle.
+    pub fn set_static_transform(
+        &mut self,
+        frame: &str,
+        parent: &str,
+        transform: impl IntoAffine3A,
+    ) -> Result<(), Error> {
+        self.link(frame, parent)?.samples = vec![(0.0, transform.into_affine3a())];
+        Ok(())
+    }
+
+    /// Records the transform of `frame` relative to `parent` at `time`, in seconds of simulated
+    /// time. Transforms between samples are interpolated.
+    ///
+    /// Fails if `frame` already has another parent or the link would close a cycle.
+    pub fn set_transform(
+        &mut self,
+        frame: &str,
+        parent: &str,
+        time: f64,
+        transform: impl IntoAffine3A,
+    ) -> Result<(), Error> {
+        let samples = &mut self.link(frame, parent)?.samples;
+        let transform = transform.into_affine3a();
+        match samples.binary_search_by(|(t, _)| t.total_cmp(&time)) {
+            Ok(i) => samples[i].1 = transform,
+            Err(i) => samples.insert(i, (time, transform)),
+        }
+        Ok(())
+    }
+
+    /// Drops the samples older than `time`, keeping the last one before it to interpolate from.
+    pub fn prune_before(&mut self, time: f64) {
+        for frame in self.frames.values_mut() {
+            let older = frame.samples.partition_point(|(t, _)| *t < time);
+            frame.samples.drain(..older.saturating_sub(1));
+        }
+    }
+
+    /// Returns the frame, creating it under `parent` if it does not exist yet.
+    fn link(&mut self, frame: &str, parent: &str) -> Result<&mut Frame, Error> {
+        match self.frames.get(frame) {
+            Some(existing) if existing.parent != parent => {
+                return Err(Error::InvalidArgument(format!(
+                    "Frame {frame} is a child of {}, not {parent}",
+                    existing.parent
+                )));
+            }
+            Some(_) => {}
+            None => {
+                if self.chain(parent).any(|ancestor| ancestor == frame) {
+                    return Err(Error::InvalidArgument(format!(
+                        "Placing {frame} under {parent} closes a cycle"
+                    )));
+                }
+            }
+        }
+        Ok(self
+            .frames
+            .entry(frame.to_string())
+            .or_insert_with(|| Frame {
+                parent: parent.to_string(),
+                samples: vec![],
+            }))
+    }
+
+    /// `frame` followed by its ancestors up to its root.
+    fn chain<'a>(&'a self, frame: &'a str) -> impl Iterator<Item = &'a str> {
+        std::iter::successors(Some(frame), |frame| {
+            self.frames.get(*frame).map(|f| f.parent.as_str())
+        })
+    }
+
+    /// The root of the tree `frame` is in, e.g. `world`.
+    pub fn root<'a>(&'a self, frame: &'a str) -> &'a str {
+        self.chain(frame)
+            .last()
+            .expect("The chain contains the frame")
+    }
+
+    /// Returns whether `frame` was added or is the parent of a frame.
+    pub fn contains(&self, frame: &str) -> bool {
+        self.frames.contains_key(frame) || self.frames.values().any(|f| f.parent == frame)
+    }
+
+    /// The pose of `frame` in its root frame at `time`.
+    ///
+    /// Fails if the frame is unknown.
+    pub fn pose(&self, frame: &str, time: f64) -> Result<Affine3A, Error> {
+        if !self.contains(frame) {
+            return Err(Error::InvalidArgument(format!("Unknown frame {frame}")));
+        }
+        let mut pose = Affine3A::IDENTITY;
+        let mut current = frame;
+        while let Some(f) = self.frames.get(current) {
+            pose = f.transform_at(time) * pose;
+            current = f.parent.as_str();
+        }
+        Ok(pose)
+    }
+
+    /// The transform taking coordinates in `source` to coordinates in `target` at `time`.
+    ///
+    /// Fails if either frame is unknown or they are not in the same tree.
+    pub fn transform(&self, source: &str, target: &str, time: f64) -> Result<Affine3A, Error> {
+        let source_pose = self.pose(source, time)?;
+        let target_pose = self.pose(target, time)?;
+        if self.root(source) != self.root(target) {
+            return Err(Error::InvalidArgument(format!(
+                "Frames {source} and {target} are not connected"
+            )));
+        }
+        Ok(target_pose.inverse() * source_pose)
+    }
+
+    /// The view matrix to render a depth camera at `frame` with, for a frame looking down -z
+    /// with y up.
+    pub fn view_matrix(&self, frame: &str, time: f64) -> Result<Mat4, Error> {
+        Ok(Mat4::from(self.pose(frame, time)?.inverse()))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_frame_tree_interpolates_chain() {
+    use glam::{Quat, Vec3};
+
+    let mut tree = FrameTree::new();
+    tree.set_static_transform("lidar", "robot", Affine3A::from_translation(Vec3::Z))
+        .unwrap();
+    tree.set_transform("robot", "world", 0.0, Affine3A::IDENTITY)
+        .unwrap();
+    tree.set_transform(
+        "robot",
+        "world",
+        2.0,
+        Affine3A::from_rotation_translation(
+            Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            Vec3::new(2.0, 0.0, 0.0),
+        ),
+    )
+    .unwrap();
+
+    let pose = tree.pose("lidar", 1.0).unwrap();
+    assert!(pose
+        .translation
+        .abs_diff_eq(Vec3::new(1.0, 0.0, 1.0).into(), 1e-5));
+    assert!(pose.transform_vector3(Vec3::X).abs_diff_eq(
+        Vec3::new(
+            std::f32::consts::FRAC_PI_4.cos(),
+            std::f32::consts::FRAC_PI_4.sin(),
+            0.0
+        ),
+        1e-5
+    ));
+    // Held past the last sample.
+    assert_eq!(
+        tree.pose("robot", 5.0).unwrap(),
+        tree.pose("robot", 2.0).unwrap()
+    );
+    assert!(tree
+        .transform("world", "lidar", 0.0)
+        .unwrap()
+        .translation
+        .abs_diff_eq(Vec3::NEG_Z.into(), 1e-6));
+
+    assert_eq!(tree.root("lidar"), "world");
+    assert!(tree.pose("camera", 0.0).is_err());
+    assert!(tree
+        .set_static_transform("world", "lidar", Affine3A::IDENTITY)
+        .is_err());
+    assert!(tree
+        .set_static_transform("lidar", "world", Affine3A::IDENTITY)
+        .is_err());
+
+    tree.prune_before(2.5);
+    assert_eq!(
+        tree.pose("robot", 1.0).unwrap(),
+        tree.pose("robot", 2.0).unwrap()
+    );
+}
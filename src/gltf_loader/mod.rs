@@ -0,0 +1,325 @@
+//! Loading of the triangle meshes of glTF and GLB scenes, see [`AssetMesh::from_gltf`].
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use base64::Engine;
+use glam::{Affine3A, Mat4};
+use gltf::{buffer, mesh::Mode, Document, Gltf, Node};
+
+use crate::{AssetMesh, Error, Instance};
+
+impl AssetMesh {
+    /// Loads the triangle meshes of a glTF or GLB file and the instances its nodes place them
+    /// with.
+    ///
+    /// Each mesh becomes one asset, or several if it has more than 65536 vertices, see
+    /// [`AssetMesh::from_triangles`], merging its triangle primitives. Point and line primitives
+    /// are skipped. Every node of the default scene, or of the first if none is set, with a mesh
+    /// becomes an instance of each of its assets, transformed by the node and its ancestors. The
+    /// id of an instance is the index of its node, see [`Instance::id`].
+    ///
+    /// glTF is y-up. Transform the instances, e.g. by
+    /// `Affine3A::from_rotation_x(FRAC_PI_2)`, for a z-up world.
+    ///
+    /// Buffers are read from the GLB binary chunk, base64 data URIs or files next to the glTF
+    /// file. Fails with [`Error::Io`] if a file can not be read and with
+    /// [`Error::InvalidArgument`] if the file is not valid glTF.
+    pub fn from_gltf(path: impl AsRef<Path>) -> Result<(Vec<AssetMesh>, Vec<Instance>), Error> {
+        let path = path.as_ref();
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        load(&std::fs::read(path)?, Some(directory))
+    }
+
+    /// Loads a glTF or GLB file read into memory like [`AssetMesh::from_gltf`].
+    ///
+    /// Without a path to resolve them from, buffers in external files are not supported and
+    /// fail with [`Error::InvalidArgument`].
+    pub fn from_gltf_bytes(bytes: &[u8]) -> Result<(Vec<AssetMesh>, Vec<Instance>), Error> {
+        load(bytes, None)
+    }
+}
+
+fn invalid(error: impl std::fmt::Display) -> Error {
+    Error::InvalidArgument(format!("Invalid glTF: {error}"))
+}
+
+fn load(
+    bytes: &[u8],
+    directory: Option<PathBuf>,
+) -> Result<(Vec<AssetMesh>, Vec<Instance>), Error> {
+    let Gltf { document, blob } = Gltf::from_slice(bytes).map_err(invalid)?;
+    let buffers = document
+        .buffers()
+        .map(|buffer| read_buffer(&buffer, blob.as_deref(), directory.as_deref()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The assets each mesh was split into.
+    let mut assets = vec![];
+    let mut mesh_assets = vec![];
+    for mesh in document.meshes() {
+        let mut positions = vec![];
+        let mut triangles = vec![];
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let Some(primitive_positions) = reader.read_positions() else {
+                continue;
+            };
+            let first = positions.len() as u32;
+            positions.extend(primitive_positions);
+            let count = positions.len() as u32 - first;
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..count).collect(),
+            };
+            if let Some(index) = indices.iter().find(|&&index| index >= count) {
+                return Err(invalid(format!(
+                    "mesh {} refers to vertex {index} of a primitive with {count}",
+                    mesh.index()
+                )));
+            }
+            triangles.extend(
+                primitive_triangles(primitive.mode(), &indices)
+                    .map(|triangle| triangle.map(|index| first + index)),
+            );
+        }
+        let first = assets.len();
+        assets.extend(AssetMesh::from_triangles(&positions, triangles));
+        mesh_assets.push(first..assets.len());
+    }
+
+    let mut instances = vec![];
+    for node in root_nodes(&document) {
+        place_node(&node, Affine3A::IDENTITY, &mesh_assets, &mut instances);
+    }
+    Ok((assets, instances))
+}
+
+/// Reads the data of `buffer` from the GLB binary chunk, a data URI or a file in `directory`.
+fn read_buffer(
+    buffer: &buffer::Buffer,
+    blob: Option<&[u8]>,
+    directory: Option<&Path>,
+) -> Result<Vec<u8>, Error> {
+    let data = match buffer.source() {
+        buffer::Source::Bin => blob
+            .ok_or_else(|| invalid("the binary chunk is missing"))?
+            .to_vec(),
+        buffer::Source::Uri(uri) if uri.starts_with("data:") => {
+            let (_, data) = uri
+                .split_once(";base64,")
+                .ok_or_else(|| invalid(format!("buffer {} is not base64", buffer.index())))?;
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(invalid)?
+        }
+        buffer::Source::Uri(uri) => {
+            let directory = directory.ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "glTF buffer {uri} is an external file, load the glTF from its path"
+                ))
+            })?;
+            std::fs::read(directory.join(uri))?
+        }
+    };
+    if data.len() < buffer.length() {
+        return Err(invalid(format!(
+            "buffer {} holds {} of its {} bytes",
+            buffer.index(),
+            data.len(),
+            buffer.length()
+        )));
+    }
+    Ok(data)
+}
+
+/// The triangles of a primitive drawn with `mode`, none for points and lines.
+fn primitive_triangles(mode: Mode, indices: &[u32]) -> Box<dyn Iterator<Item = [u32; 3]> + '_> {
+    match mode {
+        Mode::Triangles => Box::new(indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])),
+        // Every other triangle of a strip is flipped to keep the winding.
+        Mode::TriangleStrip => Box::new(indices.windows(3).enumerate().map(|(i, t)| {
+            if i % 2 == 0 {
+                [t[0], t[1], t[2]]
+            } else {
+                [t[1], t[0], t[2]]
+            }
+        })),
+        Mode::TriangleFan => Box::new(
+            indices
+                .windows(2)
+                .skip(1)
+                .map(move |t| [indices[0], t[0], t[1]]),
+        ),
+        Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => {
+            Box::new(std::iter::empty())
+        }
+    }
+}
+
+/// The root nodes of the default scene, of the first scene without one, or every node that is
+/// no child without scenes.
+fn root_nodes(document: &Document) -> Vec<Node<'_>> {
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        return scene.nodes().collect();
+    }
+    let children: HashSet<_> = document
+        .nodes()
+        .flat_map(|node| node.children().map(|child| child.index()))
+        .collect();
+    document
+        .nodes()
+        .filter(|node| !children.contains(&node.index()))
+        .collect()
+}
+
+/// Places the assets of `node` and its descendants, `parent` being the transform of its parent.
+fn place_node(
+    node: &Node,
+    parent: Affine3A,
+    mesh_assets: &[std::ops::Range<usize>],
+    instances: &mut Vec<Instance>,
+) {
+    let local = Mat4::from_cols_array_2d(&node.transform().matrix());
+    let transform = parent * Affine3A::from_mat4(local);
+    if let Some(mesh) = node.mesh() {
+        instances.extend(mesh_assets[mesh.index()].clone().map(|asset| Instance {
+            asset_mesh_index: asset,
+            transform,
+            id: node.index() as u32,
+        }));
+    }
+    for child in node.children() {
+        place_node(&child, transform, mesh_assets, instances);
+    }
+}
+
+/// A glTF document of one triangle and a strip of two, placed by a node with a parent and by a
+/// root node, whose buffer is `buffer`.
+#[cfg(test)]
+fn test_document(buffer: &str) -> (String, Vec<u8>) {
+    let positions: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+    ];
+    let indices: [u16; 4] = [0, 1, 2, 0];
+    let mut data = bytemuck::cast_slice(&positions).to_vec();
+    data.extend_from_slice(bytemuck::cast_slice(&indices));
+    let json = format!(
+        r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0, 2]}}],
+            "nodes": [
+                {{"translation": [1.0, 0.0, 0.0], "children": [1]}},
+                {{"mesh": 0, "scale": [2.0, 2.0, 2.0]}},
+                {{"mesh": 0, "translation": [0.0, 0.0, 5.0]}}
+            ],
+            "meshes": [{{"primitives": [
+                {{"attributes": {{"POSITION": 0}}, "indices": 1}},
+                {{"attributes": {{"POSITION": 0}}, "mode": 5}}
+            ]}}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+                  "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]}},
+                {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ],
+            "bufferViews": [
+                {{"buffer": 0, "byteOffset": 0, "byteLength": 48}},
+                {{"buffer": 0, "byteOffset": 48, "byteLength": 6}}
+            ],
+            "buffers": [{{"byteLength": {}{buffer}}}]
+        }}"#,
+        data.len()
+    );
+    (json, data)
+}
+
+#[cfg(test)]
+fn check_test_document(assets: &[AssetMesh], instances: &[Instance]) {
+    use glam::Vec3;
+
+    // The triangle and the two of the strip, the strip's vertices appended to the triangle's.
+    assert_eq!(assets.len(), 1);
+    assert_eq!(assets[0].vertex_buf.len(), 7);
+    assert_eq!(assets[0].index_buf.len(), 9);
+    assert_eq!(instances.len(), 2);
+    assert_eq!(instances[0].asset_mesh_index, 0);
+    assert_eq!(instances[0].id, 1);
+    assert_eq!(
+        instances[0].transform,
+        Affine3A::from_translation(Vec3::X) * Affine3A::from_scale(Vec3::splat(2.0))
+    );
+    assert_eq!(instances[1].id, 2);
+    assert_eq!(
+        instances[1].transform,
+        Affine3A::from_translation(Vec3::new(0.0, 0.0, 5.0))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_gltf_with_data_uri() {
+    let (_, data) = test_document("");
+    let uri = format!(
+        r#", "uri": "data:application/octet-stream;base64,{}""#,
+        base64::engine::general_purpose::STANDARD.encode(&data)
+    );
+    let (json, _) = test_document(&uri);
+    let (assets, instances) = AssetMesh::from_gltf_bytes(json.as_bytes()).unwrap();
+    check_test_document(&assets, &instances);
+
+    assert!(matches!(
+        AssetMesh::from_gltf_bytes(b"{}"),
+        Err(Error::InvalidArgument(_))
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn test_glb() {
+    let (json, mut data) = test_document("");
+    let mut json = json.into_bytes();
+    // Chunks are padded to 4 bytes, JSON with spaces.
+    json.resize(json.len().next_multiple_of(4), b' ');
+    data.resize(data.len().next_multiple_of(4), 0);
+    let mut glb = b"glTF".to_vec();
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((12 + 8 + json.len() as u32 + 8 + data.len() as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(&json);
+    glb.extend((data.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(&data);
+
+    let (assets, instances) = AssetMesh::from_gltf_bytes(&glb).unwrap();
+    check_test_document(&assets, &instances);
+}
+
+#[cfg(test)]
+#[test]
+fn test_gltf_with_external_buffer() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("scene.gltf");
+    let (json, data) = test_document(r#", "uri": "mesh.bin""#);
+    std::fs::write(&path, &json).unwrap();
+    std::fs::write(directory.path().join("mesh.bin"), &data).unwrap();
+
+    let (assets, instances) = AssetMesh::from_gltf(&path).unwrap();
+    check_test_document(&assets, &instances);
+    // Without the path, the buffer can not be found.
+    assert!(matches!(
+        AssetMesh::from_gltf_bytes(json.as_bytes()),
+        Err(Error::InvalidArgument(_))
+    ));
+    directory.close().unwrap();
+    assert!(matches!(AssetMesh::from_gltf(&path), Err(Error::Io(_))));
+}
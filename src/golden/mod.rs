@@ -0,0 +1,261 @@
+//! Golden tests of the sensors against an exact CPU reference tracer.
+//!
+//! [`ReferenceTracer`] intersects every ray with every triangle of every instance, in double
+//! precision and without any acceleration structure, so it shares no code with the BLAS and TLAS
+//! construction of [`RayTraceScene`] nor with the BVHs of the CPU fallback. Rendering the fixture
+//! scene, several meshes of different vertex and index counts under rotated and scaled
+//! instances, and comparing with the reference catches vertex or index offset and instance
+//! transform regressions, like meshes rendered with the geometry of another, automatically.
+
+use glam::{Affine3A, DVec3, DVec4, Mat4, Vec3, Vec4Swizzles};
+
+use crate::{
+    depth_camera::DepthCamera,
+    lidar::Lidar,
+    utils::{create_cube, get_gpu},
+    vertex, AssetMesh, Instance, RayTraceScene,
+};
+
+/// Ranges of the LiDAR shaders.
+const LIDAR_T_MIN: f64 = 0.1;
+const LIDAR_T_MAX: f64 = 50.0;
+/// Ranges of the depth camera shaders.
+const CAMERA_T_MIN: f64 = 0.1;
+const CAMERA_T_MAX: f64 = 200.0;
+/// Rays hitting this close to a triangle edge, in barycentric coordinates, may hit either
+/// neighbour or slip between them on the GPU and are not compared.
+const EDGE_MARGIN: f64 = 1e-4;
+
+/// The closest hit of a ray.
+#[derive(Debug, Clone, Copy)]
+struct Hit {
+    t: f64,
+    /// The hit lies close to an edge of its triangle.
+    near_edge: bool,
+}
+
+/// Brute force ray tracing of every triangle of a scene, in world space.
+struct ReferenceTracer {
+    triangles: Vec<[DVec3; 3]>,
+}
+
+impl ReferenceTracer {
+    fn new(assets: &[AssetMesh], instances: &[Instance]) -> Self {
+        let mut triangles = vec![];
+        for instance in instances {
+            let asset = &assets[instance.asset_mesh_index];
+            let position = |i: u16| {
+                let p = Vec3::from_slice(&asset.vertex_buf[i as usize]._pos[..3]);
+                instance.transform.transform_point3(p).as_dvec3()
+            };
+            triangles.extend(
+                asset
+                    .index_buf
+                    .chunks_exact(3)
+                    .map(|t| [position(t[0]), position(t[1]), position(t[2])]),
+            );
+        }
+        Self { triangles }
+    }
+
+    /// The closest hit in `(t_min, t_max)`, `t` in units of `direction` like a ray query.
+    fn trace(&self, origin: DVec3, direction: DVec3, t_min: f64, t_max: f64) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        for [v0, v1, v2] in &self.triangles {
+            // Möller-Trumbore
+            let e1 = *v1 - *v0;
+            let e2 = *v2 - *v0;
+            let p = direction.cross(e2);
+            let det = e1.dot(p);
+            if det == 0.0 {
+                continue;
+            }
+            let s = origin - *v0;
+            let u = s.dot(p) / det;
+            let q = s.cross(e1);
+            let v = direction.dot(q) / det;
+            let w = 1.0 - u - v;
+            if u < -EDGE_MARGIN || v < -EDGE_MARGIN || w < -EDGE_MARGIN {
+                continue;
+            }
+            let t = e2.dot(q) / det;
+            if t <= t_min || t >= t_max || closest.is_some_and(|hit| hit.t <= t) {
+                continue;
+            }
+            closest = Some(Hit {
+                t,
+                near_edge: u.min(v).min(w) < EDGE_MARGIN,
+            });
+        }
+        closest
+    }
+}
+
+/// Meshes of different vertex and index counts, placed by rotated, scaled and translated
+/// instances, with several instances of the same mesh.
+fn fixture_scene() -> (Vec<AssetMesh>, Vec<Instance>) {
+    let ground = AssetMesh {
+        vertex_buf: vec![
+            vertex([-20.0, -20.0, 0.0]),
+            vertex([20.0, -20.0, 0.0]),
+            vertex([20.0, 20.0, 0.0]),
+            vertex([-20.0, 20.0, 0.0]),
+        ],
+        index_buf: vec![0, 1, 2, 2, 3, 0],
+    };
+    let tetrahedron = AssetMesh {
+        vertex_buf: vec![
+            vertex([1.0, 1.0, 1.0]),
+            vertex([1.0, -1.0, -1.0]),
+            vertex([-1.0, 1.0, -1.0]),
+            vertex([-1.0, -1.0, 1.0]),
+        ],
+        index_buf: vec![0, 1, 2, 0, 3, 1, 0, 2, 3, 1, 3, 2],
+    };
+    let instance = |asset_mesh_index, transform| Instance {
+        asset_mesh_index,
+        transform,
+        id: 0,
+    };
+    let instances = vec![
+        instance(1, Affine3A::from_translation(Vec3::new(0.0, 0.0, -1.5))),
+        instance(0, Affine3A::IDENTITY),
+        instance(
+            2,
+            Affine3A::from_scale_rotation_translation(
+                Vec3::splat(1.5),
+                glam::Quat::from_rotation_z(0.4),
+                Vec3::new(-5.0, -3.0, 0.5),
+            ),
+        ),
+        instance(
+            0,
+            Affine3A::from_scale_rotation_translation(
+                Vec3::new(1.0, 2.0, 0.5),
+                glam::Quat::from_euler(glam::EulerRot::ZYX, 0.7, 0.2, 0.1),
+                Vec3::new(6.0, 2.0, 0.0),
+            ),
+        ),
+    ];
+    (vec![create_cube(1.0), ground, tetrahedron], instances)
+}
+
+/// Beams on 9 rings from 30° below to 10° above the horizon, 64 per ring.
+fn fixture_beams() -> Vec<Vec3> {
+    let mut beams = vec![];
+    for ring in 0..9 {
+        let elevation = (-30.0 + 5.0 * ring as f32).to_radians();
+        for i in 0..64 {
+            let azimuth = i as f32 / 64.0 * std::f32::consts::TAU;
+            beams.push(Vec3::new(
+                elevation.cos() * azimuth.cos(),
+                elevation.cos() * azimuth.sin(),
+                elevation.sin(),
+            ));
+        }
+    }
+    beams
+}
+
+/// Compares ranges with `miss` marking rays without a return, skipping rays the reference
+/// deems ambiguous. Returns the indices and values of the mismatches.
+fn compare(
+    rendered: &[f32],
+    reference: &[Option<Hit>],
+    miss: f32,
+    tolerance: f64,
+) -> Vec<(usize, f32, Option<f64>)> {
+    assert_eq!(rendered.len(), reference.len());
+    rendered
+        .iter()
+        .zip(reference)
+        .enumerate()
+        .filter(|(_, (rendered, reference))| match reference {
+            Some(hit) if hit.near_edge => false,
+            Some(hit) => (**rendered as f64 - hit.t).abs() > tolerance * hit.t.max(1.0),
+            None => **rendered != miss,
+        })
+        .map(|(i, (rendered, reference))| (i, *rendered, reference.map(|hit| hit.t)))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_golden_lidar() {
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let (assets, instances) = fixture_scene();
+    let scene = RayTraceScene::new(&device, &queue, &assets, &instances)
+        .await
+        .unwrap();
+    let reference = ReferenceTracer::new(&assets, &instances);
+
+    let beams = fixture_beams();
+    let mut lidar = Lidar::new(&device, beams.clone()).await;
+    for pose in [
+        Affine3A::from_translation(Vec3::new(0.0, -6.0, 0.3)),
+        Affine3A::from_rotation_translation(
+            glam::Quat::from_rotation_z(1.0) * glam::Quat::from_rotation_x(0.2),
+            Vec3::new(2.0, 4.0, 1.0),
+        ),
+    ] {
+        let rendered = lidar
+            .render_lidar_beams(&scene, &device, &queue, pose)
+            .await;
+        let expected: Vec<_> = beams
+            .iter()
+            .map(|beam| {
+                reference.trace(
+                    pose.translation.as_dvec3(),
+                    (pose.matrix3 * *beam).as_dvec3(),
+                    LIDAR_T_MIN,
+                    LIDAR_T_MAX,
+                )
+            })
+            .collect();
+        assert!(
+            expected.iter().filter(|hit| hit.is_some()).count() > beams.len() / 2,
+            "The fixture should be mostly in view"
+        );
+        let mismatches = compare(&rendered, &expected, 0.0, 1e-3);
+        assert!(mismatches.is_empty(), "{mismatches:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_golden_depth_camera() {
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let (assets, instances) = fixture_scene();
+    let scene = RayTraceScene::new(&device, &queue, &assets, &instances)
+        .await
+        .unwrap();
+    let reference = ReferenceTracer::new(&assets, &instances);
+
+    let (width, height) = (40, 30);
+    let mut camera = DepthCamera::new(&device, width, height, 70.0, 100.0).await;
+    let view = Mat4::look_at_rh(Vec3::new(0.0, -12.0, 4.0), Vec3::ZERO, Vec3::Z);
+    let rendered = camera
+        .render_depth_camera(&scene, &device, &queue, view)
+        .await;
+
+    // The ray through the center of each pixel, as the depth camera shaders compute it.
+    let view_inverse = view.inverse().as_dmat4();
+    let proj_inverse = camera.projection_matrix().inverse().as_dmat4();
+    let origin = (view_inverse * DVec4::W).xyz();
+    let expected: Vec<_> = (0..width * height)
+        .map(|i| {
+            let (x, y) = (i / height, i % height);
+            let d = (glam::DVec2::new(x as f64, y as f64) + 0.5)
+                / glam::DVec2::new(width as f64, height as f64)
+                * 2.0
+                - 1.0;
+            let target = (proj_inverse * DVec4::new(d.x, d.y, 1.0, 1.0))
+                .xyz()
+                .normalize();
+            let direction = (view_inverse * target.extend(0.0)).xyz();
+            reference.trace(origin, direction, CAMERA_T_MIN, CAMERA_T_MAX)
+        })
+        .collect();
+    let mismatches = compare(&rendered, &expected, 99999.0, 1e-3);
+    assert!(mismatches.is_empty(), "{mismatches:?}");
+}
@@ -0,0 +1,3 @@
+fn hit_shader(hit: HitInfo) -> f32 {
+    return hit.distance;
+}
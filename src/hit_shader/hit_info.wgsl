@@ -0,0 +1,23 @@
+// Information about a single ray hit, passed to the user supplied `hit_shader` function.
+
+struct HitInfo {
+    // Origin of the ray in world coordinates.
+    origin: vec3<f32>,
+    // Direction of the ray in world coordinates.
+    direction: vec3<f32>,
+    // Distance along the ray to the hit. LiDAR ranges include the sensor's noise model, depth
+    // camera ranges are noise free.
+    distance: f32,
+    // Index of the hit instance in the TLAS.
+    instance_index: u32,
+    // Id of the hit instance, see `Instance::id`.
+    instance_custom_data: u32,
+    // Index of the hit triangle within its mesh.
+    primitive_index: u32,
+    // Barycentric coordinates of the hit on the triangle.
+    barycentrics: vec2<f32>,
+    // Whether the ray hit the front face of the triangle.
+    front_face: bool,
+    // Index of the beam or pixel that produced the hit.
+    ray_index: u32,
+};
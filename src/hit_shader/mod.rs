@@ -0,0 +1,96 @@
+//! User supplied WGSL hit shading hooks.
+//!
+//! A [`HitShader`] is a WGSL snippet defining
+//!
+//! ```wgsl
+//! fn hit_shader(hit: HitInfo) -> f32
+//! ```
+//!
+//! that is spliced into the point cloud pipelines when a sensor is created. Its return value is
+//! written into the fourth channel of every point, so users can compute intensities, semantic
+//! classes or any other per-hit payload without forking the crate's shaders. The fields available
+//! on `HitInfo` are documented in [`HIT_INFO_WGSL`].
+
+use std::path::Path;
+#[cfg(feature = "shader-hot-reload")]
+use std::path::PathBuf;
+
+use crate::shaders;
+
+/// WGSL definition of the `HitInfo` struct passed to `hit_shader`.
+pub const HIT_INFO_WGSL: &str = include_str!("hit_info.wgsl");
+
+/// A WGSL snippet computing a per-hit payload.
+#[derive(Clone, Debug)]
+pub struct HitShader {
+    source: String,
+    /// The file the snippet was loaded from, read again when sensors reload their shaders.
+    #[cfg(feature = "shader-hot-reload")]
+    path: Option<PathBuf>,
+}
+
+impl HitShader {
+    /// Creates a hit shader from WGSL source. The source must define
+    /// `fn hit_shader(hit: HitInfo) -> f32`.
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            #[cfg(feature = "shader-hot-reload")]
+            path: None,
+        }
+    }
+
+    /// Loads a hit shader from a WGSL file.
+    ///
+    /// With the `shader-hot-reload` feature, sensors using it reload it when the file changes.
+    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        #[allow(unused_mut)]
+        let mut shader = Self::new(std::fs::read_to_string(path.as_ref())?);
+        #[cfg(feature = "shader-hot-reload")]
+        {
+            shader.path = Some(path.as_ref().to_path_buf());
+        }
+        Ok(shader)
+    }
+
+    /// Returns the WGSL source of the snippet.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Splices the snippet in front of a sensor shader.
+    pub(crate) fn compose(&self, prelude: &[&str], shader: &str) -> String {
+        let mut parts = prelude.to_vec();
+        let hit_info = shaders::HIT_INFO.source();
+        parts.extend([&hit_info, self.source.as_str(), shader]);
+        parts.join("\n")
+    }
+
+    /// The file the snippet was loaded from, if any.
+    #[cfg(feature = "shader-hot-reload")]
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Reads the snippet again from its file. Snippets not loaded from a file are kept.
+    #[cfg(feature = "shader-hot-reload")]
+    pub(crate) fn reloaded(&self) -> Self {
+        match &self.path {
+            Some(path) => Self::from_file(path).unwrap_or_else(|_| self.clone()),
+            None => self.clone(),
+        }
+    }
+}
+
+impl Default for HitShader {
+    /// The default hit shader returns the distance to the hit.
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut shader = Self::new(shaders::DEFAULT_HIT.source());
+        #[cfg(feature = "shader-hot-reload")]
+        {
+            shader.path = Some(shaders::DEFAULT_HIT.disk_path());
+        }
+        shader
+    }
+}
@@ -1,16 +1,67 @@
-#[cfg(feature = "visualization")]
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::iter;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use bytemuck_derive::{Pod, Zeroable};
 use glam::Affine3A;
 use wgpu::util::DeviceExt;
 
+pub use capabilities::{is_supported, supported_adapters, Capabilities};
+pub use context::RenderContext;
+pub use error::{Error, SceneError};
+pub use half;
+pub use render_scene::LiDARRenderScene;
+pub use scene_builder::RayTraceSceneBuilder;
 pub use wgpu;
 
+pub mod capabilities;
+pub mod context;
+mod cpu;
 pub mod depth_camera;
+pub mod device_monitor;
+pub mod error;
+pub mod export;
+pub mod fixtures;
+pub mod frame;
+pub mod frame_tree;
+#[cfg(feature = "gltf")]
+mod gltf_loader;
+#[cfg(test)]
+mod golden;
+pub mod hit_shader;
 pub mod lidar;
+#[cfg(feature = "mcap")]
+pub mod mcap;
+pub mod multi_gpu;
+pub mod noise;
+pub mod pipeline_cache;
+pub mod planner;
+pub mod pose;
+pub mod render_scene;
+pub mod render_stats;
+pub mod rng;
+#[cfg(feature = "ros2")]
+pub mod ros2;
+pub mod scene_builder;
+pub mod scene_handle;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod sdf;
+#[cfg(feature = "server")]
+pub mod server;
+mod shaders;
+#[cfg(feature = "urdf")]
+pub mod urdf;
 pub mod utils;
+#[cfg(feature = "validation")]
+pub mod validation;
+#[cfg(feature = "viewer")]
+pub mod viewer;
+pub mod visualizer;
+mod xml;
+#[cfg(feature = "zenoh")]
+pub mod zenoh;
 
 /// Helper function to convert an affine matrix to a 4x3 row matrix.
 #[inline]
@@ -65,8 +116,11 @@ fn affine_to_4x4rows(mat: &Affine3A) -> [f32; 16] {
 /// This is used for loading mesh data into the GPU.
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vertex {
+    #[cfg_attr(feature = "serde", serde(rename = "position"))]
     _pos: [f32; 4],
+    #[cfg_attr(feature = "serde", serde(rename = "tex_coord"))]
     _tex_coord: [f32; 2],
 }
 
@@ -86,6 +140,7 @@ pub fn vertex(pos: [f32; 3]) -> Vertex {
 ///
 /// This struct holds the raw geometry data for a 3D model.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssetMesh {
     /// The vertex buffer containing the mesh's vertices.
     pub vertex_buf: Vec<Vertex>,
@@ -93,31 +148,135 @@ pub struct AssetMesh {
     pub index_buf: Vec<u16>,
 }
 
+impl AssetMesh {
+    /// Builds assets from triangles indexing `positions`, e.g. as read from a mesh file.
+    ///
+    /// Assets index their vertices with `u16`, so the triangles are split across as many assets
+    /// as needed to keep each one within 65536 vertices, all to be placed with the same
+    /// transform. Each asset only holds the positions its triangles use. Returns no asset
+    /// without triangles.
+    ///
+    /// # Panics
+    ///
+    /// If a triangle refers to a position out of `positions`.
+    pub fn from_triangles(
+        positions: &[[f32; 3]],
+        triangles: impl IntoIterator<Item = [u32; 3]>,
+    ) -> Vec<AssetMesh> {
+        let empty = || AssetMesh {
+            vertex_buf: vec![],
+            index_buf: vec![],
+        };
+        let mut assets = vec![];
+        let mut asset = empty();
+        // Index of each position in the vertices of the current asset.
+        let mut local = HashMap::new();
+        for triangle in triangles {
+            let added = triangle
+                .iter()
+                .filter(|index| !local.contains_key(*index))
+                .count();
+            if asset.vertex_buf.len() + added > u16::MAX as usize + 1 {
+                assets.push(std::mem::replace(&mut asset, empty()));
+                local.clear();
+            }
+            for index in triangle {
+                let vertex_index = *local.entry(index).or_insert_with(|| {
+                    asset.vertex_buf.push(vertex(positions[index as usize]));
+                    (asset.vertex_buf.len() - 1) as u16
+                });
+                asset.index_buf.push(vertex_index);
+            }
+        }
+        if !asset.index_buf.is_empty() {
+            assets.push(asset);
+        }
+        assets
+    }
+}
+
 /// Represents an instance of a mesh asset in the scene.
 ///
 /// Each instance has a reference to a mesh asset and its own transform.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instance {
     /// The index of the `AssetMesh` in the scene's asset list.
     pub asset_mesh_index: usize,
     /// The 3D transformation of the instance.
     pub transform: Affine3A,
+    /// An id of the object the instance belongs to, e.g. a class or a track id, reported for
+    /// the hits on it by [`lidar::Lidar::render_lidar_instance_ids`] and
+    /// [`depth_camera::DepthCamera::render_depth_camera_instance_ids`].
+    ///
+    /// It is the custom index of the instance in the TLAS, `instance_custom_data` in a
+    /// [`hit_shader::HitShader`], so it is at most [`Instance::MAX_ID`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub id: u32,
+}
+
+impl Instance {
+    /// The largest id an instance can have, as the TLAS only keeps 24 bits of it.
+    pub const MAX_ID: u32 = (1 << 24) - 1;
+    /// The id reported for beams and pixels that hit no instance.
+    pub const NO_HIT: u32 = u32::MAX;
+}
+
+/// How a scene builds its acceleration structures, set through [`RayTraceSceneBuilder`].
+#[derive(Debug, Clone)]
+pub(crate) struct SceneOptions {
+    /// Number of instances the TLAS has room for. Raised to the number of instances if lower.
+    pub(crate) max_instances: usize,
+    pub(crate) build_flags: wgpu::AccelerationStructureFlags,
+    /// Mask instances are placed in the TLAS with unless set otherwise, see
+    /// [`RayTraceScene::set_instance_mask`].
+    pub(crate) instance_mask: u8,
+}
+
+impl Default for SceneOptions {
+    fn default() -> Self {
+        Self {
+            max_instances: 0,
+            build_flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
+            instance_mask: 0xff,
+        }
+    }
+}
+
+/// The acceleration structures a scene is traced against.
+pub(crate) enum SceneBackend {
+    /// One BLAS per asset and a TLAS over the instances.
+    Gpu {
+        blas: Vec<wgpu::Blas>,
+        tlas: wgpu::Tlas,
+    },
+    /// Hierarchies traced on the CPU, for devices without ray queries.
+    Cpu(cpu::CpuScene),
 }
 
 /// A hardware-accelerated ray tracing scene.
 ///
 /// This struct manages the 3D scene, including mesh assets and instances,
-/// and provides the necessary structures for GPU-based ray tracing.
+/// and provides the necessary structures for GPU-based ray tracing. On devices without ray
+/// queries the scene is traced on the CPU instead, see [`RayTraceScene::is_cpu_fallback`].
+///
+/// The scene is `Send + Sync` and renders only borrow it, so sensors on several threads can
+/// render it at once. Moving instances needs exclusive access, see
+/// [`scene_handle::SceneHandle`] for sharing a scene that changes.
 pub struct RayTraceScene {
     #[cfg(feature = "visualization")]
     pub(crate) vertex_buf: wgpu::Buffer,
     #[cfg(feature = "visualization")]
     pub(crate) index_buf: wgpu::Buffer,
-    pub(crate) blas: Vec<wgpu::Blas>,
-    pub(crate) tlas_package: wgpu::Tlas,
-    #[cfg(feature = "visualization")]
+    pub(crate) backend: SceneBackend,
+    /// Kept to rebuild the scene on another device, see [`RayTraceScene::recreate`].
     pub(crate) assets: Vec<AssetMesh>,
     pub(crate) instances: Vec<Instance>,
+    /// The mask of each instance, see [`RayTraceScene::set_instance_mask`].
+    pub(crate) instance_masks: Vec<u8>,
+    pub(crate) options: SceneOptions,
+    /// Set when instances changed since the TLAS was last built.
+    tlas_dirty: AtomicBool,
 }
 
 impl RayTraceScene {
@@ -132,12 +291,61 @@ impl RayTraceScene {
     /// * `queue` - The `wgpu::Queue` to use for submitting commands.
     /// * `assets` - A list of `AssetMesh` to populate the scene with.
     /// * `instances` - A list of `Instance` to place in the scene.
+    ///
+    /// The assets and instances are validated before any buffer is created. Fails with
+    /// [`SceneError::EmptyAsset`], [`SceneError::PartialTriangle`] or
+    /// [`SceneError::VertexOutOfBounds`] for an asset without whole triangles over its own
+    /// vertices, with [`SceneError::AssetOutOfBounds`] for an instance of a missing asset, with
+    /// [`SceneError::IdOutOfRange`] for an id above [`Instance::MAX_ID`], with
+    /// [`SceneError::NoInstances`] if there are no instances, and with [`SceneError::Gpu`] if
+    /// wgpu rejects the acceleration structures anyway.
+    ///
+    /// See [`RayTraceScene::builder`] for scenes with other options.
     pub async fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        assets: &Vec<AssetMesh>,
+        assets: &[AssetMesh],
+        instances: &[Instance],
+    ) -> Result<Self, SceneError> {
+        Self::with_options(device, queue, assets, instances, SceneOptions::default()).await
+    }
+
+    /// Creates a scene like [`RayTraceScene::new`] whose TLAS has room for `max_instances`
+    /// instances, or for the instances given if there are more.
+    ///
+    /// Scenes that spawn instances over time size the TLAS for the population they expect, so
+    /// it is not reallocated each time the population grows, see
+    /// [`RayTraceScene::reserve_instances`]. Unlike [`RayTraceScene::new`], it may start without
+    /// instances if it has room for some.
+    pub async fn with_capacity(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &[AssetMesh],
+        instances: &[Instance],
+        max_instances: usize,
+    ) -> Result<Self, SceneError> {
+        let options = SceneOptions {
+            max_instances,
+            ..Default::default()
+        };
+        Self::with_options(device, queue, assets, instances, options).await
+    }
+
+    /// Starts building a scene, e.g. with headroom for instances or other build flags.
+    pub fn builder() -> RayTraceSceneBuilder {
+        RayTraceSceneBuilder::default()
+    }
+
+    pub(crate) async fn with_options(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &[AssetMesh],
         instances: &[Instance],
-    ) -> Self {
+        mut options: SceneOptions,
+    ) -> Result<Self, SceneError> {
+        Self::validate(assets, instances, &options)?;
+        options.max_instances = options.max_instances.max(instances.len());
+        let instance_masks = vec![options.instance_mask; instances.len()];
         let mut vertex_data = vec![];
         let mut index_data = vec![];
         let mut start_vertex_address = vec![];
@@ -153,18 +361,133 @@ impl RayTraceScene {
             geometries.push(start_indices..end_indices);
         }
 
+        // Without ray queries the buffers only serve the visualization.
+        let ray_queries = utils::supports_ray_queries(device);
+        let blas_input = if ray_queries {
+            wgpu::BufferUsages::BLAS_INPUT
+        } else {
+            wgpu::BufferUsages::empty()
+        };
+
         let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
             contents: bytemuck::cast_slice(&vertex_data),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::BLAS_INPUT,
+            usage: wgpu::BufferUsages::VERTEX | blas_input,
         });
 
         let index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index Buffer"),
             contents: bytemuck::cast_slice(&index_data),
-            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::BLAS_INPUT,
+            usage: wgpu::BufferUsages::INDEX | blas_input,
         });
 
+        let backend = if ray_queries {
+            Self::build_acceleration_structures(
+                device,
+                queue,
+                assets,
+                instances,
+                &vertex_buf,
+                &index_buf,
+                &start_vertex_address,
+                &start_indices_address,
+                &instance_masks,
+                &options,
+            )
+            .await?
+        } else {
+            SceneBackend::Cpu(cpu::CpuScene::new(assets, instances, &instance_masks))
+        };
+
+        Ok(Self {
+            #[cfg(feature = "visualization")]
+            vertex_buf,
+            #[cfg(feature = "visualization")]
+            index_buf,
+            backend,
+            assets: assets.to_vec(),
+            instances: instances.to_vec(),
+            instance_masks,
+            options,
+            tlas_dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Checks that every asset holds whole triangles over its own vertices and every instance
+    /// refers to an asset.
+    fn validate(
+        assets: &[AssetMesh],
+        instances: &[Instance],
+        options: &SceneOptions,
+    ) -> Result<(), SceneError> {
+        for (asset_index, asset) in assets.iter().enumerate() {
+            if asset.vertex_buf.is_empty() || asset.index_buf.is_empty() {
+                return Err(SceneError::EmptyAsset { asset: asset_index });
+            }
+            if asset.index_buf.len() % 3 != 0 {
+                return Err(SceneError::PartialTriangle {
+                    asset: asset_index,
+                    indices: asset.index_buf.len(),
+                });
+            }
+            if let Some(&index) = asset
+                .index_buf
+                .iter()
+                .find(|&&index| index as usize >= asset.vertex_buf.len())
+            {
+                return Err(SceneError::VertexOutOfBounds {
+                    asset: asset_index,
+                    index,
+                    vertices: asset.vertex_buf.len(),
+                });
+            }
+        }
+        Self::validate_instances(assets.len(), instances, 0)?;
+        if instances.is_empty() && options.max_instances == 0 {
+            return Err(SceneError::NoInstances);
+        }
+        Ok(())
+    }
+
+    /// Checks that the instances, to be placed from index `first` on, refer to one of `assets`
+    /// and have an id the TLAS can hold.
+    fn validate_instances(
+        assets: usize,
+        instances: &[Instance],
+        first: usize,
+    ) -> Result<(), SceneError> {
+        for (index, instance) in instances.iter().enumerate() {
+            if instance.asset_mesh_index >= assets {
+                return Err(SceneError::AssetOutOfBounds {
+                    instance: first + index,
+                    asset: instance.asset_mesh_index,
+                    assets,
+                });
+            }
+            if instance.id > Instance::MAX_ID {
+                return Err(SceneError::IdOutOfRange {
+                    instance: first + index,
+                    id: instance.id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn build_acceleration_structures(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &[AssetMesh],
+        instances: &[Instance],
+        vertex_buf: &wgpu::Buffer,
+        index_buf: &wgpu::Buffer,
+        start_vertex_address: &[usize],
+        start_indices_address: &[usize],
+        instance_masks: &[u8],
+        options: &SceneOptions,
+    ) -> Result<SceneBackend, SceneError> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
         let mut geometry_desc_sizes = vec![];
         let mut blas = vec![];
         println!("Creating BLAS for {} assets", assets.len());
@@ -174,19 +497,13 @@ impl RayTraceScene {
                 asset.vertex_buf.len(),
                 asset.index_buf.len()
             );
-            let geom_list = vec![wgpu::BlasTriangleGeometrySizeDescriptor {
-                vertex_count: asset.vertex_buf.len() as u32,
-                vertex_format: wgpu::VertexFormat::Float32x3,
-                index_count: Some(asset.index_buf.len() as u32),
-                index_format: Some(wgpu::IndexFormat::Uint16),
-                flags: wgpu::AccelerationStructureGeometryFlags::OPAQUE,
-            }];
+            let geom_list = vec![Self::blas_geometry_size(asset)];
             geometry_desc_sizes.push(geom_list.clone());
 
             blas.push(device.create_blas(
                 &wgpu::CreateBlasDescriptor {
                     label: Some(&format!("BLAS {}", blas.len())),
-                    flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
+                    flags: options.build_flags,
                     update_mode: wgpu::AccelerationStructureUpdateMode::Build,
                 },
                 wgpu::BlasGeometrySizeDescriptors::Triangles {
@@ -195,23 +512,8 @@ impl RayTraceScene {
             ));
         }
 
-        let tlas = device.create_tlas(&wgpu::CreateTlasDescriptor {
-            label: None,
-            flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
-            update_mode: wgpu::AccelerationStructureUpdateMode::Build,
-            max_instances: instances.len() as u32,
-        });
-
-        let mut tlas_package = tlas;
-
-        for (idx, instance) in instances.iter().enumerate() {
-            tlas_package[idx] = Some(wgpu::TlasInstance::new(
-                &blas[instance.asset_mesh_index],
-                affine_to_rows(&instance.transform),
-                0,
-                0xff,
-            ));
-        }
+        let mut tlas_package = Self::create_tlas(device, options);
+        Self::place_instances(&mut tlas_package, &blas, instances, instance_masks, 0);
 
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -223,10 +525,10 @@ impl RayTraceScene {
                 geometry: wgpu::BlasGeometries::TriangleGeometries(vec![
                     wgpu::BlasTriangleGeometry {
                         size: &geometry_desc_sizes[index][0],
-                        vertex_buffer: &vertex_buf,
+                        vertex_buffer: vertex_buf,
                         first_vertex: start_vertex_address[index] as u32,
                         vertex_stride: std::mem::size_of::<Vertex>() as u64,
-                        index_buffer: Some(&index_buf),
+                        index_buffer: Some(index_buf),
                         first_index: Some(start_indices_address[index] as u32),
                         transform_buffer: None,
                         transform_buffer_offset: None,
@@ -237,60 +539,424 @@ impl RayTraceScene {
         encoder.build_acceleration_structures(blas_iter.iter(), iter::once(&tlas_package));
 
         queue.submit(Some(encoder.finish()));
-        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        if let Some(error) = device.pop_error_scope().await {
+            return Err(SceneError::Gpu(error));
+        }
 
-        Self {
-            #[cfg(feature = "visualization")]
-            vertex_buf,
-            #[cfg(feature = "visualization")]
-            index_buf,
+        Ok(SceneBackend::Gpu {
             blas,
-            tlas_package,
-            #[cfg(feature = "visualization")]
-            assets: assets.clone(),
-            instances: instances.to_vec(),
+            tlas: tlas_package,
+        })
+    }
+
+    /// Describes the triangles of `asset` for its BLAS.
+    fn blas_geometry_size(asset: &AssetMesh) -> wgpu::BlasTriangleGeometrySizeDescriptor {
+        wgpu::BlasTriangleGeometrySizeDescriptor {
+            vertex_count: asset.vertex_buf.len() as u32,
+            vertex_format: wgpu::VertexFormat::Float32x3,
+            index_count: Some(asset.index_buf.len() as u32),
+            index_format: Some(wgpu::IndexFormat::Uint16),
+            flags: wgpu::AccelerationStructureGeometryFlags::OPAQUE,
+        }
+    }
+
+    /// Creates a TLAS with room for `options.max_instances` instances. It still has to be built.
+    fn create_tlas(device: &wgpu::Device, options: &SceneOptions) -> wgpu::Tlas {
+        device.create_tlas(&wgpu::CreateTlasDescriptor {
+            label: None,
+            flags: options.build_flags,
+            update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+            max_instances: options.max_instances as u32,
+        })
+    }
+
+    /// Places the instances from index `first` on in their slots of the TLAS.
+    fn place_instances(
+        tlas: &mut wgpu::Tlas,
+        blas: &[wgpu::Blas],
+        instances: &[Instance],
+        instance_masks: &[u8],
+        first: usize,
+    ) {
+        let placed = instances.iter().zip(instance_masks).enumerate().skip(first);
+        for (idx, (instance, &mask)) in placed {
+            tlas[idx] = Some(wgpu::TlasInstance::new(
+                &blas[instance.asset_mesh_index],
+                affine_to_rows(&instance.transform),
+                instance.id,
+                mask,
+            ));
         }
     }
 
+    /// Returns the number of instances the TLAS has room for.
+    pub fn instance_capacity(&self) -> usize {
+        self.options.max_instances
+    }
+
+    /// Makes room in the TLAS for at least `additional` instances beyond the current ones.
+    ///
+    /// Like [`Vec::reserve`], the capacity at least doubles when the TLAS has to grow, so a
+    /// population growing one instance at a time only reallocates it a logarithmic number of
+    /// times. The new TLAS is built by the next render using the scene, the BLAS are kept.
+    pub fn reserve_instances(&mut self, device: &wgpu::Device, additional: usize) {
+        let required = self.instances.len() + additional;
+        if required <= self.options.max_instances {
+            return;
+        }
+        self.options.max_instances = required.max(2 * self.options.max_instances);
+        if let SceneBackend::Gpu { blas, tlas } = &mut self.backend {
+            *tlas = Self::create_tlas(device, &self.options);
+            Self::place_instances(tlas, blas, &self.instances, &self.instance_masks, 0);
+            self.tlas_dirty.store(true, Ordering::Release);
+        }
+    }
+
+    /// Adds instances to the scene and returns the indices they were placed at, after the
+    /// existing instances.
+    ///
+    /// The TLAS grows as in [`RayTraceScene::reserve_instances`] if it has no room for them.
+    /// Like transforms, the instances are in the TLAS from the next render using the scene on.
+    /// They get the mask the scene was built with, see [`RayTraceScene::set_instance_mask`].
+    ///
+    /// Fails with [`SceneError::AssetOutOfBounds`], adding none of them, if an instance refers
+    /// to an asset the scene does not have, and with [`SceneError::IdOutOfRange`] if its id does
+    /// not fit in the TLAS.
+    pub fn add_instances(
+        &mut self,
+        device: &wgpu::Device,
+        instances: &[Instance],
+    ) -> Result<Range<usize>, SceneError> {
+        Self::validate_instances(self.assets.len(), instances, self.instances.len())?;
+        self.reserve_instances(device, instances.len());
+        let first = self.instances.len();
+        self.instances.extend_from_slice(instances);
+        self.instance_masks
+            .resize(self.instances.len(), self.options.instance_mask);
+        if let SceneBackend::Gpu { blas, tlas } = &mut self.backend {
+            Self::place_instances(tlas, blas, &self.instances, &self.instance_masks, first);
+        }
+        self.tlas_dirty.store(true, Ordering::Release);
+        self.update_cpu_instances();
+        Ok(first..self.instances.len())
+    }
+
+    /// Removes the instances at the indices `idx`.
+    ///
+    /// Like [`Vec::remove`], the instances after a removed one move down to fill the gap and
+    /// keep their order, so their indices change. The TLAS keeps its capacity for instances
+    /// added later, and is rebuilt by the next render using the scene.
+    ///
+    /// Fails with [`SceneError::NoInstance`], removing none of them, if there is no instance at
+    /// one of the indices.
+    pub fn remove_instances(&mut self, idx: &[usize]) -> Result<(), SceneError> {
+        let mut idx = idx.to_vec();
+        idx.sort_unstable();
+        idx.dedup();
+        let (Some(&first), Some(&last)) = (idx.first(), idx.last()) else {
+            return Ok(());
+        };
+        if last >= self.instances.len() {
+            return Err(SceneError::NoInstance {
+                instance: last,
+                instances: self.instances.len(),
+            });
+        }
+
+        let count = self.instances.len();
+        let mut index = 0..;
+        self.instances
+            .retain(|_| idx.binary_search(&index.next().unwrap()).is_err());
+        let mut index = 0..;
+        self.instance_masks
+            .retain(|_| idx.binary_search(&index.next().unwrap()).is_err());
+        if let SceneBackend::Gpu { blas, tlas } = &mut self.backend {
+            Self::place_instances(tlas, blas, &self.instances, &self.instance_masks, first);
+            for slot in self.instances.len()..count {
+                tlas[slot] = None;
+            }
+        }
+        self.tlas_dirty.store(true, Ordering::Release);
+        self.update_cpu_instances();
+        Ok(())
+    }
+
+    /// Returns the number of instances in the scene.
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
     /// Updates the transform of instances within the scene.
     ///
-    /// This function updates the Top-Level Acceleration Structure (TLAS) to reflect
-    /// the new transforms of the specified instances.
+    /// The Top-Level Acceleration Structure (TLAS) is only marked as stale here. It is rebuilt by
+    /// the next render or query using the scene, and only if a transform actually changed, so
+    /// static scenes pay for no rebuild at all.
+    /// Scenes traced on the CPU rebuild their hierarchy over the instances right away.
     ///
     /// # Arguments
     ///
     /// * `device` - The `wgpu::Device` to use.
-    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
-    /// * `update_instance` - A list of `Instance` with their new transforms.
+    /// * `update_instance` - A list of `Instance` with their new transforms and ids.
     /// * `idx` - A list of indices corresponding to the instances to update.
     pub async fn set_transform(
         &mut self,
-        device: &wgpu::Device,
+        _device: &wgpu::Device,
         update_instance: &[Instance],
         idx: &[usize],
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         if update_instance.len() != idx.len() {
-            return Err("Instance and index length mismatch".to_string());
+            return Err(Error::InstanceMismatch {
+                instances: update_instance.len(),
+                indices: idx.len(),
+            });
+        }
+        for (instance, &i) in update_instance.iter().zip(idx) {
+            if i >= self.instances.len() {
+                return Err(Error::NoItem(i));
+            }
+            Self::validate_instances(self.assets.len(), std::slice::from_ref(instance), i)?;
+        }
+
+        for (instance, &i) in update_instance.iter().zip(idx) {
+            let current = &self.instances[i];
+            if current.asset_mesh_index == instance.asset_mesh_index
+                && current.transform == instance.transform
+                && current.id == instance.id
+            {
+                continue;
+            }
+            if let SceneBackend::Gpu { blas, tlas } = &mut self.backend {
+                tlas[i] = Some(wgpu::TlasInstance::new(
+                    &blas[instance.asset_mesh_index],
+                    affine_to_rows(&instance.transform),
+                    instance.id,
+                    self.instance_masks[i],
+                ));
+            }
+            self.instances[i] = instance.clone();
+            self.tlas_dirty.store(true, Ordering::Release);
+        }
+
+        self.update_cpu_instances();
+        Ok(())
+    }
+
+    /// Replaces the vertices of the asset at `asset_idx`, e.g. of terrain deforming under a
+    /// vehicle, keeping its triangles.
+    ///
+    /// Only the BLAS of that asset is rebuilt, from the new vertices alone. wgpu has no BLAS
+    /// refits yet, so this is a full build of the one BLAS, but the other assets and the
+    /// instances stay as they are. Every instance of the asset takes the new shape, and like
+    /// transforms, the TLAS is rebuilt over them by the next render using the scene. Scenes
+    /// traced on the CPU rebuild the hierarchy of the asset right away. Assets updated every
+    /// frame are best built with [`wgpu::AccelerationStructureFlags::PREFER_FAST_BUILD`], see
+    /// [`scene_builder::RayTraceSceneBuilder::with_build_flags`].
+    ///
+    /// Fails with [`SceneError::NoAsset`] if the scene has no asset at `asset_idx`, and with
+    /// [`SceneError::VertexCountMismatch`] unless there are as many vertices as before, as the
+    /// triangles index them. The asset keeps its old geometry in both cases.
+    pub async fn update_asset_geometry(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        asset_idx: usize,
+        vertices: &[Vertex],
+    ) -> Result<(), SceneError> {
+        let assets = self.assets.len();
+        let asset = self.assets.get_mut(asset_idx).ok_or(SceneError::NoAsset {
+            asset: asset_idx,
+            assets,
+        })?;
+        if vertices.len() != asset.vertex_buf.len() {
+            return Err(SceneError::VertexCountMismatch {
+                asset: asset_idx,
+                expected: asset.vertex_buf.len(),
+                vertices: vertices.len(),
+            });
+        }
+        asset.vertex_buf.copy_from_slice(vertices);
+        let asset = &self.assets[asset_idx];
+
+        match &mut self.backend {
+            SceneBackend::Gpu { blas, .. } => {
+                device.push_error_scope(wgpu::ErrorFilter::Validation);
+                let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Asset Vertex Buffer"),
+                    contents: bytemuck::cast_slice(&asset.vertex_buf),
+                    usage: wgpu::BufferUsages::BLAS_INPUT,
+                });
+                let index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Asset Index Buffer"),
+                    contents: bytemuck::cast_slice(&asset.index_buf),
+                    usage: wgpu::BufferUsages::BLAS_INPUT,
+                });
+                let size = Self::blas_geometry_size(asset);
+                let entry = wgpu::BlasBuildEntry {
+                    blas: &blas[asset_idx],
+                    geometry: wgpu::BlasGeometries::TriangleGeometries(vec![
+                        wgpu::BlasTriangleGeometry {
+                            size: &size,
+                            vertex_buffer: &vertex_buf,
+                            first_vertex: 0,
+                            vertex_stride: std::mem::size_of::<Vertex>() as u64,
+                            index_buffer: Some(&index_buf),
+                            first_index: Some(0),
+                            transform_buffer: None,
+                            transform_buffer_offset: None,
+                        },
+                    ]),
+                };
+                let mut encoder =
+                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                encoder.build_acceleration_structures(iter::once(&entry), iter::empty());
+                queue.submit(Some(encoder.finish()));
+                if let Some(error) = device.pop_error_scope().await {
+                    return Err(SceneError::Gpu(error));
+                }
+                // A TLAS built before one of its BLAS is rejected, so it is rebuilt even though
+                // no instance moved.
+                self.tlas_dirty.store(true, Ordering::Release);
+            }
+            SceneBackend::Cpu(scene) => {
+                scene.set_mesh(asset_idx, asset);
+                scene.set_instances(&self.instances, &self.instance_masks);
+            }
         }
+        Ok(())
+    }
+
+    /// Returns the mask of the instance at `idx`, see [`RayTraceScene::set_instance_mask`].
+    pub fn instance_mask(&self, idx: usize) -> Option<u8> {
+        self.instance_masks.get(idx).copied()
+    }
 
-        for (i, instance) in update_instance.iter().enumerate() {
-            self.tlas_package[idx[i]] = Some(wgpu::TlasInstance::new(
-                &self.blas[instance.asset_mesh_index],
+    /// Sets the mask of the instance at `idx`.
+    ///
+    /// Rays only hit instances whose mask shares a bit with the cull mask of the sensor tracing
+    /// them, `0xff` by default, see [`lidar::Lidar::set_cull_mask`] and
+    /// [`depth_camera::DepthCamera::set_cull_mask`]. Putting the robot carrying the sensors in a
+    /// mask of its own, e.g. `0x02`, and giving its sensors the cull mask `!0x02` keeps the robot
+    /// in the scene for other robots' sensors while its own sensors see through it.
+    ///
+    /// Fails with [`SceneError::NoInstance`] if there is no instance at `idx`. Like transforms,
+    /// the TLAS is rebuilt by the next render using the scene.
+    pub fn set_instance_mask(&mut self, idx: usize, mask: u8) -> Result<(), SceneError> {
+        let instances = self.instance_masks.len();
+        let current = self
+            .instance_masks
+            .get_mut(idx)
+            .ok_or(SceneError::NoInstance {
+                instance: idx,
+                instances,
+            })?;
+        if *current == mask {
+            return Ok(());
+        }
+        *current = mask;
+        if let SceneBackend::Gpu { blas, tlas } = &mut self.backend {
+            let instance = &self.instances[idx];
+            tlas[idx] = Some(wgpu::TlasInstance::new(
+                &blas[instance.asset_mesh_index],
                 affine_to_rows(&instance.transform),
-                0,
-                0xff,
+                instance.id,
+                mask,
             ));
         }
+        self.tlas_dirty.store(true, Ordering::Release);
+        self.update_cpu_instances();
+        Ok(())
+    }
 
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        encoder.build_acceleration_structures(iter::empty(), iter::once(&self.tlas_package));
-        // Warning: SLOW!
-        self.instances = update_instance.to_owned();
+    /// Rebuilds the hierarchy of a scene traced on the CPU if instances changed.
+    fn update_cpu_instances(&mut self) {
+        if let SceneBackend::Cpu(scene) = &mut self.backend {
+            if self.tlas_dirty.swap(false, Ordering::AcqRel) {
+                scene.set_instances(&self.instances, &self.instance_masks);
+            }
+        }
+    }
+
+    /// Returns true if the device lacks ray queries and the scene is traced on the CPU.
+    ///
+    /// Only the synchronous renders of the sensors and [`frame::FrameEncoder`] support such scenes.
+    pub fn is_cpu_fallback(&self) -> bool {
+        matches!(self.backend, SceneBackend::Cpu(_))
+    }
+
+    /// Returns the CPU hierarchies of a scene traced on the CPU.
+    pub(crate) fn cpu(&self) -> Option<&cpu::CpuScene> {
+        match &self.backend {
+            SceneBackend::Cpu(scene) => Some(scene),
+            SceneBackend::Gpu { .. } => None,
+        }
+    }
+
+    /// Returns the TLAS to bind in passes tracing the scene.
+    ///
+    /// # Panics
+    ///
+    /// If the scene is traced on the CPU.
+    pub(crate) fn tlas(&self) -> &wgpu::Tlas {
+        match &self.backend {
+            SceneBackend::Gpu { tlas, .. } => tlas,
+            SceneBackend::Cpu(_) => {
+                panic!("Tracing on the GPU requires a device supporting ray queries")
+            }
+        }
+    }
 
+    /// Uploads the scene again and rebuilds its acceleration structures on `device`.
+    ///
+    /// Every GPU resource belongs to the device it was created on, so once that device is lost,
+    /// see [`device_monitor::DeviceEvent::Lost`], the scene is recreated on a new device with
+    /// its current assets and instance transforms. Sensors have to be created again as well.
+    pub async fn recreate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(), SceneError> {
+        let mut scene = Self::with_options(
+            device,
+            queue,
+            &self.assets,
+            &self.instances,
+            self.options.clone(),
+        )
+        .await?;
+        if scene.instance_masks != self.instance_masks {
+            scene.instance_masks.clone_from(&self.instance_masks);
+            if let SceneBackend::Gpu { blas, tlas } = &mut scene.backend {
+                Self::place_instances(tlas, blas, &scene.instances, &scene.instance_masks, 0);
+            }
+            scene.tlas_dirty.store(true, Ordering::Release);
+            scene.update_cpu_instances();
+        }
+        *self = scene;
         Ok(())
     }
 
+    /// Records a rebuild of the TLAS into `encoder` if instances changed since the last one.
+    ///
+    /// Must be called before any pass tracing rays against the scene. The encoder is expected to
+    /// be submitted, otherwise the pending rebuild is lost.
+    pub(crate) fn encode_tlas_update(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.tlas_dirty.swap(false, Ordering::AcqRel) {
+            encoder.build_acceleration_structures(iter::empty(), iter::once(self.tlas()));
+        }
+    }
+
+    /// Rebuilds the TLAS right away if instances changed since it was last built, so renders
+    /// started afterwards do not record the rebuild themselves.
+    pub(crate) fn submit_tlas_update(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if !self.tlas_dirty.load(Ordering::Acquire) {
+            return;
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.encode_tlas_update(&mut encoder);
+        queue.submit(Some(encoder.finish()));
+    }
+
     /// Visualizes the scene using the `rerun` library.
     ///
     /// This function logs the scene's meshes and instances to a `rerun` recording stream
@@ -305,50 +971,565 @@ impl RayTraceScene {
     /// This method is only available when the `visualization` feature is enabled.
     #[cfg(feature = "visualization")]
     pub fn visualize(&self, rerun: &rerun::RecordingStream) {
-        // TODO
+        self.visualize_with(&mut rerun.clone());
+    }
+
+    /// Logs the scene to `visualizer`: each asset as a mesh at `mesh_{index}` and the poses of
+    /// its instances at the same path.
+    pub fn visualize_with(&self, visualizer: &mut impl visualizer::SceneVisualizer) {
         for (idx, mesh) in self.assets.iter().enumerate() {
-            let vertex: Vec<_> = mesh
+            let vertices: Vec<_> = mesh
                 .vertex_buf
                 .iter()
-                .map(|a| [a._pos[0], a._pos[1], a._pos[2]])
+                .map(|a| glam::Vec3::new(a._pos[0], a._pos[1], a._pos[2]))
                 .collect();
-            let indices: Vec<_> = mesh
+            let triangles: Vec<_> = mesh
                 .index_buf
-                .chunks(3)
+                .chunks_exact(3)
                 .map(|a| [a[0] as u32, a[1] as u32, a[2] as u32])
                 .collect();
-            rerun.log(
-                format!("mesh_{}", idx),
-                &rerun::Mesh3D::new(vertex).with_triangle_indices(indices),
-            );
+            visualizer.log_mesh(&format!("mesh_{idx}"), &vertices, &triangles);
         }
 
-        let mut instance_map = HashMap::new();
-        for (idx, instance) in self.instances.iter().enumerate() {
-            let translations = [
-                instance.transform.translation.x,
-                instance.transform.translation.y,
-                instance.transform.translation.z,
-            ];
-            let rotation = glam::Quat::from_mat3a(&instance.transform.matrix3);
-            let rotation =
-                rerun::Quaternion::from_xyzw([rotation.x, rotation.y, rotation.z, rotation.w]);
-            let Some(mesh_idx) = instance_map.get_mut(&instance.asset_mesh_index) else {
-                instance_map.insert(idx, vec![(translations, rotation)]);
-                continue;
-            };
-            mesh_idx.push((translations, rotation));
-        }
-
-        for (idx, transform) in instance_map.iter() {
-            let translations = transform.iter().map(|f| f.0);
-            let rotations = transform.iter().map(|f| f.1);
-            rerun.log(
-                format!("mesh_{}", idx),
-                &rerun::InstancePoses3D::new()
-                    .with_translations(translations)
-                    .with_quaternions(rotations),
+        let mut instance_map = BTreeMap::<usize, Vec<Affine3A>>::new();
+        for instance in &self.instances {
+            instance_map
+                .entry(instance.asset_mesh_index)
+                .or_default()
+                .push(instance.transform);
+        }
+        for (idx, poses) in &instance_map {
+            visualizer.log_poses(&format!("mesh_{idx}"), poses);
+        }
+    }
+
+    /// Visualizes the bounds of the instances, and with `blas` of the assets, using the `rerun`
+    /// library. See [`RayTraceScene::visualize_bounds_with`].
+    ///
+    /// # Note
+    ///
+    /// This method is only available when the `visualization` feature is enabled.
+    #[cfg(feature = "visualization")]
+    pub fn visualize_bounds(&self, rerun: &rerun::RecordingStream, blas: bool) {
+        self.visualize_bounds_with(&mut rerun.clone(), blas);
+    }
+
+    /// Logs the world space bounding box of every instance to `visualizer` at
+    /// `bounds/instances`, labelled with its index and asset, to spot instances with a wrong
+    /// transform or asset.
+    ///
+    /// With `blas`, also logs the bounds of the triangles each BLAS is built from, in the frame
+    /// of its asset, at `bounds/blas`.
+    pub fn visualize_bounds_with(
+        &self,
+        visualizer: &mut impl visualizer::SceneVisualizer,
+        blas: bool,
+    ) {
+        let asset_bounds: Vec<_> = self
+            .assets
+            .iter()
+            .map(|asset| {
+                cpu::bvh::Aabb::from_points(
+                    asset
+                        .index_buf
+                        .iter()
+                        .filter_map(|i| asset.vertex_buf.get(*i as usize))
+                        .map(|v| glam::Vec3::from_slice(&v._pos[..3])),
+                )
+            })
+            .collect();
+        let log = |visualizer: &mut _, path, boxes: Vec<(cpu::bvh::Aabb, String)>| {
+            let (boxes, labels): (Vec<_>, Vec<_>) = boxes
+                .into_iter()
+                .filter(|(aabb, _)| aabb.min.cmple(aabb.max).all())
+                .unzip();
+            let centers: Vec<_> = boxes.iter().map(|b| (b.min + b.max) / 2.0).collect();
+            let half_sizes: Vec<_> = boxes.iter().map(|b| (b.max - b.min) / 2.0).collect();
+            visualizer::SceneVisualizer::log_boxes(
+                visualizer,
+                path,
+                &centers,
+                &half_sizes,
+                &labels,
             );
+        };
+
+        let instances = self
+            .instances
+            .iter()
+            .enumerate()
+            .map(|(i, instance)| {
+                let aabb = asset_bounds
+                    .get(instance.asset_mesh_index)
+                    .map_or(cpu::bvh::Aabb::EMPTY, |aabb| {
+                        aabb.transformed(&instance.transform)
+                    });
+                (
+                    aabb,
+                    format!("instance_{i} (mesh_{})", instance.asset_mesh_index),
+                )
+            })
+            .collect();
+        log(visualizer, "bounds/instances", instances);
+        if blas {
+            let assets = asset_bounds
+                .iter()
+                .enumerate()
+                .map(|(i, aabb)| (*aabb, format!("mesh_{i}")))
+                .collect();
+            log(visualizer, "bounds/blas", assets);
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_from_triangles_splits_large_meshes() {
+    // A strip of 70000 vertices, more than `u16` indexes.
+    let positions: Vec<_> = (0..70000)
+        .map(|i| [i as f32, (i % 2) as f32, 0.0])
+        .collect();
+    let triangles = (0..69998).map(|i| [i, i + 1, i + 2]);
+    let assets = AssetMesh::from_triangles(&positions, triangles);
+    assert_eq!(assets.len(), 2);
+    assert_eq!(assets[0].vertex_buf.len(), 65536);
+    assert_eq!(
+        assets.iter().map(|a| a.index_buf.len()).sum::<usize>(),
+        3 * 69998
+    );
+    // Triangles keep their positions across the split.
+    let last = &assets[1];
+    let position = |i: u16| last.vertex_buf[i as usize]._pos[0];
+    assert_eq!(position(last.index_buf[last.index_buf.len() - 1]), 69999.0);
+    assert_eq!(position(last.index_buf[0]), 65534.0);
+
+    assert!(AssetMesh::from_triangles(&positions, []).is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_tlas_rebuilt_only_when_moved() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let cube = Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::IDENTITY,
+        id: 0,
+    };
+    let mut scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        std::slice::from_ref(&cube),
+    )
+    .await
+    .unwrap();
+    assert!(!scene.tlas_dirty.load(Ordering::Acquire));
+
+    scene
+        .set_transform(&device, std::slice::from_ref(&cube), &[0])
+        .await
+        .unwrap();
+    assert!(!scene.tlas_dirty.load(Ordering::Acquire));
+
+    let moved = Instance {
+        transform: Affine3A::from_translation(glam::Vec3::X),
+        ..cube
+    };
+    scene.set_transform(&device, &[moved], &[0]).await.unwrap();
+    assert!(scene.tlas_dirty.load(Ordering::Acquire));
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    scene.encode_tlas_update(&mut encoder);
+    queue.submit(Some(encoder.finish()));
+    assert!(!scene.tlas_dirty.load(Ordering::Acquire));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_reserve_instances_grows_capacity() {
+    use crate::utils::{create_cube, get_gpu};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+
+    let cube = Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::IDENTITY,
+        id: 0,
+    };
+    let mut scene = RayTraceScene::with_capacity(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        std::slice::from_ref(&cube),
+        8,
+    )
+    .await
+    .unwrap();
+    assert_eq!(scene.instance_capacity(), 8);
+
+    scene.reserve_instances(&device, 7);
+    assert_eq!(scene.instance_capacity(), 8);
+    scene.reserve_instances(&device, 8);
+    assert_eq!(scene.instance_capacity(), 16);
+    scene.reserve_instances(&device, 100);
+    assert_eq!(scene.instance_capacity(), 101);
+
+    let mut lidar = lidar::Lidar::new(&device, vec![glam::Vec3::NEG_X]).await;
+    let pose = Affine3A::from_translation(glam::Vec3::new(5.0, 0.0, 0.0));
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+
+    let scene = RayTraceScene::with_capacity(&device, &queue, &[create_cube(1.0)], &[cube], 0)
+        .await
+        .unwrap();
+    assert_eq!(scene.instance_capacity(), 1);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_sensors_see_through_masked_robot() {
+    use crate::utils::{create_cube, get_gpu};
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+
+    // A robot body around the sensor and a wall in front of it.
+    let robot = Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::from_scale(Vec3::splat(0.5)),
+        id: 0,
+    };
+    let wall = Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0)),
+        id: 0,
+    };
+    let mut scene = RayTraceScene::new(&device, &queue, &[create_cube(1.0)], &[robot, wall])
+        .await
+        .unwrap();
+    let mut lidar = lidar::Lidar::new(&device, vec![Vec3::X]).await;
+    let pose = Affine3A::IDENTITY;
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 0.5).abs() < 1e-4);
+
+    assert!(matches!(
+        scene.set_instance_mask(2, 0x02),
+        Err(SceneError::NoInstance {
+            instance: 2,
+            instances: 2
+        })
+    ));
+    scene.set_instance_mask(0, 0x02).unwrap();
+    assert_eq!(scene.instance_mask(0), Some(0x02));
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 0.5).abs() < 1e-4);
+
+    lidar.set_cull_mask(!0x02);
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+
+    // A minimum range beyond the housing hides it without masks.
+    lidar.set_cull_mask(0xff);
+    lidar.set_min_range(0.6);
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+
+    let mut camera = depth_camera::DepthCamera::new(&device, 4, 4, 10.0, 10.0).await;
+    camera.set_cull_mask(!0x02);
+    let view = glam::Mat4::look_at_rh(Vec3::ZERO, Vec3::X, Vec3::Z);
+    let depth = camera
+        .render_depth_camera(&scene, &device, &queue, view)
+        .await;
+    assert!(depth.iter().all(|d| (d - 4.0).abs() < 0.1), "{depth:?}");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_update_asset_geometry() {
+    use crate::utils::{create_cube, get_gpu};
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+
+    // A cube 5m ahead of the LiDAR, its near face at 4.5m.
+    let cube = create_cube(0.5);
+    let wall = Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0)),
+        id: 0,
+    };
+    let mut scene = RayTraceScene::new(&device, &queue, std::slice::from_ref(&cube), &[wall])
+        .await
+        .unwrap();
+    let mut lidar = lidar::Lidar::new(&device, vec![Vec3::X]).await;
+    let pose = Affine3A::IDENTITY;
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.5).abs() < 1e-4);
+
+    // Growing the cube in place brings its near face to 4m.
+    let grown: Vec<_> = cube
+        .vertex_buf
+        .iter()
+        .map(|v| vertex([v._pos[0] * 2.0, v._pos[1] * 2.0, v._pos[2] * 2.0]))
+        .collect();
+    scene
+        .update_asset_geometry(&device, &queue, 0, &grown)
+        .await
+        .unwrap();
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+
+    assert!(matches!(
+        scene
+            .update_asset_geometry(&device, &queue, 1, &grown)
+            .await,
+        Err(SceneError::NoAsset {
+            asset: 1,
+            assets: 1
+        })
+    ));
+    assert!(matches!(
+        scene
+            .update_asset_geometry(&device, &queue, 0, &grown[1..])
+            .await,
+        Err(SceneError::VertexCountMismatch {
+            asset: 0,
+            expected,
+            vertices,
+        }) if vertices + 1 == expected
+    ));
+    let beams = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_sensors_report_instance_ids() {
+    use crate::utils::{create_cube, get_gpu};
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+
+    // A cube on each side of the sensor.
+    let cube_at = |x: f32, id| Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::from_translation(Vec3::new(x, 0.0, 0.0)),
+        id,
+    };
+    let mut scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(0.5)],
+        &[cube_at(5.0, 7), cube_at(-5.0, Instance::MAX_ID)],
+    )
+    .await
+    .unwrap();
+    let mut lidar = lidar::Lidar::new(&device, vec![Vec3::X, Vec3::NEG_X, Vec3::Z]).await;
+    let pose = Affine3A::IDENTITY;
+    let ids = lidar
+        .render_lidar_instance_ids(&scene, &device, &queue, &pose)
+        .await;
+    assert_eq!(ids, [7, Instance::MAX_ID, Instance::NO_HIT]);
+
+    // Changing only the id of an instance updates it.
+    scene
+        .set_transform(&device, &[cube_at(5.0, 8)], &[0])
+        .await
+        .unwrap();
+    let ids = lidar
+        .render_lidar_instance_ids(&scene, &device, &queue, &pose)
+        .await;
+    assert_eq!(ids[0], 8);
+
+    let mut camera = depth_camera::DepthCamera::new(&device, 8, 8, 5.0, 10.0).await;
+    let view = glam::Mat4::look_at_rh(Vec3::ZERO, Vec3::X, Vec3::Z);
+    let ids = camera
+        .render_depth_camera_instance_ids(&scene, &device, &queue, view)
+        .await;
+    assert_eq!(ids, [8; 64]);
+
+    assert!(matches!(
+        scene.add_instances(&device, &[cube_at(0.0, Instance::MAX_ID + 1)]),
+        Err(SceneError::IdOutOfRange {
+            instance: 2,
+            id: 0x100_0000
+        })
+    ));
+    assert!(matches!(
+        RayTraceScene::new(
+            &device,
+            &queue,
+            &[create_cube(0.5)],
+            &[cube_at(0.0, u32::MAX)]
+        )
+        .await,
+        Err(SceneError::IdOutOfRange { instance: 0, .. })
+    ));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_add_and_remove_instances() {
+    use crate::utils::{create_cube, get_gpu};
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let cube_at = |x: f32| Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::from_translation(Vec3::new(x, 0.0, 0.0)),
+        id: 0,
+    };
+    let mut scene = RayTraceScene::new(&device, &queue, &[create_cube(1.0)], &[cube_at(10.0)])
+        .await
+        .unwrap();
+    let mut lidar = lidar::Lidar::new(&device, vec![Vec3::X]).await;
+    let mut range = async |scene: &RayTraceScene| {
+        lidar
+            .render_lidar_beams(scene, &device, &queue, &Affine3A::IDENTITY)
+            .await[0]
+    };
+    assert!((range(&scene).await - 9.0).abs() < 1e-4);
+
+    // Growing beyond the capacity reallocates the TLAS.
+    assert_eq!(
+        scene
+            .add_instances(&device, &[cube_at(4.0), cube_at(7.0)])
+            .unwrap(),
+        1..3
+    );
+    assert_eq!(scene.instance_count(), 3);
+    assert!(scene.instance_capacity() >= 3);
+    assert!((range(&scene).await - 3.0).abs() < 1e-4);
+
+    assert!(matches!(
+        scene.add_instances(
+            &device,
+            &[Instance {
+                asset_mesh_index: 1,
+                ..cube_at(0.0)
+            }]
+        ),
+        Err(SceneError::AssetOutOfBounds {
+            instance: 3,
+            asset: 1,
+            assets: 1
+        })
+    ));
+    assert!(matches!(
+        scene.remove_instances(&[0, 3]),
+        Err(SceneError::NoInstance {
+            instance: 3,
+            instances: 3
+        })
+    ));
+    assert_eq!(scene.instance_count(), 3);
+
+    // Later instances move down, the one at 7 m is now at index 1.
+    scene.remove_instances(&[1, 0, 1]).unwrap();
+    assert_eq!(scene.instance_count(), 1);
+    assert!((range(&scene).await - 6.0).abs() < 1e-4);
+    scene
+        .set_transform(&device, &[cube_at(5.0)], &[0])
+        .await
+        .unwrap();
+    assert!((range(&scene).await - 4.0).abs() < 1e-4);
+
+    scene.remove_instances(&[0]).unwrap();
+    assert_eq!(range(&scene).await, 0.0);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_invalid_scenes_are_rejected() {
+    use crate::utils::{create_cube, get_gpu};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let cube = Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::IDENTITY,
+        id: 0,
+    };
+    let new = |assets: Vec<AssetMesh>, instances: Vec<Instance>| {
+        let (device, queue) = (&device, &queue);
+        async move { RayTraceScene::new(device, queue, &assets, &instances).await }
+    };
+
+    let empty = AssetMesh {
+        vertex_buf: vec![],
+        index_buf: vec![],
+    };
+    assert!(matches!(
+        new(vec![create_cube(1.0), empty], vec![cube.clone()]).await,
+        Err(SceneError::EmptyAsset { asset: 1 })
+    ));
+    let mut partial = create_cube(1.0);
+    partial.index_buf.pop();
+    assert!(matches!(
+        new(vec![partial], vec![cube.clone()]).await,
+        Err(SceneError::PartialTriangle { asset: 0, .. })
+    ));
+    let mut dangling = create_cube(1.0);
+    dangling.index_buf[4] = 100;
+    assert!(matches!(
+        new(vec![dangling], vec![cube.clone()]).await,
+        Err(SceneError::VertexOutOfBounds { index: 100, .. })
+    ));
+    let missing = Instance {
+        asset_mesh_index: 1,
+        ..cube.clone()
+    };
+    assert!(matches!(
+        new(vec![create_cube(1.0)], vec![cube.clone(), missing.clone()]).await,
+        Err(SceneError::AssetOutOfBounds { instance: 1, .. })
+    ));
+    assert!(matches!(
+        new(vec![create_cube(1.0)], vec![]).await,
+        Err(SceneError::NoInstances)
+    ));
+
+    // A scene with room for instances may start empty.
+    let mut scene = RayTraceScene::with_capacity(&device, &queue, &[create_cube(1.0)], &[], 4)
+        .await
+        .unwrap();
+    scene
+        .add_instances(&device, std::slice::from_ref(&cube))
+        .unwrap();
+    assert!(matches!(
+        scene.set_transform(&device, &[missing], &[0]).await,
+        Err(Error::Scene(SceneError::AssetOutOfBounds {
+            instance: 0,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        scene.set_transform(&device, &[cube], &[1]).await,
+        Err(Error::NoItem(1))
+    ));
+}
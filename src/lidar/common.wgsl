@@ -0,0 +1,74 @@
+// Bindings and helpers shared by the beam and point cloud pipelines, which share a bind group
+// layout. Binding 0, the output, is declared by each pass.
+
+@group(0) @binding(1)
+var acc_struct: acceleration_structure;
+
+struct LidarBeam {
+  direction: vec3<f32>,
+  lidar_id: u32
+};
+
+@group(0) @binding(2)
+var<storage, read> lidar_beam: array<LidarBeam>;
+
+@group(0) @binding(3)
+var<uniform> lidar_position: mat4x4f;
+
+struct WorkGroupParameters {
+  width: u32,
+  height: u32,
+  depth: u32,
+  num_lidar_beams: u32,
+};
+
+@group(0) @binding(4)
+var<uniform> work_group_params: WorkGroupParameters;
+
+@group(0) @binding(5)
+var<uniform> noise_params: NoiseParameters;
+
+@group(0) @binding(6)
+var<uniform> rng_seed: RngSeed;
+
+@group(0) @binding(7)
+var<storage, read_write> render_stats: RenderStats;
+
+// Which hits the beams accept, see `Lidar::set_cull_mask` and `Lidar::set_min_range`.
+struct RayParameters {
+    cull_mask: u32,
+    t_min: f32,
+    _padding: u32,
+    _padding2: u32,
+};
+
+@group(0) @binding(8)
+var<uniform> ray_params: RayParameters;
+
+fn lidar_origin() -> vec3f {
+    return vec3f(lidar_position[0][3],
+            lidar_position[1][3],
+            lidar_position[2][3]);
+}
+
+// Rotates a beam from the sensor frame into the world frame.
+fn beam_direction(index: u32) -> vec3f {
+    let matrix = mat3x3f(lidar_position[0][0],
+                        lidar_position[0][1],
+                        lidar_position[0][2],
+                        lidar_position[1][0],
+                        lidar_position[1][1],
+                        lidar_position[1][2],
+                        lidar_position[2][0],
+                        lidar_position[2][1],
+                        lidar_position[2][2]);
+    return lidar_beam[index].direction * matrix;
+}
+
+// Returns the committed intersection of the beam starting at `origin`.
+fn trace_beam(origin: vec3f, direction: vec3f) -> RayIntersection {
+    var rq: ray_query;
+    rayQueryInitialize(&rq, acc_struct, RayDesc(0x0u, ray_params.cull_mask, ray_params.t_min, 50.0, origin, direction));
+    rayQueryProceed(&rq);
+    return rayQueryGetCommittedIntersection(&rq);
+}
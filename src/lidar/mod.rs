@@ -1,9 +1,48 @@
-use std::{borrow::Cow, iter};
+use std::sync::Arc;
 
+use bytemuck::Zeroable;
 use glam::{Affine3A, Vec3, Vec4};
+use half::f16;
 use wgpu::util::DeviceExt;
 
-use crate::{affine_to_4x4rows, RayTraceScene};
+use crate::{
+    affine_to_4x4rows, cpu,
+    hit_shader::HitShader,
+    noise::{self, NoiseModel},
+    pipeline_cache::{wgpu_cache, PipelineCache},
+    pose::IntoAffine3A,
+    render_stats::{self, GpuRenderStats, RenderStats},
+    rng::GpuRng,
+    shaders,
+    utils::{
+        bind_layout::{
+            create_compute_layout, create_pipeline_layout, ACCELERATION_STRUCTURE, STORAGE,
+            STORAGE_READ, UNIFORM,
+        },
+        buffer_pool::BufferPool,
+        half_pack::HalfPacker,
+        pop_validation_scope,
+        readback_ring::ReadbackRing,
+        supports_ray_queries,
+        uniform_belt::UniformBelt,
+    },
+    visualizer::{self, SceneVisualizer},
+    Error, RayTraceScene,
+};
+
+/// How [`Lidar::colorize_pointcloud`] colors points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointColoring {
+    /// By distance to the sensor, from 0 to `max` meters.
+    Range { max: f32 },
+    /// By the payload computed by the hit shader, e.g. an intensity, from `min` to `max`.
+    Payload { min: f32, max: f32 },
+    /// By ring, i.e. the elevation of the beam, from the lowest ring to the highest.
+    Ring,
+    /// One color per payload value, for hit shaders returning the instance index or custom
+    /// data of the hit instance.
+    InstanceId,
+}
 
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
@@ -14,14 +53,154 @@ struct WorkGroupParameters {
     num_lidar_beams: u32,
 }
 
+/// Which hits the beams accept, the `RayParameters` of `common.wgsl`.
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+struct RayParameters {
+    cull_mask: u32,
+    t_min: f32,
+    _padding: [u32; 2],
+}
+
+/// What a LiDAR render writes for each beam.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LidarOutput {
+    /// The range of the first hit.
+    Beams,
+    /// The hit point and the hit shader payload.
+    PointCloud,
+    /// The id of the instance hit, as the bits of an `f32` when traced on the CPU.
+    InstanceIds,
+}
+
+/// The compute pipelines of a LiDAR on a device supporting ray queries.
+struct LidarPipelines {
+    /// Shared by all pipelines, whose shaders declare the bindings in `common.wgsl`. The beam
+    /// and instance id shaders ignore the work group parameters.
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    pointcloud_pipeline: wgpu::ComputePipeline,
+    instance_id_pipeline: wgpu::ComputePipeline,
+}
+
+impl LidarPipelines {
+    async fn new(
+        device: &wgpu::Device,
+        hit_shader: &HitShader,
+        cache: Option<&PipelineCache>,
+    ) -> Result<Self, Error> {
+        let shader = shaders::compile(
+            device,
+            "lidar_computer",
+            &[
+                shaders::RNG.source(),
+                shaders::NOISE.source(),
+                shaders::RENDER_STATS.source(),
+                shaders::LIDAR_COMMON.source(),
+                shaders::LIDAR.source(),
+            ]
+            .join("\n"),
+        )
+        .await?;
+        let pc_shader = shaders::compile(
+            device,
+            "lidar_pointcloud",
+            &hit_shader.compose(
+                &[
+                    &shaders::RNG.source(),
+                    &shaders::NOISE.source(),
+                    &shaders::RENDER_STATS.source(),
+                    &shaders::LIDAR_COMMON.source(),
+                ],
+                &shaders::LIDAR_POINTCLOUD.source(),
+            ),
+        )
+        .await?;
+        let instance_id_shader = shaders::compile(
+            device,
+            "lidar_instance_ids",
+            &[
+                shaders::RNG.source(),
+                shaders::NOISE.source(),
+                shaders::RENDER_STATS.source(),
+                shaders::LIDAR_COMMON.source(),
+                shaders::LIDAR_INSTANCE_IDS.source(),
+            ]
+            .join("\n"),
+        )
+        .await?;
+        let bind_group_layout = create_compute_layout(
+            device,
+            "Lidar Bind Group Layout",
+            &[
+                STORAGE,
+                ACCELERATION_STRUCTURE,
+                STORAGE_READ,
+                UNIFORM,
+                UNIFORM,
+                UNIFORM,
+                UNIFORM,
+                STORAGE,
+                UNIFORM,
+            ],
+        );
+        let pipeline_layout = create_pipeline_layout(device, &bind_group_layout);
+        Ok(Self {
+            pipeline: {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("lidar"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point: Some("main"),
+                    compilation_options: Default::default(),
+                    cache: wgpu_cache(cache),
+                })
+            },
+            pointcloud_pipeline: {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("lidar"),
+                    layout: Some(&pipeline_layout),
+                    module: &pc_shader,
+                    entry_point: Some("main"),
+                    compilation_options: Default::default(),
+                    cache: wgpu_cache(cache),
+                })
+            },
+            instance_id_pipeline: {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("lidar_instance_ids"),
+                    layout: Some(&pipeline_layout),
+                    module: &instance_id_shader,
+                    entry_point: Some("main"),
+                    compilation_options: Default::default(),
+                    cache: wgpu_cache(cache),
+                })
+            },
+            bind_group_layout,
+        })
+    }
+}
+
 /// Represents a LiDAR sensor.
 ///
 /// This struct manages the compute pipelines and buffers required for simulating a LiDAR sensor.
+///
+/// Sensors are `Send + Sync`. Rendering mutates their buffers and random state, so each thread
+/// renders its own sensors.
 pub struct Lidar {
-    pipeline: wgpu::ComputePipeline,
-    pointcloud_pipeline: wgpu::ComputePipeline,
+    /// `None` on devices without ray queries.
+    pipelines: Option<LidarPipelines>,
     ray_directions: Vec<Vec4>,
     ray_direction_gpu_buf: wgpu::Buffer,
+    noise_model: Option<Box<dyn NoiseModel>>,
+    ray_filter: cpu::RayFilter,
+    rng: GpuRng,
+    buffers: Arc<BufferPool>,
+    pointcloud_frames: ReadbackRing,
+    /// Created on the first half precision render.
+    half_packer: Option<HalfPacker>,
+    #[cfg(feature = "shader-hot-reload")]
+    hot_reload: shaders::HotReload,
 }
 
 impl Lidar {
@@ -40,25 +219,199 @@ impl Lidar {
     ///
     /// This method is only available when the `visualization` feature is enabled.
     #[cfg(feature = "visualization")]
-    pub fn visualize_rays(&self, rec: &rerun::RecordingStream, lidar_pose: &Affine3A, name: &str) {
-        let (_scale, rot, translation) = lidar_pose.to_scale_rotation_translation();
-        let vectors: Vec<[f32; 3]> = self
+    pub fn visualize_rays(
+        &self,
+        rec: &rerun::RecordingStream,
+        lidar_pose: impl IntoAffine3A,
+        name: &str,
+    ) {
+        self.visualize_rays_with(&mut rec.clone(), lidar_pose, name);
+    }
+
+    /// Logs the LiDAR rays from `lidar_pose` to `visualizer` at `name`, as the counterpart of
+    /// `visualize_rays` for any [`SceneVisualizer`].
+    pub fn visualize_rays_with(
+        &self,
+        visualizer: &mut impl SceneVisualizer,
+        lidar_pose: impl IntoAffine3A,
+        name: &str,
+    ) {
+        let (_scale, rot, translation) = lidar_pose.into_affine3a().to_scale_rotation_translation();
+        let vectors: Vec<_> = self
             .ray_directions
             .iter()
-            .map(|v| (rot * Vec3::new(v.x, v.y, v.z)).to_array())
+            .map(|v| rot * Vec3::new(v.x, v.y, v.z))
             .collect();
-        let origins = vec![translation.to_array(); self.ray_directions.len()];
+        let origins = vec![translation; self.ray_directions.len()];
+        visualizer.log_rays(name, &origins, &vectors);
+    }
+
+    /// Logs a point cloud rendered from `lidar_pose` to `rerun` at `name`, in world coordinates
+    /// and colored by `coloring`. Beams without a return are left out.
+    ///
+    /// # Note
+    ///
+    /// This method is only available when the `visualization` feature is enabled.
+    #[cfg(feature = "visualization")]
+    pub fn visualize_pointcloud(
+        &self,
+        rec: &rerun::RecordingStream,
+        points: &[f32],
+        lidar_pose: impl IntoAffine3A,
+        name: &str,
+        coloring: PointColoring,
+    ) {
+        let lidar_pose = lidar_pose.into_affine3a();
+        let (positions, colors) = self.colorize_pointcloud(points, coloring);
         rec.log(
             name,
-            &rerun::Arrows3D::from_vectors(vectors).with_origins(origins),
+            &rerun::Points3D::new(
+                positions
+                    .iter()
+                    .map(|p| lidar_pose.transform_point3(*p).to_array()),
+            )
+            .with_colors(
+                colors
+                    .into_iter()
+                    .map(|[r, g, b]| rerun::Color::from_rgb(r, g, b)),
+            ),
         )
         .unwrap();
     }
 
+    /// Returns the points of a point cloud rendered by this sensor that hit something, in the
+    /// sensor frame, and their colors under `coloring`.
+    pub fn colorize_pointcloud(
+        &self,
+        points: &[f32],
+        coloring: PointColoring,
+    ) -> (Vec<Vec3>, Vec<[u8; 3]>) {
+        let rings = match coloring {
+            PointColoring::Ring => Some(self.rings()),
+            _ => None,
+        };
+        points
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, p)| p[0] < Self::no_hit_const())
+            .map(|(beam, p)| {
+                let position = Vec3::new(p[0], p[1], p[2]);
+                let color = match coloring {
+                    PointColoring::Range { max } => visualizer::viridis(position.length() / max),
+                    PointColoring::Payload { min, max } => {
+                        visualizer::viridis((p[3] - min) / (max - min))
+                    }
+                    PointColoring::Ring => {
+                        let (ring, count) = rings.as_ref().expect("Computed for rings")[beam];
+                        visualizer::viridis(ring as f32 / (count - 1).max(1) as f32)
+                    }
+                    PointColoring::InstanceId => visualizer::categorical(p[3] as u32),
+                };
+                (position, color)
+            })
+            .unzip()
+    }
+
+    /// The ring of each beam, counted from the lowest elevation, and the number of rings.
+    fn rings(&self) -> Vec<(usize, usize)> {
+        // Elevations closer than this belong to the same ring.
+        const RESOLUTION: f32 = 1e-3;
+        let elevation = |d: &Vec4| (d.z / d.truncate().length()).asin();
+        let mut elevations: Vec<_> = self.ray_directions.iter().map(elevation).collect();
+        elevations.sort_by(f32::total_cmp);
+        elevations.dedup_by(|a, b| *a - *b < RESOLUTION);
+        self.ray_directions
+            .iter()
+            .map(|d| {
+                let ring = elevations.partition_point(|e| *e <= elevation(d)) - 1;
+                (ring, elevations.len())
+            })
+            .collect()
+    }
+
     /// Returns the constant value used to indicate a "no hit" from the LiDAR sensor.
     pub fn no_hit_const() -> f32 {
         10000.0
     }
+
+    /// Attaches a noise model to the sensor. It is applied to every subsequent render.
+    pub fn set_noise_model(&mut self, model: impl NoiseModel + 'static) {
+        self.noise_model = Some(Box::new(model));
+    }
+
+    /// Removes any attached noise model.
+    pub fn clear_noise_model(&mut self) {
+        self.noise_model = None;
+    }
+
+    /// Swaps the noise model, e.g. to render the exact geometry and restore it afterwards.
+    #[cfg(feature = "validation")]
+    pub(crate) fn replace_noise_model(
+        &mut self,
+        model: Option<Box<dyn NoiseModel>>,
+    ) -> Option<Box<dyn NoiseModel>> {
+        std::mem::replace(&mut self.noise_model, model)
+    }
+
+    /// The beam directions in the sensor frame, as uploaded to the shaders.
+    #[cfg(feature = "validation")]
+    pub(crate) fn ray_directions(&self) -> &[Vec4] {
+        &self.ray_directions
+    }
+
+    /// Sets the cull mask of the beams, `0xff` by default. Beams only hit instances whose mask
+    /// shares a bit with it, see [`RayTraceScene::set_instance_mask`], e.g. to see through the
+    /// robot the sensor is mounted on.
+    pub fn set_cull_mask(&mut self, mask: u8) {
+        self.ray_filter.cull_mask = mask;
+    }
+
+    /// Returns the cull mask of the beams, see [`Lidar::set_cull_mask`].
+    pub fn cull_mask(&self) -> u8 {
+        self.ray_filter.cull_mask
+    }
+
+    /// Sets the range within which the beams return nothing, 0.1 m by default.
+    ///
+    /// Beams start at this range, so a sensor housing or robot body closer than it produces no
+    /// returns, while the geometry behind it still does.
+    pub fn set_min_range(&mut self, range: f32) {
+        self.ray_filter.t_min = range;
+    }
+
+    /// Returns the range within which the beams return nothing, see [`Lidar::set_min_range`].
+    pub fn min_range(&self) -> f32 {
+        self.ray_filter.t_min
+    }
+
+    /// The cull mask and minimum range the beams are traced with.
+    pub(crate) fn ray_filter(&self) -> cpu::RayFilter {
+        self.ray_filter
+    }
+
+    /// Reseeds the sensor's random stream. Renders issued after reseeding with the same seed
+    /// produce identical noise.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng.reseed(seed);
+    }
+
+    /// Sets how many point clouds [`Lidar::submit_lidar_pointcloud`] keeps queued on the GPU.
+    /// Defaults to 2, i.e. double buffering. One makes every submission synchronous.
+    pub fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        self.pointcloud_frames
+            .set_frames_in_flight(frames_in_flight);
+    }
+
+    /// Returns how many point clouds [`Lidar::submit_lidar_pointcloud`] keeps queued on the GPU.
+    pub fn frames_in_flight(&self) -> usize {
+        self.pointcloud_frames.frames_in_flight()
+    }
+
+    /// Returns the pool the sensor's per-render buffers are taken from.
+    pub(crate) fn buffer_pool(&self) -> Arc<BufferPool> {
+        self.buffers.clone()
+    }
+
     /// Creates a new LiDAR sensor.
     ///
     /// # Arguments
@@ -66,6 +419,47 @@ impl Lidar {
     /// * `device` - The `wgpu::Device` to use for creating GPU resources.
     /// * `ray_directions` - A list of `Vec3` representing the direction of each LiDAR beam.
     pub async fn new(device: &wgpu::Device, ray_directions: Vec<Vec3>) -> Self {
+        Self::with_hit_shader(device, ray_directions, &HitShader::default())
+            .await
+            .expect("The built-in LiDAR shaders are valid")
+    }
+
+    /// Creates a new LiDAR sensor with a custom hit shader.
+    ///
+    /// The hit shader computes the fourth channel of every point returned by
+    /// [`Lidar::render_lidar_pointcloud`].
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use for creating GPU resources.
+    /// * `ray_directions` - A list of `Vec3` representing the direction of each LiDAR beam.
+    /// * `hit_shader` - The WGSL hit shader spliced into the point cloud pipeline.
+    ///
+    /// Fails with [`Error::ShaderCompilation`] if the hit shader does not compile.
+    pub async fn with_hit_shader(
+        device: &wgpu::Device,
+        ray_directions: Vec<Vec3>,
+        hit_shader: &HitShader,
+    ) -> Result<Self, Error> {
+        Self::with_pipeline_cache(device, ray_directions, hit_shader, None).await
+    }
+
+    /// Creates a new LiDAR sensor, compiling its pipelines through a pipeline cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use for creating GPU resources.
+    /// * `ray_directions` - A list of `Vec3` representing the direction of each LiDAR beam.
+    /// * `hit_shader` - The WGSL hit shader spliced into the point cloud pipeline.
+    /// * `cache` - The cache to look up and store the compiled pipelines in.
+    ///
+    /// Fails with [`Error::ShaderCompilation`] if the hit shader does not compile.
+    pub async fn with_pipeline_cache(
+        device: &wgpu::Device,
+        ray_directions: Vec<Vec3>,
+        hit_shader: &HitShader,
+        cache: Option<&PipelineCache>,
+    ) -> Result<Self, Error> {
         device.push_error_scope(wgpu::ErrorFilter::Validation);
         let ray_directions: Vec<_> = ray_directions
             .iter()
@@ -77,38 +471,59 @@ impl Lidar {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
         });
         println!("Lidar buffer size: {:?}", ray_directions.len());
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("lidar_computer"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-        });
-        let pc_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("lidar_computer"),
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.pointcloud.wgsl"))),
-        });
-        Self {
+        // Without ray queries the shaders do not compile and scenes are traced on the CPU.
+        let pipelines = if supports_ray_queries(device) {
+            Some(LidarPipelines::new(device, hit_shader, cache).await?)
+        } else {
+            None
+        };
+        pop_validation_scope(device).await?;
+        Ok(Self {
             ray_directions,
             ray_direction_gpu_buf,
-            pipeline: {
-                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                    label: Some("lidar"),
-                    layout: None,
-                    module: &shader,
-                    entry_point: Some("main"),
-                    compilation_options: Default::default(),
-                    cache: None,
-                })
-            },
-            pointcloud_pipeline: {
-                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                    label: Some("lidar"),
-                    layout: None,
-                    module: &pc_shader,
-                    entry_point: Some("main"),
-                    compilation_options: Default::default(),
-                    cache: None,
-                })
-            },
+            noise_model: None,
+            ray_filter: cpu::RayFilter::LIDAR,
+            rng: GpuRng::default(),
+            buffers: Arc::default(),
+            pointcloud_frames: ReadbackRing::new(2),
+            half_packer: None,
+            pipelines,
+            #[cfg(feature = "shader-hot-reload")]
+            hot_reload: shaders::HotReload::new(
+                &[
+                    shaders::RNG,
+                    shaders::NOISE,
+                    shaders::HIT_INFO,
+                    shaders::RENDER_STATS,
+                    shaders::LIDAR_COMMON,
+                    shaders::LIDAR,
+                    shaders::LIDAR_POINTCLOUD,
+                    shaders::LIDAR_INSTANCE_IDS,
+                ],
+                hit_shader,
+            ),
+        })
+    }
+
+    /// Rebuilds the pipelines if a shader file they were built from changed on disk since they
+    /// were built, including a hit shader loaded with [`HitShader::from_file`].
+    ///
+    /// Call it once per frame while iterating on the shaders. Returns true if the pipelines
+    /// were rebuilt. If the changed shaders fail to compile, the previous pipelines are kept and
+    /// the validation error is returned.
+    #[cfg(feature = "shader-hot-reload")]
+    pub async fn reload_shaders(&mut self, device: &wgpu::Device) -> Result<bool, Error> {
+        let Some(hit_shader) = self.hot_reload.poll() else {
+            return Ok(false);
+        };
+        if self.pipelines.is_none() {
+            return Ok(false);
         }
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = LidarPipelines::new(device, hit_shader, None).await?;
+        pop_validation_scope(device).await?;
+        self.pipelines = Some(pipelines);
+        Ok(true)
     }
 
     /// Calculate the best distribution for
@@ -176,41 +591,258 @@ impl Lidar {
     ///
     /// # Returns
     ///
-    /// A `Vec<f32>` containing the point cloud data, where each point is represented by 4 floats (x, y, z, intensity).
+    /// A `Vec<f32>` containing the point cloud data, where each point is represented by 4 floats
+    /// (x, y, z, payload). The payload is computed by the sensor's hit shader and defaults to the range.
     pub async fn render_lidar_pointcloud(
         &mut self,
         scene: &RayTraceScene,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        pose: &Affine3A,
+        pose: impl IntoAffine3A,
     ) -> Vec<f32> {
-        device.push_error_scope(wgpu::ErrorFilter::Validation);
-        let compute_bind_group_layout = self.pointcloud_pipeline.get_bind_group_layout(0);
+        let pose = &pose.into_affine3a();
+        if let Some(points) = self.trace_on_cpu(scene, pose, LidarOutput::PointCloud) {
+            return points;
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers = self.encode_lidar(
+            scene,
+            device,
+            queue,
+            &mut encoder,
+            pose,
+            LidarOutput::PointCloud,
+            false,
+        );
+        ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, buffers)
+            .unwrap()
+    }
+
+    /// Renders a LiDAR point cloud like [`Lidar::render_lidar_pointcloud`] as an array of one
+    /// row of x, y, z and payload per beam.
+    #[cfg(feature = "ndarray")]
+    pub async fn render_lidar_pointcloud_array(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: impl IntoAffine3A,
+    ) -> ndarray::Array2<f32> {
+        let pose = &pose.into_affine3a();
+        let points = self
+            .render_lidar_pointcloud(scene, device, queue, pose)
+            .await;
+        ndarray::Array2::from_shape_vec((points.len() / 4, 4), points)
+            .expect("Point clouds have 4 floats per beam")
+    }
+
+    /// Queues a LiDAR point cloud without waiting for it.
+    ///
+    /// The GPU traces up to [`Lidar::frames_in_flight`] point clouds back to back, so a sensor
+    /// publishing at a fixed rate is not throttled by the readback of every frame. Once the queue
+    /// is full, each submission waits for the oldest point cloud and returns it, laid out as in
+    /// [`Lidar::render_lidar_pointcloud`]. Use [`Lidar::flush_lidar_pointclouds`] to collect the
+    /// point clouds still queued.
+    ///
+    /// Scenes traced on the CPU are rendered right away and every submission returns its own
+    /// point cloud.
+    ///
+    /// # Arguments
+    ///
+    /// * `scene` - The `RayTraceScene` to render.
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
+    /// * `pose` - The `Affine3A` transform of the LiDAR sensor.
+    pub async fn submit_lidar_pointcloud(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: impl IntoAffine3A,
+    ) -> Option<Vec<f32>> {
+        let pose = &pose.into_affine3a();
+        if let Some(points) = self.trace_on_cpu(scene, pose, LidarOutput::PointCloud) {
+            return Some(points);
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers = self.encode_lidar(
+            scene,
+            device,
+            queue,
+            &mut encoder,
+            pose,
+            LidarOutput::PointCloud,
+            false,
+        );
+        self.pointcloud_frames
+            .submit(device, queue, &self.buffers, encoder, buffers)
+    }
+
+    /// Waits for the point clouds queued by [`Lidar::submit_lidar_pointcloud`] and returns them
+    /// oldest first.
+    pub fn flush_lidar_pointclouds(&mut self, device: &wgpu::Device) -> Vec<Vec<f32>> {
+        self.pointcloud_frames.flush(device, &self.buffers)
+    }
+
+    /// Renders a LiDAR point cloud like [`Lidar::render_lidar_pointcloud`], converted to half
+    /// precision on the GPU to halve the readback.
+    ///
+    /// Coordinates are rounded relative to their magnitude, to 3cm between 32m and 64m from the
+    /// world origin and 6cm beyond.
+    pub async fn render_lidar_pointcloud_f16(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: impl IntoAffine3A,
+    ) -> Vec<f16> {
+        let pose = &pose.into_affine3a();
+        self.render_f16(scene, device, queue, pose, LidarOutput::PointCloud)
+    }
+
+    /// Renders the LiDAR beams like [`Lidar::render_lidar_beams`], converted to half precision
+    /// on the GPU to halve the readback.
+    ///
+    /// Ranges are rounded relative to their magnitude, to 2mm below 4m, 3cm between 32m and 64m
+    /// and 6cm beyond.
+    pub async fn render_lidar_beams_f16(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: impl IntoAffine3A,
+    ) -> Vec<f16> {
+        let pose = &pose.into_affine3a();
+        self.render_f16(scene, device, queue, pose, LidarOutput::Beams)
+    }
+
+    fn render_f16(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: &Affine3A,
+        output: LidarOutput,
+    ) -> Vec<f16> {
+        if let Some(values) = self.trace_on_cpu(scene, pose, output) {
+            return values.into_iter().map(f16::from_f32).collect();
+        }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers = self.encode_lidar(scene, device, queue, &mut encoder, pose, output, false);
+        let count = (buffers[0].size() / 4) as u32;
+        let mut packed = self
+            .half_packer
+            .get_or_insert_with(|| HalfPacker::new(device))
+            .encode(
+                device,
+                queue,
+                &self.buffers,
+                &mut encoder,
+                &buffers[0],
+                count,
+            );
+        packed.extend(buffers);
+        let mut result: Vec<f16> = ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, packed)
+            .unwrap();
+        result.truncate(count as usize);
+        result
+    }
+
+    /// Traces the render on the CPU if `scene` is traced on the CPU.
+    pub(crate) fn trace_on_cpu(
+        &mut self,
+        scene: &RayTraceScene,
+        pose: &Affine3A,
+        output: LidarOutput,
+    ) -> Option<Vec<f32>> {
+        let scene = scene.cpu()?;
+        let seed = self.rng.next_seed();
+        let noise = self.noise_model.as_deref();
+        let (directions, filter) = (&self.ray_directions, self.ray_filter());
+        Some(match output {
+            LidarOutput::Beams => {
+                cpu::trace_lidar_beams(scene, directions, pose, filter, noise, seed)
+            }
+            LidarOutput::PointCloud => {
+                cpu::trace_lidar_pointcloud(scene, directions, pose, filter, noise, seed)
+            }
+            LidarOutput::InstanceIds => {
+                cpu::trace_lidar_instance_ids(scene, directions, pose, filter)
+                    .into_iter()
+                    .map(f32::from_bits)
+                    .collect()
+            }
+        })
+    }
+
+    /// Records a render into `encoder` with buffers from the sensor's pool. The first buffer
+    /// holds the output and the last the [`GpuRenderStats`], accumulated only with `stats`. All
+    /// must be kept alive until the encoder was submitted.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn encode_lidar(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        pose: &Affine3A,
+        output: LidarOutput,
+        stats: bool,
+    ) -> Vec<wgpu::Buffer> {
         let lidar_positions = affine_to_4x4rows(pose);
+        let pipelines = self
+            .pipelines
+            .as_ref()
+            .expect("Recording a LiDAR render requires a device supporting ray queries");
 
-        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(&lidar_positions),
-            usage: wgpu::BufferUsages::UNIFORM,
-        });
+        let (pipeline, work_group_params, point_size) = match output {
+            // The beam shader ignores the work group parameters, but they are part of the shared
+            // layout.
+            LidarOutput::Beams => (&pipelines.pipeline, WorkGroupParameters::zeroed(), 4),
+            LidarOutput::PointCloud => (
+                &pipelines.pointcloud_pipeline,
+                self.distribute_workgroup(self.ray_directions.len() as u32, device),
+                4 * 4,
+            ),
+            LidarOutput::InstanceIds => (
+                &pipelines.instance_id_pipeline,
+                WorkGroupParameters::zeroed(),
+                4,
+            ),
+        };
 
-        let work_group_params = self.distribute_workgroup(self.ray_directions.len() as u32, device);
-        let work_group_params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Work Group Parameters Buffer"),
-            contents: bytemuck::cast_slice(&[work_group_params]),
-            usage: wgpu::BufferUsages::UNIFORM,
+        let mut uniforms = UniformBelt::new(device);
+        let pose_slot = uniforms.push(&lidar_positions);
+        let work_group_slot = uniforms.push(&work_group_params);
+        let noise_slot = uniforms.push(&noise::parameters_of(self.noise_model.as_deref()));
+        let rng_slot = uniforms.push(&self.rng.next_seed());
+        let ray_slot = uniforms.push(&RayParameters {
+            cull_mask: self.ray_filter.cull_mask.into(),
+            t_min: self.ray_filter.t_min,
+            _padding: [0; 2],
         });
+        let uniform_buf = uniforms.finish(device, Some(queue), &self.buffers);
 
-        let raw_buf = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (self.ray_directions.len() * 4 * 4) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
+        let raw_buf = self.buffers.take(
+            device,
+            (self.ray_directions.len() * point_size) as u64,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let stats_buf = self.buffers.take_init(
+            device,
+            queue,
+            bytemuck::bytes_of(&GpuRenderStats::new(stats)),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
 
         let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            layout: &compute_bind_group_layout,
+            layout: &pipelines.bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -218,7 +850,7 @@ impl Lidar {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::AccelerationStructure(&scene.tlas_package),
+                    resource: wgpu::BindingResource::AccelerationStructure(scene.tlas()),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
@@ -226,54 +858,43 @@ impl Lidar {
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: uniform_buf.as_entire_binding(),
+                    resource: pose_slot.binding(&uniform_buf),
                 },
                 wgpu::BindGroupEntry {
                     binding: 4,
-                    resource: work_group_params_buf.as_entire_binding(),
+                    resource: work_group_slot.binding(&uniform_buf),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: noise_slot.binding(&uniform_buf),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: rng_slot.binding(&uniform_buf),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: stats_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: ray_slot.binding(&uniform_buf),
                 },
             ],
         });
 
-        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: raw_buf.size(),
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
-        encoder.build_acceleration_structures(iter::empty(), iter::once(&scene.tlas_package));
+        scene.encode_tlas_update(encoder);
 
         {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: None,
                 timestamp_writes: None,
             });
-            cpass.set_pipeline(&self.pointcloud_pipeline);
+            cpass.set_pipeline(pipeline);
             cpass.set_bind_group(0, Some(&compute_bind_group), &[]);
             cpass.dispatch_workgroups(self.ray_directions.len() as u32, 1, 1);
         }
-        encoder.copy_buffer_to_buffer(&raw_buf, 0, &staging_buffer, 0, staging_buffer.size());
-
-        queue.submit(Some(encoder.finish()));
-        let buffer_slice = staging_buffer.slice(..);
-        let (sender, receiver) = flume::bounded(1);
-        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-
-        device.poll(wgpu::PollType::wait()).unwrap();
-
-        receiver.recv().unwrap().unwrap();
-
-        {
-            let view = buffer_slice.get_mapped_range();
-            let result: Vec<f32> = bytemuck::cast_slice(&view).to_vec();
-
-            drop(view);
-            staging_buffer.unmap();
-            result
-        }
+        vec![raw_buf, uniform_buf, stats_buf]
     }
 
     /// Renders the LiDAR beams and returns the hit distances.
@@ -289,92 +910,123 @@ impl Lidar {
     ///
     /// # Returns
     ///
-    /// A `Vec<f32>` containing the hit distance for each LiDAR beam.
+    /// A `Vec<f32>` containing the hit distance for each LiDAR beam, zero for beams without a
+    /// return.
     pub async fn render_lidar_beams(
         &mut self,
         scene: &RayTraceScene,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        pose: &Affine3A,
+        pose: impl IntoAffine3A,
     ) -> Vec<f32> {
-        device.push_error_scope(wgpu::ErrorFilter::Validation);
-        let compute_bind_group_layout = self.pipeline.get_bind_group_layout(0);
-        let lidar_positions = affine_to_4x4rows(pose);
-
-        let uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(&lidar_positions),
-            usage: wgpu::BufferUsages::UNIFORM,
-        });
-
-        let raw_buf = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (self.ray_directions.len() * 4) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
-
-        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &compute_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: raw_buf.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::AccelerationStructure(&scene.tlas_package),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: self.ray_direction_gpu_buf.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: uniform_buf.as_entire_binding(),
-                },
-            ],
-        });
-
-        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: raw_buf.size(),
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let pose = &pose.into_affine3a();
+        if let Some(ranges) = self.trace_on_cpu(scene, pose, LidarOutput::Beams) {
+            return ranges;
+        }
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers = self.encode_lidar(
+            scene,
+            device,
+            queue,
+            &mut encoder,
+            pose,
+            LidarOutput::Beams,
+            false,
+        );
+        ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, buffers)
+            .unwrap()
+    }
 
-        encoder.build_acceleration_structures(iter::empty(), iter::once(&scene.tlas_package));
-
-        {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
-            cpass.set_pipeline(&self.pipeline);
-            cpass.set_bind_group(0, Some(&compute_bind_group), &[]);
-            cpass.dispatch_workgroups(self.ray_directions.len() as u32, 1, 1);
+    /// Renders the id of the instance each beam hits, see [`crate::Instance::id`], so the
+    /// points of [`Lidar::render_lidar_pointcloud`] can be segmented by object.
+    ///
+    /// Beams without a hit read [`crate::Instance::NO_HIT`]. The ids are traced without noise,
+    /// so a beam the noise model dropped from a point cloud still has the id of what it hit.
+    pub async fn render_lidar_instance_ids(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: impl IntoAffine3A,
+    ) -> Vec<u32> {
+        let pose = &pose.into_affine3a();
+        if let Some(ids) = self.trace_on_cpu(scene, pose, LidarOutput::InstanceIds) {
+            return ids.into_iter().map(f32::to_bits).collect();
         }
-        encoder.copy_buffer_to_buffer(&raw_buf, 0, &staging_buffer, 0, staging_buffer.size());
-
-        queue.submit(Some(encoder.finish()));
-        let buffer_slice = staging_buffer.slice(..);
-        let (sender, receiver) = flume::bounded(1);
-        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-
-        device.poll(wgpu::PollType::wait()).unwrap();
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let buffers = self.encode_lidar(
+            scene,
+            device,
+            queue,
+            &mut encoder,
+            pose,
+            LidarOutput::InstanceIds,
+            false,
+        );
+        ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, buffers)
+            .unwrap()
+    }
 
-        receiver.recv().unwrap().unwrap();
+    /// Renders the LiDAR beams like [`Lidar::render_lidar_beams`] and returns the
+    /// [`RenderStats`] of the render, computed on the GPU, alongside the ranges.
+    pub async fn render_lidar_beams_with_stats(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: impl IntoAffine3A,
+    ) -> (Vec<f32>, RenderStats) {
+        let pose = &pose.into_affine3a();
+        self.render_with_stats(scene, device, queue, pose, LidarOutput::Beams)
+    }
 
-        {
-            let view = buffer_slice.get_mapped_range();
-            let result: Vec<f32> = bytemuck::cast_slice(&view).to_vec();
+    /// Renders a LiDAR point cloud like [`Lidar::render_lidar_pointcloud`] and returns the
+    /// [`RenderStats`] of the render, computed on the GPU, alongside the points.
+    pub async fn render_lidar_pointcloud_with_stats(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: impl IntoAffine3A,
+    ) -> (Vec<f32>, RenderStats) {
+        let pose = &pose.into_affine3a();
+        self.render_with_stats(scene, device, queue, pose, LidarOutput::PointCloud)
+    }
 
-            drop(view);
-            staging_buffer.unmap();
-            result
+    fn render_with_stats(
+        &mut self,
+        scene: &RayTraceScene,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pose: &Affine3A,
+        output: LidarOutput,
+    ) -> (Vec<f32>, RenderStats) {
+        if let Some(values) = self.trace_on_cpu(scene, pose, output) {
+            let stats = match output {
+                LidarOutput::Beams => {
+                    RenderStats::from_ranges(values.iter().map(|&r| (r > 0.0).then_some(r)))
+                }
+                LidarOutput::PointCloud => RenderStats::from_ranges(
+                    values
+                        .chunks_exact(4)
+                        .map(|p| (p[0] != Self::no_hit_const()).then_some(p[3])),
+                ),
+                LidarOutput::InstanceIds => unreachable!("Instance ids have no statistics"),
+            };
+            return (values, stats);
         }
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut buffers = self.encode_lidar(scene, device, queue, &mut encoder, pose, output, true);
+        let stats_buf = buffers.pop().expect("The statistics are recorded last");
+        let values = ReadbackRing::new(1)
+            .submit(device, queue, &self.buffers, encoder, buffers)
+            .unwrap();
+        let stats = render_stats::read_back(device, queue, &self.buffers, stats_buf);
+        (values, stats)
     }
 }
@@ -0,0 +1,15 @@
+@group(0) @binding(0)
+var<storage, read_write> instance_ids: array<u32>;
+
+// Writes the id of the instance each beam hits, see `Instance::id`. The ids do not depend on
+// noise, so the noise and seed are ignored.
+@compute @workgroup_size(1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let intersection = trace_beam(lidar_origin(), beam_direction(global_id.x));
+    if (intersection.kind != RAY_QUERY_INTERSECTION_NONE) {
+      instance_ids[global_id.x] = intersection.instance_custom_data;
+    } else {
+      // The output is pooled, so misses are written too. Matches `Instance::NO_HIT`.
+      instance_ids[global_id.x] = 0xffffffffu;
+    }
+}
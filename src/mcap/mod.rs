@@ -0,0 +1,345 @@
+//! Recording of sensor outputs into MCAP files.
+//!
+//! [`McapWriter`] records the messages of the [`ros2`](crate::ros2) module on named topics,
+//! stamped with their header time, in the `ros2` profile of MCAP: CDR encoded messages with
+//! their `ros2msg` schemas. Foxglove Studio and `ros2 bag play` replay the files as they would
+//! a ROS 2 recording.
+//!
+//! ```no_run
+//! # fn run(cloud: wgpu_rt_lidar::ros2::PointCloud2) -> std::io::Result<()> {
+//! use wgpu_rt_lidar::{mcap::McapWriter, ros2::{Header, PoseStamped, Time}};
+//!
+//! let mut recording = McapWriter::create("run.mcap")?;
+//! recording.write("/lidar/points", &cloud)?;
+//! let header = Header::new(Time::from_secs_f64(0.1), "map");
+//! recording.write("/lidar/pose", &PoseStamped::from_affine(header, &glam::Affine3A::IDENTITY))?;
+//! recording.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Files are written without chunks or an index, which readers rebuild by scanning the file.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::ros2::{CameraInfo, Header, Image, LaserScan, PointCloud2, PoseStamped};
+
+const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+/// Record opcodes.
+const HEADER: u8 = 0x01;
+const FOOTER: u8 = 0x02;
+const SCHEMA: u8 = 0x03;
+const CHANNEL: u8 = 0x04;
+const MESSAGE: u8 = 0x05;
+const DATA_END: u8 = 0x0F;
+
+/// A message that can be recorded, with its ROS 2 type.
+pub trait McapMessage {
+    /// The full name of the type, e.g. `sensor_msgs/msg/PointCloud2`.
+    const SCHEMA_NAME: &'static str;
+
+    /// The `.msg` definition of the type, followed by those of the types it uses.
+    fn schema() -> String;
+
+    fn header(&self) -> &Header;
+
+    /// The message serialized in CDR.
+    fn encode(&self) -> Vec<u8>;
+}
+
+/// Writes messages into an MCAP file.
+pub struct McapWriter<W: Write> {
+    writer: W,
+    /// Ids of the schemas by name.
+    schemas: HashMap<&'static str, u16>,
+    /// Ids of the channels and their number of messages, by topic.
+    channels: HashMap<String, (u16, u32)>,
+}
+
+impl McapWriter<BufWriter<File>> {
+    /// Creates the file at `path` and starts the recording.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> McapWriter<W> {
+    /// Starts a recording into `writer`.
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        let mut header = vec![];
+        put_string(&mut header, "ros2");
+        put_string(&mut header, "wgpu_rt_lidar");
+        write_record(&mut writer, HEADER, &header)?;
+        Ok(Self {
+            writer,
+            schemas: HashMap::new(),
+            channels: HashMap::new(),
+        })
+    }
+
+    /// Records `message` on `topic` at the time of its header. Channels and schemas are
+    /// declared on the first message of a topic.
+    ///
+    /// Every message of a topic has to be of the same type.
+    pub fn write<M: McapMessage>(&mut self, topic: &str, message: &M) -> std::io::Result<()> {
+        let (channel, sequence) = self.channel::<M>(topic)?;
+        let stamp = message.header().stamp;
+        let time = stamp.sec.max(0) as u64 * 1_000_000_000 + stamp.nanosec as u64;
+        let mut record = vec![];
+        record.extend_from_slice(&channel.to_le_bytes());
+        record.extend_from_slice(&sequence.to_le_bytes());
+        record.extend_from_slice(&time.to_le_bytes()); // Log time.
+        record.extend_from_slice(&time.to_le_bytes()); // Publish time.
+        record.extend_from_slice(&message.encode());
+        write_record(&mut self.writer, MESSAGE, &record)
+    }
+
+    /// Ends the recording and returns the writer, flushed.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        // No CRC of the data section, nor a summary.
+        write_record(&mut self.writer, DATA_END, &0u32.to_le_bytes())?;
+        write_record(&mut self.writer, FOOTER, &[0; 8 + 8 + 4])?;
+        self.writer.write_all(MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// The id of the channel of `topic` and the sequence number of its next message.
+    fn channel<M: McapMessage>(&mut self, topic: &str) -> std::io::Result<(u16, u32)> {
+        if let Some((id, sequence)) = self.channels.get_mut(topic) {
+            *sequence += 1;
+            return Ok((*id, *sequence));
+        }
+        let schema = match self.schemas.get(M::SCHEMA_NAME) {
+            Some(&id) => id,
+            None => {
+                // Id 0 marks channels without a schema.
+                let id = self.schemas.len() as u16 + 1;
+                let mut record = vec![];
+                record.extend_from_slice(&id.to_le_bytes());
+                put_string(&mut record, M::SCHEMA_NAME);
+                put_string(&mut record, "ros2msg");
+                put_string(&mut record, &M::schema());
+                write_record(&mut self.writer, SCHEMA, &record)?;
+                self.schemas.insert(M::SCHEMA_NAME, id);
+                id
+            }
+        };
+        let id = self.channels.len() as u16;
+        let mut record = vec![];
+        record.extend_from_slice(&id.to_le_bytes());
+        record.extend_from_slice(&schema.to_le_bytes());
+        put_string(&mut record, topic);
+        put_string(&mut record, "cdr");
+        record.extend_from_slice(&0u32.to_le_bytes()); // No metadata.
+        write_record(&mut self.writer, CHANNEL, &record)?;
+        self.channels.insert(topic.to_string(), (id, 0));
+        Ok((id, 0))
+    }
+}
+
+fn write_record(writer: &mut impl Write, opcode: u8, content: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&[opcode])?;
+    writer.write_all(&(content.len() as u64).to_le_bytes())?;
+    writer.write_all(content)
+}
+
+/// A string prefixed with its length in bytes, as both schema data and strings are.
+fn put_string(record: &mut Vec<u8>, value: &str) {
+    record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    record.extend_from_slice(value.as_bytes());
+}
+
+const TIME_MSG: &str = "int32 sec\nuint32 nanosec\n";
+const HEADER_MSG: &str = "builtin_interfaces/Time stamp\nstring frame_id\n";
+
+/// Joins the definition of a type with those of the types it uses, as `ros2msg` schemas are.
+fn definition(main: &str, dependencies: &[(&str, &str)]) -> String {
+    let mut schema = main.to_string();
+    for (name, text) in [
+        ("std_msgs/Header", HEADER_MSG),
+        ("builtin_interfaces/Time", TIME_MSG),
+    ]
+    .iter()
+    .chain(dependencies)
+    {
+        schema.push_str(&"=".repeat(80));
+        schema.push_str(&format!("\nMSG: {name}\n{text}"));
+    }
+    schema
+}
+
+impl McapMessage for PointCloud2 {
+    const SCHEMA_NAME: &'static str = "sensor_msgs/msg/PointCloud2";
+
+    fn schema() -> String {
+        definition(
+            "std_msgs/Header header\nuint32 height\nuint32 width\nPointField[] fields\n\
+             bool is_bigendian\nuint32 point_step\nuint32 row_step\nuint8[] data\nbool is_dense\n",
+            &[(
+                "sensor_msgs/PointField",
+                "uint8 INT8=1\nuint8 UINT8=2\nuint8 INT16=3\nuint8 UINT16=4\nuint8 INT32=5\n\
+                 uint8 UINT32=6\nuint8 FLOAT32=7\nuint8 FLOAT64=8\nstring name\nuint32 offset\n\
+                 uint8 datatype\nuint32 count\n",
+            )],
+        )
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_cdr()
+    }
+}
+
+impl McapMessage for LaserScan {
+    const SCHEMA_NAME: &'static str = "sensor_msgs/msg/LaserScan";
+
+    fn schema() -> String {
+        definition(
+            "std_msgs/Header header\nfloat32 angle_min\nfloat32 angle_max\n\
+             float32 angle_increment\nfloat32 time_increment\nfloat32 scan_time\n\
+             float32 range_min\nfloat32 range_max\nfloat32[] ranges\nfloat32[] intensities\n",
+            &[],
+        )
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_cdr()
+    }
+}
+
+impl McapMessage for Image {
+    const SCHEMA_NAME: &'static str = "sensor_msgs/msg/Image";
+
+    fn schema() -> String {
+        definition(
+            "std_msgs/Header header\nuint32 height\nuint32 width\nstring encoding\n\
+             uint8 is_bigendian\nuint32 step\nuint8[] data\n",
+            &[],
+        )
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_cdr()
+    }
+}
+
+impl McapMessage for CameraInfo {
+    const SCHEMA_NAME: &'static str = "sensor_msgs/msg/CameraInfo";
+
+    fn schema() -> String {
+        definition(
+            "std_msgs/Header header\nuint32 height\nuint32 width\nstring distortion_model\n\
+             float64[] d\nfloat64[9] k\nfloat64[9] r\nfloat64[12] p\nuint32 binning_x\n\
+             uint32 binning_y\nRegionOfInterest roi\n",
+            &[(
+                "sensor_msgs/RegionOfInterest",
+                "uint32 x_offset\nuint32 y_offset\nuint32 height\nuint32 width\nbool do_rectify\n",
+            )],
+        )
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_cdr()
+    }
+}
+
+impl McapMessage for PoseStamped {
+    const SCHEMA_NAME: &'static str = "geometry_msgs/msg/PoseStamped";
+
+    fn schema() -> String {
+        definition(
+            "std_msgs/Header header\nPose pose\n",
+            &[
+                (
+                    "geometry_msgs/Pose",
+                    "Point position\nQuaternion orientation\n",
+                ),
+                ("geometry_msgs/Point", "float64 x\nfloat64 y\nfloat64 z\n"),
+                (
+                    "geometry_msgs/Quaternion",
+                    "float64 x 0\nfloat64 y 0\nfloat64 z 0\nfloat64 w 1\n",
+                ),
+            ],
+        )
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        self.to_cdr()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_mcap_layout() {
+    use crate::ros2::Time;
+    use glam::Affine3A;
+
+    /// Splits the records after the leading magic, as opcodes and contents.
+    fn records(mut data: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut records = vec![];
+        while data.len() > MAGIC.len() {
+            let length = u64::from_le_bytes(data[1..9].try_into().unwrap()) as usize;
+            records.push((data[0], &data[9..9 + length]));
+            data = &data[9 + length..];
+        }
+        records
+    }
+
+    let pose = |secs| {
+        PoseStamped::from_affine(
+            Header::new(Time::from_secs_f64(secs), "map"),
+            &Affine3A::IDENTITY,
+        )
+    };
+    let mut recording = McapWriter::new(vec![]).unwrap();
+    recording.write("/a", &pose(1.5)).unwrap();
+    recording.write("/b", &pose(2.0)).unwrap();
+    recording.write("/a", &pose(2.5)).unwrap();
+    let file = recording.finish().unwrap();
+    assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+
+    let records = records(&file[MAGIC.len()..]);
+    let opcodes: Vec<_> = records.iter().map(|(opcode, _)| *opcode).collect();
+    // Both topics share the schema.
+    assert_eq!(
+        opcodes,
+        [HEADER, SCHEMA, CHANNEL, MESSAGE, CHANNEL, MESSAGE, MESSAGE, DATA_END, FOOTER]
+    );
+    let schema = records[1].1;
+    assert_eq!(&schema[..2], &1u16.to_le_bytes());
+    assert!(String::from_utf8_lossy(schema).contains("MSG: geometry_msgs/Quaternion"));
+
+    // Channel 0, its second message, logged at 2.5 s.
+    let message = records[6].1;
+    assert_eq!(&message[..2], &0u16.to_le_bytes());
+    assert_eq!(&message[2..6], &1u32.to_le_bytes());
+    assert_eq!(&message[6..14], &2_500_000_000u64.to_le_bytes());
+    assert_eq!(&message[22..], &pose(2.5).to_cdr()[..]);
+}
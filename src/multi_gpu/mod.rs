@@ -0,0 +1,149 @@
+//! Spreading scenes and sensors over several GPUs.
+//!
+//! Scenes, sensors and their buffers belong to the device they were created on, so a simulation
+//! scaling past one GPU opens a device per adapter with [`MultiGpu::new`], builds a copy of the
+//! scene on every device that hosts sensors, and asks [`MultiGpu::assign`] where each new sensor
+//! should go.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), wgpu_rt_lidar::Error> {
+//! use wgpu_rt_lidar::{lidar::Lidar, multi_gpu::MultiGpu};
+//!
+//! let mut gpus = MultiGpu::new(&wgpu::Instance::default()).await?;
+//! let mut lidars = vec![];
+//! for _ in 0..8 {
+//!     // Weighted by the number of beams.
+//!     let gpu = gpus.assign(128 * 1024);
+//!     let device = &gpus.gpu(gpu).device;
+//!     lidars.push((gpu, Lidar::new(device, vec![glam::Vec3::X; 128 * 1024]).await));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use wgpu::{Adapter, AdapterInfo, Device, Queue};
+
+use crate::utils::{enumerate_raytracing_adapters, request_raytracing_device};
+use crate::Error;
+
+/// A device opened on one adapter.
+#[derive(Debug)]
+pub struct Gpu {
+    pub adapter: Adapter,
+    pub device: Device,
+    pub queue: Queue,
+}
+
+/// A set of GPUs and the work assigned to each of them.
+#[derive(Debug)]
+pub struct MultiGpu {
+    gpus: Vec<Gpu>,
+    load: Vec<u64>,
+    failures: Vec<(AdapterInfo, Error)>,
+}
+
+impl MultiGpu {
+    /// Opens a device on every adapter supporting ray queries.
+    ///
+    /// Adapters that fail to open a device are skipped, see [`MultiGpu::failures`]. Fails with
+    /// the error of the first adapter if no device could be opened.
+    pub async fn new(instance: &wgpu::Instance) -> Result<Self, Error> {
+        let mut gpus = vec![];
+        let mut failures = vec![];
+        for adapter in enumerate_raytracing_adapters(instance) {
+            match request_raytracing_device(&adapter).await {
+                Ok((device, queue)) => gpus.push(Gpu {
+                    adapter,
+                    device,
+                    queue,
+                }),
+                Err(e) => failures.push((adapter.get_info(), e)),
+            }
+        }
+        if gpus.is_empty() {
+            return Err(match failures.into_iter().next() {
+                Some((_, e)) => e,
+                None => Error::Unsupported("No GPU supporting ray queries found"),
+            });
+        }
+        let mut gpus = Self::from_gpus(gpus);
+        gpus.failures = failures;
+        Ok(gpus)
+    }
+
+    /// Manages devices opened by the caller.
+    pub fn from_gpus(gpus: Vec<Gpu>) -> Self {
+        let load = vec![0; gpus.len()];
+        Self {
+            gpus,
+            load,
+            failures: vec![],
+        }
+    }
+
+    /// Returns the number of GPUs.
+    pub fn len(&self) -> usize {
+        self.gpus.len()
+    }
+
+    /// Returns true if there are no GPUs.
+    pub fn is_empty(&self) -> bool {
+        self.gpus.is_empty()
+    }
+
+    /// Returns all GPUs, indexed like the values returned by [`MultiGpu::assign`].
+    pub fn gpus(&self) -> &[Gpu] {
+        &self.gpus
+    }
+
+    /// Returns the adapters skipped by [`MultiGpu::new`] and why they failed to open a device.
+    pub fn failures(&self) -> &[(AdapterInfo, Error)] {
+        &self.failures
+    }
+
+    /// Returns the GPU at `index`.
+    pub fn gpu(&self, index: usize) -> &Gpu {
+        &self.gpus[index]
+    }
+
+    /// Assigns work of the given `cost`, e.g. rays per frame, to the least loaded GPU and returns
+    /// its index.
+    pub fn assign(&mut self, cost: u64) -> usize {
+        let index = least_loaded(&self.load);
+        self.load[index] += cost;
+        index
+    }
+
+    /// Releases work previously assigned to the GPU at `index`.
+    pub fn release(&mut self, index: usize, cost: u64) {
+        self.load[index] = self.load[index].saturating_sub(cost);
+    }
+
+    /// Returns the total cost assigned to each GPU.
+    pub fn load(&self) -> &[u64] {
+        &self.load
+    }
+}
+
+/// Index of the smallest load, the first one on ties.
+fn least_loaded(load: &[u64]) -> usize {
+    load.iter()
+        .enumerate()
+        .min_by_key(|(_, load)| **load)
+        .map(|(i, _)| i)
+        .expect("No GPUs to assign work to")
+}
+
+#[cfg(test)]
+#[test]
+fn test_least_loaded() {
+    let mut load = vec![0u64; 3];
+    let mut assigned = vec![];
+    for cost in [10, 10, 10, 5, 20, 1] {
+        let i = least_loaded(&load);
+        load[i] += cost;
+        assigned.push(i);
+    }
+    assert_eq!(assigned, [0, 1, 2, 0, 1, 2]);
+    assert_eq!(load, [15, 30, 11]);
+}
@@ -0,0 +1,187 @@
+//! Sensor noise models.
+//!
+//! A [`NoiseModel`] describes how a clean range measurement is perturbed before it is handed back
+//! to the user. Every model reduces to a [`NoiseParameters`] block with a fixed `#[repr(C)]` layout,
+//! so the same Gaussian/dropout/bias models can be attached to any sensor in the crate. Sensors
+//! upload the block next to a [`crate::rng::RngSeed`] and evaluate it in their shaders through
+//! [`NOISE_WGSL`].
+
+use bytemuck_derive::{Pod, Zeroable};
+use rand::{Rng, RngCore};
+
+/// WGSL source of `apply_noise`. Must be preceded by [`crate::rng::RNG_WGSL`].
+pub const NOISE_WGSL: &str = include_str!("noise.wgsl");
+
+/// GPU-side parameter block shared by all noise models.
+///
+/// The layout is 16 bytes so it can be bound directly as a uniform buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoiseParameters {
+    /// Standard deviation of the zero-mean Gaussian noise added to each range, in meters.
+    pub stddev: f32,
+    /// Probability in `[0, 1]` that a return is dropped and reported as a miss.
+    pub dropout_probability: f32,
+    /// Constant offset added to every range, in meters.
+    pub bias: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _padding: f32,
+}
+
+impl NoiseParameters {
+    /// Creates a new parameter block.
+    pub fn new(stddev: f32, dropout_probability: f32, bias: f32) -> Self {
+        Self {
+            stddev,
+            dropout_probability: dropout_probability.clamp(0.0, 1.0),
+            bias,
+            _padding: 0.0,
+        }
+    }
+
+    /// Returns true if the parameters leave measurements untouched.
+    pub fn is_identity(&self) -> bool {
+        self.stddev == 0.0 && self.dropout_probability == 0.0 && self.bias == 0.0
+    }
+
+    /// Combines two independent noise sources into one parameter block.
+    ///
+    /// Biases add, standard deviations add in quadrature and dropouts compound.
+    pub fn combine(&self, other: &NoiseParameters) -> NoiseParameters {
+        NoiseParameters::new(
+            (self.stddev * self.stddev + other.stddev * other.stddev).sqrt(),
+            1.0 - (1.0 - self.dropout_probability) * (1.0 - other.dropout_probability),
+            self.bias + other.bias,
+        )
+    }
+}
+
+/// A noise model that can be attached to a sensor.
+///
+/// Implementors only need to provide [`NoiseModel::parameters`]; the default [`NoiseModel::apply`]
+/// evaluates the parameter block so that CPU and GPU paths agree.
+pub trait NoiseModel: Send + Sync {
+    /// Returns the parameter block describing this model.
+    fn parameters(&self) -> NoiseParameters;
+
+    /// Applies the model to a single range measurement on the CPU.
+    ///
+    /// This mirrors `apply_noise` in [`NOISE_WGSL`]. Returns `None` if the return was dropped.
+    fn apply(&self, range: f32, rng: &mut dyn RngCore) -> Option<f32> {
+        let params = self.parameters();
+        if params.dropout_probability > 0.0 && rng.random::<f32>() < params.dropout_probability {
+            return None;
+        }
+        let mut range = range + params.bias;
+        if params.stddev > 0.0 {
+            // Box-Muller transform
+            let u1: f32 = rng.random::<f32>().max(f32::MIN_POSITIVE);
+            let u2: f32 = rng.random();
+            let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos();
+            range += z * params.stddev;
+        }
+        Some(range.max(0.0))
+    }
+}
+
+/// Zero-mean Gaussian range noise.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GaussianNoise {
+    /// Standard deviation in meters.
+    pub stddev: f32,
+}
+
+impl NoiseModel for GaussianNoise {
+    fn parameters(&self) -> NoiseParameters {
+        NoiseParameters::new(self.stddev, 0.0, 0.0)
+    }
+}
+
+/// Randomly drops returns with a fixed probability.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DropoutNoise {
+    /// Probability in `[0, 1]` that a return is dropped.
+    pub probability: f32,
+}
+
+impl NoiseModel for DropoutNoise {
+    fn parameters(&self) -> NoiseParameters {
+        NoiseParameters::new(0.0, self.probability, 0.0)
+    }
+}
+
+/// Adds a constant range bias.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BiasNoise {
+    /// Offset in meters.
+    pub bias: f32,
+}
+
+impl NoiseModel for BiasNoise {
+    fn parameters(&self) -> NoiseParameters {
+        NoiseParameters::new(0.0, 0.0, self.bias)
+    }
+}
+
+/// Stacks several independent noise models into one.
+#[derive(Default)]
+pub struct CompositeNoise {
+    models: Vec<Box<dyn NoiseModel>>,
+}
+
+impl CompositeNoise {
+    /// Creates an empty composite model.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds another model to the stack.
+    pub fn with(mut self, model: impl NoiseModel + 'static) -> Self {
+        self.models.push(Box::new(model));
+        self
+    }
+}
+
+impl NoiseModel for CompositeNoise {
+    fn parameters(&self) -> NoiseParameters {
+        self.models
+            .iter()
+            .fold(NoiseParameters::default(), |acc, model| {
+                acc.combine(&model.parameters())
+            })
+    }
+}
+
+/// Returns the parameter block of an optional model. Sensors without a model use the identity.
+pub(crate) fn parameters_of(model: Option<&dyn NoiseModel>) -> NoiseParameters {
+    model.map(|m| m.parameters()).unwrap_or_default()
+}
+
+#[cfg(test)]
+#[test]
+fn test_composite_noise_parameters() {
+    let model = CompositeNoise::new()
+        .with(GaussianNoise { stddev: 0.03 })
+        .with(GaussianNoise { stddev: 0.04 })
+        .with(DropoutNoise { probability: 0.5 })
+        .with(DropoutNoise { probability: 0.5 })
+        .with(BiasNoise { bias: 0.1 });
+    let params = model.parameters();
+    assert!((params.stddev - 0.05).abs() < 1e-6);
+    assert!((params.dropout_probability - 0.75).abs() < 1e-6);
+    assert!((params.bias - 0.1).abs() < 1e-6);
+    assert!(CompositeNoise::new().parameters().is_identity());
+}
+
+#[cfg(test)]
+#[test]
+fn test_apply_noise() {
+    let mut rng = rand::rng();
+    assert_eq!(BiasNoise { bias: 0.5 }.apply(1.0, &mut rng), Some(1.5));
+    assert_eq!(DropoutNoise { probability: 1.0 }.apply(1.0, &mut rng), None);
+    assert_eq!(BiasNoise { bias: -2.0 }.apply(1.0, &mut rng), Some(0.0));
+}
@@ -0,0 +1,20 @@
+// Evaluates a `NoiseParameters` block on the GPU. Requires the rng module.
+
+struct NoiseParameters {
+    stddev: f32,
+    dropout_probability: f32,
+    bias: f32,
+    _padding: f32,
+};
+
+/// Returns the noisy range, or a negative value if the return was dropped.
+fn apply_noise(range: f32, params: NoiseParameters, state: ptr<function, u32>) -> f32 {
+    if params.dropout_probability > 0.0 && rng_next_f32(state) < params.dropout_probability {
+        return -1.0;
+    }
+    var noisy = range + params.bias;
+    if params.stddev > 0.0 {
+        noisy += rng_next_gaussian(state) * params.stddev;
+    }
+    return max(noisy, 0.0);
+}
@@ -0,0 +1,135 @@
+//! Persistent pipeline caches.
+//!
+//! Compiling the ray query pipelines of a sensor can take seconds on some drivers. A
+//! [`PipelineCache`] passed to the `with_pipeline_cache` constructors of the sensors lets the
+//! backend reuse the compiled pipelines, and [`PipelineCache::save`] persists them so the next
+//! start up only takes milliseconds. Caching requires [`wgpu::Features::PIPELINE_CACHE`], which
+//! [`crate::utils::get_raytracing_gpu`] enables whenever the adapter supports it (currently
+//! Vulkan only).
+
+use std::path::{Path, PathBuf};
+
+/// A pipeline cache, optionally backed by a file.
+#[derive(Debug)]
+pub struct PipelineCache {
+    cache: wgpu::PipelineCache,
+    path: Option<PathBuf>,
+}
+
+impl PipelineCache {
+    /// Creates an empty in-memory cache, or `None` if the device does not support pipeline
+    /// caching.
+    pub fn new(device: &wgpu::Device) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        // SAFETY: No data is provided.
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Pipeline Cache"),
+                data: None,
+                fallback: true,
+            })
+        };
+        Some(Self { cache, path: None })
+    }
+
+    /// Opens the cache of `adapter` in `directory`, starting empty if no cache was saved yet.
+    ///
+    /// Every adapter gets its own file, named after [`wgpu::util::pipeline_cache_key`], so one
+    /// directory can be shared by several GPUs. Returns `None` if the device does not support
+    /// pipeline caching.
+    pub fn load(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        directory: impl AsRef<Path>,
+    ) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        let path = directory
+            .as_ref()
+            .join(wgpu::util::pipeline_cache_key(&adapter.get_info())?);
+        // A missing or unreadable file just means starting from scratch.
+        let data = std::fs::read(&path).ok();
+        // SAFETY: The file is keyed on the adapter and only ever written by `save` from
+        // `get_data`. Stale data from a driver update is discarded thanks to `fallback`.
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Pipeline Cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+        Some(Self {
+            cache,
+            path: Some(path),
+        })
+    }
+
+    /// Returns the underlying `wgpu` cache, to be set on pipeline descriptors.
+    pub fn cache(&self) -> &wgpu::PipelineCache {
+        &self.cache
+    }
+
+    /// Returns the file the cache is saved to, if it was opened with [`PipelineCache::load`].
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the serialized cache, or `None` if the backend has nothing to save.
+    pub fn data(&self) -> Option<Vec<u8>> {
+        self.cache.get_data()
+    }
+
+    /// Writes the cache back to the file it was loaded from. Does nothing for in-memory caches.
+    ///
+    /// The file is replaced atomically, so a crash while saving never leaves a truncated cache.
+    pub fn save(&self) -> std::io::Result<()> {
+        let (Some(path), Some(data)) = (&self.path, self.data()) else {
+            return Ok(());
+        };
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let temp = path.with_extension("temp");
+        std::fs::write(&temp, data)?;
+        std::fs::rename(temp, path)
+    }
+}
+
+/// Returns the `wgpu` cache of an optional [`PipelineCache`].
+pub(crate) fn wgpu_cache(cache: Option<&PipelineCache>) -> Option<&wgpu::PipelineCache> {
+    cache.map(PipelineCache::cache)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_pipeline_cache_roundtrip() {
+    use crate::{hit_shader::HitShader, lidar::Lidar, utils::get_raytracing_gpu};
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (adapter, device, _queue) = get_raytracing_gpu(&instance).await;
+    let directory = tempfile::tempdir().unwrap();
+
+    let Some(cache) = PipelineCache::load(&adapter, &device, directory.path()) else {
+        // Pipeline caching is not available on this backend.
+        return;
+    };
+    assert!(cache.path().unwrap().starts_with(directory.path()));
+    let _lidar = Lidar::with_pipeline_cache(
+        &device,
+        vec![Vec3::X, Vec3::Y],
+        &HitShader::default(),
+        Some(&cache),
+    )
+    .await
+    .unwrap();
+    cache.save().unwrap();
+    if cache.data().is_some() {
+        let reloaded = PipelineCache::load(&adapter, &device, directory.path()).unwrap();
+        assert_eq!(reloaded.path(), cache.path());
+        assert!(std::fs::metadata(cache.path().unwrap()).unwrap().len() > 0);
+    }
+}
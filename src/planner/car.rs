@@ -0,0 +1,614 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use glam::{Affine3A, Quat, Vec3};
+
+use crate::{
+    planner::{collision::check_segments, trajectory::sweep_segments, RobotShape},
+    RayTraceScene,
+};
+
+/// Tolerance of the Reeds-Shepp word conditions, in units of the turning radius.
+const WORD_TOLERANCE: f32 = 1e-5;
+
+/// Kinematics of a car-like robot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CarModel {
+    /// Drives forward only.
+    Dubins,
+    /// Drives forward and in reverse.
+    ReedsShepp,
+}
+
+/// A pose of a car-like robot driving in a horizontal plane.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CarPose {
+    pub position: Vec3,
+    /// Heading around the z axis in radians, 0 facing x.
+    pub yaw: f32,
+}
+
+impl CarPose {
+    pub fn new(position: Vec3, yaw: f32) -> Self {
+        Self { position, yaw }
+    }
+
+    /// The transform from the body frame of the robot to the world.
+    pub fn transform(&self) -> Affine3A {
+        Affine3A::from_rotation_translation(Quat::from_rotation_z(self.yaw), self.position)
+    }
+}
+
+/// The steering of a [`CarSegment`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Turn {
+    Left,
+    Straight,
+    Right,
+}
+
+impl Turn {
+    fn mirrored(self) -> Self {
+        match self {
+            Turn::Left => Turn::Right,
+            Turn::Straight => Turn::Straight,
+            Turn::Right => Turn::Left,
+        }
+    }
+}
+
+/// A piece of a [`CarPath`] driven with constant steering.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CarSegment {
+    pub turn: Turn,
+    /// Distance driven in meters, negative in reverse.
+    pub length: f32,
+}
+
+/// A shortest path of a car-like robot between two poses, made of turns at the minimum turning
+/// radius and straight lines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CarPath {
+    start: CarPose,
+    turning_radius: f32,
+    segments: Vec<CarSegment>,
+}
+
+impl CarPath {
+    /// Computes the shortest path from `from` to `to` for `model`, in the plane of `from`.
+    ///
+    /// Dubins paths are picked among the six CSC and CCC words, Reeds-Shepp paths among the 48
+    /// words of Reeds and Shepp. Returns `None` if the turning radius is not positive.
+    pub fn shortest(
+        from: CarPose,
+        to: CarPose,
+        turning_radius: f32,
+        model: CarModel,
+    ) -> Option<Self> {
+        if turning_radius <= 0.0 {
+            return None;
+        }
+        let offset = (to.position - from.position) / turning_radius;
+        let (sin, cos) = from.yaw.sin_cos();
+        let x = offset.x * cos + offset.y * sin;
+        let y = offset.y * cos - offset.x * sin;
+        let phi = to.yaw - from.yaw;
+        let words = match model {
+            CarModel::Dubins => dubins_words(x, y, phi),
+            CarModel::ReedsShepp => reeds_shepp_words(x, y, phi),
+        };
+        let word = words.into_iter().min_by(|a, b| {
+            let length = |w: &Vec<(Turn, f32)>| w.iter().map(|(_, l)| l.abs()).sum::<f32>();
+            length(a).total_cmp(&length(b))
+        })?;
+        Some(Self::from_word(from, turning_radius, &word))
+    }
+
+    fn from_word(start: CarPose, turning_radius: f32, word: &[(Turn, f32)]) -> Self {
+        Self {
+            start,
+            turning_radius,
+            segments: word
+                .iter()
+                .filter(|(_, length)| *length != 0.0)
+                .map(|(turn, length)| CarSegment {
+                    turn: *turn,
+                    length: length * turning_radius,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn start(&self) -> CarPose {
+        self.start
+    }
+
+    pub fn turning_radius(&self) -> f32 {
+        self.turning_radius
+    }
+
+    pub fn segments(&self) -> &[CarSegment] {
+        &self.segments
+    }
+
+    /// Total distance driven in meters, forward and in reverse.
+    pub fn length(&self) -> f32 {
+        self.segments.iter().map(|s| s.length.abs()).sum()
+    }
+
+    /// The pose reached after driving `distance` meters along the path, clamped to its ends.
+    pub fn pose_at(&self, distance: f32) -> CarPose {
+        let mut pose = self.start;
+        let mut remaining = distance.max(0.0);
+        for segment in &self.segments {
+            let driven = remaining.min(segment.length.abs());
+            pose = self.drive(pose, segment.turn, driven.copysign(segment.length));
+            remaining -= driven;
+            if remaining <= 0.0 {
+                break;
+            }
+        }
+        pose
+    }
+
+    pub fn end(&self) -> CarPose {
+        self.pose_at(self.length())
+    }
+
+    /// Poses along the path at most `step` meters apart, including both ends.
+    pub fn sample(&self, step: f32) -> Vec<CarPose> {
+        let length = self.length();
+        let steps = ((length / step).ceil() as usize).max(1);
+        (0..=steps)
+            .map(|i| self.pose_at(length * i as f32 / steps as f32))
+            .collect()
+    }
+
+    fn drive(&self, pose: CarPose, turn: Turn, length: f32) -> CarPose {
+        let r = self.turning_radius;
+        let (sin, cos) = pose.yaw.sin_cos();
+        let (offset, yaw) = match turn {
+            Turn::Straight => (Vec3::new(cos, sin, 0.0) * length, pose.yaw),
+            Turn::Left => {
+                let yaw = pose.yaw + length / r;
+                (Vec3::new(yaw.sin() - sin, cos - yaw.cos(), 0.0) * r, yaw)
+            }
+            Turn::Right => {
+                let yaw = pose.yaw - length / r;
+                (Vec3::new(sin - yaw.sin(), yaw.cos() - cos, 0.0) * r, yaw)
+            }
+        };
+        CarPose::new(pose.position + offset, yaw)
+    }
+}
+
+/// Checks car paths against the scene in a single GPU batch.
+///
+/// Every path is sampled every `max_step` meters and the poses are checked as a trajectory of
+/// `shape`, as in [`crate::planner::check_trajectory`]. Returns one flag per path, set if the
+/// robot collides anywhere along it.
+///
+/// # Arguments
+///
+/// * `device` - The `wgpu::Device` to use.
+/// * `queue` - The `wgpu::Queue` to use for submitting commands.
+/// * `scene` - The scene to check against.
+/// * `paths` - The paths to check, e.g. candidate edges of a planner.
+/// * `shape` - The volume of the robot in its body frame.
+/// * `max_step` - Maximum distance travelled by any probe point between two checked states.
+pub async fn check_car_paths(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    paths: &[CarPath],
+    shape: &RobotShape,
+    max_step: f32,
+) -> Vec<bool> {
+    let mut segments = vec![];
+    let mut owners = vec![];
+    for (i, path) in paths.iter().enumerate() {
+        let poses: Vec<Affine3A> = path
+            .sample(max_step)
+            .iter()
+            .map(CarPose::transform)
+            .collect();
+        let (swept, _) = sweep_segments(&poses, shape, max_step);
+        segments.extend(swept);
+        owners.resize(segments.len(), i);
+    }
+
+    let collisions = check_segments(device, queue, scene, &segments).await;
+    let mut result = vec![false; paths.len()];
+    for (owner, hit) in owners.into_iter().zip(collisions) {
+        result[owner] |= hit;
+    }
+    result
+}
+
+/// Wraps an angle to `[-pi, pi]`.
+fn wrap_angle(angle: f32) -> f32 {
+    let angle = angle % TAU;
+    if angle < -PI {
+        angle + TAU
+    } else if angle > PI {
+        angle - TAU
+    } else {
+        angle
+    }
+}
+
+/// The feasible Dubins words from the origin facing x to `(x, y, phi)`, with lengths in units of
+/// the turning radius.
+fn dubins_words(x: f32, y: f32, phi: f32) -> Vec<Vec<(Turn, f32)>> {
+    use Turn::{Left as L, Right as R, Straight as S};
+
+    let d = x.hypot(y);
+    let theta = if d > 0.0 { y.atan2(x) } else { 0.0 };
+    let a = (-theta).rem_euclid(TAU);
+    let b = (phi - theta).rem_euclid(TAU);
+    let (sa, ca) = a.sin_cos();
+    let (sb, cb) = b.sin_cos();
+    let cab = (a - b).cos();
+    let m = |angle: f32| angle.rem_euclid(TAU);
+
+    let mut words = vec![];
+    let p2 = 2.0 + d * d - 2.0 * cab + 2.0 * d * (sa - sb);
+    if p2 >= 0.0 {
+        let tmp = (cb - ca).atan2(d + sa - sb);
+        words.push(vec![(L, m(tmp - a)), (S, p2.sqrt()), (L, m(b - tmp))]);
+    }
+    let p2 = 2.0 + d * d - 2.0 * cab + 2.0 * d * (sb - sa);
+    if p2 >= 0.0 {
+        let tmp = (ca - cb).atan2(d - sa + sb);
+        words.push(vec![(R, m(a - tmp)), (S, p2.sqrt()), (R, m(tmp - b))]);
+    }
+    let p2 = -2.0 + d * d + 2.0 * cab + 2.0 * d * (sa + sb);
+    if p2 >= 0.0 {
+        let p = p2.sqrt();
+        let tmp = (-ca - cb).atan2(d + sa + sb) - (-2.0f32).atan2(p);
+        words.push(vec![(L, m(tmp - a)), (S, p), (R, m(tmp - b))]);
+    }
+    let p2 = -2.0 + d * d + 2.0 * cab - 2.0 * d * (sa + sb);
+    if p2 >= 0.0 {
+        let p = p2.sqrt();
+        let tmp = (ca + cb).atan2(d - sa - sb) - 2.0f32.atan2(p);
+        words.push(vec![(R, m(a - tmp)), (S, p), (L, m(b - tmp))]);
+    }
+    let tmp = (6.0 - d * d + 2.0 * cab + 2.0 * d * (sa - sb)) / 8.0;
+    if tmp.abs() <= 1.0 {
+        let p = m(TAU - tmp.acos());
+        let t = m(a - (ca - cb).atan2(d - sa + sb) + p / 2.0);
+        words.push(vec![(R, t), (L, p), (R, m(a - b - t + p))]);
+    }
+    let tmp = (6.0 - d * d + 2.0 * cab + 2.0 * d * (sb - sa)) / 8.0;
+    if tmp.abs() <= 1.0 {
+        let p = m(TAU - tmp.acos());
+        let t = m(-a - (ca - cb).atan2(d + sa - sb) + p / 2.0);
+        words.push(vec![(L, t), (R, p), (L, m(b - a - t + p))]);
+    }
+    words
+}
+
+/// The feasible Reeds-Shepp words from the origin facing x to `(x, y, phi)`, with lengths in
+/// units of the turning radius, negative in reverse.
+///
+/// Each base word is solved for the goal and its time flipped, reflected and, where it differs,
+/// backwards images, following Reeds and Shepp, "Optimal paths for a car that goes both forwards
+/// and backwards", 1990.
+fn reeds_shepp_words(x: f32, y: f32, phi: f32) -> Vec<Vec<(Turn, f32)>> {
+    use Turn::{Left as L, Right as R, Straight as S};
+
+    let mut words = vec![];
+    let mut add = |turns: &[Turn], word: &dyn Fn(f32, f32, f32) -> Option<Vec<f32>>, backwards| {
+        let mut goals = vec![(x, y, phi, false)];
+        if backwards {
+            let (sin, cos) = phi.sin_cos();
+            goals.push((x * cos + y * sin, x * sin - y * cos, phi, true));
+        }
+        for (x, y, phi, backwards) in goals {
+            for (flip, reflect) in [(false, false), (true, false), (false, true), (true, true)] {
+                let goal_x = if flip { -x } else { x };
+                let goal_y = if reflect { -y } else { y };
+                let goal_phi = if flip != reflect { -phi } else { phi };
+                let Some(lengths) = word(goal_x, goal_y, goal_phi) else {
+                    continue;
+                };
+                let mut result: Vec<(Turn, f32)> = turns
+                    .iter()
+                    .zip(lengths)
+                    .map(|(turn, length)| {
+                        (
+                            if reflect { turn.mirrored() } else { *turn },
+                            if flip { -length } else { length },
+                        )
+                    })
+                    .collect();
+                if backwards {
+                    result.reverse();
+                }
+                words.push(result);
+            }
+        }
+    };
+
+    add(
+        &[L, S, L],
+        &|x, y, phi| lp_sp_lp(x, y, phi).map(Vec::from),
+        false,
+    );
+    add(
+        &[L, S, R],
+        &|x, y, phi| lp_sp_rp(x, y, phi).map(Vec::from),
+        false,
+    );
+    add(
+        &[L, R, L],
+        &|x, y, phi| lp_rm_l(x, y, phi).map(Vec::from),
+        true,
+    );
+    add(
+        &[L, R, L, R],
+        &|x, y, phi| lp_rup_lum_rm(x, y, phi).map(|[t, u, v]| vec![t, u, -u, v]),
+        false,
+    );
+    add(
+        &[L, R, L, R],
+        &|x, y, phi| lp_rum_lum_rp(x, y, phi).map(|[t, u, v]| vec![t, u, u, v]),
+        false,
+    );
+    add(
+        &[L, R, S, L],
+        &|x, y, phi| lp_rm_sm_lm(x, y, phi).map(|[t, u, v]| vec![t, -FRAC_PI_2, u, v]),
+        true,
+    );
+    add(
+        &[L, R, S, R],
+        &|x, y, phi| lp_rm_sm_rm(x, y, phi).map(|[t, u, v]| vec![t, -FRAC_PI_2, u, v]),
+        true,
+    );
+    add(
+        &[L, R, S, L, R],
+        &|x, y, phi| {
+            lp_rm_s_lm_rp(x, y, phi).map(|[t, u, v]| vec![t, -FRAC_PI_2, u, -FRAC_PI_2, v])
+        },
+        false,
+    );
+    words
+}
+
+/// Distance and direction of `(x, y)` from the origin.
+fn polar(x: f32, y: f32) -> (f32, f32) {
+    (x.hypot(y), y.atan2(x))
+}
+
+fn tau_omega(u: f32, v: f32, xi: f32, eta: f32, phi: f32) -> (f32, f32) {
+    let delta = wrap_angle(u - v);
+    let a = u.sin() - delta.sin();
+    let b = u.cos() - delta.cos() - 1.0;
+    let t1 = (eta * a - xi * b).atan2(xi * a + eta * b);
+    let t2 = 2.0 * (delta.cos() - v.cos() - u.cos()) + 3.0;
+    let tau = if t2 < 0.0 {
+        wrap_angle(t1 + PI)
+    } else {
+        wrap_angle(t1)
+    };
+    (tau, wrap_angle(tau - u + v - phi))
+}
+
+fn lp_sp_lp(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let (u, t) = polar(x - phi.sin(), y - 1.0 + phi.cos());
+    let v = wrap_angle(phi - t);
+    (t >= -WORD_TOLERANCE && v >= -WORD_TOLERANCE).then_some([t, u, v])
+}
+
+fn lp_sp_rp(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let (u1, t1) = polar(x + phi.sin(), y - 1.0 - phi.cos());
+    let u1 = u1 * u1;
+    if u1 < 4.0 {
+        return None;
+    }
+    let u = (u1 - 4.0).sqrt();
+    let t = wrap_angle(t1 + 2.0f32.atan2(u));
+    let v = wrap_angle(t - phi);
+    (t >= -WORD_TOLERANCE && v >= -WORD_TOLERANCE).then_some([t, u, v])
+}
+
+fn lp_rm_l(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let (u1, theta) = polar(x - phi.sin(), y - 1.0 + phi.cos());
+    if u1 > 4.0 {
+        return None;
+    }
+    let u = -2.0 * (0.25 * u1).asin();
+    let t = wrap_angle(theta + 0.5 * u + PI);
+    let v = wrap_angle(phi - t + u);
+    (t >= -WORD_TOLERANCE && u <= WORD_TOLERANCE).then_some([t, u, v])
+}
+
+fn lp_rup_lum_rm(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let xi = x + phi.sin();
+    let eta = y - 1.0 - phi.cos();
+    let rho = 0.25 * (2.0 + xi.hypot(eta));
+    if rho > 1.0 {
+        return None;
+    }
+    let u = rho.acos();
+    let (t, v) = tau_omega(u, -u, xi, eta, phi);
+    (t >= -WORD_TOLERANCE && v <= WORD_TOLERANCE).then_some([t, u, v])
+}
+
+fn lp_rum_lum_rp(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let xi = x + phi.sin();
+    let eta = y - 1.0 - phi.cos();
+    let rho = (20.0 - xi * xi - eta * eta) / 16.0;
+    if !(0.0..=1.0).contains(&rho) {
+        return None;
+    }
+    let u = -rho.acos();
+    if u < -FRAC_PI_2 {
+        return None;
+    }
+    let (t, v) = tau_omega(u, u, xi, eta, phi);
+    (t >= -WORD_TOLERANCE && v >= -WORD_TOLERANCE).then_some([t, u, v])
+}
+
+fn lp_rm_sm_lm(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let (rho, theta) = polar(x - phi.sin(), y - 1.0 + phi.cos());
+    if rho < 2.0 {
+        return None;
+    }
+    let r = (rho * rho - 4.0).sqrt();
+    let u = 2.0 - r;
+    let t = wrap_angle(theta + r.atan2(-2.0));
+    let v = wrap_angle(phi - FRAC_PI_2 - t);
+    (t >= -WORD_TOLERANCE && u <= WORD_TOLERANCE && v <= WORD_TOLERANCE).then_some([t, u, v])
+}
+
+fn lp_rm_sm_rm(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let xi = x + phi.sin();
+    let eta = y - 1.0 - phi.cos();
+    let (rho, theta) = polar(-eta, xi);
+    if rho < 2.0 {
+        return None;
+    }
+    let t = theta;
+    let u = 2.0 - rho;
+    let v = wrap_angle(t + FRAC_PI_2 - phi);
+    (t >= -WORD_TOLERANCE && u <= WORD_TOLERANCE && v <= WORD_TOLERANCE).then_some([t, u, v])
+}
+
+fn lp_rm_s_lm_rp(x: f32, y: f32, phi: f32) -> Option<[f32; 3]> {
+    let xi = x + phi.sin();
+    let eta = y - 1.0 - phi.cos();
+    let (rho, _) = polar(xi, eta);
+    if rho < 2.0 {
+        return None;
+    }
+    let u = 4.0 - (rho * rho - 4.0).sqrt();
+    if u > WORD_TOLERANCE {
+        return None;
+    }
+    let t = wrap_angle(((4.0 - u) * xi - 2.0 * eta).atan2(-2.0 * xi + (u - 4.0) * eta));
+    let v = wrap_angle(t - phi);
+    (t >= -WORD_TOLERANCE && v >= -WORD_TOLERANCE).then_some([t, u, v])
+}
+
+#[cfg(test)]
+#[test]
+fn test_car_paths() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(7);
+    let radius = 0.8;
+    for _ in 0..200 {
+        let from = CarPose::new(
+            Vec3::new(
+                rng.random_range(-3.0..3.0),
+                rng.random_range(-3.0..3.0),
+                0.5,
+            ),
+            rng.random_range(-PI..PI),
+        );
+        let to = CarPose::new(
+            Vec3::new(
+                rng.random_range(-3.0..3.0),
+                rng.random_range(-3.0..3.0),
+                0.5,
+            ),
+            rng.random_range(-PI..PI),
+        );
+        let offset = (to.position - from.position) / radius;
+        let (sin, cos) = from.yaw.sin_cos();
+        let (x, y) = (
+            offset.x * cos + offset.y * sin,
+            offset.y * cos - offset.x * sin,
+        );
+        let phi = to.yaw - from.yaw;
+
+        // Every candidate word must actually reach the goal.
+        for (model, words) in [
+            (CarModel::Dubins, dubins_words(x, y, phi)),
+            (CarModel::ReedsShepp, reeds_shepp_words(x, y, phi)),
+        ] {
+            assert!(!words.is_empty());
+            for word in words {
+                let end = CarPath::from_word(from, radius, &word).end();
+                assert!(
+                    end.position.abs_diff_eq(to.position, 1e-3),
+                    "{model:?} {word:?}"
+                );
+                assert!(
+                    wrap_angle(end.yaw - to.yaw).abs() < 1e-3,
+                    "{model:?} {word:?}"
+                );
+                if model == CarModel::Dubins {
+                    assert!(word.iter().all(|(_, l)| *l >= 0.0));
+                }
+            }
+        }
+
+        let dubins = CarPath::shortest(from, to, radius, CarModel::Dubins).unwrap();
+        let reeds_shepp = CarPath::shortest(from, to, radius, CarModel::ReedsShepp).unwrap();
+        assert!(reeds_shepp.length() <= dubins.length() + 1e-4);
+        assert!(reeds_shepp.length() >= from.position.distance(to.position) - 1e-4);
+    }
+
+    // Parallel parking is short in reverse but needs a loop going forward only.
+    let from = CarPose::new(Vec3::ZERO, 0.0);
+    let to = CarPose::new(Vec3::new(-0.5, 0.0, 0.0), 0.0);
+    let reeds_shepp = CarPath::shortest(from, to, 1.0, CarModel::ReedsShepp).unwrap();
+    assert!(reeds_shepp.length() <= 0.5 + 1e-4);
+    assert!(
+        CarPath::shortest(from, to, 1.0, CarModel::Dubins)
+            .unwrap()
+            .length()
+            > 4.0
+    );
+
+    let samples = reeds_shepp.sample(0.1);
+    assert_eq!(samples.first(), Some(&from));
+    assert!(samples
+        .last()
+        .unwrap()
+        .position
+        .abs_diff_eq(to.position, 1e-5));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_check_car_paths() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A pillar in front of the robot.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_scale_rotation_translation(
+                Vec3::new(0.2, 0.2, 1.0),
+                Quat::IDENTITY,
+                Vec3::new(2.0, 0.0, 0.0),
+            ),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let shape = RobotShape::Box {
+        half_extents: Vec3::new(0.3, 0.2, 0.2),
+    };
+    let from = CarPose::new(Vec3::ZERO, 0.0);
+    let paths: Vec<CarPath> = [
+        CarPose::new(Vec3::new(4.0, 0.0, 0.0), 0.0),
+        CarPose::new(Vec3::new(0.0, 3.0, 0.0), PI),
+        CarPose::new(Vec3::new(-1.0, 0.0, 0.0), 0.0),
+    ]
+    .into_iter()
+    .map(|to| CarPath::shortest(from, to, 1.0, CarModel::ReedsShepp).unwrap())
+    .collect();
+    let result = check_car_paths(&device, &queue, &scene, &paths, &shape, 0.05).await;
+    assert_eq!(result, vec![true, false, false]);
+}
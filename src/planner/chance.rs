@@ -0,0 +1,286 @@
+use std::borrow::Cow;
+
+use glam::{Mat3, Vec3, Vec4};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{
+    rng::{GpuRng, RNG_WGSL},
+    utils::{read_buffer, MAX_WORKGROUPS_PER_DIMENSION},
+    Error, RayTraceScene,
+};
+
+/// A straight edge between two uncertain positions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UncertainEdge {
+    pub start: Vec3,
+    pub end: Vec3,
+    /// Covariance of the position error at `start`.
+    pub start_covariance: Mat3,
+    /// Covariance of the position error at `end`.
+    pub end_covariance: Mat3,
+}
+
+impl UncertainEdge {
+    /// Creates an edge whose ends share the same position covariance.
+    pub fn new(start: Vec3, end: Vec3, covariance: Mat3) -> Self {
+        Self {
+            start,
+            end,
+            start_covariance: covariance,
+            end_covariance: covariance,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct GpuUncertainEdge {
+    start: Vec3,
+    _padding0: f32,
+    end: Vec3,
+    _padding1: f32,
+    // Columns of a WGSL `mat3x3<f32>`, which are padded to 16 bytes.
+    start_factor: [Vec4; 3],
+    end_factor: [Vec4; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct ChanceParams {
+    num_edges: u32,
+    num_samples: u32,
+    _padding: [u32; 2],
+}
+
+/// Estimates the probability that each of a batch of uncertain edges collides with the scene.
+///
+/// For every edge, `num_samples` perturbed copies are drawn on the GPU and checked like
+/// [`crate::planner::check_edges`]. A single Gaussian draw displaces both ends, each scaled by the
+/// Cholesky factor of its covariance, so the error is correlated along the edge as for a robot
+/// whose pose estimate drifts. All samples of all edges are checked in a single dispatch and only
+/// the hit counts are read back.
+///
+/// Returns the fraction of colliding samples of each edge, to be compared against a chance
+/// constraint instead of a hard collision flag.
+///
+/// # Arguments
+///
+/// * `device` - The `wgpu::Device` to use.
+/// * `queue` - The `wgpu::Queue` to use for submitting commands.
+/// * `scene` - The scene to check against.
+/// * `edges` - The edges and the covariances of their end points.
+/// * `num_samples` - Number of perturbed copies of each edge.
+/// * `rng` - Drives the perturbations, so a fixed seed reproduces the same estimates.
+pub async fn collision_probabilities(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    edges: &[UncertainEdge],
+    num_samples: u32,
+    rng: &mut GpuRng,
+) -> Result<Vec<f32>, Error> {
+    if edges.is_empty() || num_samples == 0 {
+        return Ok(vec![0.0; edges.len()]);
+    }
+    let num_edges = edges.len() as u32;
+    let workgroups = num_edges
+        .checked_mul(num_samples)
+        .ok_or_else(|| Error::InvalidArgument("Too many samples".to_string()))?
+        .div_ceil(64);
+    if workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION) > MAX_WORKGROUPS_PER_DIMENSION {
+        return Err(Error::InvalidArgument("Too many samples".to_string()));
+    }
+
+    let gpu_edges = edges
+        .iter()
+        .map(|edge| {
+            let factor = |covariance: Mat3| {
+                let l = cholesky(covariance).ok_or_else(|| {
+                    Error::InvalidArgument("Covariance is not positive semi-definite".to_string())
+                })?;
+                Ok::<_, Error>([l.x_axis, l.y_axis, l.z_axis].map(|c| c.extend(0.0)))
+            };
+            Ok(GpuUncertainEdge {
+                start: edge.start,
+                _padding0: 0.0,
+                end: edge.end,
+                _padding1: 0.0,
+                start_factor: factor(edge.start_covariance)?,
+                end_factor: factor(edge.end_covariance)?,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("chance"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+            [RNG_WGSL, include_str!("chance.wgsl")].join("\n"),
+        )),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("chance_constrained_collision"),
+        layout: None,
+        module: &shader,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Chance Parameters"),
+        contents: bytemuck::cast_slice(&[ChanceParams {
+            num_edges,
+            num_samples,
+            _padding: [0; 2],
+        }]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let seed = rng.create_seed_buffer(device);
+    let edges_buf = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Uncertain Edges"),
+        contents: bytemuck::cast_slice(&gpu_edges),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let hit_counts = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Uncertain Edge Hit Counts"),
+        contents: bytemuck::cast_slice(&vec![0u32; edges.len()]),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::AccelerationStructure(scene.tlas()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: seed.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: edges_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: hit_counts.as_entire_binding(),
+            },
+        ],
+    });
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    scene.encode_tlas_update(&mut encoder);
+    {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(
+            workgroups.min(MAX_WORKGROUPS_PER_DIMENSION),
+            workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+            1,
+        );
+    }
+    queue.submit(Some(encoder.finish()));
+
+    let hit_counts: Vec<u32> = read_buffer(device, queue, &hit_counts).await;
+    Ok(hit_counts
+        .into_iter()
+        .map(|hits| hits as f32 / num_samples as f32)
+        .collect())
+}
+
+/// Returns the lower triangular `l` with `l * l^T = covariance`, or `None` if `covariance` is not
+/// positive semi-definite.
+fn cholesky(covariance: Mat3) -> Option<Mat3> {
+    let a = |row: usize, col: usize| covariance.col(col)[row];
+    let mut l = [[0.0f32; 3]; 3];
+    for row in 0..3 {
+        for col in 0..=row {
+            let sum: f32 = (0..col).map(|k| l[row][k] * l[col][k]).sum();
+            if row == col {
+                let diagonal = a(row, row) - sum;
+                if diagonal < -1e-6 {
+                    return None;
+                }
+                l[row][col] = diagonal.max(0.0).sqrt();
+            } else if l[col][col] > 0.0 {
+                l[row][col] = (a(row, col) - sum) / l[col][col];
+            }
+        }
+    }
+    Some(Mat3::from_cols_array_2d(&l).transpose())
+}
+
+#[cfg(test)]
+#[test]
+fn test_cholesky() {
+    let l = Mat3::from_cols(
+        Vec3::new(2.0, 0.5, -0.3),
+        Vec3::new(0.0, 1.0, 0.2),
+        Vec3::new(0.0, 0.0, 0.1),
+    );
+    let factor = cholesky(l * l.transpose()).unwrap();
+    assert!(factor.abs_diff_eq(l, 1e-5), "{factor:?}");
+
+    // Degenerate along y.
+    let flat = Mat3::from_diagonal(Vec3::new(0.04, 0.0, 0.01));
+    assert_eq!(
+        cholesky(flat).unwrap(),
+        Mat3::from_diagonal(Vec3::new(0.2, 0.0, 0.1))
+    );
+    assert!(cholesky(Mat3::from_diagonal(Vec3::new(1.0, -1.0, 1.0))).is_none());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_collision_probabilities() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+    use glam::Affine3A;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A box spanning [-1, 1] along each axis.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+
+    // Edges passing the box at different heights, with a standard deviation of 0.2m in y.
+    let covariance = Mat3::from_diagonal(Vec3::new(0.0, 0.04, 0.0));
+    let edge =
+        |y: f32| UncertainEdge::new(Vec3::new(-3.0, y, 0.0), Vec3::new(3.0, y, 0.0), covariance);
+    let edges = [edge(0.0), edge(1.0), edge(1.2), edge(3.0)];
+    let mut rng = GpuRng::new(42);
+    let probabilities = collision_probabilities(&device, &queue, &scene, &edges, 4096, &mut rng)
+        .await
+        .unwrap();
+    let expected = [1.0, 0.5, 0.159, 0.0];
+    for (p, expected) in probabilities.iter().zip(expected) {
+        assert!((p - expected).abs() < 0.03, "{probabilities:?}");
+    }
+
+    let invalid = UncertainEdge::new(Vec3::ZERO, Vec3::X, -Mat3::IDENTITY);
+    assert!(
+        collision_probabilities(&device, &queue, &scene, &[invalid], 16, &mut rng)
+            .await
+            .is_err()
+    );
+}
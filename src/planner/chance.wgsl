@@ -0,0 +1,71 @@
+// Monte Carlo collision probabilities of uncertain edges. Must be preceded by RNG_WGSL.
+
+struct UncertainEdge {
+    start: vec3<f32>,
+    _padding0: f32,
+    end: vec3<f32>,
+    _padding1: f32,
+    // Cholesky factors of the covariances of the end points.
+    start_factor: mat3x3<f32>,
+    end_factor: mat3x3<f32>,
+}
+
+struct ChanceParams {
+    num_edges: u32,
+    num_samples: u32,
+    _padding: vec2<u32>,
+}
+
+@group(0)
+@binding(0)
+var acc_struct: acceleration_structure;
+
+@group(0)
+@binding(1)
+var<uniform> params: ChanceParams;
+
+@group(0)
+@binding(2)
+var<uniform> rng_seed: RngSeed;
+
+@group(0)
+@binding(3)
+var<storage, read> edges: array<UncertainEdge>;
+
+/// Number of colliding samples of each edge.
+@group(0)
+@binding(4)
+var<storage, read_write> hit_counts: array<atomic<u32>>;
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    if index >= params.num_edges * params.num_samples {
+        return;
+    }
+    let edge = edges[index / params.num_samples];
+
+    // One draw perturbs the whole edge, so the error is correlated along it.
+    var state = rng_init(rng_seed, index);
+    let noise = vec3<f32>(rng_next_gaussian(&state), rng_next_gaussian(&state), rng_next_gaussian(&state));
+    let start = edge.start + edge.start_factor * noise;
+    let end = edge.end + edge.end_factor * noise;
+
+    let size = length(end - start);
+    if size == 0.0 {
+        return;
+    }
+    let direction = (end - start) / size;
+
+    var rq: ray_query;
+    rayQueryInitialize(&rq, acc_struct, RayDesc(0x0u, 0xFFu, 0.0, size, start, direction));
+    rayQueryProceed(&rq);
+    let intersection = rayQueryGetCommittedIntersection(&rq);
+    if intersection.kind != RAY_QUERY_INTERSECTION_NONE {
+        atomicAdd(&hit_counts[index / params.num_samples], 1u);
+    }
+}
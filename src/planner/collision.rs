@@ -0,0 +1,320 @@
+use glam::Vec3;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{
+    utils::{read_buffer, MAX_WORKGROUPS_PER_DIMENSION},
+    RayTraceScene,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct Segment {
+    start: Vec3,
+    _padding0: f32,
+    end: Vec3,
+    _padding1: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct SegmentParams {
+    num_segments: u32,
+    _padding: [u32; 3],
+}
+
+/// Outcome of checking one edge with [`check_edges`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EdgeResult {
+    /// The edge crosses no geometry.
+    Free,
+    /// The edge touches or crosses the geometry of the scene.
+    Blocked,
+}
+
+impl EdgeResult {
+    pub fn is_free(&self) -> bool {
+        *self == EdgeResult::Free
+    }
+}
+
+/// Checks a batch of straight edges against the scene on the GPU.
+///
+/// Edge `i` goes from `starts[i]` to `ends[i]` and the result at `i` tells whether it crosses
+/// any triangle of the scene, in either direction and including the end points. Zero length
+/// edges are always free. All edges are checked with one ray query each, in a single dispatch.
+///
+/// # Panics
+///
+/// If `starts` and `ends` differ in length.
+pub async fn check_edges(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    starts: &[Vec3],
+    ends: &[Vec3],
+) -> Vec<EdgeResult> {
+    if starts.len() != ends.len() {
+        panic!("Edge start and end counts do not match");
+    }
+    let segments: Vec<_> = starts.iter().copied().zip(ends.iter().copied()).collect();
+    check_segments(device, queue, scene, &segments)
+        .await
+        .into_iter()
+        .map(|hit| {
+            if hit {
+                EdgeResult::Blocked
+            } else {
+                EdgeResult::Free
+            }
+        })
+        .collect()
+}
+
+/// The first point where an edge meets the scene, as returned by [`cast_edges`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EdgeHit {
+    /// Where the edge first touches the geometry.
+    pub position: Vec3,
+    /// Distance from the start of the edge to `position`.
+    pub distance: f32,
+}
+
+/// Finds where each of a batch of straight edges first meets the scene, on the GPU.
+///
+/// Edge `i` goes from `starts[i]` to `ends[i]`. The result at `i` is the hit closest to its
+/// start, or `None` for edges that are free as defined by [`check_edges`]. This lets planners
+/// extend towards a sample up to the first obstacle in a single call.
+///
+/// # Panics
+///
+/// If `starts` and `ends` differ in length.
+pub async fn cast_edges(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    starts: &[Vec3],
+    ends: &[Vec3],
+) -> Vec<Option<EdgeHit>> {
+    if starts.len() != ends.len() {
+        panic!("Edge start and end counts do not match");
+    }
+    let segments: Vec<_> = starts.iter().copied().zip(ends.iter().copied()).collect();
+    cast_segments(device, queue, scene, &segments)
+        .await
+        .into_iter()
+        .zip(segments)
+        .map(|(distance, (start, end))| {
+            distance.map(|distance| EdgeHit {
+                position: start + (end - start).normalize() * distance,
+                distance,
+            })
+        })
+        .collect()
+}
+
+/// Checks a batch of straight segments against the scene in a single dispatch.
+///
+/// Returns, for each `(start, end)` pair, whether the segment crosses any geometry.
+pub(crate) async fn check_segments(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    segments: &[(Vec3, Vec3)],
+) -> Vec<bool> {
+    cast_segments(device, queue, scene, segments)
+        .await
+        .into_iter()
+        .map(|distance| distance.is_some())
+        .collect()
+}
+
+/// Returns, for each `(start, end)` pair, the distance from `start` to the first hit, if any.
+async fn cast_segments(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    segments: &[(Vec3, Vec3)],
+) -> Vec<Option<f32>> {
+    if segments.is_empty() {
+        return vec![];
+    }
+    let num_segments = segments.len() as u32;
+
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("collision.wgsl"));
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("segment_collision"),
+        layout: None,
+        module: &cs_module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+
+    let segments: Vec<Segment> = segments
+        .iter()
+        .map(|(start, end)| Segment {
+            start: *start,
+            _padding0: 0.0,
+            end: *end,
+            _padding1: 0.0,
+        })
+        .collect();
+    let segments_buf = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Collision Segments"),
+        contents: bytemuck::cast_slice(&segments),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Collision Parameters"),
+        contents: bytemuck::cast_slice(&[SegmentParams {
+            num_segments,
+            _padding: [0; 3],
+        }]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let hit_distances = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Collision Hit Distances"),
+        size: (segments.len() * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::AccelerationStructure(scene.tlas()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: segments_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: hit_distances.as_entire_binding(),
+            },
+        ],
+    });
+    let workgroups = num_segments.div_ceil(64);
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    scene.encode_tlas_update(&mut encoder);
+    {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(
+            workgroups.min(MAX_WORKGROUPS_PER_DIMENSION),
+            workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+            1,
+        );
+    }
+    queue.submit(Some(encoder.finish()));
+
+    let hit_distances: Vec<f32> = read_buffer(device, queue, &hit_distances).await;
+    hit_distances
+        .into_iter()
+        .map(|d| (d >= 0.0).then_some(d))
+        .collect()
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_check_edges() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+    use glam::Affine3A;
+    use EdgeResult::{Blocked, Free};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A box spanning [-1, 1] along each axis.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+
+    let edges = [
+        // Through the box.
+        (Vec3::new(-3.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 0.0)),
+        // The same edge reversed.
+        (Vec3::new(3.0, 0.0, 0.0), Vec3::new(-3.0, 0.0, 0.0)),
+        // Stopping short of a face.
+        (Vec3::new(-3.0, 0.0, 0.0), Vec3::new(-1.1, 0.0, 0.0)),
+        // Passing above the box.
+        (Vec3::new(-3.0, 0.0, 1.5), Vec3::new(3.0, 0.0, 1.5)),
+        // Leaving the box through a face.
+        (Vec3::ZERO, Vec3::new(0.0, 3.0, 0.0)),
+        // Degenerate.
+        (Vec3::splat(2.0), Vec3::splat(2.0)),
+    ];
+    let (starts, ends): (Vec<Vec3>, Vec<Vec3>) = edges.into_iter().unzip();
+    let results = check_edges(&device, &queue, &scene, &starts, &ends).await;
+    assert_eq!(results, vec![Blocked, Blocked, Free, Free, Blocked, Free]);
+    assert!(results[2].is_free());
+
+    assert!(check_edges(&device, &queue, &scene, &[], &[])
+        .await
+        .is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_cast_edges() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+    use glam::Affine3A;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A box spanning [-1, 1] along each axis.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+
+    let starts = [
+        Vec3::new(-3.0, 0.5, 0.0),
+        Vec3::new(0.2, 5.0, -0.3),
+        Vec3::new(-3.0, 0.0, 1.5),
+    ];
+    let ends = [
+        Vec3::new(3.0, 0.5, 0.0),
+        Vec3::new(0.2, -5.0, -0.3),
+        Vec3::new(3.0, 0.0, 1.5),
+    ];
+    let hits = cast_edges(&device, &queue, &scene, &starts, &ends).await;
+
+    let hit = hits[0].unwrap();
+    assert!((hit.distance - 2.0).abs() < 1e-4, "{hit:?}");
+    assert!(hit.position.abs_diff_eq(Vec3::new(-1.0, 0.5, 0.0), 1e-4));
+    let hit = hits[1].unwrap();
+    assert!((hit.distance - 4.0).abs() < 1e-4, "{hit:?}");
+    assert!(hit.position.abs_diff_eq(Vec3::new(0.2, 1.0, -0.3), 1e-4));
+    assert_eq!(hits[2], None);
+}
@@ -0,0 +1,59 @@
+// Batched straight segment collision checks against the scene.
+
+struct Segment {
+    start: vec3<f32>,
+    _padding0: f32,
+    end: vec3<f32>,
+    _padding1: f32,
+}
+
+struct SegmentParams {
+    num_segments: u32,
+    _padding: vec3<u32>,
+}
+
+@group(0)
+@binding(0)
+var acc_struct: acceleration_structure;
+
+@group(0)
+@binding(1)
+var<uniform> params: SegmentParams;
+
+@group(0)
+@binding(2)
+var<storage, read> segments: array<Segment>;
+
+/// Distance from the start of each segment to its first hit, or -1 if it crosses no geometry.
+@group(0)
+@binding(3)
+var<storage, read_write> hit_distances: array<f32>;
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    if index >= params.num_segments {
+        return;
+    }
+    let segment = segments[index];
+    let size = length(segment.end - segment.start);
+    if size == 0.0 {
+        hit_distances[index] = -1.0;
+        return;
+    }
+    let direction = (segment.end - segment.start) / size;
+
+    var rq: ray_query;
+    rayQueryInitialize(&rq, acc_struct, RayDesc(0x0u, 0xFFu, 0.0, size, segment.start, direction));
+    rayQueryProceed(&rq);
+    let intersection = rayQueryGetCommittedIntersection(&rq);
+    if intersection.kind == RAY_QUERY_INTERSECTION_NONE {
+        hit_distances[index] = -1.0;
+    } else {
+        hit_distances[index] = intersection.t;
+    }
+}
@@ -0,0 +1,20 @@
+//! Motion planning against a [`crate::RayTraceScene`].
+
+mod car;
+mod chance;
+mod collision;
+mod rrt;
+pub mod sampling;
+mod smoothing;
+mod steering;
+mod trajectory;
+mod wavefront;
+
+pub use car::{check_car_paths, CarModel, CarPath, CarPose, CarSegment, Turn};
+pub use chance::{collision_probabilities, UncertainEdge};
+pub use collision::{cast_edges, check_edges, EdgeHit, EdgeResult};
+pub use rrt::{RrtParams, RrtPlanner};
+pub use smoothing::{shortcut_path, ShortcutParams};
+pub use steering::Steering;
+pub use trajectory::{check_trajectory, RobotShape};
+pub use wavefront::{plan_costmap_path, plan_grid_path, GridPath};
@@ -0,0 +1,354 @@
+use glam::{UVec3, Vec3};
+
+use crate::{
+    planner::{
+        collision::check_segments,
+        sampling::{GoalBiasedSampler, Sampler, UniformSampler},
+        steering::{Steering, SteeringState},
+    },
+    rng::draw_seed,
+    utils::dense_voxel::{DenseVoxel, DenseVoxelGpuRepresentation, VoxelItem},
+    Error, RayTraceScene,
+};
+
+/// Parent of the root of an [`RrtPlanner`] tree.
+const NO_PARENT: u32 = u32::MAX;
+
+/// Parameters of [`RrtPlanner`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RrtParams {
+    /// Maximum length of a tree edge in meters. Also the edge length of the cells used to find
+    /// nearest nodes.
+    pub step_size: f32,
+    /// Probability of sampling the goal instead of a uniformly random point.
+    pub goal_bias: f32,
+    /// Number of samples extended in parallel per iteration.
+    pub batch_size: usize,
+    /// Maximum number of iterations before giving up.
+    pub max_iterations: usize,
+    /// Maximum number of tree nodes per cell. Nodes landing in a full cell are discarded.
+    pub max_density: u32,
+    /// Seed of the samplers, or `None` to draw one with [`draw_seed`].
+    pub seed: Option<u64>,
+    /// How nodes are extended towards samples.
+    pub steering: Steering,
+    /// Number of straight pieces each curved edge is split into for collision checking.
+    /// Straight edges are checked as a single piece.
+    pub edge_segments: usize,
+    /// Maximum distance between the end of a steered edge and the goal for the goal to count as
+    /// reached. Straight edges reach the goal exactly.
+    pub goal_tolerance: f32,
+}
+
+impl Default for RrtParams {
+    fn default() -> Self {
+        Self {
+            step_size: 0.5,
+            goal_bias: 0.05,
+            batch_size: 256,
+            max_iterations: 100,
+            max_density: 16,
+            seed: None,
+            steering: Steering::Straight,
+            edge_segments: 8,
+            goal_tolerance: 0.1,
+        }
+    }
+}
+
+/// A node of an [`RrtPlanner`] tree.
+struct Node {
+    state: SteeringState,
+    parent: u32,
+    /// Points along the edge from the parent, excluding both ends.
+    edge: Vec<Vec3>,
+}
+
+/// A rapidly exploring random tree planner for a point robot.
+///
+/// Each iteration draws a batch of samples and runs the whole extension step on the GPU in
+/// batches: the nearest tree nodes are found with the dense voxel nearest neighbour search, the
+/// samples are steered towards them and the new edges are checked against the scene with ray
+/// queries. Collision-free nodes are then inserted in the tree, whose GPU copy is updated in
+/// place.
+///
+/// With a kinodynamic [`Steering`], edges follow the steering function and are discretized into
+/// [`RrtParams::edge_segments`] pieces, all of which are checked in the same batch. Nodes then
+/// carry a velocity, so the returned path is dynamically feasible.
+pub struct RrtPlanner {
+    top_right: Vec3,
+    bottom_left: Vec3,
+    params: RrtParams,
+}
+
+impl RrtPlanner {
+    /// Creates a planner sampling the box between `bottom_left` and `top_right`.
+    pub fn new(top_right: Vec3, bottom_left: Vec3, params: RrtParams) -> Self {
+        Self {
+            top_right,
+            bottom_left,
+            params,
+        }
+    }
+
+    pub fn params(&self) -> &RrtParams {
+        &self.params
+    }
+
+    /// Searches a collision-free path from `start` to `goal` through `scene`.
+    ///
+    /// Returns the waypoints of the path, starting at `start` and ending at `goal`, or an error if
+    /// either lies outside the planning bounds or no path was found within
+    /// [`RrtParams::max_iterations`]. Curved edges contribute their intermediate points, and with
+    /// a kinodynamic [`Steering`] the path ends within [`RrtParams::goal_tolerance`] of `goal`
+    /// instead.
+    pub async fn plan(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &RayTraceScene,
+        start: Vec3,
+        goal: Vec3,
+    ) -> Result<Vec<Vec3>, Error> {
+        let in_bounds = |p: Vec3| p.cmpge(self.bottom_left).all() && p.cmplt(self.top_right).all();
+        if !in_bounds(start) || !in_bounds(goal) {
+            return Err(Error::OutOfBounds("Start or goal"));
+        }
+        let step_size = self.params.step_size;
+        let steering = self.params.steering;
+        let edge_segments = match steering {
+            Steering::Straight => 1,
+            _ => self.params.edge_segments,
+        };
+        let steer = |from: SteeringState, target: Vec3| {
+            steering.steer(from, target, step_size, edge_segments)
+        };
+
+        let mut tree = DenseVoxel::new(
+            self.top_right,
+            self.bottom_left,
+            step_size,
+            self.params.max_density,
+        );
+        let mut nodes = vec![Node {
+            state: SteeringState {
+                position: start,
+                velocity: Vec3::ZERO,
+            },
+            parent: NO_PARENT,
+            edge: vec![],
+        }];
+        tree.add_item(VoxelItem::with_payload(start, 0))?;
+        let tree_gpu = DenseVoxelGpuRepresentation::upload(device, &tree);
+
+        let seed = self.params.seed.unwrap_or_else(draw_seed);
+        let mut sampler = GoalBiasedSampler::new(
+            UniformSampler::new(self.top_right, self.bottom_left, seed),
+            goal,
+            self.params.goal_bias,
+            seed.wrapping_add(1),
+        );
+        let mut fresh = vec![0u32];
+        for _ in 0..self.params.max_iterations {
+            // Try to connect the nodes added last iteration to the goal.
+            let mut candidates = vec![];
+            for node in &fresh {
+                let from = nodes[*node as usize].state;
+                if from.position.distance(goal) > step_size {
+                    continue;
+                }
+                let states = steer(from, goal);
+                if states
+                    .last()
+                    .is_some_and(|s| s.position.distance(goal) <= self.params.goal_tolerance)
+                {
+                    candidates.push((*node, states));
+                }
+            }
+            let free = check_edges_free(device, queue, scene, &nodes, &candidates).await;
+            if let Some((parent, states)) = candidates
+                .into_iter()
+                .zip(free)
+                .find_map(|(candidate, free)| free.then_some(candidate))
+            {
+                nodes.push(new_node(parent, states));
+                return Ok(extract_path(&nodes, nodes.len() as u32 - 1));
+            }
+
+            let samples = sampler.sample_batch(self.params.batch_size);
+            let nearest = tree_gpu
+                .nearest_neighbour_indices(device, queue, &samples)
+                .await;
+
+            let mut candidates = vec![];
+            for (sample, nearest) in samples.iter().zip(nearest) {
+                let Some(item) = nearest.and_then(|i| tree.item(i as usize)) else {
+                    continue;
+                };
+                let parent = item.payload();
+                let from = nodes[parent as usize].state;
+                let states = steer(from, *sample);
+                let Some(end) = states.last() else {
+                    continue;
+                };
+                if end.position.distance_squared(from.position) > f32::EPSILON
+                    && in_bounds(end.position)
+                {
+                    candidates.push((parent, states));
+                }
+            }
+            let free = check_edges_free(device, queue, scene, &nodes, &candidates).await;
+
+            fresh.clear();
+            for ((parent, states), free) in candidates.into_iter().zip(free) {
+                if !free {
+                    continue;
+                }
+                let node = nodes.len() as u32;
+                let position = states.last().unwrap().position;
+                if tree
+                    .add_item(VoxelItem::with_payload(position, node))
+                    .is_err()
+                {
+                    continue;
+                }
+                let cell = ((position - self.bottom_left) / step_size).as_uvec3();
+                tree_gpu.update_region(queue, &tree, cell, cell + UVec3::ONE)?;
+                nodes.push(new_node(parent, states));
+                fresh.push(node);
+            }
+        }
+        Err(Error::NoPathFound)
+    }
+}
+
+/// Checks the steered edges leaving `nodes` in a single batch and returns whether each one is
+/// free along its whole length.
+async fn check_edges_free(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    nodes: &[Node],
+    edges: &[(u32, Vec<SteeringState>)],
+) -> Vec<bool> {
+    let mut segments = vec![];
+    let mut owners = vec![];
+    for (edge, (parent, states)) in edges.iter().enumerate() {
+        let mut from = nodes[*parent as usize].state.position;
+        for state in states {
+            segments.push((from, state.position));
+            owners.push(edge);
+            from = state.position;
+        }
+    }
+    let collisions = check_segments(device, queue, scene, &segments).await;
+    let mut free = vec![true; edges.len()];
+    for (owner, hit) in owners.into_iter().zip(collisions) {
+        free[owner] &= !hit;
+    }
+    free
+}
+
+/// Creates the node reached by the steered `states` from `parent`.
+fn new_node(parent: u32, mut states: Vec<SteeringState>) -> Node {
+    let state = states.pop().unwrap();
+    Node {
+        state,
+        parent,
+        edge: states.into_iter().map(|s| s.position).collect(),
+    }
+}
+
+/// Follows the parents of `node` back to the root and returns the positions root first,
+/// including the points along the edges.
+fn extract_path(nodes: &[Node], mut node: u32) -> Vec<Vec3> {
+    let mut path = vec![];
+    while node != NO_PARENT {
+        let Node {
+            state,
+            parent,
+            edge,
+        } = &nodes[node as usize];
+        path.push(state.position);
+        path.extend(edge.iter().rev());
+        node = *parent;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_rrt_planner() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+    use glam::{Affine3A, Quat};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A wall across the box with a gap above it.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_scale_rotation_translation(
+                Vec3::new(0.2, 2.5, 1.8),
+                Quat::IDENTITY,
+                Vec3::new(2.5, 2.5, 1.8),
+            ),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let start = Vec3::new(0.5, 2.5, 1.0);
+    let goal = Vec3::new(4.5, 2.5, 1.0);
+    let planner = RrtPlanner::new(Vec3::splat(5.0), Vec3::ZERO, RrtParams::default());
+
+    let path = planner
+        .plan(&device, &queue, &scene, start, goal)
+        .await
+        .unwrap();
+    assert_eq!(path.first(), Some(&start));
+    assert_eq!(path.last(), Some(&goal));
+    let segments: Vec<_> = path.windows(2).map(|w| (w[0], w[1])).collect();
+    for (from, to) in &segments {
+        assert!(from.distance(*to) <= planner.params().step_size + 1e-4);
+    }
+    let collisions = check_segments(&device, &queue, &scene, &segments).await;
+    assert!(collisions.iter().all(|hit| !hit));
+    // The wall forces the path over it.
+    assert!(path.iter().any(|p| p.z > 3.6));
+
+    assert!(planner
+        .plan(&device, &queue, &scene, Vec3::splat(-1.0), goal)
+        .await
+        .is_err());
+
+    // Arcs: consecutive pieces turn by at most the curvature bound.
+    let max_curvature = 1.5;
+    let planner = RrtPlanner::new(
+        Vec3::splat(5.0),
+        Vec3::ZERO,
+        RrtParams {
+            steering: Steering::ConstantVelocityArc { max_curvature },
+            max_iterations: 200,
+            ..Default::default()
+        },
+    );
+    let path = planner
+        .plan(&device, &queue, &scene, start, goal)
+        .await
+        .unwrap();
+    assert!(path.last().unwrap().distance(goal) <= planner.params().goal_tolerance);
+    let segments: Vec<_> = path.windows(2).map(|w| (w[0], w[1])).collect();
+    let collisions = check_segments(&device, &queue, &scene, &segments).await;
+    assert!(collisions.iter().all(|hit| !hit));
+    for w in path.windows(3) {
+        let (a, b) = (w[1] - w[0], w[2] - w[1]);
+        let turn = a.angle_between(b);
+        assert!(turn <= max_curvature * a.length().max(b.length()) + 1e-3);
+    }
+}
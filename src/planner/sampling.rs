@@ -0,0 +1,391 @@
+//! Samplers for sampling-based planners.
+//!
+//! The CPU samplers implement [`Sampler`] and are seeded, so a fixed seed reproduces the same
+//! sequence of samples. Large batches of uniform or informed samples can also be generated
+//! straight into a GPU buffer with [`encode_samples`].
+
+use std::borrow::Cow;
+use std::f32::consts::TAU;
+
+use glam::{Affine3A, Mat4, Quat, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{
+    planner::collision::cast_edges,
+    rng::{GpuRng, RNG_WGSL},
+    utils::MAX_WORKGROUPS_PER_DIMENSION,
+    RayTraceScene,
+};
+
+/// A source of configuration samples.
+pub trait Sampler {
+    fn sample(&mut self) -> Vec3;
+
+    fn sample_batch(&mut self, num_samples: usize) -> Vec<Vec3> {
+        (0..num_samples).map(|_| self.sample()).collect()
+    }
+}
+
+/// Samples uniformly within an axis aligned box.
+#[derive(Clone, Debug)]
+pub struct UniformSampler {
+    top_right: Vec3,
+    bottom_left: Vec3,
+    rng: StdRng,
+}
+
+impl UniformSampler {
+    pub fn new(top_right: Vec3, bottom_left: Vec3, seed: u64) -> Self {
+        Self {
+            top_right,
+            bottom_left,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for UniformSampler {
+    fn sample(&mut self) -> Vec3 {
+        let unit = Vec3::new(self.rng.random(), self.rng.random(), self.rng.random());
+        self.bottom_left + unit * (self.top_right - self.bottom_left)
+    }
+}
+
+/// Returns the goal with probability `goal_bias` and a sample of `inner` otherwise.
+#[derive(Clone, Debug)]
+pub struct GoalBiasedSampler<S> {
+    inner: S,
+    goal: Vec3,
+    goal_bias: f32,
+    rng: StdRng,
+}
+
+impl<S: Sampler> GoalBiasedSampler<S> {
+    pub fn new(inner: S, goal: Vec3, goal_bias: f32, seed: u64) -> Self {
+        Self {
+            inner,
+            goal,
+            goal_bias,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<S: Sampler> Sampler for GoalBiasedSampler<S> {
+    fn sample(&mut self) -> Vec3 {
+        if self.rng.random::<f32>() < self.goal_bias {
+            self.goal
+        } else {
+            self.inner.sample()
+        }
+    }
+}
+
+/// Samples uniformly within the prolate spheroid of points that could lie on a path from `start`
+/// to `goal` shorter than the best one found so far, as in Informed RRT*.
+#[derive(Clone, Debug)]
+pub struct InformedSampler {
+    start: Vec3,
+    goal: Vec3,
+    best_cost: f32,
+    rng: StdRng,
+}
+
+impl InformedSampler {
+    /// Creates a sampler for paths from `start` to `goal` shorter than `best_cost`. Costs below
+    /// the straight line distance are raised to it.
+    pub fn new(start: Vec3, goal: Vec3, best_cost: f32, seed: u64) -> Self {
+        Self {
+            start,
+            goal,
+            best_cost: best_cost.max(start.distance(goal)),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Shrinks the sampled region after a shorter path was found.
+    pub fn set_best_cost(&mut self, best_cost: f32) {
+        self.best_cost = best_cost.max(self.start.distance(self.goal));
+    }
+
+    /// The transform mapping the unit ball onto the sampled spheroid, e.g. for
+    /// [`SampleRegion::Ellipsoid`].
+    pub fn ellipsoid(&self) -> Affine3A {
+        let min_cost = self.start.distance(self.goal);
+        let minor = (self.best_cost * self.best_cost - min_cost * min_cost)
+            .max(0.0)
+            .sqrt()
+            / 2.0;
+        let axis = (self.goal - self.start).try_normalize().unwrap_or(Vec3::X);
+        Affine3A::from_scale_rotation_translation(
+            Vec3::new(self.best_cost / 2.0, minor, minor),
+            Quat::from_rotation_arc(Vec3::X, axis),
+            (self.start + self.goal) / 2.0,
+        )
+    }
+}
+
+impl Sampler for InformedSampler {
+    fn sample(&mut self) -> Vec3 {
+        let ball = loop {
+            let p = Vec3::new(self.rng.random(), self.rng.random(), self.rng.random()) * 2.0
+                - Vec3::ONE;
+            if p.length_squared() <= 1.0 {
+                break p;
+            }
+        };
+        self.ellipsoid().transform_point3(ball)
+    }
+}
+
+/// Concentrates samples near the surfaces of the scene, for narrow passages.
+///
+/// Pairs of points a normally distributed distance apart are drawn, and for each pair whose
+/// connecting segment hits the scene the point just before the hit is kept, as a ray-traced
+/// take on Gaussian sampling.
+#[derive(Clone, Debug)]
+pub struct GaussianObstacleSampler {
+    uniform: UniformSampler,
+    std_dev: f32,
+    rng: StdRng,
+}
+
+impl GaussianObstacleSampler {
+    /// Distance kept between a sample and the surface it was found on.
+    const SURFACE_OFFSET: f32 = 1e-3;
+
+    pub fn new(top_right: Vec3, bottom_left: Vec3, std_dev: f32, seed: u64) -> Self {
+        Self {
+            uniform: UniformSampler::new(top_right, bottom_left, seed),
+            std_dev,
+            rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+        }
+    }
+
+    /// Draws `num_pairs` pairs and checks them in one GPU batch. Returns one sample per pair
+    /// that straddles a surface, so usually fewer than `num_pairs`.
+    pub async fn sample_batch(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &RayTraceScene,
+        num_pairs: usize,
+    ) -> Vec<Vec3> {
+        let starts = self.uniform.sample_batch(num_pairs);
+        let ends: Vec<Vec3> = starts
+            .iter()
+            .map(|start| {
+                let offset = Vec3::new(
+                    gaussian(&mut self.rng),
+                    gaussian(&mut self.rng),
+                    gaussian(&mut self.rng),
+                );
+                *start + offset * self.std_dev
+            })
+            .collect();
+        cast_edges(device, queue, scene, &starts, &ends)
+            .await
+            .into_iter()
+            .zip(&starts)
+            .filter_map(|(hit, start)| {
+                let hit = hit?;
+                let back = (*start - hit.position).normalize_or_zero();
+                Some(hit.position + back * Self::SURFACE_OFFSET.min(hit.distance))
+            })
+            .collect()
+    }
+}
+
+/// Returns a standard normally distributed value using the Box-Muller transform.
+fn gaussian(rng: &mut StdRng) -> f32 {
+    let u1 = rng.random::<f32>().max(1e-7);
+    let u2 = rng.random::<f32>();
+    (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+}
+
+/// A region sampled uniformly on the GPU by [`encode_samples`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SampleRegion {
+    /// An axis aligned box.
+    Box { top_right: Vec3, bottom_left: Vec3 },
+    /// The image of the unit ball under `transform`, e.g. [`InformedSampler::ellipsoid`].
+    Ellipsoid { transform: Affine3A },
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct SamplingParams {
+    transform: Mat4,
+    num_samples: u32,
+    shape: u32,
+    _padding: [u32; 2],
+}
+
+/// Records the generation of `num_samples` uniform samples of `region` into `encoder`.
+///
+/// Returns a buffer, usable as `STORAGE` and `COPY_SRC`, holding 4 floats per sample with the
+/// position in the first 3. The stream is driven by `rng`, so a fixed seed reproduces the same
+/// samples.
+pub fn encode_samples(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    rng: &mut GpuRng,
+    region: &SampleRegion,
+    num_samples: u32,
+) -> wgpu::Buffer {
+    let samples = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Samples"),
+        size: num_samples.max(1) as wgpu::BufferAddress * 16,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    if num_samples == 0 {
+        return samples;
+    }
+    let (transform, shape) = match region {
+        SampleRegion::Box {
+            top_right,
+            bottom_left,
+        } => (
+            Mat4::from_scale_rotation_translation(
+                *top_right - *bottom_left,
+                Quat::IDENTITY,
+                *bottom_left,
+            ),
+            0,
+        ),
+        SampleRegion::Ellipsoid { transform } => (Mat4::from(*transform), 1),
+    };
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("sampling"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+            [RNG_WGSL, include_str!("sampling.wgsl")].join("\n"),
+        )),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("sampling"),
+        layout: None,
+        module: &shader,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Sampling Parameters"),
+        contents: bytemuck::cast_slice(&[SamplingParams {
+            transform,
+            num_samples,
+            shape,
+            _padding: [0; 2],
+        }]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let seed = rng.create_seed_buffer(device);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: seed.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: samples.as_entire_binding(),
+            },
+        ],
+    });
+    let workgroups = num_samples.div_ceil(64);
+    {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(
+            workgroups.min(MAX_WORKGROUPS_PER_DIMENSION),
+            workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+            1,
+        );
+    }
+    samples
+}
+
+#[cfg(test)]
+#[test]
+fn test_cpu_samplers() {
+    let top_right = Vec3::new(4.0, 2.0, 1.0);
+    let mut uniform = UniformSampler::new(top_right, Vec3::ZERO, 7);
+    let samples = uniform.sample_batch(1000);
+    assert!(samples
+        .iter()
+        .all(|p| p.cmpge(Vec3::ZERO).all() && p.cmplt(top_right).all()));
+    assert_eq!(
+        UniformSampler::new(top_right, Vec3::ZERO, 7).sample_batch(1000),
+        samples
+    );
+
+    let goal = Vec3::new(3.0, 1.0, 0.5);
+    let mut biased = GoalBiasedSampler::new(uniform, goal, 0.25, 3);
+    let hits = biased
+        .sample_batch(4000)
+        .iter()
+        .filter(|p| **p == goal)
+        .count();
+    assert!((800..1200).contains(&hits), "{hits}");
+
+    let start = Vec3::new(0.0, 0.0, 0.0);
+    let mut informed = InformedSampler::new(start, goal, 5.0, 11);
+    for p in informed.sample_batch(1000) {
+        assert!(p.distance(start) + p.distance(goal) <= 5.0 + 1e-4);
+    }
+    informed.set_best_cost(0.0);
+    let p = informed.sample();
+    assert!((p.distance(start) + p.distance(goal) - start.distance(goal)).abs() < 1e-4);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_gpu_samples() {
+    use crate::utils::{get_raytracing_gpu, read_buffer};
+    use glam::Vec4;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let informed = InformedSampler::new(Vec3::ZERO, Vec3::new(2.0, 2.0, 0.0), 4.0, 0);
+    let regions = [
+        SampleRegion::Box {
+            top_right: Vec3::splat(1.0),
+            bottom_left: Vec3::splat(-1.0),
+        },
+        SampleRegion::Ellipsoid {
+            transform: informed.ellipsoid(),
+        },
+    ];
+    let mut rng = GpuRng::new(5);
+    for region in regions {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let samples = encode_samples(&device, &mut encoder, &mut rng, &region, 4096);
+        queue.submit(Some(encoder.finish()));
+        let samples: Vec<Vec4> = read_buffer(&device, &queue, &samples).await;
+        assert_eq!(samples.len(), 4096);
+        for p in samples.iter().map(|p| p.truncate()) {
+            match region {
+                SampleRegion::Box { .. } => assert!(p.abs().cmple(Vec3::ONE).all(), "{p}"),
+                SampleRegion::Ellipsoid { .. } => assert!(
+                    p.length() + p.distance(Vec3::new(2.0, 2.0, 0.0)) <= 4.0 + 1e-3,
+                    "{p}"
+                ),
+            }
+        }
+    }
+}
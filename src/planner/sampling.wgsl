@@ -0,0 +1,46 @@
+// Batched sample generation. Must be preceded by RNG_WGSL.
+
+const SHAPE_BOX: u32 = 0u;
+
+struct SamplingParams {
+    // Maps the unit cube [0, 1)^3, or the unit ball, to the sampled region.
+    transform: mat4x4<f32>,
+    num_samples: u32,
+    shape: u32,
+    _padding: vec2<u32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> params: SamplingParams;
+
+@group(0)
+@binding(1)
+var<uniform> rng_seed: RngSeed;
+
+@group(0)
+@binding(2)
+var<storage, read_write> samples: array<vec4<f32>>;
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    if index >= params.num_samples {
+        return;
+    }
+    var state = rng_init(rng_seed, index);
+    var point: vec3<f32>;
+    if params.shape == SHAPE_BOX {
+        point = vec3<f32>(rng_next_f32(&state), rng_next_f32(&state), rng_next_f32(&state));
+    } else {
+        // A uniformly random direction, scaled so that the density is uniform over the ball.
+        let direction = vec3<f32>(rng_next_gaussian(&state), rng_next_gaussian(&state), rng_next_gaussian(&state));
+        let radius = pow(rng_next_f32(&state), 1.0 / 3.0);
+        point = normalize(direction + vec3<f32>(1e-12)) * radius;
+    }
+    samples[index] = vec4<f32>((params.transform * vec4<f32>(point, 1.0)).xyz, 0.0);
+}
@@ -0,0 +1,182 @@
+use glam::Vec3;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{planner::collision::check_segments, rng::draw_seed, RayTraceScene};
+
+/// Parameters of [`shortcut_path`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShortcutParams {
+    /// Number of rounds of shortcut attempts.
+    pub rounds: usize,
+    /// Number of shortcuts tested in parallel per round.
+    pub batch_size: usize,
+    /// Shortcuts saving less than this many meters are ignored.
+    pub min_improvement: f32,
+    /// Seed of the shortcut sampling, or `None` to draw one with [`draw_seed`].
+    pub seed: Option<u64>,
+}
+
+impl Default for ShortcutParams {
+    fn default() -> Self {
+        Self {
+            rounds: 10,
+            batch_size: 256,
+            min_improvement: 1e-3,
+            seed: None,
+        }
+    }
+}
+
+/// A point along a path, with the segment it lies on and its distance from the start.
+#[derive(Copy, Clone, Debug)]
+struct PathPoint {
+    segment: usize,
+    arc_length: f32,
+    position: Vec3,
+}
+
+/// Shortens a collision-free path by replacing random stretches of it with straight segments.
+///
+/// Each round draws pairs of random points along the path and checks the straight segments
+/// between them against the scene in one GPU batch. The collision-free shortcuts are then
+/// applied greedily, largest saving first, skipping those overlapping an applied one. The end
+/// points of the path are kept.
+pub async fn shortcut_path(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    path: &[Vec3],
+    params: &ShortcutParams,
+) -> Vec<Vec3> {
+    let mut path = path.to_vec();
+    let mut rng = StdRng::seed_from_u64(params.seed.unwrap_or_else(draw_seed));
+    for _ in 0..params.rounds {
+        if path.len() < 3 {
+            break;
+        }
+        let arc_lengths: Vec<f32> = std::iter::once(0.0)
+            .chain(path.windows(2).scan(0.0, |length, w| {
+                *length += w[0].distance(w[1]);
+                Some(*length)
+            }))
+            .collect();
+        let total = *arc_lengths.last().unwrap();
+        let point_at = |arc_length: f32| {
+            let segment = arc_lengths
+                .partition_point(|l| *l <= arc_length)
+                .clamp(1, path.len() - 1)
+                - 1;
+            let length = arc_lengths[segment + 1] - arc_lengths[segment];
+            let t = if length > 0.0 {
+                (arc_length - arc_lengths[segment]) / length
+            } else {
+                0.0
+            };
+            PathPoint {
+                segment,
+                arc_length,
+                position: path[segment].lerp(path[segment + 1], t.clamp(0.0, 1.0)),
+            }
+        };
+
+        let mut candidates = vec![];
+        for _ in 0..params.batch_size {
+            let (a, b) = (rng.random::<f32>() * total, rng.random::<f32>() * total);
+            let (from, to) = (point_at(a.min(b)), point_at(a.max(b)));
+            let saving = (to.arc_length - from.arc_length) - from.position.distance(to.position);
+            if from.segment != to.segment && saving >= params.min_improvement {
+                candidates.push((from, to, saving));
+            }
+        }
+        let segments: Vec<_> = candidates
+            .iter()
+            .map(|(from, to, _)| (from.position, to.position))
+            .collect();
+        let collisions = check_segments(device, queue, scene, &segments).await;
+
+        let mut shortcuts: Vec<_> = candidates
+            .into_iter()
+            .zip(collisions)
+            .filter(|(_, hit)| !hit)
+            .map(|(candidate, _)| candidate)
+            .collect();
+        shortcuts.sort_by(|a, b| b.2.total_cmp(&a.2));
+        let mut applied: Vec<(PathPoint, PathPoint)> = vec![];
+        for (from, to, _) in shortcuts {
+            // Shortcuts must not share a segment either, as they are spliced in by segment.
+            if applied
+                .iter()
+                .all(|(f, t)| to.segment < f.segment || from.segment > t.segment)
+            {
+                applied.push((from, to));
+            }
+        }
+        if applied.is_empty() {
+            continue;
+        }
+        applied.sort_by_key(|(from, _)| from.segment);
+
+        let mut shortened = vec![];
+        let mut next = 0;
+        for (from, to) in applied {
+            shortened.extend_from_slice(&path[next..=from.segment]);
+            shortened.push(from.position);
+            shortened.push(to.position);
+            next = to.segment + 1;
+        }
+        shortened.extend_from_slice(&path[next..]);
+        shortened.dedup();
+        path = shortened;
+    }
+    path
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_shortcut_path() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+    use glam::Affine3A;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A box the path has to go around.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0)),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    // A zig-zagging detour over the box, as a sampling planner would return.
+    let mut path = vec![Vec3::ZERO];
+    for i in 1..20 {
+        let x = i as f32 * 0.5;
+        let z = 2.0 + if i % 2 == 0 { 0.3 } else { -0.3 };
+        path.push(Vec3::new(x, (i % 3) as f32 * 0.2, z));
+    }
+    path.push(Vec3::new(10.0, 0.0, 0.0));
+    let length = |path: &[Vec3]| path.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>();
+    let segments = |path: &[Vec3]| path.windows(2).map(|w| (w[0], w[1])).collect::<Vec<_>>();
+    assert!(check_segments(&device, &queue, &scene, &segments(&path))
+        .await
+        .iter()
+        .all(|hit| !hit));
+
+    let shortened = shortcut_path(&device, &queue, &scene, &path, &ShortcutParams::default()).await;
+    assert_eq!(shortened.first(), path.first());
+    assert_eq!(shortened.last(), path.last());
+    assert!(shortened.len() < path.len());
+    assert!(length(&shortened) < 0.8 * length(&path));
+    assert!(
+        check_segments(&device, &queue, &scene, &segments(&shortened))
+            .await
+            .iter()
+            .all(|hit| !hit)
+    );
+}
@@ -0,0 +1,170 @@
+use glam::Vec3;
+
+/// How [`crate::planner::RrtPlanner`] extends a tree node towards a sample.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Steering {
+    /// Straight lines of at most the step size, ignoring dynamics.
+    #[default]
+    Straight,
+    /// Circular arcs of at most the step size, leaving the node along its heading at constant
+    /// speed and curving towards the sample with a curvature of at most `max_curvature`. The
+    /// root may leave in any direction.
+    ConstantVelocityArc { max_curvature: f32 },
+    /// Constant acceleration of at most `max_acceleration` during `duration` seconds, starting
+    /// from the velocity of the node and aimed at reaching the sample at the end. The root
+    /// starts at rest.
+    DoubleIntegrator {
+        max_acceleration: f32,
+        duration: f32,
+    },
+}
+
+/// A state along a steered edge.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct SteeringState {
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+impl Steering {
+    /// Steers from `from` towards `target` and returns `segments` evenly spaced states along
+    /// the edge, excluding `from` and ending with the new node.
+    pub(crate) fn steer(
+        &self,
+        from: SteeringState,
+        target: Vec3,
+        step_size: f32,
+        segments: usize,
+    ) -> Vec<SteeringState> {
+        let segments = segments.max(1);
+        let fractions = (1..=segments).map(|i| i as f32 / segments as f32);
+        let offset = target - from.position;
+        match *self {
+            Steering::Straight => {
+                let end = if offset.length() <= step_size {
+                    target
+                } else {
+                    from.position + offset.clamp_length_max(step_size)
+                };
+                fractions
+                    .map(|t| SteeringState {
+                        position: if t < 1.0 {
+                            from.position.lerp(end, t)
+                        } else {
+                            end
+                        },
+                        velocity: Vec3::ZERO,
+                    })
+                    .collect()
+            }
+            Steering::ConstantVelocityArc { max_curvature } => {
+                let distance = offset.length();
+                let Some(heading) = from
+                    .velocity
+                    .try_normalize()
+                    .or_else(|| offset.try_normalize())
+                else {
+                    return vec![];
+                };
+                // The circle tangent to the heading through the target, if curved enough.
+                let normal = (offset - heading * offset.dot(heading))
+                    .try_normalize()
+                    .unwrap_or_else(|| heading.any_orthonormal_vector());
+                let angle = heading.angle_between(offset / distance.max(f32::EPSILON));
+                let curvature = (2.0 * angle.sin() / distance.max(f32::EPSILON)).min(max_curvature);
+                let length = if curvature < 1e-6 {
+                    distance.min(step_size)
+                } else if curvature < max_curvature {
+                    (2.0 * angle / curvature).min(step_size)
+                } else {
+                    step_size
+                };
+                fractions
+                    .map(|t| {
+                        let s = t * length;
+                        let (position, direction) = if curvature < 1e-6 {
+                            (heading * s, heading)
+                        } else {
+                            let turn = curvature * s;
+                            (
+                                heading * turn.sin() / curvature
+                                    + normal * (1.0 - turn.cos()) / curvature,
+                                heading * turn.cos() + normal * turn.sin(),
+                            )
+                        };
+                        SteeringState {
+                            position: from.position + position,
+                            velocity: direction * from.velocity.length().max(1.0),
+                        }
+                    })
+                    .collect()
+            }
+            Steering::DoubleIntegrator {
+                max_acceleration,
+                duration,
+            } => {
+                let acceleration = (2.0 * (offset - from.velocity * duration)
+                    / (duration * duration))
+                    .clamp_length_max(max_acceleration);
+                fractions
+                    .map(|t| {
+                        let time = t * duration;
+                        SteeringState {
+                            position: from.position
+                                + from.velocity * time
+                                + 0.5 * acceleration * time * time,
+                            velocity: from.velocity + acceleration * time,
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_steering() {
+    let from = SteeringState {
+        position: Vec3::ZERO,
+        velocity: Vec3::X,
+    };
+
+    let straight = Steering::Straight.steer(from, Vec3::new(3.0, 4.0, 0.0), 1.0, 4);
+    assert_eq!(straight.len(), 4);
+    assert!(straight[3]
+        .position
+        .abs_diff_eq(Vec3::new(0.6, 0.8, 0.0), 1e-5));
+
+    // A target reachable with a gentle arc: a quarter circle of radius 1.
+    let arc = Steering::ConstantVelocityArc { max_curvature: 2.0 };
+    let states = arc.steer(from, Vec3::new(1.0, 1.0, 0.0), 10.0, 8);
+    let end = states.last().unwrap();
+    assert!(
+        end.position.abs_diff_eq(Vec3::new(1.0, 1.0, 0.0), 1e-4),
+        "{end:?}"
+    );
+    assert!(end.velocity.abs_diff_eq(Vec3::Y, 1e-4), "{end:?}");
+    for state in &states {
+        assert!((state.position.distance(Vec3::Y) - 1.0).abs() < 1e-4);
+    }
+    // Too sharp a turn: follow the tightest allowed circle for a full step.
+    let states = arc.steer(from, Vec3::new(0.0, 0.5, 0.0), 0.5, 8);
+    let end = states.last().unwrap();
+    assert!((end.position.distance(Vec3::new(0.0, 0.5, 0.0)) - 0.5).abs() < 1e-4);
+
+    let integrator = Steering::DoubleIntegrator {
+        max_acceleration: 10.0,
+        duration: 1.0,
+    };
+    let states = integrator.steer(from, Vec3::new(2.0, 1.0, 0.0), 1.0, 4);
+    assert!(states[3]
+        .position
+        .abs_diff_eq(Vec3::new(2.0, 1.0, 0.0), 1e-5));
+    let limited = Steering::DoubleIntegrator {
+        max_acceleration: 0.5,
+        duration: 1.0,
+    };
+    let states = limited.steer(from, Vec3::new(2.0, 1.0, 0.0), 1.0, 4);
+    assert!((states[3].velocity - from.velocity).length() <= 0.5 + 1e-5);
+}
@@ -0,0 +1,222 @@
+use std::f32::consts::TAU;
+
+use glam::{Affine3A, Quat, Vec3};
+
+use crate::{planner::collision::check_segments, RayTraceScene};
+
+/// Number of segments approximating each great circle of a [`RobotShape::Sphere`].
+const SPHERE_CIRCLE_SEGMENTS: usize = 16;
+
+/// The volume of a robot, in its body frame, as probed by [`check_trajectory`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum RobotShape {
+    /// A sphere centered on the body origin, probed along three great circles and their
+    /// diameters.
+    Sphere { radius: f32 },
+    /// A box centered on the body origin, probed along its edges and face and body diagonals.
+    Box { half_extents: Vec3 },
+    /// Arbitrary probe segments, e.g. the outline of a robot arm.
+    Segments(Vec<(Vec3, Vec3)>),
+}
+
+impl RobotShape {
+    /// The probe segments of the shape in its body frame.
+    pub fn segments(&self) -> Vec<(Vec3, Vec3)> {
+        match self {
+            RobotShape::Sphere { radius } => {
+                let mut segments = vec![];
+                for (u, v) in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)] {
+                    let point = |i: usize| {
+                        let angle = i as f32 * TAU / SPHERE_CIRCLE_SEGMENTS as f32;
+                        (u * angle.cos() + v * angle.sin()) * *radius
+                    };
+                    segments.extend((0..SPHERE_CIRCLE_SEGMENTS).map(|i| (point(i), point(i + 1))));
+                    segments.push((-u * *radius, u * *radius));
+                }
+                segments
+            }
+            RobotShape::Box { half_extents } => {
+                let corner = |i: usize| {
+                    Vec3::new(
+                        if i & 1 == 0 { -1.0 } else { 1.0 },
+                        if i & 2 == 0 { -1.0 } else { 1.0 },
+                        if i & 4 == 0 { -1.0 } else { 1.0 },
+                    ) * *half_extents
+                };
+                let mut segments = vec![];
+                for i in 0..8usize {
+                    for j in i + 1..8 {
+                        // Corners differing in one coordinate share an edge, in two a face and in
+                        // three the body diagonal; every pair is a useful probe.
+                        segments.push((corner(i), corner(j)));
+                    }
+                }
+                segments
+            }
+            RobotShape::Segments(segments) => segments.clone(),
+        }
+    }
+}
+
+/// Checks a trajectory of a robot against the scene in a single GPU batch.
+///
+/// The motion between consecutive poses is interpolated, linearly in translation and spherically
+/// in rotation, so that no probe point of `shape` moves more than `max_step` meters between two
+/// states. At every state the probe segments of the shape are cast against the scene, and so are
+/// the paths of their end points between states, approximating the swept volume. Obstacles
+/// small enough to fit between the probes without touching them go unnoticed.
+///
+/// Returns one flag per pose, set if the robot collides at that pose or on its way to the next
+/// one.
+///
+/// # Arguments
+///
+/// * `device` - The `wgpu::Device` to use.
+/// * `queue` - The `wgpu::Queue` to use for submitting commands.
+/// * `scene` - The scene to check against.
+/// * `poses` - The poses of the robot body along the trajectory.
+/// * `shape` - The volume of the robot in its body frame.
+/// * `max_step` - Maximum distance travelled by any probe point between two checked states.
+pub async fn check_trajectory(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene: &RayTraceScene,
+    poses: &[Affine3A],
+    shape: &RobotShape,
+    max_step: f32,
+) -> Vec<bool> {
+    let (segments, owners) = sweep_segments(poses, shape, max_step);
+    let collisions = check_segments(device, queue, scene, &segments).await;
+    let mut result = vec![false; poses.len()];
+    for (owner, hit) in owners.into_iter().zip(collisions) {
+        result[owner] |= hit;
+    }
+    result
+}
+
+/// The probe segments cast by [`check_trajectory`], along with the index of the pose each one
+/// belongs to.
+pub(crate) fn sweep_segments(
+    poses: &[Affine3A],
+    shape: &RobotShape,
+    max_step: f32,
+) -> (Vec<(Vec3, Vec3)>, Vec<usize>) {
+    let probes = shape.segments();
+    let vertices: Vec<Vec3> = probes.iter().flat_map(|(a, b)| [*a, *b]).collect();
+
+    let mut segments = vec![];
+    let mut owners = vec![];
+    for (i, pose) in poses.iter().enumerate() {
+        let next = poses.get(i + 1).unwrap_or(pose);
+        let displacement = vertices
+            .iter()
+            .map(|v| {
+                pose.transform_point3(*v)
+                    .distance(next.transform_point3(*v))
+            })
+            .fold(0.0, f32::max);
+        let steps = ((displacement / max_step).ceil() as usize).max(1);
+        let (_, start_rotation, start_translation) = pose.to_scale_rotation_translation();
+        let (_, end_rotation, end_translation) = next.to_scale_rotation_translation();
+        let state = |t: f32| {
+            Affine3A::from_rotation_translation(
+                Quat::slerp(start_rotation, end_rotation, t),
+                start_translation.lerp(end_translation, t),
+            )
+        };
+
+        let states: Vec<Affine3A> = if i + 1 < poses.len() {
+            (0..=steps)
+                .map(|k| state(k as f32 / steps as f32))
+                .collect()
+        } else {
+            vec![*pose]
+        };
+        for current in &states {
+            segments.extend(
+                probes
+                    .iter()
+                    .map(|(a, b)| (current.transform_point3(*a), current.transform_point3(*b))),
+            );
+        }
+        for pair in states.windows(2) {
+            segments.extend(
+                vertices
+                    .iter()
+                    .map(|v| (pair[0].transform_point3(*v), pair[1].transform_point3(*v))),
+            );
+        }
+        owners.resize(segments.len(), i);
+    }
+    (segments, owners)
+}
+
+#[cfg(test)]
+#[test]
+fn test_robot_shape_segments() {
+    let sphere = RobotShape::Sphere { radius: 0.5 }.segments();
+    assert_eq!(sphere.len(), 3 * (SPHERE_CIRCLE_SEGMENTS + 1));
+    for (a, b) in &sphere {
+        assert!((a.length() - 0.5).abs() < 1e-5 && (b.length() - 0.5).abs() < 1e-5);
+    }
+
+    let half_extents = Vec3::new(1.0, 0.5, 0.25);
+    let segments = RobotShape::Box { half_extents }.segments();
+    assert_eq!(segments.len(), 28);
+    assert!(segments.contains(&(-half_extents, half_extents)));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_check_trajectory() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A thin post at the origin.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_scale(Vec3::new(0.05, 0.05, 1.0)),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let shape = RobotShape::Box {
+        half_extents: Vec3::new(0.6, 0.2, 0.2),
+    };
+
+    // Driving past the post in big steps: no pose touches it, but the sweep from the second to
+    // the third pose does.
+    let poses = [
+        Affine3A::from_translation(Vec3::new(-3.0, 0.5, 0.0)),
+        Affine3A::from_translation(Vec3::new(-1.0, 0.1, 0.0)),
+        Affine3A::from_translation(Vec3::new(1.0, 0.1, 0.0)),
+        Affine3A::from_translation(Vec3::new(3.0, 0.5, 0.0)),
+    ];
+    let result = check_trajectory(&device, &queue, &scene, &poses, &shape, 0.1).await;
+    assert_eq!(result, vec![false, true, false, false]);
+
+    // Turning around next to the post sweeps the box over it. Only the surface of the shape is
+    // probed, so the check relies on the sweep: the post would fit inside the box half way.
+    let poses = [
+        Affine3A::from_translation(Vec3::new(0.0, 0.5, 0.0)),
+        Affine3A::from_rotation_translation(
+            Quat::from_rotation_z(std::f32::consts::PI),
+            Vec3::new(0.0, 0.5, 0.0),
+        ),
+    ];
+    let result = check_trajectory(&device, &queue, &scene, &poses, &shape, 0.05).await;
+    assert_eq!(result, vec![true, false]);
+
+    let far = [Affine3A::from_translation(Vec3::new(0.0, 2.0, 0.0))];
+    assert_eq!(
+        check_trajectory(&device, &queue, &scene, &far, &shape, 0.1).await,
+        vec![false]
+    );
+}
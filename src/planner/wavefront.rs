@@ -0,0 +1,390 @@
+use glam::{IVec3, UVec2, UVec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{
+    connected_components::Connectivity,
+    costmap::{Costmap2D, INSCRIBED_COST},
+    read_buffer,
+};
+
+/// Number of relaxation rounds recorded between two convergence checks.
+const ROUNDS_PER_CHECK: usize = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct WavefrontParams {
+    dims: UVec3,
+    full_connectivity: u32,
+    goal: UVec3,
+    obstacle_cost: u32,
+    cost_weight: f32,
+    _padding: [f32; 3],
+}
+
+/// A shortest path found by [`plan_grid_path`] or [`plan_costmap_path`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridPath<C> {
+    /// The cells of the path, from the start to the goal.
+    pub cells: Vec<C>,
+    /// Total travel cost, in cells for plain occupancy grids.
+    pub cost: f32,
+}
+
+/// Finds the shortest path between two cells of a 3D occupancy grid.
+///
+/// The travel cost to the goal is expanded from the goal to every reachable cell by relaxing
+/// all cells in parallel on the GPU until nothing changes, then the path is read off the
+/// resulting cost field by steepest descent from the start. Diagonal steps, allowed with
+/// [`Connectivity::Full`], cost their Euclidean length. Returns `None` if the start or goal is
+/// occupied or no path exists.
+///
+/// # Arguments
+///
+/// * `device` - The `wgpu::Device` to use.
+/// * `queue` - The `wgpu::Queue` to use for submitting commands.
+/// * `occupancy` - One flag per cell, non-zero for occupied cells, laid out x-major.
+/// * `dims` - Number of cells along each axis.
+/// * `start` - The cell to start from.
+/// * `goal` - The cell to reach.
+/// * `connectivity` - Which neighbouring cells can be moved between.
+pub async fn plan_grid_path(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    occupancy: &[u32],
+    dims: UVec3,
+    start: UVec3,
+    goal: UVec3,
+    connectivity: Connectivity,
+) -> Option<GridPath<UVec3>> {
+    if occupancy.len() != (dims.x * dims.y * dims.z) as usize {
+        panic!("Occupancy grid does not match dimensions");
+    }
+    let costs = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Wavefront Occupancy"),
+        contents: bytemuck::cast_slice(occupancy),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let params = WavefrontParams {
+        dims,
+        full_connectivity: (connectivity == Connectivity::Full) as u32,
+        goal,
+        obstacle_cost: 1,
+        cost_weight: 0.0,
+        _padding: [0.0; 3],
+    };
+    let distances = expand(device, queue, &costs, &params).await;
+    descend(&distances, occupancy, &params, start)
+}
+
+/// Finds the cheapest 8-connected path between two cells of a costmap.
+///
+/// Cells at or above [`INSCRIBED_COST`] are obstacles. Every step costs its length times
+/// `1 + cost_weight * c`, `c` being the mean cost of the two cells, so a larger `cost_weight`
+/// keeps the path further from obstacles. The costmap is read straight from its GPU buffer.
+/// Returns `None` if the start or goal is an obstacle or no path exists.
+pub async fn plan_costmap_path(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    costmap: &Costmap2D,
+    start: UVec2,
+    goal: UVec2,
+    cost_weight: f32,
+) -> Option<GridPath<UVec2>> {
+    let params = WavefrontParams {
+        dims: costmap.dims().extend(1),
+        full_connectivity: 1,
+        goal: goal.extend(0),
+        obstacle_cost: INSCRIBED_COST as u32,
+        cost_weight,
+        _padding: [0.0; 3],
+    };
+    let distances = expand(device, queue, costmap.buffer(), &params).await;
+    let costs: Vec<u32> = costmap
+        .download(device, queue)
+        .await
+        .into_iter()
+        .map(u32::from)
+        .collect();
+    let path = descend(&distances, &costs, &params, start.extend(0))?;
+    Some(GridPath {
+        cells: path.cells.into_iter().map(|c| c.truncate()).collect(),
+        cost: path.cost,
+    })
+}
+
+/// Computes the travel cost from every cell to the goal on the GPU.
+async fn expand(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    costs: &wgpu::Buffer,
+    params: &WavefrontParams,
+) -> Vec<f32> {
+    let dims = params.dims;
+    let num_cells = (dims.x * dims.y * dims.z) as usize;
+
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("wavefront.wgsl"));
+    let create_pipeline = |entry_point: &str| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+    let init_pipeline = create_pipeline("init");
+    let relax_pipeline = create_pipeline("relax");
+
+    let distances = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Wavefront Distances"),
+        size: (num_cells * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Wavefront Parameters"),
+        contents: bytemuck::cast_slice(&[*params]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let changed = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Wavefront Changed Flag"),
+        size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let create_bind_group = |pipeline: &wgpu::ComputePipeline, with_flag: bool| {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: costs.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: distances.as_entire_binding(),
+            },
+        ];
+        if with_flag {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: changed.as_entire_binding(),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        })
+    };
+    let init_bind_group = create_bind_group(&init_pipeline, false);
+    let relax_bind_group = create_bind_group(&relax_pipeline, true);
+
+    let workgroups = dims.map(|d| d.div_ceil(4));
+    let dispatch = |encoder: &mut wgpu::CommandEncoder,
+                    pipeline: &wgpu::ComputePipeline,
+                    bind_group: &wgpu::BindGroup| {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(pipeline);
+        cpass.set_bind_group(0, bind_group, &[]);
+        cpass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+    };
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    dispatch(&mut encoder, &init_pipeline, &init_bind_group);
+    queue.submit(Some(encoder.finish()));
+
+    loop {
+        queue.write_buffer(&changed, 0, bytemuck::cast_slice(&[0u32]));
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for _ in 0..ROUNDS_PER_CHECK {
+            dispatch(&mut encoder, &relax_pipeline, &relax_bind_group);
+        }
+        queue.submit(Some(encoder.finish()));
+        let flag: Vec<u32> = read_buffer(device, queue, &changed).await;
+        if flag[0] == 0 {
+            break;
+        }
+    }
+
+    read_buffer(device, queue, &distances).await
+}
+
+/// Must match `step_cost` in wavefront.wgsl.
+fn step_cost(offset: IVec3, from_cost: u32, to_cost: u32, cost_weight: f32) -> f32 {
+    let mean_cost = 0.5 * (from_cost as f32 + to_cost as f32);
+    offset.as_vec3().length() * (1.0 + cost_weight * mean_cost)
+}
+
+/// Follows the steepest descent of `distances` from `start` to the goal.
+fn descend(
+    distances: &[f32],
+    costs: &[u32],
+    params: &WavefrontParams,
+    start: UVec3,
+) -> Option<GridPath<UVec3>> {
+    let dims = params.dims;
+    if start.cmpge(dims).any() {
+        return None;
+    }
+    let index = |cell: UVec3| (cell.x + cell.y * dims.x + cell.z * dims.x * dims.y) as usize;
+    let cost = distances[index(start)];
+    if cost == f32::MAX || costs[index(start)] >= params.obstacle_cost {
+        return None;
+    }
+
+    let mut cells = vec![start];
+    let mut cell = start;
+    while cell != params.goal {
+        let current = distances[index(cell)];
+        let mut best: Option<(UVec3, f32)> = None;
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let offset = IVec3::new(dx, dy, dz);
+                    let manhattan = dx.abs() + dy.abs() + dz.abs();
+                    if manhattan == 0 || (params.full_connectivity == 0 && manhattan != 1) {
+                        continue;
+                    }
+                    let neighbour = cell.as_ivec3() + offset;
+                    if neighbour.cmplt(IVec3::ZERO).any() || neighbour.cmpge(dims.as_ivec3()).any()
+                    {
+                        continue;
+                    }
+                    let neighbour = neighbour.as_uvec3();
+                    let neighbour_cost = costs[index(neighbour)];
+                    let distance = distances[index(neighbour)];
+                    if neighbour_cost >= params.obstacle_cost || distance >= current {
+                        continue;
+                    }
+                    let total = distance
+                        + step_cost(
+                            offset,
+                            costs[index(cell)],
+                            neighbour_cost,
+                            params.cost_weight,
+                        );
+                    if best.is_none_or(|(_, b)| total < b) {
+                        best = Some((neighbour, total));
+                    }
+                }
+            }
+        }
+        cell = best?.0;
+        cells.push(cell);
+    }
+    Some(GridPath { cells, cost })
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_plan_grid_path() {
+    use crate::utils::{costmap::CostDecay, get_raytracing_gpu};
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A wall across a flat 10x10 grid with a gap at its far end.
+    let dims = UVec3::new(10, 10, 1);
+    let mut occupancy = vec![0u32; 100];
+    for y in 0..9 {
+        occupancy[(5 + y * 10) as usize] = 1;
+    }
+    let start = UVec3::new(1, 0, 0);
+    let goal = UVec3::new(8, 0, 0);
+
+    let face = plan_grid_path(
+        &device,
+        &queue,
+        &occupancy,
+        dims,
+        start,
+        goal,
+        Connectivity::Face,
+    )
+    .await
+    .unwrap();
+    // Up to the gap at y = 9 and back down.
+    assert_eq!(face.cost, 25.0);
+    assert_eq!(face.cells.len(), 26);
+    assert_eq!(face.cells.first(), Some(&start));
+    assert_eq!(face.cells.last(), Some(&goal));
+    for step in face.cells.windows(2) {
+        assert_eq!(
+            (step[0].as_ivec3() - step[1].as_ivec3())
+                .abs()
+                .element_sum(),
+            1
+        );
+        assert_eq!(occupancy[(step[1].x + step[1].y * 10) as usize], 0);
+    }
+
+    let full = plan_grid_path(
+        &device,
+        &queue,
+        &occupancy,
+        dims,
+        start,
+        goal,
+        Connectivity::Full,
+    )
+    .await
+    .unwrap();
+    assert!(full.cost < face.cost);
+    assert_eq!(full.cells.last(), Some(&goal));
+
+    // The gap closed.
+    occupancy[95] = 1;
+    assert!(plan_grid_path(
+        &device,
+        &queue,
+        &occupancy,
+        dims,
+        start,
+        goal,
+        Connectivity::Full
+    )
+    .await
+    .is_none());
+
+    // The same grid as a costmap, where the cells next to the wall are costly but passable.
+    occupancy[95] = 0;
+    let costmap = Costmap2D::from_occupancy(
+        &device,
+        &queue,
+        &occupancy,
+        dims,
+        Vec3::ZERO,
+        1.0,
+        1.0,
+        0.0,
+        1.0,
+    )
+    .inflate(&device, &queue, 0.5, 3.0, CostDecay::Linear);
+    let costs = costmap.download(&device, &queue).await;
+    let (start, goal) = (start.truncate(), goal.truncate());
+    let shortest = plan_costmap_path(&device, &queue, &costmap, start, goal, 0.0)
+        .await
+        .unwrap();
+    assert!((shortest.cost - full.cost).abs() < 1e-4);
+    let careful = plan_costmap_path(&device, &queue, &costmap, start, goal, 0.1)
+        .await
+        .unwrap();
+    assert!(careful.cost > shortest.cost);
+    assert_eq!(careful.cells.last(), Some(&goal));
+    for cell in &careful.cells {
+        assert!(costs[costmap.cell_index(cell.x, cell.y)] < INSCRIBED_COST);
+    }
+}
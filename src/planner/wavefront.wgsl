@@ -0,0 +1,103 @@
+// Parallel wavefront expansion of travel costs from a goal cell.
+
+// Bit pattern of the largest finite f32, the distance of unreached cells.
+const UNREACHED: u32 = 0x7f7fffffu;
+
+struct WavefrontParams {
+    dims: vec3<u32>,
+    // 0 for face connectivity, 1 for full connectivity.
+    full_connectivity: u32,
+    goal: vec3<u32>,
+    // Cells with at least this cost are obstacles.
+    obstacle_cost: u32,
+    // Extra travel cost per unit of cell cost, relative to the step length.
+    cost_weight: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> costs: array<u32>;
+
+@group(0)
+@binding(1)
+var<uniform> params: WavefrontParams;
+
+/// Travel cost from each cell to the goal, stored as f32 bits. Non-negative floats order like
+/// their bits, so the atomics can take the minimum directly.
+@group(0)
+@binding(2)
+var<storage, read_write> distances: array<atomic<u32>>;
+
+@group(0)
+@binding(3)
+var<storage, read_write> changed: atomic<u32>;
+
+fn to_index(cell: vec3<u32>) -> u32 {
+    return cell.x + cell.y * params.dims.x + cell.z * params.dims.x * params.dims.y;
+}
+
+/// Must match `step_cost` in wavefront.rs.
+fn step_cost(offset: vec3<i32>, from_cost: u32, to_cost: u32) -> f32 {
+    let mean_cost = 0.5 * (f32(from_cost) + f32(to_cost));
+    return length(vec3<f32>(offset)) * (1.0 + params.cost_weight * mean_cost);
+}
+
+@compute
+@workgroup_size(4, 4, 4)
+fn init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = to_index(global_id);
+    if all(global_id == params.goal) && costs[index] < params.obstacle_cost {
+        atomicStore(&distances[index], 0u);
+    } else {
+        atomicStore(&distances[index], UNREACHED);
+    }
+}
+
+/// Lowers the travel cost of each free cell through its neighbours.
+@compute
+@workgroup_size(4, 4, 4)
+fn relax(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = to_index(global_id);
+    let cost = costs[index];
+    if cost >= params.obstacle_cost {
+        return;
+    }
+    let current = bitcast<f32>(atomicLoad(&distances[index]));
+    let cell = vec3<i32>(global_id);
+    var best = current;
+    for (var dz: i32 = -1; dz <= 1; dz++) {
+        for (var dy: i32 = -1; dy <= 1; dy++) {
+            for (var dx: i32 = -1; dx <= 1; dx++) {
+                let offset = vec3<i32>(dx, dy, dz);
+                let manhattan = abs(dx) + abs(dy) + abs(dz);
+                if manhattan == 0 || (params.full_connectivity == 0u && manhattan != 1) {
+                    continue;
+                }
+                let neighbour = cell + offset;
+                if any(neighbour < vec3<i32>(0)) || any(neighbour >= vec3<i32>(params.dims)) {
+                    continue;
+                }
+                let neighbour_index = to_index(vec3<u32>(neighbour));
+                let neighbour_cost = costs[neighbour_index];
+                let distance = atomicLoad(&distances[neighbour_index]);
+                if neighbour_cost >= params.obstacle_cost || distance == UNREACHED {
+                    continue;
+                }
+                best = min(best, bitcast<f32>(distance) + step_cost(offset, cost, neighbour_cost));
+            }
+        }
+    }
+    if best < current {
+        atomicMin(&distances[index], bitcast<u32>(best));
+        atomicStore(&changed, 1u);
+    }
+}
@@ -0,0 +1,157 @@
+//! Pose types accepted by the sensor and scene APIs.
+//!
+//! Poses are [`Affine3A`]s and view matrices [`Mat4`]s internally, but arguments take anything
+//! implementing [`IntoAffine3A`] or [`IntoMat4`]. With the `nalgebra` feature these include
+//! nalgebra's `Isometry3<f32>` and `Matrix4<f32>`, so robotics code built on nalgebra passes its
+//! poses as they are:
+//!
+//! ```ignore
+//! let pose = nalgebra::Isometry3::translation(1.0, 0.0, 0.5);
+//! let points = lidar.render_lidar_pointcloud(&scene, &device, &queue, pose).await;
+//! ```
+
+use glam::{Affine3A, Mat4};
+
+/// Converts into a pose.
+pub trait IntoAffine3A {
+    fn into_affine3a(self) -> Affine3A;
+}
+
+/// Converts into a view matrix, or any other homogeneous transform.
+pub trait IntoMat4 {
+    fn into_mat4(self) -> Mat4;
+}
+
+impl IntoAffine3A for Affine3A {
+    fn into_affine3a(self) -> Affine3A {
+        self
+    }
+}
+
+impl IntoAffine3A for &Affine3A {
+    fn into_affine3a(self) -> Affine3A {
+        *self
+    }
+}
+
+impl IntoAffine3A for Mat4 {
+    fn into_affine3a(self) -> Affine3A {
+        Affine3A::from_mat4(self)
+    }
+}
+
+impl IntoMat4 for Mat4 {
+    fn into_mat4(self) -> Mat4 {
+        self
+    }
+}
+
+impl IntoMat4 for &Mat4 {
+    fn into_mat4(self) -> Mat4 {
+        *self
+    }
+}
+
+impl IntoMat4 for Affine3A {
+    fn into_mat4(self) -> Mat4 {
+        Mat4::from(self)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+mod nalgebra_poses {
+    use glam::{Affine3A, Mat4, Quat, Vec3};
+    use nalgebra::{Isometry3, Matrix4, Quaternion, Translation3, UnitQuaternion};
+
+    use super::{IntoAffine3A, IntoMat4};
+
+    impl IntoAffine3A for Isometry3<f32> {
+        fn into_affine3a(self) -> Affine3A {
+            (&self).into_affine3a()
+        }
+    }
+
+    impl IntoAffine3A for &Isometry3<f32> {
+        fn into_affine3a(self) -> Affine3A {
+            // Stored as i, j, k, w.
+            let q = self.rotation.coords;
+            let t = self.translation.vector;
+            Affine3A::from_rotation_translation(
+                Quat::from_xyzw(q.x, q.y, q.z, q.w),
+                Vec3::new(t.x, t.y, t.z),
+            )
+        }
+    }
+
+    impl IntoAffine3A for Matrix4<f32> {
+        fn into_affine3a(self) -> Affine3A {
+            Affine3A::from_mat4(self.into_mat4())
+        }
+    }
+
+    impl IntoAffine3A for &Matrix4<f32> {
+        fn into_affine3a(self) -> Affine3A {
+            Affine3A::from_mat4(self.into_mat4())
+        }
+    }
+
+    impl IntoMat4 for Matrix4<f32> {
+        fn into_mat4(self) -> Mat4 {
+            (&self).into_mat4()
+        }
+    }
+
+    impl IntoMat4 for &Matrix4<f32> {
+        fn into_mat4(self) -> Mat4 {
+            // Both are column major.
+            Mat4::from_cols_slice(self.as_slice())
+        }
+    }
+
+    impl IntoMat4 for Isometry3<f32> {
+        fn into_mat4(self) -> Mat4 {
+            Mat4::from(self.into_affine3a())
+        }
+    }
+
+    /// Converts a pose without scale, e.g. one returned by the crate, to nalgebra.
+    pub fn to_isometry3(pose: &Affine3A) -> Isometry3<f32> {
+        let (_, rotation, translation) = pose.to_scale_rotation_translation();
+        Isometry3::from_parts(
+            Translation3::new(translation.x, translation.y, translation.z),
+            UnitQuaternion::from_quaternion(Quaternion::new(
+                rotation.w, rotation.x, rotation.y, rotation.z,
+            )),
+        )
+    }
+
+    /// Converts a homogeneous transform to nalgebra.
+    pub fn to_matrix4(matrix: &Mat4) -> Matrix4<f32> {
+        Matrix4::from_column_slice(&matrix.to_cols_array())
+    }
+
+    #[cfg(test)]
+    #[test]
+    fn test_nalgebra_round_trip() {
+        let isometry = Isometry3::new(
+            nalgebra::Vector3::new(1.0, 2.0, 3.0),
+            nalgebra::Vector3::new(0.1, -0.2, 0.3),
+        );
+        let pose = isometry.into_affine3a();
+        let point = nalgebra::Point3::new(0.5, -1.0, 2.0);
+        let expected = isometry * point;
+        let actual = pose.transform_point3(Vec3::new(point.x, point.y, point.z));
+        assert!(actual.abs_diff_eq(Vec3::new(expected.x, expected.y, expected.z), 1e-5));
+        assert!(isometry
+            .to_homogeneous()
+            .into_mat4()
+            .abs_diff_eq(Mat4::from(pose), 1e-5));
+        let back = to_isometry3(&pose);
+        assert!((back.translation.vector - isometry.translation.vector).norm() < 1e-5);
+        assert!(back.rotation.angle_to(&isometry.rotation) < 1e-5);
+        assert_eq!(to_matrix4(&Mat4::from(pose)).into_mat4(), Mat4::from(pose));
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+pub use nalgebra_poses::{to_isometry3, to_matrix4};
@@ -0,0 +1,222 @@
+//! A declarative, handle-based entry point for LiDAR simulation.
+//!
+//! [`RayTraceScene`] and [`Lidar`] leave uploading geometry, updating transforms and keeping
+//! sensors next to the scene to the caller. A [`LiDARRenderScene`] does it for them: objects,
+//! instances and LiDARs are added and referred to through handles, and the scene is built or
+//! updated on the first render after a change.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), wgpu_rt_lidar::Error> {
+//! use glam::{Affine3A, Vec3};
+//! use wgpu_rt_lidar::{utils::create_cube, LiDARRenderScene, RenderContext};
+//!
+//! let ctx = RenderContext::new(wgpu::Instance::default()).await?;
+//! let mut scene = LiDARRenderScene::new(ctx);
+//! let cube = scene.add_object(create_cube(1.0));
+//! let obstacle = scene.add_instance(cube, Affine3A::IDENTITY)?;
+//! let lidar = scene
+//!     .add_lidar(vec![Vec3::NEG_X], Affine3A::from_translation(Vec3::X * 5.0))
+//!     .await;
+//! let points = scene.get_lidar_returns(lidar).await?;
+//!
+//! scene.set_instance_transform(obstacle, Affine3A::from_translation(Vec3::X))?;
+//! let points = scene.get_lidar_returns(lidar).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use glam::{Affine3A, Vec3, Vec4};
+
+use crate::{
+    lidar::Lidar, pose::IntoAffine3A, AssetMesh, Error, Instance, RayTraceScene, RenderContext,
+};
+
+/// Refers to an object added with [`LiDARRenderScene::add_object`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectHandle(usize);
+
+/// Refers to an instance added with [`LiDARRenderScene::add_instance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstanceHandle(usize);
+
+/// Refers to a LiDAR added with [`LiDARRenderScene::add_lidar`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LidarHandle(usize);
+
+/// A scene and the LiDARs rendering it, on the GPU of a [`RenderContext`].
+pub struct LiDARRenderScene {
+    ctx: RenderContext,
+    assets: Vec<AssetMesh>,
+    instances: Vec<Instance>,
+    lidars: Vec<(Lidar, Affine3A)>,
+    /// Built on the first render after objects were added.
+    scene: Option<RayTraceScene>,
+    /// Instances moved since the scene was last updated.
+    moved: Vec<usize>,
+}
+
+impl LiDARRenderScene {
+    /// Creates an empty scene rendered on `ctx`.
+    pub fn new(ctx: RenderContext) -> Self {
+        Self {
+            ctx,
+            assets: vec![],
+            instances: vec![],
+            lidars: vec![],
+            scene: None,
+            moved: vec![],
+        }
+    }
+
+    /// The context the scene renders on.
+    pub fn context(&self) -> &RenderContext {
+        &self.ctx
+    }
+
+    /// Adds a mesh that instances can be placed of.
+    pub fn add_object(&mut self, mesh: AssetMesh) -> ObjectHandle {
+        self.assets.push(mesh);
+        self.scene = None;
+        ObjectHandle(self.assets.len() - 1)
+    }
+
+    /// Places an instance of `object` at `transform`.
+    pub fn add_instance(
+        &mut self,
+        object: ObjectHandle,
+        transform: impl IntoAffine3A,
+    ) -> Result<InstanceHandle, Error> {
+        if object.0 >= self.assets.len() {
+            return Err(Error::OutOfBounds("Object handle"));
+        }
+        let instance = Instance {
+            asset_mesh_index: object.0,
+            transform: transform.into_affine3a(),
+            id: 0,
+        };
+        if let Some(scene) = &mut self.scene {
+            scene.add_instances(self.ctx.device(), std::slice::from_ref(&instance))?;
+        }
+        self.instances.push(instance);
+        Ok(InstanceHandle(self.instances.len() - 1))
+    }
+
+    /// Moves an instance. Takes effect on the next render.
+    pub fn set_instance_transform(
+        &mut self,
+        instance: InstanceHandle,
+        transform: impl IntoAffine3A,
+    ) -> Result<(), Error> {
+        self.instances
+            .get_mut(instance.0)
+            .ok_or(Error::OutOfBounds("Instance handle"))?
+            .transform = transform.into_affine3a();
+        if self.scene.is_some() && !self.moved.contains(&instance.0) {
+            self.moved.push(instance.0);
+        }
+        Ok(())
+    }
+
+    /// Adds a LiDAR casting `ray_directions`, given in its own frame, from `pose`.
+    pub async fn add_lidar(
+        &mut self,
+        ray_directions: Vec<Vec3>,
+        pose: impl IntoAffine3A,
+    ) -> LidarHandle {
+        let lidar = self.ctx.create_lidar(ray_directions).await;
+        self.lidars.push((lidar, pose.into_affine3a()));
+        LidarHandle(self.lidars.len() - 1)
+    }
+
+    /// Moves a LiDAR. Takes effect on its next render.
+    pub fn set_lidar_pose(
+        &mut self,
+        lidar: LidarHandle,
+        pose: impl IntoAffine3A,
+    ) -> Result<(), Error> {
+        self.lidars
+            .get_mut(lidar.0)
+            .ok_or(Error::OutOfBounds("LiDAR handle"))?
+            .1 = pose.into_affine3a();
+        Ok(())
+    }
+
+    /// Returns the LiDAR with its native API, e.g. to set a noise model.
+    pub fn lidar_mut(&mut self, lidar: LidarHandle) -> Option<&mut Lidar> {
+        self.lidars.get_mut(lidar.0).map(|(lidar, _)| lidar)
+    }
+
+    /// Renders the point cloud of a LiDAR, see [`Lidar::render_lidar_pointcloud`].
+    ///
+    /// Builds the scene first if objects were added since the last render, or applies the
+    /// transforms set since then.
+    pub async fn get_lidar_returns(&mut self, lidar: LidarHandle) -> Result<Vec<Vec4>, Error> {
+        if lidar.0 >= self.lidars.len() {
+            return Err(Error::OutOfBounds("LiDAR handle"));
+        }
+        self.update_scene().await?;
+        let scene = self.scene.as_ref().expect("The scene was just built");
+        let (sensor, pose) = &mut self.lidars[lidar.0];
+        let points = self.ctx.render_lidar_pointcloud(sensor, scene, *pose).await;
+        Ok(points.chunks_exact(4).map(Vec4::from_slice).collect())
+    }
+
+    /// Builds the scene or applies the transforms set since the last render.
+    async fn update_scene(&mut self) -> Result<(), Error> {
+        match &mut self.scene {
+            Some(scene) => {
+                if !self.moved.is_empty() {
+                    let moved: Vec<Instance> = self
+                        .moved
+                        .iter()
+                        .map(|&i| self.instances[i].clone())
+                        .collect();
+                    self.ctx.set_transform(scene, &moved, &self.moved).await?;
+                    self.moved.clear();
+                }
+            }
+            None => {
+                self.scene = Some(self.ctx.create_scene(&self.assets, &self.instances).await?);
+                self.moved.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_lidar_render_scene_handles() {
+    use crate::utils::create_cube;
+
+    let ctx = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let mut scene = LiDARRenderScene::new(ctx);
+    let cube = scene.add_object(create_cube(1.0));
+    let obstacle = scene.add_instance(cube, Affine3A::IDENTITY).unwrap();
+    let lidar = scene
+        .add_lidar(
+            vec![Vec3::NEG_X],
+            Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0)),
+        )
+        .await;
+    let points = scene.get_lidar_returns(lidar).await.unwrap();
+    assert!((points[0].w - 4.0).abs() < 1e-4);
+
+    scene
+        .set_instance_transform(obstacle, Affine3A::from_translation(Vec3::X))
+        .unwrap();
+    let points = scene.get_lidar_returns(lidar).await.unwrap();
+    assert!((points[0].w - 3.0).abs() < 1e-4);
+
+    // A second instance is added to the built scene.
+    scene
+        .add_instance(cube, Affine3A::from_translation(Vec3::new(3.0, 0.0, 0.0)))
+        .unwrap();
+    let points = scene.get_lidar_returns(lidar).await.unwrap();
+    assert!((points[0].w - 1.0).abs() < 1e-4);
+
+    assert!(scene
+        .add_instance(ObjectHandle(1), Affine3A::IDENTITY)
+        .is_err());
+    assert!(scene.get_lidar_returns(LidarHandle(1)).await.is_err());
+}
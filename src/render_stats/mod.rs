@@ -0,0 +1,197 @@
+//! Statistics of a render computed on the GPU.
+//!
+//! Sanity checking a frame, e.g. that a sensor sees anything at all, should not require scanning
+//! millions of points on the CPU. Renders issued with statistics, such as
+//! [`crate::lidar::Lidar::render_lidar_beams_with_stats`], count hits and misses and reduce the
+//! ranges with atomics in [`RENDER_STATS_WGSL`] while tracing, and return a [`RenderStats`]
+//! alongside their output.
+
+use bytemuck_derive::{Pod, Zeroable};
+
+use crate::utils::{buffer_pool::BufferPool, readback_ring::ReadbackRing};
+
+/// WGSL source of `stats_record_hit` and `stats_record_miss`. The shader must declare a
+/// `render_stats` storage variable of type `RenderStats`.
+pub const RENDER_STATS_WGSL: &str = include_str!("render_stats.wgsl");
+
+/// Statistics of one render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderStats {
+    /// Number of beams or pixels with a return.
+    pub hits: u32,
+    /// Number of beams or pixels without a return, including returns dropped by the noise model.
+    pub misses: u32,
+    /// Shortest range returned, NaN without hits.
+    pub min_range: f32,
+    /// Longest range returned, NaN without hits.
+    pub max_range: f32,
+    /// Mean range returned, NaN without hits. The GPU sums ranges rounded to a millimeter.
+    pub mean_range: f32,
+}
+
+impl RenderStats {
+    /// Computes the statistics of the ranges of a render, `None` for each miss.
+    pub(crate) fn from_ranges(ranges: impl IntoIterator<Item = Option<f32>>) -> Self {
+        let mut stats = Self {
+            hits: 0,
+            misses: 0,
+            min_range: f32::NAN,
+            max_range: f32::NAN,
+            mean_range: f32::NAN,
+        };
+        let mut sum = 0.0f64;
+        for range in ranges {
+            let Some(range) = range else {
+                stats.misses += 1;
+                continue;
+            };
+            stats.hits += 1;
+            stats.min_range = stats.min_range.min(range);
+            stats.max_range = stats.max_range.max(range);
+            sum += range as f64;
+        }
+        if stats.hits > 0 {
+            stats.mean_range = (sum / stats.hits as f64) as f32;
+        }
+        stats
+    }
+}
+
+/// Storage block `RenderStats` in [`RENDER_STATS_WGSL`].
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug, PartialEq, Eq)]
+pub(crate) struct GpuRenderStats {
+    enabled: u32,
+    hits: u32,
+    misses: u32,
+    min_range: u32,
+    max_range: u32,
+    range_sum_low: u32,
+    range_sum_high: u32,
+    _padding: u32,
+}
+
+impl GpuRenderStats {
+    /// The initial block of a render, `enabled` or not.
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled as u32,
+            min_range: f32::INFINITY.to_bits(),
+            ..bytemuck::Zeroable::zeroed()
+        }
+    }
+
+    fn resolve(&self) -> RenderStats {
+        let has_hits = self.hits > 0;
+        let range = |bits: u32| {
+            if has_hits {
+                f32::from_bits(bits)
+            } else {
+                f32::NAN
+            }
+        };
+        let sum = ((self.range_sum_high as u64) << 32) | self.range_sum_low as u64;
+        RenderStats {
+            hits: self.hits,
+            misses: self.misses,
+            min_range: range(self.min_range),
+            max_range: range(self.max_range),
+            mean_range: if has_hits {
+                (sum as f64 / 1000.0 / self.hits as f64) as f32
+            } else {
+                f32::NAN
+            },
+        }
+    }
+}
+
+/// Reads back the statistics a submitted render accumulated in `buffer`, which is recycled
+/// into `pool` afterwards.
+pub(crate) fn read_back(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pool: &BufferPool,
+    buffer: wgpu::Buffer,
+) -> RenderStats {
+    let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let block: Vec<GpuRenderStats> = ReadbackRing::new(1)
+        .submit(device, queue, pool, encoder, vec![buffer])
+        .expect("A ring of one frame returns every submission");
+    block[0].resolve()
+}
+
+#[cfg(test)]
+#[test]
+fn test_render_stats_resolve() {
+    let stats = RenderStats::from_ranges([Some(2.0), None, Some(1.0), Some(6.0), None]);
+    assert_eq!((stats.hits, stats.misses), (3, 2));
+    assert_eq!((stats.min_range, stats.max_range), (1.0, 6.0));
+    assert_eq!(stats.mean_range, 3.0);
+
+    let gpu = GpuRenderStats {
+        hits: 3,
+        misses: 2,
+        min_range: 1.0f32.to_bits(),
+        max_range: 6.0f32.to_bits(),
+        range_sum_low: 9000,
+        ..GpuRenderStats::new(true)
+    };
+    assert_eq!(gpu.resolve(), stats);
+
+    // The sum carries into the high half past 2^32 millimeters.
+    let gpu = GpuRenderStats {
+        hits: 2,
+        range_sum_low: 0,
+        range_sum_high: 1,
+        ..gpu
+    };
+    assert_eq!(gpu.resolve().mean_range, (1u64 << 32) as f32 / 2000.0);
+
+    let empty = GpuRenderStats::new(true).resolve();
+    assert_eq!(empty.hits, 0);
+    assert!(empty.min_range.is_nan() && empty.max_range.is_nan() && empty.mean_range.is_nan());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_lidar_render_stats() {
+    use crate::{
+        lidar::Lidar,
+        utils::{create_cube, get_gpu},
+        Instance, RayTraceScene,
+    };
+    use glam::{Affine3A, Vec3};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let mut lidar = Lidar::new(&device, vec![Vec3::NEG_X, Vec3::NEG_X, Vec3::X, Vec3::Y]).await;
+    let pose = Affine3A::from_translation(Vec3::new(3.0, 0.0, 0.0));
+
+    let (ranges, stats) = lidar
+        .render_lidar_beams_with_stats(&scene, &device, &queue, &pose)
+        .await;
+    assert_eq!(ranges.len(), 4);
+    assert_eq!((stats.hits, stats.misses), (2, 2));
+    assert!((stats.min_range - 2.0).abs() < 1e-4);
+    assert!((stats.max_range - 2.0).abs() < 1e-4);
+    assert!((stats.mean_range - 2.0).abs() < 1e-3);
+
+    let (points, pointcloud_stats) = lidar
+        .render_lidar_pointcloud_with_stats(&scene, &device, &queue, &pose)
+        .await;
+    assert_eq!(points.len(), 16);
+    assert_eq!((pointcloud_stats.hits, pointcloud_stats.misses), (2, 2));
+    assert!((pointcloud_stats.mean_range - 2.0).abs() < 1e-3);
+}
@@ -0,0 +1,38 @@
+// Statistics of a render, accumulated with atomics. Sensors declare `render_stats` at a binding
+// of their own.
+
+struct RenderStats {
+    // Zero when the render was issued without statistics, which skips the atomics.
+    enabled: u32,
+    hits: atomic<u32>,
+    misses: atomic<u32>,
+    // Bits of the ranges, which order like the ranges as they are not negative.
+    min_range: atomic<u32>,
+    max_range: atomic<u32>,
+    // Sum of the ranges in millimeters, in two 32 bit halves.
+    range_sum_low: atomic<u32>,
+    range_sum_high: atomic<u32>,
+    padding: u32,
+};
+
+fn stats_record_hit(range: f32) {
+    if (render_stats.enabled == 0u) {
+        return;
+    }
+    atomicAdd(&render_stats.hits, 1u);
+    let bits = bitcast<u32>(abs(range));
+    atomicMin(&render_stats.min_range, bits);
+    atomicMax(&render_stats.max_range, bits);
+    let millimeters = u32(round(abs(range) * 1000.0));
+    let low = atomicAdd(&render_stats.range_sum_low, millimeters);
+    if (low > 0xffffffffu - millimeters) {
+        atomicAdd(&render_stats.range_sum_high, 1u);
+    }
+}
+
+fn stats_record_miss() {
+    if (render_stats.enabled == 0u) {
+        return;
+    }
+    atomicAdd(&render_stats.misses, 1u);
+}
@@ -0,0 +1,165 @@
+//! GPU random number generation.
+//!
+//! Shaders that need randomness prepend [`RNG_WGSL`] to their source and bind a [`RngSeed`]
+//! uniform. Each invocation then calls `rng_init(seed, invocation_index)` to obtain an independent
+//! PCG stream. On the Rust side a [`GpuRng`] tracks the seed and advances the frame counter on
+//! every dispatch, so a fixed seed replays the exact same sequence of frames.
+//!
+//! Sensors, samplers and planners not given a seed draw one with [`draw_seed`], from entropy by
+//! default. After [`set_global_seed`] they draw from a stream of that seed instead, so two runs
+//! that set the same seed and create their components in the same order produce identical
+//! outputs:
+//!
+//! ```no_run
+//! wgpu_rt_lidar::rng::set_global_seed(42);
+//! // Noise of sensors created from here on replays across runs.
+//! ```
+
+use std::sync::Mutex;
+
+use bytemuck_derive::{Pod, Zeroable};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use wgpu::util::DeviceExt;
+
+/// The stream seeds are drawn from in deterministic mode.
+static GLOBAL_RNG: Mutex<Option<StdRng>> = Mutex::new(None);
+
+/// Makes every seed drawn from now on, see [`draw_seed`], follow from `seed`.
+pub fn set_global_seed(seed: u64) {
+    *GLOBAL_RNG.lock().unwrap() = Some(StdRng::seed_from_u64(seed));
+}
+
+/// Returns to drawing seeds from entropy.
+pub fn clear_global_seed() {
+    *GLOBAL_RNG.lock().unwrap() = None;
+}
+
+/// Draws the seed of a new random stream, the next of the global seed's stream if one is set
+/// and from entropy otherwise.
+pub fn draw_seed() -> u64 {
+    match GLOBAL_RNG.lock().unwrap().as_mut() {
+        Some(rng) => rng.random(),
+        None => rand::random(),
+    }
+}
+
+/// WGSL source of the shared PCG generator.
+pub const RNG_WGSL: &str = include_str!("rng.wgsl");
+
+/// Uniform block consumed by `rng_init` in [`RNG_WGSL`].
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, Debug, Default, PartialEq, Eq)]
+pub struct RngSeed {
+    /// User provided seed.
+    pub seed: u32,
+    /// Index of the dispatch this seed was issued for.
+    pub frame: u32,
+    _padding: [u32; 2],
+}
+
+/// Host side state of a GPU random stream.
+#[derive(Clone, Debug)]
+pub struct GpuRng {
+    seed: u32,
+    frame: u32,
+}
+
+impl GpuRng {
+    /// Creates a generator with a fixed seed.
+    pub fn new(seed: u32) -> Self {
+        Self { seed, frame: 0 }
+    }
+
+    /// Creates a generator seeded with [`draw_seed`], from entropy unless a global seed is set.
+    pub fn from_entropy() -> Self {
+        Self::new(draw_seed() as u32)
+    }
+
+    /// Returns the current seed.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Returns the number of dispatches issued since the last reseed.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Resets the generator to a new seed and rewinds the frame counter.
+    pub fn reseed(&mut self, seed: u32) {
+        self.seed = seed;
+        self.frame = 0;
+    }
+
+    /// Returns the uniform block for the next dispatch and advances the frame counter.
+    pub fn next_seed(&mut self) -> RngSeed {
+        let seed = RngSeed {
+            seed: self.seed,
+            frame: self.frame,
+            _padding: [0; 2],
+        };
+        self.frame = self.frame.wrapping_add(1);
+        seed
+    }
+
+    /// Uploads the uniform block for the next dispatch.
+    pub(crate) fn create_seed_buffer(&mut self, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Rng Seed Buffer"),
+            contents: bytemuck::cast_slice(&[self.next_seed()]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        })
+    }
+}
+
+impl Default for GpuRng {
+    fn default() -> Self {
+        Self::from_entropy()
+    }
+}
+
+fn pcg_hash(input: u32) -> u32 {
+    let state = input.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// The stream of one invocation, computed on the CPU exactly like `rng_init` and
+/// `rng_next_u32` in [`RNG_WGSL`].
+pub(crate) struct PcgStream(u32);
+
+impl PcgStream {
+    pub(crate) fn new(seed: RngSeed, invocation: u32) -> Self {
+        Self(pcg_hash(
+            seed.seed ^ pcg_hash(seed.frame ^ pcg_hash(invocation)),
+        ))
+    }
+}
+
+impl rand::RngCore for PcgStream {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.0;
+        self.0 = old.wrapping_mul(747796405).wrapping_add(2891336453);
+        let word = ((old >> ((old >> 28) + 4)) ^ old).wrapping_mul(277803737);
+        (word >> 22) ^ word
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand::rand_core::impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand::rand_core::impls::fill_bytes_via_next(self, dest)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_gpu_rng_replay() {
+    let mut rng = GpuRng::new(42);
+    let first: Vec<_> = (0..3).map(|_| rng.next_seed()).collect();
+    assert_eq!(first[2].frame, 2);
+    rng.reseed(42);
+    let second: Vec<_> = (0..3).map(|_| rng.next_seed()).collect();
+    assert_eq!(first, second);
+}
@@ -0,0 +1,42 @@
+// Shared PCG random number generator.
+//
+// Every invocation derives its own stream from the sensor seed, the frame counter and its
+// invocation index, so a fixed seed reproduces the exact same sequence of frames.
+
+struct RngSeed {
+    seed: u32,
+    frame: u32,
+    _padding: u32,
+    _padding2: u32,
+};
+
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+/// Initializes the state for one invocation.
+fn rng_init(seed: RngSeed, invocation: u32) -> u32 {
+    return pcg_hash(seed.seed ^ pcg_hash(seed.frame ^ pcg_hash(invocation)));
+}
+
+/// Returns the next random u32 and advances the state.
+fn rng_next_u32(state: ptr<function, u32>) -> u32 {
+    let old = *state;
+    *state = old * 747796405u + 2891336453u;
+    let word = ((old >> ((old >> 28u) + 4u)) ^ old) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+/// Returns a uniformly distributed float in [0, 1).
+fn rng_next_f32(state: ptr<function, u32>) -> f32 {
+    return f32(rng_next_u32(state) >> 8u) / 16777216.0;
+}
+
+/// Returns a standard normally distributed float using the Box-Muller transform.
+fn rng_next_gaussian(state: ptr<function, u32>) -> f32 {
+    let u1 = max(rng_next_f32(state), 1e-7);
+    let u2 = rng_next_f32(state);
+    return sqrt(-2.0 * log(u1)) * cos(6.283185307 * u2);
+}
@@ -0,0 +1,257 @@
+//! `sensor_msgs/Image` and `sensor_msgs/CameraInfo` from depth cameras.
+
+use super::{CdrWriter, Header};
+use crate::{depth_camera::DepthCamera, Error};
+
+/// How depths are stored in an [`Image`], following REP 118.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthEncoding {
+    /// `16UC1`, millimeters. Pixels without a return are 0.
+    Millimeters16,
+    /// `32FC1`, meters. Pixels without a return are NaN.
+    Meters32,
+}
+
+impl DepthEncoding {
+    /// The name of the encoding in `sensor_msgs/Image`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DepthEncoding::Millimeters16 => "16UC1",
+            DepthEncoding::Meters32 => "32FC1",
+        }
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        match self {
+            DepthEncoding::Millimeters16 => 2,
+            DepthEncoding::Meters32 => 4,
+        }
+    }
+}
+
+/// `sensor_msgs/Image`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub encoding: String,
+    pub is_bigendian: u8,
+    /// Length of a row in bytes.
+    pub step: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Converts the output of [`DepthCamera::render_depth_camera`] into a depth image.
+    ///
+    /// The camera renders the range along each pixel's ray, column by column from the bottom,
+    /// while ROS depth images hold the distance along the optical axis, row by row from the top.
+    /// Both are converted, so the image lines up with [`CameraInfo::from_depth_camera`].
+    ///
+    /// Fails if `depth` is not an image of `camera`.
+    pub fn from_depth_image(
+        header: Header,
+        camera: &DepthCamera,
+        depth: &[f32],
+        encoding: DepthEncoding,
+    ) -> Result<Self, Error> {
+        let depths = camera.optical_depths(depth)?;
+        let mut data = Vec::with_capacity(depths.len() * encoding.bytes_per_pixel());
+        for z in depths {
+            match encoding {
+                DepthEncoding::Millimeters16 => {
+                    let millimeters = if z.is_nan() {
+                        0
+                    } else {
+                        (z * 1000.0).round().min(u16::MAX as f32) as u16
+                    };
+                    data.extend_from_slice(&millimeters.to_le_bytes());
+                }
+                DepthEncoding::Meters32 => data.extend_from_slice(&z.to_le_bytes()),
+            }
+        }
+        let (width, height) = (camera.width() as usize, camera.height() as usize);
+        let step = width * encoding.bytes_per_pixel();
+        Ok(Self {
+            header,
+            height: height as u32,
+            width: width as u32,
+            encoding: encoding.name().to_string(),
+            is_bigendian: 0,
+            step: step as u32,
+            data,
+        })
+    }
+
+    /// Serializes the message as published by ROS 2, in little endian CDR.
+    pub fn to_cdr(&self) -> Vec<u8> {
+        let mut cdr = CdrWriter::new();
+        self.header.write(&mut cdr);
+        cdr.u32(self.height);
+        cdr.u32(self.width);
+        cdr.string(&self.encoding);
+        cdr.u8(self.is_bigendian);
+        cdr.u32(self.step);
+        cdr.bytes(&self.data);
+        cdr.finish()
+    }
+}
+
+/// `sensor_msgs/RegionOfInterest`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegionOfInterest {
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub height: u32,
+    pub width: u32,
+    pub do_rectify: bool,
+}
+
+/// `sensor_msgs/CameraInfo`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraInfo {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub distortion_model: String,
+    /// Distortion coefficients.
+    pub d: Vec<f64>,
+    /// Intrinsic camera matrix, row major.
+    pub k: [f64; 9],
+    /// Rectification matrix, row major.
+    pub r: [f64; 9],
+    /// Projection matrix, row major.
+    pub p: [f64; 12],
+    pub binning_x: u32,
+    pub binning_y: u32,
+    pub roi: RegionOfInterest,
+}
+
+impl CameraInfo {
+    /// Describes the pinhole of `camera`, which has no distortion, for images converted with
+    /// [`Image::from_depth_image`].
+    pub fn from_depth_camera(header: Header, camera: &DepthCamera) -> Self {
+        let projection = camera.projection_matrix();
+        let (width, height) = (camera.width() as f64, camera.height() as f64);
+        let fx = projection.x_axis.x as f64 * width / 2.0;
+        let fy = projection.y_axis.y as f64 * height / 2.0;
+        // Pixel centers are at integer coordinates in ROS.
+        let cx = width / 2.0 - 0.5;
+        let cy = height / 2.0 - 0.5;
+        Self {
+            header,
+            height: camera.height(),
+            width: camera.width(),
+            distortion_model: "plumb_bob".to_string(),
+            d: vec![0.0; 5],
+            k: [fx, 0.0, cx, 0.0, fy, cy, 0.0, 0.0, 1.0],
+            r: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            p: [fx, 0.0, cx, 0.0, 0.0, fy, cy, 0.0, 0.0, 0.0, 1.0, 0.0],
+            binning_x: 0,
+            binning_y: 0,
+            roi: RegionOfInterest::default(),
+        }
+    }
+
+    /// Serializes the message as published by ROS 2, in little endian CDR.
+    pub fn to_cdr(&self) -> Vec<u8> {
+        let mut cdr = CdrWriter::new();
+        self.header.write(&mut cdr);
+        cdr.u32(self.height);
+        cdr.u32(self.width);
+        cdr.string(&self.distortion_model);
+        cdr.u32(self.d.len() as u32);
+        for &v in self.d.iter().chain(&self.k).chain(&self.r).chain(&self.p) {
+            cdr.f64(v);
+        }
+        cdr.u32(self.binning_x);
+        cdr.u32(self.binning_y);
+        cdr.u32(self.roi.x_offset);
+        cdr.u32(self.roi.y_offset);
+        cdr.u32(self.roi.height);
+        cdr.u32(self.roi.width);
+        cdr.bool(self.roi.do_rectify);
+        cdr.finish()
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_depth_image_matches_camera_info() {
+    use super::Time;
+    use crate::{
+        utils::{create_cube, get_gpu},
+        Instance, RayTraceScene,
+    };
+    use glam::{Affine3A, Mat4, Vec3, Vec4};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    // A wall at z = -4 in front of the camera, which looks down -z.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_scale_rotation_translation(
+                Vec3::new(100.0, 100.0, 1.0),
+                glam::Quat::IDENTITY,
+                Vec3::new(0.0, 0.0, -5.0),
+            ),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let mut camera = DepthCamera::new(&device, 8, 6, 60.0, 100.0).await;
+    let depth = camera
+        .render_depth_camera(&scene, &device, &queue, Mat4::IDENTITY)
+        .await;
+    let header = Header::new(Time::default(), "camera");
+
+    let image =
+        Image::from_depth_image(header.clone(), &camera, &depth, DepthEncoding::Meters32).unwrap();
+    assert_eq!((image.width, image.height, image.step), (8, 6, 32));
+    let meters: Vec<f32> = bytemuck::pod_collect_to_vec(&image.data);
+    assert!(meters.iter().all(|z| (z - 4.0).abs() < 1e-3), "{meters:?}");
+
+    let image = Image::from_depth_image(
+        header.clone(),
+        &camera,
+        &depth,
+        DepthEncoding::Millimeters16,
+    )
+    .unwrap();
+    assert_eq!(image.encoding, "16UC1");
+    let millimeters: Vec<u16> = bytemuck::pod_collect_to_vec(&image.data);
+    assert!(millimeters.iter().all(|&z| z == 4000));
+    assert!(Image::from_depth_image(
+        header.clone(),
+        &camera,
+        &depth[1..],
+        DepthEncoding::Meters32
+    )
+    .is_err());
+
+    // Back projecting a pixel with the intrinsics gives the ray the camera traced for it.
+    let info = CameraInfo::from_depth_camera(header, &camera);
+    let proj_inverse = camera.projection_matrix().inverse();
+    for (u, v) in [(0, 0), (7, 5), (2, 4)] {
+        let d = (glam::Vec2::new(u as f32, (5 - v) as f32) + 0.5) / glam::Vec2::new(8.0, 6.0) * 2.0
+            - 1.0;
+        let ray = (proj_inverse * Vec4::new(d.x, d.y, 1.0, 1.0)).truncate();
+        // From the camera frame, y up and looking down -z, to the optical frame.
+        let ray = Vec3::new(ray.x, -ray.y, -ray.z);
+        let ray = ray / ray.z;
+        assert!((ray.x as f64 - (u as f64 - info.k[2]) / info.k[0]).abs() < 1e-5);
+        assert!((ray.y as f64 - (v as f64 - info.k[5]) / info.k[4]).abs() < 1e-5);
+    }
+    // The header, the size, the padded model name, 5 distortion coefficients and 30 matrix
+    // entries, the binning and the region of interest.
+    assert_eq!(
+        info.to_cdr().len(),
+        4 + 20 + 8 + 4 + 12 + 4 + 35 * 8 + 8 + 17
+    );
+}
@@ -0,0 +1,213 @@
+//! `sensor_msgs/LaserScan` from planar LiDARs.
+
+use glam::Vec3;
+
+use super::{CdrWriter, Header};
+use crate::{
+    cpu::{LIDAR_T_MAX, LIDAR_T_MIN},
+    lidar::Lidar,
+    Error,
+};
+
+/// `sensor_msgs/LaserScan`.
+///
+/// Beams without a return have a range of `+inf`, as REP 117 asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaserScan {
+    pub header: Header,
+    /// Angle of the first beam about the z axis, in radians.
+    pub angle_min: f32,
+    /// Angle of the last beam.
+    pub angle_max: f32,
+    pub angle_increment: f32,
+    /// Time between beams in seconds.
+    pub time_increment: f32,
+    /// Time between scans in seconds.
+    pub scan_time: f32,
+    pub range_min: f32,
+    pub range_max: f32,
+    pub ranges: Vec<f32>,
+    /// Empty, or one per range.
+    pub intensities: Vec<f32>,
+}
+
+impl LaserScan {
+    /// The beam directions of a planar LiDAR scanning `num_beams` beams from `angle_min` to
+    /// `angle_max` about its z axis, to create the [`Lidar`] with.
+    pub fn planar_beams(angle_min: f32, angle_max: f32, num_beams: usize) -> Vec<Vec3> {
+        let increment = increment(angle_min, angle_max, num_beams);
+        (0..num_beams)
+            .map(|i| {
+                let angle = angle_min + i as f32 * increment;
+                Vec3::new(angle.cos(), angle.sin(), 0.0)
+            })
+            .collect()
+    }
+
+    /// Converts the output of [`Lidar::render_lidar_beams`] of a LiDAR created with
+    /// [`LaserScan::planar_beams`]. Beams without a return, whose range is zero, become `+inf`.
+    pub fn from_lidar_beams(
+        header: Header,
+        ranges: &[f32],
+        angle_min: f32,
+        angle_max: f32,
+    ) -> Self {
+        Self::new(
+            header,
+            angle_min,
+            angle_max,
+            ranges.iter().map(|&range| to_scan_range(range)).collect(),
+            vec![],
+        )
+    }
+
+    /// Converts the output of [`Lidar::render_lidar_pointcloud`] of a LiDAR created with
+    /// [`LaserScan::planar_beams`]. The intensities are the hit shader payloads, zero for beams
+    /// without a return.
+    ///
+    /// Fails if `points` is not a point cloud.
+    pub fn from_lidar_pointcloud(
+        header: Header,
+        points: &[f32],
+        angle_min: f32,
+        angle_max: f32,
+    ) -> Result<Self, Error> {
+        if !points.len().is_multiple_of(4) {
+            return Err(Error::InvalidArgument(format!(
+                "A point cloud of {} floats is not made of points of 4",
+                points.len()
+            )));
+        }
+        let (ranges, intensities) = points
+            .chunks_exact(4)
+            .map(|point| {
+                if point[0] == Lidar::no_hit_const() {
+                    (f32::INFINITY, 0.0)
+                } else {
+                    (Vec3::from_slice(point).length(), point[3])
+                }
+            })
+            .unzip();
+        Ok(Self::new(header, angle_min, angle_max, ranges, intensities))
+    }
+
+    fn new(
+        header: Header,
+        angle_min: f32,
+        angle_max: f32,
+        ranges: Vec<f32>,
+        intensities: Vec<f32>,
+    ) -> Self {
+        Self {
+            header,
+            angle_min,
+            angle_max,
+            angle_increment: increment(angle_min, angle_max, ranges.len()),
+            time_increment: 0.0,
+            scan_time: 0.0,
+            range_min: LIDAR_T_MIN,
+            range_max: LIDAR_T_MAX,
+            ranges,
+            intensities,
+        }
+    }
+
+    /// Sets the timing of a scanner whose beams fire one after the other over `scan_time`
+    /// seconds. The ranges are still traced at once, so this only informs consumers deskewing
+    /// the scan.
+    pub fn with_scan_time(mut self, scan_time: f32) -> Self {
+        self.scan_time = scan_time;
+        self.time_increment = scan_time / self.ranges.len().max(1) as f32;
+        self
+    }
+
+    /// Serializes the message as published by ROS 2, in little endian CDR.
+    pub fn to_cdr(&self) -> Vec<u8> {
+        let mut cdr = CdrWriter::new();
+        self.header.write(&mut cdr);
+        cdr.f32(self.angle_min);
+        cdr.f32(self.angle_max);
+        cdr.f32(self.angle_increment);
+        cdr.f32(self.time_increment);
+        cdr.f32(self.scan_time);
+        cdr.f32(self.range_min);
+        cdr.f32(self.range_max);
+        cdr.f32s(&self.ranges);
+        cdr.f32s(&self.intensities);
+        cdr.finish()
+    }
+}
+
+fn increment(angle_min: f32, angle_max: f32, num_beams: usize) -> f32 {
+    if num_beams > 1 {
+        (angle_max - angle_min) / (num_beams - 1) as f32
+    } else {
+        0.0
+    }
+}
+
+/// Maps a range returned by a LiDAR, zero without a return, to a `LaserScan` range.
+fn to_scan_range(range: f32) -> f32 {
+    if range > 0.0 {
+        range
+    } else {
+        f32::INFINITY
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_laser_scan_from_planar_lidar() {
+    use super::Time;
+    use crate::{
+        utils::{create_cube, get_gpu},
+        Instance, RayTraceScene,
+    };
+    use glam::Affine3A;
+    use std::f32::consts::PI;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    // Four beams at -x, -y, +x and +y, of which only -x sees the cube.
+    let directions = LaserScan::planar_beams(PI, 2.5 * PI, 4);
+    let mut lidar = Lidar::new(&device, directions).await;
+    let pose = Affine3A::from_translation(Vec3::new(3.0, 0.0, 0.0));
+    let header = Header::new(Time::default(), "laser");
+
+    let ranges = lidar
+        .render_lidar_beams(&scene, &device, &queue, &pose)
+        .await;
+    let scan = LaserScan::from_lidar_beams(header.clone(), &ranges, PI, 2.5 * PI);
+    assert!((scan.angle_increment - 0.5 * PI).abs() < 1e-6);
+    assert!((scan.ranges[0] - 2.0).abs() < 1e-4);
+    assert!(scan.ranges[1..].iter().all(|r| *r == f32::INFINITY));
+    assert!(scan.intensities.is_empty());
+
+    let points = lidar
+        .render_lidar_pointcloud(&scene, &device, &queue, &pose)
+        .await;
+    let scan = LaserScan::from_lidar_pointcloud(header, &points, PI, 2.5 * PI)
+        .unwrap()
+        .with_scan_time(0.1);
+    assert!((scan.ranges[0] - 2.0).abs() < 1e-4);
+    assert!((scan.intensities[0] - 2.0).abs() < 1e-4);
+    assert_eq!(scan.intensities[1..], [0.0; 3]);
+    assert_eq!(scan.time_increment, 0.025);
+
+    let bytes = scan.to_cdr();
+    // The header of 8 + 4 + 6 bytes is padded to 20, followed by seven floats and the arrays.
+    assert_eq!(bytes.len(), 4 + 20 + 7 * 4 + 4 + 16 + 4 + 16);
+    assert_eq!(bytes[52..56], 4u32.to_le_bytes());
+}
@@ -0,0 +1,178 @@
+//! Conversions of sensor outputs into ROS 2 messages.
+//!
+//! The messages mirror their `sensor_msgs` definitions field for field, so they map one to one
+//! onto the message types of any ROS 2 client library, and serialize into the CDR wire format
+//! ROS 2 publishes, see [`PointCloud2::to_cdr`]. No ROS 2 installation is needed to build them.
+//!
+//! ```no_run
+//! # async fn run(
+//! #     scene: wgpu_rt_lidar::RayTraceScene,
+//! #     device: wgpu::Device,
+//! #     queue: wgpu::Queue,
+//! #     mut lidar: wgpu_rt_lidar::lidar::Lidar,
+//! # ) -> Result<(), wgpu_rt_lidar::Error> {
+//! use wgpu_rt_lidar::ros2::{Header, PointCloud2, Time};
+//!
+//! let points = lidar
+//!     .render_lidar_pointcloud(&scene, &device, &queue, &glam::Affine3A::IDENTITY)
+//!     .await;
+//! let header = Header::new(Time::default(), "lidar");
+//! let message = PointCloud2::from_lidar_pointcloud(header, &points, &[])?;
+//! let bytes = message.to_cdr();
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod image;
+pub mod laser_scan;
+pub mod point_cloud;
+pub mod pose;
+
+pub use image::{CameraInfo, DepthEncoding, Image, RegionOfInterest};
+pub use laser_scan::LaserScan;
+pub use point_cloud::{BeamInfo, PointCloud2, PointField};
+pub use pose::PoseStamped;
+
+/// `builtin_interfaces/Time`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Time {
+    pub sec: i32,
+    pub nanosec: u32,
+}
+
+impl Time {
+    /// Converts seconds since the epoch of the clock, e.g. the simulation time.
+    pub fn from_secs_f64(secs: f64) -> Self {
+        let sec = secs.floor();
+        Self {
+            sec: sec as i32,
+            nanosec: (((secs - sec) * 1e9) as u32).min(999_999_999),
+        }
+    }
+}
+
+/// `std_msgs/Header`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Header {
+    pub stamp: Time,
+    /// The TF frame the data is expressed in.
+    pub frame_id: String,
+}
+
+impl Header {
+    pub fn new(stamp: Time, frame_id: impl Into<String>) -> Self {
+        Self {
+            stamp,
+            frame_id: frame_id.into(),
+        }
+    }
+
+    fn write(&self, cdr: &mut CdrWriter) {
+        cdr.i32(self.stamp.sec);
+        cdr.u32(self.stamp.nanosec);
+        cdr.string(&self.frame_id);
+    }
+}
+
+/// Serializes messages in little endian CDR, as published by ROS 2.
+///
+/// Every primitive is aligned to its size, relative to the end of the encapsulation header.
+pub(crate) struct CdrWriter {
+    buf: Vec<u8>,
+}
+
+impl CdrWriter {
+    /// Encapsulation header of little endian plain CDR.
+    const CDR_LE: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+
+    pub(crate) fn new() -> Self {
+        Self {
+            buf: Self::CDR_LE.to_vec(),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn align(&mut self, alignment: usize) {
+        let offset = self.buf.len() - Self::CDR_LE.len();
+        self.buf.resize(
+            self.buf.len() + (alignment - offset % alignment) % alignment,
+            0,
+        );
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn i32(&mut self, value: i32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, value: f32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) {
+        self.align(8);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// A string, counted with its terminating null.
+    pub(crate) fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32 + 1);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub(crate) fn f32s(&mut self, value: &[f32]) {
+        self.u32(value.len() as u32);
+        for &v in value {
+            self.f32(v);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_cdr_alignment() {
+    let mut cdr = CdrWriter::new();
+    Header::new(Time { sec: 1, nanosec: 2 }, "ab").write(&mut cdr);
+    cdr.u8(7);
+    cdr.u32(5);
+    let bytes = cdr.finish();
+    assert_eq!(
+        bytes[..19],
+        [0, 1, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, b'a', b'b', 0]
+    );
+    // The byte lands at 15 after the header, the integer is aligned to 16.
+    assert_eq!(bytes[19], 7);
+    assert_eq!(bytes.len(), 4 + 16 + 4);
+    assert_eq!(bytes[20..], [5, 0, 0, 0]);
+
+    assert_eq!(
+        Time::from_secs_f64(12.25),
+        Time {
+            sec: 12,
+            nanosec: 250_000_000
+        }
+    );
+}
@@ -0,0 +1,215 @@
+//! `sensor_msgs/PointCloud2` from LiDAR and depth camera point clouds.
+
+use glam::Vec4;
+
+use super::{CdrWriter, Header};
+use crate::{lidar::Lidar, Error};
+
+/// `sensor_msgs/PointField`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointField {
+    pub name: String,
+    /// Byte offset of the field in a point.
+    pub offset: u32,
+    /// One of the `PointField` datatype constants, e.g. [`PointField::FLOAT32`].
+    pub datatype: u8,
+    /// Number of elements in the field.
+    pub count: u32,
+}
+
+impl PointField {
+    pub const INT8: u8 = 1;
+    pub const UINT8: u8 = 2;
+    pub const INT16: u8 = 3;
+    pub const UINT16: u8 = 4;
+    pub const INT32: u8 = 5;
+    pub const UINT32: u8 = 6;
+    pub const FLOAT32: u8 = 7;
+    pub const FLOAT64: u8 = 8;
+
+    fn new(name: &str, offset: u32, datatype: u8) -> Self {
+        Self {
+            name: name.to_string(),
+            offset,
+            datatype,
+            count: 1,
+        }
+    }
+}
+
+/// Where a LiDAR beam sits in the scan pattern, written to the `ring` and `t` fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BeamInfo {
+    /// The laser, i.e. the row of the scan, that fired the beam.
+    pub ring: u16,
+    /// When the beam fired, in seconds since the stamp of the scan.
+    pub t: f32,
+}
+
+/// `sensor_msgs/PointCloud2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCloud2 {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub fields: Vec<PointField>,
+    pub is_bigendian: bool,
+    /// Length of a point in bytes.
+    pub point_step: u32,
+    /// Length of a row in bytes.
+    pub row_step: u32,
+    pub data: Vec<u8>,
+    /// True if no point is invalid.
+    pub is_dense: bool,
+}
+
+impl PointCloud2 {
+    /// Converts the output of [`Lidar::render_lidar_pointcloud`] into an unordered cloud with the
+    /// float fields `x`, `y`, `z`, `intensity` and `t` and the `u16` field `ring`, the layout of
+    /// common LiDAR drivers.
+    ///
+    /// Points are in the frame of the sensor. `intensity` is the hit shader payload, the range
+    /// with the default hit shader. `ring` and `t` come from `beams`, one per beam of the sensor,
+    /// or are zero if `beams` is empty. Beams without a return are left out.
+    ///
+    /// Fails if `points` is not a point cloud or `beams` does not match it.
+    pub fn from_lidar_pointcloud(
+        header: Header,
+        points: &[f32],
+        beams: &[BeamInfo],
+    ) -> Result<Self, Error> {
+        if !points.len().is_multiple_of(4) {
+            return Err(Error::InvalidArgument(format!(
+                "A point cloud of {} floats is not made of points of 4",
+                points.len()
+            )));
+        }
+        let num_beams = points.len() / 4;
+        if !beams.is_empty() && beams.len() != num_beams {
+            return Err(Error::InvalidArgument(format!(
+                "{} beams given for a point cloud of {num_beams}",
+                beams.len()
+            )));
+        }
+        const POINT_STEP: usize = 24;
+        let fields = vec![
+            PointField::new("x", 0, PointField::FLOAT32),
+            PointField::new("y", 4, PointField::FLOAT32),
+            PointField::new("z", 8, PointField::FLOAT32),
+            PointField::new("intensity", 12, PointField::FLOAT32),
+            PointField::new("ring", 16, PointField::UINT16),
+            PointField::new("t", 20, PointField::FLOAT32),
+        ];
+        let mut data = Vec::with_capacity(num_beams * POINT_STEP);
+        for (i, point) in points.chunks_exact(4).enumerate() {
+            if point[0] == Lidar::no_hit_const() {
+                continue;
+            }
+            let beam = beams.get(i).copied().unwrap_or_default();
+            data.extend_from_slice(bytemuck::cast_slice(point));
+            data.extend_from_slice(&beam.ring.to_le_bytes());
+            data.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(&beam.t.to_le_bytes());
+        }
+        Ok(Self::unordered(header, fields, POINT_STEP, data))
+    }
+
+    /// Converts the output of [`crate::depth_camera::DepthCamera::render_depth_camera_pointcloud`]
+    /// into an unordered cloud with the float fields `x`, `y`, `z` and `intensity`.
+    ///
+    /// Each point is its ray direction scaled by the payload, so the camera must use the default
+    /// hit shader, which returns the range. The points are offsets from the camera in the world
+    /// frame. `intensity` is the range. Pixels without a return are left out.
+    pub fn from_depth_pointcloud(header: Header, points: &[Vec4]) -> Self {
+        const POINT_STEP: usize = 16;
+        let fields = vec![
+            PointField::new("x", 0, PointField::FLOAT32),
+            PointField::new("y", 4, PointField::FLOAT32),
+            PointField::new("z", 8, PointField::FLOAT32),
+            PointField::new("intensity", 12, PointField::FLOAT32),
+        ];
+        let mut data = Vec::with_capacity(points.len() * POINT_STEP);
+        for point in points.iter().filter(|p| p.w > 0.0) {
+            let position = point.truncate() * point.w;
+            data.extend_from_slice(bytemuck::cast_slice(&[position.extend(point.w)]));
+        }
+        Self::unordered(header, fields, POINT_STEP, data)
+    }
+
+    fn unordered(
+        header: Header,
+        fields: Vec<PointField>,
+        point_step: usize,
+        data: Vec<u8>,
+    ) -> Self {
+        let width = (data.len() / point_step) as u32;
+        Self {
+            header,
+            height: 1,
+            width,
+            fields,
+            is_bigendian: false,
+            point_step: point_step as u32,
+            row_step: data.len() as u32,
+            data,
+            is_dense: true,
+        }
+    }
+
+    /// Serializes the message as published by ROS 2, in little endian CDR.
+    pub fn to_cdr(&self) -> Vec<u8> {
+        let mut cdr = CdrWriter::new();
+        self.header.write(&mut cdr);
+        cdr.u32(self.height);
+        cdr.u32(self.width);
+        cdr.u32(self.fields.len() as u32);
+        for field in &self.fields {
+            cdr.string(&field.name);
+            cdr.u32(field.offset);
+            cdr.u8(field.datatype);
+            cdr.u32(field.count);
+        }
+        cdr.bool(self.is_bigendian);
+        cdr.u32(self.point_step);
+        cdr.u32(self.row_step);
+        cdr.bytes(&self.data);
+        cdr.bool(self.is_dense);
+        cdr.finish()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_lidar_pointcloud2_layout() {
+    use super::Time;
+
+    let no_hit = Lidar::no_hit_const();
+    let points = [
+        1.0, 2.0, 3.0, 4.0, //
+        no_hit, no_hit, 100000.0, 100000.0, //
+        5.0, 6.0, 7.0, 8.0,
+    ];
+    let beams = [
+        BeamInfo { ring: 0, t: 0.0 },
+        BeamInfo { ring: 1, t: 0.1 },
+        BeamInfo { ring: 2, t: 0.2 },
+    ];
+    let header = Header::new(Time::default(), "lidar");
+    let cloud = PointCloud2::from_lidar_pointcloud(header.clone(), &points, &beams).unwrap();
+    assert_eq!((cloud.height, cloud.width), (1, 2));
+    assert_eq!(cloud.row_step, 48);
+    let second = &cloud.data[24..];
+    assert_eq!(
+        bytemuck::pod_read_unaligned::<[f32; 4]>(&second[..16]),
+        [5.0, 6.0, 7.0, 8.0]
+    );
+    assert_eq!(u16::from_le_bytes([second[16], second[17]]), 2);
+    assert_eq!(f32::from_le_bytes(second[20..24].try_into().unwrap()), 0.2);
+
+    let bytes = cloud.to_cdr();
+    assert!(bytes.ends_with(&[1]));
+    assert!(bytes.windows(cloud.data.len()).any(|w| w == cloud.data));
+
+    assert!(PointCloud2::from_lidar_pointcloud(header.clone(), &points[..5], &[]).is_err());
+    assert!(PointCloud2::from_lidar_pointcloud(header, &points, &beams[..1]).is_err());
+}
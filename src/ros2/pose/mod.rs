@@ -0,0 +1,41 @@
+//! `geometry_msgs/PoseStamped` of sensors and instances.
+
+use glam::{Affine3A, DQuat, DVec3};
+
+use super::{CdrWriter, Header};
+
+/// `geometry_msgs/PoseStamped`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoseStamped {
+    pub header: Header,
+    pub position: DVec3,
+    pub orientation: DQuat,
+}
+
+impl PoseStamped {
+    /// The pose of a transform without scale, e.g. the pose a sensor is rendered at, in the
+    /// frame of `header`.
+    pub fn from_affine(header: Header, pose: &Affine3A) -> Self {
+        let (_, rotation, translation) = pose.to_scale_rotation_translation();
+        Self {
+            header,
+            position: translation.as_dvec3(),
+            orientation: rotation.as_dquat(),
+        }
+    }
+
+    /// Serializes the message as published by ROS 2, in little endian CDR.
+    pub fn to_cdr(&self) -> Vec<u8> {
+        let mut cdr = CdrWriter::new();
+        self.header.write(&mut cdr);
+        for v in self
+            .position
+            .to_array()
+            .into_iter()
+            .chain(self.orientation.to_array())
+        {
+            cdr.f64(v);
+        }
+        cdr.finish()
+    }
+}
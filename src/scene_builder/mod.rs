@@ -0,0 +1,207 @@
+//! Assembling a [`RayTraceScene`] with options beyond its assets and instances.
+//!
+//! ```no_run
+//! # async fn run(ctx: &wgpu_rt_lidar::RenderContext) -> Result<(), wgpu_rt_lidar::Error> {
+//! use glam::Affine3A;
+//! use wgpu_rt_lidar::{utils::create_cube, Instance, RayTraceScene};
+//!
+//! let scene = RayTraceScene::builder()
+//!     .with_asset(create_cube(1.0))
+//!     .with_instances([Instance {
+//!         asset_mesh_index: 0,
+//!         transform: Affine3A::IDENTITY,
+//!         id: 0,
+//!     }])
+//!     .with_max_instances(1024)
+//!     .build(ctx)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Setups adding meshes and placing them as they go, e.g. while walking a world file, use
+//! [`RayTraceSceneBuilder::add_asset`] and [`RayTraceSceneBuilder::add_instance`], which return
+//! indices to refer to what was added. Nothing touches the GPU until the scene is built.
+
+use crate::{AssetMesh, Instance, RayTraceScene, RenderContext, SceneError, SceneOptions};
+
+/// Builds a [`RayTraceScene`], see [`RayTraceScene::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct RayTraceSceneBuilder {
+    assets: Vec<AssetMesh>,
+    instances: Vec<Instance>,
+    options: SceneOptions,
+}
+
+impl RayTraceSceneBuilder {
+    /// Adds an asset. Its index is the number of assets added before it.
+    pub fn with_asset(mut self, asset: AssetMesh) -> Self {
+        self.assets.push(asset);
+        self
+    }
+
+    /// Adds several assets, see [`RayTraceSceneBuilder::with_asset`].
+    pub fn with_assets(mut self, assets: impl IntoIterator<Item = AssetMesh>) -> Self {
+        self.assets.extend(assets);
+        self
+    }
+
+    /// Adds an asset and returns its index, for instances to refer to.
+    pub fn add_asset(&mut self, asset: AssetMesh) -> usize {
+        self.assets.push(asset);
+        self.assets.len() - 1
+    }
+
+    /// Places an instance and returns its index in the scene.
+    pub fn add_instance(&mut self, instance: Instance) -> usize {
+        self.instances.push(instance);
+        self.instances.len() - 1
+    }
+
+    /// Returns the number of assets added so far.
+    pub fn asset_count(&self) -> usize {
+        self.assets.len()
+    }
+
+    /// Returns the number of instances placed so far.
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Places an instance.
+    pub fn with_instance(mut self, instance: Instance) -> Self {
+        self.instances.push(instance);
+        self
+    }
+
+    /// Places several instances.
+    pub fn with_instances(mut self, instances: impl IntoIterator<Item = Instance>) -> Self {
+        self.instances.extend(instances);
+        self
+    }
+
+    /// Sizes the TLAS for `max_instances` instances, leaving headroom above the instances placed.
+    ///
+    /// See [`RayTraceScene::reserve_instances`] to grow it later.
+    pub fn with_max_instances(mut self, max_instances: usize) -> Self {
+        self.options.max_instances = max_instances;
+        self
+    }
+
+    /// Sets the flags the acceleration structures are built with, `PREFER_FAST_TRACE` by default.
+    ///
+    /// Scenes whose instances move every frame may trade trace speed for build speed with
+    /// `PREFER_FAST_BUILD`.
+    pub fn with_build_flags(mut self, flags: wgpu::AccelerationStructureFlags) -> Self {
+        self.options.build_flags = flags;
+        self
+    }
+
+    /// Sets the mask instances are placed in the TLAS with, `0xff` by default.
+    ///
+    /// Rays only hit instances whose mask shares a bit with the cull mask of the sensor, `0xff`
+    /// by default, so a mask of zero hides the instances. See
+    /// [`RayTraceScene::set_instance_mask`] to change the mask of single instances.
+    pub fn with_instance_mask(mut self, mask: u8) -> Self {
+        self.options.instance_mask = mask;
+        self
+    }
+
+    /// Builds the scene on the device of `ctx`.
+    pub async fn build(self, ctx: &RenderContext) -> Result<RayTraceScene, SceneError> {
+        self.build_on(ctx.device(), ctx.queue()).await
+    }
+
+    /// Builds the scene on `device`.
+    ///
+    /// Fails with [`SceneError::CapacityExceeded`] if fewer instances than placed were asked
+    /// room for, or as [`RayTraceScene::new`], before any buffer is created. A scene with room
+    /// for instances may start without any.
+    pub async fn build_on(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<RayTraceScene, SceneError> {
+        if self.options.max_instances != 0 && self.options.max_instances < self.instances.len() {
+            return Err(SceneError::CapacityExceeded {
+                capacity: self.options.max_instances,
+                instances: self.instances.len(),
+            });
+        }
+        RayTraceScene::with_options(device, queue, &self.assets, &self.instances, self.options)
+            .await
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_builder_matches_new() {
+    use crate::utils::create_cube;
+    use glam::{Affine3A, Vec3};
+
+    let ctx = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let instance = Instance {
+        asset_mesh_index: 0,
+        transform: Affine3A::IDENTITY,
+        id: 0,
+    };
+    let scene = RayTraceScene::builder()
+        .with_asset(create_cube(1.0))
+        .with_instance(instance.clone())
+        .with_max_instances(16)
+        .with_build_flags(wgpu::AccelerationStructureFlags::PREFER_FAST_BUILD)
+        .build(&ctx)
+        .await
+        .unwrap();
+    let mut lidar = ctx.create_lidar(vec![Vec3::NEG_X]).await;
+    let pose = Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0));
+    let beams = ctx.render_lidar_beams(&mut lidar, &scene, &pose).await;
+    assert!((beams[0] - 4.0).abs() < 1e-4);
+
+    assert!(RayTraceScene::builder()
+        .with_asset(create_cube(1.0))
+        .with_instances([instance.clone(), instance])
+        .with_max_instances(1)
+        .build(&ctx)
+        .await
+        .is_err_and(|e| matches!(e, SceneError::CapacityExceeded { .. })));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_builder_adds_incrementally() {
+    use crate::utils::create_cube;
+    use glam::Affine3A;
+
+    let ctx = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let mut builder = RayTraceScene::builder();
+    let cube = builder.add_asset(create_cube(1.0));
+    let first = builder.add_instance(Instance {
+        asset_mesh_index: cube,
+        transform: Affine3A::IDENTITY,
+        id: 0,
+    });
+    let second = builder.add_instance(Instance {
+        asset_mesh_index: cube,
+        transform: Affine3A::from_translation(glam::Vec3::X * 3.0),
+        id: 0,
+    });
+    assert_eq!((cube, first, second), (0, 0, 1));
+    assert_eq!((builder.asset_count(), builder.instance_count()), (1, 2));
+    let scene = builder.clone().build(&ctx).await.unwrap();
+    assert_eq!(scene.instance_count(), 2);
+
+    builder.add_instance(Instance {
+        asset_mesh_index: 1,
+        transform: Affine3A::IDENTITY,
+        id: 0,
+    });
+    assert!(matches!(
+        builder.build(&ctx).await,
+        Err(SceneError::AssetOutOfBounds {
+            instance: 2,
+            asset: 1,
+            assets: 1
+        })
+    ));
+}
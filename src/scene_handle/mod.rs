@@ -0,0 +1,152 @@
+//! Sharing one scene between sensors rendering on several threads.
+//!
+//! Renders only borrow a [`RayTraceScene`], so any number of them can run at once, while moving
+//! instances needs the scene exclusively. A [`SceneHandle`] puts the scene behind a read-write
+//! lock: sensor threads render under read locks, and [`SceneHandle::set_transform`] takes the
+//! write lock and rebuilds the TLAS before releasing it, so concurrent renders never race to
+//! record the rebuild.
+//!
+//! ```no_run
+//! # async fn run(
+//! #     scene: wgpu_rt_lidar::RayTraceScene,
+//! #     device: wgpu::Device,
+//! #     queue: wgpu::Queue,
+//! #     mut lidars: Vec<wgpu_rt_lidar::lidar::Lidar>,
+//! # ) {
+//! use wgpu_rt_lidar::scene_handle::SceneHandle;
+//!
+//! let handle = SceneHandle::new(scene);
+//! let tasks: Vec<_> = lidars
+//!     .drain(..)
+//!     .map(|mut lidar| {
+//!         let (handle, device, queue) = (handle.clone(), device.clone(), queue.clone());
+//!         tokio::spawn(async move {
+//!             let scene = handle.read().await;
+//!             lidar
+//!                 .render_lidar_beams(&scene, &device, &queue, &glam::Affine3A::IDENTITY)
+//!                 .await
+//!         })
+//!     })
+//!     .collect();
+//! for task in tasks {
+//!     let ranges = task.await.unwrap();
+//! }
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{depth_camera::DepthCamera, lidar::Lidar, Error, Instance, RayTraceScene};
+
+// Scenes and sensors are shared and sent between threads, keep it that way.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<RayTraceScene>();
+    assert_send_sync::<Lidar>();
+    assert_send_sync::<DepthCamera>();
+    assert_send_sync::<SceneHandle>();
+};
+
+/// A [`RayTraceScene`] shared between threads. Cloning the handle shares the same scene.
+#[derive(Clone)]
+pub struct SceneHandle {
+    scene: Arc<RwLock<RayTraceScene>>,
+}
+
+impl SceneHandle {
+    pub fn new(scene: RayTraceScene) -> Self {
+        Self {
+            scene: Arc::new(RwLock::new(scene)),
+        }
+    }
+
+    /// Locks the scene for rendering. Waits for a pending [`SceneHandle::set_transform`].
+    pub async fn read(&self) -> RwLockReadGuard<'_, RayTraceScene> {
+        self.scene.read().await
+    }
+
+    /// Locks the scene exclusively, e.g. to recreate it after the device was lost.
+    ///
+    /// Transforms changed through the guard are applied by the next render, which records the
+    /// TLAS rebuild itself, so prefer [`SceneHandle::set_transform`] while others render.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, RayTraceScene> {
+        self.scene.write().await
+    }
+
+    /// Updates the transform of instances, see [`RayTraceScene::set_transform`], and rebuilds the
+    /// TLAS before renders waiting for the scene resume.
+    pub async fn set_transform(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        update_instance: &[Instance],
+        idx: &[usize],
+    ) -> Result<(), Error> {
+        let mut scene = self.scene.write().await;
+        scene.set_transform(device, update_instance, idx).await?;
+        scene.submit_tlas_update(device, queue);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_scene_handle_concurrent_renders() {
+    use crate::utils::{create_cube, get_gpu};
+    use glam::{Affine3A, Vec3};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let handle = SceneHandle::new(scene);
+
+    let render_all = |expected: f32| {
+        (1..=4)
+            .map(|i| {
+                let (handle, device, queue) = (handle.clone(), device.clone(), queue.clone());
+                tokio::spawn(async move {
+                    let mut lidar = Lidar::new(&device, vec![Vec3::NEG_X]).await;
+                    let pose = Affine3A::from_translation(Vec3::new(1.0 + i as f32, 0.0, 0.0));
+                    let scene = handle.read().await;
+                    let beams = lidar
+                        .render_lidar_beams(&scene, &device, &queue, &pose)
+                        .await;
+                    assert!((beams[0] - (i as f32 - expected)).abs() < 1e-4);
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    for task in render_all(0.0) {
+        task.await.unwrap();
+    }
+
+    handle
+        .set_transform(
+            &device,
+            &queue,
+            &[Instance {
+                asset_mesh_index: 0,
+                transform: Affine3A::from_translation(Vec3::new(0.5, 0.0, 0.0)),
+                id: 0,
+            }],
+            &[0],
+        )
+        .await
+        .unwrap();
+    for task in render_all(0.5) {
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+//! A compact protobuf schema for storing sensor frames or sending them over sockets.
+//!
+//! [`LidarFrame`] and [`DepthFrame`] are the messages of `proto/frames.proto`, so other languages
+//! decode them with code generated from that file. Frames are encoded one per buffer, or length
+//! delimited to write several to one stream:
+//!
+//! ```no_run
+//! # async fn run(
+//! #     scene: &wgpu_rt_lidar::RayTraceScene,
+//! #     device: &wgpu::Device,
+//! #     queue: &wgpu::Queue,
+//! #     lidar: &mut wgpu_rt_lidar::lidar::Lidar,
+//! #     socket: &mut std::net::TcpStream,
+//! # ) -> std::io::Result<()> {
+//! use std::io::Write;
+//! use wgpu_rt_lidar::schema::LidarFrame;
+//!
+//! let pose = glam::Affine3A::IDENTITY;
+//! let points = lidar.render_lidar_pointcloud(scene, device, queue, pose).await;
+//! socket.write_all(&LidarFrame::new(0, pose, points).encode_delimited())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use glam::{Affine3A, Quat, Vec3};
+use prost::{DecodeError, Message};
+
+use crate::{depth_camera::DepthCamera, pose::IntoAffine3A, Error};
+
+/// A pose without scale.
+#[derive(Clone, Copy, PartialEq, Message)]
+pub struct Pose {
+    #[prost(float, tag = "1")]
+    pub x: f32,
+    #[prost(float, tag = "2")]
+    pub y: f32,
+    #[prost(float, tag = "3")]
+    pub z: f32,
+    #[prost(float, tag = "4")]
+    pub qx: f32,
+    #[prost(float, tag = "5")]
+    pub qy: f32,
+    #[prost(float, tag = "6")]
+    pub qz: f32,
+    #[prost(float, tag = "7")]
+    pub qw: f32,
+}
+
+impl Pose {
+    /// The pose of a transform, dropping its scale.
+    pub fn from_affine(pose: impl IntoAffine3A) -> Self {
+        let (_, rotation, translation) = pose.into_affine3a().to_scale_rotation_translation();
+        Self {
+            x: translation.x,
+            y: translation.y,
+            z: translation.z,
+            qx: rotation.x,
+            qy: rotation.y,
+            qz: rotation.z,
+            qw: rotation.w,
+        }
+    }
+
+    pub fn to_affine(&self) -> Affine3A {
+        Affine3A::from_rotation_translation(
+            Quat::from_xyzw(self.qx, self.qy, self.qz, self.qw).normalize(),
+            Vec3::new(self.x, self.y, self.z),
+        )
+    }
+}
+
+/// A point cloud rendered by a LiDAR.
+#[derive(Clone, PartialEq, Message)]
+pub struct LidarFrame {
+    /// Time of the frame in nanoseconds, in the clock of the producer.
+    #[prost(uint64, tag = "1")]
+    pub timestamp_ns: u64,
+    /// Pose of the sensor in the world.
+    #[prost(message, optional, tag = "2")]
+    pub pose: Option<Pose>,
+    /// The output of [`Lidar::render_lidar_pointcloud`](crate::lidar::Lidar::render_lidar_pointcloud).
+    #[prost(float, repeated, tag = "3")]
+    pub points: Vec<f32>,
+}
+
+impl LidarFrame {
+    pub fn new(timestamp_ns: u64, pose: impl IntoAffine3A, points: Vec<f32>) -> Self {
+        Self {
+            timestamp_ns,
+            pose: Some(Pose::from_affine(pose)),
+            points,
+        }
+    }
+}
+
+/// A depth image rendered by a depth camera.
+#[derive(Clone, PartialEq, Message)]
+pub struct DepthFrame {
+    #[prost(uint64, tag = "1")]
+    pub timestamp_ns: u64,
+    #[prost(message, optional, tag = "2")]
+    pub pose: Option<Pose>,
+    #[prost(uint32, tag = "3")]
+    pub width: u32,
+    #[prost(uint32, tag = "4")]
+    pub height: u32,
+    /// Depths along the optical axis, row by row from the top. NaN without a return.
+    #[prost(float, repeated, tag = "5")]
+    pub depth: Vec<f32>,
+}
+
+impl DepthFrame {
+    /// A frame of the output of [`DepthCamera::render_depth_camera`], see
+    /// [`DepthCamera::optical_depths`].
+    ///
+    /// Fails if `depth` is not an image of `camera`.
+    pub fn from_depth_image(
+        timestamp_ns: u64,
+        pose: impl IntoAffine3A,
+        camera: &DepthCamera,
+        depth: &[f32],
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            timestamp_ns,
+            pose: Some(Pose::from_affine(pose)),
+            width: camera.width(),
+            height: camera.height(),
+            depth: camera.optical_depths(depth)?,
+        })
+    }
+}
+
+macro_rules! impl_codec {
+    ($($frame:ty),*) => {$(
+        impl $frame {
+            /// Encodes the frame as a single message.
+            pub fn encode(&self) -> Vec<u8> {
+                self.encode_to_vec()
+            }
+
+            /// Decodes a frame encoded with `encode`.
+            pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+                Message::decode(bytes)
+            }
+
+            /// Encodes the frame prefixed with its length, to write several frames to a stream.
+            pub fn encode_delimited(&self) -> Vec<u8> {
+                self.encode_length_delimited_to_vec()
+            }
+
+            /// Decodes the next frame encoded with `encode_delimited` and advances `bytes` past
+            /// it.
+            pub fn decode_delimited(bytes: &mut &[u8]) -> Result<Self, DecodeError> {
+                Message::decode_length_delimited(bytes)
+            }
+        }
+    )*};
+}
+
+impl_codec!(LidarFrame, DepthFrame);
+
+#[cfg(test)]
+#[test]
+fn test_frame_round_trip() {
+    let pose =
+        Affine3A::from_rotation_translation(Quat::from_rotation_z(0.5), Vec3::new(1.0, 2.0, 3.0));
+    let lidar = LidarFrame::new(42, pose, vec![1.0, 2.0, 3.0, 4.0, 10000.0, 0.0, 0.0, 0.0]);
+    let depth = DepthFrame {
+        timestamp_ns: 43,
+        pose: Some(Pose::from_affine(pose)),
+        width: 2,
+        height: 1,
+        depth: vec![1.5, f32::NAN],
+    };
+
+    assert_eq!(LidarFrame::decode(&lidar.encode()).unwrap(), lidar);
+    assert!(lidar.pose.unwrap().to_affine().abs_diff_eq(pose, 1e-6));
+
+    let mut stream = lidar.encode_delimited();
+    stream.extend(depth.encode_delimited());
+    let mut bytes = stream.as_slice();
+    assert_eq!(LidarFrame::decode_delimited(&mut bytes).unwrap(), lidar);
+    let decoded = DepthFrame::decode_delimited(&mut bytes).unwrap();
+    assert!(bytes.is_empty());
+    assert_eq!((decoded.width, decoded.height), (2, 1));
+    assert_eq!(decoded.depth[0], 1.5);
+    assert!(decoded.depth[1].is_nan());
+}
@@ -0,0 +1,385 @@
+//! Sensor configurations from Gazebo SDF descriptions.
+//!
+//! [`parse_sensors`] reads every `<sensor>` of an SDF document, e.g. a robot model, and returns
+//! the LiDARs and depth cameras among them as configurations that create the equivalent
+//! sensors, so existing robot descriptions configure the simulated ones.
+//!
+//! ```no_run
+//! # async fn run(device: wgpu::Device, sdf: &str) -> Result<(), wgpu_rt_lidar::Error> {
+//! use wgpu_rt_lidar::sdf::{parse_sensors, SensorConfig};
+//!
+//! for sensor in parse_sensors(sdf)? {
+//!     match sensor {
+//!         SensorConfig::Lidar(config) => {
+//!             let lidar = config.create(&device).await;
+//!         }
+//!         SensorConfig::DepthCamera(config) => {
+//!             let camera = config.create(&device).await;
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `serde` feature the configurations also serialize, so sensors can be set up from
+//! configuration files as well.
+
+use glam::{Affine3A, EulerRot, Mat3, Mat4, Quat, Vec3};
+
+use crate::{
+    depth_camera::DepthCamera,
+    lidar::Lidar,
+    noise::{BiasNoise, CompositeNoise, GaussianNoise},
+    xml::{self, Element},
+    Error,
+};
+
+/// Sensor types read as LiDARs.
+const LIDAR_TYPES: [&str; 4] = ["gpu_lidar", "gpu_ray", "lidar", "ray"];
+/// Sensor types read as depth cameras.
+const DEPTH_CAMERA_TYPES: [&str; 4] = ["depth_camera", "depth", "rgbd_camera", "rgbd"];
+
+/// A sensor described in SDF.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SensorConfig {
+    Lidar(LidarConfig),
+    DepthCamera(DepthCameraConfig),
+}
+
+/// Gaussian noise of an SDF `<noise>` block.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SdfNoise {
+    pub mean: f32,
+    pub stddev: f32,
+}
+
+impl SdfNoise {
+    fn model(&self) -> CompositeNoise {
+        CompositeNoise::new()
+            .with(GaussianNoise {
+                stddev: self.stddev,
+            })
+            .with(BiasNoise { bias: self.mean })
+    }
+}
+
+/// A `gpu_lidar` sensor.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LidarConfig {
+    pub name: String,
+    /// Pose of the sensor relative to its parent.
+    pub pose: Affine3A,
+    /// Scans per second, zero if unset.
+    pub update_rate: f32,
+    /// Beam directions in the frame of the sensor, x forward and z up, row by row.
+    pub ray_directions: Vec<Vec3>,
+    /// Number of rows, i.e. of vertical samples.
+    pub rows: usize,
+    /// Range limits of the description. The sensor traces its own fixed range, so returns
+    /// outside of these are left to the consumer to discard.
+    pub min_range: f32,
+    pub max_range: f32,
+    pub noise: Option<SdfNoise>,
+}
+
+impl LidarConfig {
+    /// Creates the LiDAR, with its minimum range and its noise model if it has one.
+    pub async fn create(&self, device: &wgpu::Device) -> Lidar {
+        let mut lidar = Lidar::new(device, self.ray_directions.clone()).await;
+        lidar.set_min_range(self.min_range);
+        if let Some(noise) = &self.noise {
+            lidar.set_noise_model(noise.model());
+        }
+        lidar
+    }
+}
+
+/// A depth camera sensor.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DepthCameraConfig {
+    pub name: String,
+    /// Pose of the sensor relative to its parent. The camera looks along x with z up.
+    pub pose: Affine3A,
+    /// Frames per second, zero if unset.
+    pub update_rate: f32,
+    pub width: u32,
+    pub height: u32,
+    /// Vertical field of view in degrees, derived from the horizontal one of the description.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+    pub noise: Option<SdfNoise>,
+}
+
+impl DepthCameraConfig {
+    /// Creates the depth camera, with its near clip as minimum range and its noise model if it
+    /// has one.
+    pub async fn create(&self, device: &wgpu::Device) -> DepthCamera {
+        let mut camera =
+            DepthCamera::new(device, self.width, self.height, self.fov_y, self.far).await;
+        camera.set_min_range(self.near);
+        if let Some(noise) = &self.noise {
+            camera.set_noise_model(noise.model());
+        }
+        camera
+    }
+
+    /// The view matrix to render the camera with when its parent is at `parent_pose`.
+    ///
+    /// SDF cameras look along x with z up, while the depth camera looks along -z with y up.
+    pub fn view_matrix(&self, parent_pose: &Affine3A) -> Mat4 {
+        let camera_to_sensor =
+            Affine3A::from_mat3(Mat3::from_cols(Vec3::NEG_Y, Vec3::Z, Vec3::NEG_X));
+        Mat4::from(*parent_pose * self.pose * camera_to_sensor).inverse()
+    }
+}
+
+/// Reads the LiDARs and depth cameras of an SDF document, in document order. Sensors of other
+/// types are skipped.
+///
+/// Fails if the document is not XML or a sensor's parameters are malformed.
+pub fn parse_sensors(sdf: &str) -> Result<Vec<SensorConfig>, Error> {
+    let root = xml::parse(sdf)?;
+    let sensors = if root.name == "sensor" {
+        vec![&root]
+    } else {
+        root.descendants("sensor")
+    };
+    let mut configs = vec![];
+    for sensor in sensors {
+        let kind = sensor.attribute("type").unwrap_or_default();
+        if LIDAR_TYPES.contains(&kind) {
+            configs.push(SensorConfig::Lidar(parse_lidar(sensor)?));
+        } else if DEPTH_CAMERA_TYPES.contains(&kind) {
+            configs.push(SensorConfig::DepthCamera(parse_depth_camera(sensor)?));
+        }
+    }
+    Ok(configs)
+}
+
+fn parse_lidar(sensor: &Element) -> Result<LidarConfig, Error> {
+    // Gazebo Classic names the block <ray>.
+    let lidar = sensor
+        .child("lidar")
+        .or_else(|| sensor.child("ray"))
+        .ok_or_else(|| missing(sensor, "lidar"))?;
+    let horizontal = ScanAxis::parse(lidar, "horizontal")?;
+    let vertical = ScanAxis::parse(lidar, "vertical")?;
+    let mut ray_directions = Vec::with_capacity(horizontal.samples * vertical.samples);
+    for pitch in vertical.angles() {
+        for yaw in horizontal.angles() {
+            ray_directions.push(Vec3::new(
+                pitch.cos() * yaw.cos(),
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+            ));
+        }
+    }
+    Ok(LidarConfig {
+        name: sensor.attribute("name").unwrap_or_default().to_string(),
+        pose: parse_pose(sensor)?,
+        update_rate: sensor.parse_at(&["update_rate"])?.unwrap_or(0.0),
+        ray_directions,
+        rows: vertical.samples,
+        min_range: lidar.parse_at(&["range", "min"])?.unwrap_or(0.0),
+        max_range: lidar.parse_at(&["range", "max"])?.unwrap_or(f32::INFINITY),
+        noise: parse_noise(lidar)?,
+    })
+}
+
+/// The samples of a scan along one axis. Gazebo casts `samples * resolution` rays.
+struct ScanAxis {
+    samples: usize,
+    min_angle: f32,
+    max_angle: f32,
+}
+
+impl ScanAxis {
+    fn parse(lidar: &Element, axis: &str) -> Result<Self, Error> {
+        let samples: usize = lidar.parse_at(&["scan", axis, "samples"])?.unwrap_or(1);
+        let resolution: f32 = lidar
+            .parse_at(&["scan", axis, "resolution"])?
+            .unwrap_or(1.0);
+        Ok(Self {
+            samples: ((samples as f32 * resolution).round() as usize).max(1),
+            min_angle: lidar.parse_at(&["scan", axis, "min_angle"])?.unwrap_or(0.0),
+            max_angle: lidar.parse_at(&["scan", axis, "max_angle"])?.unwrap_or(0.0),
+        })
+    }
+
+    fn angles(&self) -> impl Iterator<Item = f32> + '_ {
+        let step = if self.samples > 1 {
+            (self.max_angle - self.min_angle) / (self.samples - 1) as f32
+        } else {
+            0.0
+        };
+        (0..self.samples).map(move |i| self.min_angle + i as f32 * step)
+    }
+}
+
+fn parse_depth_camera(sensor: &Element) -> Result<DepthCameraConfig, Error> {
+    let camera = sensor
+        .child("camera")
+        .ok_or_else(|| missing(sensor, "camera"))?;
+    let width: u32 = camera.parse_at(&["image", "width"])?.unwrap_or(320);
+    let height: u32 = camera.parse_at(&["image", "height"])?.unwrap_or(240);
+    let fov_x: f32 = camera.parse_at(&["horizontal_fov"])?.unwrap_or(1.047);
+    let fov_y = 2.0 * ((fov_x / 2.0).tan() * height as f32 / width as f32).atan();
+    Ok(DepthCameraConfig {
+        name: sensor.attribute("name").unwrap_or_default().to_string(),
+        pose: parse_pose(sensor)?,
+        update_rate: sensor.parse_at(&["update_rate"])?.unwrap_or(0.0),
+        width,
+        height,
+        fov_y: fov_y.to_degrees(),
+        near: camera.parse_at(&["clip", "near"])?.unwrap_or(0.1),
+        far: camera.parse_at(&["clip", "far"])?.unwrap_or(100.0),
+        noise: parse_noise(camera)?,
+    })
+}
+
+/// Reads `<pose>x y z roll pitch yaw</pose>`, the identity if absent.
+fn parse_pose(sensor: &Element) -> Result<Affine3A, Error> {
+    let Some(text) = sensor.text_at(&["pose"]) else {
+        return Ok(Affine3A::IDENTITY);
+    };
+    let values: Vec<f32> = text
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| Error::InvalidArgument(format!("Invalid <pose>: {text}")))?;
+    let [x, y, z, roll, pitch, yaw] = values[..] else {
+        return Err(Error::InvalidArgument(format!("Invalid <pose>: {text}")));
+    };
+    Ok(Affine3A::from_rotation_translation(
+        Quat::from_euler(EulerRot::ZYX, yaw, pitch, roll),
+        Vec3::new(x, y, z),
+    ))
+}
+
+/// Reads a Gaussian `<noise>` block. Other noise types are ignored.
+fn parse_noise(block: &Element) -> Result<Option<SdfNoise>, Error> {
+    let Some(noise) = block.child("noise") else {
+        return Ok(None);
+    };
+    if !noise
+        .text_at(&["type"])
+        .is_some_and(|t| t.starts_with("gaussian"))
+    {
+        return Ok(None);
+    }
+    Ok(Some(SdfNoise {
+        mean: noise.parse_at(&["mean"])?.unwrap_or(0.0),
+        stddev: noise.parse_at(&["stddev"])?.unwrap_or(0.0),
+    }))
+}
+
+fn missing(sensor: &Element, block: &str) -> Error {
+    Error::InvalidArgument(format!(
+        "Sensor {} has no <{block}> block",
+        sensor.attribute("name").unwrap_or_default()
+    ))
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_sensors() {
+    let sdf = r#"<?xml version="1.0"?>
+    <sdf version="1.9">
+      <model name="robot">
+        <link name="base">
+          <sensor name="front_lidar" type="gpu_lidar">
+            <pose>0 0 0.5 0 0 1.5707963</pose>
+            <update_rate>10</update_rate>
+            <lidar>
+              <scan>
+                <horizontal><samples>4</samples><min_angle>-0.5</min_angle><max_angle>0.5</max_angle></horizontal>
+                <vertical><samples>2</samples><min_angle>-0.1</min_angle><max_angle>0.1</max_angle></vertical>
+              </scan>
+              <range><min>0.08</min><max>30</max></range>
+              <noise><type>gaussian</type><mean>0.01</mean><stddev>0.02</stddev></noise>
+            </lidar>
+          </sensor>
+          <sensor name="imu" type="imu"/>
+          <sensor name="depth" type="depth_camera">
+            <camera>
+              <horizontal_fov>1.5707963</horizontal_fov>
+              <image><width>640</width><height>640</height></image>
+              <clip><near>0.2</near><far>12</far></clip>
+            </camera>
+          </sensor>
+        </link>
+      </model>
+    </sdf>"#;
+    let sensors = parse_sensors(sdf).unwrap();
+    assert_eq!(sensors.len(), 2);
+
+    let SensorConfig::Lidar(lidar) = &sensors[0] else {
+        panic!("Expected a LiDAR");
+    };
+    assert_eq!(lidar.name, "front_lidar");
+    assert_eq!(lidar.update_rate, 10.0);
+    assert_eq!((lidar.ray_directions.len(), lidar.rows), (8, 2));
+    let first = lidar.ray_directions[0];
+    assert!((first.z - (-0.1f32).sin()).abs() < 1e-6);
+    assert!((first.y.atan2(first.x) - -0.5).abs() < 1e-6);
+    assert_eq!((lidar.min_range, lidar.max_range), (0.08, 30.0));
+    assert_eq!(
+        lidar.noise,
+        Some(SdfNoise {
+            mean: 0.01,
+            stddev: 0.02
+        })
+    );
+    assert!(lidar
+        .pose
+        .transform_vector3(Vec3::X)
+        .abs_diff_eq(Vec3::Y, 1e-6));
+
+    let SensorConfig::DepthCamera(camera) = &sensors[1] else {
+        panic!("Expected a depth camera");
+    };
+    assert_eq!((camera.width, camera.height), (640, 640));
+    assert!((camera.fov_y - 90.0).abs() < 1e-3);
+    assert_eq!((camera.near, camera.far), (0.2, 12.0));
+    // The camera looks along x of its pose.
+    let view = camera.view_matrix(&Affine3A::IDENTITY);
+    assert!(view
+        .transform_point3(Vec3::new(2.0, 0.0, 1.0))
+        .abs_diff_eq(Vec3::new(0.0, 1.0, -2.0), 1e-6));
+
+    assert!(parse_sensors(r#"<sensor type="gpu_lidar"/>"#).is_err());
+    assert!(parse_sensors(r#"<sensor type="depth"><pose>1 2</pose><camera/></sensor>"#).is_err());
+}
+
+#[cfg(all(test, feature = "serde"))]
+#[test]
+fn test_config_from_json() {
+    let json = r#"{
+        "Lidar": {
+            "name": "front_lidar",
+            "pose": [1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0.5],
+            "update_rate": 10,
+            "ray_directions": [[1, 0, 0], [0, 1, 0]],
+            "rows": 1,
+            "min_range": 0.1,
+            "max_range": 30,
+            "noise": { "mean": 0, "stddev": 0.02 }
+        }
+    }"#;
+    let SensorConfig::Lidar(config) = serde_json::from_str(json).unwrap() else {
+        panic!("Expected a LiDAR");
+    };
+    assert_eq!(config.pose.translation.z, 0.5);
+    assert_eq!(config.ray_directions, vec![Vec3::X, Vec3::Y]);
+    assert_eq!(config.noise.unwrap().stddev, 0.02);
+
+    let sensor = SensorConfig::Lidar(config);
+    let json = serde_json::to_string(&sensor).unwrap();
+    assert_eq!(serde_json::from_str::<SensorConfig>(&json).unwrap(), sensor);
+}
@@ -0,0 +1,253 @@
+//! A gRPC server rendering the sensors of a scene for remote clients.
+//!
+//! [`SimulationServer`] hosts a scene and named sensors and serves the `SensorSimulation`
+//! service of `proto/sensor_simulation.proto`, so clients in any language with gRPC support
+//! place the sensors and render them:
+//!
+//! ```no_run
+//! # async fn run(
+//! #     context: wgpu_rt_lidar::RenderContext,
+//! #     scene: wgpu_rt_lidar::RayTraceScene,
+//! #     lidar: wgpu_rt_lidar::lidar::Lidar,
+//! # ) -> Result<(), tonic::transport::Error> {
+//! use wgpu_rt_lidar::server::SimulationServer;
+//!
+//! SimulationServer::new(context, scene)
+//!     .with_lidar("lidar", lidar)
+//!     .serve("0.0.0.0:50051".parse().unwrap())
+//!     .await
+//! # }
+//! ```
+//!
+//! Depth cameras look down -z with y up, like [`DepthCamera::render_depth_camera`] with the
+//! inverse of their pose as the view matrix. Building the feature needs `protoc`.
+
+use std::{collections::HashMap, net::SocketAddr};
+
+use glam::{Affine3A, Mat4, Quat, Vec3};
+use tokio::sync::Mutex;
+use tonic::{Request, Response, Status};
+
+use crate::{depth_camera::DepthCamera, lidar::Lidar, RayTraceScene, RenderContext};
+
+/// The messages and service generated from `proto/sensor_simulation.proto`.
+pub mod proto {
+    tonic::include_proto!("wgpu_rt_lidar");
+}
+
+use proto::{
+    sensor_simulation_server::{SensorSimulation, SensorSimulationServer},
+    DepthImage, PointCloud, Pose, RenderRequest, SetPoseReply, SetPoseRequest,
+};
+
+struct State {
+    scene: RayTraceScene,
+    lidars: HashMap<String, (Lidar, Affine3A)>,
+    cameras: HashMap<String, (DepthCamera, Affine3A)>,
+}
+
+/// Serves renders of a scene's sensors over gRPC.
+///
+/// Requests are served one at a time, as renders share the device.
+pub struct SimulationServer {
+    context: RenderContext,
+    state: Mutex<State>,
+}
+
+impl SimulationServer {
+    pub fn new(context: RenderContext, scene: RayTraceScene) -> Self {
+        Self {
+            context,
+            state: Mutex::new(State {
+                scene,
+                lidars: HashMap::new(),
+                cameras: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Adds a LiDAR clients refer to as `name`.
+    pub fn with_lidar(mut self, name: &str, lidar: Lidar) -> Self {
+        self.state
+            .get_mut()
+            .lidars
+            .insert(name.to_string(), (lidar, Affine3A::IDENTITY));
+        self
+    }
+
+    /// Adds a depth camera clients refer to as `name`.
+    pub fn with_depth_camera(mut self, name: &str, camera: DepthCamera) -> Self {
+        self.state
+            .get_mut()
+            .cameras
+            .insert(name.to_string(), (camera, Affine3A::IDENTITY));
+        self
+    }
+
+    /// The service, to serve next to others.
+    pub fn into_service(self) -> SensorSimulationServer<Self> {
+        SensorSimulationServer::new(self)
+    }
+
+    /// Serves the sensors on `address` until the server fails.
+    pub async fn serve(self, address: SocketAddr) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_service())
+            .serve(address)
+            .await
+    }
+}
+
+fn to_affine(pose: &Pose) -> Result<Affine3A, Status> {
+    let rotation = Quat::from_xyzw(pose.qx, pose.qy, pose.qz, pose.qw);
+    if !rotation.is_finite() || rotation.length_squared() < 1e-6 {
+        return Err(Status::invalid_argument("The rotation is not a quaternion"));
+    }
+    Ok(Affine3A::from_rotation_translation(
+        rotation.normalize(),
+        Vec3::new(pose.x, pose.y, pose.z),
+    ))
+}
+
+fn unknown_sensor(name: &str) -> Status {
+    Status::not_found(format!("No sensor named {name}"))
+}
+
+#[tonic::async_trait]
+impl SensorSimulation for SimulationServer {
+    async fn set_pose(
+        &self,
+        request: Request<SetPoseRequest>,
+    ) -> Result<Response<SetPoseReply>, Status> {
+        let request = request.into_inner();
+        let pose = to_affine(&request.pose.unwrap_or_default())?;
+        let mut state = self.state.lock().await;
+        if let Some((_, lidar_pose)) = state.lidars.get_mut(&request.sensor) {
+            *lidar_pose = pose;
+        } else if let Some((_, camera_pose)) = state.cameras.get_mut(&request.sensor) {
+            *camera_pose = pose;
+        } else {
+            return Err(unknown_sensor(&request.sensor));
+        }
+        Ok(Response::new(SetPoseReply {}))
+    }
+
+    async fn render_lidar(
+        &self,
+        request: Request<RenderRequest>,
+    ) -> Result<Response<PointCloud>, Status> {
+        let name = request.into_inner().sensor;
+        let state = &mut *self.state.lock().await;
+        let (lidar, pose) = state
+            .lidars
+            .get_mut(&name)
+            .ok_or_else(|| unknown_sensor(&name))?;
+        let points = self
+            .context
+            .render_lidar_pointcloud(lidar, &state.scene, pose)
+            .await;
+        Ok(Response::new(PointCloud { points }))
+    }
+
+    async fn render_depth(
+        &self,
+        request: Request<RenderRequest>,
+    ) -> Result<Response<DepthImage>, Status> {
+        let name = request.into_inner().sensor;
+        let state = &mut *self.state.lock().await;
+        let (camera, pose) = state
+            .cameras
+            .get_mut(&name)
+            .ok_or_else(|| unknown_sensor(&name))?;
+        let view_matrix = Mat4::from(*pose).inverse();
+        let depth = self
+            .context
+            .render_depth_camera(camera, &state.scene, view_matrix)
+            .await;
+        let (width, height) = (camera.width() as usize, camera.height() as usize);
+        // The camera renders column by column from the bottom.
+        let depth = (0..height)
+            .rev()
+            .flat_map(|y| (0..width).map(move |x| x * height + y))
+            .map(|i| {
+                if depth[i] < DepthCamera::no_hit_const() {
+                    depth[i]
+                } else {
+                    f32::NAN
+                }
+            })
+            .collect();
+        Ok(Response::new(DepthImage {
+            width: camera.width(),
+            height: camera.height(),
+            depth,
+        }))
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_server_renders_posed_sensors() {
+    use crate::{utils::create_cube, Instance};
+
+    let context = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let scene = context
+        .create_scene(
+            &[create_cube(1.0)],
+            &[Instance {
+                asset_mesh_index: 0,
+                transform: Affine3A::IDENTITY,
+                id: 0,
+            }],
+        )
+        .await
+        .unwrap();
+    let lidar = context.create_lidar(vec![Vec3::NEG_X]).await;
+    let camera = context.create_depth_camera(4, 2, 60.0, 100.0).await;
+    let server = SimulationServer::new(context, scene)
+        .with_lidar("lidar", lidar)
+        .with_depth_camera("camera", camera);
+
+    // From 3 m along x the LiDAR sees the face of the cube at 1 m.
+    let pose = || Pose {
+        x: 3.0,
+        qw: 1.0,
+        ..Default::default()
+    };
+    for sensor in ["lidar", "camera"] {
+        server
+            .set_pose(Request::new(SetPoseRequest {
+                sensor: sensor.to_string(),
+                pose: Some(pose()),
+            }))
+            .await
+            .unwrap();
+    }
+    let cloud = server
+        .render_lidar(Request::new(RenderRequest {
+            sensor: "lidar".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!((cloud.points[0] + 2.0).abs() < 1e-3);
+
+    // Looking down -z, away from the cube.
+    let image = server
+        .render_depth(Request::new(RenderRequest {
+            sensor: "camera".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!((image.width, image.height, image.depth.len()), (4, 2, 8));
+    assert!(image.depth.iter().all(|d| d.is_nan()));
+
+    let status = server
+        .render_lidar(Request::new(RenderRequest {
+            sensor: "camera".to_string(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
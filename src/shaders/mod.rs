@@ -0,0 +1,354 @@
+//! The WGSL files the sensor pipelines are built from.
+//!
+//! The files are embedded at compile time. With the `shader-hot-reload` feature they are read
+//! from the crate's source directory instead whenever a pipeline is built, and a
+//! [`ShaderWatcher`] tells sensors when to rebuild theirs, see `Lidar::reload_shaders`.
+//!
+//! Sensors compile their shaders through [`compile`], which turns compilation errors into an
+//! [`Error::ShaderCompilation`] pointing at the offending lines.
+
+use std::borrow::Cow;
+#[cfg(feature = "shader-hot-reload")]
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[cfg(any(test, feature = "shader-hot-reload"))]
+use crate::hit_shader::HitShader;
+use crate::Error;
+
+/// A WGSL file of the crate.
+pub(crate) struct ShaderFile {
+    /// Path relative to `src`.
+    #[cfg_attr(not(feature = "shader-hot-reload"), allow(dead_code))]
+    path: &'static str,
+    embedded: &'static str,
+}
+
+pub(crate) const RNG: ShaderFile = ShaderFile {
+    path: "rng/rng.wgsl",
+    embedded: crate::rng::RNG_WGSL,
+};
+pub(crate) const NOISE: ShaderFile = ShaderFile {
+    path: "noise/noise.wgsl",
+    embedded: crate::noise::NOISE_WGSL,
+};
+pub(crate) const HIT_INFO: ShaderFile = ShaderFile {
+    path: "hit_shader/hit_info.wgsl",
+    embedded: crate::hit_shader::HIT_INFO_WGSL,
+};
+pub(crate) const DEFAULT_HIT: ShaderFile = ShaderFile {
+    path: "hit_shader/default.wgsl",
+    embedded: include_str!("../hit_shader/default.wgsl"),
+};
+pub(crate) const RENDER_STATS: ShaderFile = ShaderFile {
+    path: "render_stats/render_stats.wgsl",
+    embedded: crate::render_stats::RENDER_STATS_WGSL,
+};
+pub(crate) const LIDAR_COMMON: ShaderFile = ShaderFile {
+    path: "lidar/common.wgsl",
+    embedded: include_str!("../lidar/common.wgsl"),
+};
+pub(crate) const LIDAR: ShaderFile = ShaderFile {
+    path: "lidar/shader.wgsl",
+    embedded: include_str!("../lidar/shader.wgsl"),
+};
+pub(crate) const LIDAR_POINTCLOUD: ShaderFile = ShaderFile {
+    path: "lidar/shader.pointcloud.wgsl",
+    embedded: include_str!("../lidar/shader.pointcloud.wgsl"),
+};
+pub(crate) const LIDAR_INSTANCE_IDS: ShaderFile = ShaderFile {
+    path: "lidar/shader.instance_ids.wgsl",
+    embedded: include_str!("../lidar/shader.instance_ids.wgsl"),
+};
+pub(crate) const DEPTH_CAMERA: ShaderFile = ShaderFile {
+    path: "depth_camera/shader.wgsl",
+    embedded: include_str!("../depth_camera/shader.wgsl"),
+};
+pub(crate) const DEPTH_CAMERA_POINTCLOUD: ShaderFile = ShaderFile {
+    path: "depth_camera/shader.pointcloud.wgsl",
+    embedded: include_str!("../depth_camera/shader.pointcloud.wgsl"),
+};
+pub(crate) const DEPTH_CAMERA_INSTANCE_IDS: ShaderFile = ShaderFile {
+    path: "depth_camera/shader.instance_ids.wgsl",
+    embedded: include_str!("../depth_camera/shader.instance_ids.wgsl"),
+};
+
+impl ShaderFile {
+    /// Returns the current source, from disk if hot reloading and the file is readable.
+    pub(crate) fn source(&self) -> Cow<'static, str> {
+        #[cfg(feature = "shader-hot-reload")]
+        if let Ok(source) = std::fs::read_to_string(self.disk_path()) {
+            return Cow::Owned(source);
+        }
+        Cow::Borrowed(self.embedded)
+    }
+
+    #[cfg(feature = "shader-hot-reload")]
+    pub(crate) fn disk_path(&self) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join(self.path)
+    }
+}
+
+/// Compiles WGSL `source`, failing with its diagnostics if it does not compile.
+pub(crate) async fn compile(
+    device: &wgpu::Device,
+    label: &str,
+    source: &str,
+) -> Result<wgpu::ShaderModule, Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+    });
+    let info = module.get_compilation_info().await;
+    let scope = device.pop_error_scope().await;
+    let errors: Vec<_> = info
+        .messages
+        .iter()
+        .filter(|m| m.message_type == wgpu::CompilationMessageType::Error)
+        .collect();
+    if !errors.is_empty() {
+        return Err(Error::ShaderCompilation {
+            label: label.to_string(),
+            diagnostics: format_diagnostics(label, source, &errors),
+        });
+    }
+    match scope {
+        // Backends that do not report compilation info still fail the scope.
+        Some(error) => Err(Error::ShaderCompilation {
+            label: label.to_string(),
+            diagnostics: error.to_string(),
+        }),
+        None => Ok(module),
+    }
+}
+
+/// Renders compilation messages as `label:line:column: message`, followed by the offending
+/// source line unless the message quotes it already.
+fn format_diagnostics(label: &str, source: &str, messages: &[&wgpu::CompilationMessage]) -> String {
+    let mut out = String::new();
+    for message in messages {
+        let text = message.message.trim();
+        let Some(location) = message.location else {
+            out.push_str(&format!("{label}: {text}\n"));
+            continue;
+        };
+        out.push_str(&format!(
+            "{label}:{}:{}: {text}\n",
+            location.line_number, location.line_position
+        ));
+        let line = source
+            .lines()
+            .nth(location.line_number.saturating_sub(1) as usize)
+            .unwrap_or_default();
+        if line.trim().is_empty() || text.contains(line.trim()) {
+            continue;
+        }
+        let gutter = location.line_number.to_string();
+        let column = location.line_position.saturating_sub(1) as usize;
+        let width = (location.length as usize).clamp(1, line.len().saturating_sub(column).max(1));
+        out.push_str(&format!(
+            "{gutter} | {line}\n{} | {}{}\n",
+            " ".repeat(gutter.len()),
+            " ".repeat(column),
+            "^".repeat(width)
+        ));
+    }
+    out
+}
+
+/// Tracks the modification times of shader files.
+#[cfg(feature = "shader-hot-reload")]
+#[derive(Debug)]
+pub(crate) struct ShaderWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+#[cfg(feature = "shader-hot-reload")]
+impl ShaderWatcher {
+    pub(crate) fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            files: paths
+                .into_iter()
+                .map(|path| {
+                    let modified = modified(&path);
+                    (path, modified)
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns true if a file changed since the last call, or since the watcher was created.
+    pub(crate) fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last {
+                *last = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Watches the shader files of a sensor's pipelines, its hit shader's included.
+#[cfg(feature = "shader-hot-reload")]
+#[derive(Debug)]
+pub(crate) struct HotReload {
+    watcher: ShaderWatcher,
+    hit_shader: HitShader,
+}
+
+#[cfg(feature = "shader-hot-reload")]
+impl HotReload {
+    pub(crate) fn new(files: &[ShaderFile], hit_shader: &HitShader) -> Self {
+        let paths = files
+            .iter()
+            .map(ShaderFile::disk_path)
+            .chain(hit_shader.path().map(Path::to_path_buf));
+        Self {
+            watcher: ShaderWatcher::new(paths),
+            hit_shader: hit_shader.clone(),
+        }
+    }
+
+    /// Returns the hit shader to rebuild the pipelines with if a file changed since the last call.
+    pub(crate) fn poll(&mut self) -> Option<&HitShader> {
+        if !self.watcher.changed() {
+            return None;
+        }
+        self.hit_shader = self.hit_shader.reloaded();
+        Some(&self.hit_shader)
+    }
+}
+
+#[cfg(feature = "shader-hot-reload")]
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(all(test, feature = "shader-hot-reload"))]
+#[test]
+fn test_shader_watcher_detects_changes() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("watch.wgsl");
+    std::fs::write(&path, "fn a() {}").unwrap();
+    let mut watcher = ShaderWatcher::new([path.clone()]);
+    assert!(!watcher.changed());
+
+    // Some file systems only keep whole seconds.
+    let later = SystemTime::now() + std::time::Duration::from_secs(2);
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    assert!(watcher.changed());
+    assert!(!watcher.changed());
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(watcher.changed());
+}
+
+#[cfg(all(test, feature = "shader-hot-reload"))]
+#[test]
+fn test_shader_files_exist_on_disk() {
+    for file in [
+        RNG,
+        NOISE,
+        HIT_INFO,
+        DEFAULT_HIT,
+        RENDER_STATS,
+        LIDAR_COMMON,
+        LIDAR,
+        LIDAR_POINTCLOUD,
+        LIDAR_INSTANCE_IDS,
+        DEPTH_CAMERA,
+        DEPTH_CAMERA_POINTCLOUD,
+        DEPTH_CAMERA_INSTANCE_IDS,
+    ] {
+        assert_eq!(file.source(), file.embedded, "{}", file.path);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_format_diagnostics_points_at_source() {
+    let source = "fn main() {\n    let x = y;\n}";
+    let message = wgpu::CompilationMessage {
+        message: "no definition in scope for identifier: `y`".to_string(),
+        message_type: wgpu::CompilationMessageType::Error,
+        location: Some(wgpu::SourceLocation {
+            line_number: 2,
+            line_position: 13,
+            offset: 24,
+            length: 1,
+        }),
+    };
+    assert_eq!(
+        format_diagnostics("lidar", source, &[&message]),
+        "lidar:2:13: no definition in scope for identifier: `y`\n\
+         2 |     let x = y;\n  \
+           |             ^\n"
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_compile_reports_diagnostics() {
+    let instance = wgpu::Instance::default();
+    let (_, device, _) = crate::utils::get_gpu(&instance).await;
+    let error = compile(&device, "broken", "fn f() -> f32 {\n    return y;\n}")
+        .await
+        .unwrap_err();
+    let Error::ShaderCompilation { label, diagnostics } = error else {
+        panic!("Expected a compilation error, got {error}");
+    };
+    assert_eq!(label, "broken");
+    assert!(diagnostics.starts_with("broken:2:12:"), "{diagnostics}");
+
+    assert!(
+        compile(&device, "valid", "fn f() -> f32 {\n    return 1.0;\n}")
+            .await
+            .is_ok()
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_sensor_shaders_validate() {
+    use wgpu::naga::{
+        front::wgsl,
+        valid::{Capabilities, ValidationFlags, Validator},
+    };
+
+    let hit_shader = HitShader::default();
+    let prelude = [
+        RNG.source(),
+        NOISE.source(),
+        RENDER_STATS.source(),
+        LIDAR_COMMON.source(),
+    ];
+    let prelude: Vec<&str> = prelude.iter().map(|s| s.as_ref()).collect();
+    let sources = [
+        [prelude.join("\n"), LIDAR.source().into_owned()].join("\n"),
+        hit_shader.compose(&prelude, &LIDAR_POINTCLOUD.source()),
+        [prelude.join("\n"), LIDAR_INSTANCE_IDS.source().into_owned()].join("\n"),
+        [RNG.source(), NOISE.source(), DEPTH_CAMERA.source()].join("\n"),
+        hit_shader.compose(&[], &DEPTH_CAMERA_POINTCLOUD.source()),
+        DEPTH_CAMERA_INSTANCE_IDS.source().into_owned(),
+    ];
+    for source in sources {
+        let module =
+            wgsl::parse_str(&source).unwrap_or_else(|e| panic!("{}", e.emit_to_string(&source)));
+        Validator::new(ValidationFlags::all(), Capabilities::RAY_QUERY)
+            .validate(&module)
+            .unwrap_or_else(|e| panic!("{}", e.emit_to_string(&source)));
+    }
+}
@@ -0,0 +1,731 @@
+//! Robots described in URDF, their links placed in a scene by joint positions.
+//!
+//! A [`UrdfRobot`] loads the geometry of every link as assets and keeps the kinematic tree of
+//! the joints. Adding it to a scene places an instance per piece of geometry at the link poses
+//! of some joint positions, and new joint positions move the instances, e.g. as a simulated
+//! arm sweeps through the field of view of its own sensors:
+//!
+//! ```no_run
+//! # async fn run(ctx: &wgpu_rt_lidar::RenderContext) -> Result<(), wgpu_rt_lidar::Error> {
+//! use glam::Affine3A;
+//! use wgpu_rt_lidar::{
+//!     urdf::{LinkGeometry, UrdfRobot},
+//!     RayTraceScene,
+//! };
+//!
+//! let robot = UrdfRobot::from_file("robot.urdf", LinkGeometry::Visual)?;
+//! let mut positions = vec![0.0; robot.joint_names().len()];
+//! let mut builder = RayTraceScene::builder();
+//! let placed = robot.add_to(&mut builder, Affine3A::IDENTITY, &positions)?;
+//! let mut scene = builder.build(ctx).await?;
+//!
+//! positions[0] = 0.5;
+//! robot
+//!     .set_joint_positions(&mut scene, ctx.device(), &placed, Affine3A::IDENTITY, &positions)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use glam::{Affine3A, EulerRot, Quat, Vec3};
+use urdf_rs::{Geometry, JointType};
+
+use crate::{
+    pose::IntoAffine3A,
+    scene_builder::RayTraceSceneBuilder,
+    utils::{create_cube, create_cylinder, create_sphere, load_obj, load_stl},
+    AssetMesh, Error, Instance, RayTraceScene, RenderContext,
+};
+
+/// Segments around the axis of the cylinders, capsules and spheres of links.
+const ROUND_SLICES: u16 = 24;
+/// Segments from pole to pole of the spheres of links.
+const SPHERE_STACKS: u16 = 12;
+
+/// Which geometry of the links to load, see [`UrdfRobot::from_file`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkGeometry {
+    /// The `<visual>` geometry, as sensors see the robot.
+    Visual,
+    /// The `<collision>` geometry, e.g. of robots whose visual meshes are COLLADA files.
+    Collision,
+}
+
+/// How a joint moves its child link, by the position of a driving joint scaled and offset as
+/// for `<mimic>` joints.
+struct Drive {
+    prismatic: bool,
+    axis: Vec3,
+    /// The index of the driving joint in the joint positions.
+    state: usize,
+    multiplier: f32,
+    offset: f32,
+}
+
+/// A joint between two links.
+struct Joint {
+    parent: usize,
+    child: usize,
+    origin: Affine3A,
+    /// `None` for fixed joints, and for floating, planar and spherical ones, which stay at
+    /// their origin.
+    drive: Option<Drive>,
+}
+
+/// A piece of the geometry of a link.
+struct Part {
+    link: usize,
+    asset: usize,
+    /// The transform of the asset relative to the link.
+    transform: Affine3A,
+}
+
+/// A robot loaded from URDF, see the [module documentation](self).
+pub struct UrdfRobot {
+    links: Vec<String>,
+    /// Ordered so the pose of each parent link is known before the joints to its children.
+    joints: Vec<Joint>,
+    joint_names: Vec<String>,
+    assets: Vec<AssetMesh>,
+    parts: Vec<Part>,
+}
+
+/// The instances of a [`UrdfRobot`] placed in a scene, see [`UrdfRobot::add_to`].
+#[derive(Clone, Debug)]
+pub struct PlacedRobot {
+    first_asset: usize,
+    instances: Vec<usize>,
+}
+
+impl PlacedRobot {
+    /// The indices of the instances of the robot in the scene.
+    pub fn instances(&self) -> &[usize] {
+        &self.instances
+    }
+}
+
+impl UrdfRobot {
+    /// Loads a robot from a URDF file.
+    ///
+    /// Relative mesh paths are resolved from the directory of the file, `package://` ones from
+    /// the ancestor of that directory named like the package, or else from the packages on
+    /// `ROS_PACKAGE_PATH` and `AMENT_PREFIX_PATH`. Meshes must be STL or OBJ files, see
+    /// [`crate::utils::load_stl`]; load the [`LinkGeometry::Collision`] geometry of robots
+    /// whose visual meshes are not. Each mesh file is loaded once, however many links use it,
+    /// and boxes, cylinders, spheres and capsules share one asset per shape.
+    ///
+    /// The revolute, continuous and prismatic joints that mimic no other are driven by the
+    /// joint positions, see [`UrdfRobot::joint_names`]. Fails with [`Error::Io`] if a file can
+    /// not be read and with [`Error::InvalidArgument`] if the robot is not valid URDF, e.g. has
+    /// a link with two parents, or a mesh is missing or in another format.
+    pub fn from_file(path: impl AsRef<Path>, geometry: LinkGeometry) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let urdf = std::fs::read_to_string(path)?;
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Self::parse(&urdf, &directory, geometry)
+    }
+
+    /// Loads a robot from a URDF document like [`UrdfRobot::from_file`], resolving relative
+    /// mesh paths from `directory`.
+    pub fn parse(urdf: &str, directory: &Path, geometry: LinkGeometry) -> Result<Self, Error> {
+        let robot = urdf_rs::read_from_string(urdf)
+            .map_err(|e| Error::InvalidArgument(format!("Invalid URDF: {e}")))?;
+        let links: Vec<String> = robot.links.iter().map(|link| link.name.clone()).collect();
+        let link_index = |name: &str| {
+            links.iter().position(|link| link == name).ok_or_else(|| {
+                Error::InvalidArgument(format!("URDF joint refers to missing link {name}"))
+            })
+        };
+
+        let driven = |joint: &urdf_rs::Joint| {
+            joint.mimic.is_none()
+                && matches!(
+                    joint.joint_type,
+                    JointType::Revolute | JointType::Continuous | JointType::Prismatic
+                )
+        };
+        let joint_names: Vec<String> = robot
+            .joints
+            .iter()
+            .filter(|joint| driven(joint))
+            .map(|joint| joint.name.clone())
+            .collect();
+
+        let mut joints = vec![];
+        let mut parents = vec![None; links.len()];
+        for joint in &robot.joints {
+            let (parent, child) = (
+                link_index(&joint.parent.link)?,
+                link_index(&joint.child.link)?,
+            );
+            if parents[child].replace(joints.len()).is_some() {
+                return Err(Error::InvalidArgument(format!(
+                    "URDF link {} has two parent joints",
+                    links[child]
+                )));
+            }
+            let drive = match joint.joint_type {
+                JointType::Revolute | JointType::Continuous | JointType::Prismatic => {
+                    let (state, multiplier, offset) = driving_joint(&robot, joint, &joint_names)?;
+                    Some(Drive {
+                        prismatic: matches!(joint.joint_type, JointType::Prismatic),
+                        axis: vec3(&joint.axis.xyz).normalize_or(Vec3::X),
+                        state,
+                        multiplier,
+                        offset,
+                    })
+                }
+                _ => None,
+            };
+            joints.push(Joint {
+                parent,
+                child,
+                origin: pose(&joint.origin),
+                drive,
+            });
+        }
+        let joints = tree_order(joints, &parents)?;
+
+        let mut assets = Assets::default();
+        let mut parts = vec![];
+        for (link, urdf_link) in robot.links.iter().enumerate() {
+            let geometries: Vec<(&urdf_rs::Pose, &Geometry)> = match geometry {
+                LinkGeometry::Visual => urdf_link
+                    .visual
+                    .iter()
+                    .map(|visual| (&visual.origin, &visual.geometry))
+                    .collect(),
+                LinkGeometry::Collision => urdf_link
+                    .collision
+                    .iter()
+                    .map(|collision| (&collision.origin, &collision.geometry))
+                    .collect(),
+            };
+            for (origin, geometry) in geometries {
+                let origin = pose(origin);
+                for (asset, transform) in assets.load(geometry, directory)? {
+                    parts.push(Part {
+                        link,
+                        asset,
+                        transform: origin * transform,
+                    });
+                }
+            }
+        }
+
+        Ok(Self {
+            links,
+            joints,
+            joint_names,
+            assets: assets.assets,
+            parts,
+        })
+    }
+
+    /// The names of the joints in the order of joint positions, as in a `JointState` message.
+    /// Positions are in radians for revolute joints and in meters for prismatic ones.
+    pub fn joint_names(&self) -> &[String] {
+        &self.joint_names
+    }
+
+    /// The names of the links.
+    pub fn link_names(&self) -> &[String] {
+        &self.links
+    }
+
+    /// The assets of the geometry of the links.
+    pub fn assets(&self) -> &[AssetMesh] {
+        &self.assets
+    }
+
+    /// The pose of every link, in the order of [`UrdfRobot::link_names`], with the root links
+    /// at `base`.
+    ///
+    /// Fails with [`Error::InvalidArgument`] unless there is a position for each joint of
+    /// [`UrdfRobot::joint_names`].
+    pub fn link_poses(
+        &self,
+        base: impl IntoAffine3A,
+        joint_positions: &[f32],
+    ) -> Result<Vec<Affine3A>, Error> {
+        if joint_positions.len() != self.joint_names.len() {
+            return Err(Error::InvalidArgument(format!(
+                "Expected {} joint positions, got {}",
+                self.joint_names.len(),
+                joint_positions.len()
+            )));
+        }
+        let mut poses = vec![base.into_affine3a(); self.links.len()];
+        for joint in &self.joints {
+            let motion = joint.drive.as_ref().map_or(Affine3A::IDENTITY, |drive| {
+                let position = drive.multiplier * joint_positions[drive.state] + drive.offset;
+                if drive.prismatic {
+                    Affine3A::from_translation(drive.axis * position)
+                } else {
+                    Affine3A::from_axis_angle(drive.axis, position)
+                }
+            });
+            poses[joint.child] = poses[joint.parent] * joint.origin * motion;
+        }
+        Ok(poses)
+    }
+
+    /// The pose of the link named `link`, e.g. of a sensor mounted on the robot, see
+    /// [`UrdfRobot::link_poses`]. Fails with [`Error::InvalidArgument`] if there is no such
+    /// link.
+    pub fn link_pose(
+        &self,
+        link: &str,
+        base: impl IntoAffine3A,
+        joint_positions: &[f32],
+    ) -> Result<Affine3A, Error> {
+        let index = self
+            .links
+            .iter()
+            .position(|name| name == link)
+            .ok_or_else(|| Error::InvalidArgument(format!("No URDF link {link}")))?;
+        Ok(self.link_poses(base, joint_positions)?[index])
+    }
+
+    /// An instance of the assets for each piece of geometry of the links, placed at the link
+    /// poses of [`UrdfRobot::link_poses`]. The id of each instance is the index of its link,
+    /// see [`Instance::id`].
+    pub fn instances(
+        &self,
+        base: impl IntoAffine3A,
+        joint_positions: &[f32],
+    ) -> Result<Vec<Instance>, Error> {
+        let poses = self.link_poses(base, joint_positions)?;
+        Ok(self
+            .parts
+            .iter()
+            .map(|part| Instance {
+                asset_mesh_index: part.asset,
+                transform: poses[part.link] * part.transform,
+                id: part.link as u32,
+            })
+            .collect())
+    }
+
+    /// Adds the assets of the robot to `builder` with their instances at the link poses of the
+    /// joint positions, e.g. next to the world the robot moves through.
+    pub fn add_to(
+        &self,
+        builder: &mut RayTraceSceneBuilder,
+        base: impl IntoAffine3A,
+        joint_positions: &[f32],
+    ) -> Result<PlacedRobot, Error> {
+        let instances = self.instances(base, joint_positions)?;
+        let first_asset = builder.asset_count();
+        for asset in &self.assets {
+            builder.add_asset(asset.clone());
+        }
+        let instances = instances
+            .into_iter()
+            .map(|instance| {
+                builder.add_instance(Instance {
+                    asset_mesh_index: first_asset + instance.asset_mesh_index,
+                    ..instance
+                })
+            })
+            .collect();
+        Ok(PlacedRobot {
+            first_asset,
+            instances,
+        })
+    }
+
+    /// Creates a scene of the robot alone, see [`UrdfRobot::add_to`].
+    pub async fn create_scene(
+        &self,
+        ctx: &RenderContext,
+        base: impl IntoAffine3A,
+        joint_positions: &[f32],
+    ) -> Result<(RayTraceScene, PlacedRobot), Error> {
+        let mut builder = RayTraceScene::builder();
+        let placed = self.add_to(&mut builder, base, joint_positions)?;
+        Ok((builder.build(ctx).await?, placed))
+    }
+
+    /// Moves the instances of the robot placed in `scene` to the link poses of new joint
+    /// positions, see [`RayTraceScene::set_transform`]. Only the links that moved touch the
+    /// TLAS.
+    pub async fn set_joint_positions(
+        &self,
+        scene: &mut RayTraceScene,
+        device: &wgpu::Device,
+        placed: &PlacedRobot,
+        base: impl IntoAffine3A,
+        joint_positions: &[f32],
+    ) -> Result<(), Error> {
+        let instances: Vec<_> = self
+            .instances(base, joint_positions)?
+            .into_iter()
+            .map(|instance| Instance {
+                asset_mesh_index: placed.first_asset + instance.asset_mesh_index,
+                ..instance
+            })
+            .collect();
+        scene
+            .set_transform(device, &instances, &placed.instances)
+            .await
+    }
+}
+
+/// The index of the joint position driving `joint`, and the multiplier and offset to apply
+/// to it, following chains of `<mimic>` joints.
+fn driving_joint(
+    robot: &urdf_rs::Robot,
+    joint: &urdf_rs::Joint,
+    joint_names: &[String],
+) -> Result<(usize, f32, f32), Error> {
+    let (mut multiplier, mut offset) = (1.0, 0.0);
+    let mut current = joint;
+    // A chain longer than the joints loops.
+    for _ in 0..=robot.joints.len() {
+        let Some(mimic) = &current.mimic else {
+            let state = joint_names
+                .iter()
+                .position(|name| *name == current.name)
+                .ok_or_else(|| {
+                    Error::InvalidArgument(format!(
+                        "URDF joint {} mimics joint {}, which is not driven",
+                        joint.name, current.name
+                    ))
+                })?;
+            return Ok((state, multiplier, offset));
+        };
+        // The position of `joint` is `m * leader + o`, with the leader driven in turn.
+        let m = mimic.multiplier.unwrap_or(1.0) as f32;
+        offset += multiplier * mimic.offset.unwrap_or(0.0) as f32;
+        multiplier *= m;
+        current = robot
+            .joints
+            .iter()
+            .find(|leader| leader.name == mimic.joint)
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "URDF joint {} mimics missing joint {}",
+                    current.name, mimic.joint
+                ))
+            })?;
+    }
+    Err(Error::InvalidArgument(format!(
+        "URDF joint {} is part of a loop of mimic joints",
+        joint.name
+    )))
+}
+
+/// Orders `joints` from the root links down, given the joint to the parent of each link.
+fn tree_order(joints: Vec<Joint>, parents: &[Option<usize>]) -> Result<Vec<Joint>, Error> {
+    let mut children = vec![vec![]; parents.len()];
+    for (index, joint) in joints.iter().enumerate() {
+        children[joint.parent].push(index);
+    }
+    let mut order = vec![];
+    let mut links: Vec<usize> = (0..parents.len())
+        .filter(|&link| parents[link].is_none())
+        .collect();
+    while let Some(link) = links.pop() {
+        for &joint in &children[link] {
+            order.push(joint);
+            links.push(joints[joint].child);
+        }
+    }
+    if order.len() != joints.len() {
+        return Err(Error::InvalidArgument(
+            "URDF joints form a loop".to_string(),
+        ));
+    }
+    let mut joints: Vec<_> = joints.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .map(|index| joints[index].take().unwrap())
+        .collect())
+}
+
+/// The assets of the geometry of a robot, with each mesh file and shape loaded once.
+#[derive(Default)]
+struct Assets {
+    assets: Vec<AssetMesh>,
+    meshes: HashMap<PathBuf, Vec<usize>>,
+    shapes: HashMap<&'static str, usize>,
+}
+
+impl Assets {
+    /// The assets of `geometry` and their transforms relative to its origin.
+    fn load(
+        &mut self,
+        geometry: &Geometry,
+        directory: &Path,
+    ) -> Result<Vec<(usize, Affine3A)>, Error> {
+        Ok(match geometry {
+            Geometry::Box { size } => vec![(
+                self.shape("box", || create_cube(0.5)),
+                Affine3A::from_scale(vec3(size)),
+            )],
+            Geometry::Cylinder { radius, length } => vec![(
+                self.shape("cylinder", || create_cylinder(1.0, 1.0, ROUND_SLICES)),
+                Affine3A::from_scale(Vec3::new(*radius as f32, *radius as f32, *length as f32)),
+            )],
+            Geometry::Sphere { radius } => vec![(
+                self.shape("sphere", || create_sphere(1.0, ROUND_SLICES, SPHERE_STACKS)),
+                Affine3A::from_scale(Vec3::splat(*radius as f32)),
+            )],
+            // A cylinder capped by a sphere at each end.
+            Geometry::Capsule { radius, length } => {
+                let (radius, length) = (*radius as f32, *length as f32);
+                let cylinder = self.shape("cylinder", || create_cylinder(1.0, 1.0, ROUND_SLICES));
+                let sphere =
+                    self.shape("sphere", || create_sphere(1.0, ROUND_SLICES, SPHERE_STACKS));
+                let cap = |z: f32| {
+                    Affine3A::from_scale_rotation_translation(
+                        Vec3::splat(radius),
+                        Quat::IDENTITY,
+                        Vec3::new(0.0, 0.0, z),
+                    )
+                };
+                vec![
+                    (
+                        cylinder,
+                        Affine3A::from_scale(Vec3::new(radius, radius, length)),
+                    ),
+                    (sphere, cap(length / 2.0)),
+                    (sphere, cap(-length / 2.0)),
+                ]
+            }
+            Geometry::Mesh { filename, scale } => {
+                let scale = Affine3A::from_scale(scale.as_ref().map_or(Vec3::ONE, |s| vec3(s)));
+                let path = resolve_mesh(filename, directory)?;
+                if !self.meshes.contains_key(&path) {
+                    let assets = load_mesh(&path)?;
+                    let first = self.assets.len();
+                    self.assets.extend(assets);
+                    self.meshes
+                        .insert(path.clone(), (first..self.assets.len()).collect());
+                }
+                self.meshes[&path]
+                    .iter()
+                    .map(|&asset| (asset, scale))
+                    .collect()
+            }
+        })
+    }
+
+    /// The asset of a unit shape, created the first time it is used.
+    fn shape(&mut self, name: &'static str, create: impl FnOnce() -> AssetMesh) -> usize {
+        *self.shapes.entry(name).or_insert_with(|| {
+            self.assets.push(create());
+            self.assets.len() - 1
+        })
+    }
+}
+
+/// Loads the assets of an STL or OBJ mesh.
+fn load_mesh(path: &Path) -> Result<Vec<AssetMesh>, Error> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("stl") => load_stl(path),
+        Some("obj") => load_obj(path),
+        _ => Err(Error::InvalidArgument(format!(
+            "Unsupported URDF mesh {}, convert it to STL or OBJ or load the collision geometry",
+            path.display()
+        ))),
+    }
+}
+
+/// Resolves the `filename` of a URDF mesh to a path, see [`UrdfRobot::from_file`].
+fn resolve_mesh(filename: &str, directory: &Path) -> Result<PathBuf, Error> {
+    if let Some(path) = filename.strip_prefix("file://") {
+        return Ok(PathBuf::from(path));
+    }
+    let Some(package_path) = filename.strip_prefix("package://") else {
+        return Ok(directory.join(filename));
+    };
+    let (package, path) = package_path
+        .split_once('/')
+        .ok_or_else(|| Error::InvalidArgument(format!("Invalid URDF mesh {filename}")))?;
+    let ancestor = directory
+        .ancestors()
+        .find(|ancestor| ancestor.file_name().is_some_and(|name| name == package))
+        .map(Path::to_path_buf);
+    let env_paths = |variable: &str| {
+        std::env::var_os(variable)
+            .map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    let ros_packages = env_paths("ROS_PACKAGE_PATH")
+        .into_iter()
+        .map(|directory| directory.join(package));
+    let ament_packages = env_paths("AMENT_PREFIX_PATH")
+        .into_iter()
+        .map(|prefix| prefix.join("share").join(package));
+    ancestor
+        .into_iter()
+        .chain(
+            ros_packages
+                .chain(ament_packages)
+                .filter(|package| package.is_dir()),
+        )
+        .next()
+        .map(|package| package.join(path))
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "URDF package {package} of {filename} is not on ROS_PACKAGE_PATH or \
+                 AMENT_PREFIX_PATH"
+            ))
+        })
+}
+
+fn vec3(v: &[f64; 3]) -> Vec3 {
+    Vec3::new(v[0] as f32, v[1] as f32, v[2] as f32)
+}
+
+/// The transform of an `<origin xyz="..." rpy="..."/>`, rotating about the fixed x, y and z
+/// axes in turn.
+fn pose(origin: &urdf_rs::Pose) -> Affine3A {
+    let [roll, pitch, yaw] = *origin.rpy;
+    Affine3A::from_rotation_translation(
+        Quat::from_euler(EulerRot::ZYX, yaw as f32, pitch as f32, roll as f32),
+        vec3(&origin.xyz),
+    )
+}
+
+/// A base box carrying an arm on a revolute joint, with a gripper finger mimicking the arm and
+/// a sensor on a fixed joint.
+#[cfg(test)]
+const TEST_ROBOT: &str = r#"
+<robot name="arm">
+  <link name="base">
+    <visual><geometry><box size="1 1 0.5"/></geometry></visual>
+  </link>
+  <link name="arm">
+    <visual>
+      <origin xyz="0.5 0 0" rpy="0 1.5707963 0"/>
+      <geometry><cylinder radius="0.1" length="1"/></geometry>
+    </visual>
+  </link>
+  <link name="finger">
+    <visual><geometry><sphere radius="0.05"/></geometry></visual>
+  </link>
+  <link name="lidar"/>
+  <joint name="shoulder" type="revolute">
+    <parent link="base"/>
+    <child link="arm"/>
+    <origin xyz="0 0 0.25"/>
+    <axis xyz="0 0 1"/>
+    <limit lower="-3" upper="3" effort="1" velocity="1"/>
+  </joint>
+  <joint name="finger" type="prismatic">
+    <parent link="arm"/>
+    <child link="finger"/>
+    <origin xyz="1 0 0"/>
+    <axis xyz="1 0 0"/>
+    <limit lower="0" upper="1" effort="1" velocity="1"/>
+    <mimic joint="shoulder" multiplier="0.5" offset="0.1"/>
+  </joint>
+  <joint name="lidar_mount" type="fixed">
+    <parent link="base"/>
+    <child link="lidar"/>
+    <origin xyz="0 0 1" rpy="0 0 3.1415927"/>
+  </joint>
+</robot>
+"#;
+
+#[cfg(test)]
+#[test]
+fn test_urdf_link_poses() {
+    use std::f32::consts::FRAC_PI_2;
+
+    let robot = UrdfRobot::parse(TEST_ROBOT, Path::new("."), LinkGeometry::Visual).unwrap();
+    assert_eq!(robot.joint_names(), ["shoulder"]);
+    // A box, a cylinder and a sphere, one instance each.
+    assert_eq!(robot.assets().len(), 3);
+
+    let base = Affine3A::from_translation(Vec3::new(10.0, 0.0, 0.0));
+    let poses = robot.link_poses(base, &[FRAC_PI_2]).unwrap();
+    let origins: Vec<Vec3> = poses.iter().map(|pose| pose.translation.into()).collect();
+    assert!(origins[0].abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-5));
+    assert!(origins[1].abs_diff_eq(Vec3::new(10.0, 0.0, 0.25), 1e-5));
+    // The arm points along y, the finger slid out by 0.5 * pi / 2 + 0.1 along it.
+    let finger = 1.0 + 0.5 * FRAC_PI_2 + 0.1;
+    assert!(origins[2].abs_diff_eq(Vec3::new(10.0, finger, 0.25), 1e-5));
+    let lidar = robot.link_pose("lidar", base, &[FRAC_PI_2]).unwrap();
+    assert!(lidar
+        .transform_vector3(Vec3::X)
+        .abs_diff_eq(Vec3::NEG_X, 1e-5));
+
+    let instances = robot.instances(base, &[FRAC_PI_2]).unwrap();
+    assert_eq!(instances.len(), 3);
+    assert_eq!(instances[1].id, 1);
+    // The arm cylinder is centered half way along the arm.
+    let arm: Vec3 = instances[1].transform.translation.into();
+    assert!(arm.abs_diff_eq(Vec3::new(10.0, 0.5, 0.25), 1e-5));
+
+    assert!(matches!(
+        robot.link_poses(base, &[]),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        robot.link_pose("wheel", base, &[0.0]),
+        Err(Error::InvalidArgument(_))
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn test_urdf_invalid_robots() {
+    let parse = |urdf: &str| UrdfRobot::parse(urdf, Path::new("."), LinkGeometry::Visual);
+    let two_parents = TEST_ROBOT.replace(r#"<child link="lidar"/>"#, r#"<child link="finger"/>"#);
+    assert!(matches!(
+        parse(&two_parents),
+        Err(Error::InvalidArgument(_))
+    ));
+    let missing_link = TEST_ROBOT.replace(r#"<parent link="base"/>"#, r#"<parent link="x"/>"#);
+    assert!(matches!(
+        parse(&missing_link),
+        Err(Error::InvalidArgument(_))
+    ));
+    let collada = TEST_ROBOT.replace(
+        r#"<sphere radius="0.05"/>"#,
+        r#"<mesh filename="finger.dae"/>"#,
+    );
+    assert!(matches!(parse(&collada), Err(Error::InvalidArgument(_))));
+    // Only the visual geometry was COLLADA.
+    let robot = UrdfRobot::parse(&collada, Path::new("."), LinkGeometry::Collision).unwrap();
+    assert!(robot.assets().is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_urdf_scene_follows_joint_positions() {
+    let ctx = RenderContext::with_cpu_fallback(wgpu::Instance::default()).await;
+    let robot = UrdfRobot::parse(TEST_ROBOT, Path::new("."), LinkGeometry::Visual).unwrap();
+    let (mut scene, placed) = robot
+        .create_scene(&ctx, Affine3A::IDENTITY, &[0.0])
+        .await
+        .unwrap();
+    assert_eq!(placed.instances(), [0, 1, 2]);
+    robot
+        .set_joint_positions(
+            &mut scene,
+            ctx.device(),
+            &placed,
+            Affine3A::IDENTITY,
+            &[1.0],
+        )
+        .await
+        .unwrap();
+    assert!(robot
+        .set_joint_positions(&mut scene, ctx.device(), &placed, Affine3A::IDENTITY, &[])
+        .await
+        .is_err());
+}
@@ -0,0 +1,121 @@
+use std::sync::Mutex;
+
+use wgpu::util::DeviceExt;
+
+/// Upper bound on the number of idle buffers kept around by a [`BufferPool`].
+const MAX_FREE_BUFFERS: usize = 32;
+
+/// Recycles the short lived buffers of a render or query so repeated calls stop allocating.
+///
+/// A buffer is taken for the duration of one submission and handed back with
+/// [`BufferPool::recycle`] once the GPU is done with it, i.e. after the readback completed.
+/// Buffers are matched on their exact size and usage, so a sensor with a fixed resolution settles
+/// on a single set of buffers after its first render. Concurrent callers that find no free
+/// buffer simply allocate a new one.
+#[derive(Debug, Default)]
+pub(crate) struct BufferPool {
+    free: Mutex<Vec<wgpu::Buffer>>,
+}
+
+impl BufferPool {
+    /// Takes a free buffer of `size` bytes with `usage`, allocating one if none is available.
+    pub(crate) fn take(
+        &self,
+        device: &wgpu::Device,
+        size: wgpu::BufferAddress,
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        if let Some(buffer) = self.reuse(size, usage) {
+            return buffer;
+        }
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Takes a free buffer holding `contents`, uploaded with [`wgpu::Queue::write_buffer`].
+    ///
+    /// `COPY_DST` is added to `usage`. The write lands before the next submission on `queue`.
+    pub(crate) fn take_init(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        contents: &[u8],
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+        let size = contents.len() as wgpu::BufferAddress;
+        if let Some(buffer) = self.reuse(size, usage) {
+            queue.write_buffer(&buffer, 0, contents);
+            return buffer;
+        }
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents,
+            usage,
+        })
+    }
+
+    fn reuse(&self, size: wgpu::BufferAddress, usage: wgpu::BufferUsages) -> Option<wgpu::Buffer> {
+        let mut free = self.free.lock().unwrap();
+        let i = free
+            .iter()
+            .position(|buffer| buffer.size() == size && buffer.usage() == usage)?;
+        Some(free.swap_remove(i))
+    }
+
+    /// Hands buffers back to the pool. They must no longer be in use by any pending submission.
+    pub(crate) fn recycle(&self, buffers: impl IntoIterator<Item = wgpu::Buffer>) {
+        let mut free = self.free.lock().unwrap();
+        free.extend(buffers);
+        if free.len() > MAX_FREE_BUFFERS {
+            let excess = free.len() - MAX_FREE_BUFFERS;
+            free.drain(..excess);
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_buffer_pool() {
+    use crate::utils::{get_raytracing_gpu, read_buffer};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    let pool = BufferPool::default();
+
+    let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC;
+    let first = pool.take_init(
+        &device,
+        &queue,
+        bytemuck::cast_slice(&[1u32, 2, 3, 4]),
+        usage,
+    );
+    assert_eq!(
+        read_buffer::<u32>(&device, &queue, &first).await,
+        [1, 2, 3, 4]
+    );
+    pool.recycle([first.clone()]);
+
+    // The same size and usage gets the recycled buffer back, with the new contents.
+    let second = pool.take_init(
+        &device,
+        &queue,
+        bytemuck::cast_slice(&[5u32, 6, 7, 8]),
+        usage,
+    );
+    assert_eq!(second, first);
+    assert_eq!(
+        read_buffer::<u32>(&device, &queue, &second).await,
+        [5, 6, 7, 8]
+    );
+
+    // A buffer in use is never handed out twice.
+    let third = pool.take(&device, 16, usage | wgpu::BufferUsages::COPY_DST);
+    assert_ne!(third, second);
+    pool.recycle([second, third]);
+    assert_ne!(pool.take(&device, 32, usage), first);
+}
@@ -0,0 +1,70 @@
+// Differences between two occupancy snapshots, one row of cells per invocation.
+
+struct ChangeParams {
+    dims: vec3<u32>,
+    num_rows: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> before: array<u32>;
+
+@group(0)
+@binding(1)
+var<storage, read> after: array<u32>;
+
+@group(0)
+@binding(2)
+var<uniform> params: ChangeParams;
+
+@group(0)
+@binding(3)
+var<storage, read_write> counts: array<u32>;
+
+@group(0)
+@binding(4)
+var<storage, read> offsets: array<u32>;
+
+/// Changes are written as `x * 2`, plus 1 for cells that became free.
+@group(0)
+@binding(5)
+var<storage, read_write> changes: array<u32>;
+
+fn is_changed(index: u32) -> bool {
+    return (before[index] != 0u) != (after[index] != 0u);
+}
+
+@compute
+@workgroup_size(64)
+fn count(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_rows {
+        return;
+    }
+    let row = global_id.x * params.dims.x;
+    var found = 0u;
+    for (var x = 0u; x < params.dims.x; x++) {
+        if is_changed(row + x) {
+            found += 1u;
+        }
+    }
+    counts[global_id.x] = found;
+}
+
+@compute
+@workgroup_size(64)
+fn write(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_rows {
+        return;
+    }
+    let row = global_id.x * params.dims.x;
+    var next = 0u;
+    if global_id.x > 0u {
+        next = offsets[global_id.x - 1u];
+    }
+    for (var x = 0u; x < params.dims.x; x++) {
+        if is_changed(row + x) {
+            changes[next] = x * 2u + u32(before[row + x] != 0u);
+            next += 1u;
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use glam::UVec3;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{dense_voxel::DenseVoxel, prefix_sum::run_compacted_query};
+use crate::Error;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct ChangeParams {
+    dims: UVec3,
+    num_rows: u32,
+}
+
+/// Cells whose occupancy differs between two snapshots of a grid.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OccupancyChanges {
+    /// Cells free before and occupied after, e.g. dynamic obstacles against a static map.
+    pub appeared: Vec<UVec3>,
+    /// Cells occupied before and free after.
+    pub disappeared: Vec<UVec3>,
+}
+
+impl OccupancyChanges {
+    pub fn is_empty(&self) -> bool {
+        self.appeared.is_empty() && self.disappeared.is_empty()
+    }
+}
+
+/// Compares two occupancy grids of the same dimensions on the GPU.
+///
+/// Only the changed cells are read back, in x-major order.
+///
+/// # Arguments
+///
+/// * `device` - The `wgpu::Device` to use.
+/// * `queue` - The `wgpu::Queue` to use for submitting commands.
+/// * `before` - One flag per cell, non-zero for occupied cells, laid out x-major.
+/// * `after` - The same grid at a later time.
+/// * `dims` - Number of cells along each axis.
+pub async fn detect_changes(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    before: &[u32],
+    after: &[u32],
+    dims: UVec3,
+) -> OccupancyChanges {
+    let num_cells = (dims.x * dims.y * dims.z) as usize;
+    if before.len() != num_cells || after.len() != num_cells {
+        panic!("Occupancy grid does not match dimensions");
+    }
+    let num_rows = dims.y * dims.z;
+
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("change_detection.wgsl"));
+    let before_buf = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Occupancy Before"),
+        contents: bytemuck::cast_slice(before),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let after_buf = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Occupancy After"),
+        contents: bytemuck::cast_slice(after),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Change Detection Parameters"),
+        contents: bytemuck::cast_slice(&[ChangeParams { dims, num_rows }]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let rows = run_compacted_query(
+        device,
+        queue,
+        &cs_module,
+        ["count", "write"],
+        [3, 4, 5],
+        &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: before_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: after_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params.as_entire_binding(),
+            },
+        ],
+        num_rows,
+    )
+    .await;
+
+    let mut changes = OccupancyChanges::default();
+    for (row, row_changes) in rows.into_iter().enumerate() {
+        let (y, z) = (row as u32 % dims.y, row as u32 / dims.y);
+        for change in row_changes {
+            let cell = UVec3::new(change / 2, y, z);
+            if change % 2 == 0 {
+                changes.appeared.push(cell);
+            } else {
+                changes.disappeared.push(cell);
+            }
+        }
+    }
+    changes
+}
+
+/// Compares the cells occupied in two [`DenseVoxel`]s covering the same bounds.
+///
+/// Fails if the grids differ in bounds or resolution.
+pub async fn detect_dense_voxel_changes(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    before: &DenseVoxel,
+    after: &DenseVoxel,
+) -> Result<OccupancyChanges, Error> {
+    let dims = |voxel: &DenseVoxel| {
+        UVec3::new(
+            voxel.width_steps() as u32,
+            voxel.length_steps() as u32,
+            voxel.height_steps() as u32,
+        )
+    };
+    if dims(before) != dims(after)
+        || before.bottom_left() != after.bottom_left()
+        || before.resolution() != after.resolution()
+    {
+        return Err(Error::GridMismatch);
+    }
+    Ok(detect_changes(
+        device,
+        queue,
+        &before.occupancy_grid(),
+        &after.occupancy_grid(),
+        dims(before),
+    )
+    .await)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_detect_changes() {
+    use crate::utils::{dense_voxel::VoxelItem, get_raytracing_gpu};
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A prior map with two walls, one of which has been removed, and a person walking in.
+    let mut prior = DenseVoxel::new(Vec3::splat(4.0), Vec3::ZERO, 0.5, 4);
+    for y in 0..8 {
+        prior
+            .add_item(VoxelItem::new(Vec3::new(0.25, y as f32 * 0.5 + 0.25, 0.25)))
+            .unwrap();
+        prior
+            .add_item(VoxelItem::new(Vec3::new(3.75, y as f32 * 0.5 + 0.25, 0.25)))
+            .unwrap();
+    }
+    let mut current = DenseVoxel::new(Vec3::splat(4.0), Vec3::ZERO, 0.5, 4);
+    for y in 0..8 {
+        current
+            .add_item(VoxelItem::new(Vec3::new(0.3, y as f32 * 0.5 + 0.2, 0.3)))
+            .unwrap();
+    }
+    current
+        .add_item(VoxelItem::new(Vec3::new(2.1, 1.6, 0.3)))
+        .unwrap();
+    current
+        .add_item(VoxelItem::new(Vec3::new(2.1, 1.6, 0.8)))
+        .unwrap();
+
+    let changes = detect_dense_voxel_changes(&device, &queue, &prior, &current)
+        .await
+        .unwrap();
+    assert_eq!(
+        changes.appeared,
+        vec![UVec3::new(4, 3, 0), UVec3::new(4, 3, 1)]
+    );
+    assert_eq!(
+        changes.disappeared,
+        (0..8).map(|y| UVec3::new(7, y, 0)).collect::<Vec<_>>()
+    );
+
+    let unchanged = detect_dense_voxel_changes(&device, &queue, &prior, &prior)
+        .await
+        .unwrap();
+    assert!(unchanged.is_empty());
+
+    let other = DenseVoxel::new(Vec3::splat(4.0), Vec3::splat(-1.0), 0.5, 4);
+    assert!(detect_dense_voxel_changes(&device, &queue, &prior, &other)
+        .await
+        .is_err());
+}
@@ -0,0 +1,97 @@
+const NO_LABEL: u32 = 0xFFFFFFFFu;
+
+struct ComponentParams {
+    dims: vec3<u32>,
+    // 0 for face (6) connectivity, 1 for full (26) connectivity.
+    full_connectivity: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> occupancy: array<u32>;
+
+/// Label of every cell: the index of a cell in the same component, converging to the smallest.
+@group(0)
+@binding(1)
+var<storage, read_write> labels: array<atomic<u32>>;
+
+@group(0)
+@binding(2)
+var<uniform> params: ComponentParams;
+
+@group(0)
+@binding(3)
+var<storage, read_write> changed: atomic<u32>;
+
+fn to_index(cell: vec3<u32>) -> u32 {
+    return cell.x + cell.y * params.dims.x + cell.z * params.dims.x * params.dims.y;
+}
+
+@compute
+@workgroup_size(4, 4, 4)
+fn init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = to_index(global_id);
+    if occupancy[index] != 0u {
+        atomicStore(&labels[index], index);
+    } else {
+        atomicStore(&labels[index], NO_LABEL);
+    }
+}
+
+/// Lowers the label of each occupied cell to the smallest label among its neighbours.
+@compute
+@workgroup_size(4, 4, 4)
+fn propagate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = to_index(global_id);
+    let current = atomicLoad(&labels[index]);
+    if current == NO_LABEL {
+        return;
+    }
+    let cell = vec3<i32>(global_id);
+    var smallest = current;
+    for (var dz: i32 = -1; dz <= 1; dz++) {
+        for (var dy: i32 = -1; dy <= 1; dy++) {
+            for (var dx: i32 = -1; dx <= 1; dx++) {
+                let offset = vec3<i32>(dx, dy, dz);
+                let manhattan = abs(dx) + abs(dy) + abs(dz);
+                if manhattan == 0 || (params.full_connectivity == 0u && manhattan != 1) {
+                    continue;
+                }
+                let neighbour = cell + offset;
+                if any(neighbour < vec3<i32>(0)) || any(neighbour >= vec3<i32>(params.dims)) {
+                    continue;
+                }
+                smallest = min(smallest, atomicLoad(&labels[to_index(vec3<u32>(neighbour))]));
+            }
+        }
+    }
+    if smallest < current {
+        atomicMin(&labels[index], smallest);
+        atomicStore(&changed, 1u);
+    }
+}
+
+/// Pointer jumping: replaces each label with the label of the cell it points to.
+@compute
+@workgroup_size(4, 4, 4)
+fn jump(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = to_index(global_id);
+    let current = atomicLoad(&labels[index]);
+    if current == NO_LABEL {
+        return;
+    }
+    let target_label = atomicLoad(&labels[current]);
+    if target_label < current {
+        atomicMin(&labels[index], target_label);
+        atomicStore(&changed, 1u);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use glam::UVec3;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::read_buffer;
+
+/// Label of cells that do not belong to any component.
+pub const NO_COMPONENT: u32 = u32::MAX;
+
+/// Number of propagation rounds recorded between two convergence checks.
+const ROUNDS_PER_CHECK: usize = 8;
+
+/// Which neighbouring cells are considered connected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Cells sharing a face.
+    Face,
+    /// Cells sharing a face, an edge or a corner.
+    Full,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct ComponentParams {
+    dims: UVec3,
+    full_connectivity: u32,
+}
+
+/// The connected components of the occupied cells of a grid.
+#[derive(Clone, Debug)]
+pub struct ConnectedComponents {
+    /// Component of every cell, numbered from 0, or [`NO_COMPONENT`] for free cells. Laid out
+    /// like the occupancy grid.
+    pub labels: Vec<u32>,
+    /// Number of cells in each component.
+    pub sizes: Vec<u32>,
+}
+
+impl ConnectedComponents {
+    pub fn num_components(&self) -> usize {
+        self.sizes.len()
+    }
+}
+
+/// Labels the connected components of an occupancy grid on the GPU.
+///
+/// Labels are propagated between neighbouring occupied cells and shortcut with pointer jumping
+/// until they settle on the smallest cell index of each component. Components are then numbered
+/// in the order of their smallest cell index.
+///
+/// # Arguments
+///
+/// * `device` - The `wgpu::Device` to use.
+/// * `queue` - The `wgpu::Queue` to use for submitting commands.
+/// * `occupancy` - One flag per cell, non-zero for occupied cells, laid out x-major.
+/// * `dims` - Number of cells along each axis.
+/// * `connectivity` - Which neighbouring cells are connected.
+pub async fn label_components(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    occupancy: &[u32],
+    dims: UVec3,
+    connectivity: Connectivity,
+) -> ConnectedComponents {
+    let num_cells = (dims.x * dims.y * dims.z) as usize;
+    if occupancy.len() != num_cells {
+        panic!("Occupancy grid does not match dimensions");
+    }
+
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("connected_components.wgsl"));
+    let create_pipeline = |entry_point: &str| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+    let init_pipeline = create_pipeline("init");
+    let propagate_pipeline = create_pipeline("propagate");
+    let jump_pipeline = create_pipeline("jump");
+
+    let occupancy_buf = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Component Occupancy"),
+        contents: bytemuck::cast_slice(occupancy),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let labels = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Component Labels"),
+        size: (num_cells * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Component Parameters"),
+        contents: bytemuck::cast_slice(&[ComponentParams {
+            dims,
+            full_connectivity: (connectivity == Connectivity::Full) as u32,
+        }]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let changed = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Component Changed Flag"),
+        size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let init_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &init_pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: occupancy_buf.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: labels.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params.as_entire_binding(),
+            },
+        ],
+    });
+    let create_update_bind_group = |pipeline: &wgpu::ComputePipeline| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: labels.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: changed.as_entire_binding(),
+                },
+            ],
+        })
+    };
+    let propagate_bind_group = create_update_bind_group(&propagate_pipeline);
+    let jump_bind_group = create_update_bind_group(&jump_pipeline);
+
+    let workgroups = dims.map(|d| d.div_ceil(4));
+    let dispatch = |encoder: &mut wgpu::CommandEncoder,
+                    pipeline: &wgpu::ComputePipeline,
+                    bind_group: &wgpu::BindGroup| {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(pipeline);
+        cpass.set_bind_group(0, bind_group, &[]);
+        cpass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+    };
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    dispatch(&mut encoder, &init_pipeline, &init_bind_group);
+    queue.submit(Some(encoder.finish()));
+
+    loop {
+        queue.write_buffer(&changed, 0, bytemuck::cast_slice(&[0u32]));
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for _ in 0..ROUNDS_PER_CHECK {
+            dispatch(&mut encoder, &propagate_pipeline, &propagate_bind_group);
+            dispatch(&mut encoder, &jump_pipeline, &jump_bind_group);
+        }
+        queue.submit(Some(encoder.finish()));
+        let flag: Vec<u32> = read_buffer(device, queue, &changed).await;
+        if flag[0] == 0 {
+            break;
+        }
+    }
+
+    let roots: Vec<u32> = read_buffer(device, queue, &labels).await;
+    let mut components = HashMap::new();
+    let mut sizes = vec![];
+    let labels = roots
+        .into_iter()
+        .map(|root| {
+            if root == NO_COMPONENT {
+                return NO_COMPONENT;
+            }
+            let component = *components.entry(root).or_insert_with(|| {
+                sizes.push(0);
+                sizes.len() as u32 - 1
+            });
+            sizes[component as usize] += 1;
+            component
+        })
+        .collect();
+
+    ConnectedComponents { labels, sizes }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_label_components() {
+    use crate::utils::get_raytracing_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let dims = UVec3::new(12, 10, 3);
+    let index = |x: u32, y: u32, z: u32| (x + y * dims.x + z * dims.x * dims.y) as usize;
+    let mut occupancy = vec![0u32; (dims.x * dims.y * dims.z) as usize];
+    // A winding wall, so labels must travel a long way.
+    for y in 0..10 {
+        occupancy[index(1, y, 0)] = 1;
+    }
+    for x in 1..10 {
+        occupancy[index(x, 9, 0)] = 1;
+    }
+    for y in 2..10 {
+        occupancy[index(9, y, 0)] = 1;
+    }
+    // Two blocks touching only along a diagonal.
+    occupancy[index(4, 4, 2)] = 1;
+    occupancy[index(5, 5, 2)] = 1;
+
+    let face = label_components(&device, &queue, &occupancy, dims, Connectivity::Face).await;
+    assert_eq!(face.num_components(), 3);
+    assert_eq!(face.sizes, vec![25, 1, 1]);
+    assert_eq!(face.labels[index(1, 0, 0)], face.labels[index(9, 2, 0)]);
+    assert_eq!(face.labels[index(0, 0, 0)], NO_COMPONENT);
+
+    let full = label_components(&device, &queue, &occupancy, dims, Connectivity::Full).await;
+    assert_eq!(full.sizes, vec![25, 2]);
+    assert_eq!(full.labels[index(4, 4, 2)], full.labels[index(5, 5, 2)]);
+}
@@ -0,0 +1,130 @@
+// 2D costmaps derived from 3D occupancy grids.
+
+const FREE_COST: u32 = 0u;
+const LETHAL_COST: u32 = 254u;
+
+struct ProjectionParams {
+    // Voxel grid
+    voxel_origin: vec3<f32>,
+    voxel_resolution: f32,
+    voxel_dims: vec3<u32>,
+    min_z: f32,
+
+    // Costmap
+    origin: vec2<f32>,
+    dims: vec2<u32>,
+    resolution: f32,
+    max_z: f32,
+    _padding: vec2<f32>,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> occupancy: array<u32>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> costs: array<u32>;
+
+@group(0)
+@binding(2)
+var<uniform> params: ProjectionParams;
+
+/// Marks a costmap cell lethal if any occupied voxel overlapping it lies in the height band.
+@compute
+@workgroup_size(8, 8)
+fn project(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id.xy >= params.dims) {
+        return;
+    }
+    let cell_min = params.origin + vec2<f32>(global_id.xy) * params.resolution;
+    let cell_max = cell_min + vec2<f32>(params.resolution);
+    let band_min = vec3<f32>(cell_min, params.min_z);
+    let band_max = vec3<f32>(cell_max, params.max_z);
+
+    // Voxels overlapping the column above this cell, clamped to the grid.
+    let lo = max(vec3<i32>(floor((band_min - params.voxel_origin) / params.voxel_resolution)), vec3<i32>(0));
+    let hi = min(vec3<i32>(ceil((band_max - params.voxel_origin) / params.voxel_resolution)), vec3<i32>(params.voxel_dims)) - 1;
+
+    var cost = FREE_COST;
+    for (var z = lo.z; z <= hi.z; z++) {
+        for (var y = lo.y; y <= hi.y; y++) {
+            for (var x = lo.x; x <= hi.x; x++) {
+                let index = u32(x) + u32(y) * params.voxel_dims.x + u32(z) * params.voxel_dims.x * params.voxel_dims.y;
+                if occupancy[index] != 0u {
+                    cost = LETHAL_COST;
+                }
+            }
+        }
+    }
+    costs[global_id.x + global_id.y * params.dims.x] = cost;
+}
+
+const INSCRIBED_COST: u32 = 253u;
+
+const DECAY_EXPONENTIAL: u32 = 0u;
+const DECAY_LINEAR: u32 = 1u;
+
+struct InflationParams {
+    dims: vec2<u32>,
+    resolution: f32,
+    robot_radius: f32,
+    inflation_radius: f32,
+    decay: u32,
+    decay_factor: f32,
+    _padding: f32,
+}
+
+@group(0)
+@binding(3)
+var<uniform> inflation: InflationParams;
+
+@group(0)
+@binding(4)
+var<storage, read_write> inflated: array<u32>;
+
+/// Cost of a cell `distance` meters away from the nearest lethal cell.
+fn inflation_cost(distance: f32) -> u32 {
+    if distance == 0.0 {
+        return LETHAL_COST;
+    }
+    if distance <= inflation.robot_radius {
+        return INSCRIBED_COST;
+    }
+    if distance > inflation.inflation_radius {
+        return FREE_COST;
+    }
+    let excess = distance - inflation.robot_radius;
+    var factor: f32;
+    if inflation.decay == DECAY_LINEAR {
+        factor = 1.0 - excess / max(inflation.inflation_radius - inflation.robot_radius, 1e-6);
+    } else {
+        factor = exp(-inflation.decay_factor * excess);
+    }
+    return u32(f32(INSCRIBED_COST - 1u) * factor);
+}
+
+/// Dilates the lethal cells of `costs` into `inflated`, keeping the larger of the existing and
+/// inflated cost.
+@compute
+@workgroup_size(8, 8)
+fn inflate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id.xy >= inflation.dims) {
+        return;
+    }
+    let cell = vec2<i32>(global_id.xy);
+    let reach = i32(ceil(inflation.inflation_radius / inflation.resolution));
+    let lo = max(cell - reach, vec2<i32>(0));
+    let hi = min(cell + reach, vec2<i32>(inflation.dims) - 1);
+
+    var nearest: f32 = 3.4e38;
+    for (var y = lo.y; y <= hi.y; y++) {
+        for (var x = lo.x; x <= hi.x; x++) {
+            if costs[u32(x) + u32(y) * inflation.dims.x] == LETHAL_COST {
+                nearest = min(nearest, length(vec2<f32>(vec2<i32>(x, y) - cell)) * inflation.resolution);
+            }
+        }
+    }
+    let index = global_id.x + global_id.y * inflation.dims.x;
+    inflated[index] = max(costs[index], inflation_cost(nearest));
+}
@@ -0,0 +1,465 @@
+use glam::{UVec2, UVec3, Vec2, Vec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{dense_voxel::DenseVoxel, read_buffer};
+
+/// Cost of a cell with no obstacle in it.
+pub const FREE_COST: u8 = 0;
+/// Cost of a cell containing an obstacle.
+pub const LETHAL_COST: u8 = 254;
+/// Cost of a cell within the robot radius of an obstacle, i.e. certainly in collision.
+pub const INSCRIBED_COST: u8 = 253;
+
+/// How the cost of inflated cells falls off beyond the robot radius.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CostDecay {
+    /// `(INSCRIBED_COST - 1) * exp(-scaling_factor * (distance - robot_radius))`, as in ROS
+    /// `costmap_2d`.
+    Exponential { scaling_factor: f32 },
+    /// Falls linearly from `INSCRIBED_COST - 1` at the robot radius to 0 at the inflation radius.
+    Linear,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct ProjectionParams {
+    voxel_origin: Vec3,
+    voxel_resolution: f32,
+    voxel_dims: UVec3,
+    min_z: f32,
+    origin: Vec2,
+    dims: UVec2,
+    resolution: f32,
+    max_z: f32,
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct InflationParams {
+    dims: UVec2,
+    resolution: f32,
+    robot_radius: f32,
+    inflation_radius: f32,
+    decay: u32,
+    decay_factor: f32,
+    _padding: f32,
+}
+
+/// A 2D grid of traversal costs for wheeled robots.
+///
+/// Costs follow the ROS `costmap_2d` convention, from [`FREE_COST`] to [`LETHAL_COST`], and are
+/// stored on the GPU as one `u32` per cell. Cells are laid out x-major, i.e. cell `(x, y)` is at
+/// `x + y * dims.x`.
+pub struct Costmap2D {
+    origin: Vec2,
+    dims: UVec2,
+    resolution: f32,
+    costs: wgpu::Buffer,
+}
+
+impl Costmap2D {
+    /// Projects the occupied cells of a 3D occupancy grid lying between `min_z` and `max_z` onto
+    /// the ground plane.
+    ///
+    /// A costmap cell is lethal if any occupied voxel overlaps its column within the height
+    /// band, and free otherwise. The costmap covers the same footprint as the grid.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
+    /// * `occupancy` - One flag per voxel, non-zero for occupied voxels, laid out x-major.
+    /// * `voxel_dims` - Number of voxels along each axis.
+    /// * `voxel_origin` - World position of the corner of the first voxel.
+    /// * `voxel_resolution` - Edge length of a voxel in meters.
+    /// * `resolution` - Edge length of a costmap cell in meters.
+    /// * `min_z` - Bottom of the height band, typically just above the ground.
+    /// * `max_z` - Top of the height band, typically the height of the robot.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_occupancy(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        occupancy: &[u32],
+        voxel_dims: UVec3,
+        voxel_origin: Vec3,
+        voxel_resolution: f32,
+        resolution: f32,
+        min_z: f32,
+        max_z: f32,
+    ) -> Self {
+        if occupancy.len() != (voxel_dims.x * voxel_dims.y * voxel_dims.z) as usize {
+            panic!("Occupancy grid does not match dimensions");
+        }
+        let origin = voxel_origin.truncate();
+        let extent = voxel_dims.truncate().as_vec2() * voxel_resolution;
+        let dims = (extent / resolution).ceil().as_uvec2().max(UVec2::ONE);
+
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("costmap.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("costmap_projection"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("project"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let occupancy_buf = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Costmap Occupancy"),
+            contents: bytemuck::cast_slice(occupancy),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let costs = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Costmap Costs"),
+            size: ((dims.x * dims.y) as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Costmap Projection Parameters"),
+            contents: bytemuck::cast_slice(&[ProjectionParams {
+                voxel_origin,
+                voxel_resolution,
+                voxel_dims,
+                min_z,
+                origin,
+                dims,
+                resolution,
+                max_z,
+                _padding: [0.0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: occupancy_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: costs.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(dims.x.div_ceil(8), dims.y.div_ceil(8), 1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            origin,
+            dims,
+            resolution,
+            costs,
+        }
+    }
+
+    /// Projects the cells occupied in a [`DenseVoxel`] between `min_z` and `max_z`.
+    pub fn from_dense_voxel(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        voxel: &DenseVoxel,
+        resolution: f32,
+        min_z: f32,
+        max_z: f32,
+    ) -> Self {
+        let dims = UVec3::new(
+            voxel.width_steps() as u32,
+            voxel.length_steps() as u32,
+            voxel.height_steps() as u32,
+        );
+        Self::from_occupancy(
+            device,
+            queue,
+            &voxel.occupancy_grid(),
+            dims,
+            voxel.bottom_left(),
+            voxel.resolution(),
+            resolution,
+            min_z,
+            max_z,
+        )
+    }
+
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    pub fn dims(&self) -> UVec2 {
+        self.dims
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// The GPU buffer holding one `u32` cost per cell.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.costs
+    }
+
+    /// Returns the index of cell `(x, y)` in the cost buffer.
+    pub fn cell_index(&self, x: u32, y: u32) -> usize {
+        (x + y * self.dims.x) as usize
+    }
+
+    /// Returns the cell containing a world position, if it lies on the map.
+    pub fn world_to_cell(&self, position: Vec2) -> Option<UVec2> {
+        let cell = ((position - self.origin) / self.resolution).floor();
+        if cell.cmplt(Vec2::ZERO).any() || cell.cmpge(self.dims.as_vec2()).any() {
+            return None;
+        }
+        Some(cell.as_uvec2())
+    }
+
+    /// Returns a copy of this costmap with its lethal cells dilated by the robot footprint.
+    ///
+    /// Cells within `robot_radius` of a lethal cell become [`INSCRIBED_COST`], and cells up to
+    /// `inflation_radius` away get a cost following `decay`. Cells already costlier keep their
+    /// cost.
+    pub fn inflate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        robot_radius: f32,
+        inflation_radius: f32,
+        decay: CostDecay,
+    ) -> Self {
+        let (decay, decay_factor) = match decay {
+            CostDecay::Exponential { scaling_factor } => (0, scaling_factor),
+            CostDecay::Linear => (1, 0.0),
+        };
+
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("costmap.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("costmap_inflation"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("inflate"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let inflated = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Costmap Costs"),
+            size: self.costs.size(),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Costmap Inflation Parameters"),
+            contents: bytemuck::cast_slice(&[InflationParams {
+                dims: self.dims,
+                resolution: self.resolution,
+                robot_radius,
+                inflation_radius: inflation_radius.max(robot_radius),
+                decay,
+                decay_factor,
+                _padding: 0.0,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.costs.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: inflated.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(self.dims.x.div_ceil(8), self.dims.y.div_ceil(8), 1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            origin: self.origin,
+            dims: self.dims,
+            resolution: self.resolution,
+            costs: inflated,
+        }
+    }
+
+    /// Reads the costs back to the CPU.
+    pub async fn download(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8> {
+        let costs: Vec<u32> = read_buffer(device, queue, &self.costs).await;
+        costs.into_iter().map(|c| c as u8).collect()
+    }
+
+    /// Logs the cells with a cost to `rerun` under `costmap/cells`, as flat boxes at `height`
+    /// colored from white for low costs to red for lethal ones.
+    ///
+    /// # Note
+    ///
+    /// This method is only available when the `visualization` feature is enabled.
+    #[cfg(feature = "visualization")]
+    pub async fn visualize(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rerun: &rerun::RecordingStream,
+        height: f32,
+    ) {
+        let costs = self.download(device, queue).await;
+        let mut centers = vec![];
+        let mut colors = vec![];
+        for y in 0..self.dims.y {
+            for x in 0..self.dims.x {
+                let cost = costs[self.cell_index(x, y)];
+                if cost == FREE_COST {
+                    continue;
+                }
+                let center = self.origin + (UVec2::new(x, y).as_vec2() + 0.5) * self.resolution;
+                centers.push(center.extend(height).to_array());
+                colors.push(crate::visualizer::diverging_color(
+                    -(cost.min(LETHAL_COST) as f32) / LETHAL_COST as f32,
+                ));
+            }
+        }
+        let half_size = [self.resolution / 2.0, self.resolution / 2.0, 0.0];
+        rerun
+            .log(
+                "costmap/cells",
+                &rerun::Boxes3D::from_centers_and_half_sizes(
+                    centers.clone(),
+                    vec![half_size; centers.len()],
+                )
+                .with_colors(colors),
+            )
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_costmap_projection() {
+    use crate::utils::get_raytracing_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let dims = UVec3::new(8, 6, 4);
+    let mut occupancy = vec![0u32; (dims.x * dims.y * dims.z) as usize];
+    let mut occupy = |x: u32, y: u32, z: u32| {
+        occupancy[(x + y * dims.x + z * dims.x * dims.y) as usize] = 1;
+    };
+    // Ground, inside the band, and overhead.
+    occupy(1, 1, 0);
+    occupy(4, 2, 1);
+    occupy(6, 5, 3);
+
+    let costmap = Costmap2D::from_occupancy(
+        &device,
+        &queue,
+        &occupancy,
+        dims,
+        Vec3::ZERO,
+        0.5,
+        1.0,
+        0.5,
+        1.5,
+    );
+    assert_eq!(costmap.dims(), UVec2::new(4, 3));
+    let costs = costmap.download(&device, &queue).await;
+
+    for y in 0..3 {
+        for x in 0..4 {
+            let expected = if (x, y) == (2, 1) {
+                LETHAL_COST
+            } else {
+                FREE_COST
+            };
+            assert_eq!(costs[costmap.cell_index(x, y)], expected, "{x} {y}");
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_costmap_inflation() {
+    use crate::utils::get_raytracing_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A single obstacle in the middle of a 21x21 map.
+    let dims = UVec3::new(21, 21, 1);
+    let mut occupancy = vec![0u32; (dims.x * dims.y) as usize];
+    occupancy[(10 + 10 * dims.x) as usize] = 1;
+    let costmap = Costmap2D::from_occupancy(
+        &device,
+        &queue,
+        &occupancy,
+        dims,
+        Vec3::ZERO,
+        0.1,
+        0.1,
+        0.0,
+        1.0,
+    );
+
+    for decay in [
+        CostDecay::Exponential {
+            scaling_factor: 5.0,
+        },
+        CostDecay::Linear,
+    ] {
+        let inflated = costmap.inflate(&device, &queue, 0.25, 0.65, decay);
+        let costs = inflated.download(&device, &queue).await;
+        for y in 0..dims.y {
+            for x in 0..dims.x {
+                let distance = Vec2::new(x as f32 - 10.0, y as f32 - 10.0).length() * 0.1;
+                let got = costs[inflated.cell_index(x, y)];
+                if distance == 0.0 {
+                    assert_eq!(got, LETHAL_COST);
+                } else if distance <= 0.25 {
+                    assert_eq!(got, INSCRIBED_COST, "{x} {y}");
+                } else if distance > 0.65 {
+                    assert_eq!(got, FREE_COST, "{x} {y}");
+                } else {
+                    assert!(got > FREE_COST && got < INSCRIBED_COST, "{x} {y}: {got}");
+                }
+            }
+        }
+    }
+}
@@ -1,15 +1,102 @@
-use glam::Vec3;
-use rand::Rng;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use glam::{Affine3A, IVec3, Mat4, UVec3, Vec3, Vec4};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 
-use crate::utils::get_raytracing_gpu;
-use crate::RayTraceScene;
+use crate::utils::{
+    buffer_pool::BufferPool, get_raytracing_gpu, prefix_sum::run_compacted_query, read_buffer,
+};
+use crate::{Error, RayTraceScene};
+
+/// Marker returned by the nearest neighbour kernel when no item was found.
+pub const NO_MATCH: u32 = u32::MAX;
 
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VoxelItem {
     pub position: Vec3,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub occupied: u32,
+    /// User data carried along with the item, e.g. the index of a planner tree node.
+    pub payload: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _padding: [u32; 3],
+}
+
+impl VoxelItem {
+    /// Creates an item at `position` with an empty payload.
+    pub fn new(position: Vec3) -> Self {
+        Self::with_payload(position, 0)
+    }
+
+    /// Creates an item at `position` carrying `payload`.
+    pub fn with_payload(position: Vec3, payload: u32) -> Self {
+        Self {
+            position,
+            occupied: 0,
+            payload,
+            _padding: [0; 3],
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn payload(&self) -> u32 {
+        self.payload
+    }
+
+    pub fn is_occupied(&self) -> bool {
+        self.occupied != 0
+    }
+}
+
+/// Magic bytes at the start of a serialized [`DenseVoxel`].
+const DENSE_VOXEL_MAGIC: &[u8; 4] = b"WGVX";
+/// Version of the serialized [`DenseVoxel`] format.
+const DENSE_VOXEL_FORMAT_VERSION: u32 = 2;
+
+/// Largest number of cells along an axis of a [`VoxelLayout::Morton`] grid.
+pub const MAX_MORTON_STEPS: usize = 1 << 10;
+
+/// Order in which the cells of a [`DenseVoxel`] are stored.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VoxelLayout {
+    /// Cell `(x, y, z)` is at `x + y * width_steps + z * width_steps * length_steps`.
+    #[default]
+    Linear,
+    /// Cells are stored along a Z-order curve, so cells that are close in space are also close
+    /// in memory, which helps neighbourhood searches on the GPU.
+    ///
+    /// The buffer is sized for the smallest power of two cube covering the grid, which wastes
+    /// memory on flat grids. Supports up to [`MAX_MORTON_STEPS`] cells per axis.
+    Morton,
+}
+
+impl VoxelLayout {
+    /// Must match `cell_offset` in the shaders.
+    fn cell_offset(&self, x: usize, y: usize, z: usize, width: usize, length: usize) -> usize {
+        match self {
+            VoxelLayout::Linear => x + y * width + z * width * length,
+            VoxelLayout::Morton => spread_bits(x) | (spread_bits(y) << 1) | (spread_bits(z) << 2),
+        }
+    }
+}
+
+/// Inserts two zero bits between each of the lower 10 bits of `v`.
+fn spread_bits(v: usize) -> usize {
+    let mut x = v & 0x3ff;
+    x = (x | (x << 16)) & 0x030000ff;
+    x = (x | (x << 8)) & 0x0300f00f;
+    x = (x | (x << 4)) & 0x030c30c3;
+    x = (x | (x << 2)) & 0x09249249;
+    x
 }
 
 pub struct DenseVoxel {
@@ -23,24 +110,49 @@ pub struct DenseVoxel {
     max_density: u32,
     /// Voxel data
     data_on_cpu: Vec<VoxelItem>,
+    /// Grow the bounds instead of rejecting items that fall outside them.
+    auto_grow: bool,
+    /// Order of the cells in `data_on_cpu`.
+    layout: VoxelLayout,
 }
 
 impl DenseVoxel {
     pub fn new(top_right: Vec3, bottom_left: Vec3, resolution: f32, max_density: u32) -> Self {
+        Self::with_layout(
+            top_right,
+            bottom_left,
+            resolution,
+            max_density,
+            VoxelLayout::Linear,
+        )
+    }
+
+    /// Creates a grid whose cells are stored in the given order.
+    pub fn with_layout(
+        top_right: Vec3,
+        bottom_left: Vec3,
+        resolution: f32,
+        max_density: u32,
+        layout: VoxelLayout,
+    ) -> Self {
         if top_right.x < bottom_left.x || top_right.y < bottom_left.y || top_right.z < bottom_left.z
         {
             panic!("Invalid voxel grid bounds");
         }
-        let num_voxel_grids = (top_right - bottom_left) / resolution;
-        let num_voxel_grids = num_voxel_grids.x.ceil() as usize
-            * num_voxel_grids.y.ceil() as usize
-            * num_voxel_grids.z.ceil() as usize
-            * max_density as usize;
-        let data_on_cpu = (0..num_voxel_grids)
-            .map(|_| VoxelItem {
-                position: Vec3::new(0.0, 0.0, 0.0),
-                occupied: 0,
-            })
+        let steps = ((top_right - bottom_left) / resolution).ceil();
+        let (width, length, height) = (steps.x as usize, steps.y as usize, steps.z as usize);
+        let num_cells = match layout {
+            VoxelLayout::Linear => width * length * height,
+            VoxelLayout::Morton => {
+                if width.max(length).max(height) > MAX_MORTON_STEPS {
+                    panic!("Voxel grid too large for a Morton layout");
+                }
+                let side = width.max(length).max(height).max(1).next_power_of_two();
+                side * side * side
+            }
+        };
+        let data_on_cpu = (0..num_cells * max_density as usize)
+            .map(|_| VoxelItem::default())
             .collect();
         Self {
             top_right,
@@ -48,9 +160,75 @@ impl DenseVoxel {
             resolution,
             max_density,
             data_on_cpu,
+            auto_grow: false,
+            layout,
         }
     }
 
+    pub fn layout(&self) -> VoxelLayout {
+        self.layout
+    }
+
+    /// When enabled, [`DenseVoxel::add_item`] grows the grid to fit items outside its bounds
+    /// instead of returning an error. See [`DenseVoxel::grow_to_include`].
+    pub fn set_auto_grow(&mut self, auto_grow: bool) {
+        self.auto_grow = auto_grow;
+    }
+
+    /// Grows the bounds so that `position` lies inside the grid, re-binning existing items.
+    ///
+    /// Each axis that needs to grow is at least doubled so repeated growth stays cheap, and the
+    /// new bounds stay aligned to the existing cells. Growing invalidates the indices previously
+    /// returned by [`DenseVoxel::add_item`] and any GPU copies of the grid.
+    ///
+    /// Fails with [`Error::OutOfBounds`], leaving the grid as it is, if a [`VoxelLayout::Morton`]
+    /// grid would grow past [`MAX_MORTON_STEPS`] cells along an axis.
+    pub fn grow_to_include(&mut self, position: Vec3) -> Result<(), Error> {
+        let extent = self.top_right - self.bottom_left;
+        let mut bottom_left = self.bottom_left;
+        let mut top_right = self.top_right;
+        for axis in 0..3 {
+            if position[axis] < bottom_left[axis] {
+                let needed = bottom_left[axis] - position[axis];
+                bottom_left[axis] -=
+                    (needed.max(extent[axis]) / self.resolution).ceil() * self.resolution;
+            } else if position[axis] >= top_right[axis] {
+                let needed = position[axis] - top_right[axis];
+                top_right[axis] +=
+                    ((needed.max(extent[axis]) / self.resolution).floor() + 1.0) * self.resolution;
+            }
+        }
+        if bottom_left == self.bottom_left && top_right == self.top_right {
+            return Ok(());
+        }
+        let steps = ((top_right - bottom_left) / self.resolution).ceil();
+        if self.layout == VoxelLayout::Morton && steps.max_element() as usize > MAX_MORTON_STEPS {
+            return Err(Error::OutOfBounds("Grown Morton voxel grid"));
+        }
+
+        let items: Vec<_> = self
+            .data_on_cpu
+            .iter()
+            .filter(|item| item.occupied != 0)
+            .copied()
+            .collect();
+        *self = Self {
+            auto_grow: self.auto_grow,
+            ..Self::with_layout(
+                top_right,
+                bottom_left,
+                self.resolution,
+                self.max_density,
+                self.layout,
+            )
+        };
+        for item in items {
+            self.add_item(item)
+                .expect("Re-binned item must fit in the grown grid");
+        }
+        Ok(())
+    }
+
     pub fn width(&self) -> f32 {
         self.top_right.x - self.bottom_left.x
     }
@@ -75,30 +253,45 @@ impl DenseVoxel {
         (self.length() / self.resolution).ceil() as usize
     }
 
+    /// Number of item slots, including the padding cells of a [`VoxelLayout::Morton`] grid.
     pub fn capacity(&self) -> usize {
-        self.width_steps() * self.height_steps() * self.length_steps() * self.max_density as usize
+        self.data_on_cpu.len()
     }
 
-    pub fn add_item(&mut self, item: VoxelItem) -> Result<usize, String> {
-        if item.position.x < self.bottom_left.x
-            || item.position.y < self.bottom_left.y
-            || item.position.z < self.bottom_left.z
-        {
-            return Err("Out of voxel bounds".to_string());
-        }
-        if item.position.x > self.top_right.x
-            || item.position.y > self.top_right.y
-            || item.position.z > self.top_right.z
-        {
-            return Err("Out of voxel bounds".to_string());
-        }
+    pub fn bottom_left(&self) -> Vec3 {
+        self.bottom_left
+    }
 
-        let x = ((item.position.x - self.bottom_left.x) / self.resolution) as usize;
-        let y = ((item.position.y - self.bottom_left.y) / self.resolution) as usize;
-        let z = ((item.position.z - self.bottom_left.z) / self.resolution) as usize;
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
 
-        let index = x + y * self.width_steps() + z * self.height_steps() * self.width_steps();
-        let index = index * self.max_density as usize;
+    /// Returns one flag per cell that is non-zero if the cell holds at least one item.
+    ///
+    /// Cells are laid out x-major over `(width_steps, length_steps, height_steps)`, i.e. cell
+    /// `(x, y, z)` is at `x + y * width_steps + z * width_steps * length_steps`.
+    pub fn occupancy_grid(&self) -> Vec<u32> {
+        let (width, length, height) =
+            (self.width_steps(), self.length_steps(), self.height_steps());
+        let mut occupancy = vec![0u32; width * length * height];
+        for item in self.data_on_cpu.iter().filter(|item| item.occupied != 0) {
+            let cell = ((item.position - self.bottom_left) / self.resolution).as_uvec3();
+            let x = (cell.x as usize).min(width - 1);
+            let y = (cell.y as usize).min(length - 1);
+            let z = (cell.z as usize).min(height - 1);
+            occupancy[x + y * width + z * width * length] = 1;
+        }
+        occupancy
+    }
+
+    pub fn add_item(&mut self, item: VoxelItem) -> Result<usize, Error> {
+        if self.auto_grow {
+            self.grow_to_include(item.position)?;
+        }
+        let cell = self
+            .cell_of(item.position)
+            .ok_or(Error::OutOfBounds("Voxel position"))?;
+        let index = self.index(cell.x as usize, cell.y as usize, cell.z as usize);
 
         for i in 0..self.max_density as usize {
             if self.data_on_cpu[index + i].occupied == 0 {
@@ -107,27 +300,104 @@ impl DenseVoxel {
                 return Ok(index + i);
             }
         }
-        Err("No space in voxel grid".to_string())
+        Err(Error::NoSpace)
+    }
+
+    /// Inserts many items at once.
+    ///
+    /// Equivalent to calling [`DenseVoxel::add_item`] for each item in order, but the grid grows
+    /// at most once to fit all of them. Returns the index of each item, or `None` for items out
+    /// of bounds or in a full cell.
+    pub fn add_items(&mut self, items: &[VoxelItem]) -> Vec<Option<usize>> {
+        if self.auto_grow && !items.is_empty() {
+            let (min, max) = items
+                .iter()
+                .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), item| {
+                    (min.min(item.position), max.max(item.position))
+                });
+            // Items the grid can not grow to fit are reported as `None` below.
+            let _ = self
+                .grow_to_include(min)
+                .and_then(|()| self.grow_to_include(max));
+        }
+
+        let density = self.max_density as usize;
+        let mut indices = Vec::with_capacity(items.len());
+        for item in items {
+            let Some(cell) = self.cell_of(item.position) else {
+                indices.push(None);
+                continue;
+            };
+            let start = self.index(cell.x as usize, cell.y as usize, cell.z as usize);
+            let slot = self.data_on_cpu[start..start + density]
+                .iter()
+                .position(|slot| slot.occupied == 0)
+                .map(|slot| start + slot);
+            if let Some(slot) = slot {
+                self.data_on_cpu[slot] = VoxelItem {
+                    occupied: 1,
+                    ..*item
+                };
+            }
+            indices.push(slot);
+        }
+        indices
+    }
+
+    /// The cell holding `position`, or `None` if it lies outside the grid.
+    ///
+    /// Positions on the top right faces belong to the last cell along that axis.
+    fn cell_of(&self, position: Vec3) -> Option<UVec3> {
+        let steps = UVec3::new(
+            self.width_steps() as u32,
+            self.length_steps() as u32,
+            self.height_steps() as u32,
+        );
+        if position.cmplt(self.bottom_left).any()
+            || position.cmpgt(self.top_right).any()
+            || steps.cmpeq(UVec3::ZERO).any()
+        {
+            return None;
+        }
+        Some(
+            ((position - self.bottom_left) / self.resolution)
+                .as_uvec3()
+                .min(steps - 1),
+        )
     }
 
     fn index(&self, x: usize, y: usize, z: usize) -> usize {
-        (x + y * self.width_steps() + z * self.height_steps() * self.width_steps())
+        self.layout
+            .cell_offset(x, y, z, self.width_steps(), self.length_steps())
             * self.max_density as usize
     }
 
     fn from_index(&self, index: usize) -> (usize, usize, usize) {
-        let z = index / (self.height_steps() * self.width_steps() * self.max_density as usize);
-        let index =
-            index - z * self.height_steps() * self.width_steps() * self.max_density as usize;
-        let y = index / (self.width_steps() * self.max_density as usize);
-        let index = index - y * self.width_steps() * self.max_density as usize;
-        let x = index / self.max_density as usize;
-        (x, y, z)
+        /// Inverse of [`spread_bits`].
+        fn compact_bits(v: usize) -> usize {
+            let mut x = v & 0x09249249;
+            x = (x | (x >> 2)) & 0x030c30c3;
+            x = (x | (x >> 4)) & 0x0300f00f;
+            x = (x | (x >> 8)) & 0x030000ff;
+            (x | (x >> 16)) & 0x3ff
+        }
+        let cell = index / self.max_density as usize;
+        match self.layout {
+            VoxelLayout::Linear => (
+                cell % self.width_steps(),
+                cell / self.width_steps() % self.length_steps(),
+                cell / (self.width_steps() * self.length_steps()),
+            ),
+            VoxelLayout::Morton => (
+                compact_bits(cell),
+                compact_bits(cell >> 1),
+                compact_bits(cell >> 2),
+            ),
+        }
     }
 
     pub fn get_items_in_cell(&self, x: usize, y: usize, z: usize) -> Vec<VoxelItem> {
-        let index = x + y * self.width_steps() + z * self.height_steps() * self.width_steps();
-        let index = index * self.max_density as usize;
+        let index = self.index(x, y, z);
         let mut items = vec![];
         for i in 0..self.max_density as usize {
             if self.data_on_cpu[index + i].occupied == 1 {
@@ -144,37 +414,337 @@ impl DenseVoxel {
         self.get_items_in_cell(x, y, z)
     }
 
+    /// Returns the item stored at `index`, e.g. as returned by a nearest neighbour query.
+    pub fn item(&self, index: usize) -> Option<VoxelItem> {
+        self.data_on_cpu
+            .get(index)
+            .copied()
+            .filter(|item| item.occupied != 0)
+    }
+
+    /// Removes the item stored at `index`, as returned by [`DenseVoxel::add_item`].
+    ///
+    /// The remaining items of the cell are shifted down to keep its slots compact, so the indices
+    /// of items added to the same cell after the removed one decrease by one.
+    pub fn remove_item(&mut self, index: usize) -> Result<VoxelItem, Error> {
+        if index >= self.data_on_cpu.len() {
+            return Err(Error::OutOfBounds("Voxel index"));
+        }
+        if self.data_on_cpu[index].occupied == 0 {
+            return Err(Error::NoItem(index));
+        }
+        let item = self.data_on_cpu[index];
+        let density = self.max_density as usize;
+        let cell_end = (index / density + 1) * density;
+        self.data_on_cpu.copy_within(index + 1..cell_end, index);
+        self.data_on_cpu[cell_end - 1].occupied = 0;
+        Ok(item)
+    }
+
+    /// Removes every item in cell `(x, y, z)`. Returns the number of items removed.
+    pub fn clear_cell(&mut self, x: usize, y: usize, z: usize) -> usize {
+        let index = self.index(x, y, z);
+        let mut cleared = 0;
+        for item in &mut self.data_on_cpu[index..index + self.max_density as usize] {
+            if item.occupied != 0 {
+                item.occupied = 0;
+                cleared += 1;
+            }
+        }
+        cleared
+    }
+
+    /// Removes every item in the grid.
+    pub fn clear_all(&mut self) {
+        self.data_on_cpu
+            .iter_mut()
+            .for_each(|item| item.occupied = 0);
+    }
+
+    /// Marks every cell traversed by the ray from `origin` to `hit` as free space.
+    ///
+    /// Items in the traversed cells are removed. The cell containing `hit` itself is left
+    /// untouched, so a new observation can be inserted there. Cells outside the grid are skipped.
+    /// Returns the number of items removed.
+    pub fn clear_ray(&mut self, origin: Vec3, hit: Vec3) -> usize {
+        let dims = IVec3::new(
+            self.width_steps() as i32,
+            self.length_steps() as i32,
+            self.height_steps() as i32,
+        );
+        let start = (origin - self.bottom_left) / self.resolution;
+        let end = (hit - self.bottom_left) / self.resolution;
+        let direction = end - start;
+
+        // Amanatides & Woo voxel traversal, parameterized by t in [0, 1] along the segment.
+        let mut cell = start.floor().as_ivec3();
+        let end_cell = end.floor().as_ivec3();
+        let step = IVec3::new(
+            direction.x.signum() as i32,
+            direction.y.signum() as i32,
+            direction.z.signum() as i32,
+        );
+        let t_delta = Vec3::ONE / direction.abs();
+        let next_boundary = cell.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+        let mut t_max = Vec3::select(
+            direction.cmpeq(Vec3::ZERO),
+            Vec3::INFINITY,
+            (next_boundary - start) / direction,
+        );
+
+        let mut cleared = 0;
+        while cell != end_cell {
+            if cell.cmpge(IVec3::ZERO).all() && cell.cmplt(dims).all() {
+                cleared += self.clear_cell(cell.x as usize, cell.y as usize, cell.z as usize);
+            }
+            let axis = if t_max.x <= t_max.y && t_max.x <= t_max.z {
+                0
+            } else if t_max.y <= t_max.z {
+                1
+            } else {
+                2
+            };
+            if t_max[axis] > 1.0 {
+                break;
+            }
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+        }
+        cleared
+    }
+
+    /// Clears the free space between a sensor at `origin` and each of its `hits`.
+    ///
+    /// Returns the total number of items removed. See [`DenseVoxel::clear_ray`].
+    pub fn clear_rays(&mut self, origin: Vec3, hits: &[Vec3]) -> usize {
+        hits.iter().map(|hit| self.clear_ray(origin, *hit)).sum()
+    }
+
+    /// Writes the grid to `writer` in a compact little-endian binary format.
+    ///
+    /// Only occupied items are stored, so sparse maps stay small. Read it back with
+    /// [`DenseVoxel::read_from`].
+    pub fn write_to(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(DENSE_VOXEL_MAGIC)?;
+        writer.write_all(&DENSE_VOXEL_FORMAT_VERSION.to_le_bytes())?;
+        for value in self
+            .top_right
+            .to_array()
+            .into_iter()
+            .chain(self.bottom_left.to_array())
+            .chain([self.resolution])
+        {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&self.max_density.to_le_bytes())?;
+        let layout: u32 = match self.layout {
+            VoxelLayout::Linear => 0,
+            VoxelLayout::Morton => 1,
+        };
+        writer.write_all(&layout.to_le_bytes())?;
+        let items: Vec<_> = self
+            .data_on_cpu
+            .iter()
+            .filter(|item| item.occupied != 0)
+            .collect();
+        writer.write_all(&(items.len() as u64).to_le_bytes())?;
+        for item in items {
+            for value in item.position.to_array() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&item.payload.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a grid written by [`DenseVoxel::write_to`].
+    pub fn read_from(mut reader: impl Read) -> std::io::Result<Self> {
+        fn invalid(message: &str) -> std::io::Error {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+        }
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != DENSE_VOXEL_MAGIC {
+            return Err(invalid("Not a voxel grid file"));
+        }
+        let mut word = [0u8; 4];
+        let mut read_word = |reader: &mut dyn Read| -> std::io::Result<[u8; 4]> {
+            reader.read_exact(&mut word)?;
+            Ok(word)
+        };
+        let version = u32::from_le_bytes(read_word(&mut reader)?);
+        if version == 0 || version > DENSE_VOXEL_FORMAT_VERSION {
+            return Err(invalid("Unsupported voxel grid format version"));
+        }
+        let mut floats = [0f32; 7];
+        for value in &mut floats {
+            *value = f32::from_le_bytes(read_word(&mut reader)?);
+        }
+        let max_density = u32::from_le_bytes(read_word(&mut reader)?);
+        // Version 1 predates layouts and is always linear.
+        let layout = match version {
+            1 => 0,
+            _ => u32::from_le_bytes(read_word(&mut reader)?),
+        };
+        let layout = match layout {
+            0 => VoxelLayout::Linear,
+            1 => VoxelLayout::Morton,
+            _ => return Err(invalid("Unknown voxel grid layout")),
+        };
+        let mut count = [0u8; 8];
+        reader.read_exact(&mut count)?;
+        let count = u64::from_le_bytes(count);
+
+        let top_right = Vec3::from_slice(&floats[0..3]);
+        let bottom_left = Vec3::from_slice(&floats[3..6]);
+        let resolution = floats[6];
+        if top_right.cmplt(bottom_left).any() || resolution <= 0.0 || max_density == 0 {
+            return Err(invalid("Invalid voxel grid bounds"));
+        }
+        let mut voxel = Self::with_layout(top_right, bottom_left, resolution, max_density, layout);
+        for _ in 0..count {
+            let mut position = [0f32; 3];
+            for value in &mut position {
+                *value = f32::from_le_bytes(read_word(&mut reader)?);
+            }
+            let payload = u32::from_le_bytes(read_word(&mut reader)?);
+            voxel
+                .add_item(VoxelItem::with_payload(Vec3::from_array(position), payload))
+                .map_err(|e| invalid(&e.to_string()))?;
+        }
+        Ok(voxel)
+    }
+
+    /// Saves the grid to a file. See [`DenseVoxel::write_to`].
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads a grid saved with [`DenseVoxel::save`].
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Visualizes the grid using the `rerun` library.
+    ///
+    /// Occupied cells are logged as boxes labelled with the number of items they hold, and the
+    /// items themselves as points.
+    ///
+    /// # Arguments
+    ///
+    /// * `rerun` - The `rerun::RecordingStream` to log the visualization to.
+    ///
+    /// # Note
+    ///
+    /// This method is only available when the `visualization` feature is enabled.
+    #[cfg(feature = "visualization")]
+    pub fn visualize(&self, rerun: &rerun::RecordingStream) {
+        let mut centers = vec![];
+        let mut labels = vec![];
+        for cell in self.data_on_cpu.chunks(self.max_density as usize) {
+            let count = cell.iter().filter(|item| item.occupied != 0).count();
+            if count == 0 {
+                continue;
+            }
+            let index = ((cell[0].position - self.bottom_left) / self.resolution).floor();
+            centers
+                .push((self.bottom_left + (index + Vec3::splat(0.5)) * self.resolution).to_array());
+            labels.push(count.to_string());
+        }
+        let half_size = [self.resolution / 2.0; 3];
+        rerun
+            .log(
+                "dense_voxel/cells",
+                &rerun::Boxes3D::from_centers_and_half_sizes(
+                    centers.clone(),
+                    vec![half_size; centers.len()],
+                )
+                .with_labels(labels),
+            )
+            .unwrap();
+
+        let points: Vec<_> = self
+            .data_on_cpu
+            .iter()
+            .filter(|item| item.occupied != 0)
+            .map(|item| item.position.to_array())
+            .collect();
+        rerun
+            .log("dense_voxel/items", &rerun::Points3D::new(points))
+            .unwrap();
+    }
+
+    /// Uploads the grid to the GPU. See [`DenseVoxelGpuRepresentation::upload`].
     pub fn to_gpu_buffers(&self, device: &wgpu::Device) -> DenseVoxelGpuRepresentation {
-        let data_on_gpu = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Voxel Grid Data"),
-            contents: bytemuck::cast_slice(&self.data_on_cpu),
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
-        let dense_parameters = DenseVoxelGpuParams {
+        DenseVoxelGpuRepresentation::upload(device, self)
+    }
+}
+
+/// The serde representation of a [`DenseVoxel`], its bounds and occupied items like
+/// [`DenseVoxel::write_to`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DenseVoxelData {
+    top_right: Vec3,
+    bottom_left: Vec3,
+    resolution: f32,
+    max_density: u32,
+    #[serde(default)]
+    layout: VoxelLayout,
+    #[serde(default)]
+    auto_grow: bool,
+    items: Vec<VoxelItem>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DenseVoxel {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DenseVoxelData {
             top_right: self.top_right,
-            width_steps: self.width_steps() as u32,
             bottom_left: self.bottom_left,
-            height_steps: self.height_steps() as u32,
-            max_density: self.max_density,
             resolution: self.resolution,
-            _padding: 0.0,
-            _padding2: 0.0,
-        };
-        let parameters = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Voxel Grid Parameters"),
-            contents: bytemuck::cast_slice(&[dense_parameters]),
-            usage: wgpu::BufferUsages::UNIFORM
-                | wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_DST,
-        });
-        DenseVoxelGpuRepresentation {
-            data_on_gpu,
-            parameters,
-            cpu_parameters: dense_parameters,
-            height_steps: self.height_steps() as u32,
-            width_steps: self.width_steps() as u32,
-            length_steps: self.length_steps() as u32,
+            max_density: self.max_density,
+            layout: self.layout,
+            auto_grow: self.auto_grow,
+            items: self
+                .data_on_cpu
+                .iter()
+                .filter(|item| item.occupied != 0)
+                .copied()
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DenseVoxel {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let data = DenseVoxelData::deserialize(deserializer)?;
+        if data.top_right.cmplt(data.bottom_left).any()
+            || data.resolution <= 0.0
+            || data.max_density == 0
+        {
+            return Err(D::Error::custom("Invalid voxel grid bounds"));
+        }
+        let mut voxel = Self::with_layout(
+            data.top_right,
+            data.bottom_left,
+            data.resolution,
+            data.max_density,
+            data.layout,
+        );
+        voxel.set_auto_grow(data.auto_grow);
+        for item in data.items {
+            voxel
+                .add_item(VoxelItem::with_payload(item.position, item.payload))
+                .map_err(D::Error::custom)?;
         }
+        Ok(voxel)
     }
 }
 
@@ -192,10 +762,16 @@ struct DenseVoxelGpuParams {
     // Word 3
     max_density: u32,
     resolution: f32,
-    _padding: f32,
-    _padding2: f32,
+    length_steps: u32,
+    /// 0 for [`VoxelLayout::Linear`], 1 for [`VoxelLayout::Morton`].
+    cell_layout: u32,
 }
 
+/// A [`DenseVoxel`] resident on the GPU.
+///
+/// Uploading once and keeping this around lets repeated nearest neighbour or collision queries
+/// reuse the same GPU memory. After editing the CPU side grid, push the changes with
+/// [`DenseVoxelGpuRepresentation::update_region`] instead of re-uploading everything.
 pub struct DenseVoxelGpuRepresentation {
     data_on_gpu: wgpu::Buffer,
     parameters: wgpu::Buffer,
@@ -203,33 +779,374 @@ pub struct DenseVoxelGpuRepresentation {
     height_steps: u32,
     width_steps: u32,
     length_steps: u32,
+    layout: VoxelLayout,
+    buffers: BufferPool,
 }
 
 impl DenseVoxelGpuRepresentation {
-    fn prepare_query_points(&self, query_points: &Vec<Vec3>) -> DenseVoxel {
-        let mut query_voxel = DenseVoxel::new(
+    /// Allocates GPU buffers for `voxel` and uploads its contents.
+    pub fn upload(device: &wgpu::Device, voxel: &DenseVoxel) -> Self {
+        let data_on_gpu = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Voxel Grid Data"),
+            contents: bytemuck::cast_slice(&voxel.data_on_cpu),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let dense_parameters = DenseVoxelGpuParams {
+            top_right: voxel.top_right,
+            width_steps: voxel.width_steps() as u32,
+            bottom_left: voxel.bottom_left,
+            height_steps: voxel.height_steps() as u32,
+            max_density: voxel.max_density,
+            resolution: voxel.resolution,
+            length_steps: voxel.length_steps() as u32,
+            cell_layout: match voxel.layout {
+                VoxelLayout::Linear => 0,
+                VoxelLayout::Morton => 1,
+            },
+        };
+        let parameters = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Voxel Grid Parameters"),
+            contents: bytemuck::cast_slice(&[dense_parameters]),
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        DenseVoxelGpuRepresentation {
+            data_on_gpu,
+            parameters,
+            cpu_parameters: dense_parameters,
+            height_steps: voxel.height_steps() as u32,
+            width_steps: voxel.width_steps() as u32,
+            length_steps: voxel.length_steps() as u32,
+            layout: voxel.layout,
+            buffers: BufferPool::default(),
+        }
+    }
+
+    /// Re-uploads the cells `min..max` (exclusive) of `voxel`.
+    ///
+    /// `voxel` must have the same bounds, resolution, density and layout as the grid this
+    /// representation was uploaded from. The region is clamped to the grid.
+    pub fn update_region(
+        &self,
+        queue: &wgpu::Queue,
+        voxel: &DenseVoxel,
+        min: UVec3,
+        max: UVec3,
+    ) -> Result<(), Error> {
+        if voxel.width_steps() as u32 != self.width_steps
+            || voxel.length_steps() as u32 != self.length_steps
+            || voxel.height_steps() as u32 != self.height_steps
+            || voxel.max_density != self.cpu_parameters.max_density
+            || voxel.layout != self.layout
+        {
+            return Err(Error::GridMismatch);
+        }
+        let max = max.min(UVec3::new(
+            self.width_steps,
+            self.length_steps,
+            self.height_steps,
+        ));
+        if min.cmpge(max).any() {
+            return Ok(());
+        }
+        let density = voxel.max_density as usize;
+        let write = |start: usize, end: usize| {
+            queue.write_buffer(
+                &self.data_on_gpu,
+                (start * std::mem::size_of::<VoxelItem>()) as wgpu::BufferAddress,
+                bytemuck::cast_slice(&voxel.data_on_cpu[start..end]),
+            );
+        };
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                match self.layout {
+                    VoxelLayout::Linear => {
+                        // Cells along x are contiguous, so each row is a single write.
+                        let start = voxel.index(min.x as usize, y as usize, z as usize);
+                        let end = voxel.index(max.x as usize - 1, y as usize, z as usize) + density;
+                        write(start, end);
+                    }
+                    VoxelLayout::Morton => {
+                        for x in min.x..max.x {
+                            let start = voxel.index(x as usize, y as usize, z as usize);
+                            write(start, start + density);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Scatters a point cloud straight from a GPU buffer into the grid.
+    ///
+    /// `points` holds `num_points` points of 4 floats, as produced by
+    /// [`crate::lidar::Lidar::render_lidar_pointcloud`], and must be usable as `STORAGE`. Each
+    /// point is moved by `transform`, e.g. the sensor pose, and stored in the first free slot of
+    /// its cell with payload `first_payload + i`, `i` being its index in `points`. All zero
+    /// points, points outside the grid and points in full cells are skipped. Slots are claimed
+    /// atomically, so the order of items within a cell is not deterministic.
+    ///
+    /// Only the GPU copy is updated, use [`Self::download`] to bring a [`DenseVoxel`] up to date.
+    pub fn scatter_points(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        points: &wgpu::Buffer,
+        num_points: u32,
+        transform: &Affine3A,
+        first_payload: u32,
+    ) {
+        if num_points == 0 {
+            return;
+        }
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("scatter.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("dense_voxel_scatter"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Scatter Parameters"),
+            contents: bytemuck::cast_slice(&[ScatterParams {
+                transform: Mat4::from(*transform),
+                num_points,
+                first_payload,
+                _padding: [0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                self.bind(0, 1),
+                [
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: points.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: params.as_entire_binding(),
+                    },
+                ],
+            ]
+            .concat(),
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(num_points.div_ceil(64), 1, 1);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Copies the GPU contents of the grid back into `voxel`.
+    ///
+    /// `voxel` must have the same bounds, resolution, density and layout as the grid this
+    /// representation was uploaded from.
+    pub async fn download(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        voxel: &mut DenseVoxel,
+    ) -> Result<(), Error> {
+        if voxel.width_steps() as u32 != self.width_steps
+            || voxel.length_steps() as u32 != self.length_steps
+            || voxel.height_steps() as u32 != self.height_steps
+            || voxel.max_density != self.cpu_parameters.max_density
+            || voxel.layout != self.layout
+        {
+            return Err(Error::GridMismatch);
+        }
+        voxel.data_on_cpu = read_buffer(device, queue, &self.data_on_gpu).await;
+        Ok(())
+    }
+
+    /// Returns bind group entries exposing the voxel data and grid parameters at the given
+    /// bindings, matching the layout expected by `nn.wgsl`.
+    pub fn bind(
+        &self,
+        data_binding: u32,
+        parameters_binding: u32,
+    ) -> [wgpu::BindGroupEntry<'_>; 2] {
+        [
+            wgpu::BindGroupEntry {
+                binding: data_binding,
+                resource: self.data_on_gpu.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: parameters_binding,
+                resource: self.parameters.as_entire_binding(),
+            },
+        ]
+    }
+
+    /// The buffer holding `max_density` [`VoxelItem`]s per cell.
+    pub fn data_buffer(&self) -> &wgpu::Buffer {
+        &self.data_on_gpu
+    }
+
+    /// The uniform buffer holding the grid bounds and resolution.
+    pub fn parameters_buffer(&self) -> &wgpu::Buffer {
+        &self.parameters
+    }
+
+    fn capacity(&self) -> usize {
+        self.data_on_gpu.size() as usize / std::mem::size_of::<VoxelItem>()
+    }
+
+    /// Bins the query points into a grid shaped like this one.
+    ///
+    /// Also returns where each point was stored, or `None` for points outside the grid or in a
+    /// full cell.
+    fn prepare_query_points(&self, query_points: &[Vec3]) -> (DenseVoxel, Vec<Option<usize>>) {
+        let mut query_voxel = DenseVoxel::with_layout(
             self.cpu_parameters.top_right,
             self.cpu_parameters.bottom_left,
             self.cpu_parameters.resolution,
             self.cpu_parameters.max_density,
+            self.layout,
         );
-        query_points.iter().for_each(|point| {
-            query_voxel
-                .add_item(VoxelItem {
-                    position: *point,
-                    occupied: 1,
-                })
-                .unwrap();
-        });
-        query_voxel
+        let slots = query_points
+            .iter()
+            .map(|point| query_voxel.add_item(VoxelItem::new(*point)).ok())
+            .collect();
+        (query_voxel, slots)
+    }
+
+    /// Queries an approximate nearest neighbour for each point in `points` against the uploaded
+    /// grid.
+    ///
+    /// The result is laid out like the voxel data buffer, with the matches of the query points
+    /// binned into the cell they fall in, and [`NO_MATCH`] in the remaining slots. Use
+    /// [`Self::nearest_neighbour_indices`] to get one match per query point instead.
+    pub async fn nearest_neighbours(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        points: &[Vec3],
+    ) -> Option<Vec<u32>> {
+        let (query_voxel, _) = self.prepare_query_points(points);
+        dense_voxel_nearest_neighbor(device, queue, self, &query_voxel).await
     }
+
+    /// Queries the nearest item for each point in `points`, in order.
+    ///
+    /// Returns the index of the match in the voxel data buffer, see [`DenseVoxel::item`], or
+    /// `None` if the point lies outside the grid, its cell is over `max_density` query points, or
+    /// the grid is empty.
+    pub async fn nearest_neighbour_indices(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        points: &[Vec3],
+    ) -> Vec<Option<u32>> {
+        let (query_voxel, slots) = self.prepare_query_points(points);
+        let Some(matches) = dense_voxel_nearest_neighbor(device, queue, self, &query_voxel).await
+        else {
+            return vec![None; points.len()];
+        };
+        slots
+            .into_iter()
+            .map(|slot| slot.map(|slot| matches[slot]).filter(|m| *m != NO_MATCH))
+            .collect()
+    }
+
+    /// Finds every item within `radius` of each point in `points`.
+    ///
+    /// Returns, for each query, the indices of the matching items in the voxel data buffer. The
+    /// per query results are compacted on the GPU with a prefix sum.
+    pub async fn radius_search(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        points: &[Vec3],
+        radius: f32,
+    ) -> Vec<Vec<u32>> {
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("radius.wgsl"));
+        let queries: Vec<Vec4> = points.iter().map(|p| p.extend(0.0)).collect();
+        let queries = self.buffers.take_init(
+            device,
+            queue,
+            bytemuck::cast_slice(&queries),
+            wgpu::BufferUsages::STORAGE,
+        );
+        let params = self.buffers.take_init(
+            device,
+            queue,
+            bytemuck::bytes_of(&RadiusSearchParams {
+                num_queries: points.len() as u32,
+                radius,
+                _padding: [0.0; 2],
+            }),
+            wgpu::BufferUsages::UNIFORM,
+        );
+        let [data, parameters] = self.bind(0, 1);
+        let matches = run_compacted_query(
+            device,
+            queue,
+            &cs_module,
+            ["count", "write"],
+            [4, 5, 6],
+            &[
+                data,
+                parameters,
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: queries.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+            points.len() as u32,
+        )
+        .await;
+        self.buffers.recycle([queries, params]);
+        matches
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct ScatterParams {
+    transform: Mat4,
+    num_points: u32,
+    first_payload: u32,
+    _padding: [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct RadiusSearchParams {
+    num_queries: u32,
+    radius: f32,
+    _padding: [f32; 2],
 }
 
 /// Queries an approximate nearest neighbour for each point in the `points` vector.
 pub async fn query_nearest_neighbours(voxel: &DenseVoxel, points: Vec<Vec3>) -> Option<Vec<u32>> {
     let instance = wgpu::Instance::default();
     let (_adapter, device, queue) = get_raytracing_gpu(&instance).await;
-    dense_voxel_nearest_neighbor(&device, &queue, voxel, &points).await
+    voxel
+        .to_gpu_buffers(&device)
+        .nearest_neighbours(&device, &queue, &points)
+        .await
 }
 
 pub struct DenseVoxelNearestNeighbors {
@@ -240,7 +1157,7 @@ pub struct DenseVoxelNearestNeighbors {
 impl DenseVoxelNearestNeighbors {
     fn new(device: &wgpu::Device, voxel: &DenseVoxel) -> Self {
         let cs_module = device.create_shader_module(wgpu::include_wgsl!("nn.wgsl"));
-        let results = vec![0xFFFFu32; voxel.capacity()];
+        let results = vec![NO_MATCH; voxel.capacity()];
         let result_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Result"),
             contents: bytemuck::cast_slice(&results),
@@ -268,42 +1185,37 @@ impl DenseVoxelNearestNeighbors {
 async fn dense_voxel_nearest_neighbor(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-    voxel: &DenseVoxel,
-    query_points: &Vec<Vec3>,
+    base: &DenseVoxelGpuRepresentation,
+    query_voxel: &DenseVoxel,
 ) -> Option<Vec<u32>> {
     // Loads the shader from WGSL
     let cs_module = device.create_shader_module(wgpu::include_wgsl!("nn.wgsl"));
 
     // Gets the size in bytes of the buffer.
-    let size = (voxel.capacity() * 4) as wgpu::BufferAddress;
+    let size = (base.capacity() * 4) as wgpu::BufferAddress;
 
-    let results = vec![0xFFFFu32; voxel.capacity()];
-    let result_buffer = device.create_buffer_init(&BufferInitDescriptor {
-        label: Some("Result"),
-        contents: bytemuck::cast_slice(&results),
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_DST
-            | wgpu::BufferUsages::COPY_SRC,
-    });
+    let results = vec![NO_MATCH; base.capacity()];
+    let result_buffer = base.buffers.take_init(
+        device,
+        queue,
+        bytemuck::cast_slice(&results),
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    );
 
-    // Instantiates buffer without data.
+    // Takes a buffer without data from the pool.
     // `usage` of buffer specifies how it can be used:
     //   `BufferUsages::MAP_READ` allows it to be read (outside the shader).
     //   `BufferUsages::COPY_DST` allows it to be the destination of the copy.
-    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
+    let staging_buffer = base.buffers.take(
+        device,
         size,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+        wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+    );
 
     // A bind group defines how buffers are accessed by shaders.
     // It is to WebGPU what a descriptor set is to Vulkan.
     // `binding` here refers to the `binding` of a buffer in the shader (`layout(set = 0, binding = 0) buffer`).
-    let base = voxel.to_gpu_buffers(device);
-    let other = base
-        .prepare_query_points(query_points)
-        .to_gpu_buffers(device);
+    let other = query_voxel.to_gpu_buffers(device);
     // A pipeline specifies the operation of a shader
 
     // Instantiates the pipeline.
@@ -323,23 +1235,19 @@ async fn dense_voxel_nearest_neighbor(
         label: None,
         layout: &bind_group_layout,
         entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: base.data_on_gpu.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: base.parameters.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: other.data_on_gpu.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: result_buffer.as_entire_binding(),
-            },
-        ],
+            base.bind(0, 1),
+            [
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: other.data_on_gpu.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: result_buffer.as_entire_binding(),
+                },
+            ],
+        ]
+        .concat(),
     });
 
     // A command encoder executes one or many pipelines.
@@ -354,11 +1262,8 @@ async fn dense_voxel_nearest_neighbor(
         cpass.set_pipeline(&compute_pipeline);
         cpass.set_bind_group(0, &bind_group, &[]);
         cpass.insert_debug_marker("compute collatz iterations");
-        cpass.dispatch_workgroups(
-            voxel.length_steps() as u32,
-            voxel.width_steps() as u32,
-            voxel.height_steps() as u32,
-        ); // Number of cells to run, the (x,y,z) size of item being processed
+        cpass.dispatch_workgroups(base.width_steps, base.length_steps, base.height_steps);
+        // Number of cells to run, the (x,y,z) size of item being processed
     }
     // Sets adds copy operation to command encoder.
     // Will copy data from storage buffer on GPU to staging buffer on CPU.
@@ -393,6 +1298,7 @@ async fn dense_voxel_nearest_neighbor(
                                 //   delete myPointer;
                                 //   myPointer = NULL;
                                 // It effectively frees the memory
+        base.buffers.recycle([result_buffer, staging_buffer]);
 
         // Returns data from buffer
         Some(result)
@@ -401,14 +1307,41 @@ async fn dense_voxel_nearest_neighbor(
     }
 }
 
+/// Parent of the roots of a [`Tree`].
+pub const NO_PARENT: u32 = u32::MAX;
+
+/// A node of the tree grown by [`execute_experimental_gpu_rrt`].
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
 pub struct Tree {
-    /*x: i32,
-    y: i32,
-    z: i32,*/
     pub position: Vec3,
+    /// Slot of the parent node, or [`NO_PARENT`] for roots and unused slots.
     pub parent: u32,
+    /// Length of the path from the root to this node.
+    pub cost: f32,
+    _padding: [u32; 3],
+}
+
+/// Outcome of [`execute_experimental_gpu_rrt`].
+#[derive(Clone, Debug)]
+pub struct GpuRrtResult {
+    /// One node per slot of the voxel grid, the items of the grid being the roots.
+    pub tree: Vec<Tree>,
+    /// The waypoints from a root to the goal, if it was reached.
+    pub path: Option<Vec<Vec3>>,
+}
+
+impl GpuRrtResult {
+    /// Number of nodes added to the tree.
+    pub fn num_expanded(&self) -> usize {
+        self.tree.iter().filter(|n| n.parent != NO_PARENT).count()
+    }
+
+    /// Length of [`Self::path`].
+    pub fn path_cost(&self) -> Option<f32> {
+        let path = self.path.as_ref()?;
+        Some(path.windows(2).map(|w| w[0].distance(w[1])).sum())
+    }
 }
 
 #[repr(C)]
@@ -420,25 +1353,36 @@ struct State {
     w: u32,
 }
 
+/// Grows a tree from the items of `voxel` on the GPU until one of its nodes sees the goal.
+///
+/// The parent and cost of every node are written by the kernel into GPU buffers laid out like the
+/// slots of the grid, and the path is walked back from the goal on the GPU, so the tree never
+/// has to be reconstructed on the CPU.
+///
+/// `seed` seeds the per-node sampling, or `None` to draw one with [`crate::rng::draw_seed`].
 pub async fn execute_experimental_gpu_rrt(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     voxel: &DenseVoxel,
     lidar: &RayTraceScene,
-) -> Option<Vec<Tree>> {
+    seed: Option<u64>,
+) -> Option<GpuRrtResult> {
     // Loads the shader from WGSL
     let cs_module = device.create_shader_module(wgpu::include_wgsl!("rrt.wgsl"));
 
-    let results = vec![
-        Tree {
-            position: Vec3::new(0.0, 0.0, 0.0),
-            parent: 50000,
-        };
-        voxel.capacity()
-    ];
+    let results: Vec<_> = voxel
+        .data_on_cpu
+        .iter()
+        .map(|item| Tree {
+            position: item.position,
+            parent: NO_PARENT,
+            cost: 0.0,
+            _padding: [0; 3],
+        })
+        .collect();
 
     let result_buffer = device.create_buffer_init(&BufferInitDescriptor {
-        label: Some("Result"),
+        label: Some("RRT Tree"),
         contents: bytemuck::cast_slice(&results),
         usage: wgpu::BufferUsages::STORAGE
             | wgpu::BufferUsages::COPY_DST
@@ -446,21 +1390,23 @@ pub async fn execute_experimental_gpu_rrt(
     });
 
     let found = device.create_buffer_init(&BufferInitDescriptor {
-        label: Some("Result"),
-        contents: bytemuck::cast_slice(&[0u32]),
+        label: Some("RRT Goal Node"),
+        contents: bytemuck::cast_slice(&[NO_PARENT]),
         usage: wgpu::BufferUsages::STORAGE
             | wgpu::BufferUsages::COPY_DST
             | wgpu::BufferUsages::COPY_SRC,
     });
-
-    // Instantiates buffer without data.
-    // `usage` of buffer specifies how it can be used:
-    //   `BufferUsages::MAP_READ` allows it to be read (outside the shader).
-    //   `BufferUsages::COPY_DST` allows it to be the destination of the copy.
-    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
-        size: result_buffer.size(),
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+    // The goal and at most every slot.
+    let path_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("RRT Path"),
+        size: (voxel.capacity() + 1) as wgpu::BufferAddress * 16,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let path_length = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("RRT Path Length"),
+        size: 4,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
         mapped_at_creation: false,
     });
 
@@ -469,7 +1415,7 @@ pub async fn execute_experimental_gpu_rrt(
     // `binding` here refers to the `binding` of a buffer in the shader (`layout(set = 0, binding = 0) buffer`).
     let base = voxel.to_gpu_buffers(device);
 
-    let mut rng = rand::rng();
+    let mut rng = StdRng::seed_from_u64(seed.unwrap_or_else(crate::rng::draw_seed));
     let random_seed: Vec<_> = (0..voxel.capacity())
         .map(|_| State {
             x: rng.random(),
@@ -488,22 +1434,24 @@ pub async fn execute_experimental_gpu_rrt(
     });
     // A pipeline specifies the operation of a shader
 
-    // Instantiates the pipeline.
-    let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: None,
-        layout: None,
-        module: &cs_module,
-        entry_point: Some("main"),
-        compilation_options: Default::default(),
-        cache: None,
-    });
+    // Instantiates the pipelines.
+    let create_pipeline = |entry_point: &str| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+    let compute_pipeline = create_pipeline("main");
+    let path_pipeline = create_pipeline("extract_path");
 
-    // Instantiates the bind group, once again specifying the binding of buffers.
-    let bind_group_layout = compute_pipeline.get_bind_group_layout(0);
-    println!("Bind group layout: {bind_group_layout:?}");
+    // Instantiates the bind groups, once again specifying the binding of buffers.
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: None,
-        layout: &bind_group_layout,
+        layout: &compute_pipeline.get_bind_group_layout(0),
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
@@ -523,18 +1471,41 @@ pub async fn execute_experimental_gpu_rrt(
             },
             wgpu::BindGroupEntry {
                 binding: 4,
-                resource: wgpu::BindingResource::AccelerationStructure(&lidar.tlas_package),
+                resource: wgpu::BindingResource::AccelerationStructure(lidar.tlas()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: found.as_entire_binding(),
+            },
+        ],
+    });
+    let path_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &path_pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: result_buffer.as_entire_binding(),
             },
             wgpu::BindGroupEntry {
                 binding: 5,
                 resource: found.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: path_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: path_length.as_entire_binding(),
+            },
         ],
     });
     let time = std::time::Instant::now();
 
     let mut encoder =
         device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    lidar.encode_tlas_update(&mut encoder);
     {
         let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: None,
@@ -542,55 +1513,35 @@ pub async fn execute_experimental_gpu_rrt(
         });
         cpass.set_pipeline(&compute_pipeline);
         cpass.set_bind_group(0, &bind_group, &[]);
-        cpass.insert_debug_marker("compute collatz iterations");
         cpass.dispatch_workgroups(
             2, //voxel.length_steps() as u32,
             2, //voxel.width_steps() as u32,
             2, //voxel.height_steps() as u32,
         ); // Number of cells to run, the (x,y,z) size of item being processed
+        cpass.set_pipeline(&path_pipeline);
+        cpass.set_bind_group(0, &path_bind_group, &[]);
+        cpass.dispatch_workgroups(1, 1, 1);
     }
-
-    // Sets adds copy operation to command encoder.
-    // Will copy data from storage buffer on GPU to staging buffer on CPU.
-    encoder.copy_buffer_to_buffer(&result_buffer, 0, &staging_buffer, 0, result_buffer.size());
-
-    // Submits command encoder for processing
     queue.submit(Some(encoder.finish()));
 
-    // Note that we're not calling `.await` here.
-    let buffer_slice = staging_buffer.slice(..);
-    // Sets the buffer up for mapping, sending over the result of the mapping back to us when it is finished.
-    let (sender, receiver) = flume::bounded(1);
-    buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-
-    // Poll the device in a blocking manner so that our future resolves.
-    // In an actual application, `device.poll(...)` should
-    // be called in an event loop or on another thread.
-    device.poll(wgpu::PollType::wait()).unwrap();
-
-    // Awaits until `buffer_future` can be read from
-    if let Ok(Ok(())) = receiver.recv_async().await {
-        // Gets contents of buffer
-        let data = buffer_slice.get_mapped_range();
-        // Since contents are got in bytes, this converts these bytes back to u32
-        let result = bytemuck::cast_slice(&data).to_vec();
-
-        // With the current interface, we have to make sure all mapped views are
-        // dropped before we unmap the buffer.
-        drop(data);
-        staging_buffer.unmap(); // Unmaps buffer from memory
-                                // If you are familiar with C++ these 2 lines can be thought of similarly to:
-                                //   delete myPointer;
-                                //   myPointer = NULL;
-                                // It effectively frees the memory
-
-        println!("Time taken: {:?}", time.elapsed());
-
-        // Returns data from buffer
-        Some(result)
+    // Only the path is needed to drive the robot, so read it first.
+    let path_length: Vec<u32> = read_buffer(device, queue, &path_length).await;
+    let path = if path_length[0] > 0 {
+        let waypoints: Vec<Vec4> = read_buffer(device, queue, &path_buffer).await;
+        Some(
+            waypoints[..path_length[0] as usize]
+                .iter()
+                .rev()
+                .map(|w| w.truncate())
+                .collect(),
+        )
     } else {
-        panic!("failed to run compute on gpu!")
-    }
+        None
+    };
+    let tree = read_buffer(device, queue, &result_buffer).await;
+    println!("Time taken: {:?}", time.elapsed());
+
+    Some(GpuRrtResult { tree, path })
 }
 
 #[cfg(test)]
@@ -600,24 +1551,15 @@ async fn test_voxel_nn() {
         DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 10);
 
     voxel_grid
-        .add_item(VoxelItem {
-            position: Vec3::new(0.5, 0.5, 0.5),
-            occupied: 0,
-        })
+        .add_item(VoxelItem::new(Vec3::new(0.5, 0.5, 0.5)))
         .unwrap();
 
     voxel_grid
-        .add_item(VoxelItem {
-            position: Vec3::new(1.55, 1.55, 1.55),
-            occupied: 0,
-        })
+        .add_item(VoxelItem::new(Vec3::new(1.55, 1.55, 1.55)))
         .unwrap();
 
     let target = voxel_grid
-        .add_item(VoxelItem {
-            position: Vec3::new(1.6, 1.6, 1.6),
-            occupied: 0,
-        })
+        .add_item(VoxelItem::new(Vec3::new(1.6, 1.6, 1.6)))
         .unwrap();
 
     let items = voxel_grid.get_items_in_cell(1, 1, 1);
@@ -644,7 +1586,7 @@ async fn test_voxel_nn() {
         .await
         .unwrap();
     println!("Time taken: {:?}", times_now.elapsed());
-    let result: Vec<_> = result.iter().filter(|p| **p != 0xFFFFu32).collect();
+    let result: Vec<_> = result.iter().filter(|p| **p != NO_MATCH).collect();
     assert_eq!(result.len(), 1);
     assert_eq!(*result[0], target as u32);
     //run().await;
@@ -657,24 +1599,15 @@ async fn test_voxel_nn_far_away() {
         DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 10);
 
     voxel_grid
-        .add_item(VoxelItem {
-            position: Vec3::new(0.5, 0.5, 0.5),
-            occupied: 0,
-        })
+        .add_item(VoxelItem::new(Vec3::new(0.5, 0.5, 0.5)))
         .unwrap();
 
     voxel_grid
-        .add_item(VoxelItem {
-            position: Vec3::new(1.55, 1.55, 1.55),
-            occupied: 0,
-        })
+        .add_item(VoxelItem::new(Vec3::new(1.55, 1.55, 1.55)))
         .unwrap();
 
     let target = voxel_grid
-        .add_item(VoxelItem {
-            position: Vec3::new(1.6, 1.6, 1.6),
-            occupied: 0,
-        })
+        .add_item(VoxelItem::new(Vec3::new(1.6, 1.6, 1.6)))
         .unwrap();
 
     let items = voxel_grid.get_items_in_cell(1, 1, 1);
@@ -701,12 +1634,35 @@ async fn test_voxel_nn_far_away() {
         .await
         .unwrap();
     println!("Time taken: {:?}", times_now.elapsed());
-    let result: Vec<_> = result.iter().filter(|p| **p != 0xFFFFu32).collect();
+    let result: Vec<_> = result.iter().filter(|p| **p != NO_MATCH).collect();
     assert_eq!(result.len(), 1);
     assert_eq!(*result[0], target as u32);
     //run().await;
 }
 
+#[cfg(test)]
+#[tokio::test]
+async fn test_voxel_nn_across_cell_boundary() {
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 10);
+
+    // Just across the boundary from the query.
+    let target = voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(1.45, 1.6, 1.6)))
+        .unwrap();
+    // In the query's own cell, but further away.
+    voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(1.95, 1.6, 1.6)))
+        .unwrap();
+
+    let result = query_nearest_neighbours(&voxel_grid, vec![Vec3::new(1.55, 1.6, 1.6)])
+        .await
+        .unwrap();
+    let result: Vec<_> = result.iter().filter(|p| **p != NO_MATCH).collect();
+    assert_eq!(result.len(), 1);
+    assert_eq!(*result[0], target as u32);
+}
+
 #[cfg(test)]
 #[tokio::test]
 async fn test_voxel_rrt() {
@@ -716,10 +1672,7 @@ async fn test_voxel_rrt() {
         DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 10);
 
     voxel_grid
-        .add_item(VoxelItem {
-            position: Vec3::new(0.5, 0.5, 0.5),
-            occupied: 0,
-        })
+        .add_item(VoxelItem::new(Vec3::new(0.5, 0.5, 0.5)))
         .unwrap();
 
     for i in 0..5 {
@@ -751,19 +1704,456 @@ async fn test_voxel_rrt() {
                             z: z as f32 + 0.5,
                         },
                     ),
+                    id: 0,
                 })
             })
         })
         .collect();
 
-    let scene = RayTraceScene::new(&device, &queue, &vec![cube], &instances).await;
-    let one = execute_experimental_gpu_rrt(&device, &queue, &voxel_grid, &scene)
+    let scene = RayTraceScene::new(&device, &queue, &[cube], &instances)
+        .await
+        .unwrap();
+    // Seeded so that the tree, and whether it reaches the goal, is the same on every run.
+    let one = execute_experimental_gpu_rrt(&device, &queue, &voxel_grid, &scene, Some(3))
+        .await
+        .unwrap();
+    println!("{:?}", one.tree.len());
+    println!("States expanded {:?}", one.num_expanded());
+
+    for node in one.tree.iter().filter(|n| n.parent != NO_PARENT) {
+        assert!((node.parent as usize) < one.tree.len());
+        let parent = one.tree[node.parent as usize];
+        assert!(node.cost >= node.position.distance(parent.position) - 1e-3);
+    }
+    let path = one.path.as_ref().expect("goal reachable in this scene");
+    assert!(path.len() >= 2);
+    assert_eq!(path.last(), Some(&Vec3::new(4.0, 4.0, 3.0)));
+    println!("Path cost: {:?}", one.path_cost());
+}
+
+#[cfg(test)]
+#[test]
+fn test_clear_ray() {
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 10);
+    for x in [0.75, 1.75, 2.75, 3.75] {
+        voxel_grid
+            .add_item(VoxelItem::new(Vec3::new(x, 1.25, 1.25)))
+            .unwrap();
+    }
+    // Off the ray, must survive.
+    voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(1.75, 3.25, 1.25)))
+        .unwrap();
+
+    let cleared = voxel_grid.clear_rays(Vec3::new(0.1, 1.25, 1.25), &[Vec3::new(3.75, 1.25, 1.25)]);
+    assert_eq!(cleared, 3);
+    assert!(voxel_grid
+        .get_items_in_cell_position(Vec3::new(1.75, 1.25, 1.25))
+        .is_empty());
+    assert_eq!(
+        voxel_grid
+            .get_items_in_cell_position(Vec3::new(3.75, 1.25, 1.25))
+            .len(),
+        1
+    );
+    assert_eq!(
+        voxel_grid
+            .get_items_in_cell_position(Vec3::new(1.75, 3.25, 1.25))
+            .len(),
+        1
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_gpu_representation_update_region() {
+    let instance = wgpu::Instance::default();
+    let (_adapter, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 10);
+    let gpu_voxel = voxel_grid.to_gpu_buffers(&device);
+
+    let queries = vec![Vec3::new(1.65, 1.65, 1.65)];
+    let result = gpu_voxel
+        .nearest_neighbours(&device, &queue, &queries)
+        .await
+        .unwrap();
+    assert!(result.iter().all(|p| *p == NO_MATCH));
+
+    let target = voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(1.6, 1.6, 1.6)))
+        .unwrap();
+    gpu_voxel
+        .update_region(
+            &queue,
+            &voxel_grid,
+            UVec3::new(3, 3, 3),
+            UVec3::new(4, 4, 4),
+        )
+        .unwrap();
+
+    let result = gpu_voxel
+        .nearest_neighbours(&device, &queue, &queries)
+        .await
+        .unwrap();
+    let result: Vec<_> = result.iter().filter(|p| **p != NO_MATCH).collect();
+    assert_eq!(result.len(), 1);
+    assert_eq!(*result[0], target as u32);
+}
+
+#[cfg(test)]
+#[test]
+fn test_remove_and_clear() {
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    let positions = [
+        Vec3::new(1.55, 1.55, 1.55),
+        Vec3::new(1.6, 1.6, 1.6),
+        Vec3::new(1.65, 1.65, 1.65),
+    ];
+    let indices: Vec<_> = positions
+        .iter()
+        .map(|position| voxel_grid.add_item(VoxelItem::new(*position)).unwrap())
+        .collect();
+
+    let removed = voxel_grid.remove_item(indices[0]).unwrap();
+    assert_eq!(removed.position, positions[0]);
+    assert!(voxel_grid.remove_item(indices[2]).is_err());
+    let items = voxel_grid.get_items_in_cell(3, 3, 3);
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].position, positions[1]);
+    assert_eq!(items[1].position, positions[2]);
+
+    assert_eq!(voxel_grid.clear_cell(3, 3, 3), 2);
+    assert!(voxel_grid.get_items_in_cell(3, 3, 3).is_empty());
+
+    voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(0.5, 0.5, 0.5)))
+        .unwrap();
+    voxel_grid.clear_all();
+    assert!(voxel_grid.occupancy_grid().iter().all(|o| *o == 0));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_radius_search() {
+    let instance = wgpu::Instance::default();
+    let (_adapter, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 10);
+    let mut near: Vec<_> = [Vec3::new(1.6, 1.6, 1.6), Vec3::new(2.4, 1.6, 1.6)]
+        .iter()
+        .map(|position| voxel_grid.add_item(VoxelItem::new(*position)).unwrap() as u32)
+        .collect();
+    voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(4.5, 1.6, 1.6)))
+        .unwrap();
+
+    let mut result = voxel_grid
+        .to_gpu_buffers(&device)
+        .radius_search(&device, &queue, &[Vec3::new(2.0, 1.6, 1.6)], 1.0)
+        .await;
+    result[0].sort();
+    near.sort();
+    assert_eq!(result, vec![near]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_serialization_round_trip() {
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 4.0, 3.0), Vec3::new(-1.0, 0.0, 0.0), 0.5, 4);
+    for position in [
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(1.55, 1.55, 1.55),
+        Vec3::new(1.6, 1.6, 1.6),
+    ] {
+        voxel_grid.add_item(VoxelItem::new(position)).unwrap();
+    }
+
+    let mut bytes = vec![];
+    voxel_grid.write_to(&mut bytes).unwrap();
+    let loaded = DenseVoxel::read_from(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.capacity(), voxel_grid.capacity());
+    assert_eq!(loaded.occupancy_grid(), voxel_grid.occupancy_grid());
+    assert_eq!(
+        loaded
+            .get_items_in_cell_position(Vec3::new(1.6, 1.6, 1.6))
+            .len(),
+        2
+    );
+
+    assert!(DenseVoxel::read_from(&bytes[1..]).is_err());
+}
+
+#[cfg(all(test, feature = "serde"))]
+#[test]
+fn test_serde_round_trip() {
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 4.0, 3.0), Vec3::new(-1.0, 0.0, 0.0), 0.5, 4);
+    voxel_grid
+        .add_item(VoxelItem::with_payload(Vec3::new(1.6, 1.6, 1.6), 7))
+        .unwrap();
+
+    let json = serde_json::to_string(&voxel_grid).unwrap();
+    let loaded: DenseVoxel = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.occupancy_grid(), voxel_grid.occupancy_grid());
+    assert_eq!(
+        loaded.get_items_in_cell_position(Vec3::new(1.6, 1.6, 1.6))[0].payload(),
+        7
+    );
+
+    // Items outside the bounds of a grid that does not grow are rejected.
+    let json = json.replace("1.6", "-9.0");
+    assert!(serde_json::from_str::<DenseVoxel>(&json).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_auto_grow() {
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(0.25, 0.25, 0.25)))
+        .unwrap();
+    let outside = VoxelItem::new(Vec3::new(-3.2, 0.25, 4.0));
+    assert!(voxel_grid.add_item(outside).is_err());
+
+    voxel_grid.set_auto_grow(true);
+    voxel_grid.add_item(outside).unwrap();
+    assert!(voxel_grid.bottom_left().x <= -3.2);
+    assert!(voxel_grid.height() > 4.0);
+    // Cells stay aligned with the original grid.
+    assert_eq!(voxel_grid.bottom_left().x % 0.5, 0.0);
+    assert_eq!(
+        voxel_grid
+            .get_items_in_cell_position(Vec3::new(0.25, 0.25, 0.25))
+            .len(),
+        1
+    );
+    assert_eq!(
+        voxel_grid
+            .get_items_in_cell_position(outside.position)
+            .len(),
+        1
+    );
+
+    let mut voxel_grid = DenseVoxel::with_layout(
+        Vec3::new(2.0, 2.0, 2.0),
+        Vec3::ZERO,
+        1.0,
+        1,
+        VoxelLayout::Morton,
+    );
+    voxel_grid.set_auto_grow(true);
+    let far = VoxelItem::new(Vec3::new(MAX_MORTON_STEPS as f32, 0.5, 0.5));
+    assert!(matches!(
+        voxel_grid.add_item(far),
+        Err(Error::OutOfBounds(_))
+    ));
+    assert_eq!(voxel_grid.width(), 2.0);
+    voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(20.0, 0.5, 0.5)))
+        .unwrap();
+    assert_eq!(voxel_grid.add_items(&[far]), [None]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_item_payload() {
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    let index = voxel_grid
+        .add_item(VoxelItem::with_payload(Vec3::new(1.6, 1.6, 1.6), 42))
+        .unwrap();
+    assert_eq!(voxel_grid.item(index).unwrap().payload(), 42);
+    assert!(voxel_grid.item(index + 1).is_none());
+
+    let mut bytes = vec![];
+    voxel_grid.write_to(&mut bytes).unwrap();
+    let loaded = DenseVoxel::read_from(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.item(index).unwrap().payload(), 42);
+}
+
+#[cfg(test)]
+#[test]
+fn test_add_items() {
+    // Five items per cell, one more than fits, and one item outside the grid.
+    let items: Vec<_> = (0..50)
+        .map(|i| VoxelItem::with_payload(Vec3::new(0.1 * i as f32, 1.2, 2.3), i))
+        .chain([VoxelItem::new(Vec3::new(-1.0, 1.0, 1.0))])
+        .collect();
+
+    let mut one_by_one =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    let expected: Vec<_> = items
+        .iter()
+        .map(|item| one_by_one.add_item(*item).ok())
+        .collect();
+    let mut bulk = DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    assert_eq!(bulk.add_items(&items), expected);
+    assert_eq!(bulk.data_on_cpu, one_by_one.data_on_cpu);
+
+    let mut growing = DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    growing.set_auto_grow(true);
+    let indices = growing.add_items(&items);
+    assert_eq!(indices.iter().filter(|index| index.is_none()).count(), 10);
+    assert!(indices.last().unwrap().is_some());
+    assert!(growing.bottom_left().x <= -1.0);
+}
+
+#[cfg(test)]
+#[test]
+fn test_add_item_on_top_right() {
+    let top_right = VoxelItem::new(Vec3::new(5.0, 5.0, 5.0));
+    let mut one_by_one =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    let index = one_by_one.add_item(top_right).unwrap();
+    // The item belongs to the last cell rather than one past it.
+    let last_cell = one_by_one.get_items_in_cell_position(Vec3::splat(4.75));
+    assert_eq!(
+        last_cell,
+        vec![VoxelItem {
+            occupied: 1,
+            ..top_right
+        }]
+    );
+
+    let mut bulk = DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    assert_eq!(bulk.add_items(&[top_right]), vec![Some(index)]);
+
+    // A grid without cells along an axis holds nothing.
+    let mut flat = DenseVoxel::new(Vec3::new(5.0, 5.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    let item = VoxelItem::new(Vec3::new(1.0, 1.0, 0.0));
+    assert!(flat.add_item(item).is_err());
+    assert_eq!(flat.add_items(&[item]), vec![None]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_scatter_points() {
+    let instance = wgpu::Instance::default();
+    let (_adapter, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let mut voxel_grid =
+        DenseVoxel::new(Vec3::new(5.0, 5.0, 5.0), Vec3::new(0.0, 0.0, 0.0), 0.5, 4);
+    let gpu_voxel = voxel_grid.to_gpu_buffers(&device);
+
+    // Six points in one cell, a miss and a point outside the grid.
+    let mut points: Vec<Vec4> = (0..6)
+        .map(|i| Vec4::new(0.1 + 0.01 * i as f32, 0.2, 0.3, 1.0))
+        .collect();
+    points.push(Vec4::ZERO);
+    points.push(Vec4::new(10.0, 0.0, 0.0, 1.0));
+    let buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&points),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let pose = Affine3A::from_translation(Vec3::new(1.0, 1.0, 1.0));
+    gpu_voxel.scatter_points(&device, &queue, &buffer, points.len() as u32, &pose, 100);
+    gpu_voxel
+        .download(&device, &queue, &mut voxel_grid)
         .await
         .unwrap();
-    println!("{:?}", one.len());
-    let result: Vec<_> = one.iter().filter(|p| p.parent > 50000).collect();
-    println!("Valid end goals: {:?}", result.len());
 
-    let result: Vec<_> = one.iter().filter(|p| p.parent != 50000).collect();
-    println!("States expanded {:?}", result.len());
+    // The cell only has room for four of the six points.
+    let items = voxel_grid.get_items_in_cell_position(Vec3::new(1.1, 1.2, 1.3));
+    assert_eq!(items.len(), 4);
+    for item in items {
+        let i = item.payload() - 100;
+        assert!(i < 6);
+        assert!(item
+            .position()
+            .abs_diff_eq(pose.transform_point3(points[i as usize].truncate()), 1e-6));
+    }
+    assert_eq!(voxel_grid.occupancy_grid().iter().sum::<u32>(), 1);
+}
+
+#[cfg(test)]
+#[test]
+fn test_morton_layout() {
+    let mut voxel_grid = DenseVoxel::with_layout(
+        Vec3::new(3.0, 2.0, 1.5),
+        Vec3::new(0.0, 0.0, 0.0),
+        0.5,
+        2,
+        VoxelLayout::Morton,
+    );
+    assert_eq!(voxel_grid.capacity(), 8 * 8 * 8 * 2);
+    for z in 0..voxel_grid.height_steps() {
+        for y in 0..voxel_grid.length_steps() {
+            for x in 0..voxel_grid.width_steps() {
+                assert_eq!(voxel_grid.from_index(voxel_grid.index(x, y, z)), (x, y, z));
+            }
+        }
+    }
+    // Neighbouring cells of a 2x2x2 block are contiguous.
+    assert_eq!(voxel_grid.index(1, 1, 1), 7 * 2);
+
+    let position = Vec3::new(2.6, 1.1, 0.7);
+    let index = voxel_grid.add_item(VoxelItem::new(position)).unwrap();
+    assert_eq!(voxel_grid.from_index(index), (5, 2, 1));
+    assert_eq!(voxel_grid.get_items_in_cell_position(position).len(), 1);
+
+    let mut bytes = vec![];
+    voxel_grid.write_to(&mut bytes).unwrap();
+    let loaded = DenseVoxel::read_from(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.layout(), VoxelLayout::Morton);
+    assert_eq!(loaded.item(index).unwrap().position(), position);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_voxel_nn_morton() {
+    let mut voxel_grid = DenseVoxel::with_layout(
+        Vec3::new(5.0, 5.0, 5.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        0.5,
+        10,
+        VoxelLayout::Morton,
+    );
+    voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(0.5, 0.5, 0.5)))
+        .unwrap();
+    let target = voxel_grid
+        .add_item(VoxelItem::new(Vec3::new(3.6, 1.6, 2.6)))
+        .unwrap();
+
+    let instance = wgpu::Instance::default();
+    let (_adapter, device, queue) = get_raytracing_gpu(&instance).await;
+    let result = voxel_grid
+        .to_gpu_buffers(&device)
+        .nearest_neighbour_indices(&device, &queue, &[Vec3::new(3.2, 2.1, 2.4)])
+        .await;
+    assert_eq!(result, vec![Some(target as u32)]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_voxel_nn_index_above_u16() {
+    // 16^3 cells of 16 slots, so the last slot of the last cell is 0xFFFF.
+    let mut voxel_grid = DenseVoxel::new(Vec3::splat(16.0), Vec3::ZERO, 1.0, 16);
+    let mut target = 0;
+    for i in 0..16 {
+        target = voxel_grid
+            .add_item(VoxelItem::new(Vec3::new(
+                15.5,
+                15.5,
+                15.05 + i as f32 * 0.05,
+            )))
+            .unwrap();
+    }
+    assert_eq!(target, 0xFFFF);
+
+    let instance = wgpu::Instance::default();
+    let (_adapter, device, queue) = get_raytracing_gpu(&instance).await;
+    let result = voxel_grid
+        .to_gpu_buffers(&device)
+        .nearest_neighbour_indices(&device, &queue, &[Vec3::new(15.5, 15.5, 15.95)])
+        .await;
+    assert_eq!(result, vec![Some(target as u32)]);
 }
@@ -10,14 +10,15 @@ struct DenseVoxelGpuParams {
     // Word 3
     max_density: u32,
     resolution: f32,
-    _padding: f32,
-    _padding2: f32
+    length_steps: u32,
+    cell_layout: u32
 }
 
 
 struct VoxelNode {
     position: vec3<f32>,
-    occupied: u32
+    occupied: u32,
+    payload: u32
 }
 
 struct SearchResult {
@@ -41,139 +42,99 @@ var<storage, read_write> query_grid: array<VoxelNode>;
 @binding(3)
 var<storage, read_write> query_matches: array<u32>; 
 
-fn to_index(pos: vec3<u32>) -> u32 {
-    return (pos.x + pos.y * uniforms_base.width_steps + pos.z * uniforms_base.width_steps * uniforms_base.height_steps) * uniforms_base.max_density;
-}
-
-
-/// Very inefficient way to find approximate closest point in the grid
-fn get_closest_point(pos: vec3<f32>, starting_cell: vec3<u32>) -> SearchResult {
-    var step: u32 = 0;
-    var found: u32 = 0;
-    let index = to_index(starting_cell + vec3<u32>(step, 0, 0));
-    let result = get_closest_point_in_cell(pos, index);
-    if result.found == 1 {
-        return result;
-    }
-    step = step + 1;
-    while found == 0 {
-        var start_x: u32 = 0;
-        if starting_cell.x > step {
-            start_x = starting_cell.x - step;
-        }
-
-        var end_x: u32 = uniforms_base.width_steps;
-        if starting_cell.x + step < uniforms_base.width_steps {
-            end_x = starting_cell.x + step;
-        }
-
-        var start_y: u32 = 0;
-        if starting_cell.y > step {
-            start_y = starting_cell.y - step;
-        }
+const LAYOUT_MORTON: u32 = 1u;
 
-        var end_y: u32 = uniforms_base.width_steps;
-        if starting_cell.y + step < uniforms_base.width_steps {
-            end_y = starting_cell.y + step;
-        }
+/// Must match `NO_MATCH` in mod.rs.
+const NO_MATCH: u32 = 0xFFFFFFFFu;
 
-        var start_z: u32 = 0;
-        if starting_cell.z > step {
-            start_z = starting_cell.z - step;
-        }
+/// Inserts two zero bits between each of the lower 10 bits of `v`.
+fn spread_bits(v: u32) -> u32 {
+    var x = v & 0x3ffu;
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
 
-        var end_z: u32 = uniforms_base.width_steps;
-        if starting_cell.y + step < uniforms_base.width_steps {
-            end_z = starting_cell.z + step;
-        }
+/// Must match `VoxelLayout::cell_offset` in mod.rs.
+fn cell_offset(pos: vec3<u32>) -> u32 {
+    if uniforms_base.cell_layout == LAYOUT_MORTON {
+        return spread_bits(pos.x) | (spread_bits(pos.y) << 1u) | (spread_bits(pos.z) << 2u);
+    }
+    return pos.x + pos.y * uniforms_base.width_steps + pos.z * uniforms_base.width_steps * uniforms_base.length_steps;
+}
 
+fn to_index(pos: vec3<u32>) -> u32 {
+    return cell_offset(pos) * uniforms_base.max_density;
+}
 
 
-        var i = start_x;
-        var j = start_y;
-        var k = start_z;
-        while i <= end_x { 
-            j = start_y;
-            while j <= end_y {
-                k = start_z;
-                while k <= end_z {    
-                    let index = to_index(vec3<u32>(i, j, k));
-                    let result = get_closest_point_in_cell(pos, index);
-                    if result.found == 1 {
-                        return result;
-                    }
-                    k += 1;
-                }
-                j += 1;
-            }
-            i += step;
-        }
+fn grid_steps() -> vec3<i32> {
+    return vec3<i32>(ceil((uniforms_base.top_right - uniforms_base.bottom_left) / uniforms_base.resolution));
+}
 
-        j = start_y;
-        while j <= end_y {
-            i = start_x;
-            while i <= end_x {
-                k = start_z;
-                while k <= end_z {     
-                    let index = to_index(vec3<u32>(i, j, k));
-                    let result = get_closest_point_in_cell(pos, index);
-                    if result.found == 1 {
-                        return result;
+/// Finds the closest point in the grid by scanning shells of cells of growing Chebyshev radius
+/// around `starting_cell`.
+///
+/// Any item in shell `ring + 1` is at least `ring * resolution` away from a query inside the
+/// starting cell, so the search stops once the best match is closer than that.
+fn get_closest_point(pos: vec3<f32>, starting_cell: vec3<u32>) -> SearchResult {
+    let steps = grid_steps();
+    let center = vec3<i32>(starting_cell);
+    let max_ring = max(steps.x, max(steps.y, steps.z));
+    var best = SearchResult(0, 0);
+    var best_distance: f32 = 3.4e38;
+    for (var ring: i32 = 0; ring <= max_ring; ring++) {
+        let lo = max(center - vec3<i32>(ring), vec3<i32>(0));
+        let hi = min(center + vec3<i32>(ring), steps - 1);
+        for (var z = lo.z; z <= hi.z; z++) {
+            for (var y = lo.y; y <= hi.y; y++) {
+                for (var x = lo.x; x <= hi.x; x++) {
+                    let offset = abs(vec3<i32>(x, y, z) - center);
+                    if max(offset.x, max(offset.y, offset.z)) != ring {
+                        continue;
                     }
-                    k += 1;
-                }
-                i += 1;
-            }
-            j += step;
-        }
-
-        k = start_z;
-        while k <= end_z {
-            i = start_x;
-            while i <= end_x {
-                j = start_y;
-                while j <= end_y {    
-                    let index = to_index(vec3<u32>(i, j, k));
-                    let result = get_closest_point_in_cell(pos, index);
-                    if result.found == 1 {
-                        return result;
+                    let result = get_closest_point_in_cell(pos, to_index(vec3<u32>(vec3<i32>(x, y, z))));
+                    if result.found == 1 && result.distance < best_distance {
+                        best_distance = result.distance;
+                        best = SearchResult(1, result.index);
                     }
-                    j += 1;
                 }
-                i += 1;
             }
-            k += step;
         }
-        step = step + 1;
-        if step == uniforms_base.width_steps {
-            return SearchResult(0, 0);
+        if best.found == 1 && best_distance <= f32(ring) * uniforms_base.resolution {
+            break;
         }
     }
-    return SearchResult(0, 0);
+    return best;
 }
+
+struct CellSearchResult {
+    found: u32,
+    index: u32,
+    distance: f32
+}
+
 /// Check in a cell for the closest point to a given position
-fn get_closest_point_in_cell(pos: vec3<f32>, index: u32) -> SearchResult{
-    var i: u32 = 0;
-    var nearest_distance: f32 = 10000.0;
+fn get_closest_point_in_cell(pos: vec3<f32>, index: u32) -> CellSearchResult {
+    var nearest_distance: f32 = 3.4e38;
     var nearest_index: u32 = uniforms_base.max_density;
-    while i < uniforms_base.max_density {
+    for (var i: u32 = 0; i < uniforms_base.max_density; i++) {
         let node = base_grid[index + i];
-        if (node.occupied == 0) {
-            if uniforms_base.max_density == nearest_index {
-                return SearchResult(0, 0);
-            }
-            else {
-                return SearchResult(1, nearest_index + index);
-            }
+        if node.occupied == 0 {
+            break;
         }
         let distance = length(node.position - pos);
         if distance < nearest_distance {
             nearest_distance = distance;
             nearest_index = i;
         }
-        i = i + 1;
     }
-    return SearchResult(0, 0);
+    if nearest_index == uniforms_base.max_density {
+        return CellSearchResult(0, 0, nearest_distance);
+    }
+    return CellSearchResult(1, nearest_index + index, nearest_distance);
 }
 
 
@@ -191,6 +152,8 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         let result = get_closest_point(query_point.position, global_id);
         if result.found == 1 {
             query_matches[query_index + i] = result.index;
+        } else {
+            query_matches[query_index + i] = NO_MATCH;
         }
         i = i + 1;
     }
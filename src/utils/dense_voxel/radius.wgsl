@@ -0,0 +1,160 @@
+struct DenseVoxelGpuParams {
+    // Word 1
+    top_right: vec3<f32>,
+    width_steps: u32,
+
+    // Word 2
+    bottom_left: vec3<f32>,
+    height_steps: u32,
+
+    // Word 3
+    max_density: u32,
+    resolution: f32,
+    length_steps: u32,
+    cell_layout: u32
+}
+
+struct VoxelNode {
+    position: vec3<f32>,
+    occupied: u32,
+    payload: u32
+}
+
+struct RadiusSearchParams {
+    num_queries: u32,
+    radius: f32,
+    _padding: vec2<f32>,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> base_grid: array<VoxelNode>;
+
+@group(0)
+@binding(1)
+var<uniform> uniforms_base: DenseVoxelGpuParams;
+
+@group(0)
+@binding(2)
+var<storage, read> queries: array<vec4<f32>>;
+
+@group(0)
+@binding(3)
+var<uniform> params: RadiusSearchParams;
+
+@group(0)
+@binding(4)
+var<storage, read_write> counts: array<u32>;
+
+/// Inclusive prefix sum of `counts`.
+@group(0)
+@binding(5)
+var<storage, read> offsets: array<u32>;
+
+@group(0)
+@binding(6)
+var<storage, read_write> matches: array<u32>;
+
+const LAYOUT_MORTON: u32 = 1u;
+
+/// Inserts two zero bits between each of the lower 10 bits of `v`.
+fn spread_bits(v: u32) -> u32 {
+    var x = v & 0x3ffu;
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
+
+/// Must match `VoxelLayout::cell_offset` in mod.rs.
+fn cell_offset(pos: vec3<u32>) -> u32 {
+    if uniforms_base.cell_layout == LAYOUT_MORTON {
+        return spread_bits(pos.x) | (spread_bits(pos.y) << 1u) | (spread_bits(pos.z) << 2u);
+    }
+    return pos.x + pos.y * uniforms_base.width_steps + pos.z * uniforms_base.width_steps * uniforms_base.length_steps;
+}
+
+fn to_index(pos: vec3<u32>) -> u32 {
+    return cell_offset(pos) * uniforms_base.max_density;
+}
+
+struct CellRange {
+    lo: vec3<u32>,
+    hi: vec3<u32>,
+    empty: bool,
+}
+
+/// Cells overlapping the axis aligned box around the search sphere, clamped to the grid.
+fn cell_range(pos: vec3<f32>) -> CellRange {
+    let dims = vec3<i32>(ceil((uniforms_base.top_right - uniforms_base.bottom_left) / uniforms_base.resolution));
+    let lo = vec3<i32>(floor((pos - params.radius - uniforms_base.bottom_left) / uniforms_base.resolution));
+    let hi = vec3<i32>(floor((pos + params.radius - uniforms_base.bottom_left) / uniforms_base.resolution));
+    let lo_clamped = max(lo, vec3<i32>(0));
+    let hi_clamped = min(hi, dims - 1);
+    return CellRange(vec3<u32>(lo_clamped), vec3<u32>(max(hi_clamped, vec3<i32>(0))), any(lo_clamped > hi_clamped));
+}
+
+@compute
+@workgroup_size(64)
+fn count(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_queries {
+        return;
+    }
+    let pos = queries[global_id.x].xyz;
+    let range = cell_range(pos);
+    var found: u32 = 0;
+    if !range.empty {
+        for (var z = range.lo.z; z <= range.hi.z; z++) {
+            for (var y = range.lo.y; y <= range.hi.y; y++) {
+                for (var x = range.lo.x; x <= range.hi.x; x++) {
+                    let base = to_index(vec3<u32>(x, y, z));
+                    for (var i: u32 = 0; i < uniforms_base.max_density; i++) {
+                        let node = base_grid[base + i];
+                        if node.occupied == 0 {
+                            break;
+                        }
+                        if distance(node.position, pos) <= params.radius {
+                            found += 1u;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    counts[global_id.x] = found;
+}
+
+@compute
+@workgroup_size(64)
+fn write(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_queries {
+        return;
+    }
+    let pos = queries[global_id.x].xyz;
+    let range = cell_range(pos);
+    if range.empty {
+        return;
+    }
+    var next: u32 = 0;
+    if global_id.x > 0 {
+        next = offsets[global_id.x - 1];
+    }
+    for (var z = range.lo.z; z <= range.hi.z; z++) {
+        for (var y = range.lo.y; y <= range.hi.y; y++) {
+            for (var x = range.lo.x; x <= range.hi.x; x++) {
+                let base = to_index(vec3<u32>(x, y, z));
+                for (var i: u32 = 0; i < uniforms_base.max_density; i++) {
+                    let node = base_grid[base + i];
+                    if node.occupied == 0 {
+                        break;
+                    }
+                    if distance(node.position, pos) <= params.radius {
+                        matches[next] = base + i;
+                        next += 1u;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -10,14 +10,15 @@ struct DenseVoxelGpuParams {
     // Word 3
     max_density: u32,
     resolution: f32,
-    _padding: f32,
-    _padding2: f32
+    length_steps: u32,
+    cell_layout: u32
 }
 
 
 struct VoxelNode {
     position: vec3<f32>,
-    occupied: u32
+    occupied: u32,
+    payload: u32
 }
 
 struct SearchResult {
@@ -25,11 +26,21 @@ struct SearchResult {
     index: u32
 }
 
+/// A tree node, stored in the slot of the grid holding it. Roots have no parent.
 struct Tree {
     position: vec3<f32>,
     parent: u32,
+    // Length of the path from the root.
+    cost: f32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
 }
 
+const NO_NODE: u32 = 0xFFFFFFFFu;
+
+const GOAL: vec3<f32> = vec3<f32>(4.0, 4.0, 3.0);
+
 struct State {
   x: u32,
   y: u32,
@@ -64,7 +75,7 @@ fn random_point(inp: State) -> RandomPointResult {
     let y = state.value;
     state = xorshift(state.state);
     let z = state.value;
-    RandomPointResult(state.state, vec3<f32>(f32(x) , f32(y) , f32(z)));
+    return RandomPointResult(state.state, vec3<f32>(f32(x) , f32(y) , f32(z)));
 }
 
 @group(0)
@@ -87,12 +98,42 @@ var<storage, read_write> query_matches: array<Tree>;
 @binding(4)
 var acc_struct: acceleration_structure;
 
+/// Slot of the node connected to the goal, or NO_NODE.
 @group(0)
 @binding(5)
 var<storage, read_write> found: atomic<u32>;
 
+/// Waypoints from the goal back to the root, with the cost in w.
+@group(0)
+@binding(6)
+var<storage, read_write> path: array<vec4<f32>>;
+
+@group(0)
+@binding(7)
+var<storage, read_write> path_length: u32;
+
+const LAYOUT_MORTON: u32 = 1u;
+
+/// Inserts two zero bits between each of the lower 10 bits of `v`.
+fn spread_bits(v: u32) -> u32 {
+    var x = v & 0x3ffu;
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
+
+/// Must match `VoxelLayout::cell_offset` in mod.rs.
+fn cell_offset(pos: vec3<u32>) -> u32 {
+    if uniforms_base.cell_layout == LAYOUT_MORTON {
+        return spread_bits(pos.x) | (spread_bits(pos.y) << 1u) | (spread_bits(pos.z) << 2u);
+    }
+    return pos.x + pos.y * uniforms_base.width_steps + pos.z * uniforms_base.width_steps * uniforms_base.length_steps;
+}
+
 fn to_index(pos: vec3<u32>) -> u32 {
-    return (pos.x + pos.y * uniforms_base.width_steps + pos.z * uniforms_base.width_steps * uniforms_base.height_steps) * uniforms_base.max_density;
+    return cell_offset(pos) * uniforms_base.max_density;
 }
 
 /// Check in a cell for the closest point to a given position
@@ -237,8 +278,6 @@ fn to_f(inp: u32) -> f32 {
 @compute
 @workgroup_size(5,5,5)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let goal = vec3<f32>(4.0, 4.0, 3.0);
-  
     var i: u32 = 0;
     let base_index = to_index(global_id);
     let query_index = to_index(global_id);
@@ -259,31 +298,33 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         let result = get_closest_point(query_point, global_id);
         if result.found == 1 {
             var rq: ray_query;
-            let size = length(query_point - base_grid[result.index].position);
-            let direction =  (query_point - base_grid[result.index].position) / size;
-            rayQueryInitialize(&rq, acc_struct, RayDesc(0x0u, 0xFFu, 0.0, size, query_point, direction));
+            let nearest = base_grid[result.index].position;
+            let size = length(query_point - nearest);
+            let direction =  (query_point - nearest) / size;
+            rayQueryInitialize(&rq, acc_struct, RayDesc(0x0u, 0xFFu, 0.0, size, nearest, direction));
             rayQueryProceed(&rq);
             ///query_matches[query_index + i] = result.index;
             let intersection = rayQueryGetCommittedIntersection(&rq);
             if (intersection.kind == RAY_QUERY_INTERSECTION_NONE) 
             {
+                let node = Tree(query_point, result.index, query_matches[result.index].cost + size, 0u, 0u, 0u);
                 //Check goal
               var rq2: ray_query;
-                let size = length(goal - query_point);
-                let direction =  (goal - query_point) / size;
+                let size = length(GOAL - query_point);
+                let direction =  (GOAL - query_point) / size;
                 rayQueryInitialize(&rq2, acc_struct, RayDesc(0x0u, 0xFFu, 0.0, size, query_point, direction));
                 rayQueryProceed(&rq2);
                 let intersection2 = rayQueryGetCommittedIntersection(&rq2);
                 if (intersection2.kind == RAY_QUERY_INTERSECTION_NONE) {
-                    query_matches[base_index + insert_at] = Tree(query_point, result.index | 0XF0000000);
-                    base_grid[base_index + insert_at] = VoxelNode(query_point, 1);
+                    query_matches[base_index + insert_at] = node;
+                    base_grid[base_index + insert_at] = VoxelNode(query_point, 1, 0);
+                    atomicMin(&found, base_index + insert_at);
                     insert_at += 1;
-                    atomicStore(&found, u32(1));
                     storageBarrier();
                     return;
                 }
-              query_matches[base_index + insert_at] = Tree(query_point, result.index);
-              base_grid[base_index + insert_at] = VoxelNode(query_point, 1);
+              query_matches[base_index + insert_at] = node;
+              base_grid[base_index + insert_at] = VoxelNode(query_point, 1, 0);
               insert_at += 1;
               storageBarrier();
               //return;
@@ -291,9 +332,32 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         }
 
         let p = atomicLoad(&found);
-        if p == 1 {
+        if p != NO_NODE {
             return;
         }
         i = i + 1;
     }
-}
\ No newline at end of file
+}
+
+/// Walks the parents from the node connected to the goal back to its root, writing the goal and
+/// every node along the way to `path`.
+@compute
+@workgroup_size(1)
+fn extract_path() {
+    var node = atomicLoad(&found);
+    if node == NO_NODE {
+        path_length = 0u;
+        return;
+    }
+    let tree_node = query_matches[node];
+    path[0] = vec4<f32>(GOAL, tree_node.cost + length(GOAL - tree_node.position));
+    var count = 1u;
+    // Bounded by the number of slots in case the tree was corrupted by concurrent insertions.
+    while node != NO_NODE && count < arrayLength(&path) {
+        let tree_node = query_matches[node];
+        path[count] = vec4<f32>(tree_node.position, tree_node.cost);
+        count += 1u;
+        node = tree_node.parent;
+    }
+    path_length = count;
+}
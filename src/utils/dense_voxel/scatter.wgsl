@@ -0,0 +1,107 @@
+struct DenseVoxelGpuParams {
+    // Word 1
+    top_right: vec3<f32>,
+    width_steps: u32,
+
+    // Word 2
+    bottom_left: vec3<f32>,
+    height_steps: u32,
+
+    // Word 3
+    max_density: u32,
+    resolution: f32,
+    length_steps: u32,
+    cell_layout: u32
+}
+
+struct ScatterParams {
+    transform: mat4x4<f32>,
+    num_points: u32,
+    first_payload: u32,
+    _padding: vec2<u32>,
+}
+
+/// Size of a `VoxelNode` in words.
+const NODE_WORDS: u32 = 8u;
+/// Word offset of `VoxelNode::occupied`.
+const OCCUPIED_WORD: u32 = 3u;
+/// Word offset of `VoxelNode::payload`.
+const PAYLOAD_WORD: u32 = 4u;
+/// Marks a slot claimed by an invocation that has not finished writing it yet.
+const CLAIMED: u32 = 2u;
+
+/// The voxel data viewed as raw words, so slots can be claimed atomically.
+@group(0)
+@binding(0)
+var<storage, read_write> base_grid: array<atomic<u32>>;
+
+@group(0)
+@binding(1)
+var<uniform> uniforms_base: DenseVoxelGpuParams;
+
+@group(0)
+@binding(2)
+var<storage, read> points: array<vec4<f32>>;
+
+@group(0)
+@binding(3)
+var<uniform> params: ScatterParams;
+
+const LAYOUT_MORTON: u32 = 1u;
+
+/// Inserts two zero bits between each of the lower 10 bits of `v`.
+fn spread_bits(v: u32) -> u32 {
+    var x = v & 0x3ffu;
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
+
+/// Must match `VoxelLayout::cell_offset` in mod.rs.
+fn cell_offset(pos: vec3<u32>) -> u32 {
+    if uniforms_base.cell_layout == LAYOUT_MORTON {
+        return spread_bits(pos.x) | (spread_bits(pos.y) << 1u) | (spread_bits(pos.z) << 2u);
+    }
+    return pos.x + pos.y * uniforms_base.width_steps + pos.z * uniforms_base.width_steps * uniforms_base.length_steps;
+}
+
+@compute
+@workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_points {
+        return;
+    }
+    let point = points[global_id.x];
+    // All zero points are sensor misses.
+    if all(point == vec4<f32>(0.0)) {
+        return;
+    }
+    let position = (params.transform * vec4<f32>(point.xyz, 1.0)).xyz;
+    if any(position < uniforms_base.bottom_left) || any(position > uniforms_base.top_right) {
+        return;
+    }
+    let dims = vec3<u32>(uniforms_base.width_steps, uniforms_base.length_steps, uniforms_base.height_steps);
+    let cell = min(vec3<u32>((position - uniforms_base.bottom_left) / uniforms_base.resolution), dims - 1u);
+    let base = cell_offset(cell) * uniforms_base.max_density;
+
+    // Claim the first free slot, keeping the occupied slots of the cell contiguous.
+    for (var i: u32 = 0; i < uniforms_base.max_density; i++) {
+        let word = (base + i) * NODE_WORDS;
+        loop {
+            let result = atomicCompareExchangeWeak(&base_grid[word + OCCUPIED_WORD], 0u, CLAIMED);
+            if result.exchanged {
+                atomicStore(&base_grid[word], bitcast<u32>(position.x));
+                atomicStore(&base_grid[word + 1u], bitcast<u32>(position.y));
+                atomicStore(&base_grid[word + 2u], bitcast<u32>(position.z));
+                atomicStore(&base_grid[word + PAYLOAD_WORD], params.first_payload + global_id.x);
+                atomicStore(&base_grid[word + OCCUPIED_WORD], 1u);
+                return;
+            }
+            if result.old_value != 0u {
+                break;
+            }
+        }
+    }
+}
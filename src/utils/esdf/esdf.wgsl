@@ -0,0 +1,116 @@
+// Euclidean distance field via 3D jump flooding.
+//
+// `init` seeds every occupied cell with its own index, `jump` is run for
+// step = N/2, N/4, ..., 1 (plus one extra pass at step 1) ping-ponging between
+// two seed buffers, and `write_distances` turns the final nearest-seed map into
+// metric distances.
+
+struct EsdfParams {
+    dims: vec3<u32>,
+    step: u32,
+    resolution: f32,
+    no_obstacle: f32,
+    _padding: vec2<f32>,
+}
+
+const NO_SEED: u32 = 0xFFFFFFFFu;
+
+@group(0)
+@binding(0)
+var<storage, read> occupancy: array<u32>;
+
+@group(0)
+@binding(1)
+var<storage, read> seeds_in: array<u32>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> seeds_out: array<u32>;
+
+@group(0)
+@binding(3)
+var<uniform> params: EsdfParams;
+
+@group(0)
+@binding(4)
+var<storage, read_write> distances: array<f32>;
+
+fn cell_index(cell: vec3<u32>) -> u32 {
+    return cell.x + cell.y * params.dims.x + cell.z * params.dims.x * params.dims.y;
+}
+
+fn index_to_cell(index: u32) -> vec3<u32> {
+    let plane = params.dims.x * params.dims.y;
+    return vec3<u32>(index % params.dims.x, (index % plane) / params.dims.x, index / plane);
+}
+
+fn squared_distance(a: vec3<u32>, b: vec3<u32>) -> f32 {
+    let d = vec3<f32>(a) - vec3<f32>(b);
+    return dot(d, d);
+}
+
+@compute
+@workgroup_size(4, 4, 4)
+fn init(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = cell_index(global_id);
+    if occupancy[index] != 0u {
+        seeds_out[index] = index;
+    } else {
+        seeds_out[index] = NO_SEED;
+    }
+}
+
+@compute
+@workgroup_size(4, 4, 4)
+fn jump(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = cell_index(global_id);
+    var best = seeds_in[index];
+    var best_distance = 3.4e38;
+    if best != NO_SEED {
+        best_distance = squared_distance(global_id, index_to_cell(best));
+    }
+
+    let step = i32(params.step);
+    let dims = vec3<i32>(params.dims);
+    for (var dz = -1; dz <= 1; dz++) {
+        for (var dy = -1; dy <= 1; dy++) {
+            for (var dx = -1; dx <= 1; dx++) {
+                let neighbour = vec3<i32>(global_id) + vec3<i32>(dx, dy, dz) * step;
+                if any(neighbour < vec3<i32>(0)) || any(neighbour >= dims) {
+                    continue;
+                }
+                let seed = seeds_in[cell_index(vec3<u32>(neighbour))];
+                if seed == NO_SEED {
+                    continue;
+                }
+                let distance = squared_distance(global_id, index_to_cell(seed));
+                if distance < best_distance {
+                    best_distance = distance;
+                    best = seed;
+                }
+            }
+        }
+    }
+    seeds_out[index] = best;
+}
+
+@compute
+@workgroup_size(4, 4, 4)
+fn write_distances(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = cell_index(global_id);
+    let seed = seeds_in[index];
+    if seed == NO_SEED {
+        distances[index] = params.no_obstacle;
+    } else {
+        distances[index] = sqrt(squared_distance(global_id, index_to_cell(seed))) * params.resolution;
+    }
+}
@@ -0,0 +1,61 @@
+// Trilinear sampling of a Euclidean distance field and its gradient at arbitrary points.
+
+struct SampleParams {
+    origin: vec3<f32>,
+    resolution: f32,
+    dims: vec3<u32>,
+    num_points: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> distances: array<f32>;
+
+@group(0)
+@binding(1)
+var<uniform> params: SampleParams;
+
+@group(0)
+@binding(2)
+var<storage, read> points: array<vec4<f32>>;
+
+/// Gradient in xyz and distance in w of each point.
+@group(0)
+@binding(3)
+var<storage, read_write> samples: array<vec4<f32>>;
+
+fn distance_at(cell: vec3<u32>) -> f32 {
+    return distances[cell.x + cell.y * params.dims.x + cell.z * params.dims.x * params.dims.y];
+}
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    if index >= params.num_points {
+        return;
+    }
+    // Position in cell units relative to the center of the first cell, clamped to the grid.
+    let last = vec3<f32>(params.dims - vec3<u32>(1u));
+    let grid = clamp((points[index].xyz - params.origin) / params.resolution - 0.5, vec3<f32>(0.0), last);
+    // The last cell interpolates with the one before it, so the gradient stays defined there.
+    let lower = min(vec3<u32>(floor(grid)), max(params.dims, vec3<u32>(2u)) - vec3<u32>(2u));
+    let upper = min(lower + vec3<u32>(1u), params.dims - vec3<u32>(1u));
+    let f = grid - vec3<f32>(lower);
+
+    var distance = 0.0;
+    var gradient = vec3<f32>(0.0);
+    for (var corner = 0u; corner < 8u; corner++) {
+        let high = vec3<bool>((corner & 1u) != 0u, (corner & 2u) != 0u, (corner & 4u) != 0u);
+        let cell = select(lower, upper, high);
+        let value = distance_at(cell);
+        let w = select(1.0 - f, f, high);
+        let dw = select(vec3<f32>(-1.0), vec3<f32>(1.0), high);
+        distance += w.x * w.y * w.z * value;
+        gradient += vec3<f32>(dw.x * w.y * w.z, w.x * dw.y * w.z, w.x * w.y * dw.z) * value;
+    }
+    samples[index] = vec4<f32>(gradient / params.resolution, distance);
+}
@@ -0,0 +1,598 @@
+use glam::{UVec3, Vec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{dense_voxel::DenseVoxel, read_buffer, MAX_WORKGROUPS_PER_DIMENSION};
+
+/// Distance written to cells when the grid contains no obstacle at all.
+pub const NO_OBSTACLE: f32 = f32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct EsdfParams {
+    dims: UVec3,
+    step: u32,
+    resolution: f32,
+    no_obstacle: f32,
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct SampleParams {
+    origin: Vec3,
+    resolution: f32,
+    dims: UVec3,
+    num_points: u32,
+}
+
+/// The distance field and its gradient at a point, as returned by [`Esdf::sample`].
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, PartialEq)]
+pub struct EsdfSample {
+    /// Gradient of the distance, pointing away from the nearest obstacle.
+    pub gradient: Vec3,
+    /// Distance in meters.
+    pub distance: f32,
+}
+
+/// A Euclidean distance field over a regular grid.
+///
+/// Every cell stores the distance in meters from its center to the center of the nearest occupied
+/// cell, so occupied cells are 0. The field is computed on the GPU with the jump flooding
+/// algorithm and stays resident there, ready to be bound by trajectory optimizers. Cells are laid
+/// out x-major, i.e. cell `(x, y, z)` is at `x + y * dims.x + z * dims.x * dims.y`.
+pub struct Esdf {
+    origin: Vec3,
+    dims: UVec3,
+    resolution: f32,
+    distances: wgpu::Buffer,
+}
+
+impl Esdf {
+    /// Computes the distance field of an occupancy grid.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
+    /// * `occupancy` - One flag per cell, non-zero for occupied cells.
+    /// * `dims` - Number of cells along each axis.
+    /// * `origin` - World position of the corner of the first cell.
+    /// * `resolution` - Edge length of a cell in meters.
+    pub fn from_occupancy(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        occupancy: &[u32],
+        dims: UVec3,
+        origin: Vec3,
+        resolution: f32,
+    ) -> Self {
+        let num_cells = (dims.x * dims.y * dims.z) as usize;
+        if occupancy.len() != num_cells {
+            panic!("Occupancy grid does not match dimensions");
+        }
+
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("esdf.wgsl"));
+        let create_pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                module: &cs_module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let init_pipeline = create_pipeline("init");
+        let jump_pipeline = create_pipeline("jump");
+        let distance_pipeline = create_pipeline("write_distances");
+
+        let occupancy_buf = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("ESDF Occupancy"),
+            contents: bytemuck::cast_slice(occupancy),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let seeds = [0, 1].map(|_| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("ESDF Seeds"),
+                size: (num_cells * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        });
+        let distances = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ESDF Distances"),
+            size: (num_cells * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let create_params = |step: u32| {
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("ESDF Parameters"),
+                contents: bytemuck::cast_slice(&[EsdfParams {
+                    dims,
+                    step,
+                    resolution,
+                    no_obstacle: NO_OBSTACLE,
+                    _padding: [0.0; 2],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+        };
+
+        // Jump flooding steps: N/2, N/4, ..., 1 followed by an extra pass at 1 to fix up the
+        // few cells plain JFA gets wrong.
+        let mut steps = vec![];
+        let mut step = dims.max_element().next_power_of_two() / 2;
+        while step >= 1 {
+            steps.push(step);
+            step /= 2;
+        }
+        steps.push(1);
+
+        let workgroups = dims.map(|d| d.div_ceil(4));
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut dispatch = |pipeline: &wgpu::ComputePipeline, entries: &[wgpu::BindGroupEntry]| {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &pipeline.get_bind_group_layout(0),
+                entries,
+            });
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+        };
+
+        let params = create_params(0);
+        dispatch(
+            &init_pipeline,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: occupancy_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: seeds[0].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        );
+        let mut current = 0;
+        for step in steps {
+            let params = create_params(step);
+            dispatch(
+                &jump_pipeline,
+                &[
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: seeds[current].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: seeds[1 - current].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: params.as_entire_binding(),
+                    },
+                ],
+            );
+            current = 1 - current;
+        }
+        dispatch(
+            &distance_pipeline,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: seeds[current].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: distances.as_entire_binding(),
+                },
+            ],
+        );
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            origin,
+            dims,
+            resolution,
+            distances,
+        }
+    }
+
+    /// Computes the distance field of the cells occupied in a [`DenseVoxel`].
+    pub fn from_dense_voxel(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        voxel: &DenseVoxel,
+    ) -> Self {
+        let dims = UVec3::new(
+            voxel.width_steps() as u32,
+            voxel.length_steps() as u32,
+            voxel.height_steps() as u32,
+        );
+        Self::from_occupancy(
+            device,
+            queue,
+            &voxel.occupancy_grid(),
+            dims,
+            voxel.bottom_left(),
+            voxel.resolution(),
+        )
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn dims(&self) -> UVec3 {
+        self.dims
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// The GPU buffer holding one `f32` distance per cell.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.distances
+    }
+
+    /// Returns the index of cell `(x, y, z)` in the distance buffer.
+    pub fn cell_index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + y * self.dims.x + z * self.dims.x * self.dims.y) as usize
+    }
+
+    /// Reads the distance field back to the CPU.
+    pub async fn download(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<f32> {
+        read_buffer(device, queue, &self.distances).await
+    }
+
+    /// Records the sampling of the field at `num_points` points into `encoder`.
+    ///
+    /// The distance is interpolated trilinearly between cell centers and the gradient is the
+    /// exact derivative of that interpolation, so both are continuous enough for gradient based
+    /// trajectory optimizers. Points outside the grid are clamped to it. Cells without any
+    /// obstacle in the grid hold [`NO_OBSTACLE`], which makes the samples meaningless.
+    ///
+    /// `points` holds 4 floats per point with the position in the first 3, e.g. the output of
+    /// [`crate::planner::sampling::encode_samples`], and must be usable as `STORAGE`. Returns a
+    /// buffer, usable as `STORAGE` and `COPY_SRC`, holding one [`EsdfSample`] per point.
+    pub fn encode_sample(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        points: &wgpu::Buffer,
+        num_points: u32,
+    ) -> wgpu::Buffer {
+        let samples = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ESDF Samples"),
+            size: (num_points.max(1) as usize * std::mem::size_of::<EsdfSample>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        if num_points == 0 {
+            return samples;
+        }
+
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("esdf_sample.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("esdf_sample"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("ESDF Sample Parameters"),
+            contents: bytemuck::cast_slice(&[SampleParams {
+                origin: self.origin,
+                resolution: self.resolution,
+                dims: self.dims,
+                num_points,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.distances.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: samples.as_entire_binding(),
+                },
+            ],
+        });
+        let workgroups = num_points.div_ceil(64);
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(
+                workgroups.min(MAX_WORKGROUPS_PER_DIMENSION),
+                workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+                1,
+            );
+        }
+        samples
+    }
+
+    /// Samples the distance and its gradient at a batch of points in a single dispatch.
+    ///
+    /// See [`Self::encode_sample`].
+    pub async fn sample(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        points: &[Vec3],
+    ) -> Vec<EsdfSample> {
+        if points.is_empty() {
+            return vec![];
+        }
+        let points_buf = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("ESDF Sample Points"),
+            contents: bytemuck::cast_slice(
+                &points.iter().map(|p| p.extend(0.0)).collect::<Vec<_>>(),
+            ),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let samples = self.encode_sample(device, &mut encoder, &points_buf, points.len() as u32);
+        queue.submit(Some(encoder.finish()));
+        read_buffer(device, queue, &samples).await
+    }
+
+    /// Meshes the boundary of the cells within `distance` of an obstacle, as downloaded by
+    /// [`Self::download`], in world coordinates.
+    ///
+    /// The mesh is made of the cell faces between cells inside and outside the level set, so it
+    /// is blocky but closed. With a `distance` of 0 it outlines the obstacles themselves.
+    pub fn isosurface(&self, distances: &[f32], distance: f32) -> (Vec<Vec3>, Vec<[u32; 3]>) {
+        let inside = |cell: glam::IVec3| {
+            cell.cmpge(glam::IVec3::ZERO).all()
+                && cell.cmplt(self.dims.as_ivec3()).all()
+                && distances[self.cell_index(cell.x as u32, cell.y as u32, cell.z as u32)]
+                    <= distance
+        };
+        let half = self.resolution / 2.0;
+        let mut vertices = vec![];
+        let mut triangles = vec![];
+        for z in 0..self.dims.z {
+            for y in 0..self.dims.y {
+                for x in 0..self.dims.x {
+                    let cell = UVec3::new(x, y, z);
+                    if !inside(cell.as_ivec3()) {
+                        continue;
+                    }
+                    let center =
+                        self.origin + (cell.as_vec3() + Vec3::splat(0.5)) * self.resolution;
+                    for axis in 0..3 {
+                        let normal = Vec3::AXES[axis];
+                        let u = Vec3::AXES[(axis + 1) % 3] * half;
+                        let v = Vec3::AXES[(axis + 2) % 3] * half;
+                        for side in [-1.0, 1.0] {
+                            let neighbour = cell.as_ivec3() + (normal * side).as_ivec3();
+                            if inside(neighbour) {
+                                continue;
+                            }
+                            // Counter-clockwise seen from outside the level set.
+                            let face = center + normal * half * side;
+                            let first = vertices.len() as u32;
+                            vertices.extend([
+                                face - u - v,
+                                face + u - v,
+                                face + u + v,
+                                face - u + v,
+                            ]);
+                            if side > 0.0 {
+                                triangles.extend([
+                                    [first, first + 1, first + 2],
+                                    [first + 2, first + 3, first],
+                                ]);
+                            } else {
+                                triangles.extend([
+                                    [first, first + 2, first + 1],
+                                    [first + 2, first, first + 3],
+                                ]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        (vertices, triangles)
+    }
+
+    /// Logs the isosurface of the cells within `distance` of an obstacle, see
+    /// [`Self::isosurface`], to `rerun` under `esdf/isosurface`.
+    ///
+    /// # Note
+    ///
+    /// This method is only available when the `visualization` feature is enabled.
+    #[cfg(feature = "visualization")]
+    pub async fn visualize(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rerun: &rerun::RecordingStream,
+        distance: f32,
+    ) {
+        let distances = self.download(device, queue).await;
+        let (vertices, triangles) = self.isosurface(&distances, distance);
+        rerun
+            .log(
+                "esdf/isosurface",
+                &rerun::Mesh3D::new(vertices.iter().map(|v| v.to_array()))
+                    .with_triangle_indices(triangles),
+            )
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_esdf_matches_brute_force() {
+    use crate::utils::get_raytracing_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let dims = UVec3::new(9, 7, 5);
+    let obstacles = [
+        UVec3::new(1, 1, 1),
+        UVec3::new(7, 5, 3),
+        UVec3::new(4, 0, 4),
+    ];
+    let mut occupancy = vec![0u32; (dims.x * dims.y * dims.z) as usize];
+    for o in obstacles {
+        occupancy[(o.x + o.y * dims.x + o.z * dims.x * dims.y) as usize] = 1;
+    }
+
+    let esdf = Esdf::from_occupancy(&device, &queue, &occupancy, dims, Vec3::ZERO, 0.5);
+    let distances = esdf.download(&device, &queue).await;
+
+    for z in 0..dims.z {
+        for y in 0..dims.y {
+            for x in 0..dims.x {
+                let cell = UVec3::new(x, y, z).as_vec3();
+                let expected = obstacles
+                    .iter()
+                    .map(|o| cell.distance(o.as_vec3()))
+                    .fold(f32::MAX, f32::min)
+                    * 0.5;
+                let got = distances[esdf.cell_index(x, y, z)];
+                assert!(
+                    (got - expected).abs() < 1e-4,
+                    "{x} {y} {z}: {got} != {expected}"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_esdf_sample() {
+    use crate::utils::get_raytracing_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A single obstacle in the middle of the grid, whose cell centers lie at 0.25 + 0.5 * i.
+    let dims = UVec3::splat(9);
+    let mut occupancy = vec![0u32; 9 * 9 * 9];
+    occupancy[4 + 4 * 9 + 4 * 81] = 1;
+    let esdf = Esdf::from_occupancy(&device, &queue, &occupancy, dims, Vec3::ZERO, 0.5);
+    let center = Vec3::splat(2.25);
+
+    let points = [
+        center,
+        // Between two cell centers on the row through the obstacle.
+        center + Vec3::new(1.75, 0.0, 0.0),
+        center - Vec3::new(0.0, 0.0, 1.25),
+        // Outside the grid, clamped to its corner.
+        Vec3::splat(-3.0),
+    ];
+    let samples = esdf.sample(&device, &queue, &points).await;
+    assert_eq!(samples.len(), points.len());
+
+    assert!(samples[0].distance.abs() < 1e-5);
+    assert!(
+        (samples[1].distance - 1.75).abs() < 1e-4,
+        "{:?}",
+        samples[1]
+    );
+    assert!(
+        samples[1].gradient.abs_diff_eq(Vec3::X, 1e-4),
+        "{:?}",
+        samples[1]
+    );
+    assert!(
+        (samples[2].distance - 1.25).abs() < 1e-4,
+        "{:?}",
+        samples[2]
+    );
+    assert!(samples[2].gradient.z < -0.9, "{:?}", samples[2]);
+    let corner = center.distance(Vec3::splat(0.25));
+    assert!(
+        (samples[3].distance - corner).abs() < 1e-4,
+        "{:?}",
+        samples[3]
+    );
+    assert!(
+        samples[3].gradient.cmple(Vec3::ZERO).all(),
+        "{:?}",
+        samples[3]
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_esdf_isosurface() {
+    use crate::utils::get_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+
+    let dims = UVec3::splat(5);
+    let mut occupancy = vec![0u32; 125];
+    occupancy[2 + 2 * 5 + 2 * 25] = 1;
+    let esdf = Esdf::from_occupancy(&device, &queue, &occupancy, dims, Vec3::ZERO, 0.5);
+    let distances = esdf.download(&device, &queue).await;
+
+    // The obstacle cell alone is a cube.
+    let (vertices, triangles) = esdf.isosurface(&distances, 0.0);
+    assert_eq!(triangles.len(), 12);
+    assert!(vertices
+        .iter()
+        .all(|v| v.cmpge(Vec3::splat(1.0)).all() && v.cmple(Vec3::splat(1.5)).all()));
+    // Faces point away from the cube.
+    for [a, b, c] in &triangles {
+        let (a, b, c) = (
+            vertices[*a as usize],
+            vertices[*b as usize],
+            vertices[*c as usize],
+        );
+        let normal = (b - a).cross(c - a);
+        assert!(normal.dot((a + b + c) / 3.0 - Vec3::splat(1.25)) > 0.0);
+    }
+
+    // One cell further, the obstacle and its 6 face neighbours form a cross of 30 faces.
+    let (_, triangles) = esdf.isosurface(&distances, 0.5);
+    assert_eq!(triangles.len(), 60);
+}
@@ -0,0 +1,140 @@
+use crate::utils::{
+    bind_layout::{create_compute_layout, create_pipeline_layout, STORAGE, STORAGE_READ, UNIFORM},
+    buffer_pool::BufferPool,
+    MAX_WORKGROUPS_PER_DIMENSION,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct PackParams {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+/// Converts `f32` sensor outputs to half precision on the GPU before they are read back.
+///
+/// Halves the readback bandwidth at the cost of precision: a range of 100m is rounded to
+/// 6.25cm, and values past 65504 such as the depth camera's no hit constant become infinity.
+pub(crate) struct HalfPacker {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl HalfPacker {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = create_compute_layout(
+            device,
+            "Half Pack Bind Group Layout",
+            &[STORAGE_READ, STORAGE, UNIFORM],
+        );
+        let shader = device.create_shader_module(wgpu::include_wgsl!("pack.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("half_pack"),
+            layout: Some(&create_pipeline_layout(device, &bind_group_layout)),
+            module: &shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    /// Records the conversion of the first `count` floats of `input`.
+    ///
+    /// Returns the buffers taken from `pool`, output first. The output holds `count` rounded up
+    /// to an even number of `f16`s, the padding being zero.
+    pub(crate) fn encode(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &BufferPool,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::Buffer,
+        count: u32,
+    ) -> Vec<wgpu::Buffer> {
+        let words = count.div_ceil(2).max(1);
+        let output = pool.take(
+            device,
+            words as u64 * 4,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let params = pool.take_init(
+            device,
+            queue,
+            bytemuck::bytes_of(&PackParams {
+                count,
+                _padding: [0; 3],
+            }),
+            wgpu::BufferUsages::UNIFORM,
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: output.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+        let workgroups = words.div_ceil(64);
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(
+                workgroups.min(MAX_WORKGROUPS_PER_DIMENSION),
+                workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+                1,
+            );
+        }
+        vec![output, params]
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_half_pack() {
+    use crate::utils::{get_raytracing_gpu, read_buffer};
+    use half::f16;
+    use wgpu::util::DeviceExt;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    let values = [0.0f32, 1.5, -2.25, 10000.0, 99999.0];
+    let input = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&values),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let pool = BufferPool::default();
+    let mut encoder = device.create_command_encoder(&Default::default());
+    let buffers = HalfPacker::new(&device).encode(
+        &device,
+        &queue,
+        &pool,
+        &mut encoder,
+        &input,
+        values.len() as u32,
+    );
+    queue.submit(Some(encoder.finish()));
+    let packed: Vec<f16> = read_buffer(&device, &queue, &buffers[0]).await;
+    assert_eq!(
+        packed,
+        [0.0, 1.5, -2.25, 10000.0, f32::INFINITY, 0.0].map(f16::from_f32)
+    );
+}
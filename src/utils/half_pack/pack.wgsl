@@ -0,0 +1,34 @@
+struct PackParams {
+    count: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0) @binding(0)
+var<storage, read> input: array<f32>;
+
+/// Two consecutive values per word, the first one in the low half.
+@group(0) @binding(1)
+var<storage, read_write> output: array<u32>;
+
+@group(0) @binding(2)
+var<uniform> params: PackParams;
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    let first = 2u * index;
+    if first >= params.count {
+        return;
+    }
+    var second = 0.0;
+    if first + 1u < params.count {
+        second = input[first + 1u];
+    }
+    output[index] = pack2x16float(vec2<f32>(input[first], second));
+}
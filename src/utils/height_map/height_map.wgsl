@@ -0,0 +1,63 @@
+// 2.5D elevation maps extracted from 3D occupancy grids.
+
+struct HeightMapParams {
+    origin: vec3<f32>,
+    resolution: f32,
+    dims: vec3<u32>,
+    _padding: u32,
+}
+
+/// Height of the highest occupied voxel of a column and variance of the occupied voxel heights.
+struct HeightCell {
+    height: f32,
+    variance: f32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> occupancy: array<u32>;
+
+@group(0)
+@binding(1)
+var<uniform> params: HeightMapParams;
+
+@group(0)
+@binding(2)
+var<storage, read_write> cells: array<HeightCell>;
+
+/// Scans the column of voxels above a cell. Columns without any occupied voxel get a NaN height.
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id.xy >= params.dims.xy) {
+        return;
+    }
+    let column = global_id.x + global_id.y * params.dims.x;
+    let layer = params.dims.x * params.dims.y;
+
+    var count = 0u;
+    var top = 0u;
+    // Moments of the voxel centers relative to the bottom of the grid, in voxels.
+    var sum = 0.0;
+    var sum_squares = 0.0;
+    for (var z = 0u; z < params.dims.z; z++) {
+        if occupancy[column + z * layer] != 0u {
+            let center = f32(z) + 0.5;
+            count += 1u;
+            top = z;
+            sum += center;
+            sum_squares += center * center;
+        }
+    }
+
+    if count == 0u {
+        cells[column] = HeightCell(bitcast<f32>(0x7fc00000u), 0.0);
+        return;
+    }
+    let mean = sum / f32(count);
+    let variance = max(sum_squares / f32(count) - mean * mean, 0.0);
+    cells[column] = HeightCell(
+        params.origin.z + f32(top + 1u) * params.resolution,
+        variance * params.resolution * params.resolution,
+    );
+}
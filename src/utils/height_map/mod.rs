@@ -0,0 +1,253 @@
+use glam::{UVec2, UVec3, Vec2, Vec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{dense_voxel::DenseVoxel, read_buffer};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct HeightMapParams {
+    origin: Vec3,
+    resolution: f32,
+    dims: UVec3,
+    _padding: u32,
+}
+
+/// Elevation of one column of a [`HeightMap`].
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, PartialEq)]
+pub struct HeightCell {
+    /// Height of the top of the highest occupied voxel in the column, or NaN if the column is
+    /// empty.
+    pub height: f32,
+    /// Variance of the heights of the occupied voxel centers in the column, in square meters. A
+    /// large variance hints at overhangs or vegetation rather than solid ground.
+    pub variance: f32,
+}
+
+impl HeightCell {
+    /// Whether any voxel of the column is occupied.
+    pub fn is_known(&self) -> bool {
+        !self.height.is_nan()
+    }
+}
+
+/// A 2.5D elevation map for terrain analysis.
+///
+/// Each cell holds a [`HeightCell`] for one column of voxels of the grid it was extracted from,
+/// so the map has the footprint and resolution of that grid. Cells are stored on the GPU laid out
+/// x-major, i.e. cell `(x, y)` is at `x + y * dims.x`.
+pub struct HeightMap {
+    origin: Vec2,
+    dims: UVec2,
+    resolution: f32,
+    cells: wgpu::Buffer,
+}
+
+impl HeightMap {
+    /// Extracts the elevation of every column of a 3D occupancy grid.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
+    /// * `occupancy` - One flag per voxel, non-zero for occupied voxels, laid out x-major.
+    /// * `dims` - Number of voxels along each axis.
+    /// * `origin` - World position of the corner of the first voxel.
+    /// * `resolution` - Edge length of a voxel in meters.
+    pub fn from_occupancy(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        occupancy: &[u32],
+        dims: UVec3,
+        origin: Vec3,
+        resolution: f32,
+    ) -> Self {
+        if occupancy.len() != (dims.x * dims.y * dims.z) as usize {
+            panic!("Occupancy grid does not match dimensions");
+        }
+
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("height_map.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("height_map"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let occupancy_buf = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Height Map Occupancy"),
+            contents: bytemuck::cast_slice(occupancy),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let cells = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Height Map Cells"),
+            size: ((dims.x * dims.y) as usize * std::mem::size_of::<HeightCell>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Height Map Parameters"),
+            contents: bytemuck::cast_slice(&[HeightMapParams {
+                origin,
+                resolution,
+                dims,
+                _padding: 0,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: occupancy_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cells.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(dims.x.div_ceil(8), dims.y.div_ceil(8), 1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Self {
+            origin: origin.truncate(),
+            dims: dims.truncate(),
+            resolution,
+            cells,
+        }
+    }
+
+    /// Extracts the elevation of every column of a [`DenseVoxel`].
+    pub fn from_dense_voxel(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        voxel: &DenseVoxel,
+    ) -> Self {
+        let dims = UVec3::new(
+            voxel.width_steps() as u32,
+            voxel.length_steps() as u32,
+            voxel.height_steps() as u32,
+        );
+        Self::from_occupancy(
+            device,
+            queue,
+            &voxel.occupancy_grid(),
+            dims,
+            voxel.bottom_left(),
+            voxel.resolution(),
+        )
+    }
+
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    pub fn dims(&self) -> UVec2 {
+        self.dims
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// The GPU buffer holding one [`HeightCell`] per cell.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.cells
+    }
+
+    /// Returns the index of cell `(x, y)` in the cell buffer.
+    pub fn cell_index(&self, x: u32, y: u32) -> usize {
+        (x + y * self.dims.x) as usize
+    }
+
+    /// Returns the cell containing a world position, if it lies on the map.
+    pub fn world_to_cell(&self, position: Vec2) -> Option<UVec2> {
+        let cell = ((position - self.origin) / self.resolution).floor();
+        if cell.cmplt(Vec2::ZERO).any() || cell.cmpge(self.dims.as_vec2()).any() {
+            return None;
+        }
+        Some(cell.as_uvec2())
+    }
+
+    /// Reads the cells back to the CPU.
+    pub async fn download(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<HeightCell> {
+        read_buffer(device, queue, &self.cells).await
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_height_map() {
+    use crate::utils::get_raytracing_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let dims = UVec3::new(4, 3, 5);
+    let mut occupancy = vec![0u32; (dims.x * dims.y * dims.z) as usize];
+    let mut occupy = |x: u32, y: u32, z: u32| {
+        occupancy[(x + y * dims.x + z * dims.x * dims.y) as usize] = 1;
+    };
+    // Ground with an overhang above it, and a single raised voxel.
+    occupy(0, 0, 0);
+    occupy(0, 0, 2);
+    occupy(1, 0, 4);
+
+    let height_map = HeightMap::from_occupancy(
+        &device,
+        &queue,
+        &occupancy,
+        dims,
+        Vec3::new(1.0, 2.0, -1.0),
+        0.5,
+    );
+    assert_eq!(height_map.dims(), UVec2::new(4, 3));
+    assert_eq!(
+        height_map.world_to_cell(Vec2::new(1.6, 2.2)),
+        Some(UVec2::new(1, 0))
+    );
+    let cells = height_map.download(&device, &queue).await;
+
+    assert_eq!(
+        cells[height_map.cell_index(0, 0)],
+        HeightCell {
+            height: 0.5,
+            variance: 0.25
+        }
+    );
+    assert_eq!(
+        cells[height_map.cell_index(1, 0)],
+        HeightCell {
+            height: 1.5,
+            variance: 0.0
+        }
+    );
+    for y in 0..3 {
+        for x in 0..4 {
+            if y > 0 || x > 1 {
+                assert!(!cells[height_map.cell_index(x, y)].is_known(), "{x} {y}");
+            }
+        }
+    }
+}
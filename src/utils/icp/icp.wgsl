@@ -0,0 +1,262 @@
+struct DenseVoxelGpuParams {
+    // Word 1
+    top_right: vec3<f32>,
+    width_steps: u32,
+
+    // Word 2
+    bottom_left: vec3<f32>,
+    height_steps: u32,
+
+    // Word 3
+    max_density: u32,
+    resolution: f32,
+    length_steps: u32,
+    cell_layout: u32
+}
+
+struct VoxelNode {
+    position: vec3<f32>,
+    occupied: u32,
+    payload: u32
+}
+
+struct CorrespondenceParams {
+    transform: mat4x4<f32>,
+    num_points: u32,
+    /// Zero to skip normal estimation.
+    normal_radius: f32,
+    _padding: vec2<f32>,
+}
+
+/// Must match `Correspondence` in mod.rs.
+struct Correspondence {
+    moved: vec3<f32>,
+    found: u32,
+    matched: vec3<f32>,
+    has_normal: u32,
+    normal: vec3<f32>,
+    _padding: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> base_grid: array<VoxelNode>;
+
+@group(0)
+@binding(1)
+var<uniform> uniforms_base: DenseVoxelGpuParams;
+
+@group(0)
+@binding(2)
+var<storage, read> source: array<vec4<f32>>;
+
+@group(0)
+@binding(3)
+var<uniform> params: CorrespondenceParams;
+
+@group(0)
+@binding(4)
+var<storage, read_write> correspondences: array<Correspondence>;
+
+const LAYOUT_MORTON: u32 = 1u;
+
+/// Inserts two zero bits between each of the lower 10 bits of `v`.
+fn spread_bits(v: u32) -> u32 {
+    var x = v & 0x3ffu;
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
+
+/// Must match `VoxelLayout::cell_offset` in dense_voxel/mod.rs.
+fn cell_offset(pos: vec3<u32>) -> u32 {
+    if uniforms_base.cell_layout == LAYOUT_MORTON {
+        return spread_bits(pos.x) | (spread_bits(pos.y) << 1u) | (spread_bits(pos.z) << 2u);
+    }
+    return pos.x + pos.y * uniforms_base.width_steps + pos.z * uniforms_base.width_steps * uniforms_base.length_steps;
+}
+
+fn to_index(pos: vec3<u32>) -> u32 {
+    return cell_offset(pos) * uniforms_base.max_density;
+}
+
+fn grid_steps() -> vec3<i32> {
+    return vec3<i32>(ceil((uniforms_base.top_right - uniforms_base.bottom_left) / uniforms_base.resolution));
+}
+
+/// The cell containing `pos`, clamped to the grid.
+fn clamped_cell(pos: vec3<f32>) -> vec3<i32> {
+    let cell = vec3<i32>(floor((pos - uniforms_base.bottom_left) / uniforms_base.resolution));
+    return clamp(cell, vec3<i32>(0), grid_steps() - 1);
+}
+
+struct SearchResult {
+    found: bool,
+    position: vec3<f32>,
+}
+
+/// Finds the closest item by scanning shells of cells of growing Chebyshev radius around the
+/// cell of `pos`, as in dense_voxel/nn.wgsl.
+///
+/// Points outside the grid start from the nearest cell on its boundary. Moving away from that
+/// cell only moves further from `pos`, so the same stopping rule holds.
+fn closest_item(pos: vec3<f32>) -> SearchResult {
+    let steps = grid_steps();
+    let center = clamped_cell(pos);
+    let max_ring = max(steps.x, max(steps.y, steps.z));
+    var best = SearchResult(false, vec3<f32>(0.0));
+    var best_distance: f32 = 3.4e38;
+    for (var ring: i32 = 0; ring <= max_ring; ring++) {
+        let lo = max(center - vec3<i32>(ring), vec3<i32>(0));
+        let hi = min(center + vec3<i32>(ring), steps - 1);
+        for (var z = lo.z; z <= hi.z; z++) {
+            for (var y = lo.y; y <= hi.y; y++) {
+                for (var x = lo.x; x <= hi.x; x++) {
+                    let offset = abs(vec3<i32>(x, y, z) - center);
+                    if max(offset.x, max(offset.y, offset.z)) != ring {
+                        continue;
+                    }
+                    let base = to_index(vec3<u32>(vec3<i32>(x, y, z)));
+                    for (var i: u32 = 0; i < uniforms_base.max_density; i++) {
+                        let node = base_grid[base + i];
+                        if node.occupied == 0 {
+                            break;
+                        }
+                        let distance = length(node.position - pos);
+                        if distance < best_distance {
+                            best_distance = distance;
+                            best = SearchResult(true, node.position);
+                        }
+                    }
+                }
+            }
+        }
+        if best.found && best_distance <= f32(ring) * uniforms_base.resolution {
+            break;
+        }
+    }
+    return best;
+}
+
+/// Range of cells overlapping the box around the sphere of `params.normal_radius` at `pos`.
+struct CellRange {
+    lo: vec3<u32>,
+    hi: vec3<u32>,
+}
+
+fn cell_range(pos: vec3<f32>) -> CellRange {
+    return CellRange(
+        vec3<u32>(clamped_cell(pos - params.normal_radius)),
+        vec3<u32>(clamped_cell(pos + params.normal_radius)),
+    );
+}
+
+/// Mean of the items within `params.normal_radius` of `pos`, with their count in `w`.
+fn neighbourhood_mean(pos: vec3<f32>) -> vec4<f32> {
+    let range = cell_range(pos);
+    var sum = vec3<f32>(0.0);
+    var count: f32 = 0.0;
+    for (var z = range.lo.z; z <= range.hi.z; z++) {
+        for (var y = range.lo.y; y <= range.hi.y; y++) {
+            for (var x = range.lo.x; x <= range.hi.x; x++) {
+                let base = to_index(vec3<u32>(x, y, z));
+                for (var i: u32 = 0; i < uniforms_base.max_density; i++) {
+                    let node = base_grid[base + i];
+                    if node.occupied == 0 {
+                        break;
+                    }
+                    if distance(node.position, pos) <= params.normal_radius {
+                        sum += node.position;
+                        count += 1.0;
+                    }
+                }
+            }
+        }
+    }
+    return vec4<f32>(sum / max(count, 1.0), count);
+}
+
+/// Covariance, up to scale, of the items within `params.normal_radius` of `pos` around `mean`.
+fn neighbourhood_covariance(pos: vec3<f32>, mean: vec3<f32>) -> mat3x3<f32> {
+    let range = cell_range(pos);
+    var covariance = mat3x3<f32>(vec3<f32>(0.0), vec3<f32>(0.0), vec3<f32>(0.0));
+    for (var z = range.lo.z; z <= range.hi.z; z++) {
+        for (var y = range.lo.y; y <= range.hi.y; y++) {
+            for (var x = range.lo.x; x <= range.hi.x; x++) {
+                let base = to_index(vec3<u32>(x, y, z));
+                for (var i: u32 = 0; i < uniforms_base.max_density; i++) {
+                    let node = base_grid[base + i];
+                    if node.occupied == 0 {
+                        break;
+                    }
+                    if distance(node.position, pos) <= params.normal_radius {
+                        let d = node.position - mean;
+                        covariance += mat3x3<f32>(d * d.x, d * d.y, d * d.z);
+                    }
+                }
+            }
+        }
+    }
+    return covariance;
+}
+
+/// Estimates the surface normal at `pos` as the direction of least variance of its
+/// neighbourhood. Returns a zero vector for fewer than 3 neighbours.
+fn estimate_normal(pos: vec3<f32>) -> vec3<f32> {
+    let mean = neighbourhood_mean(pos);
+    if mean.w < 3.0 {
+        return vec3<f32>(0.0);
+    }
+    let covariance = neighbourhood_covariance(pos, mean.xyz);
+    // Power iteration on `trace * I - covariance`, whose dominant eigenvector is the one of
+    // least variance.
+    let trace = covariance[0].x + covariance[1].y + covariance[2].z;
+    let shifted = mat3x3<f32>(
+        vec3<f32>(trace, 0.0, 0.0),
+        vec3<f32>(0.0, trace, 0.0),
+        vec3<f32>(0.0, 0.0, trace),
+    ) - covariance;
+    var normal = vec3<f32>(0.57, 0.58, 0.59);
+    for (var i = 0; i < 32; i++) {
+        let next = shifted * normal;
+        let norm = length(next);
+        if norm < 1e-20 {
+            return vec3<f32>(0.0);
+        }
+        normal = next / norm;
+    }
+    return normal;
+}
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    if index >= params.num_points {
+        return;
+    }
+    let point = source[index].xyz;
+    let moved = (params.transform * vec4<f32>(point, 1.0)).xyz;
+    // All zero points are LiDAR misses.
+    var result = SearchResult(false, vec3<f32>(0.0));
+    if any(point != vec3<f32>(0.0)) {
+        result = closest_item(moved);
+    }
+    var normal = vec3<f32>(0.0);
+    if result.found && params.normal_radius > 0.0 {
+        normal = estimate_normal(result.position);
+    }
+    correspondences[index] = Correspondence(
+        moved,
+        u32(result.found),
+        result.position,
+        u32(any(normal != vec3<f32>(0.0))),
+        normal,
+        0u,
+    );
+}
@@ -0,0 +1,524 @@
+use glam::{Affine3A, Mat4, Quat, Vec3};
+
+use crate::utils::{
+    dense_voxel::DenseVoxelGpuRepresentation, read_buffer, MAX_WORKGROUPS_PER_DIMENSION,
+};
+use crate::Error;
+
+/// The error metric minimized by [`icp`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IcpVariant {
+    /// Minimizes the distance between each source point and its nearest target point.
+    PointToPoint,
+    /// Minimizes the distance between each source point and the tangent plane at its nearest
+    /// target point. Target normals are estimated from the target points within `normal_radius`.
+    PointToPlane { normal_radius: f32 },
+}
+
+/// Parameters of [`icp`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IcpParams {
+    pub variant: IcpVariant,
+    /// Maximum number of iterations.
+    pub max_iterations: usize,
+    /// Correspondences further apart than this are rejected.
+    pub max_correspondence_distance: f32,
+    /// Stop once an iteration moves the estimate by less than this, in meters and radians.
+    pub convergence_threshold: f32,
+}
+
+impl Default for IcpParams {
+    fn default() -> Self {
+        Self {
+            variant: IcpVariant::PointToPoint,
+            max_iterations: 30,
+            max_correspondence_distance: 1.0,
+            convergence_threshold: 1e-5,
+        }
+    }
+}
+
+/// Outcome of [`icp`].
+#[derive(Copy, Clone, Debug)]
+pub struct IcpResult {
+    /// Transform taking the source points onto the target.
+    pub transform: Affine3A,
+    /// Number of iterations run.
+    pub iterations: usize,
+    /// Whether the estimate converged before `max_iterations`.
+    pub converged: bool,
+    /// Root mean square distance between corresponding points after the last iteration.
+    pub rmse: f32,
+    /// Number of correspondences used in the last iteration.
+    pub num_correspondences: usize,
+}
+
+/// A source point moved by the current estimate and its nearest target item.
+///
+/// Must match `Correspondence` in icp.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct Correspondence {
+    moved: Vec3,
+    found: u32,
+    matched: Vec3,
+    /// Whether `normal` holds the target surface normal at `matched`.
+    has_normal: u32,
+    normal: Vec3,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct CorrespondenceParams {
+    transform: Mat4,
+    num_points: u32,
+    /// Zero to skip normal estimation.
+    normal_radius: f32,
+    _padding: [f32; 2],
+}
+
+/// Matches the points of a GPU buffer to the items of an uploaded voxel grid.
+struct CorrespondenceSearch {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    params: wgpu::Buffer,
+    correspondences: wgpu::Buffer,
+    num_points: u32,
+}
+
+impl CorrespondenceSearch {
+    fn new(
+        device: &wgpu::Device,
+        source: &wgpu::Buffer,
+        num_points: u32,
+        target: &DenseVoxelGpuRepresentation,
+    ) -> Result<Self, Error> {
+        if num_points
+            .div_ceil(64)
+            .div_ceil(MAX_WORKGROUPS_PER_DIMENSION)
+            > MAX_WORKGROUPS_PER_DIMENSION
+        {
+            return Err(Error::InvalidArgument("Too many points".to_string()));
+        }
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("icp.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("ICP Correspondences"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ICP Parameters"),
+            size: std::mem::size_of::<CorrespondenceParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let correspondences = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ICP Correspondences"),
+            size: (num_points as usize * std::mem::size_of::<Correspondence>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let [data, parameters] = target.bind(0, 1);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                data,
+                parameters,
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: source.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: correspondences.as_entire_binding(),
+                },
+            ],
+        });
+        Ok(Self {
+            pipeline,
+            bind_group,
+            params,
+            correspondences,
+            num_points,
+        })
+    }
+
+    /// Moves every source point by `transform` and finds its nearest target item, estimating
+    /// the target normal there from the items within `normal_radius` if it is positive.
+    async fn run(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        transform: Affine3A,
+        normal_radius: f32,
+    ) -> Vec<Correspondence> {
+        queue.write_buffer(
+            &self.params,
+            0,
+            bytemuck::bytes_of(&CorrespondenceParams {
+                transform: Mat4::from(transform),
+                num_points: self.num_points,
+                normal_radius,
+                _padding: [0.0; 2],
+            }),
+        );
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &self.bind_group, &[]);
+            let workgroups = self.num_points.div_ceil(64);
+            cpass.dispatch_workgroups(
+                workgroups.min(MAX_WORKGROUPS_PER_DIMENSION),
+                workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+                1,
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        read_buffer(device, queue, &self.correspondences).await
+    }
+}
+
+/// Aligns the point cloud in `source` to the points stored in `target` with iterative closest
+/// point, without either cloud leaving the GPU.
+///
+/// `source` holds `num_points` points of 4 floats, (x, y, z, payload), as produced by
+/// [`crate::lidar::Lidar::render_lidar_pointcloud`] or
+/// [`crate::utils::outlier_removal::remove_outliers`], and must be usable as `STORAGE`. All zero
+/// points are treated as misses and ignored.
+///
+/// Each iteration moves the source points by the current estimate, finds their nearest target
+/// items and, for [`IcpVariant::PointToPlane`], the target normals in one kernel. Only the
+/// correspondences are read back to solve the linearized least squares problem for a small
+/// rigid motion on the CPU.
+///
+/// # Arguments
+///
+/// * `device` - The `wgpu::Device` to use.
+/// * `queue` - The `wgpu::Queue` to use for submitting commands.
+/// * `source` - The points to align.
+/// * `num_points` - The number of points in `source`.
+/// * `target` - The reference points, see [`DenseVoxel::to_gpu_buffers`].
+/// * `initial` - Initial guess of the transform taking `source` onto `target`.
+/// * `params` - Variant and stopping criteria.
+///
+/// [`DenseVoxel::to_gpu_buffers`]: crate::utils::dense_voxel::DenseVoxel::to_gpu_buffers
+pub async fn icp(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    source: &wgpu::Buffer,
+    num_points: u32,
+    target: &DenseVoxelGpuRepresentation,
+    initial: Affine3A,
+    params: &IcpParams,
+) -> Result<IcpResult, Error> {
+    if num_points == 0 {
+        return Err(Error::RegistrationFailed(
+            "No correspondences within the maximum distance",
+        ));
+    }
+    let search = CorrespondenceSearch::new(device, source, num_points, target)?;
+    let normal_radius = match params.variant {
+        IcpVariant::PointToPoint => 0.0,
+        IcpVariant::PointToPlane { normal_radius } => normal_radius,
+    };
+
+    let mut transform = initial;
+    let mut result = IcpResult {
+        transform,
+        iterations: 0,
+        converged: false,
+        rmse: 0.0,
+        num_correspondences: 0,
+    };
+    for iteration in 1..=params.max_iterations {
+        let correspondences = search.run(device, queue, transform, normal_radius).await;
+
+        let mut hessian = [[0.0f64; 6]; 6];
+        let mut gradient = [0.0f64; 6];
+        let mut squared_error = 0.0;
+        let mut num_correspondences = 0;
+        for correspondence in &correspondences {
+            let error = correspondence.moved - correspondence.matched;
+            if correspondence.found == 0 || error.length() > params.max_correspondence_distance {
+                continue;
+            }
+            let point = correspondence.moved;
+            match params.variant {
+                IcpVariant::PointToPoint => {
+                    for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+                        accumulate(&mut hessian, &mut gradient, point, axis, axis.dot(error));
+                    }
+                }
+                IcpVariant::PointToPlane { .. } => {
+                    if correspondence.has_normal == 0 {
+                        continue;
+                    }
+                    let normal = correspondence.normal;
+                    accumulate(
+                        &mut hessian,
+                        &mut gradient,
+                        point,
+                        normal,
+                        normal.dot(error),
+                    );
+                }
+            }
+            squared_error += error.length_squared();
+            num_correspondences += 1;
+        }
+        if num_correspondences == 0 {
+            return Err(Error::RegistrationFailed(
+                "No correspondences within the maximum distance",
+            ));
+        }
+
+        let step = solve(hessian, gradient.map(|g| -g))
+            .ok_or(Error::RegistrationFailed("Degenerate ICP problem"))?;
+        let rotation = Vec3::new(step[0] as f32, step[1] as f32, step[2] as f32);
+        let translation = Vec3::new(step[3] as f32, step[4] as f32, step[5] as f32);
+        transform =
+            Affine3A::from_rotation_translation(Quat::from_scaled_axis(rotation), translation)
+                * transform;
+
+        result = IcpResult {
+            transform,
+            iterations: iteration,
+            converged: rotation.length() < params.convergence_threshold
+                && translation.length() < params.convergence_threshold,
+            rmse: (squared_error / num_correspondences as f32).sqrt(),
+            num_correspondences,
+        };
+        if result.converged {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+/// Adds the residual `direction . (point - target)` to the normal equations.
+///
+/// For a small rotation `w` and translation `t` the residual changes by
+/// `(point x direction) . w + direction . t`.
+fn accumulate(
+    hessian: &mut [[f64; 6]; 6],
+    gradient: &mut [f64; 6],
+    point: Vec3,
+    direction: Vec3,
+    residual: f32,
+) {
+    let rotation = point.cross(direction);
+    let jacobian = [
+        rotation.x,
+        rotation.y,
+        rotation.z,
+        direction.x,
+        direction.y,
+        direction.z,
+    ]
+    .map(|j| j as f64);
+    for i in 0..6 {
+        for j in 0..6 {
+            hessian[i][j] += jacobian[i] * jacobian[j];
+        }
+        gradient[i] += jacobian[i] * residual as f64;
+    }
+}
+
+/// Solves `a * x = b` with Gaussian elimination, or returns `None` if `a` is singular.
+fn solve(mut a: [[f64; 6]; 6], mut b: [f64; 6]) -> Option<[f64; 6]> {
+    for col in 0..6 {
+        let pivot = (col..6).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..6 {
+            let factor = a[row][col] / a[col][col];
+            let pivot_row = a[col];
+            for (value, pivot_value) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot_value;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; 6];
+    for row in (0..6).rev() {
+        let sum: f64 = (row + 1..6).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+#[test]
+fn test_solve() {
+    let mut a = [[0.0; 6]; 6];
+    for (i, row) in a.iter_mut().enumerate() {
+        row[i] = 2.0;
+        row[(i + 1) % 6] = 1.0;
+    }
+    let x = solve(a, [3.0; 6]).unwrap();
+    for value in x {
+        assert!((value - 1.0).abs() < 1e-9);
+    }
+}
+
+#[cfg(test)]
+fn upload_points(device: &wgpu::Device, points: &[Vec3]) -> wgpu::Buffer {
+    use wgpu::util::DeviceExt;
+
+    let points: Vec<glam::Vec4> = points.iter().map(|p| p.extend(1.0)).collect();
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&points),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_correspondences_and_normals() {
+    use crate::utils::{
+        dense_voxel::{DenseVoxel, VoxelItem},
+        get_raytracing_gpu,
+    };
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let mut target = DenseVoxel::new(Vec3::splat(4.0), Vec3::ZERO, 0.5, 16);
+    for i in 0..25 {
+        let position = Vec3::new(
+            1.0 + (i % 5) as f32 * 0.25,
+            1.0 + (i / 5) as f32 * 0.25,
+            2.0,
+        );
+        target.add_item(VoxelItem::new(position)).unwrap();
+    }
+    let target = target.to_gpu_buffers(&device);
+
+    // The last point is outside the grid and the zero point is a miss.
+    let queries = [
+        Vec3::new(1.3, 1.45, 2.2),
+        Vec3::new(1.5, 1.5, 1.9),
+        Vec3::new(1.5, 1.5, 5.0),
+        Vec3::ZERO,
+    ];
+    let source = upload_points(&device, &queries);
+    let search =
+        CorrespondenceSearch::new(&device, &source, queries.len() as u32, &target).unwrap();
+    let offset = Vec3::new(0.0, 0.0, 0.05);
+    let correspondences = search
+        .run(&device, &queue, Affine3A::from_translation(offset), 0.6)
+        .await;
+
+    let expected = [
+        Some(Vec3::new(1.25, 1.5, 2.0)),
+        Some(Vec3::new(1.5, 1.5, 2.0)),
+        Some(Vec3::new(1.5, 1.5, 2.0)),
+        None,
+    ];
+    for ((correspondence, query), expected) in correspondences.iter().zip(queries).zip(expected) {
+        let Some(expected) = expected else {
+            assert_eq!(correspondence.found, 0);
+            continue;
+        };
+        assert_eq!(correspondence.found, 1, "{query}");
+        assert!(correspondence.moved.abs_diff_eq(query + offset, 1e-6));
+        assert!(
+            correspondence.matched.abs_diff_eq(expected, 1e-6),
+            "{query}"
+        );
+        assert_eq!(correspondence.has_normal, 1);
+        assert!(
+            correspondence.normal.abs().abs_diff_eq(Vec3::Z, 1e-4),
+            "{}",
+            correspondence.normal
+        );
+    }
+
+    // Without a radius no normals are estimated.
+    let correspondences = search.run(&device, &queue, Affine3A::IDENTITY, 0.0).await;
+    assert!(correspondences.iter().all(|c| c.has_normal == 0));
+}
+#[cfg(test)]
+#[tokio::test]
+async fn test_icp_recovers_transform() {
+    use crate::utils::{
+        dense_voxel::{DenseVoxel, VoxelItem},
+        get_raytracing_gpu,
+    };
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // Three walls of a room corner, so the alignment is fully constrained.
+    let mut points = vec![];
+    for i in 0..20 {
+        for j in 0..20 {
+            let (u, v) = (0.5 + i as f32 * 0.2, 0.5 + j as f32 * 0.2);
+            points.push(Vec3::new(u, v, 0.5));
+            points.push(Vec3::new(u, 0.5, v));
+            points.push(Vec3::new(0.5, u, v));
+        }
+    }
+    let mut target = DenseVoxel::new(Vec3::splat(6.0), Vec3::ZERO, 0.5, 64);
+    for p in &points {
+        target.add_item(VoxelItem::new(*p)).unwrap();
+    }
+    let target = target.to_gpu_buffers(&device);
+
+    let truth = Affine3A::from_rotation_translation(
+        Quat::from_euler(glam::EulerRot::XYZ, 0.03, -0.02, 0.05),
+        Vec3::new(0.1, -0.05, 0.08),
+    );
+    let source: Vec<Vec3> = points
+        .iter()
+        .map(|p| truth.inverse().transform_point3(*p))
+        .collect();
+    let source = upload_points(&device, &source);
+
+    for variant in [
+        IcpVariant::PointToPoint,
+        IcpVariant::PointToPlane { normal_radius: 0.5 },
+    ] {
+        let params = IcpParams {
+            variant,
+            max_iterations: 50,
+            ..Default::default()
+        };
+        let result = icp(
+            &device,
+            &queue,
+            &source,
+            points.len() as u32,
+            &target,
+            Affine3A::IDENTITY,
+            &params,
+        )
+        .await
+        .unwrap();
+        assert!(result.converged, "{variant:?}");
+        assert!(
+            result.transform.abs_diff_eq(truth, 1e-3),
+            "{variant:?}: {:?}",
+            result.transform
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Loading of OBJ and STL meshes, as exported by most CAD tools, see [`load_obj`] and
+//! [`load_stl`].
+
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+
+use glam::Vec3;
+
+use crate::{AssetMesh, Error};
+
+/// Loads the triangles of an STL file, ASCII or binary, as assets.
+///
+/// The mesh becomes one asset, or several if it has more than 65536 vertices, see
+/// [`AssetMesh::from_triangles`], with the vertices shared by facets merged. The winding of the
+/// facets is normalized: facets sharing an edge are wound the same way and each connected part
+/// of the mesh faces along most of its facet normals, or outwards if a closed part has none.
+///
+/// Fails with [`Error::Io`] if the file can not be read or is not valid STL.
+pub fn load_stl(path: impl AsRef<Path>) -> Result<Vec<AssetMesh>, Error> {
+    let mesh = stl_io::read_stl(&mut BufReader::new(File::open(path)?))?;
+    let positions: Vec<[f32; 3]> = mesh.vertices.iter().map(|vertex| vertex.0).collect();
+    let mut triangles = vec![];
+    let mut normals = vec![];
+    for face in &mesh.faces {
+        triangles.push(face.vertices.map(|index| index as u32));
+        // Many exporters leave the facet normals zero.
+        normals.push(Some(Vec3::from(face.normal.0)).filter(|normal| *normal != Vec3::ZERO));
+    }
+    normalize_winding(&positions, &mut triangles, &normals);
+    Ok(AssetMesh::from_triangles(&positions, triangles))
+}
+
+/// Loads the faces of every object of an OBJ file as assets.
+///
+/// The objects are merged into one mesh, which becomes one asset, or several if it has more
+/// than 65536 vertices, see [`AssetMesh::from_triangles`]. Quads and other polygons are
+/// triangulated as fans, points and lines are skipped and materials are ignored. The winding
+/// of the triangles is normalized like [`load_stl`] does, along the vertex normals of the file.
+///
+/// Fails with [`Error::Io`] if the file can not be read and with [`Error::InvalidArgument`] if
+/// it is not valid OBJ.
+pub fn load_obj(path: impl AsRef<Path>) -> Result<Vec<AssetMesh>, Error> {
+    let path = path.as_ref();
+    let options = tobj::LoadOptions {
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+        ..Default::default()
+    };
+    let (models, _) = tobj::load_obj_buf(&mut BufReader::new(File::open(path)?), &options, |_| {
+        Err(tobj::LoadError::GenericFailure)
+    })
+    .map_err(|e| Error::InvalidArgument(format!("Invalid OBJ {}: {e}", path.display())))?;
+
+    let mut positions = vec![];
+    let mut triangles = vec![];
+    let mut normals = vec![];
+    for tobj::Model { mesh, .. } in models {
+        let first = positions.len() as u32;
+        positions.extend(mesh.positions.chunks_exact(3).map(|p| [p[0], p[1], p[2]]));
+        let vertex_normals: Vec<_> = mesh.normals.chunks_exact(3).map(Vec3::from_slice).collect();
+        for (t, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+            triangles.push([triangle[0], triangle[1], triangle[2]].map(|index| first + index));
+            // Normals are indexed separately, and only if the file has any.
+            let normal = mesh
+                .normal_indices
+                .get(3 * t..3 * t + 3)
+                .map(|indices| indices.iter().map(|&i| vertex_normals[i as usize]).sum());
+            normals.push(normal.filter(|normal: &Vec3| *normal != Vec3::ZERO));
+        }
+    }
+    normalize_winding(&positions, &mut triangles, &normals);
+    Ok(AssetMesh::from_triangles(&positions, triangles))
+}
+
+/// Winds the triangles of each connected part of a mesh the same way, then flips the parts
+/// facing against most of the `normals` given for their triangles. Parts without any normals
+/// are flipped if their signed volume is negative, so closed ones face outwards.
+fn normalize_winding(positions: &[[f32; 3]], triangles: &mut [[u32; 3]], normals: &[Option<Vec3>]) {
+    let edges_of = |triangle: [u32; 3]| (0..3).map(move |e| (triangle[e], triangle[(e + 1) % 3]));
+    let mut edge_triangles: HashMap<[u32; 2], Vec<usize>> = HashMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        for (a, b) in edges_of(*triangle) {
+            edge_triangles
+                .entry([a.min(b), a.max(b)])
+                .or_default()
+                .push(t);
+        }
+    }
+    let normal_of = |triangle: [u32; 3]| {
+        let [p0, p1, p2] = triangle.map(|i| Vec3::from(positions[i as usize]));
+        (p1 - p0).cross(p2 - p0)
+    };
+
+    let mut visited = vec![false; triangles.len()];
+    for seed in 0..triangles.len() {
+        if visited[seed] {
+            continue;
+        }
+        // Walks the part of the seed, flipping the neighbours running along a shared edge the
+        // same way as the triangle they were reached from.
+        visited[seed] = true;
+        let mut part = vec![seed];
+        let mut next = 0;
+        while let Some(&t) = part.get(next) {
+            next += 1;
+            for (a, b) in edges_of(triangles[t]) {
+                for &neighbour in &edge_triangles[&[a.min(b), a.max(b)]] {
+                    if visited[neighbour] {
+                        continue;
+                    }
+                    visited[neighbour] = true;
+                    if edges_of(triangles[neighbour]).any(|edge| edge == (a, b)) {
+                        triangles[neighbour].swap(1, 2);
+                    }
+                    part.push(neighbour);
+                }
+            }
+        }
+
+        let agreement: i64 = part
+            .iter()
+            .filter_map(|&t| normals[t].map(|normal| normal_of(triangles[t]).dot(normal)))
+            .map(|dot| (dot > 0.0) as i64 - (dot < 0.0) as i64)
+            .sum();
+        let flip = match agreement.cmp(&0) {
+            std::cmp::Ordering::Less => true,
+            std::cmp::Ordering::Greater => false,
+            std::cmp::Ordering::Equal => {
+                let volume: f32 = part
+                    .iter()
+                    .map(|&t| {
+                        let [p0, p1, p2] = triangles[t].map(|i| Vec3::from(positions[i as usize]));
+                        p0.dot(p1.cross(p2))
+                    })
+                    .sum();
+                volume < 0.0
+            }
+        };
+        if flip {
+            for &t in &part {
+                triangles[t].swap(1, 2);
+            }
+        }
+    }
+}
+
+/// Whether every triangle of a convex asset around the origin faces away from it.
+#[cfg(test)]
+fn faces_outwards(asset: &AssetMesh) -> bool {
+    let position = |i: u16| Vec3::from_slice(&asset.vertex_buf[i as usize]._pos[..3]);
+    asset.index_buf.chunks_exact(3).all(|t| {
+        let [p0, p1, p2] = [t[0], t[1], t[2]].map(position);
+        (p1 - p0).cross(p2 - p0).dot(p0 + p1 + p2) > 0.0
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn test_load_stl() {
+    let directory = tempfile::tempdir().unwrap();
+
+    // A tetrahedron without normals, the facet on z = -1 wound inwards.
+    let path = directory.path().join("tetrahedron.stl");
+    let facet = |vertices: [[f32; 3]; 3]| {
+        let vertices = vertices.map(|[x, y, z]| format!("vertex {x} {y} {z}\n"));
+        format!(
+            "facet normal 0 0 0\nouter loop\n{}endloop\nendfacet\n",
+            vertices.concat()
+        )
+    };
+    let (a, b, c, d) = (
+        [1.0, 1.0, -1.0],
+        [-1.0, 1.0, -1.0],
+        [0.0, -1.0, -1.0],
+        [0.0, 0.0, 1.0],
+    );
+    let facets = [[a, b, c], [a, b, d], [b, c, d], [c, a, d]]
+        .map(facet)
+        .concat();
+    std::fs::write(
+        &path,
+        format!("solid tetrahedron\n{facets}endsolid tetrahedron\n"),
+    )
+    .unwrap();
+    let assets = load_stl(&path).unwrap();
+    assert_eq!(assets.len(), 1);
+    assert_eq!(assets[0].vertex_buf.len(), 4);
+    assert_eq!(assets[0].index_buf.len(), 12);
+    assert!(faces_outwards(&assets[0]));
+
+    // A binary STL of one facet wound against its normal.
+    let path = directory.path().join("facet.stl");
+    let mut stl = vec![0u8; 80];
+    stl.extend(1u32.to_le_bytes());
+    for value in [
+        0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 1.0, 0.0, 1.0,
+    ] {
+        stl.extend(value.to_le_bytes());
+    }
+    stl.extend([0, 0]);
+    std::fs::write(&path, &stl).unwrap();
+    let assets = load_stl(&path).unwrap();
+    assert_eq!(assets.len(), 1);
+    assert!(faces_outwards(&assets[0]));
+
+    directory.close().unwrap();
+    assert!(matches!(load_stl(&path), Err(Error::Io(_))));
+}
+
+#[cfg(test)]
+#[test]
+fn test_load_obj() {
+    let directory = tempfile::tempdir().unwrap();
+
+    // A cube of quads, the top and bottom wound inwards, in two objects.
+    let path = directory.path().join("cube.obj");
+    let mut obj = String::new();
+    for i in 0..8 {
+        let [x, y, z] = [i & 1, (i >> 1) & 1, (i >> 2) & 1].map(|bit| 2 * bit - 1);
+        obj += &format!("v {x} {y} {z}\n");
+    }
+    obj += "o sides\nf 1 2 6 5\nf 2 4 8 6\nf 4 3 7 8\nf 3 1 5 7\n";
+    obj += "o caps\nf 5 7 8 6\nf 1 2 4 3\n";
+    std::fs::write(&path, &obj).unwrap();
+    let assets = load_obj(&path).unwrap();
+    assert_eq!(assets.len(), 1);
+    assert_eq!(assets[0].index_buf.len(), 36);
+    assert!(faces_outwards(&assets[0]));
+
+    // A quad at z = 1 wound against its vertex normals.
+    let path = directory.path().join("quad.obj");
+    let quad = "v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\nvn 0 0 1\nf 1//1 4//1 3//1 2//1\n";
+    std::fs::write(&path, quad).unwrap();
+    let assets = load_obj(&path).unwrap();
+    assert_eq!(assets[0].index_buf.len(), 6);
+    assert!(faces_outwards(&assets[0]));
+
+    std::fs::write(&path, "f 1 2 3\n").unwrap();
+    assert!(matches!(load_obj(&path), Err(Error::InvalidArgument(_))));
+    directory.close().unwrap();
+    assert!(matches!(load_obj(&path), Err(Error::Io(_))));
+}
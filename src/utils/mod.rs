@@ -1,8 +1,37 @@
 use wgpu::{Adapter, Device, Queue};
 
-use crate::{vertex, AssetMesh};
+use crate::{vertex, AssetMesh, Error};
 
+pub(crate) mod bind_layout;
+pub(crate) mod buffer_pool;
+pub mod change_detection;
+pub mod connected_components;
+pub mod costmap;
 pub mod dense_voxel;
+pub mod esdf;
+pub(crate) mod half_pack;
+pub mod height_map;
+pub mod icp;
+#[cfg(feature = "asset-import")]
+mod mesh_import;
+pub mod octree;
+pub mod outlier_removal;
+pub(crate) mod prefix_sum;
+pub(crate) mod readback_ring;
+pub mod sparse_voxel;
+pub mod tsdf;
+pub(crate) mod uniform_belt;
+pub mod voxel_raycast;
+pub mod voxelize;
+
+#[cfg(feature = "asset-import")]
+pub use mesh_import::{load_obj, load_stl};
+
+/// Maximum number of workgroups along one dispatch dimension.
+///
+/// This is the minimum of `max_compute_workgroups_per_dimension` guaranteed by
+/// [`wgpu::Limits::default`], so compute kernels split larger 1D dispatches into a 2D grid.
+pub(crate) const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
 
 /// Lets create a cube with 6 faces
 pub fn create_cube(size: f32) -> AssetMesh {
@@ -54,90 +83,210 @@ pub fn create_cube(size: f32) -> AssetMesh {
     }
 }
 
-/// If the environment variable `WGPU_ADAPTER_NAME` is set, this function will attempt to
-/// initialize the adapter with that name. If it is not set, it will attempt to initialize
-/// the adapter which supports the required features.
-async fn get_adapter_with_capabilities_or_from_env(
-    instance: &wgpu::Instance,
-    required_features: &wgpu::Features,
-    required_downlevel_capabilities: &wgpu::DownlevelCapabilities,
-) -> wgpu::Adapter {
-    use wgpu::Backends;
-    if std::env::var("WGPU_ADAPTER_NAME").is_ok() {
-        let adapter = wgpu::util::initialize_adapter_from_env_or_default(instance, None)
-            .await
-            .expect("No suitable GPU adapters found on the system!");
-
-        let adapter_info = adapter.get_info();
-        println!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
-
-        let adapter_features = adapter.features();
-        assert!(
-            adapter_features.contains(*required_features),
-            "Adapter does not support required features for this example: {:?}",
-            *required_features - adapter_features
-        );
-
-        let downlevel_capabilities = adapter.get_downlevel_capabilities();
-        assert!(
-            downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
-            "Adapter does not support the minimum shader model required to run this example: {:?}",
-            required_downlevel_capabilities.shader_model
-        );
-        assert!(
-                downlevel_capabilities
-                    .flags
-                    .contains(required_downlevel_capabilities.flags),
-                "Adapter does not support the downlevel capabilities required to run this example: {:?}",
-                required_downlevel_capabilities.flags - downlevel_capabilities.flags
-            );
-        adapter
-    } else {
-        let adapters = instance.enumerate_adapters(Backends::all());
-
-        let mut chosen_adapter = None;
-        for adapter in adapters {
-            let required_features = *required_features;
-            let adapter_features = adapter.features();
-            if !adapter_features.contains(required_features) {
-                continue;
-            } else {
-                chosen_adapter = Some(adapter);
-                break;
-            }
+/// Creates a UV sphere of `radius` centered on the origin, with `slices` segments around the z
+/// axis and `stacks` from pole to pole. Its vertices lie on the sphere.
+pub fn create_sphere(radius: f32, slices: u16, stacks: u16) -> AssetMesh {
+    assert!(
+        slices >= 3 && stacks >= 2,
+        "A sphere needs 3 slices and 2 stacks"
+    );
+    let mut vertex_data = vec![vertex([0.0, 0.0, radius])];
+    for stack in 1..stacks {
+        let polar = std::f32::consts::PI * stack as f32 / stacks as f32;
+        for slice in 0..slices {
+            let azimuth = std::f32::consts::TAU * slice as f32 / slices as f32;
+            vertex_data.push(vertex([
+                radius * polar.sin() * azimuth.cos(),
+                radius * polar.sin() * azimuth.sin(),
+                radius * polar.cos(),
+            ]));
         }
+    }
+    vertex_data.push(vertex([0.0, 0.0, -radius]));
+
+    let ring = |stack: u16, slice: u16| 1 + (stack - 1) * slices + slice % slices;
+    let south = vertex_data.len() as u16 - 1;
+    let mut index_data = vec![];
+    for slice in 0..slices {
+        index_data.extend([0, ring(1, slice), ring(1, slice + 1)]);
+        for stack in 1..stacks - 1 {
+            let (a, b) = (ring(stack, slice), ring(stack, slice + 1));
+            let (c, d) = (ring(stack + 1, slice), ring(stack + 1, slice + 1));
+            index_data.extend([a, c, d, d, b, a]);
+        }
+        index_data.extend([ring(stacks - 1, slice), south, ring(stacks - 1, slice + 1)]);
+    }
 
-        chosen_adapter.expect("No suitable GPU adapters found on the system!")
+    AssetMesh {
+        vertex_buf: vertex_data,
+        index_buf: index_data,
     }
 }
 
-pub async fn get_raytracing_gpu(instance: &wgpu::Instance) -> (Adapter, Device, Queue) {
-    let required_features = wgpu::Features::TEXTURE_BINDING_ARRAY
-        | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY
-        | wgpu::Features::VERTEX_WRITABLE_STORAGE
-        | wgpu::Features::EXPERIMENTAL_RAY_QUERY
-        | wgpu::Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE;
-    let required_downlevel_capabilities = wgpu::DownlevelCapabilities::default();
-    let adapter = get_adapter_with_capabilities_or_from_env(
-        instance,
-        &required_features,
-        &required_downlevel_capabilities,
-    )
-    .await;
-
-    let Ok((device, queue)) = adapter
+/// Creates a closed cylinder of `radius` along the z axis, `length` long and centered on the
+/// origin, with `slices` segments around the axis. Its faces point outwards.
+pub fn create_cylinder(radius: f32, length: f32, slices: u16) -> AssetMesh {
+    assert!(slices >= 3, "A cylinder needs 3 slices");
+    let half = length / 2.0;
+    let mut vertex_data = vec![vertex([0.0, 0.0, half]), vertex([0.0, 0.0, -half])];
+    for slice in 0..slices {
+        let azimuth = std::f32::consts::TAU * slice as f32 / slices as f32;
+        let (x, y) = (radius * azimuth.cos(), radius * azimuth.sin());
+        vertex_data.extend([vertex([x, y, half]), vertex([x, y, -half])]);
+    }
+
+    let top = |slice: u16| 2 + 2 * (slice % slices);
+    let bottom = |slice: u16| top(slice) + 1;
+    let mut index_data = vec![];
+    for slice in 0..slices {
+        index_data.extend([0, top(slice), top(slice + 1)]);
+        index_data.extend([1, bottom(slice + 1), bottom(slice)]);
+        index_data.extend([bottom(slice), bottom(slice + 1), top(slice + 1)]);
+        index_data.extend([top(slice + 1), top(slice), bottom(slice)]);
+    }
+
+    AssetMesh {
+        vertex_buf: vertex_data,
+        index_buf: index_data,
+    }
+}
+
+/// Copies a GPU buffer into a staging buffer and reads it back to the CPU.
+///
+/// `buffer` must have been created with `COPY_SRC`. Blocks until the device is idle.
+pub(crate) async fn read_buffer<T: bytemuck::Pod>(
+    device: &Device,
+    queue: &Queue,
+    buffer: &wgpu::Buffer,
+) -> Vec<T> {
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    queue.submit(Some(encoder.finish()));
+
+    let buffer_slice = staging_buffer.slice(..);
+    let (sender, receiver) = flume::bounded(1);
+    buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    device.poll(wgpu::PollType::wait()).unwrap();
+    receiver.recv_async().await.unwrap().unwrap();
+
+    let data = buffer_slice.get_mapped_range();
+    let result = bytemuck::cast_slice(&data).to_vec();
+    drop(data);
+    staging_buffer.unmap();
+    result
+}
+
+/// Pops an error scope pushed with [`wgpu::ErrorFilter::Validation`] and returns the error it
+/// captured, if any.
+pub(crate) async fn pop_validation_scope(device: &Device) -> Result<(), Error> {
+    match device.pop_error_scope().await {
+        Some(error) => Err(Error::Gpu(error)),
+        None => Ok(()),
+    }
+}
+
+/// Features every device running the crate's pipelines must support.
+pub const RAYTRACING_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+    .union(wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY)
+    .union(wgpu::Features::VERTEX_WRITABLE_STORAGE)
+    .union(wgpu::Features::EXPERIMENTAL_RAY_QUERY)
+    .union(wgpu::Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE);
+
+/// Returns true if `device` was opened with [`RAYTRACING_FEATURES`].
+pub(crate) fn supports_ray_queries(device: &Device) -> bool {
+    device.features().contains(RAYTRACING_FEATURES)
+}
+
+/// Lists every adapter supporting [`RAYTRACING_FEATURES`], in the order reported by `instance`.
+///
+/// The same GPU may show up once per backend, e.g. through Vulkan and DX12 on Windows.
+pub fn enumerate_raytracing_adapters(instance: &wgpu::Instance) -> Vec<Adapter> {
+    instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .filter(crate::is_supported)
+        .collect()
+}
+
+/// Opens a device on `adapter` with [`RAYTRACING_FEATURES`] and, where supported, pipeline
+/// caching.
+pub async fn request_raytracing_device(adapter: &Adapter) -> Result<(Device, Queue), Error> {
+    // Pipeline caching is optional, see `crate::pipeline_cache`.
+    let optional_features = adapter.features() & wgpu::Features::PIPELINE_CACHE;
+    adapter
         .request_device(&wgpu::DeviceDescriptor {
             label: None,
-            required_features,
+            required_features: RAYTRACING_FEATURES | optional_features,
             required_limits: wgpu::Limits::default()
                 .using_minimum_supported_acceleration_structure_values(),
             memory_hints: wgpu::MemoryHints::MemoryUsage,
             trace: wgpu::Trace::Off,
         })
         .await
-    else {
-        panic!("Failed to create device");
+        .map_err(Error::from)
+}
+
+/// Opens a device with [`RAYTRACING_FEATURES`] on the adapter named by `WGPU_ADAPTER_NAME`, or on
+/// the first adapter supporting them.
+///
+/// Fails with [`Error::Unsupported`] if the named adapter, or every adapter, lacks ray queries and
+/// with [`Error::DeviceRequestFailed`] if the device can not be opened.
+pub async fn try_get_raytracing_gpu(
+    instance: &wgpu::Instance,
+) -> Result<(Adapter, Device, Queue), Error> {
+    let adapter = match wgpu::util::initialize_adapter_from_env(instance, None) {
+        Ok(adapter) if crate::is_supported(&adapter) => adapter,
+        Ok(_) => {
+            return Err(Error::Unsupported(
+                "WGPU_ADAPTER_NAME names a GPU without ray queries",
+            ))
+        }
+        Err(_) => enumerate_raytracing_adapters(instance)
+            .into_iter()
+            .next()
+            .ok_or(Error::Unsupported("No GPU supporting ray queries found"))?,
+    };
+    let (device, queue) = request_raytracing_device(&adapter).await?;
+    Ok((adapter, device, queue))
+}
+
+/// Opens a device like [`try_get_raytracing_gpu`], panicking if that fails.
+pub async fn get_raytracing_gpu(instance: &wgpu::Instance) -> (Adapter, Device, Queue) {
+    try_get_raytracing_gpu(instance)
+        .await
+        .unwrap_or_else(|e| panic!("No suitable GPU adapters found: {e}"))
+}
+
+/// Opens a device like [`get_raytracing_gpu`] if an adapter supports ray queries, and on the
+/// default adapter otherwise.
+///
+/// An adapter named by `WGPU_ADAPTER_NAME` is always used, with ray queries if it supports them.
+/// Scenes created on a device without them are traced on the CPU, see
+/// [`crate::RayTraceScene::is_cpu_fallback`].
+pub async fn get_gpu(instance: &wgpu::Instance) -> (Adapter, Device, Queue) {
+    let adapter = match wgpu::util::initialize_adapter_from_env(instance, None) {
+        Ok(adapter) => adapter,
+        Err(_) => match enumerate_raytracing_adapters(instance).into_iter().next() {
+            Some(adapter) => adapter,
+            None => wgpu::util::initialize_adapter_from_env_or_default(instance, None)
+                .await
+                .expect("No GPU adapters found on the system!"),
+        },
+    };
+    let (device, queue) = if crate::is_supported(&adapter) {
+        request_raytracing_device(&adapter).await.unwrap()
+    } else {
+        adapter
+            .request_device(&wgpu::DeviceDescriptor::default())
+            .await
+            .unwrap()
     };
-    println!("Using {device:?}");
     (adapter, device, queue)
 }
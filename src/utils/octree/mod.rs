@@ -0,0 +1,530 @@
+use std::collections::VecDeque;
+
+use glam::{Vec3, Vec4};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{
+    bind_layout::{create_compute_layout, create_pipeline_layout, STORAGE, STORAGE_READ, UNIFORM},
+    read_buffer,
+};
+
+/// Marker returned by the GPU kernels when no node or point was found.
+pub const NO_MATCH: u32 = 0xFFFFFFFF;
+
+/// Maximum depth supported by the 30 bit Morton keys.
+pub const MAX_OCTREE_DEPTH: u32 = 10;
+
+/// A node of the octree, laid out as read by `query.wgsl`.
+///
+/// Children of a node are stored contiguously starting at `first_child`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+pub struct OctreeNode {
+    pub bounds_min: Vec3,
+    pub first_child: u32,
+    pub bounds_max: Vec3,
+    pub child_count: u32,
+    /// Offset of the first point of this node in the sorted point list.
+    pub point_start: u32,
+    /// Number of points below this node.
+    pub point_count: u32,
+    pub depth: u32,
+    _padding: u32,
+}
+
+impl OctreeNode {
+    pub fn is_leaf(&self) -> bool {
+        self.child_count == 0
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct OctreePoint {
+    position: Vec3,
+    index: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct MortonParams {
+    bounds_min: Vec3,
+    num_points: u32,
+    bounds_max: Vec3,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct QueryParams {
+    num_queries: u32,
+    _padding: [u32; 3],
+}
+
+/// Spreads the lower 10 bits of `v`. Must match `expand_bits` in `morton.wgsl`.
+fn expand_bits(v: u32) -> u32 {
+    let mut x = v & 0x3FF;
+    x = (x | (x << 16)) & 0x030000FF;
+    x = (x | (x << 8)) & 0x0300F00F;
+    x = (x | (x << 4)) & 0x030C30C3;
+    x = (x | (x << 2)) & 0x09249249;
+    x
+}
+
+/// Computes the 30 bit Morton key of a point inside the given bounds.
+pub fn morton_code(point: Vec3, bounds_min: Vec3, bounds_max: Vec3) -> u32 {
+    let extent = (bounds_max - bounds_min).max(Vec3::splat(1e-6));
+    let q = ((point - bounds_min) / extent * 1024.0).clamp(Vec3::ZERO, Vec3::splat(1023.0));
+    expand_bits(q.x as u32) | (expand_bits(q.y as u32) << 1) | (expand_bits(q.z as u32) << 2)
+}
+
+/// An adaptive octree over a static point set.
+///
+/// Nodes are subdivided only while they hold more than `max_points_per_leaf` points, so dense
+/// regions get fine cells while empty space costs nothing. The hierarchy is built on the CPU and
+/// uploaded to the GPU for batched nearest-neighbour and occupancy queries.
+pub struct Octree {
+    bounds_min: Vec3,
+    bounds_max: Vec3,
+    nodes: Vec<OctreeNode>,
+    points: Vec<OctreePoint>,
+}
+
+impl Octree {
+    /// Builds an octree on the CPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - The points to index.
+    /// * `max_depth` - Maximum subdivision depth, clamped to [`MAX_OCTREE_DEPTH`].
+    /// * `max_points_per_leaf` - Nodes with more points than this are subdivided.
+    pub fn from_points(points: &[Vec3], max_depth: u32, max_points_per_leaf: u32) -> Self {
+        let (bounds_min, bounds_max) = Self::compute_bounds(points);
+        let codes = points
+            .iter()
+            .map(|p| morton_code(*p, bounds_min, bounds_max))
+            .collect();
+        Self::from_codes(
+            points,
+            codes,
+            bounds_min,
+            bounds_max,
+            max_depth,
+            max_points_per_leaf,
+        )
+    }
+
+    /// Builds the same tree as [`Octree::from_points`], computing the Morton keys on the GPU.
+    ///
+    /// Only the key computation is offloaded. Sorting the keys and emitting the node hierarchy
+    /// still run on the CPU, so this pays off when the points are many and the tree is shallow.
+    pub async fn build_on_gpu(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        points: &[Vec3],
+        max_depth: u32,
+        max_points_per_leaf: u32,
+    ) -> Self {
+        let (bounds_min, bounds_max) = Self::compute_bounds(points);
+        if points.is_empty() {
+            return Self::from_codes(points, vec![], bounds_min, bounds_max, 0, 0);
+        }
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("morton.wgsl"));
+        let point_data: Vec<Vec4> = points.iter().map(|p| p.extend(0.0)).collect();
+        let point_buf = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Octree Points"),
+            contents: bytemuck::cast_slice(&point_data),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Morton Parameters"),
+            contents: bytemuck::cast_slice(&[MortonParams {
+                bounds_min,
+                num_points: points.len() as u32,
+                bounds_max,
+                _padding: 0,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let code_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Morton Codes"),
+            size: (points.len() * 4) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("octree_morton"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &compute_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: point_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: code_buf.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&compute_pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups((points.len() as u32).div_ceil(64), 1, 1);
+        }
+        queue.submit(Some(encoder.finish()));
+        let codes = read_buffer(device, queue, &code_buf).await;
+
+        Self::from_codes(
+            points,
+            codes,
+            bounds_min,
+            bounds_max,
+            max_depth,
+            max_points_per_leaf,
+        )
+    }
+
+    fn compute_bounds(points: &[Vec3]) -> (Vec3, Vec3) {
+        if points.is_empty() {
+            return (Vec3::ZERO, Vec3::ZERO);
+        }
+        let (min, max) = points.iter().fold((Vec3::MAX, Vec3::MIN), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        });
+        // Make the root a cube so that children stay cubic.
+        let size = (max - min).max_element().max(1e-3);
+        (min, min + Vec3::splat(size))
+    }
+
+    fn from_codes(
+        points: &[Vec3],
+        codes: Vec<u32>,
+        bounds_min: Vec3,
+        bounds_max: Vec3,
+        max_depth: u32,
+        max_points_per_leaf: u32,
+    ) -> Self {
+        let max_depth = max_depth.min(MAX_OCTREE_DEPTH);
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        order.sort_by_key(|&i| codes[i]);
+        let sorted_codes: Vec<u32> = order.iter().map(|&i| codes[i]).collect();
+        let sorted_points = order
+            .iter()
+            .map(|&i| OctreePoint {
+                position: points[i],
+                index: i as u32,
+            })
+            .collect();
+
+        let mut nodes = vec![OctreeNode {
+            bounds_min,
+            first_child: 0,
+            bounds_max,
+            child_count: 0,
+            point_start: 0,
+            point_count: points.len() as u32,
+            depth: 0,
+            _padding: 0,
+        }];
+
+        // Breadth first so that the children of every node are contiguous.
+        let mut queue = VecDeque::from([0usize]);
+        while let Some(node_index) = queue.pop_front() {
+            let node = nodes[node_index];
+            if node.point_count <= max_points_per_leaf || node.depth >= max_depth {
+                continue;
+            }
+            let shift = 3 * (MAX_OCTREE_DEPTH - node.depth - 1);
+            let half = (node.bounds_max - node.bounds_min) * 0.5;
+            let first_child = nodes.len() as u32;
+            let start = node.point_start as usize;
+            let end = start + node.point_count as usize;
+            let mut run_start = start;
+            while run_start < end {
+                let octant = (sorted_codes[run_start] >> shift) & 7;
+                let run_end = run_start
+                    + sorted_codes[run_start..end]
+                        .iter()
+                        .take_while(|c| (*c >> shift) & 7 == octant)
+                        .count();
+                let offset = Vec3::new(
+                    (octant & 1) as f32,
+                    ((octant >> 1) & 1) as f32,
+                    ((octant >> 2) & 1) as f32,
+                ) * half;
+                queue.push_back(nodes.len());
+                nodes.push(OctreeNode {
+                    bounds_min: node.bounds_min + offset,
+                    first_child: 0,
+                    bounds_max: node.bounds_min + offset + half,
+                    child_count: 0,
+                    point_start: run_start as u32,
+                    point_count: (run_end - run_start) as u32,
+                    depth: node.depth + 1,
+                    _padding: 0,
+                });
+                run_start = run_end;
+            }
+            nodes[node_index].first_child = first_child;
+            nodes[node_index].child_count = nodes.len() as u32 - first_child;
+        }
+
+        Self {
+            bounds_min,
+            bounds_max,
+            nodes,
+            points: sorted_points,
+        }
+    }
+
+    /// Returns the bounds of the root node.
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        (self.bounds_min, self.bounds_max)
+    }
+
+    /// Returns all nodes. The root is at index 0.
+    pub fn nodes(&self) -> &[OctreeNode] {
+        &self.nodes
+    }
+
+    /// Returns the original indices of the points stored below `node`.
+    pub fn points_in_node(&self, node: &OctreeNode) -> Vec<u32> {
+        let start = node.point_start as usize;
+        self.points[start..start + node.point_count as usize]
+            .iter()
+            .map(|p| p.index)
+            .collect()
+    }
+
+    async fn run_query(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        entry_point: &str,
+        query_points: &[Vec3],
+    ) -> Vec<u32> {
+        if query_points.is_empty() {
+            return vec![];
+        }
+        if self.points.is_empty() {
+            return vec![NO_MATCH; query_points.len()];
+        }
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("query.wgsl"));
+        let nodes = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Octree Nodes"),
+            contents: bytemuck::cast_slice(&self.nodes),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let points = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Octree Points"),
+            contents: bytemuck::cast_slice(&self.points),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Octree Query Parameters"),
+            contents: bytemuck::cast_slice(&[QueryParams {
+                num_queries: query_points.len() as u32,
+                _padding: [0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let queries: Vec<Vec4> = query_points.iter().map(|p| p.extend(0.0)).collect();
+        let queries = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Queries"),
+            contents: bytemuck::cast_slice(&queries),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let result_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Result"),
+            size: (query_points.len() * 4) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // Explicit, as `occupancy` does not read the points and they would be missing from a
+        // derived layout.
+        let bind_group_layout = create_compute_layout(
+            device,
+            "Octree Query Bind Group Layout",
+            &[STORAGE_READ, STORAGE_READ, UNIFORM, STORAGE_READ, STORAGE],
+        );
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("octree_query"),
+            layout: Some(&create_pipeline_layout(device, &bind_group_layout)),
+            module: &cs_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: nodes.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: points.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: queries.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: result_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&compute_pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups((query_points.len() as u32).div_ceil(64), 1, 1);
+        }
+        queue.submit(Some(encoder.finish()));
+        read_buffer(device, queue, &result_buffer).await
+    }
+
+    /// Finds the exact nearest point for each query on the GPU.
+    ///
+    /// Returns the index of the nearest point in the slice the tree was built from.
+    pub async fn nearest_neighbours(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        query_points: &[Vec3],
+    ) -> Vec<u32> {
+        self.run_query(device, queue, "nearest", query_points).await
+    }
+
+    /// Finds the deepest node containing each query on the GPU.
+    ///
+    /// Returns an index into [`Octree::nodes`], or [`NO_MATCH`] if the query lies in a region
+    /// without points. The node's depth gives the resolution at which the space is occupied.
+    pub async fn occupancy(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        query_points: &[Vec3],
+    ) -> Vec<u32> {
+        self.run_query(device, queue, "occupancy", query_points)
+            .await
+    }
+}
+
+#[cfg(test)]
+fn check_leaves(octree: &Octree, points: &[Vec3], max_depth: u32, max_points_per_leaf: u32) {
+    let leaves: Vec<_> = octree.nodes().iter().filter(|n| n.is_leaf()).collect();
+    let total: u32 = leaves.iter().map(|n| n.point_count).sum();
+    assert_eq!(total as usize, points.len());
+    for leaf in leaves {
+        assert!(leaf.point_count <= max_points_per_leaf || leaf.depth == max_depth);
+        for index in octree.points_in_node(leaf) {
+            let p = points[index as usize];
+            assert!(p.cmpge(leaf.bounds_min).all() && p.cmple(leaf.bounds_max).all());
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_octree_build() {
+    let mut points = vec![];
+    for x in 0..8 {
+        for y in 0..8 {
+            for z in 0..8 {
+                points.push(Vec3::new(x as f32, y as f32, z as f32) * 0.5);
+            }
+        }
+    }
+    points.push(Vec3::new(100.0, 100.0, 100.0));
+    let octree = Octree::from_points(&points, 8, 4);
+    check_leaves(&octree, &points, 8, 4);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_octree_queries_match_brute_force() {
+    use crate::utils::get_raytracing_gpu;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A dense cluster next to sparse points, so leaves end up at different depths.
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut points: Vec<Vec3> = (0..1500)
+        .map(|_| Vec3::new(rng.random(), rng.random(), rng.random()) * 0.5)
+        .collect();
+    points.extend((0..200).map(|_| Vec3::new(rng.random(), rng.random(), rng.random()) * 8.0));
+    let queries: Vec<Vec3> = (0..500)
+        .map(|_| Vec3::new(rng.random(), rng.random(), rng.random()) * 10.0 - Vec3::ONE)
+        .collect();
+
+    let cpu = Octree::from_points(&points, 8, 8);
+    let gpu = Octree::build_on_gpu(&device, &queue, &points, 8, 8).await;
+    check_leaves(&gpu, &points, 8, 8);
+    for octree in [cpu, gpu] {
+        let leaf_depths: Vec<u32> = octree
+            .nodes()
+            .iter()
+            .filter(|n| n.is_leaf())
+            .map(|n| n.depth)
+            .collect();
+        assert!(leaf_depths.iter().max() > leaf_depths.iter().min());
+
+        let nearest = octree.nearest_neighbours(&device, &queue, &queries).await;
+        for (query, index) in queries.iter().zip(nearest) {
+            let expected = points
+                .iter()
+                .map(|p| p.distance(*query))
+                .fold(f32::MAX, f32::min);
+            assert_eq!(points[index as usize].distance(*query), expected, "{query}");
+        }
+
+        let occupancy = octree.occupancy(&device, &queue, &queries).await;
+        for (query, node) in queries.iter().zip(occupancy) {
+            let deepest = octree
+                .nodes()
+                .iter()
+                .enumerate()
+                .filter(|(_, n)| query.cmpge(n.bounds_min).all() && query.cmple(n.bounds_max).all())
+                .max_by_key(|(_, n)| n.depth);
+            let expected = match deepest {
+                Some((index, n)) if n.is_leaf() => index as u32,
+                _ => NO_MATCH,
+            };
+            assert_eq!(node, expected, "{query}");
+        }
+    }
+}
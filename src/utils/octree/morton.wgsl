@@ -0,0 +1,40 @@
+struct MortonParams {
+    bounds_min: vec3<f32>,
+    num_points: u32,
+    bounds_max: vec3<f32>,
+    _padding: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> points: array<vec4<f32>>;
+
+@group(0)
+@binding(1)
+var<uniform> params: MortonParams;
+
+@group(0)
+@binding(2)
+var<storage, read_write> codes: array<u32>;
+
+/// Spreads the lower 10 bits of `v` so there are two zero bits between each bit.
+fn expand_bits(v: u32) -> u32 {
+    var x = v & 0x3FFu;
+    x = (x | (x << 16u)) & 0x030000FFu;
+    x = (x | (x << 8u)) & 0x0300F00Fu;
+    x = (x | (x << 4u)) & 0x030C30C3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
+
+@compute
+@workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_points {
+        return;
+    }
+    let extent = max(params.bounds_max - params.bounds_min, vec3<f32>(1e-6));
+    let normalized = (points[global_id.x].xyz - params.bounds_min) / extent;
+    let q = vec3<u32>(clamp(normalized * 1024.0, vec3<f32>(0.0), vec3<f32>(1023.0)));
+    codes[global_id.x] = expand_bits(q.x) | (expand_bits(q.y) << 1u) | (expand_bits(q.z) << 2u);
+}
@@ -0,0 +1,129 @@
+struct OctreeNode {
+    bounds_min: vec3<f32>,
+    first_child: u32,
+    bounds_max: vec3<f32>,
+    child_count: u32,
+    point_start: u32,
+    point_count: u32,
+    depth: u32,
+    _padding: u32,
+}
+
+struct OctreePoint {
+    position: vec3<f32>,
+    index: u32,
+}
+
+struct QueryParams {
+    num_queries: u32,
+    _padding: u32,
+    _padding2: u32,
+    _padding3: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> nodes: array<OctreeNode>;
+
+@group(0)
+@binding(1)
+var<storage, read> points: array<OctreePoint>;
+
+@group(0)
+@binding(2)
+var<uniform> params: QueryParams;
+
+@group(0)
+@binding(3)
+var<storage, read> queries: array<vec4<f32>>;
+
+@group(0)
+@binding(4)
+var<storage, read_write> results: array<u32>;
+
+const NO_MATCH: u32 = 0xFFFFFFFFu;
+const STACK_SIZE: u32 = 96u;
+
+fn aabb_distance_squared(p: vec3<f32>, node: OctreeNode) -> f32 {
+    let d = max(max(node.bounds_min - p, p - node.bounds_max), vec3<f32>(0.0));
+    return dot(d, d);
+}
+
+/// Exact nearest neighbour using a depth first traversal pruned by node bounds.
+@compute
+@workgroup_size(64)
+fn nearest(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_queries {
+        return;
+    }
+    let p = queries[global_id.x].xyz;
+    var stack: array<u32, STACK_SIZE>;
+    var stack_len: u32 = 1u;
+    stack[0] = 0u;
+    var best_distance: f32 = 3.4e38;
+    var best_index: u32 = NO_MATCH;
+    while stack_len > 0u {
+        stack_len -= 1u;
+        let node = nodes[stack[stack_len]];
+        if aabb_distance_squared(p, node) >= best_distance {
+            continue;
+        }
+        if node.child_count == 0u {
+            for (var i: u32 = 0u; i < node.point_count; i++) {
+                let point = points[node.point_start + i];
+                let diff = point.position - p;
+                let distance = dot(diff, diff);
+                if distance < best_distance {
+                    best_distance = distance;
+                    best_index = point.index;
+                }
+            }
+            continue;
+        }
+        for (var c: u32 = 0u; c < node.child_count; c++) {
+            if stack_len < STACK_SIZE {
+                stack[stack_len] = node.first_child + c;
+                stack_len += 1u;
+            }
+        }
+    }
+    results[global_id.x] = best_index;
+}
+
+fn contains(node: OctreeNode, p: vec3<f32>) -> bool {
+    return all(p >= node.bounds_min) && all(p <= node.bounds_max);
+}
+
+/// Returns the deepest node containing the query, or NO_MATCH if the query lies in empty space.
+@compute
+@workgroup_size(64)
+fn occupancy(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_queries {
+        return;
+    }
+    let p = queries[global_id.x].xyz;
+    if !contains(nodes[0], p) || nodes[0].point_count == 0u {
+        results[global_id.x] = NO_MATCH;
+        return;
+    }
+    var current: u32 = 0u;
+    loop {
+        let node = nodes[current];
+        if node.child_count == 0u {
+            results[global_id.x] = current;
+            return;
+        }
+        var next = NO_MATCH;
+        for (var c: u32 = 0u; c < node.child_count; c++) {
+            if contains(nodes[node.first_child + c], p) {
+                next = node.first_child + c;
+                break;
+            }
+        }
+        if next == NO_MATCH {
+            results[global_id.x] = NO_MATCH;
+            return;
+        }
+        current = next;
+    }
+}
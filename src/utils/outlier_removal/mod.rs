@@ -0,0 +1,280 @@
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{prefix_sum::encode_inclusive_scan, read_buffer, MAX_WORKGROUPS_PER_DIMENSION};
+use crate::Error;
+
+/// Largest `k` supported by [`OutlierFilter::Statistical`].
+pub const MAX_K: u32 = 16;
+
+/// Criterion deciding which points of a cloud are outliers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutlierFilter {
+    /// Keeps points with at least `min_neighbours` other points within `radius`.
+    Radius { radius: f32, min_neighbours: u32 },
+    /// Keeps points whose mean distance to their `k` nearest neighbours is at most `std_ratio`
+    /// standard deviations above the mean over the whole cloud, as in PCL's
+    /// `StatisticalOutlierRemoval`.
+    Statistical { k: u32, std_ratio: f32 },
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct FilterParams {
+    num_points: u32,
+    mode: u32,
+    k: u32,
+    min_neighbours: u32,
+    radius: f32,
+    std_ratio: f32,
+    _padding: [f32; 2],
+}
+
+/// The points kept by [`remove_outliers`], still on the GPU.
+pub struct FilteredPointCloud {
+    points: wgpu::Buffer,
+    len: u32,
+}
+
+impl FilteredPointCloud {
+    /// The buffer holding the kept points as 4 floats each, in their original order. Only the
+    /// first [`Self::len`] points are meaningful.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.points
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads the kept points back to the CPU, 4 floats per point.
+    pub async fn download(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<f32> {
+        let mut points: Vec<f32> = read_buffer(device, queue, &self.points).await;
+        points.truncate(self.len as usize * 4);
+        points
+    }
+}
+
+/// Removes outliers from a point cloud without leaving the GPU.
+///
+/// `points` holds `num_points` points of 4 floats, (x, y, z, payload), as produced by
+/// [`crate::lidar::Lidar::render_lidar_pointcloud`], and must be usable as `STORAGE`. All zero
+/// points are treated as misses and dropped. Neighbours are found by comparing every pair of
+/// points, which suits single scans but not accumulated maps.
+///
+/// Only the number of kept points is read back to the CPU.
+pub async fn remove_outliers(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    points: &wgpu::Buffer,
+    num_points: u32,
+    filter: OutlierFilter,
+) -> Result<FilteredPointCloud, Error> {
+    let (mode, k, min_neighbours, radius, std_ratio) = match filter {
+        OutlierFilter::Radius {
+            radius,
+            min_neighbours,
+        } => (0, 1, min_neighbours, radius, 0.0),
+        OutlierFilter::Statistical { k, std_ratio } => {
+            if k == 0 || k > MAX_K {
+                return Err(Error::InvalidArgument(format!(
+                    "k must be between 1 and {MAX_K}"
+                )));
+            }
+            (1, k, 0, 0.0, std_ratio)
+        }
+    };
+    let workgroups = num_points.div_ceil(64);
+    if workgroups > MAX_WORKGROUPS_PER_DIMENSION {
+        return Err(Error::InvalidArgument("Too many points".to_string()));
+    }
+    let point_size = (4 * std::mem::size_of::<f32>()) as wgpu::BufferAddress;
+    let filtered = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Filtered Points"),
+        size: num_points.max(1) as wgpu::BufferAddress * point_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    if num_points == 0 {
+        return Ok(FilteredPointCloud {
+            points: filtered,
+            len: 0,
+        });
+    }
+
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("outlier_removal.wgsl"));
+    let create_pipeline = |entry_point: &str| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Outlier Filter Parameters"),
+        contents: bytemuck::cast_slice(&[FilterParams {
+            num_points,
+            mode,
+            k,
+            min_neighbours,
+            radius,
+            std_ratio,
+            _padding: [0.0; 2],
+        }]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let create_storage = |label: &str, size: wgpu::BufferAddress| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    };
+    let scores = create_storage("Outlier Scores", num_points as wgpu::BufferAddress * 4);
+    let stats = create_storage("Outlier Score Statistics", 8);
+    let keep = create_storage("Outlier Keep Flags", num_points as wgpu::BufferAddress * 4);
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let dispatch = |encoder: &mut wgpu::CommandEncoder,
+                    entry_point: &str,
+                    entries: &[wgpu::BindGroupEntry],
+                    workgroups: u32| {
+        let pipeline = create_pipeline(entry_point);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries,
+        });
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(workgroups, 1, 1);
+    };
+
+    dispatch(
+        &mut encoder,
+        "score",
+        &[entry(0, points), entry(1, &params), entry(2, &scores)],
+        workgroups,
+    );
+    dispatch(
+        &mut encoder,
+        "statistics",
+        &[entry(1, &params), entry(2, &scores), entry(3, &stats)],
+        1,
+    );
+    dispatch(
+        &mut encoder,
+        "mark",
+        &[
+            entry(1, &params),
+            entry(2, &scores),
+            entry(3, &stats),
+            entry(4, &keep),
+        ],
+        workgroups,
+    );
+    let offsets = encode_inclusive_scan(device, &mut encoder, &keep, num_points);
+    dispatch(
+        &mut encoder,
+        "compact",
+        &[
+            entry(0, points),
+            entry(1, &params),
+            entry(4, &keep),
+            entry(5, &offsets),
+            entry(6, &filtered),
+        ],
+        workgroups,
+    );
+    let count = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Filtered Point Count"),
+        size: 4,
+        usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    encoder.copy_buffer_to_buffer(
+        &offsets,
+        (num_points as wgpu::BufferAddress - 1) * 4,
+        &count,
+        0,
+        4,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let len: Vec<u32> = read_buffer(device, queue, &count).await;
+    Ok(FilteredPointCloud {
+        points: filtered,
+        len: len[0],
+    })
+}
+
+fn entry(binding: u32, buffer: &wgpu::Buffer) -> wgpu::BindGroupEntry<'_> {
+    wgpu::BindGroupEntry {
+        binding,
+        resource: buffer.as_entire_binding(),
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_remove_outliers() {
+    use crate::utils::get_raytracing_gpu;
+    use glam::Vec4;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A dense patch of a wall, a couple of stray returns and a miss.
+    let mut cloud: Vec<Vec4> = (0..400)
+        .map(|i| Vec4::new(5.0, (i % 20) as f32 * 0.05, (i / 20) as f32 * 0.05, 5.0))
+        .collect();
+    let outliers = [
+        Vec4::new(2.0, 3.0, 1.0, 4.0),
+        Vec4::new(9.0, -4.0, 0.5, 9.0),
+    ];
+    cloud.insert(37, outliers[0]);
+    cloud.insert(211, Vec4::ZERO);
+    cloud.push(outliers[1]);
+    let buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&cloud),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    for filter in [
+        OutlierFilter::Radius {
+            radius: 0.2,
+            min_neighbours: 3,
+        },
+        OutlierFilter::Statistical {
+            k: 8,
+            std_ratio: 2.0,
+        },
+    ] {
+        let result = remove_outliers(&device, &queue, &buffer, cloud.len() as u32, filter)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 400, "{filter:?}");
+        let kept = result.download(&device, &queue).await;
+        let kept: &[Vec4] = bytemuck::cast_slice(&kept);
+        let expected: Vec<Vec4> = cloud
+            .iter()
+            .copied()
+            .filter(|p| *p != Vec4::ZERO && !outliers.contains(p))
+            .collect();
+        assert_eq!(kept, expected.as_slice());
+    }
+}
@@ -0,0 +1,186 @@
+// Point cloud outlier removal. Points are `vec4<f32>`s of (x, y, z, payload), all zero points are
+// misses and are always dropped.
+
+const MAX_K: u32 = 16u;
+const TILE_SIZE: u32 = 64u;
+
+const MODE_RADIUS: u32 = 0u;
+const MODE_STATISTICAL: u32 = 1u;
+
+/// Score of points that can never be kept.
+const NO_SCORE: f32 = -1.0;
+
+struct FilterParams {
+    num_points: u32,
+    mode: u32,
+    k: u32,
+    min_neighbours: u32,
+    radius: f32,
+    std_ratio: f32,
+    _padding: vec2<f32>,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> points: array<vec4<f32>>;
+
+@group(0)
+@binding(1)
+var<uniform> params: FilterParams;
+
+/// Neighbour count in radius mode, mean distance to the `k` nearest neighbours in statistical
+/// mode.
+@group(0)
+@binding(2)
+var<storage, read_write> scores: array<f32>;
+
+/// Mean and standard deviation of the valid scores.
+@group(0)
+@binding(3)
+var<storage, read_write> stats: array<f32, 2>;
+
+@group(0)
+@binding(4)
+var<storage, read_write> keep: array<u32>;
+
+/// Inclusive prefix sum of `keep`.
+@group(0)
+@binding(5)
+var<storage, read> offsets: array<u32>;
+
+@group(0)
+@binding(6)
+var<storage, read_write> filtered: array<vec4<f32>>;
+
+var<workgroup> tile: array<vec4<f32>, TILE_SIZE>;
+var<workgroup> partial_sums: array<vec3<f32>, TILE_SIZE>;
+
+fn is_valid(point: vec4<f32>) -> bool {
+    return any(point != vec4<f32>(0.0));
+}
+
+/// Compares every point against every other one, a tile at a time through workgroup memory.
+@compute
+@workgroup_size(64)
+fn score(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let index = global_id.x;
+    var own = vec4<f32>(0.0);
+    if index < params.num_points {
+        own = points[index];
+    }
+    let valid = is_valid(own);
+
+    var count: u32 = 0;
+    var nearest: array<f32, MAX_K>;
+    for (var i: u32 = 0; i < MAX_K; i++) {
+        nearest[i] = 3.4e38;
+    }
+
+    let num_tiles = (params.num_points + TILE_SIZE - 1u) / TILE_SIZE;
+    for (var t: u32 = 0; t < num_tiles; t++) {
+        let load = t * TILE_SIZE + local_index;
+        if load < params.num_points {
+            tile[local_index] = points[load];
+        } else {
+            tile[local_index] = vec4<f32>(0.0);
+        }
+        workgroupBarrier();
+        for (var j: u32 = 0; j < TILE_SIZE; j++) {
+            let other = tile[j];
+            if !valid || !is_valid(other) || t * TILE_SIZE + j == index {
+                continue;
+            }
+            let d = distance(own.xyz, other.xyz);
+            if params.mode == MODE_RADIUS {
+                if d <= params.radius {
+                    count += 1u;
+                }
+            } else if d < nearest[params.k - 1u] {
+                // Insertion into the sorted list of the k nearest distances.
+                var slot = params.k - 1u;
+                while slot > 0u && nearest[slot - 1u] > d {
+                    nearest[slot] = nearest[slot - 1u];
+                    slot -= 1u;
+                }
+                nearest[slot] = d;
+                count = min(count + 1u, params.k);
+            }
+        }
+        workgroupBarrier();
+    }
+
+    if index >= params.num_points {
+        return;
+    }
+    if !valid || (params.mode == MODE_STATISTICAL && count == 0u) {
+        scores[index] = NO_SCORE;
+    } else if params.mode == MODE_RADIUS {
+        scores[index] = f32(count);
+    } else {
+        var sum = 0.0;
+        for (var i: u32 = 0; i < count; i++) {
+            sum += nearest[i];
+        }
+        scores[index] = sum / f32(count);
+    }
+}
+
+/// Reduces the valid scores to their mean and standard deviation. Dispatched as one workgroup.
+@compute
+@workgroup_size(64)
+fn statistics(@builtin(local_invocation_index) local_index: u32) {
+    var sums = vec3<f32>(0.0);
+    for (var i = local_index; i < params.num_points; i += TILE_SIZE) {
+        let s = scores[i];
+        if s >= 0.0 {
+            sums += vec3<f32>(1.0, s, s * s);
+        }
+    }
+    partial_sums[local_index] = sums;
+    workgroupBarrier();
+    for (var stride = TILE_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if local_index < stride {
+            partial_sums[local_index] += partial_sums[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+    if local_index == 0u {
+        let total = partial_sums[0];
+        let n = max(total.x, 1.0);
+        let mean = total.y / n;
+        stats[0] = mean;
+        stats[1] = sqrt(max(total.z / n - mean * mean, 0.0));
+    }
+}
+
+@compute
+@workgroup_size(64)
+fn mark(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= params.num_points {
+        return;
+    }
+    let s = scores[index];
+    var kept = false;
+    if s >= 0.0 {
+        if params.mode == MODE_RADIUS {
+            kept = s >= f32(params.min_neighbours);
+        } else {
+            kept = s <= stats[0] + params.std_ratio * stats[1];
+        }
+    }
+    keep[index] = select(0u, 1u, kept);
+}
+
+@compute
+@workgroup_size(64)
+fn compact(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= params.num_points || keep[index] == 0u {
+        return;
+    }
+    filtered[offsets[index] - 1u] = points[index];
+}
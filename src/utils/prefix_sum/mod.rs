@@ -0,0 +1,235 @@
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{read_buffer, MAX_WORKGROUPS_PER_DIMENSION};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct ScanParams {
+    len: u32,
+    offset: u32,
+    _padding: [u32; 2],
+}
+
+/// Records an inclusive prefix sum of the first `len` `u32`s of `input` into `encoder`.
+///
+/// Returns a new buffer, usable as `STORAGE` and `COPY_SRC`, whose element `i` is the sum of
+/// `input[0..=i]`. An exclusive sum for element `i` is the inclusive sum at `i - 1`, or 0.
+/// `input` must have been created with `COPY_SRC`.
+pub(crate) fn encode_inclusive_scan(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    input: &wgpu::Buffer,
+    len: u32,
+) -> wgpu::Buffer {
+    let size = (len.max(1) as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+    let mut buffers = [0, 1].map(|_| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Prefix Sum"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    });
+    encoder.copy_buffer_to_buffer(input, 0, &buffers[0], 0, size);
+
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("prefix_sum.wgsl"));
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("prefix_sum"),
+        layout: None,
+        module: &cs_module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+
+    let workgroups = len.div_ceil(64);
+    let workgroups_x = workgroups.min(MAX_WORKGROUPS_PER_DIMENSION);
+    let workgroups_y = workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION);
+
+    let mut offset = 1;
+    while offset < len {
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Prefix Sum Parameters"),
+            contents: bytemuck::cast_slice(&[ScanParams {
+                len,
+                offset,
+                _padding: [0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffers[0].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffers[1].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+        drop(cpass);
+
+        buffers.swap(0, 1);
+        offset *= 2;
+    }
+    let [result, _] = buffers;
+    result
+}
+
+/// Runs a count/write kernel pair producing a variable number of `u32` results per query and
+/// returns them grouped by query.
+///
+/// The `count` entry point writes the number of results of each query to `counts_binding`. Their
+/// inclusive prefix sum is then bound at `offsets_binding` for the `write` entry point, which
+/// stores the results of query `i` contiguously into `matches_binding`, starting at the inclusive
+/// sum of query `i - 1`. `entries` holds the remaining bindings, shared by both passes.
+pub(crate) async fn run_compacted_query(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    module: &wgpu::ShaderModule,
+    [count, write]: [&str; 2],
+    [counts_binding, offsets_binding, matches_binding]: [u32; 3],
+    entries: &[wgpu::BindGroupEntry<'_>],
+    num_queries: u32,
+) -> Vec<Vec<u32>> {
+    if num_queries == 0 {
+        return vec![];
+    }
+    let create_pipeline = |entry_point: &str| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+    let dispatch = |encoder: &mut wgpu::CommandEncoder,
+                    pipeline: &wgpu::ComputePipeline,
+                    extra: &[wgpu::BindGroupEntry]| {
+        let all_entries = [entries, extra].concat();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &all_entries,
+        });
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(num_queries.div_ceil(64), 1, 1);
+    };
+
+    // Pass 1: count results per query and scan the counts.
+    let counts = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Result Counts"),
+        size: (num_queries as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    dispatch(
+        &mut encoder,
+        &create_pipeline(count),
+        &[wgpu::BindGroupEntry {
+            binding: counts_binding,
+            resource: counts.as_entire_binding(),
+        }],
+    );
+    let offsets_buf = encode_inclusive_scan(device, &mut encoder, &counts, num_queries);
+    queue.submit(Some(encoder.finish()));
+    let offsets: Vec<u32> = read_buffer(device, queue, &offsets_buf).await;
+    let offsets = &offsets[..num_queries as usize];
+
+    // Pass 2: write the results at their compacted offsets.
+    let total = *offsets.last().unwrap();
+    let matches = if total == 0 {
+        vec![]
+    } else {
+        let matches = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Results"),
+            size: (total as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        dispatch(
+            &mut encoder,
+            &create_pipeline(write),
+            &[
+                wgpu::BindGroupEntry {
+                    binding: offsets_binding,
+                    resource: offsets_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: matches_binding,
+                    resource: matches.as_entire_binding(),
+                },
+            ],
+        );
+        queue.submit(Some(encoder.finish()));
+        read_buffer::<u32>(device, queue, &matches).await
+    };
+
+    let mut start = 0;
+    offsets
+        .iter()
+        .map(|end| {
+            let result = matches[start as usize..*end as usize].to_vec();
+            start = *end;
+            result
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_inclusive_scan() {
+    use crate::utils::get_raytracing_gpu;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    let values: Vec<u32> = (0..1000).map(|i| i % 7).collect();
+    let input = device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&values),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let result = encode_inclusive_scan(&device, &mut encoder, &input, values.len() as u32);
+    queue.submit(Some(encoder.finish()));
+
+    let result: Vec<u32> = read_buffer(&device, &queue, &result).await;
+    let expected: Vec<u32> = values
+        .iter()
+        .scan(0, |sum, v| {
+            *sum += v;
+            Some(*sum)
+        })
+        .collect();
+    assert_eq!(result, expected);
+}
@@ -0,0 +1,38 @@
+// One Hillis-Steele step of an inclusive prefix sum. Running this for
+// offset = 1, 2, 4, ... while ping-ponging between two buffers scans the
+// whole array in log2(len) dispatches.
+
+struct ScanParams {
+    len: u32,
+    offset: u32,
+    _padding: vec2<u32>,
+}
+
+@group(0)
+@binding(0)
+var<storage, read> scan_in: array<u32>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> scan_out: array<u32>;
+
+@group(0)
+@binding(2)
+var<uniform> params: ScanParams;
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let i = global_id.x + global_id.y * num_workgroups.x * 64u;
+    if i >= params.len {
+        return;
+    }
+    var value = scan_in[i];
+    if i >= params.offset {
+        value += scan_in[i - params.offset];
+    }
+    scan_out[i] = value;
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+
+use crate::utils::buffer_pool::BufferPool;
+
+/// A frame whose output is being copied to a staging buffer and mapped.
+struct InFlight {
+    staging: wgpu::Buffer,
+    /// The buffers the frame was recorded with, recycled once it completed.
+    buffers: Vec<wgpu::Buffer>,
+    submission: wgpu::SubmissionIndex,
+    mapped: flume::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+/// Keeps up to `frames_in_flight` sensor frames queued on the GPU.
+///
+/// Submitting frame `k` only waits for the readback of frame `k - frames_in_flight + 1`, so the
+/// GPU traces the next frame while the previous one is mapped instead of idling on every
+/// `map_async`/`poll` round trip. Staging buffers come from the sensor's [`BufferPool`], which
+/// settles on one per frame in flight.
+pub(crate) struct ReadbackRing {
+    frames_in_flight: usize,
+    in_flight: VecDeque<InFlight>,
+}
+
+impl ReadbackRing {
+    pub(crate) fn new(frames_in_flight: usize) -> Self {
+        Self {
+            frames_in_flight: frames_in_flight.max(1),
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
+
+    /// Changes the depth of the ring. Frames already in flight are kept.
+    pub(crate) fn set_frames_in_flight(&mut self, frames_in_flight: usize) {
+        self.frames_in_flight = frames_in_flight.max(1);
+    }
+
+    /// Copies the first of `buffers` into a staging buffer, submits `encoder` and starts mapping
+    /// the copy.
+    ///
+    /// `buffers` must hold every pooled buffer the frame reads or writes, output first, so they
+    /// are not reused before the frame completed. Returns the oldest frame once the ring is full.
+    pub(crate) fn submit<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &BufferPool,
+        mut encoder: wgpu::CommandEncoder,
+        buffers: Vec<wgpu::Buffer>,
+    ) -> Option<Vec<T>> {
+        let output = &buffers[0];
+        let staging = pool.take(
+            device,
+            output.size(),
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+        encoder.copy_buffer_to_buffer(output, 0, &staging, 0, staging.size());
+        let submission = queue.submit(Some(encoder.finish()));
+
+        let (sender, mapped) = flume::bounded(1);
+        staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+        self.in_flight.push_back(InFlight {
+            staging,
+            buffers,
+            submission,
+            mapped,
+        });
+
+        (self.in_flight.len() >= self.frames_in_flight).then(|| self.retire(device, pool))
+    }
+
+    /// Waits for every frame still in flight and returns them in submission order.
+    pub(crate) fn flush<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        pool: &BufferPool,
+    ) -> Vec<Vec<T>> {
+        std::iter::from_fn(|| (!self.in_flight.is_empty()).then(|| self.retire(device, pool)))
+            .collect()
+    }
+
+    /// Blocks until the oldest frame is mapped, reads it and recycles its buffers.
+    fn retire<T: bytemuck::Pod>(&mut self, device: &wgpu::Device, pool: &BufferPool) -> Vec<T> {
+        let frame = self.in_flight.pop_front().unwrap();
+        device
+            .poll(wgpu::PollType::wait_for(frame.submission))
+            .unwrap();
+        frame.mapped.recv().unwrap().unwrap();
+
+        let view = frame.staging.slice(..).get_mapped_range();
+        let result = bytemuck::cast_slice(&view).to_vec();
+        drop(view);
+        frame.staging.unmap();
+        pool.recycle(frame.buffers.into_iter().chain([frame.staging]));
+        result
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_pipelined_lidar_matches_synchronous() {
+    use crate::{lidar::Lidar, utils::create_cube, utils::get_raytracing_gpu, RayTraceScene};
+    use glam::{Affine3A, Vec3};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::IDENTITY,
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let mut lidar = Lidar::new(&device, vec![Vec3::X, Vec3::Y, Vec3::NEG_X]).await;
+    // Backing away from the cube along -x, so the +x beam hits at a different range each frame.
+    let poses: Vec<_> = (0..5)
+        .map(|i| Affine3A::from_translation(Vec3::new(-3.0 - i as f32, 0.0, 0.0)))
+        .collect();
+
+    let mut expected = vec![];
+    for pose in &poses {
+        expected.push(
+            lidar
+                .render_lidar_pointcloud(&scene, &device, &queue, pose)
+                .await,
+        );
+    }
+    for (i, frame) in expected.iter().enumerate() {
+        // The payload of the first point is the range of the +x beam.
+        assert!((frame[3] - (2.0 + i as f32)).abs() < 1e-3, "{frame:?}");
+    }
+
+    lidar.set_frames_in_flight(3);
+    let mut pipelined = vec![];
+    for (i, pose) in poses.iter().enumerate() {
+        let frame = lidar
+            .submit_lidar_pointcloud(&scene, &device, &queue, pose)
+            .await;
+        // The first frames only fill the ring.
+        assert_eq!(frame.is_some(), i >= 2);
+        pipelined.extend(frame);
+    }
+    pipelined.extend(lidar.flush_lidar_pointclouds(&device));
+    assert_eq!(pipelined, expected);
+}
@@ -0,0 +1,480 @@
+use glam::{IVec3, Vec3, Vec4};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{dense_voxel::VoxelItem, prefix_sum::run_compacted_query, read_buffer};
+use crate::Error;
+
+/// Marker returned by the GPU kernels when no item was found.
+pub const NO_MATCH: u32 = 0xFFFFFFFF;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, Default)]
+struct HashSlot {
+    cell: IVec3,
+    occupied: u32,
+}
+
+/// Spatial hash from Teschner et al. Must match `spatial_hash` in `sparse_voxel.wgsl`.
+fn spatial_hash(cell: IVec3, table_size: usize) -> usize {
+    let h = (cell.x as u32).wrapping_mul(73856093)
+        ^ (cell.y as u32).wrapping_mul(19349663)
+        ^ (cell.z as u32).wrapping_mul(83492791);
+    h as usize & (table_size - 1)
+}
+
+/// A voxel grid backed by a spatial hash map.
+///
+/// Unlike [`crate::utils::dense_voxel::DenseVoxel`], only cells that contain items are allocated, so
+/// large and mostly empty workspaces stay cheap. The grid is unbounded; cells are addressed by
+/// their integer coordinates `floor(position / resolution)`.
+pub struct SparseVoxel {
+    /// Resolution of each cell
+    resolution: f32,
+    /// Max number of items in each cell.
+    max_density: u32,
+    /// Open addressing hash table of cells. Always a power of two in size.
+    slots: Vec<HashSlot>,
+    /// Voxel data, `max_density` entries per slot.
+    data_on_cpu: Vec<VoxelItem>,
+    /// Number of occupied slots.
+    num_cells: usize,
+}
+
+impl SparseVoxel {
+    /// Creates an empty sparse voxel grid with room for `initial_cells` cells before rehashing.
+    pub fn new(resolution: f32, max_density: u32, initial_cells: usize) -> Self {
+        if resolution <= 0.0 {
+            panic!("Invalid voxel resolution");
+        }
+        let table_size = (initial_cells * 2).next_power_of_two().max(16);
+        Self {
+            resolution,
+            max_density,
+            slots: vec![HashSlot::default(); table_size],
+            data_on_cpu: vec![VoxelItem::default(); table_size * max_density as usize],
+            num_cells: 0,
+        }
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// Number of allocated cells.
+    pub fn num_cells(&self) -> usize {
+        self.num_cells
+    }
+
+    /// Number of item slots currently allocated, including empty ones.
+    pub fn capacity(&self) -> usize {
+        self.data_on_cpu.len()
+    }
+
+    /// Returns the integer cell coordinates containing `position`.
+    pub fn cell_of(&self, position: Vec3) -> IVec3 {
+        (position / self.resolution).floor().as_ivec3()
+    }
+
+    fn find_slot(&self, cell: IVec3) -> Result<usize, usize> {
+        let mut slot = spatial_hash(cell, self.slots.len());
+        loop {
+            let entry = &self.slots[slot];
+            if entry.occupied == 0 {
+                return Err(slot);
+            }
+            if entry.cell == cell {
+                return Ok(slot);
+            }
+            slot = (slot + 1) & (self.slots.len() - 1);
+        }
+    }
+
+    fn rehash(&mut self, table_size: usize) {
+        let old_slots = std::mem::replace(&mut self.slots, vec![HashSlot::default(); table_size]);
+        let old_data = std::mem::replace(
+            &mut self.data_on_cpu,
+            vec![VoxelItem::default(); table_size * self.max_density as usize],
+        );
+        let density = self.max_density as usize;
+        for (old_slot, entry) in old_slots.iter().enumerate() {
+            if entry.occupied == 0 {
+                continue;
+            }
+            let Err(new_slot) = self.find_slot(entry.cell) else {
+                unreachable!("duplicate cell in sparse voxel table");
+            };
+            self.slots[new_slot] = *entry;
+            self.data_on_cpu[new_slot * density..(new_slot + 1) * density]
+                .copy_from_slice(&old_data[old_slot * density..(old_slot + 1) * density]);
+        }
+    }
+
+    /// Adds an item, allocating its cell if needed. Returns the index of the item in the data
+    /// buffer. Indices are invalidated when the table grows.
+    pub fn add_item(&mut self, item: VoxelItem) -> Result<usize, Error> {
+        let cell = self.cell_of(item.position);
+        let slot = match self.find_slot(cell) {
+            Ok(slot) => slot,
+            Err(_) => {
+                if (self.num_cells + 1) * 2 > self.slots.len() {
+                    self.rehash(self.slots.len() * 2);
+                }
+                let Err(slot) = self.find_slot(cell) else {
+                    unreachable!();
+                };
+                self.slots[slot] = HashSlot { cell, occupied: 1 };
+                self.num_cells += 1;
+                slot
+            }
+        };
+
+        let index = slot * self.max_density as usize;
+        for i in 0..self.max_density as usize {
+            if self.data_on_cpu[index + i].occupied == 0 {
+                self.data_on_cpu[index + i] = item;
+                self.data_on_cpu[index + i].occupied = 1;
+                return Ok(index + i);
+            }
+        }
+        Err(Error::NoSpace)
+    }
+
+    /// Returns the item stored at `index`, as returned by [`SparseVoxel::add_item`] or a query.
+    pub fn item(&self, index: usize) -> Option<VoxelItem> {
+        self.data_on_cpu
+            .get(index)
+            .filter(|item| item.occupied == 1)
+            .copied()
+    }
+
+    pub fn get_items_in_cell(&self, x: i32, y: i32, z: i32) -> Vec<VoxelItem> {
+        let Ok(slot) = self.find_slot(IVec3::new(x, y, z)) else {
+            return vec![];
+        };
+        let index = slot * self.max_density as usize;
+        self.data_on_cpu[index..index + self.max_density as usize]
+            .iter()
+            .filter(|item| item.occupied == 1)
+            .copied()
+            .collect()
+    }
+
+    pub fn get_items_in_cell_position(&self, position: Vec3) -> Vec<VoxelItem> {
+        let cell = self.cell_of(position);
+        self.get_items_in_cell(cell.x, cell.y, cell.z)
+    }
+
+    pub fn to_gpu_buffers(&self, device: &wgpu::Device) -> SparseVoxelGpuRepresentation {
+        let slots = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Sparse Voxel Slots"),
+            contents: bytemuck::cast_slice(&self.slots),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let data_on_gpu = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Sparse Voxel Data"),
+            contents: bytemuck::cast_slice(&self.data_on_cpu),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        SparseVoxelGpuRepresentation {
+            slots,
+            data_on_gpu,
+            cpu_parameters: SparseVoxelGpuParams {
+                resolution: self.resolution,
+                max_density: self.max_density,
+                table_size: self.slots.len() as u32,
+                num_queries: 0,
+                radius: 0.0,
+                _padding: [0.0; 3],
+            },
+        }
+    }
+}
+
+/// The serde representation of a [`SparseVoxel`], its occupied items.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SparseVoxelData {
+    resolution: f32,
+    max_density: u32,
+    items: Vec<VoxelItem>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SparseVoxel {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SparseVoxelData {
+            resolution: self.resolution,
+            max_density: self.max_density,
+            items: self
+                .data_on_cpu
+                .iter()
+                .filter(|item| item.occupied != 0)
+                .copied()
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SparseVoxel {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let data = SparseVoxelData::deserialize(deserializer)?;
+        if data.resolution <= 0.0 || data.max_density == 0 {
+            return Err(D::Error::custom("Invalid voxel resolution or density"));
+        }
+        let mut voxel = Self::new(data.resolution, data.max_density, data.items.len());
+        for item in data.items {
+            voxel
+                .add_item(VoxelItem::with_payload(item.position, item.payload))
+                .map_err(D::Error::custom)?;
+        }
+        Ok(voxel)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct SparseVoxelGpuParams {
+    resolution: f32,
+    max_density: u32,
+    table_size: u32,
+    num_queries: u32,
+    radius: f32,
+    _padding: [f32; 3],
+}
+
+/// GPU copy of a [`SparseVoxel`].
+pub struct SparseVoxelGpuRepresentation {
+    slots: wgpu::Buffer,
+    data_on_gpu: wgpu::Buffer,
+    cpu_parameters: SparseVoxelGpuParams,
+}
+
+/// Finds the nearest item for each query point among the query's cell and its 26 neighbours.
+///
+/// Returns one index into the voxel's data buffer per query, or [`NO_MATCH`] if no item lies in
+/// the neighbourhood.
+pub async fn sparse_voxel_nearest_neighbor(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    voxel: &SparseVoxel,
+    query_points: &[Vec3],
+) -> Vec<u32> {
+    if query_points.is_empty() {
+        return vec![];
+    }
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("sparse_voxel.wgsl"));
+    let gpu = voxel.to_gpu_buffers(device);
+
+    let params = SparseVoxelGpuParams {
+        num_queries: query_points.len() as u32,
+        ..gpu.cpu_parameters
+    };
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Sparse Voxel Parameters"),
+        contents: bytemuck::cast_slice(&[params]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let queries: Vec<Vec4> = query_points.iter().map(|p| p.extend(0.0)).collect();
+    let queries = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Queries"),
+        contents: bytemuck::cast_slice(&queries),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let result_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Result"),
+        size: (query_points.len() * 4) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("sparse_voxel_nn"),
+        layout: None,
+        module: &cs_module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &compute_pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: gpu.slots.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: gpu.data_on_gpu.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: queries.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: result_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&compute_pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups((query_points.len() as u32).div_ceil(64), 1, 1);
+    }
+    queue.submit(Some(encoder.finish()));
+
+    read_buffer(device, queue, &result_buffer).await
+}
+
+/// Finds every item within `radius` of each query point.
+///
+/// Returns, for each query, the indices of the matching items in the voxel's data buffer. The per
+/// query results are compacted on the GPU with a prefix sum.
+pub async fn sparse_voxel_radius_search(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    voxel: &SparseVoxel,
+    query_points: &[Vec3],
+    radius: f32,
+) -> Vec<Vec<u32>> {
+    let cs_module = device.create_shader_module(wgpu::include_wgsl!("sparse_voxel.wgsl"));
+    let gpu = voxel.to_gpu_buffers(device);
+
+    let params = SparseVoxelGpuParams {
+        num_queries: query_points.len() as u32,
+        radius,
+        ..gpu.cpu_parameters
+    };
+    let params = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Sparse Voxel Parameters"),
+        contents: bytemuck::cast_slice(&[params]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let queries: Vec<Vec4> = query_points.iter().map(|p| p.extend(0.0)).collect();
+    let queries = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Queries"),
+        contents: bytemuck::cast_slice(&queries),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    run_compacted_query(
+        device,
+        queue,
+        &cs_module,
+        ["radius_count", "radius_write"],
+        [5, 6, 7],
+        &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: gpu.slots.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: gpu.data_on_gpu.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: queries.as_entire_binding(),
+            },
+        ],
+        query_points.len() as u32,
+    )
+    .await
+}
+
+#[cfg(test)]
+#[test]
+fn test_sparse_voxel_insert_and_rehash() {
+    let mut voxel = SparseVoxel::new(0.5, 4, 4);
+    let initial_capacity = voxel.capacity();
+    for i in 0..100 {
+        voxel
+            .add_item(VoxelItem::new(Vec3::new(
+                i as f32 * 10.0,
+                -(i as f32) * 3.0,
+                0.25,
+            )))
+            .unwrap();
+    }
+    assert_eq!(voxel.num_cells(), 100);
+    assert!(voxel.capacity() > initial_capacity);
+
+    for i in 0..100 {
+        let items =
+            voxel.get_items_in_cell_position(Vec3::new(i as f32 * 10.0, -(i as f32) * 3.0, 0.25));
+        assert_eq!(items.len(), 1);
+    }
+    assert!(voxel.get_items_in_cell(1000, 1000, 1000).is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_sparse_voxel_nn() {
+    use crate::utils::get_raytracing_gpu;
+
+    let mut voxel = SparseVoxel::new(0.5, 10, 16);
+    voxel
+        .add_item(VoxelItem::new(Vec3::new(-100.5, 0.5, 0.5)))
+        .unwrap();
+    let target = voxel
+        .add_item(VoxelItem::new(Vec3::new(1.6, 1.6, 1.6)))
+        .unwrap();
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    let result = sparse_voxel_nearest_neighbor(
+        &device,
+        &queue,
+        &voxel,
+        &[Vec3::new(1.9, 1.6, 1.6), Vec3::new(50.0, 50.0, 50.0)],
+    )
+    .await;
+    assert_eq!(result, vec![target as u32, NO_MATCH]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_sparse_voxel_radius_search() {
+    use crate::utils::get_raytracing_gpu;
+
+    let mut voxel = SparseVoxel::new(0.5, 10, 16);
+    let mut near: Vec<_> = [Vec3::new(1.6, 1.6, 1.6), Vec3::new(2.4, 1.6, 1.6)]
+        .iter()
+        .map(|position| voxel.add_item(VoxelItem::new(*position)).unwrap() as u32)
+        .collect();
+    voxel
+        .add_item(VoxelItem::new(Vec3::new(5.0, 1.6, 1.6)))
+        .unwrap();
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+    let mut result = sparse_voxel_radius_search(
+        &device,
+        &queue,
+        &voxel,
+        &[Vec3::new(2.0, 1.6, 1.6), Vec3::new(50.0, 50.0, 50.0)],
+        1.0,
+    )
+    .await;
+    result[0].sort();
+    near.sort();
+    assert_eq!(result, vec![near, vec![]]);
+}
@@ -0,0 +1,181 @@
+struct SparseVoxelGpuParams {
+    resolution: f32,
+    max_density: u32,
+    table_size: u32,
+    num_queries: u32,
+    radius: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+}
+
+struct HashSlot {
+    cell: vec3<i32>,
+    occupied: u32
+}
+
+struct VoxelNode {
+    position: vec3<f32>,
+    occupied: u32,
+    payload: u32
+}
+
+@group(0)
+@binding(0)
+var<storage, read> slots: array<HashSlot>;
+
+@group(0)
+@binding(1)
+var<storage, read> items: array<VoxelNode>;
+
+@group(0)
+@binding(2)
+var<uniform> params: SparseVoxelGpuParams;
+
+@group(0)
+@binding(3)
+var<storage, read> queries: array<vec4<f32>>;
+
+@group(0)
+@binding(4)
+var<storage, read_write> query_matches: array<u32>;
+
+@group(0)
+@binding(5)
+var<storage, read_write> radius_counts: array<u32>;
+
+/// Inclusive prefix sum of `radius_counts`.
+@group(0)
+@binding(6)
+var<storage, read> radius_offsets: array<u32>;
+
+@group(0)
+@binding(7)
+var<storage, read_write> radius_matches: array<u32>;
+
+/// Must match `spatial_hash` in mod.rs.
+fn spatial_hash(cell: vec3<i32>) -> u32 {
+    return ((bitcast<u32>(cell.x) * 73856093u) ^ (bitcast<u32>(cell.y) * 19349663u) ^ (bitcast<u32>(cell.z) * 83492791u)) & (params.table_size - 1u);
+}
+
+/// Returns the slot holding `cell`, or 0xFFFFFFFF if the cell is empty.
+fn lookup(cell: vec3<i32>) -> u32 {
+    var slot = spatial_hash(cell);
+    var probes: u32 = 0;
+    while probes < params.table_size {
+        let entry = slots[slot];
+        if entry.occupied == 0 {
+            return 0xFFFFFFFFu;
+        }
+        if all(entry.cell == cell) {
+            return slot;
+        }
+        slot = (slot + 1u) & (params.table_size - 1u);
+        probes += 1u;
+    }
+    return 0xFFFFFFFFu;
+}
+
+@compute
+@workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_queries {
+        return;
+    }
+    let pos = queries[global_id.x].xyz;
+    let center = vec3<i32>(floor(pos / params.resolution));
+    var nearest_distance: f32 = 3.4e38;
+    var nearest_index: u32 = 0xFFFFFFFFu;
+    for (var dx: i32 = -1; dx <= 1; dx++) {
+        for (var dy: i32 = -1; dy <= 1; dy++) {
+            for (var dz: i32 = -1; dz <= 1; dz++) {
+                let slot = lookup(center + vec3<i32>(dx, dy, dz));
+                if slot == 0xFFFFFFFFu {
+                    continue;
+                }
+                let base = slot * params.max_density;
+                for (var i: u32 = 0; i < params.max_density; i++) {
+                    let node = items[base + i];
+                    if node.occupied == 0 {
+                        break;
+                    }
+                    let distance = length(node.position - pos);
+                    if distance < nearest_distance {
+                        nearest_distance = distance;
+                        nearest_index = base + i;
+                    }
+                }
+            }
+        }
+    }
+    query_matches[global_id.x] = nearest_index;
+}
+
+@compute
+@workgroup_size(64)
+fn radius_count(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_queries {
+        return;
+    }
+    let pos = queries[global_id.x].xyz;
+    let lo = vec3<i32>(floor((pos - params.radius) / params.resolution));
+    let hi = vec3<i32>(floor((pos + params.radius) / params.resolution));
+    var found: u32 = 0;
+    for (var z = lo.z; z <= hi.z; z++) {
+        for (var y = lo.y; y <= hi.y; y++) {
+            for (var x = lo.x; x <= hi.x; x++) {
+                let slot = lookup(vec3<i32>(x, y, z));
+                if slot == 0xFFFFFFFFu {
+                    continue;
+                }
+                let base = slot * params.max_density;
+                for (var i: u32 = 0; i < params.max_density; i++) {
+                    let node = items[base + i];
+                    if node.occupied == 0 {
+                        break;
+                    }
+                    if distance(node.position, pos) <= params.radius {
+                        found += 1u;
+                    }
+                }
+            }
+        }
+    }
+    radius_counts[global_id.x] = found;
+}
+
+@compute
+@workgroup_size(64)
+fn radius_write(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if global_id.x >= params.num_queries {
+        return;
+    }
+    let pos = queries[global_id.x].xyz;
+    let lo = vec3<i32>(floor((pos - params.radius) / params.resolution));
+    let hi = vec3<i32>(floor((pos + params.radius) / params.resolution));
+    var next: u32 = 0;
+    if global_id.x > 0 {
+        next = radius_offsets[global_id.x - 1];
+    }
+    for (var z = lo.z; z <= hi.z; z++) {
+        for (var y = lo.y; y <= hi.y; y++) {
+            for (var x = lo.x; x <= hi.x; x++) {
+                let slot = lookup(vec3<i32>(x, y, z));
+                if slot == 0xFFFFFFFFu {
+                    continue;
+                }
+                let base = slot * params.max_density;
+                for (var i: u32 = 0; i < params.max_density; i++) {
+                    let node = items[base + i];
+                    if node.occupied == 0 {
+                        break;
+                    }
+                    if distance(node.position, pos) <= params.radius {
+                        radius_matches[next] = base + i;
+                        next += 1u;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use glam::{Mat4, UVec3, Vec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{depth_camera::DepthCamera, pose::IntoMat4, utils::read_buffer, RayTraceScene};
+
+/// A single cell of a [`TsdfVolume`].
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, PartialEq)]
+pub struct TsdfVoxel {
+    /// Truncated signed distance normalized to `[-1, 1]`. Positive in free space.
+    pub tsdf: f32,
+    /// Accumulated integration weight. Zero for cells that were never observed.
+    pub weight: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct TsdfParams {
+    view: Mat4,
+    proj: Mat4,
+    origin: Vec3,
+    voxel_size: f32,
+    dims: UVec3,
+    truncation: f32,
+    width: u32,
+    height: u32,
+    max_weight: f32,
+    no_hit: f32,
+}
+
+/// A truncated signed distance volume fused from depth camera frames.
+///
+/// The volume lives entirely on the GPU. Frames are integrated KinectFusion-style with a running
+/// weighted average, and can be fed straight from [`DepthCamera::encode_depth_camera`] without
+/// reading the depth image back.
+pub struct TsdfVolume {
+    origin: Vec3,
+    dims: UVec3,
+    voxel_size: f32,
+    truncation: f32,
+    max_weight: f32,
+    volume: wgpu::Buffer,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl TsdfVolume {
+    /// Creates an empty volume.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to allocate the volume on.
+    /// * `origin` - World position of the corner of the first voxel.
+    /// * `dims` - Number of voxels along each axis.
+    /// * `voxel_size` - Edge length of a voxel in meters.
+    /// * `truncation` - Truncation distance in meters, typically a few voxels.
+    pub fn new(
+        device: &wgpu::Device,
+        origin: Vec3,
+        dims: UVec3,
+        voxel_size: f32,
+        truncation: f32,
+    ) -> Self {
+        let voxels = vec![
+            TsdfVoxel {
+                tsdf: 1.0,
+                weight: 0.0
+            };
+            (dims.x * dims.y * dims.z) as usize
+        ];
+        let volume = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("TSDF Volume"),
+            contents: bytemuck::cast_slice(&voxels),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("tsdf.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("tsdf_integrate"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Self {
+            origin,
+            dims,
+            voxel_size,
+            truncation,
+            max_weight: 100.0,
+            volume,
+            pipeline,
+        }
+    }
+
+    /// Caps the integration weight so the volume keeps adapting to changes.
+    pub fn set_max_weight(&mut self, max_weight: f32) {
+        self.max_weight = max_weight;
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn dims(&self) -> UVec3 {
+        self.dims
+    }
+
+    pub fn voxel_size(&self) -> f32 {
+        self.voxel_size
+    }
+
+    pub fn truncation(&self) -> f32 {
+        self.truncation
+    }
+
+    /// The GPU buffer holding one [`TsdfVoxel`] per cell, x-major.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.volume
+    }
+
+    /// Records the integration of a depth frame into `encoder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `encoder` - The command encoder to record into.
+    /// * `camera` - The camera that produced the frame.
+    /// * `depth` - The depth buffer returned by [`DepthCamera::encode_depth_camera`].
+    /// * `view_matrix` - The view matrix the frame was rendered with.
+    pub fn encode_integrate(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &DepthCamera,
+        depth: &wgpu::Buffer,
+        view_matrix: impl IntoMat4,
+    ) {
+        let view_matrix = view_matrix.into_mat4();
+        let params = TsdfParams {
+            view: view_matrix,
+            proj: camera.projection_matrix(),
+            origin: self.origin,
+            voxel_size: self.voxel_size,
+            dims: self.dims,
+            truncation: self.truncation,
+            width: camera.width(),
+            height: camera.height(),
+            max_weight: self.max_weight,
+            no_hit: DepthCamera::no_hit_const(),
+        };
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("TSDF Parameters"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.volume.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: depth.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&self.pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.dispatch_workgroups(
+            self.dims.x.div_ceil(4),
+            self.dims.y.div_ceil(4),
+            self.dims.z.div_ceil(4),
+        );
+    }
+
+    /// Renders a depth frame from `scene` and fuses it into the volume in one submission.
+    ///
+    /// # Arguments
+    ///
+    /// * `scene` - The `RayTraceScene` to render.
+    /// * `camera` - The depth camera to render with.
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
+    /// * `view_matrix` - The `Mat4` view matrix of the camera.
+    pub fn integrate_from_scene(
+        &self,
+        scene: &RayTraceScene,
+        camera: &mut DepthCamera,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_matrix: impl IntoMat4,
+    ) {
+        let view_matrix = view_matrix.into_mat4();
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let depth = camera.encode_depth_camera(scene, device, &mut encoder, view_matrix);
+        self.encode_integrate(device, &mut encoder, camera, &depth, view_matrix);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Reads the whole volume back to the CPU.
+    pub async fn download(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<TsdfVoxel> {
+        read_buffer(device, queue, &self.volume).await
+    }
+
+    /// Returns the world position of the center of voxel `(x, y, z)`.
+    pub fn voxel_center(&self, x: u32, y: u32, z: u32) -> Vec3 {
+        self.origin + (UVec3::new(x, y, z).as_vec3() + Vec3::splat(0.5)) * self.voxel_size
+    }
+
+    /// Logs the observed voxels of the horizontal slice `z` to `rerun` under `tsdf/slice`, as
+    /// points colored from red behind surfaces through white on them to blue in free space.
+    ///
+    /// # Note
+    ///
+    /// This method is only available when the `visualization` feature is enabled.
+    #[cfg(feature = "visualization")]
+    pub async fn visualize_slice(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rerun: &rerun::RecordingStream,
+        z: u32,
+    ) {
+        let voxels = self.download(device, queue).await;
+        let mut positions = vec![];
+        let mut colors = vec![];
+        for y in 0..self.dims.y {
+            for x in 0..self.dims.x {
+                let voxel = voxels[(x + y * self.dims.x + z * self.dims.x * self.dims.y) as usize];
+                if voxel.weight == 0.0 {
+                    continue;
+                }
+                positions.push(self.voxel_center(x, y, z).to_array());
+                colors.push(crate::visualizer::diverging_color(voxel.tsdf));
+            }
+        }
+        rerun
+            .log(
+                "tsdf/slice",
+                &rerun::Points3D::new(positions)
+                    .with_colors(colors)
+                    .with_radii([self.voxel_size / 2.0]),
+            )
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_tsdf_params_layout() {
+    // Must match the size of `TsdfParams` in tsdf.wgsl.
+    assert_eq!(std::mem::size_of::<TsdfParams>(), 176);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_tsdf_integrates_plane() {
+    use crate::{
+        utils::{create_cube, get_gpu},
+        Instance,
+    };
+    use glam::{Affine3A, Quat};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    // A wall whose front face is 2 m in front of the camera, which looks down -z.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0)],
+        &[Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_scale_rotation_translation(
+                Vec3::new(100.0, 100.0, 1.0),
+                Quat::IDENTITY,
+                Vec3::new(0.0, 0.0, -3.0),
+            ),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let mut camera = DepthCamera::new(&device, 64, 48, 60.0, 100.0).await;
+    // Traced on the CPU so that the test does not need ray queries.
+    let depth = camera.trace_on_cpu(&scene, Mat4::IDENTITY).unwrap();
+    let depth = device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&depth),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    let truncation = 0.3;
+    let dims = UVec3::new(6, 6, 20);
+    let volume = TsdfVolume::new(&device, Vec3::new(-0.3, -0.3, -3.0), dims, 0.1, truncation);
+    for _ in 0..2 {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        volume.encode_integrate(&device, &mut encoder, &camera, &depth, Mat4::IDENTITY);
+        queue.submit(Some(encoder.finish()));
+    }
+    let voxels = volume.download(&device, &queue).await;
+
+    for z in 0..dims.z {
+        for y in 0..dims.y {
+            for x in 0..dims.x {
+                let voxel = voxels[(x + y * dims.x + z * dims.x * dims.y) as usize];
+                let center = volume.voxel_center(x, y, z);
+                // Signed distance to the wall along the ray through the voxel.
+                let sdf = center.length() * (2.0 / -center.z - 1.0);
+                if sdf < -truncation - 0.05 {
+                    assert_eq!(
+                        voxel,
+                        TsdfVoxel {
+                            tsdf: 1.0,
+                            weight: 0.0
+                        },
+                        "{center}"
+                    );
+                } else if sdf > -truncation + 0.05 {
+                    assert_eq!(voxel.weight, 2.0, "{center}");
+                    let expected = (sdf / truncation).min(1.0);
+                    assert!((voxel.tsdf - expected).abs() < 0.05, "{center}: {voxel:?}");
+                }
+            }
+        }
+    }
+
+    // Along the optical axis voxel 10 is centered 5 cm in front of the wall and voxel 9 5 cm
+    // behind it, so the TSDF crosses zero between them.
+    let column: Vec<f32> = (0..dims.z)
+        .map(|z| voxels[(3 + 3 * dims.x + z * dims.x * dims.y) as usize].tsdf)
+        .collect();
+    assert!(column[10..].iter().all(|tsdf| *tsdf > 0.0), "{column:?}");
+    assert!(column[7..10].iter().all(|tsdf| *tsdf < 0.0), "{column:?}");
+}
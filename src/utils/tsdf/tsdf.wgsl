@@ -0,0 +1,59 @@
+struct TsdfParams {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    origin: vec3<f32>,
+    voxel_size: f32,
+    dims: vec3<u32>,
+    truncation: f32,
+    width: u32,
+    height: u32,
+    max_weight: f32,
+    no_hit: f32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read_write> volume: array<vec2<f32>>;
+
+@group(0)
+@binding(1)
+var<storage, read> depth: array<f32>;
+
+@group(0)
+@binding(2)
+var<uniform> params: TsdfParams;
+
+@compute
+@workgroup_size(4, 4, 4)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id >= params.dims) {
+        return;
+    }
+    let index = global_id.x + global_id.y * params.dims.x + global_id.z * params.dims.x * params.dims.y;
+    let world = params.origin + (vec3<f32>(global_id) + vec3<f32>(0.5)) * params.voxel_size;
+    let camera = params.view * vec4<f32>(world, 1.0);
+    // The camera looks down -z.
+    if camera.z >= 0.0 {
+        return;
+    }
+    let clip = params.proj * camera;
+    let ndc = clip.xy / clip.w;
+    if any(abs(ndc) >= vec2<f32>(1.0)) {
+        return;
+    }
+    // Same pixel layout as the depth camera shader: index = x * height + y.
+    let pixel = vec2<u32>((ndc * 0.5 + vec2<f32>(0.5)) * vec2<f32>(f32(params.width), f32(params.height)));
+    let range = depth[pixel.x * params.height + pixel.y];
+    if range <= 0.0 || range >= params.no_hit {
+        return;
+    }
+    // Ranges are measured along the ray, so compare against the distance to the voxel.
+    let sdf = range - length(camera.xyz);
+    if sdf < -params.truncation {
+        return;
+    }
+    let tsdf = min(1.0, sdf / params.truncation);
+    let voxel = volume[index];
+    let weight = voxel.y + 1.0;
+    volume[index] = vec2<f32>((voxel.x * voxel.y + tsdf) / weight, min(weight, params.max_weight));
+}
@@ -0,0 +1,99 @@
+use std::num::NonZeroU64;
+
+use wgpu::util::DeviceExt;
+
+use crate::utils::buffer_pool::BufferPool;
+
+/// Where a uniform was placed in the buffer of a [`UniformBelt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UniformSlot {
+    offset: wgpu::BufferAddress,
+    size: NonZeroU64,
+}
+
+impl UniformSlot {
+    /// Binds the uniform within `buffer`, the buffer returned by [`UniformBelt::finish`].
+    pub(crate) fn binding(self, buffer: &wgpu::Buffer) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer,
+            offset: self.offset,
+            size: Some(self.size),
+        })
+    }
+}
+
+/// Packs the small uniforms of one render, such as the pose, work group and noise parameters,
+/// into a single buffer.
+///
+/// Every uniform starts at the device's `min_uniform_buffer_offset_alignment` and is bound at
+/// its offset with [`UniformSlot::binding`]. A render then uploads one buffer with one write
+/// instead of allocating a handful of tiny ones. As the layout only depends on the uniforms
+/// pushed, a sensor gets the same size every frame and its [`BufferPool`] hands the buffer back.
+#[derive(Debug)]
+pub(crate) struct UniformBelt {
+    alignment: wgpu::BufferAddress,
+    data: Vec<u8>,
+}
+
+impl UniformBelt {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        Self::with_alignment(device.limits().min_uniform_buffer_offset_alignment as u64)
+    }
+
+    fn with_alignment(alignment: wgpu::BufferAddress) -> Self {
+        Self {
+            alignment,
+            data: vec![],
+        }
+    }
+
+    /// Appends `value` and returns where it was placed.
+    pub(crate) fn push<T: bytemuck::Pod>(&mut self, value: &T) -> UniformSlot {
+        let bytes = bytemuck::bytes_of(value);
+        let offset = (self.data.len() as u64).next_multiple_of(self.alignment);
+        self.data.resize(offset as usize, 0);
+        self.data.extend_from_slice(bytes);
+        UniformSlot {
+            offset,
+            size: NonZeroU64::new(bytes.len() as u64).expect("Uniforms can not be empty"),
+        }
+    }
+
+    /// Uploads the uniforms pushed so far.
+    ///
+    /// With a `queue` the buffer is taken from `pool`, and must be recycled once the submission
+    /// using it completed. Without one it is freshly allocated.
+    pub(crate) fn finish(
+        mut self,
+        device: &wgpu::Device,
+        queue: Option<&wgpu::Queue>,
+        pool: &BufferPool,
+    ) -> wgpu::Buffer {
+        // Buffer writes must be a multiple of four bytes.
+        let size = (self.data.len() as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+        self.data.resize(size as usize, 0);
+        match queue {
+            Some(queue) => pool.take_init(device, queue, &self.data, wgpu::BufferUsages::UNIFORM),
+            None => device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Uniform Belt"),
+                contents: &self.data,
+                usage: wgpu::BufferUsages::UNIFORM,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_uniform_belt_layout() {
+    let mut belt = UniformBelt::with_alignment(256);
+    let pose = belt.push(&[1.0f32; 16]);
+    let count = belt.push(&7u32);
+    let seed = belt.push(&[3u32; 4]);
+    assert_eq!((pose.offset, pose.size.get()), (0, 64));
+    assert_eq!((count.offset, count.size.get()), (256, 4));
+    assert_eq!((seed.offset, seed.size.get()), (512, 16));
+    assert_eq!(&belt.data[256..260], bytemuck::bytes_of(&7u32));
+    assert!(belt.data[64..256].iter().all(|&b| b == 0));
+    assert_eq!(belt.data.len(), 528);
+}
@@ -0,0 +1,288 @@
+use glam::{UVec3, Vec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::utils::{dense_voxel::DenseVoxel, read_buffer, MAX_WORKGROUPS_PER_DIMENSION};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct RaycastParams {
+    origin: Vec3,
+    resolution: f32,
+    dims: UVec3,
+    num_rays: u32,
+}
+
+/// A ray to cast with [`VoxelRaycaster`].
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, PartialEq)]
+pub struct VoxelRay {
+    origin: Vec3,
+    max_distance: f32,
+    direction: Vec3,
+    _padding: f32,
+}
+
+impl VoxelRay {
+    /// Creates a ray starting at `origin` that gives up after `max_distance` meters. `direction`
+    /// does not need to be normalized.
+    pub fn new(origin: Vec3, direction: Vec3, max_distance: f32) -> Self {
+        Self {
+            origin,
+            max_distance,
+            direction,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// The first occupied cell pierced by a [`VoxelRay`].
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, PartialEq)]
+pub struct VoxelHit {
+    /// Where the ray enters the cell.
+    pub position: Vec3,
+    /// Distance from the ray origin to `position`, negative if the ray hit nothing.
+    pub distance: f32,
+    /// Coordinates of the cell.
+    pub cell: UVec3,
+    _padding: u32,
+}
+
+impl VoxelHit {
+    pub fn is_hit(&self) -> bool {
+        self.distance >= 0.0
+    }
+}
+
+/// Casts rays against an occupancy grid on the GPU without a hardware acceleration structure.
+///
+/// Rays step from cell to cell with a 3D DDA until they reach an occupied cell, leave the grid or
+/// exceed their maximum distance. This works on maps built from real sensor data, such as a
+/// [`DenseVoxel`], as well as on any device with compute shaders. The grid is uploaded once and
+/// can be queried many times.
+pub struct VoxelRaycaster {
+    origin: Vec3,
+    dims: UVec3,
+    resolution: f32,
+    occupancy: wgpu::Buffer,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl VoxelRaycaster {
+    /// Uploads an occupancy grid.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `occupancy` - One flag per cell, non-zero for occupied cells, laid out x-major.
+    /// * `dims` - Number of cells along each axis.
+    /// * `origin` - World position of the corner of the first cell.
+    /// * `resolution` - Edge length of a cell in meters.
+    pub fn from_occupancy(
+        device: &wgpu::Device,
+        occupancy: &[u32],
+        dims: UVec3,
+        origin: Vec3,
+        resolution: f32,
+    ) -> Self {
+        if occupancy.len() != (dims.x * dims.y * dims.z) as usize {
+            panic!("Occupancy grid does not match dimensions");
+        }
+
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("voxel_raycast.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("voxel_raycast"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let occupancy = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raycast Occupancy"),
+            contents: bytemuck::cast_slice(occupancy),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            origin,
+            dims,
+            resolution,
+            occupancy,
+            pipeline,
+        }
+    }
+
+    /// Uploads the cells occupied in a [`DenseVoxel`].
+    pub fn from_dense_voxel(device: &wgpu::Device, voxel: &DenseVoxel) -> Self {
+        let dims = UVec3::new(
+            voxel.width_steps() as u32,
+            voxel.length_steps() as u32,
+            voxel.height_steps() as u32,
+        );
+        Self::from_occupancy(
+            device,
+            &voxel.occupancy_grid(),
+            dims,
+            voxel.bottom_left(),
+            voxel.resolution(),
+        )
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn dims(&self) -> UVec3 {
+        self.dims
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// The GPU buffer holding one `u32` occupancy flag per cell. It may be written to update the
+    /// grid between casts.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.occupancy
+    }
+
+    /// Records casting `num_rays` rays into `encoder`.
+    ///
+    /// `rays` holds [`VoxelRay`]s and must be usable as `STORAGE`. Returns a buffer receiving one
+    /// [`VoxelHit`] per ray.
+    pub fn encode_cast(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        rays: &wgpu::Buffer,
+        num_rays: u32,
+    ) -> wgpu::Buffer {
+        let hits = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Raycast Hits"),
+            size: (num_rays.max(1) as usize * std::mem::size_of::<VoxelHit>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        if num_rays == 0 {
+            return hits;
+        }
+        let params = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raycast Parameters"),
+            contents: bytemuck::cast_slice(&[RaycastParams {
+                origin: self.origin,
+                resolution: self.resolution,
+                dims: self.dims,
+                num_rays,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.occupancy.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: rays.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: hits.as_entire_binding(),
+                },
+            ],
+        });
+        let workgroups = num_rays.div_ceil(64);
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(
+                workgroups.min(MAX_WORKGROUPS_PER_DIMENSION),
+                workgroups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION),
+                1,
+            );
+        }
+        hits
+    }
+
+    /// Casts rays and reads the hits back to the CPU, one per ray.
+    pub async fn cast_rays(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rays: &[VoxelRay],
+    ) -> Vec<VoxelHit> {
+        if rays.is_empty() {
+            return vec![];
+        }
+        let rays_buf = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Raycast Rays"),
+            contents: bytemuck::cast_slice(rays),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let hits = self.encode_cast(device, &mut encoder, &rays_buf, rays.len() as u32);
+        queue.submit(Some(encoder.finish()));
+        read_buffer(device, queue, &hits).await
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_voxel_raycast() {
+    use crate::utils::{dense_voxel::VoxelItem, get_raytracing_gpu};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A wall at x = 3 m built from points, as a scan would.
+    let mut map = DenseVoxel::new(Vec3::splat(5.0), Vec3::ZERO, 0.5, 4);
+    for y in 0..10 {
+        for z in 0..10 {
+            let position = Vec3::new(3.2, y as f32 * 0.5 + 0.25, z as f32 * 0.5 + 0.25);
+            map.add_item(VoxelItem::new(position)).unwrap();
+        }
+    }
+    let raycaster = VoxelRaycaster::from_dense_voxel(&device, &map);
+
+    let rays = [
+        // Straight at the wall.
+        VoxelRay::new(Vec3::new(0.1, 2.3, 1.1), Vec3::X, 10.0),
+        // Diagonally, from outside the grid.
+        VoxelRay::new(Vec3::new(-1.0, -1.0, 2.0), Vec3::new(1.0, 1.0, 0.0), 10.0),
+        // Away from the wall.
+        VoxelRay::new(Vec3::new(2.0, 2.0, 2.0), -Vec3::X, 10.0),
+        // Stopping short of the wall.
+        VoxelRay::new(Vec3::new(0.1, 2.3, 1.1), Vec3::X * 2.0, 2.0),
+    ];
+    let hits = raycaster.cast_rays(&device, &queue, &rays).await;
+    assert_eq!(hits.len(), 4);
+
+    assert!(hits[0].is_hit());
+    assert!((hits[0].distance - 2.9).abs() < 1e-4, "{:?}", hits[0]);
+    assert_eq!(hits[0].cell, UVec3::new(6, 4, 2));
+
+    assert!(hits[1].is_hit());
+    assert!(
+        hits[1].position.abs_diff_eq(Vec3::new(3.0, 3.0, 2.0), 1e-4),
+        "{:?}",
+        hits[1]
+    );
+    assert_eq!(hits[1].cell.x, 6);
+
+    assert!(!hits[2].is_hit());
+    assert!(!hits[3].is_hit());
+}
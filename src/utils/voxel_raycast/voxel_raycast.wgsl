@@ -0,0 +1,117 @@
+// Ray casting against occupancy grids with 3D DDA (Amanatides and Woo).
+
+struct RaycastParams {
+    origin: vec3<f32>,
+    resolution: f32,
+    dims: vec3<u32>,
+    num_rays: u32,
+}
+
+struct Ray {
+    origin: vec3<f32>,
+    max_distance: f32,
+    direction: vec3<f32>,
+    _padding: f32,
+}
+
+/// Must match `VoxelHit` in mod.rs. Misses have a negative distance.
+struct Hit {
+    position: vec3<f32>,
+    distance: f32,
+    cell: vec3<u32>,
+    _padding: u32,
+}
+
+const FAR: f32 = 3.4e38;
+
+@group(0)
+@binding(0)
+var<storage, read> occupancy: array<u32>;
+
+@group(0)
+@binding(1)
+var<uniform> params: RaycastParams;
+
+@group(0)
+@binding(2)
+var<storage, read> rays: array<Ray>;
+
+@group(0)
+@binding(3)
+var<storage, read_write> hits: array<Hit>;
+
+fn is_occupied(cell: vec3<i32>) -> bool {
+    let c = vec3<u32>(cell);
+    return occupancy[c.x + c.y * params.dims.x + c.z * params.dims.x * params.dims.y] != 0u;
+}
+
+/// Walks the cells pierced by a ray, in grid units, and returns the first occupied one.
+fn march(ray: Ray) -> Hit {
+    var miss: Hit;
+    miss.distance = -1.0;
+
+    let direction = normalize(ray.direction);
+    let start = (ray.origin - params.origin) / params.resolution;
+    let t_limit = ray.max_distance / params.resolution;
+    let dims = vec3<f32>(params.dims);
+
+    // Clip the ray to the grid bounds.
+    let moving = direction != vec3<f32>(0.0);
+    let inverse = select(vec3<f32>(FAR), 1.0 / direction, moving);
+    let t0 = select(vec3<f32>(-FAR), (vec3<f32>(0.0) - start) * inverse, moving);
+    let t1 = select(vec3<f32>(FAR), (dims - start) * inverse, moving);
+    if any(!moving & ((start < vec3<f32>(0.0)) | (start >= dims))) {
+        return miss;
+    }
+    let t_enter = max(max(max(min(t0.x, t1.x), min(t0.y, t1.y)), min(t0.z, t1.z)), 0.0);
+    let t_exit = min(min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z)), t_limit);
+    if t_enter > t_exit {
+        return miss;
+    }
+
+    let entry = start + direction * t_enter;
+    var cell = clamp(vec3<i32>(floor(entry)), vec3<i32>(0), vec3<i32>(params.dims) - 1);
+    let step = vec3<i32>(sign(direction));
+    let t_delta = abs(inverse);
+    let boundary = vec3<f32>(cell) + select(vec3<f32>(0.0), vec3<f32>(1.0), step > vec3<i32>(0));
+    var t_next = select(vec3<f32>(FAR), t_enter + (boundary - entry) * inverse, moving);
+    var t = t_enter;
+
+    let max_steps = params.dims.x + params.dims.y + params.dims.z + 3u;
+    for (var i = 0u; i < max_steps; i++) {
+        if is_occupied(cell) {
+            let distance = t * params.resolution;
+            return Hit(ray.origin + direction * distance, distance, vec3<u32>(cell), 0u);
+        }
+        var axis = 0;
+        if t_next.y < t_next[axis] {
+            axis = 1;
+        }
+        if t_next.z < t_next[axis] {
+            axis = 2;
+        }
+        t = t_next[axis];
+        if t > t_exit {
+            break;
+        }
+        cell[axis] += step[axis];
+        if cell[axis] < 0 || cell[axis] >= i32(params.dims[axis]) {
+            break;
+        }
+        t_next[axis] += t_delta[axis];
+    }
+    return miss;
+}
+
+@compute
+@workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let index = global_id.x + global_id.y * num_workgroups.x * 64u;
+    if index >= params.num_rays {
+        return;
+    }
+    hits[index] = march(rays[index]);
+}
@@ -0,0 +1,192 @@
+use glam::{UVec3, Vec3};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+use crate::{utils::read_buffer, RayTraceScene};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct VoxelizeParams {
+    origin: Vec3,
+    resolution: f32,
+    dims: UVec3,
+    axis: u32,
+}
+
+/// A boolean occupancy grid.
+///
+/// Cells are laid out x-major, i.e. cell `(x, y, z)` is at `x + y * dims.x + z * dims.x * dims.y`,
+/// matching what [`crate::utils::esdf::Esdf::from_occupancy`] and
+/// [`crate::utils::costmap::Costmap2D::from_occupancy`] expect.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OccupancyGrid {
+    origin: Vec3,
+    dims: UVec3,
+    resolution: f32,
+    cells: Vec<u32>,
+}
+
+impl OccupancyGrid {
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn dims(&self) -> UVec3 {
+        self.dims
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// One flag per cell, 1 for occupied cells and 0 otherwise.
+    pub fn cells(&self) -> &[u32] {
+        &self.cells
+    }
+
+    /// Returns the index of cell `(x, y, z)` in [`Self::cells`].
+    pub fn cell_index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + y * self.dims.x + z * self.dims.x * self.dims.y) as usize
+    }
+
+    pub fn is_occupied(&self, x: u32, y: u32, z: u32) -> bool {
+        self.cells[self.cell_index(x, y, z)] != 0
+    }
+}
+
+impl RayTraceScene {
+    /// Rasterizes the surfaces of the scene into an occupancy grid.
+    ///
+    /// Rays are traced through the TLAS along the center line of every row of cells in each of the
+    /// three axes, and each cell in which a ray hits a surface is marked occupied. Only surfaces
+    /// are marked, the insides of closed meshes stay free. Geometry thinner than a cell that no
+    /// center line crosses, such as a sharp edge, may be missed.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The `wgpu::Device` to use.
+    /// * `queue` - The `wgpu::Queue` to use for submitting commands.
+    /// * `top_right` - The corner of the grid with the largest coordinates.
+    /// * `bottom_left` - The corner of the grid with the smallest coordinates.
+    /// * `resolution` - Edge length of a cell in meters.
+    pub async fn voxelize(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        top_right: Vec3,
+        bottom_left: Vec3,
+        resolution: f32,
+    ) -> OccupancyGrid {
+        let dims = ((top_right - bottom_left) / resolution)
+            .ceil()
+            .as_uvec3()
+            .max(UVec3::ONE);
+        let num_cells = (dims.x * dims.y * dims.z) as usize;
+
+        let cs_module = device.create_shader_module(wgpu::include_wgsl!("voxelize.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("voxelize"),
+            layout: None,
+            module: &cs_module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let occupancy = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Voxelized Occupancy"),
+            contents: bytemuck::cast_slice(&vec![0u32; num_cells]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.encode_tlas_update(&mut encoder);
+        for axis in 0..3 {
+            let params = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Voxelize Parameters"),
+                contents: bytemuck::cast_slice(&[VoxelizeParams {
+                    origin: bottom_left,
+                    resolution,
+                    dims,
+                    axis,
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::AccelerationStructure(self.tlas()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: occupancy.as_entire_binding(),
+                    },
+                ],
+            });
+            let u = dims[(axis as usize + 1) % 3];
+            let v = dims[(axis as usize + 2) % 3];
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.dispatch_workgroups(u.div_ceil(8), v.div_ceil(8), 1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        OccupancyGrid {
+            origin: bottom_left,
+            dims,
+            resolution,
+            cells: read_buffer(device, queue, &occupancy).await,
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_voxelize_cube() {
+    use crate::utils::{create_cube, get_raytracing_gpu};
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_raytracing_gpu(&instance).await;
+
+    // A cube spanning [0.8, 2.2] along each axis: its faces lie in cells 1 and 4 at 0.5m
+    // resolution and the center lines of cells 2 and 3 cross them.
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(0.7)],
+        &[crate::Instance {
+            asset_mesh_index: 0,
+            transform: glam::Affine3A::from_translation(Vec3::splat(1.5)),
+            id: 0,
+        }],
+    )
+    .await
+    .unwrap();
+    let grid = scene
+        .voxelize(&device, &queue, Vec3::splat(3.0), Vec3::ZERO, 0.5)
+        .await;
+    assert_eq!(grid.dims(), UVec3::splat(6));
+
+    for z in 0..6 {
+        for y in 0..6 {
+            for x in 0..6 {
+                let cell = [x, y, z];
+                let on_face = cell.iter().filter(|c| [1, 4].contains(*c)).count();
+                let inside = cell.iter().filter(|c| [2, 3].contains(*c)).count();
+                let on_surface = on_face == 1 && inside == 2;
+                assert_eq!(grid.is_occupied(x, y, z), on_surface, "{x} {y} {z}");
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+struct VoxelizeParams {
+    origin: vec3<f32>,
+    resolution: f32,
+    dims: vec3<u32>,
+    axis: u32,
+}
+
+@group(0)
+@binding(0)
+var acc_struct: acceleration_structure;
+
+@group(0)
+@binding(1)
+var<uniform> params: VoxelizeParams;
+
+@group(0)
+@binding(2)
+var<storage, read_write> occupancy: array<atomic<u32>>;
+
+/// Casts a ray along `params.axis` through the centers of one column of cells, marking every cell
+/// a surface crosses. Running this along all three axes catches surfaces of any orientation.
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let u_axis = (params.axis + 1u) % 3u;
+    let v_axis = (params.axis + 2u) % 3u;
+    if global_id.x >= params.dims[u_axis] || global_id.y >= params.dims[v_axis] {
+        return;
+    }
+
+    var cell = vec3<u32>(0u);
+    cell[u_axis] = global_id.x;
+    cell[v_axis] = global_id.y;
+    var origin = params.origin + (vec3<f32>(cell) + 0.5) * params.resolution;
+    origin[params.axis] = params.origin[params.axis];
+    var direction = vec3<f32>(0.0);
+    direction[params.axis] = 1.0;
+    let length = f32(params.dims[params.axis]) * params.resolution;
+
+    var t_min: f32 = 0.0;
+    while t_min < length {
+        var rq: ray_query;
+        rayQueryInitialize(&rq, acc_struct, RayDesc(0x0u, 0xFFu, t_min, length, origin, direction));
+        rayQueryProceed(&rq);
+        let intersection = rayQueryGetCommittedIntersection(&rq);
+        if intersection.kind == RAY_QUERY_INTERSECTION_NONE {
+            break;
+        }
+        let step = min(u32(intersection.t / params.resolution), params.dims[params.axis] - 1u);
+        cell[params.axis] = step;
+        atomicOr(&occupancy[cell.x + cell.y * params.dims.x + cell.z * params.dims.x * params.dims.y], 1u);
+        // Any further hits in this cell would mark it again, skip to the next one.
+        t_min = f32(step + 1u) * params.resolution;
+    }
+}
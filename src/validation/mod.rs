@@ -0,0 +1,346 @@
+//! Cross-validation of rendered sensor outputs against a software reference.
+//!
+//! The functions here render a sensor as usual and cast the same rays through an independent
+//! reference, the triangle meshes of the scene as [`parry3d`] shapes, then report the rays on
+//! which the two disagree. This catches acceleration structure errors, e.g. wrong vertex or
+//! index offsets of a BLAS or instances placed with the wrong transform, which show up as
+//! missing or misplaced geometry rather than as failures. Only the rays, ranges and instance
+//! masks are shared with the sensors; the intersections are parry3d's own, so the reference does
+//! not share the bugs of the CPU tracer scenes without ray queries fall back to, see
+//! [`RayTraceScene::is_cpu_fallback`], and validates those scenes too.
+//!
+//! Noise models are bypassed for the render, so only the geometry is compared.
+//!
+//! ```no_run
+//! # async fn run(
+//! #     scene: &wgpu_rt_lidar::RayTraceScene,
+//! #     lidar: &mut wgpu_rt_lidar::lidar::Lidar,
+//! #     device: &wgpu::Device,
+//! #     queue: &wgpu::Queue,
+//! # ) {
+//! let report = wgpu_rt_lidar::validation::validate_lidar(
+//!     scene,
+//!     lidar,
+//!     device,
+//!     queue,
+//!     &glam::Affine3A::IDENTITY,
+//!     1e-3,
+//! )
+//! .await;
+//! if !report.is_ok() {
+//!     eprintln!("{report}");
+//! }
+//! # }
+//! ```
+
+use std::fmt;
+
+use glam::{Affine3A, Mat4, Vec3};
+use parry3d::{
+    math::{Point, Vector},
+    query::{Ray, RayCast},
+    shape::TriMesh,
+};
+
+use crate::{cpu, depth_camera::DepthCamera, lidar::Lidar, RayTraceScene};
+
+/// A ray on which the rendered and the reference range differ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Discrepancy {
+    /// Index of the ray in the sensor's output.
+    pub index: usize,
+    /// Range of the render, `None` for a miss.
+    pub rendered: Option<f32>,
+    /// Range of the reference tracer, `None` for a miss.
+    pub reference: Option<f32>,
+}
+
+/// The outcome of a validation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Number of rays compared.
+    pub rays: usize,
+    /// The rays hit in one trace and missed in the other, or whose ranges differ by more than
+    /// the tolerance, in ray order.
+    pub discrepancies: Vec<Discrepancy>,
+    /// Largest range difference among the rays both traces hit.
+    pub max_error: f32,
+}
+
+impl ValidationReport {
+    /// Compares ranges ray by ray, misses being marked by `miss`.
+    fn compare(rendered: &[f32], reference: &[f32], miss: f32, tolerance: f32) -> Self {
+        let range = |value: f32| (value != miss).then_some(value);
+        let mut report = Self {
+            rays: rendered.len(),
+            ..Default::default()
+        };
+        for (index, (&rendered, &reference)) in rendered.iter().zip(reference).enumerate() {
+            let (rendered, reference) = (range(rendered), range(reference));
+            let consistent = match (rendered, reference) {
+                (Some(a), Some(b)) => {
+                    report.max_error = report.max_error.max((a - b).abs());
+                    (a - b).abs() <= tolerance
+                }
+                (a, b) => a == b,
+            };
+            if !consistent {
+                report.discrepancies.push(Discrepancy {
+                    index,
+                    rendered,
+                    reference,
+                });
+            }
+        }
+        report
+    }
+
+    /// True if every ray agreed.
+    pub fn is_ok(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} rays differ from the reference, max error {}",
+            self.discrepancies.len(),
+            self.rays,
+            self.max_error
+        )?;
+        for d in self.discrepancies.iter().take(10) {
+            write!(
+                f,
+                "\n  ray {}: rendered {:?}, reference {:?}",
+                d.index, d.rendered, d.reference
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the beams of `lidar` at `pose` and compares them with the reference tracer.
+/// Ranges within `tolerance` meters agree.
+pub async fn validate_lidar(
+    scene: &RayTraceScene,
+    lidar: &mut Lidar,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pose: &Affine3A,
+    tolerance: f32,
+) -> ValidationReport {
+    let noise = lidar.replace_noise_model(None);
+    let rendered = lidar.render_lidar_beams(scene, device, queue, pose).await;
+    lidar.replace_noise_model(noise);
+    let reference_scene = ReferenceScene::new(scene);
+    let filter = lidar.ray_filter();
+    let reference: Vec<f32> = lidar
+        .ray_directions()
+        .iter()
+        .map(|direction| {
+            reference_scene
+                .intersect(
+                    pose.translation.into(),
+                    pose.matrix3 * direction.truncate(),
+                    filter,
+                    cpu::LIDAR_T_MAX,
+                )
+                .unwrap_or(0.0)
+        })
+        .collect();
+    ValidationReport::compare(&rendered, &reference, 0.0, tolerance)
+}
+
+/// Renders a depth image of `camera` from `view_matrix` and compares it with the reference
+/// tracer. Ranges within `tolerance` meters agree.
+pub async fn validate_depth_camera(
+    scene: &RayTraceScene,
+    camera: &mut DepthCamera,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    view_matrix: Mat4,
+    tolerance: f32,
+) -> ValidationReport {
+    let noise = camera.replace_noise_model(None);
+    let rendered = camera
+        .render_depth_camera(scene, device, queue, view_matrix)
+        .await;
+    camera.replace_noise_model(noise);
+    let reference_scene = ReferenceScene::new(scene);
+    let (view_inverse, proj_inverse) =
+        (view_matrix.inverse(), camera.projection_matrix().inverse());
+    let size = (camera.width(), camera.height());
+    let filter = camera.ray_filter();
+    let reference: Vec<f32> = (0..size.0 * size.1)
+        .map(|i| {
+            let (origin, direction) =
+                cpu::camera_ray(&view_inverse, &proj_inverse, i / size.1, i % size.1, size);
+            reference_scene
+                .intersect(origin, direction, filter, cpu::CAMERA_T_MAX)
+                .unwrap_or(DepthCamera::no_hit_const())
+        })
+        .collect();
+    ValidationReport::compare(
+        &rendered,
+        &reference,
+        DepthCamera::no_hit_const(),
+        tolerance,
+    )
+}
+
+/// The scene as parry3d triangle meshes, one per asset, and the instances placing them.
+struct ReferenceScene {
+    meshes: Vec<TriMesh>,
+    /// The mesh, world to object transform and mask of each instance.
+    instances: Vec<(usize, Affine3A, u8)>,
+}
+
+impl ReferenceScene {
+    fn new(scene: &RayTraceScene) -> Self {
+        let meshes = scene
+            .assets
+            .iter()
+            .map(|asset| {
+                let vertices = asset
+                    .vertex_buf
+                    .iter()
+                    .map(|vertex| Point::new(vertex._pos[0], vertex._pos[1], vertex._pos[2]))
+                    .collect();
+                let indices = asset
+                    .index_buf
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]].map(u32::from))
+                    .collect();
+                TriMesh::new(vertices, indices).expect("scene assets have whole triangles")
+            })
+            .collect();
+        let instances = scene
+            .instances
+            .iter()
+            .zip(&scene.instance_masks)
+            .map(|(instance, &mask)| {
+                (
+                    instance.asset_mesh_index,
+                    instance.transform.inverse(),
+                    mask,
+                )
+            })
+            .collect();
+        Self { meshes, instances }
+    }
+
+    /// Distance to the closest hit in `(filter.t_min, t_max)` of an instance the filter does not
+    /// cull, in the units of `direction` like a ray query. Triangles are hit from both sides.
+    fn intersect(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        filter: cpu::RayFilter,
+        t_max: f32,
+    ) -> Option<f32> {
+        self.instances
+            .iter()
+            .filter(|(_, _, mask)| mask & filter.cull_mask != 0)
+            .filter_map(|(mesh, world_to_object, _)| {
+                // Casting in object space keeps the time of impact as long as the direction is
+                // not normalized, so the ray starts at `t_min` and scales along.
+                let origin = world_to_object.transform_point3(origin + direction * filter.t_min);
+                let direction = world_to_object.transform_vector3(direction);
+                let ray = Ray::new(
+                    Point::new(origin.x, origin.y, origin.z),
+                    Vector::new(direction.x, direction.y, direction.z),
+                );
+                self.meshes[*mesh].cast_local_ray(&ray, t_max - filter.t_min, false)
+            })
+            .map(|time_of_impact| filter.t_min + time_of_impact)
+            .filter(|&t| t > filter.t_min)
+            .min_by(f32::total_cmp)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_compare_reports_discrepancies() {
+    let report = ValidationReport::compare(
+        &[1.0, 0.0, 2.0, 3.0, 4.0],
+        &[1.0005, 5.0, 0.0, 3.5, 4.0],
+        0.0,
+        1e-3,
+    );
+    assert_eq!(report.rays, 5);
+    assert_eq!(
+        report.discrepancies,
+        [
+            Discrepancy {
+                index: 1,
+                rendered: None,
+                reference: Some(5.0)
+            },
+            Discrepancy {
+                index: 2,
+                rendered: Some(2.0),
+                reference: None
+            },
+            Discrepancy {
+                index: 3,
+                rendered: Some(3.0),
+                reference: Some(3.5)
+            },
+        ]
+    );
+    assert_eq!(report.max_error, 0.5);
+    assert!(report.to_string().starts_with("3 of 5 rays differ"));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_validate_sensors() {
+    use crate::{
+        noise::GaussianNoise,
+        utils::{create_cube, get_gpu},
+        Instance,
+    };
+    use glam::Vec3;
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let cubes: Vec<_> = [-4.0, 4.0]
+        .into_iter()
+        .map(|x| Instance {
+            asset_mesh_index: 0,
+            transform: Affine3A::from_translation(Vec3::new(x, 0.0, -4.0)),
+            id: 0,
+        })
+        .collect();
+    let scene = RayTraceScene::new(&device, &queue, &[create_cube(1.0)], &cubes)
+        .await
+        .unwrap();
+
+    let directions = (0..64)
+        .map(|i| {
+            let angle = i as f32 / 64.0 * std::f32::consts::TAU;
+            Vec3::new(angle.cos(), 0.0, angle.sin())
+        })
+        .collect();
+    let mut lidar = Lidar::new(&device, directions).await;
+    lidar.set_noise_model(GaussianNoise { stddev: 0.5 });
+    let report = validate_lidar(
+        &scene,
+        &mut lidar,
+        &device,
+        &queue,
+        &Affine3A::IDENTITY,
+        1e-3,
+    )
+    .await;
+    assert_eq!(report.rays, 64);
+    assert!(report.is_ok(), "{report}");
+
+    let mut camera = DepthCamera::new(&device, 16, 12, 90.0, 100.0).await;
+    let report =
+        validate_depth_camera(&scene, &mut camera, &device, &queue, Mat4::IDENTITY, 1e-3).await;
+    assert_eq!(report.rays, 16 * 12);
+    assert!(report.is_ok(), "{report}");
+}
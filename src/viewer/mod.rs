@@ -0,0 +1,530 @@
+//! A window previewing a scene, to sanity-check it without setting up rerun.
+//!
+//! The [`Viewer`] shows a fly-through view of the scene, ray traced like a depth camera and
+//! colored by depth, next to the output of a depth camera of your own if one is attached. Hold
+//! the right mouse button to look around, move with WASD, Q and E down and up, hold shift to go
+//! faster and press escape to quit:
+//!
+//! ```no_run
+//! # fn run(
+//! #     context: wgpu_rt_lidar::RenderContext,
+//! #     scene: wgpu_rt_lidar::RayTraceScene,
+//! #     camera: wgpu_rt_lidar::depth_camera::DepthCamera,
+//! # ) -> Result<(), wgpu_rt_lidar::viewer::ViewerError> {
+//! use glam::{Mat4, Vec3};
+//! use wgpu_rt_lidar::viewer::Viewer;
+//!
+//! let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 3.0), Vec3::ZERO, Vec3::Y);
+//! Viewer::new(context, scene)
+//!     .with_depth_camera(camera, view)
+//!     .run()
+//! # }
+//! ```
+
+use std::{collections::HashSet, sync::Arc, time::Instant};
+
+use glam::{Mat4, Vec3};
+use winit::{
+    application::ApplicationHandler,
+    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent},
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{Window, WindowId},
+};
+
+use crate::{
+    depth_camera::DepthCamera, pose::IntoMat4, visualizer::viridis, RayTraceScene, RenderContext,
+};
+
+/// Resolution and vertical field of view of the fly-through view.
+const PREVIEW_WIDTH: u32 = 480;
+const PREVIEW_HEIGHT: u32 = 360;
+const PREVIEW_FOV: f32 = 70.0;
+/// Speed of the fly-through camera in meters per second, and how much faster it goes with
+/// shift held.
+const SPEED: f32 = 3.0;
+const BOOST: f32 = 4.0;
+/// Radians the camera turns per pixel the mouse moves.
+const SENSITIVITY: f32 = 0.003;
+
+/// Errors of a [`Viewer`].
+#[derive(Debug, thiserror::Error)]
+pub enum ViewerError {
+    #[error(transparent)]
+    EventLoop(#[from] winit::error::EventLoopError),
+    #[error(transparent)]
+    Window(#[from] winit::error::OsError),
+    #[error(transparent)]
+    Surface(#[from] wgpu::CreateSurfaceError),
+    #[error("The adapter cannot present to the window")]
+    UnsupportedSurface,
+    #[error(transparent)]
+    Runtime(#[from] std::io::Error),
+}
+
+/// A fly-through camera in a z up world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlyCam {
+    pub position: Vec3,
+    /// Heading in radians, counter-clockwise from +x.
+    pub yaw: f32,
+    /// Radians above the horizon.
+    pub pitch: f32,
+}
+
+impl FlyCam {
+    /// A camera at `position` looking at `target`.
+    pub fn new(position: Vec3, target: Vec3) -> Self {
+        let direction = (target - position).normalize_or(Vec3::X);
+        Self {
+            position,
+            yaw: direction.y.atan2(direction.x),
+            pitch: direction.z.asin(),
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+        )
+    }
+
+    /// The view matrix to render a depth camera from the camera with.
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_to_rh(self.position, self.forward(), Vec3::Z)
+    }
+}
+
+/// A window showing a scene and, optionally, a depth camera's output in real time.
+pub struct Viewer {
+    context: RenderContext,
+    scene: RayTraceScene,
+    sensor: Option<(DepthCamera, Mat4)>,
+    fly_cam: FlyCam,
+    max_depth: f32,
+}
+
+impl Viewer {
+    pub fn new(context: RenderContext, scene: RayTraceScene) -> Self {
+        Self {
+            context,
+            scene,
+            sensor: None,
+            fly_cam: FlyCam::new(Vec3::new(-5.0, 0.0, 2.0), Vec3::ZERO),
+            max_depth: 50.0,
+        }
+    }
+
+    /// Shows the output of `camera` rendered with `view_matrix` next to the fly-through view.
+    pub fn with_depth_camera(mut self, camera: DepthCamera, view_matrix: impl IntoMat4) -> Self {
+        self.sensor = Some((camera, view_matrix.into_mat4()));
+        self
+    }
+
+    /// Where the fly-through camera starts.
+    pub fn with_fly_cam(mut self, fly_cam: FlyCam) -> Self {
+        self.fly_cam = fly_cam;
+        self
+    }
+
+    /// The depth, in meters, mapped to the end of the colormap. Defaults to 50.
+    pub fn with_max_depth(mut self, max_depth: f32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Opens the window and renders until it is closed.
+    ///
+    /// Call it from the main thread, outside of an async runtime: the viewer drives its own.
+    pub fn run(self) -> Result<(), ViewerError> {
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        let preview = runtime.block_on(DepthCamera::new(
+            self.context.device(),
+            PREVIEW_WIDTH,
+            PREVIEW_HEIGHT,
+            PREVIEW_FOV,
+            self.max_depth,
+        ));
+        let event_loop = EventLoop::new()?;
+        let mut app = App {
+            viewer: self,
+            runtime,
+            preview,
+            window: None,
+            keys: HashSet::new(),
+            looking: false,
+            last_frame: Instant::now(),
+            error: None,
+        };
+        event_loop.run_app(&mut app)?;
+        app.error.map_or(Ok(()), Err)
+    }
+}
+
+/// A texture the depth images of a camera are shown through.
+struct Panel {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
+impl Panel {
+    fn new(
+        device: &wgpu::Device,
+        pipeline: &wgpu::RenderPipeline,
+        sampler: &wgpu::Sampler,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Viewer Panel"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+        Self {
+            texture,
+            bind_group,
+            width,
+            height,
+        }
+    }
+
+    /// Shows depths along the optical axis, row by row from the top, colored up to
+    /// `max_depth`. Pixels without a return, NaN, are black.
+    fn upload(&self, queue: &wgpu::Queue, depths: &[f32], max_depth: f32) {
+        let pixels: Vec<u8> = depths
+            .iter()
+            .flat_map(|depth| {
+                let [r, g, b] = if depth.is_nan() {
+                    [0; 3]
+                } else {
+                    viridis(depth / max_depth)
+                };
+                [r, g, b, 255]
+            })
+            .collect();
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * self.width),
+                rows_per_image: Some(self.height),
+            },
+            self.texture.size(),
+        );
+    }
+
+    /// The largest rectangle of the panel's aspect ratio centered in `area`, as x, y, width
+    /// and height in pixels.
+    fn fit(&self, area: [f32; 4]) -> [f32; 4] {
+        let [x, y, width, height] = area;
+        let scale = (width / self.width as f32).min(height / self.height as f32);
+        let (fit_width, fit_height) = (self.width as f32 * scale, self.height as f32 * scale);
+        [
+            x + (width - fit_width) / 2.0,
+            y + (height - fit_height) / 2.0,
+            fit_width,
+            fit_height,
+        ]
+    }
+}
+
+/// The window and what is drawn to it.
+struct WindowState {
+    window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    pipeline: wgpu::RenderPipeline,
+    sensor_panel: Option<Panel>,
+    preview_panel: Panel,
+}
+
+struct App {
+    viewer: Viewer,
+    runtime: tokio::runtime::Runtime,
+    /// The depth camera rendering the fly-through view.
+    preview: DepthCamera,
+    window: Option<WindowState>,
+    keys: HashSet<KeyCode>,
+    /// Set while the right mouse button is held.
+    looking: bool,
+    last_frame: Instant,
+    error: Option<ViewerError>,
+}
+
+impl App {
+    fn create_window(&self, event_loop: &ActiveEventLoop) -> Result<WindowState, ViewerError> {
+        let window = Arc::new(
+            event_loop.create_window(Window::default_attributes().with_title("wgpu_rt_lidar"))?,
+        );
+        let context = &self.viewer.context;
+        let device = context.device();
+        let surface = context.instance().create_surface(window.clone())?;
+        let size = window.inner_size();
+        let config = surface
+            .get_default_config(context.adapter(), size.width.max(1), size.height.max(1))
+            .ok_or(ViewerError::UnsupportedSurface)?;
+        surface.configure(device, &config);
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("viewer_blit"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(config.format.into())],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let sensor_panel = self.viewer.sensor.as_ref().map(|(camera, _)| {
+            Panel::new(device, &pipeline, &sampler, camera.width(), camera.height())
+        });
+        let preview_panel = Panel::new(device, &pipeline, &sampler, PREVIEW_WIDTH, PREVIEW_HEIGHT);
+        Ok(WindowState {
+            window,
+            surface,
+            config,
+            pipeline,
+            sensor_panel,
+            preview_panel,
+        })
+    }
+
+    fn move_fly_cam(&mut self, dt: f32) {
+        let fly_cam = &mut self.viewer.fly_cam;
+        let forward = fly_cam.forward();
+        let right = forward.cross(Vec3::Z).normalize_or_zero();
+        let velocity: Vec3 = [
+            (KeyCode::KeyW, forward),
+            (KeyCode::KeyS, -forward),
+            (KeyCode::KeyD, right),
+            (KeyCode::KeyA, -right),
+            (KeyCode::KeyE, Vec3::Z),
+            (KeyCode::KeyQ, Vec3::NEG_Z),
+        ]
+        .into_iter()
+        .filter(|(key, _)| self.keys.contains(key))
+        .map(|(_, direction)| direction)
+        .sum();
+        let speed = if self.keys.contains(&KeyCode::ShiftLeft) {
+            SPEED * BOOST
+        } else {
+            SPEED
+        };
+        fly_cam.position += velocity.normalize_or_zero() * speed * dt;
+    }
+
+    fn redraw(&mut self) {
+        let dt = self.last_frame.elapsed().as_secs_f32();
+        self.last_frame = Instant::now();
+        self.move_fly_cam(dt);
+
+        let Self {
+            viewer,
+            runtime,
+            preview,
+            window: Some(state),
+            ..
+        } = self
+        else {
+            return;
+        };
+        let (device, queue) = (viewer.context.device(), viewer.context.queue());
+        let depth = runtime.block_on(viewer.context.render_depth_camera(
+            preview,
+            &viewer.scene,
+            viewer.fly_cam.view_matrix(),
+        ));
+        let depths = preview
+            .optical_depths(&depth)
+            .expect("Rendered by the camera");
+        state.preview_panel.upload(queue, &depths, viewer.max_depth);
+        if let (Some((camera, view_matrix)), Some(panel)) =
+            (&mut viewer.sensor, &state.sensor_panel)
+        {
+            let depth = runtime.block_on(viewer.context.render_depth_camera(
+                camera,
+                &viewer.scene,
+                *view_matrix,
+            ));
+            let depths = camera
+                .optical_depths(&depth)
+                .expect("Rendered by the camera");
+            panel.upload(queue, &depths, viewer.max_depth);
+        }
+
+        let frame = match state.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                state.surface.configure(device, &state.config);
+                state.window.request_redraw();
+                return;
+            }
+            Err(e) => {
+                eprintln!("Skipping a frame: {e}");
+                state.window.request_redraw();
+                return;
+            }
+        };
+        let view = frame.texture.create_view(&Default::default());
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("viewer"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&state.pipeline);
+            let (width, height) = (state.config.width as f32, state.config.height as f32);
+            // The depth camera on the left half, if any, and the fly-through view on the rest.
+            let panels = match &state.sensor_panel {
+                Some(sensor) => vec![
+                    (sensor, [0.0, 0.0, width / 2.0, height]),
+                    (
+                        &state.preview_panel,
+                        [width / 2.0, 0.0, width / 2.0, height],
+                    ),
+                ],
+                None => vec![(&state.preview_panel, [0.0, 0.0, width, height])],
+            };
+            for (panel, area) in panels {
+                let [x, y, w, h] = panel.fit(area);
+                if w < 1.0 || h < 1.0 {
+                    continue;
+                }
+                pass.set_viewport(x, y, w, h, 0.0, 1.0);
+                pass.set_bind_group(0, &panel.bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+        queue.submit(Some(encoder.finish()));
+        frame.present();
+        state.window.request_redraw();
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+        match self.create_window(event_loop) {
+            Ok(state) => {
+                state.window.request_redraw();
+                self.window = Some(state);
+            }
+            Err(e) => {
+                self.error = Some(e);
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(size) => {
+                if let Some(state) = &mut self.window {
+                    state.config.width = size.width.max(1);
+                    state.config.height = size.height.max(1);
+                    state
+                        .surface
+                        .configure(self.viewer.context.device(), &state.config);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => match (key, state) {
+                (KeyCode::Escape, _) => event_loop.exit(),
+                (_, ElementState::Pressed) => {
+                    self.keys.insert(key);
+                }
+                (_, ElementState::Released) => {
+                    self.keys.remove(&key);
+                }
+            },
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => self.looking = state == ElementState::Pressed,
+            WindowEvent::RedrawRequested => self.redraw(),
+            _ => {}
+        }
+    }
+
+    fn device_event(&mut self, _: &ActiveEventLoop, _: DeviceId, event: DeviceEvent) {
+        if let (DeviceEvent::MouseMotion { delta: (dx, dy) }, true) = (event, self.looking) {
+            let fly_cam = &mut self.viewer.fly_cam;
+            fly_cam.yaw -= dx as f32 * SENSITIVITY;
+            fly_cam.pitch = (fly_cam.pitch - dy as f32 * SENSITIVITY).clamp(-1.5, 1.5);
+        }
+    }
+}
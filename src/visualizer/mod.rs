@@ -0,0 +1,404 @@
+//! Backends the scene and sensors are visualized with.
+//!
+//! [`RayTraceScene::visualize_with`](crate::RayTraceScene::visualize_with) and
+//! [`Lidar::visualize_rays_with`](crate::lidar::Lidar::visualize_rays_with) log through a
+//! [`SceneVisualizer`], so any viewer, e.g. Foxglove, a custom renderer or a file dump, can show
+//! them by implementing it. With the `visualization` feature, `rerun::RecordingStream`
+//! implements it.
+
+use glam::{Affine3A, Vec3};
+
+/// Something that displays or records geometry logged under hierarchical entity paths, e.g.
+/// `"mesh_0"`.
+pub trait SceneVisualizer {
+    /// Logs a triangle mesh, in its own frame.
+    fn log_mesh(&mut self, path: &str, vertices: &[Vec3], triangles: &[[u32; 3]]);
+
+    /// Logs points.
+    fn log_points(&mut self, path: &str, points: &[Vec3]);
+
+    /// Logs poses of the entity at `path`, e.g. every instance of the mesh logged there.
+    fn log_poses(&mut self, path: &str, poses: &[Affine3A]);
+
+    /// Logs rays from `origins` along `vectors`. Logs the end points of the rays unless
+    /// implemented.
+    fn log_rays(&mut self, path: &str, origins: &[Vec3], vectors: &[Vec3]) {
+        let ends: Vec<_> = origins
+            .iter()
+            .zip(vectors)
+            .map(|(origin, vector)| *origin + *vector)
+            .collect();
+        self.log_points(path, &ends);
+    }
+
+    /// Logs axis aligned boxes with a label each. Logs the edges of the boxes as rays unless
+    /// implemented.
+    fn log_boxes(&mut self, path: &str, centers: &[Vec3], half_sizes: &[Vec3], _labels: &[String]) {
+        let mut origins = vec![];
+        let mut vectors = vec![];
+        for (center, half_size) in centers.iter().zip(half_sizes) {
+            let min = *center - *half_size;
+            let size = *half_size * 2.0;
+            for axis in 0..3 {
+                let edge = Vec3::AXES[axis] * size;
+                let (u, v) = (
+                    Vec3::AXES[(axis + 1) % 3] * size,
+                    Vec3::AXES[(axis + 2) % 3] * size,
+                );
+                for corner in [Vec3::ZERO, u, v, u + v] {
+                    origins.push(min + corner);
+                    vectors.push(edge);
+                }
+            }
+        }
+        self.log_rays(path, &origins, &vectors);
+    }
+}
+
+/// Samples of the viridis colormap, evenly spaced from 0 to 1.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+/// Colors `value`, clamped to [0, 1], with the viridis colormap, from dark purple to yellow.
+pub fn viridis(value: f32) -> [u8; 3] {
+    let position = value.clamp(0.0, 1.0) * (VIRIDIS.len() - 1) as f32;
+    let i = (position as usize).min(VIRIDIS.len() - 2);
+    let s = position - i as f32;
+    std::array::from_fn(|c| {
+        (VIRIDIS[i][c] as f32 + (VIRIDIS[i + 1][c] as f32 - VIRIDIS[i][c] as f32) * s).round() as u8
+    })
+}
+
+/// A distinct color for each of a few dozen categories, e.g. instance IDs. Neighbouring IDs
+/// get very different hues.
+pub fn categorical(id: u32) -> [u8; 3] {
+    // Golden ratio steps around the hue circle.
+    let hue = (id as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [r, g, b].map(|c| (55.0 + 200.0 * c) as u8)
+}
+
+/// Colors `value` from red at -1 through white at 0 to blue at 1, e.g. signed distances.
+#[cfg(feature = "visualization")]
+pub(crate) fn diverging_color(value: f32) -> rerun::Color {
+    let value = value.clamp(-1.0, 1.0);
+    let fade = ((1.0 - value.abs()) * 255.0) as u8;
+    if value < 0.0 {
+        rerun::Color::from_rgb(255, fade, fade)
+    } else {
+        rerun::Color::from_rgb(fade, fade, 255)
+    }
+}
+
+#[cfg(feature = "visualization")]
+impl SceneVisualizer for rerun::RecordingStream {
+    fn log_mesh(&mut self, path: &str, vertices: &[Vec3], triangles: &[[u32; 3]]) {
+        let vertices = vertices.iter().map(|v| v.to_array());
+        self.log(
+            path,
+            &rerun::Mesh3D::new(vertices).with_triangle_indices(triangles.iter().copied()),
+        )
+        .unwrap();
+    }
+
+    fn log_points(&mut self, path: &str, points: &[Vec3]) {
+        self.log(
+            path,
+            &rerun::Points3D::new(points.iter().map(|p| p.to_array())),
+        )
+        .unwrap();
+    }
+
+    fn log_poses(&mut self, path: &str, poses: &[Affine3A]) {
+        let translations = poses.iter().map(|pose| pose.translation.to_array());
+        let rotations = poses.iter().map(|pose| {
+            let rotation = glam::Quat::from_mat3a(&pose.matrix3);
+            rerun::Quaternion::from_xyzw(rotation.to_array())
+        });
+        self.log(
+            path,
+            &rerun::InstancePoses3D::new()
+                .with_translations(translations)
+                .with_quaternions(rotations),
+        )
+        .unwrap();
+    }
+
+    fn log_rays(&mut self, path: &str, origins: &[Vec3], vectors: &[Vec3]) {
+        self.log(
+            path,
+            &rerun::Arrows3D::from_vectors(vectors.iter().map(|v| v.to_array()))
+                .with_origins(origins.iter().map(|o| o.to_array())),
+        )
+        .unwrap();
+    }
+
+    fn log_boxes(&mut self, path: &str, centers: &[Vec3], half_sizes: &[Vec3], labels: &[String]) {
+        self.log(
+            path,
+            &rerun::Boxes3D::from_centers_and_half_sizes(
+                centers.iter().map(|c| c.to_array()),
+                half_sizes.iter().map(|h| h.to_array()),
+            )
+            .with_labels(labels.iter().map(String::as_str)),
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_scene_visualize_with() {
+    use crate::{
+        lidar::Lidar,
+        utils::{create_cube, get_gpu},
+        Instance, RayTraceScene,
+    };
+
+    #[derive(Default)]
+    struct Recorder {
+        meshes: Vec<(String, usize, usize)>,
+        poses: Vec<(String, Vec<Affine3A>)>,
+        points: Vec<(String, Vec<Vec3>)>,
+    }
+
+    impl SceneVisualizer for Recorder {
+        fn log_mesh(&mut self, path: &str, vertices: &[Vec3], triangles: &[[u32; 3]]) {
+            self.meshes
+                .push((path.to_string(), vertices.len(), triangles.len()));
+        }
+
+        fn log_points(&mut self, path: &str, points: &[Vec3]) {
+            self.points.push((path.to_string(), points.to_vec()));
+        }
+
+        fn log_poses(&mut self, path: &str, poses: &[Affine3A]) {
+            self.poses.push((path.to_string(), poses.to_vec()));
+        }
+    }
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let cube = create_cube(1.0);
+    let (vertices, triangles) = (cube.vertex_buf.len(), cube.index_buf.len() / 3);
+    let at = |x: f32| Affine3A::from_translation(Vec3::new(x, 0.0, 0.0));
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[cube.clone(), cube],
+        &[
+            Instance {
+                asset_mesh_index: 1,
+                transform: at(1.0),
+                id: 0,
+            },
+            Instance {
+                asset_mesh_index: 0,
+                transform: at(2.0),
+                id: 0,
+            },
+            Instance {
+                asset_mesh_index: 1,
+                transform: at(3.0),
+                id: 0,
+            },
+        ],
+    )
+    .await
+    .unwrap();
+
+    let mut recorder = Recorder::default();
+    scene.visualize_with(&mut recorder);
+    assert_eq!(
+        recorder.meshes,
+        [
+            ("mesh_0".to_string(), vertices, triangles),
+            ("mesh_1".to_string(), vertices, triangles)
+        ]
+    );
+    assert_eq!(
+        recorder.poses,
+        [
+            ("mesh_0".to_string(), vec![at(2.0)]),
+            ("mesh_1".to_string(), vec![at(1.0), at(3.0)])
+        ]
+    );
+
+    // Without arrows the rays are logged as their end points.
+    let lidar = Lidar::new(&device, vec![Vec3::X, Vec3::Z]).await;
+    lidar.visualize_rays_with(&mut recorder, at(1.0), "lidar");
+    assert_eq!(
+        recorder.points,
+        [(
+            "lidar".to_string(),
+            vec![Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 1.0)]
+        )]
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_scene_bounds() {
+    use crate::{
+        utils::{create_cube, get_gpu},
+        Instance, RayTraceScene,
+    };
+
+    #[derive(Default)]
+    struct Recorder {
+        boxes: Vec<(String, Vec<Vec3>, Vec<Vec3>)>,
+        labels: Vec<String>,
+    }
+
+    impl SceneVisualizer for Recorder {
+        fn log_mesh(&mut self, _: &str, _: &[Vec3], _: &[[u32; 3]]) {}
+
+        fn log_points(&mut self, _: &str, _: &[Vec3]) {}
+
+        fn log_poses(&mut self, _: &str, _: &[Affine3A]) {}
+
+        fn log_boxes(
+            &mut self,
+            path: &str,
+            centers: &[Vec3],
+            half_sizes: &[Vec3],
+            labels: &[String],
+        ) {
+            self.boxes
+                .push((path.to_string(), centers.to_vec(), half_sizes.to_vec()));
+            self.labels.extend_from_slice(labels);
+        }
+    }
+
+    let instance = wgpu::Instance::default();
+    let (_, device, queue) = get_gpu(&instance).await;
+    let scene = RayTraceScene::new(
+        &device,
+        &queue,
+        &[create_cube(1.0), create_cube(0.5)],
+        &[
+            Instance {
+                asset_mesh_index: 1,
+                transform: Affine3A::from_translation(Vec3::new(3.0, 0.0, 0.0)),
+                id: 0,
+            },
+            Instance {
+                asset_mesh_index: 0,
+                transform: Affine3A::from_rotation_z(std::f32::consts::FRAC_PI_4),
+                id: 0,
+            },
+        ],
+    )
+    .await
+    .unwrap();
+
+    let mut recorder = Recorder::default();
+    scene.visualize_bounds_with(&mut recorder, true);
+    let [(path, centers, half_sizes), (blas_path, _, blas_half_sizes)] = recorder.boxes.as_slice()
+    else {
+        panic!("Expected instance and BLAS bounds");
+    };
+    assert_eq!(path, "bounds/instances");
+    assert_eq!(
+        recorder.labels,
+        [
+            "instance_0 (mesh_1)",
+            "instance_1 (mesh_0)",
+            "mesh_0",
+            "mesh_1"
+        ]
+    );
+    assert!(centers[0].abs_diff_eq(Vec3::new(3.0, 0.0, 0.0), 1e-6));
+    assert!(half_sizes[0].abs_diff_eq(Vec3::splat(0.5), 1e-6));
+    // The rotated cube's box grows to its diagonal.
+    assert!(half_sizes[1].abs_diff_eq(
+        Vec3::new(std::f32::consts::SQRT_2, std::f32::consts::SQRT_2, 1.0),
+        1e-5
+    ));
+    assert_eq!(blas_path, "bounds/blas");
+    assert_eq!(blas_half_sizes, &[Vec3::ONE, Vec3::splat(0.5)]);
+
+    // Without an implementation, boxes are logged as their 12 edges.
+    struct Edges(usize);
+    impl SceneVisualizer for Edges {
+        fn log_mesh(&mut self, _: &str, _: &[Vec3], _: &[[u32; 3]]) {}
+
+        fn log_points(&mut self, _: &str, points: &[Vec3]) {
+            self.0 += points.len();
+        }
+
+        fn log_poses(&mut self, _: &str, _: &[Affine3A]) {}
+    }
+    let mut edges = Edges(0);
+    scene.visualize_bounds_with(&mut edges, false);
+    assert_eq!(edges.0, 24);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_pointcloud_colors() {
+    use crate::{
+        lidar::{Lidar, PointColoring},
+        utils::get_gpu,
+    };
+
+    assert_eq!(viridis(-1.0), [68, 1, 84]);
+    assert_eq!(viridis(1.0), [253, 231, 37]);
+    assert_ne!(categorical(1), categorical(2));
+
+    let instance = wgpu::Instance::default();
+    let (_, device, _) = get_gpu(&instance).await;
+    // Two rings of two beams, the second ring 10° up.
+    let up = 10f32.to_radians();
+    let lidar = Lidar::new(
+        &device,
+        vec![
+            Vec3::X,
+            Vec3::new(up.cos(), 0.0, up.sin()),
+            Vec3::Y,
+            Vec3::new(0.0, up.cos(), up.sin()),
+        ],
+    )
+    .await;
+    let miss = Lidar::no_hit_const();
+    let points = [
+        [1.0, 0.0, 0.0, 3.0],
+        [miss, 0.0, 0.0, 0.0],
+        [0.0, 10.0, 0.0, 3.0],
+        [0.0, 2.0, 0.5, 7.0],
+    ]
+    .concat();
+
+    let (positions, colors) =
+        lidar.colorize_pointcloud(&points, PointColoring::Range { max: 10.0 });
+    assert_eq!(
+        positions,
+        [Vec3::X, Vec3::new(0.0, 10.0, 0.0), Vec3::new(0.0, 2.0, 0.5)]
+    );
+    assert_eq!(colors[0], viridis(0.1));
+    assert_eq!(colors[1], viridis(1.0));
+
+    let (_, colors) = lidar.colorize_pointcloud(&points, PointColoring::Ring);
+    assert_eq!(colors, [viridis(0.0), viridis(0.0), viridis(1.0)]);
+
+    let (_, colors) = lidar.colorize_pointcloud(&points, PointColoring::InstanceId);
+    assert_eq!(colors[0], colors[1]);
+    assert_ne!(colors[0], colors[2]);
+}
@@ -0,0 +1,252 @@
+//! A minimal XML reader for robot description formats.
+//!
+//! Reads elements, attributes and text, skipping the prolog, comments and processing
+//! instructions. Namespaces and DTDs are not interpreted.
+
+use crate::Error;
+
+/// An element and everything nested in it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) children: Vec<Element>,
+    /// The text directly inside the element, with entities resolved.
+    pub(crate) text: String,
+}
+
+impl Element {
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first child named `name`.
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Every element named `name` nested in this one, depth first.
+    pub(crate) fn descendants<'a>(&'a self, name: &str) -> Vec<&'a Element> {
+        let mut found = vec![];
+        for child in &self.children {
+            if child.name == name {
+                found.push(child);
+            }
+            found.extend(child.descendants(name));
+        }
+        found
+    }
+
+    /// The trimmed text of the child at `path`, e.g. `["range", "max"]`.
+    pub(crate) fn text_at(&self, path: &[&str]) -> Option<&str> {
+        path.iter()
+            .try_fold(self, |element, name| element.child(name))
+            .map(|element| element.text.trim())
+    }
+
+    /// Parses the text of the child at `path`. Missing children are `None`, malformed ones an
+    /// error.
+    pub(crate) fn parse_at<T: std::str::FromStr>(&self, path: &[&str]) -> Result<Option<T>, Error> {
+        self.text_at(path)
+            .map(|text| {
+                text.parse().map_err(|_| {
+                    Error::InvalidArgument(format!("Invalid <{}>: {text}", path.join("><")))
+                })
+            })
+            .transpose()
+    }
+}
+
+/// Parses a document and returns its root element.
+pub(crate) fn parse(document: &str) -> Result<Element, Error> {
+    let mut reader = Reader {
+        input: document,
+        pos: 0,
+    };
+    reader.skip_misc()?;
+    let root = reader.element()?;
+    reader.skip_misc()?;
+    if reader.pos < reader.input.len() {
+        return Err(reader.error("content after the root element"));
+    }
+    Ok(root)
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, what: &str) -> Error {
+        let line = self.input[..self.pos].lines().count().max(1);
+        Error::InvalidArgument(format!("Invalid XML at line {line}: {what}"))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips past the next `end`.
+    fn skip_past(&mut self, end: &str) -> Result<(), Error> {
+        let offset = self
+            .rest()
+            .find(end)
+            .ok_or_else(|| self.error(&format!("missing {end}")))?;
+        self.pos += offset + end.len();
+        Ok(())
+    }
+
+    /// Skips whitespace, comments, processing instructions and declarations.
+    fn skip_misc(&mut self) -> Result<(), Error> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '>' | '/' | '='))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        let name = rest[..len].to_string();
+        self.pos += len;
+        Ok(name)
+    }
+
+    fn element(&mut self) -> Result<Element, Error> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+        self.pos += 1;
+        let mut element = Element {
+            name: self.name()?,
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("expected = after an attribute name"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = self
+                .rest()
+                .chars()
+                .next()
+                .filter(|c| matches!(c, '"' | '\''))
+                .ok_or_else(|| self.error("expected a quoted attribute value"))?;
+            self.pos += 1;
+            let len = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| self.error("unterminated attribute value"))?;
+            let value = unescape(&self.rest()[..len]);
+            self.pos += len + 1;
+            element.attributes.push((key, value));
+        }
+
+        loop {
+            let rest = self.rest();
+            let text_len = rest
+                .find('<')
+                .ok_or_else(|| self.error("unclosed element"))?;
+            element.text.push_str(&unescape(&rest[..text_len]));
+            self.pos += text_len;
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+                if name != element.name {
+                    return Err(self.error(&format!("</{name}> closes <{}>", element.name)));
+                }
+                self.skip_whitespace();
+                self.skip_past(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let len = self
+                    .rest()
+                    .find("]]>")
+                    .ok_or_else(|| self.error("unterminated CDATA"))?;
+                element.text.push_str(&self.rest()[..len]);
+                self.pos += len + 3;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else {
+                element.children.push(self.element()?);
+            }
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_xml() {
+    let root = parse(
+        r#"<?xml version="1.0"?>
+        <!-- A comment -->
+        <sdf version='1.9'>
+          <model name="a &amp; b">
+            <pose>1 2 3 0 0 0</pose>
+            <link name="l"><sensor type="gpu_lidar"/></link>
+            <!-- Skipped <sensor/> -->
+          </model>
+        </sdf>"#,
+    )
+    .unwrap();
+    assert_eq!(root.name, "sdf");
+    assert_eq!(root.attribute("version"), Some("1.9"));
+    let model = root.child("model").unwrap();
+    assert_eq!(model.attribute("name"), Some("a & b"));
+    assert_eq!(model.text_at(&["pose"]), Some("1 2 3 0 0 0"));
+    assert_eq!(root.descendants("sensor").len(), 1);
+    assert!(model.parse_at::<f32>(&["pose"]).is_err());
+    assert_eq!(model.parse_at::<f32>(&["scale"]).unwrap(), None);
+
+    assert!(parse("<a><b></a>").is_err());
+    assert!(parse("<a></a><b/>").is_err());
+}
@@ -0,0 +1,103 @@
+//! Publishing of sensor outputs over Zenoh.
+//!
+//! A [`ZenohPublisher`] puts the messages of the [`ros2`](crate::ros2) module, serialized in
+//! CDR like ROS 2 over Zenoh does, on key expressions chosen per call, typically once per
+//! simulation tick and sensor. This feeds Zenoh based robot middleware, and ROS 2 nodes through
+//! `zenoh-bridge-ros2dds`, without a ROS 2 installation.
+//!
+//! ```no_run
+//! # async fn run(cloud: wgpu_rt_lidar::ros2::PointCloud2) -> zenoh::Result<()> {
+//! use wgpu_rt_lidar::zenoh::ZenohPublisher;
+//!
+//! let session = zenoh::open(zenoh::Config::default()).await?;
+//! let mut publisher = ZenohPublisher::new(session);
+//! publisher.put_point_cloud("robot/lidar/points", &cloud).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use ::zenoh::{bytes::Encoding, pubsub::Publisher, Session};
+
+use crate::ros2::{CameraInfo, Image, LaserScan, PointCloud2};
+
+/// Publishes sensor messages on a Zenoh session.
+pub struct ZenohPublisher {
+    session: Session,
+    /// Publishers declared so far, by key expression.
+    publishers: HashMap<String, Publisher<'static>>,
+}
+
+impl ZenohPublisher {
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            publishers: HashMap::new(),
+        }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub async fn put_point_cloud(&mut self, key: &str, cloud: &PointCloud2) -> ::zenoh::Result<()> {
+        self.put(key, cloud.to_cdr()).await
+    }
+
+    pub async fn put_laser_scan(&mut self, key: &str, scan: &LaserScan) -> ::zenoh::Result<()> {
+        self.put(key, scan.to_cdr()).await
+    }
+
+    pub async fn put_image(&mut self, key: &str, image: &Image) -> ::zenoh::Result<()> {
+        self.put(key, image.to_cdr()).await
+    }
+
+    pub async fn put_camera_info(&mut self, key: &str, info: &CameraInfo) -> ::zenoh::Result<()> {
+        self.put(key, info.to_cdr()).await
+    }
+
+    /// Puts `payload` on `key`, declaring a publisher for the key on its first use.
+    async fn put(&mut self, key: &str, payload: Vec<u8>) -> ::zenoh::Result<()> {
+        if !self.publishers.contains_key(key) {
+            let publisher = self
+                .session
+                .declare_publisher(key.to_string())
+                .encoding(Encoding::APPLICATION_CDR)
+                .await?;
+            self.publishers.insert(key.to_string(), publisher);
+        }
+        self.publishers[key].put(payload).await
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_publisher_puts_cdr() {
+    use crate::ros2::{Header, Time};
+    use glam::Vec4;
+
+    let mut config = ::zenoh::Config::default();
+    config
+        .insert_json5("scouting/multicast/enabled", "false")
+        .unwrap();
+    let session = ::zenoh::open(config).await.unwrap();
+    let subscriber = session
+        .declare_subscriber("sim/depth/points")
+        .await
+        .unwrap();
+
+    let mut publisher = ZenohPublisher::new(session);
+    let cloud = PointCloud2::from_depth_pointcloud(
+        Header::new(Time::default(), "camera"),
+        &[Vec4::new(0.0, 0.0, -1.0, 2.0)],
+    );
+    publisher
+        .put_point_cloud("sim/depth/points", &cloud)
+        .await
+        .unwrap();
+
+    let sample = subscriber.recv_async().await.unwrap();
+    assert_eq!(sample.encoding(), &Encoding::APPLICATION_CDR);
+    assert_eq!(sample.payload().to_bytes(), cloud.to_cdr());
+}